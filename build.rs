@@ -0,0 +1,57 @@
+//! Checks the server jar before `--features embed-server` compiles it in
+
+use std::path::Path;
+
+/// scrcpy-server build the client speaks to (see `Server 3.3.3` in src/server.rs)
+const SERVER_URL: &str =
+    "https://github.com/Genymobile/scrcpy/releases/download/v3.3.3/scrcpy-server-v3.3.3";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_EMBED_SERVER").is_none() {
+        return;
+    }
+    let jar = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets/scrcpy-server");
+    println!("cargo:rerun-if-changed={}", jar.display());
+    println!("cargo:rerun-if-env-changed=SCRCPY_SERVER_SHA256");
+
+    let bytes = match std::fs::read(&jar) {
+        Ok(bytes) => bytes,
+        Err(e) => fail(&format!(
+            "embed-server needs the server jar at {} ({}). Download it with\n    \
+             curl -L -o assets/scrcpy-server {}",
+            jar.display(),
+            e,
+            SERVER_URL
+        )),
+    };
+    // A failed download leaves an HTML page or an empty file behind
+    if !bytes.starts_with(b"PK\x03\x04") {
+        fail(&format!(
+            "{} is not a jar (zip) file; download it again from {}",
+            jar.display(),
+            SERVER_URL
+        ));
+    }
+    if let Ok(expected) = std::env::var("SCRCPY_SERVER_SHA256") {
+        let digest = ring::digest::digest(&ring::digest::SHA256, &bytes);
+        let actual: String = digest
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            fail(&format!(
+                "{} has SHA-256 {}, but SCRCPY_SERVER_SHA256 is {}",
+                jar.display(),
+                actual,
+                expected.trim()
+            ));
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
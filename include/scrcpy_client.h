@@ -0,0 +1,123 @@
+#ifndef SCRCPY_CLIENT_H
+#define SCRCPY_CLIENT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Mirroring session handle
+typedef struct ScrcpyClient ScrcpyClient;
+
+// Decoded video frame, RGBA with 4 bytes per pixel
+typedef struct ScrcpyFrame {
+  int64_t pts;
+  uint32_t width;
+  uint32_t height;
+  // Bytes per row
+  uint32_t stride;
+  // Valid until the next `scrcpy_client_poll_frame` or disconnect
+  const uint8_t *data;
+  size_t len;
+} ScrcpyFrame;
+
+// Decoded audio chunk, interleaved 32-bit float samples
+typedef struct ScrcpyAudio {
+  int64_t pts;
+  uint32_t sample_rate;
+  uint16_t channels;
+  // Valid until the next `scrcpy_client_poll_audio` or disconnect
+  const float *samples;
+  // Number of samples (frames times channels)
+  size_t len;
+} ScrcpyAudio;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Connect over TCP to a scrcpy server listening at `host:port`
+//
+// Returns null on failure, see `scrcpy_client_last_error`.
+//
+// # Safety
+// `host` is a NUL-terminated string
+ScrcpyClient *scrcpy_client_connect(const char *host, uint16_t port);
+
+// Connect with the settings of a config file (see config.example.toml)
+//
+// Returns null on failure, see `scrcpy_client_last_error`.
+//
+// # Safety
+// `config_path` is a NUL-terminated string
+ScrcpyClient *scrcpy_client_connect_config(const char *config_path);
+
+// Take the newest decoded frame, skipping older ones
+//
+// Returns 1 when `frame` was filled, 0 when no new frame arrived since the
+// last call and -1 once the session ended (or on null arguments). Never blocks.
+//
+// # Safety
+// `client` comes from a connect function; `frame` points to writable memory
+int32_t scrcpy_client_poll_frame(ScrcpyClient *client, ScrcpyFrame *frame);
+
+// Take the next decoded audio chunk, in order
+//
+// Returns 1 when `audio` was filled, 0 when no chunk is waiting and -1 once
+// the session ended (or on null arguments). Never blocks.
+//
+// # Safety
+// `client` comes from a connect function; `audio` points to writable memory
+int32_t scrcpy_client_poll_audio(ScrcpyClient *client, ScrcpyAudio *audio);
+
+// Press (`down` non-zero) or release an Android key (`AKEYCODE_*`)
+//
+// Returns 0, or -1 if `client` is null.
+//
+// # Safety
+// `client` comes from a connect function
+int32_t scrcpy_client_send_key(ScrcpyClient *client,
+                               uint32_t keycode,
+                               int32_t down,
+                               uint32_t metastate);
+
+// Touch the screen: `action` 0 = down, 1 = up, 2 = move
+//
+// `x` and `y` are in a `width` x `height` frame, normally the size of the
+// last polled frame. Returns 0, or -1 if `client` is null or `action` unknown.
+//
+// # Safety
+// `client` comes from a connect function
+int32_t scrcpy_client_send_touch(ScrcpyClient *client,
+                                 int32_t action,
+                                 uint64_t pointer_id,
+                                 uint32_t x,
+                                 uint32_t y,
+                                 uint32_t width,
+                                 uint32_t height);
+
+// Ask the device for a keyframe, e.g. after dropping frames
+//
+// # Safety
+// `client` comes from a connect function
+int32_t scrcpy_client_request_keyframe(ScrcpyClient *client);
+
+// Close the connection and free the client; null is ignored
+//
+// # Safety
+// `client` comes from a connect function and is not used afterwards
+void scrcpy_client_disconnect(ScrcpyClient *client);
+
+// Why the last connect failed on this thread ("" if it did not)
+//
+// The string stays valid until the next failing call on this thread.
+const char *scrcpy_client_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SCRCPY_CLIENT_H */
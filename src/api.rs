@@ -0,0 +1,326 @@
+use crate::events::{EventBus, SessionEvent};
+use crate::network::{AdaptiveFecController, FecControl, FecSetting};
+use crate::restream::PacketTap;
+use crate::video::{DecodeQueueStats, LatencyTracer, ReplayBuffer};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Largest request body accepted
+const MAX_BODY: usize = 4096;
+
+/// Facts about the current session, shared by the network thread, the UI
+/// and the control API
+#[derive(Debug, Clone, Default)]
+pub struct SessionInfo {
+    device_name: Arc<Mutex<Option<String>>>,
+    frames_dropped: Arc<AtomicU64>,
+    idle_expired: Arc<AtomicBool>,
+    decode_queue: Arc<Mutex<DecodeQueueStats>>,
+}
+
+impl SessionInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name the device reported in the handshake
+    pub fn device_name(&self) -> Option<String> {
+        self.device_name.lock().clone()
+    }
+
+    pub fn set_device_name(&self, name: Option<String>) {
+        *self.device_name.lock() = name;
+    }
+
+    /// Decoded frames replaced before the renderer got to them
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn set_frames_dropped(&self, dropped: u64) {
+        self.frames_dropped.store(dropped, Ordering::Relaxed);
+    }
+
+    /// Packets waiting for, and dropped before, the video decoder
+    pub fn decode_queue(&self) -> DecodeQueueStats {
+        *self.decode_queue.lock()
+    }
+
+    pub fn set_decode_queue(&self, stats: DecodeQueueStats) {
+        *self.decode_queue.lock() = stats;
+    }
+
+    /// Whether the UI ended the session for being idle
+    pub fn idle_expired(&self) -> bool {
+        self.idle_expired.load(Ordering::Relaxed)
+    }
+
+    pub fn set_idle_expired(&self) {
+        self.idle_expired.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Everything the control API reads or changes
+#[derive(Debug, Clone)]
+pub struct ApiState {
+    pub fec: FecControl,
+    pub session: SessionInfo,
+    pub events: EventBus,
+    pub replay: ReplayBuffer,
+    /// Encoded stream for re-streaming outputs
+    pub tap: PacketTap,
+    /// Where frames spend their time on the way to the screen
+    pub latency: LatencyTracer,
+}
+
+/// Partial FEC update: fields left out keep their current value
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FecUpdate {
+    enabled: Option<bool>,
+    redundancy: Option<u8>,
+}
+
+/// Answer one request, returning the status code and JSON body
+///
+/// `GET /session` describes the session and `GET /events` lists its latest
+/// events. `POST /replay` saves the last seconds of video as an MP4 and
+/// returns its path. `GET /fec` reports the setting,
+/// `PUT`/`POST /fec` changes it, e.g. `{"enabled": true, "redundancy": 20}`.
+fn handle(method: &str, path: &str, body: &[u8], state: &ApiState) -> (u16, String) {
+    let error =
+        |status, message: &str| (status, serde_json::json!({ "error": message }).to_string());
+    let fec = &state.fec;
+    match (method, path) {
+        ("GET", "/session") => {
+            let session = serde_json::json!({
+                "device_name": state.session.device_name(),
+                "frames_dropped": state.session.frames_dropped(),
+                "decode_queue": state.session.decode_queue(),
+            });
+            return (200, session.to_string());
+        }
+        ("GET", "/events") => {
+            let events = serde_json::to_string(&state.events.recent()).unwrap_or_default();
+            return (200, events);
+        }
+        ("POST", "/replay") => {
+            if !state.replay.is_enabled() {
+                return error(409, "Replay buffer is disabled");
+            }
+            return match state.replay.save() {
+                Ok(path) => {
+                    state
+                        .events
+                        .publish(SessionEvent::ReplaySaved { path: path.clone() });
+                    (200, serde_json::json!({ "path": path }).to_string())
+                }
+                Err(e) => error(500, &format!("{:#}", e)),
+            };
+        }
+        (_, "/session" | "/events" | "/replay") => return error(405, "Method not allowed"),
+        (_, "/fec") => {}
+        _ => return error(404, "Not found"),
+    }
+    match method {
+        "GET" => (
+            200,
+            serde_json::to_string(&fec.current()).unwrap_or_default(),
+        ),
+        "PUT" | "POST" => {
+            let update: FecUpdate = match serde_json::from_slice(body) {
+                Ok(update) => update,
+                Err(e) => return error(400, &e.to_string()),
+            };
+            let current = fec.current();
+            let setting = FecSetting {
+                enabled: update.enabled.unwrap_or(current.enabled),
+                redundancy: update.redundancy.unwrap_or(current.redundancy),
+            };
+            if setting.redundancy as f64 > AdaptiveFecController::MAX_REDUNDANCY {
+                return error(400, "redundancy must be between 0 and 50");
+            }
+            fec.request(setting);
+            (200, serde_json::to_string(&setting).unwrap_or_default())
+        }
+        _ => error(405, "Method not allowed"),
+    }
+}
+
+/// Serve the control API on `addr` until `shutdown`
+pub async fn serve(addr: SocketAddr, state: ApiState, shutdown: CancellationToken) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind control API on {}", addr))?;
+    info!("Control API listening on http://{}", addr);
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            accepted = listener.accept() => accepted?,
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &state).await {
+                warn!("Control API request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Read one HTTP/1.1 request from `stream` and answer it
+async fn respond(stream: TcpStream, state: &ApiState) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let (status, body) = match read_request(&mut reader, MAX_BODY).await? {
+        Some(request) => handle(&request.method, &request.path, &request.body, state),
+        None => (413, r#"{"error":"Request too large"}"#.to_string()),
+    };
+    write_response(
+        reader.get_mut(),
+        status,
+        "application/json",
+        body.as_bytes(),
+    )
+    .await
+}
+
+/// HTTP request line and body
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// Read one HTTP/1.1 request, None when its body is over `max_body` bytes
+pub(crate) async fn read_request(
+    reader: &mut BufReader<TcpStream>,
+    max_body: usize,
+) -> Result<Option<Request>> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Bad Content-Length")?;
+            }
+        }
+    }
+
+    if content_length > max_body {
+        return Ok(None);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(Request { method, path, body }))
+}
+
+/// Send a complete response and let the client close the connection
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fec_requests() {
+        let state = ApiState {
+            fec: FecControl::new(FecSetting {
+                enabled: true,
+                redundancy: 10,
+            }),
+            session: SessionInfo::new(),
+            events: EventBus::new(),
+            replay: ReplayBuffer::new(Duration::ZERO, std::env::temp_dir()),
+            tap: PacketTap::new(),
+            latency: LatencyTracer::new(),
+        };
+        let fec = &state.fec;
+        assert_eq!(
+            handle("GET", "/fec", b"", &state),
+            (200, r#"{"enabled":true,"redundancy":10}"#.to_string())
+        );
+
+        let (status, _) = handle("PUT", "/fec", br#"{"redundancy": 25}"#, &state);
+        assert_eq!(status, 200);
+        assert_eq!(
+            fec.take_request(),
+            Some(FecSetting {
+                enabled: true,
+                redundancy: 25,
+            })
+        );
+
+        assert_eq!(
+            handle("PUT", "/fec", br#"{"redundancy": 80}"#, &state).0,
+            400
+        );
+        assert_eq!(handle("PUT", "/fec", b"off", &state).0, 400);
+        assert_eq!(handle("DELETE", "/fec", b"", &state).0, 405);
+        assert_eq!(handle("GET", "/", b"", &state).0, 404);
+        assert_eq!(fec.take_request(), None);
+
+        state.session.set_device_name(Some("Pixel 7".into()));
+        state.session.set_frames_dropped(3);
+        assert_eq!(
+            handle("GET", "/session", b"", &state),
+            (
+                200,
+                r#"{"decode_queue":{"depth":0,"dropped":0,"peak":0},"device_name":"Pixel 7","frames_dropped":3}"#.to_string()
+            )
+        );
+
+        state.events.publish(SessionEvent::Disconnected);
+        assert_eq!(
+            handle("GET", "/events", b"", &state),
+            (200, r#"[{"event":"disconnected"}]"#.to_string())
+        );
+
+        assert_eq!(
+            handle("POST", "/replay", b"", &state),
+            (409, r#"{"error":"Replay buffer is disabled"}"#.to_string())
+        );
+        assert_eq!(handle("GET", "/replay", b"", &state).0, 405);
+    }
+}
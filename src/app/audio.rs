@@ -0,0 +1,95 @@
+//! Audio codec negotiation and audio-only sessions
+
+use super::{server_addr, start_dialed_server, stop_dialed_server};
+use anyhow::Result;
+use scrcpy_custom::{
+    audio::{decoder::HardwareAudioDecoder, player::AudioPlayer},
+    config::{AudioCodec, Config, ConnectionMode},
+    network::{self, Connection, PacketType, TcpConnection},
+};
+use tracing::{error, info, warn};
+
+/// Pick an audio codec this machine can decode, disabling audio if none
+///
+/// Lossless codecs are only used when asked for; otherwise Opus, falling back
+/// to AAC. Runs before the server starts so it is told what to send.
+pub(crate) fn negotiate_audio_codec(config: &mut Config) {
+    if !config.audio.enabled {
+        return;
+    }
+    let lossless = config.audio.codec.to_server_arg();
+    if matches!(config.audio.codec, AudioCodec::Raw | AudioCodec::Flac)
+        && HardwareAudioDecoder::new(lossless, 48000, 2).is_ok()
+    {
+        info!("Requesting lossless {} audio from server.", lossless);
+    } else if HardwareAudioDecoder::new("opus", 48000, 2).is_ok() {
+        info!("Client supports Opus audio. Requesting Opus from server.");
+        config.audio.codec = AudioCodec::Opus;
+    } else if HardwareAudioDecoder::new("aac", 48000, 2).is_ok() {
+        warn!("Client does not support Opus. Requesting AAC from server.");
+        config.audio.codec = AudioCodec::Aac;
+    } else {
+        warn!("No supported audio decoder found (Opus/AAC). Disabling audio.");
+        config.audio.enabled = false;
+    }
+}
+
+/// Start a server without video and play the device audio until Ctrl+C
+///
+/// No decoder, renderer or window is created; the console shows what plays.
+pub(crate) async fn run_audio_only(mut config: Config) -> Result<()> {
+    negotiate_audio_codec(&mut config);
+    if !config.audio.enabled {
+        anyhow::bail!("--no-video needs audio, but no audio decoder is available");
+    }
+    if config.connection.mode != ConnectionMode::Tcp {
+        info!("Audio-only sessions use TCP");
+    }
+    let server = start_dialed_server(&mut config).await?;
+
+    let result = async {
+        let addr = server_addr(&config, network::ConnectionMode::Tcp).await?;
+        let mut connection = TcpConnection::connect_audio_only(addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect: {}", e))?;
+        let codec = config.audio.codec.to_server_arg();
+        let mut decoder = HardwareAudioDecoder::new(codec, 48000, 2)?;
+        let mut player = AudioPlayer::new(48000, 2, config.performance.jitter_buffer_ms)?;
+        println!(
+            "Playing {} audio from {}. Press Ctrl+C to stop.",
+            codec,
+            connection.device_name().unwrap_or("the device")
+        );
+
+        let result = loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+                packet = connection.recv() => match packet {
+                    Ok(packet) if packet.packet_type != PacketType::Audio => {}
+                    Ok(packet) if packet.flags.config => {
+                        if let Err(e) = decoder.set_config(&packet.data) {
+                            error!("Audio config error: {}", e);
+                        }
+                    }
+                    Ok(packet) => match decoder.decode(&packet.data, packet.pts) {
+                        Ok(Some(audio)) => {
+                            if let Err(e) = player.play(audio) {
+                                error!("Audio playback error: {}", e);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("Audio decoding error: {}", e),
+                    },
+                    Err(e) => break Err(anyhow::anyhow!("Device stream ended: {}", e)),
+                },
+            }
+        };
+        if let Err(e) = connection.close().await {
+            warn!("Failed to close connection: {}", e);
+        }
+        result
+    }
+    .await;
+
+    stop_dialed_server(server, result).await
+}
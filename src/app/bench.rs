@@ -0,0 +1,61 @@
+//! The `bench-transport` command
+
+use super::{open_connection, server_addr, start_dialed_server, stop_dialed_server};
+use anyhow::Result;
+use scrcpy_custom::{
+    config::Config,
+    network::{self, BenchReport},
+    server::ServerManager,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Run one short session per transport against the same device
+pub(crate) async fn bench_transports(
+    config: Config,
+    duration: Duration,
+) -> Vec<(
+    network::ConnectionMode,
+    std::result::Result<BenchReport, String>,
+)> {
+    // With adb up the server is reached through an adb tunnel, which only
+    // carries TCP; QUIC needs a server listening on the device's address
+    let tunneled = ServerManager::new().await.is_ok();
+    let mut results = Vec::new();
+    for mode in [network::ConnectionMode::Tcp, network::ConnectionMode::Quic] {
+        if mode == network::ConnectionMode::Quic && tunneled {
+            info!("Skipping QUIC: the server is reached through an adb tunnel");
+            continue;
+        }
+        info!("Benchmarking {:?} for {}s...", mode, duration.as_secs());
+        let result = bench_transport(config.clone(), mode, duration).await;
+        if let Err(e) = &result {
+            warn!("{:?} benchmark failed: {}", mode, e);
+        }
+        results.push((mode, result.map_err(|e| e.to_string())));
+    }
+    results
+}
+
+/// Start a fresh server, stream over `mode` for `duration` and tear down
+async fn bench_transport(
+    mut config: Config,
+    mode: network::ConnectionMode,
+    duration: Duration,
+) -> Result<BenchReport> {
+    // Each run gets its own server so neither transport inherits a warm encoder
+    let server = start_dialed_server(&mut config).await?;
+
+    let result = async {
+        let addr = server_addr(&config, mode).await?;
+        let mut connection = open_connection(mode, addr, &config).await?;
+        let report = network::bench::measure(connection.as_mut(), mode, duration).await;
+        if let Err(e) = connection.close().await {
+            warn!("Failed to close connection: {}", e);
+        }
+        Ok::<_, anyhow::Error>(report?)
+    }
+    .await;
+
+    stop_dialed_server(server, result).await
+}
@@ -0,0 +1,129 @@
+//! The `screenshot` and `record` commands
+
+use super::{open_connection, server_addr, start_dialed_server, stop_dialed_server};
+use anyhow::{Context, Result};
+use scrcpy_custom::{
+    config::Config,
+    network::{ControlMessage, PacketType},
+    restream::{mpegts, PacketTap},
+    video::{
+        decoder::{HardwareVideoDecoder, PixelFormat},
+        snapshot,
+    },
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long the `screenshot` command waits for the first decoded frame
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server, decode until the first picture and save it in `dir`
+pub(crate) async fn capture_screenshot(mut config: Config, dir: &Path) -> Result<PathBuf> {
+    config.audio.enabled = false;
+    let server = start_dialed_server(&mut config).await?;
+
+    let result = async {
+        let addr = server_addr(&config, config.connection.mode.into()).await?;
+        let mut connection = open_connection(config.connection.mode.into(), addr, &config).await?;
+        let codec = connection.video_codec().unwrap_or(config.video.codec);
+        let mut decoder =
+            HardwareVideoDecoder::new(&config.video.hw_decoder, codec, PixelFormat::RGBA)?;
+        let first_frame = async {
+            loop {
+                let packet = connection.recv().await?;
+                match packet.packet_type {
+                    PacketType::Video if packet.flags.config => decoder.set_config(&packet.data),
+                    PacketType::Video => {
+                        if let Some(frame) = decoder.decode(&packet.data, packet.pts)? {
+                            return Ok::<_, anyhow::Error>(frame);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        };
+        let frame = tokio::time::timeout(SCREENSHOT_TIMEOUT, first_frame)
+            .await
+            .context("No video frame received")??;
+        if let Err(e) = connection.close().await {
+            warn!("Failed to close connection: {}", e);
+        }
+        snapshot::save_screenshot(&frame, dir)
+    }
+    .await;
+
+    stop_dialed_server(server, result).await
+}
+
+/// Start a server and write its video to `output` as MPEG-TS until Ctrl+C
+/// or `duration`
+pub(crate) async fn run_record(
+    mut config: Config,
+    output: &Path,
+    duration: Option<Duration>,
+) -> Result<()> {
+    config.audio.enabled = false;
+    let server = start_dialed_server(&mut config).await?;
+
+    let result = async {
+        let addr = server_addr(&config, config.connection.mode.into()).await?;
+        let mut connection = open_connection(config.connection.mode.into(), addr, &config).await?;
+        let tap = PacketTap::new();
+        tap.set_codec(connection.video_codec().unwrap_or(config.video.codec));
+        let shutdown = CancellationToken::new();
+        let mut writer = tokio::spawn({
+            let (output, tap, shutdown) = (output.to_path_buf(), tap.clone(), shutdown.clone());
+            async move { mpegts::record(&output, tap, shutdown).await }
+        });
+        let deadline = async {
+            match duration {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(deadline);
+
+        // The writer only ends early on an error, e.g. an unwritable file
+        let mut finished = None;
+        let streamed = loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+                _ = &mut deadline => break Ok(()),
+                written = &mut writer => {
+                    finished = Some(written);
+                    break Ok(());
+                }
+                packet = connection.recv() => match packet {
+                    Ok(packet) if packet.packet_type == PacketType::Video => {
+                        tap.publish(&packet);
+                        if tap.take_keyframe_request() {
+                            let request = ControlMessage::RequestKeyframe;
+                            if let Err(e) = connection.send_control(request).await {
+                                warn!("Failed to request a keyframe: {}", e);
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => break Err(anyhow::anyhow!("Device stream ended: {}", e)),
+                },
+            }
+        };
+        shutdown.cancel();
+        let written = match finished {
+            Some(written) => written,
+            None => writer.await,
+        }
+        .context("Recording task failed")?;
+        if let Err(e) = connection.close().await {
+            warn!("Failed to close connection: {}", e);
+        }
+        streamed.and(written)?;
+        info!("Recording saved to {}", output.display());
+        Ok(())
+    }
+    .await;
+
+    stop_dialed_server(server, result).await
+}
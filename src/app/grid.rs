@@ -0,0 +1,136 @@
+//! Several devices in one window (`display.grid`)
+
+use super::session::run_app;
+use scrcpy_custom::{
+    api::{ApiState, SessionInfo},
+    config::Config,
+    events::EventBus,
+    network::{ControlMessage, FecControl, FecSetting},
+    restream::PacketTap,
+    server::ServerManager,
+    video::{
+        grid::{self, Grid},
+        latency::LatencyTracer,
+        mailbox::{latest_frame, FrameSender},
+        replay::ReplayBuffer,
+    },
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+/// Interval the grid view is composited at
+const GRID_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Mirror every connected device into one composited picture (`display.grid`)
+///
+/// Each device gets its own server, connection and decoder. Input from the
+/// window goes to the focused tile.
+pub(crate) async fn run_grid(
+    mut config: Config,
+    frame_tx: FrameSender,
+    mut control_rx: tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    shutdown: CancellationToken,
+) {
+    let serials = match ServerManager::devices().await {
+        Ok(serials) if !serials.is_empty() => serials,
+        Ok(_) => {
+            error!("Grid view: no adb devices connected");
+            return;
+        }
+        Err(e) => {
+            error!("Grid view needs adb: {:#}", e);
+            return;
+        }
+    };
+    info!("Mirroring {} devices in a grid", serials.len());
+    // Several phones playing at once would only be noise
+    config.audio.enabled = false;
+    // Per-session outputs and fixed ports would clash between devices
+    config.connection.forward_port = None;
+    config.restream.share = None;
+    config.output.ndi = None;
+
+    let mut tiles = Vec::new();
+    let mut sessions = tokio::task::JoinSet::new();
+    for (tile, serial) in serials.into_iter().enumerate() {
+        let (tile_tx, tile_rx) = latest_frame();
+        let (tile_control_tx, tile_control_rx) = tokio::sync::mpsc::unbounded_channel();
+        tiles.push((tile_rx, tile_control_tx));
+        let mut config = config.clone();
+        config.connection.serial = Some(serial);
+        sessions.spawn(run_tile(
+            tile,
+            config,
+            tile_tx,
+            tile_control_rx,
+            shutdown.clone(),
+        ));
+    }
+
+    let mut grid = Grid::new(tiles.len(), grid::CANVAS_SIZE);
+    let mut tick = tokio::time::interval(GRID_FRAME_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(message) = control_rx.recv() => {
+                if let Some((tile, message)) = grid.route(message) {
+                    let _ = tiles[tile].1.send(message);
+                }
+            }
+            Some(Ok(tile)) = sessions.join_next() => grid.clear(tile),
+            _ = tick.tick() => {
+                for (tile, (frames, _)) in tiles.iter().enumerate() {
+                    if let Some(frame) = frames.try_recv() {
+                        grid.set_frame(tile, frame);
+                    }
+                }
+                if let Some(frame) = grid.compose() {
+                    if frame_tx.send(frame).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    // Each session closes its connection and server once cancelled
+    while sessions.join_next().await.is_some() {}
+}
+
+/// One device of the grid view, returning its tile once the session is over
+async fn run_tile(
+    tile: usize,
+    config: Config,
+    frame_tx: FrameSender,
+    mut control_rx: tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    shutdown: CancellationToken,
+) -> usize {
+    let serial = config.connection.serial.clone().unwrap_or_default();
+    // Nothing is shared with the other devices
+    let api_state = ApiState {
+        fec: FecControl::new(FecSetting {
+            enabled: config.performance.fec_redundancy > 0,
+            redundancy: config.performance.fec_redundancy,
+        }),
+        session: SessionInfo::new(),
+        events: EventBus::new(),
+        replay: ReplayBuffer::new(Duration::ZERO, config.display.screenshot_dir.clone()),
+        tap: PacketTap::new(),
+        latency: LatencyTracer::new(),
+    };
+    let result = run_app(
+        config,
+        frame_tx,
+        &mut control_rx,
+        shutdown,
+        Arc::default(),
+        &api_state,
+    )
+    .await;
+    match result {
+        Ok(()) => info!("{}: session ended", serial),
+        Err(e) => error!("{}: {:#}", serial, e),
+    }
+    tile
+}
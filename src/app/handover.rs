@@ -0,0 +1,99 @@
+//! Moving a USB session over to WiFi (F7)
+
+use super::open_connection;
+use super::session::AdbSession;
+use anyhow::Result;
+use scrcpy_custom::{
+    config::{Config, TunnelMode},
+    network::{self, Connection},
+    server::{ServerManager, Tunnel},
+};
+use std::net::SocketAddr;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// adbd TCP port used for USB -> WiFi handover
+pub(crate) const WIRELESS_ADB_PORT: u16 = 5555;
+
+/// Start a second server over adb-over-WiFi and connect to it
+///
+/// Runs beside the USB session, so video keeps flowing until the new
+/// connection is up (or until adbd restarting in TCP mode drops USB).
+pub(crate) async fn start_wireless_session(
+    usb_serial: String,
+    config: Config,
+) -> Result<(ServerManager, Box<dyn Connection>)> {
+    let server = ServerManager::new().await?;
+    let wireless_serial = server
+        .enable_wireless(&usb_serial, WIRELESS_ADB_PORT)
+        .await?;
+    start_forwarded_session(server, Some(&wireless_serial), config).await
+}
+
+/// Launch a server on `serial` behind a new forward tunnel and dial it
+pub(crate) async fn start_forwarded_session(
+    mut server: ServerManager,
+    serial: Option<&str>,
+    mut config: Config,
+) -> Result<(ServerManager, Box<dyn Connection>)> {
+    config.connection.tunnel = TunnelMode::Forward;
+    let port = match server.start_server(&config, serial).await? {
+        Tunnel::Forward(port) => port,
+        Tunnel::Reverse(_) => anyhow::bail!("Expected a forward tunnel"),
+    };
+    // Only TCP crosses an adb tunnel
+    config.connection.host = "127.0.0.1".to_string();
+    config.connection.port = port;
+    let addr = SocketAddr::new("127.0.0.1".parse().unwrap(), port);
+    let connection = open_connection(network::ConnectionMode::Tcp, addr, &config)
+        .await
+        .map_err(|e| server.explain(e))?;
+    Ok((server, connection))
+}
+
+/// Resolves when the UI asks to move to WiFi, never without `trigger`
+pub(crate) async fn wireless_requested(trigger: Option<&Notify>) {
+    match trigger {
+        Some(trigger) => trigger.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Outcome of the handover in progress, pending while there is none
+pub(crate) async fn handover_finished(
+    task: &mut Option<JoinHandle<Result<(ServerManager, Box<dyn Connection>)>>>,
+) -> Result<(ServerManager, Box<dyn Connection>)> {
+    match task {
+        Some(task) => task.await.map_err(anyhow::Error::from).and_then(|r| r),
+        None => std::future::pending().await,
+    }
+}
+
+/// Adopt the session from `start_wireless_session` and retire the USB one
+pub(crate) async fn complete_handover(
+    result: Result<(ServerManager, Box<dyn Connection>)>,
+    connection: &mut Box<dyn Connection>,
+    adb: &mut Option<AdbSession>,
+) -> bool {
+    match result {
+        Ok((server, next)) => {
+            let mut previous = std::mem::replace(connection, next);
+            if let Err(e) = previous.close().await {
+                warn!("Failed to close USB connection: {}", e);
+            }
+            if let Some(session) = adb {
+                let mut usb = std::mem::replace(&mut session.server, server);
+                if let Err(e) = usb.stop().await {
+                    warn!("USB session cleanup failed: {}", e);
+                }
+            }
+            info!("Now streaming over WiFi, the USB cable can be unplugged");
+            true
+        }
+        Err(e) => {
+            warn!("WiFi handover failed: {}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! What `main` runs once the command line is parsed: the mirror window,
+//! the sessions behind it and the commands without a window
+
+pub(crate) mod audio;
+pub(crate) mod bench;
+pub(crate) mod capture;
+mod grid;
+mod handover;
+pub(crate) mod otg;
+mod replay;
+pub(crate) mod restream;
+mod session;
+mod switch;
+pub(crate) mod window;
+
+use anyhow::{Context, Result};
+use scrcpy_custom::{
+    assets::Assets,
+    config::{Config, TunnelMode},
+    network::{self, Connection, SharedSecret},
+    server::{ServerManager, Tunnel},
+};
+use std::net::SocketAddr;
+use tracing::{error, info, warn};
+
+/// Start a server over ADB and point `config` at a forward tunnel to it
+///
+/// Without ADB the server at `config.connection.host` is used directly.
+pub(crate) async fn start_dialed_server(config: &mut Config) -> Result<Option<ServerManager>> {
+    let mut manager = match ServerManager::new().await {
+        Ok(manager) => manager,
+        Err(e) => {
+            warn!(
+                "Could not connect to ADB: {}. Using {} directly.",
+                e, config.connection.host
+            );
+            return Ok(None);
+        }
+    };
+    // A listener can only accept TCP, so always dial the server
+    config.connection.tunnel = TunnelMode::Forward;
+    let serial = config.connection.adb_serial();
+    if let Tunnel::Forward(port) = manager.start_server(config, serial.as_deref()).await? {
+        config.connection.host = "127.0.0.1".to_string();
+        config.connection.port = port;
+    }
+    Ok(Some(manager))
+}
+
+/// Tear down a server from [`start_dialed_server`], explaining `result`'s error
+pub(crate) async fn stop_dialed_server<T>(
+    server: Option<ServerManager>,
+    result: Result<T>,
+) -> Result<T> {
+    let Some(mut manager) = server else {
+        return result;
+    };
+    let result = result.map_err(|e| manager.explain(e));
+    if let Err(e) = manager.stop().await {
+        warn!("Device cleanup failed: {}", e);
+    }
+    result
+}
+
+/// `ro.product.model` of the device, for per-model profiles
+pub(crate) fn device_model(serial: Option<&str>) -> Option<String> {
+    let mut cmd = std::process::Command::new(Assets::get_adb_path().ok()?);
+    if let Some(serial) = serial {
+        cmd.args(["-s", serial]);
+    }
+    let output = cmd
+        .args(["shell", "getprop", "ro.product.model"])
+        .output()
+        .ok()?;
+    let model = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !model.is_empty()).then_some(model)
+}
+
+fn handle_connection_error(e: &anyhow::Error) {
+    let error_msg = e.to_string();
+    if error_msg.contains("10061") || error_msg.contains("Connection refused") {
+        error!("--------------------------------------------------");
+        error!("CONNECTION REFUSED");
+        error!("1. Ensure 'adb' is in your PATH.");
+        error!("2. Ensure 'scrcpy-server' is in the same folder (or build with --features embed-server).");
+        error!("3. Check if 'adb devices' lists your device.");
+        error!("--------------------------------------------------");
+    }
+}
+
+/// Address of the configured server; a host with several addresses is
+/// raced over `mode` and the first to answer is used
+pub(crate) async fn server_addr(
+    config: &Config,
+    mode: network::ConnectionMode,
+) -> Result<SocketAddr> {
+    network::resolve::pick(mode, &config.connection.host, config.connection.port)
+        .await
+        .with_context(|| format!("Cannot reach {}", config.connection.host))
+}
+
+/// Connect over `mode`, authenticate and apply the performance settings
+pub(crate) async fn open_connection(
+    mode: network::ConnectionMode,
+    addr: SocketAddr,
+    config: &Config,
+) -> Result<Box<dyn Connection>> {
+    let serial = config.connection.adb_serial();
+    let mut connection =
+        network::switcher::connect(mode, addr, config.audio.enabled, serial.as_deref())
+            .await
+            .map_err(|e| {
+                handle_connection_error(&anyhow::anyhow!(e.to_string()));
+                anyhow::anyhow!("Failed to connect: {}", e)
+            })?;
+
+    info!("Connected successfully!");
+
+    // Prove the shared secret before any media is accepted
+    if let Some(secret) = SharedSecret::for_connection(&config.connection, mode) {
+        connection
+            .authenticate(&secret)
+            .await
+            .map_err(|e| anyhow::anyhow!("Authentication failed: {}", e))?;
+    }
+
+    connection.apply_performance_config(&config.performance);
+    Ok(connection)
+}
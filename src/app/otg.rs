@@ -0,0 +1,147 @@
+//! The `otg` command
+
+use anyhow::Result;
+use scrcpy_custom::config::Config;
+
+/// Forward the keyboard and mouse to the device over USB (AOA HID)
+///
+/// Opens an empty window for input only. Clicking it captures the mouse,
+/// F1 or leaving the window releases it.
+#[cfg(feature = "otg")]
+pub(crate) fn run_otg(config: &Config) -> Result<()> {
+    use scrcpy_custom::{
+        config::KeyboardMode,
+        input::{keyboard, mouse, KeyForwarder},
+        otg::AoaHid,
+    };
+    use tracing::warn;
+    use winit::event::{DeviceEvent, ElementState, Event, KeyEvent, MouseScrollDelta, WindowEvent};
+    use winit::event_loop::EventLoop;
+    use winit::keyboard::{KeyCode, PhysicalKey};
+    use winit::window::{CursorGrabMode, Window};
+
+    let mut device = AoaHid::open(config.connection.serial.as_deref())?;
+    device.send(&keyboard::uhid_create())?;
+    device.send(&mouse::uhid_create())?;
+    println!(
+        "Controlling {} over USB. Click the window to capture the mouse, F1 releases it.",
+        device.serial()
+    );
+
+    let event_loop = EventLoop::new()?;
+    let window = event_loop.create_window(
+        Window::default_attributes()
+            .with_title(format!("scrcpy-custom OTG — {}", device.serial()))
+            .with_inner_size(winit::dpi::LogicalSize::new(480.0, 270.0)),
+    )?;
+    let capture = |on: bool| {
+        let grab = if on {
+            // Not every platform can lock the pointer in place
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = grab {
+            warn!("Failed to capture the mouse: {}", e);
+        }
+        window.set_cursor_visible(!on);
+        on
+    };
+
+    let mut keys = KeyForwarder::new(KeyboardMode::Uhid);
+    let mut hid_mouse = mouse::HidMouse::new();
+    let mut captured = false;
+    let mut result = Ok(());
+    event_loop.run(|event, target| {
+        let messages = match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                target.exit();
+                return;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(code),
+                                state,
+                                repeat,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if code == KeyCode::F1 {
+                    if state == ElementState::Pressed && captured {
+                        captured = capture(false);
+                        hid_mouse.release_all().into_iter().collect()
+                    } else {
+                        Vec::new()
+                    }
+                } else {
+                    keys.key(code, state == ElementState::Pressed, repeat)
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { state, button, .. },
+                ..
+            } => {
+                if captured {
+                    let pressed = state == ElementState::Pressed;
+                    hid_mouse.button(button, pressed).into_iter().collect()
+                } else {
+                    // The click that captures the mouse stays local
+                    if state == ElementState::Pressed {
+                        captured = capture(true);
+                    }
+                    Vec::new()
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } if captured => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y.round() as i32,
+                    MouseScrollDelta::PixelDelta(pos) => (pos.y / 40.0).round() as i32,
+                };
+                hid_mouse.wheel(steps).into_iter().collect()
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } => {
+                if captured {
+                    captured = capture(false);
+                }
+                let mut messages = keys.release_all();
+                messages.extend(hid_mouse.release_all());
+                messages
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (dx, dy) },
+                ..
+            } if captured => hid_mouse.motion(dx, dy),
+            _ => Vec::new(),
+        };
+        for msg in &messages {
+            if let Err(e) = device.send(msg) {
+                result = Err(e.context("Device stopped taking input"));
+                target.exit();
+                return;
+            }
+        }
+    })?;
+    result
+}
+
+#[cfg(not(feature = "otg"))]
+pub(crate) fn run_otg(_config: &Config) -> Result<()> {
+    anyhow::bail!("OTG mode needs a build with the `otg` feature")
+}
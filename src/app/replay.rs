@@ -0,0 +1,25 @@
+//! Playing back session captures (`replay`)
+
+use anyhow::{Context, Result};
+use scrcpy_custom::{
+    config::Config,
+    network::{Connection, MockConnection},
+};
+use std::path::Path;
+use tracing::info;
+
+/// Connection playing back the capture at `path` at its recorded pace (`replay`)
+pub(crate) fn open_replay(path: &Path, config: &mut Config) -> Result<Box<dyn Connection>> {
+    let replay = MockConnection::from_file(path)
+        .with_context(|| format!("Cannot read session capture {}", path.display()))?;
+    info!(
+        "Replaying {} packets from {}",
+        replay.remaining(),
+        path.display()
+    );
+    // Decode the audio the recorded server sent
+    if let Some(codec) = replay.header().audio_codec {
+        config.audio.codec = codec;
+    }
+    Ok(Box::new(replay.paced()))
+}
@@ -0,0 +1,121 @@
+//! The device stream for other machines: browsers (WebRTC), other
+//! instances (`restream.share`) and the `relay` command
+
+use super::{open_connection, server_addr, start_dialed_server, stop_dialed_server};
+use anyhow::{Context, Result};
+use scrcpy_custom::{
+    config::Config,
+    network::{self, ControlMessage, Relay, SharedSecret},
+    restream::PacketTap,
+};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Serve the mirror to browsers until `shutdown`
+#[cfg(feature = "webrtc")]
+pub(crate) fn start_webrtc_gateway(addr: SocketAddr, tap: PacketTap, shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        if let Err(e) = scrcpy_custom::restream::webrtc::serve(addr, tap, shutdown).await {
+            warn!("WebRTC gateway unavailable: {:#}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "webrtc"))]
+pub(crate) fn start_webrtc_gateway(
+    addr: SocketAddr,
+    _tap: PacketTap,
+    _shutdown: CancellationToken,
+) {
+    warn!("WebRTC gateway on {} needs the `webrtc` feature", addr);
+}
+
+/// Serve the session to other instances on `listen` until `shutdown`
+///
+/// Viewers must prove `secret` when one is given.
+pub(crate) async fn start_share(
+    listen: SocketAddr,
+    header: network::relay::StreamHeader,
+    secret: Option<SharedSecret>,
+    shutdown: CancellationToken,
+) -> Option<Relay> {
+    let listener = match TcpListener::bind(listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Session sharing unavailable on {}: {}", listen, e);
+            return None;
+        }
+    };
+    info!("Sharing the session on {}", listen);
+    // Viewers only watch; input stays with this window
+    let mut relay = Relay::new(header, None);
+    if let Some(secret) = secret {
+        relay = relay.with_auth(secret);
+    }
+    let server = relay.clone();
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(listener, shutdown).await {
+            warn!("Session sharing stopped: {}", e);
+        }
+    });
+    Some(relay)
+}
+
+/// Start a server and relay its stream to viewers on `listen` until Ctrl+C
+pub(crate) async fn run_relay(mut config: Config, listen: SocketAddr) -> Result<()> {
+    let server = start_dialed_server(&mut config).await?;
+
+    let result = async {
+        let addr = server_addr(&config, config.connection.mode.into()).await?;
+        let mut connection = open_connection(config.connection.mode.into(), addr, &config).await?;
+        let listener = TcpListener::bind(listen)
+            .await
+            .with_context(|| format!("Failed to listen on {}", listen))?;
+        let header = network::relay::StreamHeader {
+            device_name: connection.device_name().map(str::to_string),
+            video_codec: connection.video_codec().unwrap_or(config.video.codec),
+            audio_codec: config.audio.enabled.then_some(config.audio.codec),
+        };
+        let (control_tx, mut control_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut relay = Relay::new(header, Some(control_tx));
+        if let Some(secret) = SharedSecret::for_relay(&config.connection) {
+            relay = relay.with_auth(secret);
+        }
+        let shutdown = CancellationToken::new();
+        tokio::spawn(relay.clone().serve(listener, shutdown.clone()));
+        info!("Relaying the device to viewers on {}", listen);
+
+        let result = loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+                packet = connection.recv() => match packet {
+                    Ok(packet) => {
+                        relay.publish(&packet);
+                        if relay.take_keyframe_request() {
+                            let request = ControlMessage::RequestKeyframe;
+                            if let Err(e) = connection.send_control(request).await {
+                                warn!("Failed to request a keyframe: {}", e);
+                            }
+                        }
+                    }
+                    Err(e) => break Err(anyhow::anyhow!("Device stream ended: {}", e)),
+                },
+                Some(message) = control_rx.recv() => {
+                    if let Err(e) = connection.send_control(message).await {
+                        warn!("Failed to forward a viewer's control message: {}", e);
+                    }
+                }
+            }
+        };
+        shutdown.cancel();
+        if let Err(e) = connection.close().await {
+            warn!("Failed to close connection: {}", e);
+        }
+        result
+    }
+    .await;
+
+    stop_dialed_server(server, result).await
+}
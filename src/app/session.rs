@@ -0,0 +1,1032 @@
+//! Mirroring sessions: starting the server, the receive loop, and starting
+//! over after an unplug or a system sleep
+
+use super::handover::{
+    complete_handover, handover_finished, start_wireless_session, wireless_requested,
+};
+use super::replay::open_replay;
+use super::restream::{start_share, start_webrtc_gateway};
+use super::switch::{adbd_addr, open_switched};
+use super::{open_connection, server_addr};
+use anyhow::{Context, Result};
+use scrcpy_custom::{
+    api::{self, ApiState},
+    audio::{decoder::HardwareAudioDecoder, player::AudioPlayer},
+    config::{Config, KeyboardMode},
+    events::SessionEvent,
+    hotplug::DeviceWatcher,
+    input::{keyboard, EventLog, EventReplay, Haptics},
+    network::{
+        self,
+        capture::{CaptureHeader, CaptureWriter},
+        Connection, ControlMessage, ControlQueue, DeviceMessage, NetworkError, PacketType, Relay,
+        SharedSecret, StreamWatchdog, TcpConnection, TransportSwitcher, WatchdogAction,
+    },
+    plugin::{PluginHost, Verdict},
+    power::{PowerEvent, PowerWatcher},
+    restream::mpegts,
+    server::{PortInUse, ServerManager, Tunnel},
+    stats_log::{StatsLog, StatsRow},
+    video::{
+        decoder::PixelFormat, latency::Mark, mailbox::FrameSender, replay::ReplayPacket, sink,
+        worker::DecodeWorker,
+    },
+};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Notify};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// How long adb may take to report an unplug after the stream broke
+const UNPLUG_GRACE: Duration = Duration::from_secs(2);
+
+/// Time for USB and WiFi to come back after the PC wakes up
+const RESUME_SETTLE: Duration = Duration::from_secs(3);
+
+/// Reconnect attempts after waking up before giving up
+const RESUME_ATTEMPTS: u32 = 5;
+
+/// Video packets queued for the decode thread before packets are dropped
+const DECODE_QUEUE_PACKETS: usize = 8;
+
+/// Interval of `SessionEvent::StatsTick`
+pub(crate) const STATS_TICK: Duration = Duration::from_secs(1);
+
+/// The device-side server of a session started over ADB
+pub(crate) struct AdbSession {
+    pub(crate) server: ServerManager,
+    /// Reverse tunnels: the server connects here instead of us dialing it
+    listener: Option<TcpListener>,
+    /// Raised by the UI to hand a USB session over to WiFi
+    go_wireless: Arc<Notify>,
+}
+
+/// Mirror the device, again after each replug when `connection.hotplug` is set
+pub(crate) async fn run_sessions(
+    config: Config,
+    frame_tx: FrameSender,
+    mut control_rx: tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    shutdown: CancellationToken,
+    go_wireless: Arc<Notify>,
+    banner: Arc<Mutex<Option<String>>>,
+    api_state: ApiState,
+) {
+    let serial = config.connection.adb_serial();
+    let mut watcher = if config.connection.hotplug {
+        DeviceWatcher::spawn()
+            .map_err(|e| warn!("Device hotplug unavailable: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let set_banner = |text: Option<&str>| {
+        if let Ok(mut banner) = banner.lock() {
+            *banner = text.map(str::to_string);
+        }
+    };
+
+    if let Some(addr) = config.api.listen {
+        let (api_state, shutdown) = (api_state.clone(), shutdown.clone());
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(addr, api_state, shutdown).await {
+                warn!("Control API unavailable: {:#}", e);
+            }
+        });
+    }
+    if let Some(addr) = config.restream.webrtc {
+        start_webrtc_gateway(addr, api_state.tap.clone(), shutdown.clone());
+    }
+    if let Some(target) = config.restream.mpegts_udp {
+        let (tap, shutdown) = (api_state.tap.clone(), shutdown.clone());
+        tokio::spawn(async move {
+            if let Err(e) = mpegts::serve_udp(target, tap, shutdown).await {
+                warn!("MPEG-TS output unavailable: {:#}", e);
+            }
+        });
+    }
+    if let Some(addr) = config.restream.mpegts_http {
+        let (tap, shutdown) = (api_state.tap.clone(), shutdown.clone());
+        tokio::spawn(async move {
+            if let Err(e) = mpegts::serve_http(addr, tap, shutdown).await {
+                warn!("MPEG-TS server unavailable: {:#}", e);
+            }
+        });
+    }
+
+    // Every session event goes to the debug log
+    let mut events = api_state.events.subscribe();
+    let log_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                _ = log_shutdown.cancelled() => break,
+                event = events.recv() => event,
+            };
+            match event {
+                Ok(event) => tracing::debug!("Session event: {:?}", event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::debug!("Session event log missed {} events", missed)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    // Sessions are paused across PC sleep and set up again on wake
+    let mut power = PowerWatcher::spawn();
+    let mut resume_attempts = 0;
+
+    let mut waiting = "Waiting for the device to be connected...";
+    loop {
+        if let Some(watcher) = &mut watcher {
+            if !watcher.is_online(serial.as_deref()) {
+                info!("{}", waiting);
+                set_banner(Some(waiting));
+                let online = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    online = watcher.wait_online(serial.as_deref()) => online,
+                };
+                set_banner(None);
+                if !online {
+                    break;
+                }
+            }
+        }
+
+        let session = shutdown.child_token();
+        let app = run_app(
+            config.clone(),
+            frame_tx.clone(),
+            &mut control_rx,
+            session.clone(),
+            go_wireless.clone(),
+            &api_state,
+        );
+        tokio::pin!(app);
+        let (result, power_event) = tokio::select! {
+            result = &mut app => (result, None),
+            Some(event) = power.next() => {
+                // Close the connection and the device side cleanly
+                session.cancel();
+                (app.await, Some(event))
+            }
+        };
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        if let Some(event) = power_event {
+            info!("Session paused for system sleep");
+            set_banner(Some("Paused while the computer sleeps..."));
+            if event == PowerEvent::Suspending {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = async {
+                        while power.next().await.is_some_and(|e| e != PowerEvent::Resumed) {}
+                    } => {}
+                }
+            }
+            set_banner(Some("Reconnecting after sleep..."));
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(RESUME_SETTLE) => {}
+            }
+            set_banner(None);
+            resume_attempts = RESUME_ATTEMPTS;
+            continue;
+        }
+        match result {
+            Ok(()) => resume_attempts = 0,
+            Err(e) => {
+                error!("Application error: {}", e);
+                // Devices can take a while to reappear after a wake-up
+                if resume_attempts > 0 {
+                    resume_attempts -= 1;
+                    warn!("Reconnecting after sleep failed, retrying...");
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = tokio::time::sleep(RESUME_SETTLE) => {}
+                    }
+                    continue;
+                }
+            }
+        }
+
+        // Pause instead of exiting when the session ended because of an unplug
+        match &mut watcher {
+            Some(watcher) if watcher.wait_offline(serial.as_deref(), UNPLUG_GRACE).await => {
+                waiting = "Device disconnected, plug it back in to resume";
+            }
+            _ => break,
+        }
+    }
+}
+
+pub(crate) async fn run_app(
+    mut config: Config,
+    frame_tx: FrameSender,
+    control_rx: &mut tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    shutdown: CancellationToken,
+    go_wireless: Arc<Notify>,
+    api_state: &ApiState,
+) -> Result<()> {
+    // The capture stands in for the device, there is nothing to start
+    if config.connection.replay.is_some() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], config.connection.port));
+        return run_with_connection(
+            addr, &mut None, config, frame_tx, control_rx, shutdown, api_state,
+        )
+        .await;
+    }
+
+    // Attempt to auto-start server via ADB
+    info!("Checking matching scrcpy-server via ADB...");
+    let mut tunnel = None;
+    // Kept until the session ends so the forward and server can be removed
+    let mut server = None;
+
+    match ServerManager::new().await {
+        Ok(mut manager) => {
+            let serial = config.connection.adb_serial();
+
+            match manager.start_server(&config, serial.as_deref()).await {
+                Ok(started) => {
+                    info!("Server setup successful via ADB!");
+                    tunnel = Some(started);
+                }
+                // Dialing the configured port would only fail more confusingly
+                Err(e) if e.is::<PortInUse>() => return Err(e),
+                Err(e) => warn!("ADB Server setup failed: {}.", e),
+            }
+            server = Some(manager);
+        }
+        Err(e) => {
+            warn!("Could not connect to ADB: {}. Proceeding without ADB.", e);
+        }
+    }
+
+    // If ADB setup was successful, we MUST connect to localhost because we used 'adb forward'
+    let mut listener = None;
+    match tunnel {
+        Some(Tunnel::Forward(port)) => {
+            info!(
+                "Redirecting connection to localhost:{} (tunnel via ADB)",
+                port
+            );
+            config.connection.host = "127.0.0.1".to_string();
+            config.connection.port = port;
+        }
+        Some(Tunnel::Reverse(reverse)) => {
+            info!("Waiting for the server to connect (adb reverse)");
+            // Nothing to dial, but switching must not try the device directly
+            config.connection.host = "127.0.0.1".to_string();
+            listener = Some(reverse);
+        }
+        None => {}
+    }
+
+    let addr = server_addr(&config, config.connection.mode.into()).await?;
+    info!("Connecting to {}...", addr);
+
+    info!("Using {:?} connection", config.connection.mode);
+    let mut adb = server.map(|server| AdbSession {
+        server,
+        listener,
+        go_wireless,
+    });
+    let sleep_on_idle = config.server.sleep_on_idle;
+    let mut result = run_with_connection(
+        addr, &mut adb, config, frame_tx, control_rx, shutdown, api_state,
+    )
+    .await;
+
+    // After a WiFi handover this is the wireless server
+    if let Some(mut session) = adb {
+        result = result.map_err(|e| session.server.explain(e));
+        if let Err(e) = session.server.stop().await {
+            warn!("Device cleanup failed: {}", e);
+        }
+        if sleep_on_idle && api_state.session.idle_expired() {
+            info!("Turning the device display off");
+            if let Err(e) = session.server.sleep_display().await {
+                warn!("Could not turn the display off: {}", e);
+            }
+        }
+    }
+    result
+}
+
+async fn run_with_connection(
+    addr: SocketAddr,
+    adb: &mut Option<AdbSession>,
+    mut config: Config,
+    frame_tx: FrameSender,
+    control_rx: &mut tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    shutdown: CancellationToken,
+    api_state: &ApiState,
+) -> Result<()> {
+    let fec = &api_state.fec;
+    let mode: network::ConnectionMode = config.connection.mode.into();
+    if config.connection.encrypt_payloads && config.connection.auth_token.is_none() {
+        tracing::warn!("Payload encryption needs an auth_token, sending payloads unencrypted");
+    }
+    let listener = adb.as_mut().and_then(|session| session.listener.take());
+    let mut connection = match (listener, config.connection.replay.clone()) {
+        (_, Some(path)) => open_replay(&path, &mut config)?,
+        // Reverse tunnels carry plain TCP; there is nothing to dial
+        (Some(listener), None) => {
+            let mut connection: Box<dyn Connection> = Box::new(
+                TcpConnection::accept(listener, config.audio.enabled)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Server did not connect: {}", e))?,
+            );
+            info!("Connected successfully!");
+            connection.apply_performance_config(&config.performance);
+            connection
+        }
+        (None, None) => open_connection(mode, addr, &config).await?,
+    };
+    if let Some(name) = connection.device_name() {
+        info!("Mirroring {}", name);
+        api_state.session.set_device_name(Some(name.to_string()));
+    }
+    let events = &api_state.events;
+    events.publish(SessionEvent::Connected {
+        mode: config.connection.mode,
+    });
+
+    // Switching relaunches the server over adb and probes adbd and the QUIC
+    // server, so it needs the device's own address rather than a tunnel
+    let mut switcher = if config.connection.auto_switch {
+        if addr.ip().is_loopback() {
+            warn!(
+                "Transport switching needs a wireless connection, staying on {:?}",
+                mode
+            );
+            None
+        } else {
+            Some(TransportSwitcher::new(mode))
+        }
+    } else {
+        None
+    };
+    let (probe_tx, mut probe_rx) = tokio::sync::mpsc::unbounded_channel();
+    let adbd = adbd_addr(&config, addr);
+    // Server launched for a switch to TCP, stopped when the stream moves on
+    let mut relaunched: Option<ServerManager> = None;
+
+    // Initialize Decoders
+    let output_format = PixelFormat::RGBA; // WGPU prefers RGBA usually
+
+    // QUIC streams carry no codec header, those use the configured codec
+    let codec = connection.video_codec().unwrap_or(config.video.codec);
+    events.publish(SessionEvent::HandshakeComplete {
+        device_name: connection.device_name().map(str::to_string),
+        codec,
+    });
+    api_state.tap.set_codec(codec);
+    // Everything received, for `replay`
+    let mut dump = match &config.connection.dump_session {
+        Some(path) => {
+            let header = CaptureHeader {
+                video_codec: Some(codec),
+                audio_codec: config.audio.enabled.then_some(config.audio.codec),
+                device_name: connection.device_name().map(str::to_string),
+            };
+            info!("Recording the session to {}", path.display());
+            Some(
+                CaptureWriter::create(path, &header)
+                    .with_context(|| format!("Cannot create {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+    // Stopped with the session; the next one announces its own stream
+    let share_stop = shutdown.child_token();
+    let _share_guard = share_stop.clone().drop_guard();
+    let share = match config.restream.share {
+        Some(listen) => {
+            let header = network::relay::StreamHeader {
+                device_name: connection.device_name().map(str::to_string),
+                video_codec: codec,
+                audio_codec: config.audio.enabled.then_some(config.audio.codec),
+            };
+            let secret = SharedSecret::for_relay(&config.connection);
+            start_share(listen, header, secret, share_stop).await
+        }
+        None => None,
+    };
+    // Third-party hooks, loaded fresh for each session
+    let plugins = match &config.plugins.dir {
+        Some(dir) => PluginHost::load_dir(dir),
+        None => PluginHost::new(),
+    };
+    // Outputs for other apps ride along with the plugins, off the decode thread
+    if let Some(name) = &config.output.ndi {
+        match sink::ndi_output(name) {
+            Ok(output) => plugins.register(Box::new(output)),
+            Err(e) => warn!("NDI output unavailable: {:#}", e),
+        }
+    }
+    // Decoding runs on its own thread so a slow frame never stalls socket reads
+    let mut video_decoder = DecodeWorker::spawn(
+        &config.video.hw_decoder,
+        codec,
+        output_format,
+        frame_tx,
+        plugins.clone(),
+        api_state.latency.clone(),
+        DECODE_QUEUE_PACKETS,
+    )?;
+
+    // Initialize Audio for the codec negotiated with the server (48kHz stereo)
+    let mut audio_decoder = HardwareAudioDecoder::new(config.audio.codec.to_server_arg(), 48000, 2);
+
+    let mut audio_player = if audio_decoder.is_ok() {
+        match AudioPlayer::new(48000, 2, config.performance.jitter_buffer_ms) {
+            // 50ms jitter buffer
+            Ok(player) => Some(player),
+            Err(e) => {
+                warn!("Failed to initialize audio player: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Outbound control messages, prioritized and rate-limited
+    let mut control_queue = ControlQueue::default();
+
+    // Device vibrations are opt-in: the server only reports them when asked
+    let mut haptics = Haptics::new(config.input.haptics);
+    if haptics.enabled() {
+        control_queue.push(ControlMessage::SetHapticsEnabled(true));
+    }
+
+    // The UHID keyboard lives as long as the server, so each one needs it made
+    let uhid_keyboard = config.input.keyboard == KeyboardMode::Uhid;
+    if uhid_keyboard {
+        control_queue.push(keyboard::uhid_create());
+    }
+
+    // Kiosk and demo setups open their app once the mirror is up
+    if let Some(package) = &config.server.start_app {
+        info!("Starting {}", package);
+        control_queue.push(ControlMessage::StartApp {
+            package: package.clone(),
+            force_stop: config.server.force_stop_app,
+        });
+    }
+
+    // QA sessions: log what was pressed when, or play an earlier log back
+    let mut event_log = config
+        .input
+        .event_log
+        .as_deref()
+        .map(EventLog::create)
+        .transpose()?;
+    let mut replay = match &config.input.replay {
+        Some(path) => {
+            let events = EventLog::read(path)?;
+            info!(
+                "Replaying {} input events from {}",
+                events.len(),
+                path.display()
+            );
+            Some(EventReplay::new(events))
+        }
+        None => None,
+    };
+    let mut last_video_pts = None;
+    let mut stream_corrupted = false;
+    let mut last_stats_tick = Instant::now();
+
+    // One row per stats tick for flaky-session post-mortems
+    let mut stats_log = config
+        .display
+        .stats_log
+        .as_deref()
+        .map(StatsLog::create)
+        .transpose()?;
+    let session_started = Instant::now();
+    let mut last_audio_pts = None;
+    // Video frames and payload bytes since the last stats tick
+    let (mut tick_frames, mut tick_bytes) = (0u64, 0u64);
+
+    // FEC chosen at runtime, re-applied when the connection is replaced
+    let mut manual_fec = None;
+
+    // Consecutive reconnects before giving up (QUIC resumes these with 0-RTT)
+    const MAX_RECONNECT_ATTEMPTS: u32 = 3;
+    let mut reconnect_attempts = 0;
+
+    // Stalled video: "no signal", keyframe requests, then a reconnect (not for
+    // replays, whose captures may have gaps)
+    let stall_timeout = config.connection.stall_timeout;
+    let mut watchdog = (config.video.enabled && config.connection.replay.is_none()).then(|| {
+        let reconnect_after =
+            (stall_timeout > 0).then(|| Duration::from_secs(stall_timeout.into()));
+        StreamWatchdog::new(reconnect_after, Instant::now())
+    });
+
+    // USB -> WiFi handover in progress
+    let mut handover: Option<JoinHandle<Result<(ServerManager, Box<dyn Connection>)>>> = None;
+    // Only sessions started over ADB can move to WiFi
+    let go_wireless = adb.as_ref().map(|session| session.go_wireless.clone());
+
+    // Main receive loop
+    info!("Starting receive loop...");
+    loop {
+        // Shutdown must not wait for the next packet, which may never come
+        let stall = watchdog.as_ref().and_then(StreamWatchdog::deadline);
+        // Input must not wait for the next packet either, a static screen sends none
+        let next_control = control_queue.next_ready();
+        let next_replayed = replay.as_ref().and_then(EventReplay::next_due);
+        let received = tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => {
+                info!("Shutdown signal received");
+                break;
+            }
+            _ = wireless_requested(go_wireless.as_deref()), if handover.is_none() => {
+                if let Some(session) = adb.as_mut() {
+                    match session.server.pin_serial().await {
+                        // Wireless serials are ip:port
+                        Ok(serial) if serial.contains(':') => info!("Already connected over WiFi"),
+                        Ok(serial) => {
+                            handover =
+                                Some(tokio::spawn(start_wireless_session(serial, config.clone())));
+                        }
+                        Err(e) => warn!("WiFi handover unavailable: {}", e),
+                    }
+                }
+                continue;
+            }
+            result = handover_finished(&mut handover) => {
+                handover = None;
+                if complete_handover(result, &mut connection, adb).await {
+                    reconnect_attempts = 0;
+                    // Fresh server: new stream and none of the session opt-ins
+                    control_queue.push(ControlMessage::RequestKeyframe);
+                    if haptics.enabled() {
+                        control_queue.push(ControlMessage::SetHapticsEnabled(true));
+                    }
+                    if uhid_keyboard {
+                        control_queue.push(keyboard::uhid_create());
+                    }
+                    if let Some(setting) = manual_fec {
+                        fec.request(setting);
+                    }
+                }
+                continue;
+            }
+            Some(msg) = control_rx.recv() => {
+                control_queue.push(msg);
+                send_controls(
+                    &mut control_queue,
+                    control_rx,
+                    &mut replay,
+                    connection.as_mut(),
+                    &plugins,
+                    &mut event_log,
+                    last_video_pts,
+                )
+                .await;
+                continue;
+            }
+            _ = tokio::time::sleep_until(next_control.unwrap_or_else(Instant::now).into()),
+                if next_control.is_some() =>
+            {
+                send_controls(
+                    &mut control_queue,
+                    control_rx,
+                    &mut replay,
+                    connection.as_mut(),
+                    &plugins,
+                    &mut event_log,
+                    last_video_pts,
+                )
+                .await;
+                continue;
+            }
+            _ = tokio::time::sleep_until(next_replayed.unwrap_or_else(Instant::now).into()),
+                if next_replayed.is_some() =>
+            {
+                send_controls(
+                    &mut control_queue,
+                    control_rx,
+                    &mut replay,
+                    connection.as_mut(),
+                    &plugins,
+                    &mut event_log,
+                    last_video_pts,
+                )
+                .await;
+                continue;
+            }
+            received = connection.recv() => received,
+            _ = tokio::time::sleep_until(stall.unwrap_or_else(Instant::now).into()),
+                if stall.is_some() =>
+            {
+                match watchdog.as_mut().and_then(|w| w.poll(Instant::now())) {
+                    Some(WatchdogAction::NoSignal) => {
+                        warn!("No video received for {:?}", network::watchdog::NO_SIGNAL_AFTER);
+                        events.publish(SessionEvent::StreamStalled);
+                        continue;
+                    }
+                    Some(WatchdogAction::RequestKeyframe) => {
+                        info!("Still no video, requesting a keyframe");
+                        control_queue.push(ControlMessage::RequestKeyframe);
+                        send_controls(
+                            &mut control_queue,
+                            control_rx,
+                            &mut replay,
+                            connection.as_mut(),
+                            &plugins,
+                            &mut event_log,
+                            last_video_pts,
+                        )
+                        .await;
+                        continue;
+                    }
+                    Some(WatchdogAction::Reconnect) => {
+                        warn!("No video for {} s, reconnecting", stall_timeout);
+                        Err(NetworkError::Timeout)
+                    }
+                    None => continue,
+                }
+            }
+        };
+
+        let received_at = Instant::now();
+        let packet = match received {
+            Ok(p) => {
+                reconnect_attempts = 0;
+                p
+            }
+            // adbd restarting for WiFi takes the USB tunnel down with it
+            Err(e) if handover.is_some() => {
+                info!("USB link closed ({}), waiting for WiFi", e);
+                let Some(task) = handover.take() else {
+                    continue;
+                };
+                let result = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    result = task => result.map_err(anyhow::Error::from).and_then(|r| r),
+                };
+                if !complete_handover(result, &mut connection, adb).await {
+                    break;
+                }
+                reconnect_attempts = 0;
+                control_queue.push(ControlMessage::RequestKeyframe);
+                if haptics.enabled() {
+                    control_queue.push(ControlMessage::SetHapticsEnabled(true));
+                }
+                if uhid_keyboard {
+                    control_queue.push(keyboard::uhid_create());
+                }
+                if let Some(setting) = manual_fec {
+                    fec.request(setting);
+                }
+                continue;
+            }
+            // A replay ends with its capture
+            Err(NetworkError::ConnectionClosed) if config.connection.replay.is_some() => {
+                info!("Replay finished");
+                break;
+            }
+            Err(e) if reconnect_attempts < MAX_RECONNECT_ATTEMPTS => {
+                reconnect_attempts += 1;
+                events.publish(SessionEvent::Reconnecting {
+                    attempt: reconnect_attempts,
+                });
+                // Brief WiFi drops usually clear within a second, back off a little each time
+                if reconnect_attempts > 1 {
+                    let backoff = Duration::from_millis(250 * reconnect_attempts as u64);
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = tokio::time::sleep(backoff) => {}
+                    }
+                }
+                warn!(
+                    "Receive error: {}, reconnecting ({})",
+                    e, reconnect_attempts
+                );
+                match connection.reconnect().await {
+                    Ok(()) => {
+                        // The decoder lost its reference frames with the old connection
+                        control_queue.push(ControlMessage::RequestKeyframe);
+                        if let Some(watchdog) = &mut watchdog {
+                            watchdog.on_reconnect(Instant::now());
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Reconnect failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Receive error: {}", e);
+                break;
+            }
+        };
+        tick_bytes += packet.data.len() as u64;
+        if let Some(writer) = &mut dump {
+            if let Err(e) = writer.write(&packet) {
+                warn!("Failed to write session dump, disabling it: {}", e);
+                dump = None;
+            }
+        }
+        if plugins.on_packet(&packet) == Verdict::Drop {
+            continue;
+        }
+        if let Some(share) = &share {
+            share.publish(&packet);
+        }
+
+        match packet.packet_type {
+            PacketType::Video if packet.flags.config => {
+                api_state.tap.publish(&packet);
+                api_state.replay.set_config(codec, packet.data.clone());
+                video_decoder.set_config(packet.data);
+            }
+            PacketType::Audio if packet.flags.config => {
+                if let Ok(decoder) = &mut audio_decoder {
+                    if let Err(e) = decoder.set_config(&packet.data) {
+                        error!("Audio config error: {}", e);
+                    }
+                }
+            }
+            PacketType::Video => {
+                let _span = tracing::trace_span!("parse", pts = packet.pts).entered();
+                let latency = &api_state.latency;
+                latency.mark_at(packet.pts, Mark::Received, received_at);
+                if watchdog.as_mut().is_some_and(|w| w.on_video(received_at)) {
+                    info!("Video resumed");
+                    events.publish(SessionEvent::StreamResumed);
+                }
+                last_video_pts = Some(packet.pts);
+                tick_frames += 1;
+                let keyframe = packet.is_keyframe();
+                api_state.tap.publish(&packet);
+                api_state.replay.push(ReplayPacket {
+                    pts: packet.pts,
+                    keyframe,
+                    data: packet.data.clone(),
+                });
+                let pts = packet.pts;
+                if video_decoder.decode(packet.data, pts, keyframe) {
+                    latency.mark(pts, Mark::Queued);
+                }
+                if video_decoder.receiver_gone() {
+                    error!("Failed to send frame to UI: receiver dropped");
+                    break; // UI thread likely dead
+                }
+                if video_decoder.corrupted() != stream_corrupted {
+                    stream_corrupted = !stream_corrupted;
+                    events.publish(if stream_corrupted {
+                        SessionEvent::StreamCorrupted
+                    } else {
+                        SessionEvent::StreamRecovered
+                    });
+                }
+                // Decode errors, queue overflows and re-streaming or sharing viewers
+                // joining; duplicates collapse in the queue
+                let viewer_wants_keyframe = api_state.tap.take_keyframe_request();
+                let share_wants_keyframe = share.as_ref().is_some_and(Relay::take_keyframe_request);
+                if video_decoder.take_keyframe_request()
+                    || viewer_wants_keyframe
+                    || share_wants_keyframe
+                {
+                    control_queue.push(ControlMessage::RequestKeyframe);
+                }
+                api_state.session.set_decode_queue(video_decoder.stats());
+            }
+            PacketType::Audio => {
+                last_audio_pts = Some(packet.pts);
+                if let (Ok(decoder), Some(player)) = (&mut audio_decoder, &mut audio_player) {
+                    match decoder.decode(&packet.data, packet.pts) {
+                        Ok(Some(audio_frame)) => {
+                            if let Err(e) = player.play(audio_frame) {
+                                error!("Audio playback error: {}", e);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("Audio decoding error: {}", e),
+                    }
+                }
+            }
+            PacketType::Control => match DeviceMessage::from_bytes(&packet.data) {
+                Ok(DeviceMessage::Vibrate {
+                    duration_ms,
+                    amplitude,
+                }) => haptics.vibrate(duration_ms, amplitude, audio_player.as_mut()),
+                // Only in reply to clipboard requests, which we don't make
+                Ok(DeviceMessage::Clipboard { .. } | DeviceMessage::AckClipboard { .. }) => {}
+                // Keyboard LED state; the PC keeps its own
+                Ok(DeviceMessage::UhidOutput { .. }) => {}
+                Err(e) => warn!("Ignoring unknown device message: {}", e),
+            },
+            PacketType::Handshake => {
+                info!("Received handshake packet");
+                // In a full impl, we'd parse device name/size here
+            }
+            PacketType::Fec | PacketType::Nack => {}
+        }
+
+        // Keyframe requests queued while handling the packet
+        send_controls(
+            &mut control_queue,
+            control_rx,
+            &mut replay,
+            connection.as_mut(),
+            &plugins,
+            &mut event_log,
+            last_video_pts,
+        )
+        .await;
+
+        if last_stats_tick.elapsed() >= STATS_TICK {
+            let interval = last_stats_tick.elapsed().as_secs_f64();
+            last_stats_tick = Instant::now();
+            let stats = connection.stats();
+            let decode_queue = video_decoder.stats();
+            api_state.latency.set_rtt(stats.rtt_ms);
+            events.publish(SessionEvent::StatsTick {
+                rtt_ms: stats.rtt_ms,
+                packet_loss: stats.packet_loss,
+                bandwidth_mbps: stats.bandwidth_mbps,
+                decode_queue,
+            });
+            if let Some(log) = &mut stats_log {
+                let row = StatsRow {
+                    unix_time: SystemTime::UNIX_EPOCH
+                        .elapsed()
+                        .unwrap_or_default()
+                        .as_secs_f64(),
+                    elapsed_s: session_started.elapsed().as_secs_f64(),
+                    fps: tick_frames as f64 / interval,
+                    bitrate_mbps: tick_bytes as f64 * 8.0 / interval / 1e6,
+                    rtt_ms: stats.rtt_ms,
+                    loss_percent: stats.packet_loss,
+                    drift_ms: last_video_pts
+                        .zip(last_audio_pts)
+                        .map(|(video, audio)| (video - audio) as f64 / 1000.0),
+                    frames_dropped: api_state.session.frames_dropped(),
+                    packets_dropped: decode_queue.dropped,
+                    decode_queue: decode_queue.depth,
+                    audio_buffer: audio_player.as_ref().map(AudioPlayer::buffer_level),
+                };
+                if let Err(e) = log.write(&row) {
+                    warn!("Failed to write stats log, disabling it: {:#}", e);
+                    stats_log = None;
+                }
+            }
+            (tick_frames, tick_bytes) = (0, 0);
+        }
+
+        if let Some(setting) = fec.take_request() {
+            match connection.set_fec(setting).await {
+                Ok(()) => {
+                    fec.applied(setting);
+                    manual_fec = Some(setting);
+                }
+                Err(e) => warn!("FEC change not applied: {}", e),
+            }
+        }
+
+        if let Some(switcher) = &mut switcher {
+            let now = Instant::now();
+            while let Ok((tcp, quic)) = probe_rx.try_recv() {
+                switcher.record(tcp, quic);
+            }
+
+            if switcher.probe_due(now) {
+                let probe_tx = probe_tx.clone();
+                tokio::spawn(async move {
+                    let _ = probe_tx.send(network::switcher::probe_links(adbd, addr).await);
+                });
+            }
+
+            if let Some(target) = switcher.should_switch(now) {
+                info!(
+                    "Switching transport {:?} -> {:?}",
+                    switcher.active(),
+                    target
+                );
+                match open_switched(target, addr, &config).await {
+                    Ok((next, server)) => {
+                        let mut previous = std::mem::replace(&mut connection, next);
+                        if let Err(e) = previous.close().await {
+                            warn!("Failed to close previous transport: {}", e);
+                        }
+                        if let Some(mut previous) = std::mem::replace(&mut relaunched, server) {
+                            if let Err(e) = previous.stop().await {
+                                warn!("Previous server cleanup failed: {}", e);
+                            }
+                        }
+                        switcher.switched(target, now);
+                        reconnect_attempts = 0;
+
+                        // The new stream starts mid-GOP and forgets per-session opt-ins
+                        control_queue.push(ControlMessage::RequestKeyframe);
+                        if haptics.enabled() {
+                            control_queue.push(ControlMessage::SetHapticsEnabled(true));
+                        }
+                        if uhid_keyboard {
+                            control_queue.push(keyboard::uhid_create());
+                        }
+                        if let Some(setting) = manual_fec {
+                            fec.request(setting);
+                        }
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Transport switch failed, staying on {:?}: {}",
+                            switcher.active(),
+                            e
+                        );
+                        switcher.switch_failed(now);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Err(e) = connection.close().await {
+        warn!("Failed to close connection: {}", e);
+    }
+    if let Some(mut server) = relaunched {
+        if let Err(e) = server.stop().await {
+            warn!("Device cleanup failed: {}", e);
+        }
+    }
+    if let Some(mut writer) = dump {
+        if let Err(e) = writer.flush() {
+            warn!("Failed to finish session dump: {}", e);
+        }
+    }
+
+    // Frames still inside the decoder are flushed to the UI (which may be gone)
+    let decode_queue = video_decoder.stats();
+    video_decoder.finish();
+    if decode_queue.dropped > 0 {
+        info!(
+            "Decode queue dropped {} packets (peak depth {})",
+            decode_queue.dropped, decode_queue.peak
+        );
+    }
+    if config.display.latency_report {
+        info!("{}", api_state.latency.report());
+    }
+    events.publish(SessionEvent::Disconnected);
+    info!("Connection closed");
+    Ok(())
+}
+
+/// Queue the input from the UI thread and from an input replay, then send
+/// whatever the rate limit allows
+async fn send_controls(
+    control_queue: &mut ControlQueue,
+    control_rx: &mut tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
+    replay: &mut Option<EventReplay>,
+    connection: &mut dyn Connection,
+    plugins: &PluginHost,
+    event_log: &mut Option<EventLog>,
+    last_video_pts: Option<i64>,
+) {
+    while let Ok(msg) = control_rx.try_recv() {
+        control_queue.push(msg);
+    }
+    if let Some(events) = replay {
+        for msg in events.poll(Instant::now()) {
+            control_queue.push(msg);
+        }
+        if events.is_finished() {
+            info!("Input replay finished");
+            *replay = None;
+        }
+    }
+
+    for msg in control_queue.drain_ready(Instant::now()) {
+        if plugins.on_control(&msg) == Verdict::Drop {
+            continue;
+        }
+        if let Some(log) = event_log {
+            if let Err(e) = log.log(&msg, last_video_pts) {
+                warn!("Failed to write input log, disabling it: {}", e);
+                *event_log = None;
+            }
+        }
+        if let Err(e) = connection.send_control(msg).await {
+            warn!("Failed to send control message: {}", e);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Reconnecting over the other transport for `connection.auto_switch`
+
+use super::handover::{start_forwarded_session, WIRELESS_ADB_PORT};
+use super::open_connection;
+use anyhow::Result;
+use scrcpy_custom::{
+    config::Config,
+    network::{self, Connection},
+    server::ServerManager,
+};
+use std::net::SocketAddr;
+
+/// Open the stream over `mode` to take over from the active transport
+///
+/// scrcpy-server serves one session per launch, so a TCP stream comes from
+/// a server relaunched over adb, returned to be stopped once the stream
+/// moves on. QUIC dials the device at `addr` again.
+pub(crate) async fn open_switched(
+    mode: network::ConnectionMode,
+    addr: SocketAddr,
+    config: &Config,
+) -> Result<(Box<dyn Connection>, Option<ServerManager>)> {
+    match mode {
+        network::ConnectionMode::Quic => Ok((open_connection(mode, addr, config).await?, None)),
+        network::ConnectionMode::Tcp => {
+            let serial = config.connection.adb_serial();
+            let server = ServerManager::new().await?;
+            let (server, connection) =
+                start_forwarded_session(server, serial.as_deref(), config.clone()).await?;
+            Ok((connection, Some(server)))
+        }
+    }
+}
+
+/// Where adbd listens on a device reached directly at `addr`
+pub(crate) fn adbd_addr(config: &Config, addr: SocketAddr) -> SocketAddr {
+    config
+        .connection
+        .adb_serial()
+        .and_then(|serial| serial.parse().ok())
+        .unwrap_or_else(|| SocketAddr::new(addr.ip(), WIRELESS_ADB_PORT))
+}
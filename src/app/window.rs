@@ -0,0 +1,1178 @@
+//! The mirror window and its event loop
+
+use super::audio::negotiate_audio_codec;
+use super::device_model;
+use super::grid::run_grid;
+use super::session::{run_sessions, STATS_TICK};
+use anyhow::Result;
+use scrcpy_custom::{
+    api::{ApiState, SessionInfo},
+    assets::Assets,
+    config::{Config, VideoCodec},
+    events::{EventBus, SessionEvent},
+    input::{
+        game_map::{self, GameProfile},
+        gesture, GameMapper, GameProfiles, GestureKind, IdleTimer, KeyForwarder, MouseGestures,
+        MoveCoalescer, TouchForwarder, POINTER_ID_MOUSE,
+    },
+    network::{ControlMessage, FecControl, FecSetting, TouchAction},
+    restream::PacketTap,
+    ui::{
+        show_banner, theme, CaptionSource, CaptionTrack, DeviceMonitor, DeviceStatus, FileBrowser,
+        GeometryStore, GuideOverlay, GuideProfiles, LogcatPanel, OnScreenKeyboard, PixelInspector,
+        SettingsPanel, ShellPane, StatsOverlay, Toasts, WindowGeometry,
+    },
+    video::{
+        calibration::ColorProfiles,
+        decoder::{DecodedFrame, HardwareVideoDecoder},
+        latency::{LatencyTracer, Mark},
+        mailbox::frame_channel,
+        renderer::VideoRenderer,
+        replay::ReplayBuffer,
+        snapshot::{self, FrameBurst},
+    },
+};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use winit::{
+    event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    window::Window,
+};
+
+/// Time the network thread gets to close the connection and flush decoders on exit
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// How often the foreground app is checked for a matching game profile
+const APP_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub(crate) fn run(mut config: Config, flag_geometry: WindowGeometry) -> Result<()> {
+    // Setup Winit Event Loop
+    let event_loop = EventLoop::new().unwrap();
+
+    // adb serial of the device (wireless devices are addressed by host)
+    let device_serial = config.connection.adb_serial();
+
+    // Flags first, then where the window was last closed for this device
+    let geometry_store = config
+        .display
+        .remember_window
+        .then(|| GeometryStore::for_device(device_serial.as_deref().unwrap_or("usb")));
+    let geometry = match &geometry_store {
+        Some(store) => flag_geometry.or(store.load()),
+        None => flag_geometry,
+    };
+
+    // Create window using winit 0.30 API
+    let window_attributes = geometry.apply(
+        Window::default_attributes()
+            .with_title("scrcpy-custom")
+            .with_inner_size(winit::dpi::LogicalSize::new(1024.0, 576.0)),
+    );
+
+    let window = event_loop.create_window(window_attributes).unwrap();
+
+    // Initialize Video Renderer
+    let mut renderer = VideoRenderer::new(&window)?;
+    renderer.set_adaptive_resolution(config.display.adaptive_resolution);
+    renderer.set_present_mode(config.video.present_mode);
+    renderer.set_scaling(config.display.scaling)?;
+    renderer.set_upscale_filter(config.display.upscale_filter)?;
+    theme::apply(
+        renderer.ui_context(),
+        config.display.theme,
+        config.display.ui_scale,
+    );
+
+    if let Some(path) = &config.display.color_profiles {
+        match ColorProfiles::load(path) {
+            Ok(profiles) => {
+                renderer.set_calibration(&profiles.for_device(device_serial.as_deref()))
+            }
+            Err(e) => warn!("Color calibration disabled: {}", e),
+        }
+    }
+
+    // Alignment guides for UI review
+    let guide_profiles = match &config.display.guide_profiles {
+        Some(path) => match GuideProfiles::load(path) {
+            Ok(profiles) => Some(profiles),
+            Err(e) => {
+                warn!("Guide profiles not loaded: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    // Without adb the handshake device name stands in for the model later
+    let model = guide_profiles
+        .as_ref()
+        .and_then(|_| device_model(device_serial.as_deref()));
+    let guide_profile = guide_profiles.as_ref().map_or_else(
+        || GuideProfiles::default().for_device(None, None),
+        |profiles| profiles.for_device(device_serial.as_deref(), model.as_deref()),
+    );
+    let mut guides = GuideOverlay::new(guide_profile, config.display.guides);
+
+    // Keyboard-to-touch mapping for games, by profile name
+    let game_profiles = match &config.input.game_profiles {
+        Some(path) => match GameProfiles::load(path) {
+            Ok(profiles) => Some(profiles),
+            Err(e) => {
+                warn!("Game mapping disabled: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    // A profile named on the command line stays; otherwise it follows the app
+    let follow_app = game_profiles.is_some() && config.input.game_profile.is_none();
+    let start_app = follow_app
+        .then(|| foreground_app(device_serial.as_deref()))
+        .flatten();
+    let mut game: Option<(String, GameMapper)> = None;
+    if let Some(profiles) = &game_profiles {
+        let name = config.input.game_profile.as_deref();
+        match profiles.select(name, start_app.as_deref()) {
+            Some((name, profile)) => {
+                info!("Game profile: {}", name);
+                game = Some((name.to_string(), GameMapper::new(profile.clone())));
+            }
+            None => warn!(
+                "No game profile for {}",
+                name.or(start_app.as_deref()).unwrap_or("this app")
+            ),
+        }
+    }
+
+    // Decoded frames, handed from the network thread to the UI thread
+    let (frame_tx, frame_rx) = frame_channel(
+        config.performance.frame_drop,
+        Duration::from_millis(config.performance.jitter_buffer_ms.into()),
+    );
+
+    // Channel to forward input from the UI thread to the network thread
+    let (control_tx, control_rx) = tokio::sync::mpsc::unbounded_channel::<ControlMessage>();
+
+    // Mouse state: cursor in window pixels, pressed position in video pixels
+    let mut cursor: Option<(f64, f64)> = None;
+    let mut pressed_at: Option<(u32, u32)> = None;
+    // Ctrl or Shift turn mouse drags into two-finger gestures
+    let mut modifiers = ModifiersState::empty();
+    let mut gestures = MouseGestures::new();
+    let mut coalescer = MoveCoalescer::new();
+    let mut touches = TouchForwarder::new();
+
+    // Last frame on screen, kept so the overlay can redraw without a new one
+    let mut shown_frame: Option<DecodedFrame> = None;
+    let mut overlay_dirty = false;
+    let mut inspector = PixelInspector::new();
+    let mut shown_banner: Option<String> = None;
+    // No video for a while, or decode errors until the next clean keyframe
+    let (mut stream_stalled, mut stream_corrupted) = (false, false);
+
+    // Frame burst in progress (F12)
+    let mut burst: Option<FrameBurst> = None;
+    let screenshot_dir = config.display.screenshot_dir.clone();
+    let burst_frames = config.display.burst_frames;
+
+    // Accessibility captions, read straight from adb on the UI thread
+    let (caption_source, mut captions) = if config.display.captions {
+        let source = CaptionSource::spawn(device_serial.as_deref())
+            .map_err(|e| warn!("Captions unavailable: {}", e))
+            .ok();
+        let track = CaptionTrack::new(config.display.captions_srt.as_deref())?;
+        (source, Some(track))
+    } else {
+        (None, None)
+    };
+
+    // Set by the UI to move a USB session to WiFi (F7)
+    let go_wireless = Arc::new(Notify::new());
+    let network_go_wireless = go_wireless.clone();
+
+    // FEC changes from the settings panel (F6) and the control API
+    let fec = FecControl::new(FecSetting {
+        enabled: config.performance.fec_redundancy > 0,
+        redundancy: config.performance.fec_redundancy,
+    });
+    let mut settings = SettingsPanel::new(fec.clone(), config.display.upscale_filter);
+
+    // Filled in by the network thread once the device introduces itself
+    let session = SessionInfo::new();
+    // What happens in the session: the UI reacts to it, the API lists it
+    let events = EventBus::new();
+    let mut event_rx = events.subscribe();
+    // Recent encoded video, saved as an MP4 on F4 or through the API
+    let replay = ReplayBuffer::new(
+        Duration::from_secs(config.display.replay_seconds as u64),
+        config.display.screenshot_dir.clone(),
+    );
+    // Where frames spend their time, reported on Shift+F9 and by --latency-report
+    let latency = LatencyTracer::new();
+    renderer.set_latency_tracer(latency.clone());
+    let api_state = ApiState {
+        fec,
+        session: session.clone(),
+        events: events.clone(),
+        replay: replay.clone(),
+        tap: PacketTap::new(),
+        latency: latency.clone(),
+    };
+
+    // Ends a forgotten session (no input, static screen)
+    let mut idle = config
+        .server
+        .idle_timeout
+        .map(|minutes| IdleTimer::new(Duration::from_secs(minutes as u64 * 60), Instant::now()));
+
+    // On-screen keyboard for touch-only setups (F10)
+    let mut keyboard = OnScreenKeyboard::new(config.display.keyboard);
+
+    // Device log panel for app debugging (F1)
+    let mut logcat = LogcatPanel::new(
+        device_serial.clone(),
+        config.display.screenshot_dir.clone(),
+        config.display.logcat,
+    );
+
+    // Interactive adb shell (Shift+F1)
+    let mut shell = ShellPane::new(device_serial.clone());
+
+    // Device file browser (F2)
+    let mut files = FileBrowser::new(device_serial.clone(), config.display.downloads_dir.clone());
+
+    // Battery, temperature and WiFi signal for the title bar
+    let mut status_monitor = config
+        .display
+        .device_status
+        .then(|| DeviceMonitor::spawn(device_serial.clone(), config.display.overheat_celsius));
+    let mut title_name: Option<String> = None;
+    // Resolution, fps and latency, refreshed in the title every second
+    let show_stats = config.display.show_stats;
+    let mut live_stats = StatsOverlay::new();
+    let mut shown_title = String::new();
+    let mut title_refreshed = Instant::now();
+    let mut title_dirty = false;
+
+    // Fading notifications for session events
+    let mut toasts = Toasts::new(config.display.toasts);
+
+    // Physical keyboard, forwarded as key events or a UHID keyboard
+    let mut keys = KeyForwarder::new(config.input.keyboard);
+
+    // Status shown over the video while there is no session (hotplug)
+    let banner: Arc<Mutex<Option<String>>> = Arc::default();
+    let network_banner = banner.clone();
+
+    // Shutdown signal
+    let shutdown = CancellationToken::new();
+    let network_shutdown = shutdown.clone();
+    let ui_shutdown = shutdown.clone();
+
+    // Foreground app changes, for switching game profiles
+    let (app_tx, app_rx) = std::sync::mpsc::channel::<String>();
+    if follow_app {
+        let (serial, shutdown) = (device_serial.clone(), shutdown.clone());
+        thread::spawn(move || {
+            let mut last = start_app;
+            while !shutdown.is_cancelled() {
+                thread::sleep(APP_POLL_INTERVAL);
+                // adb hiccups keep the current profile
+                let Some(app) = foreground_app(serial.as_deref()) else {
+                    continue;
+                };
+                if last.as_ref() != Some(&app) {
+                    last = Some(app.clone());
+                    if app_tx.send(app).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    // Spawn Network/Decoding Thread
+    let network = thread::spawn(move || {
+        // Create a new Tokio runtime for async network operations
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            negotiate_audio_codec(&mut config);
+
+            // Only ask the server for a codec we can decode
+            if !HardwareVideoDecoder::supports(config.video.codec) {
+                warn!(
+                    "No local {:?} decoder, requesting H.264 from the server instead.",
+                    config.video.codec
+                );
+                config.video.codec = VideoCodec::H264;
+            }
+
+            // Setup (ADB, connecting) has nothing to clean up; the receive loop
+            // closes the connection itself once cancelled
+            let sessions = async {
+                if config.display.grid {
+                    run_grid(config, frame_tx, control_rx, network_shutdown.clone()).await
+                } else {
+                    run_sessions(
+                        config,
+                        frame_tx,
+                        control_rx,
+                        network_shutdown.clone(),
+                        network_go_wireless,
+                        network_banner,
+                        api_state,
+                    )
+                    .await
+                }
+            };
+            tokio::select! {
+                _ = sessions => {}
+                _ = async {
+                    network_shutdown.cancelled().await;
+                    tokio::time::sleep(SHUTDOWN_GRACE).await;
+                } => warn!("Network thread did not stop in time"),
+            }
+        });
+    });
+
+    // Run Event Loop
+    let _ = event_loop.run(move |event, target| {
+        target.set_control_flow(ControlFlow::Poll); // Check for events continuously
+
+        // Clicks on overlay UI stay with the overlay
+        if let Event::WindowEvent { event, .. } = &event {
+            let consumed = renderer.on_window_event(event);
+            if let Some(idle) = &mut idle {
+                if is_pointer_input(event) || matches!(event, WindowEvent::KeyboardInput { .. }) {
+                    idle.input(Instant::now());
+                }
+            }
+            // Open panels need redraws to react to the pointer and to Tab navigation
+            let panel_open = settings.is_open()
+                || keyboard.is_open()
+                || files.is_open()
+                || logcat.is_open()
+                || shell.is_open();
+            if panel_open
+                && (is_pointer_input(event) || matches!(event, WindowEvent::KeyboardInput { .. }))
+            {
+                overlay_dirty = true;
+            }
+            if consumed && is_pointer_input(event) {
+                return;
+            }
+        }
+
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                if let Some(track) = &mut captions {
+                    if let Err(e) = track.finish(Instant::now()) {
+                        warn!("Failed to write captions: {}", e);
+                    }
+                }
+                ui_shutdown.cancel();
+                target.exit();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F5),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let _ = control_tx.send(ControlMessage::RotateDevice);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F6),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                settings.toggle();
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F7),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                info!("Moving to WiFi...");
+                go_wireless.notify_one();
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F8),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                guides.toggle();
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F10),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                keyboard.toggle();
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F2),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                files.toggle();
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F1),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if modifiers.shift_key() {
+                    shell.toggle();
+                } else {
+                    logcat.toggle();
+                }
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::DroppedFile(path),
+                ..
+            } => {
+                // Dropped files go to the folder the file panel shows
+                if path.is_file() {
+                    if !files.is_open() {
+                        files.toggle();
+                    }
+                    files.push(path);
+                    overlay_dirty = true;
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F9),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if modifiers.shift_key() => {
+                info!("{}", latency.report());
+                let toast = match latency.median_ms() {
+                    Some(ms) => format!("Latency {:.0} ms + network, breakdown in the log", ms),
+                    None => "No frames traced yet".to_string(),
+                };
+                toasts.push(toast, Instant::now());
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F9),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let enabled = inspector.toggle();
+                info!("Pixel inspector {}", if enabled { "on" } else { "off" });
+                overlay_dirty = true;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F3),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Cycle through the game profiles, then mapping off
+                if let Some(profiles) = &game_profiles {
+                    let current = game.as_ref().map(|(name, _)| name.as_str());
+                    let next = profiles.next(current);
+                    let name = next.map_or("off", |(name, _)| name);
+                    info!("Game profile: {}", name);
+                    toasts.push(format!("Game profile: {}", name), Instant::now());
+                    overlay_dirty = true;
+                    switch_game(&mut game, next, renderer.current_video_size(), &control_tx);
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F4),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if replay.is_enabled() {
+                    let replay = replay.clone();
+                    let events = events.clone();
+                    thread::spawn(move || match replay.save() {
+                        Ok(path) => {
+                            info!("Replay saved to {}", path.display());
+                            events.publish(SessionEvent::ReplaySaved { path });
+                        }
+                        Err(e) => warn!("Replay failed: {:#}", e),
+                    });
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F11),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Encoding a large PNG would stall a few frames
+                if let Some(frame) = renderer.last_frame().cloned() {
+                    let dir = screenshot_dir.clone();
+                    let events = events.clone();
+                    thread::spawn(move || match snapshot::save_screenshot(&frame, &dir) {
+                        Ok(path) => {
+                            info!("Screenshot saved to {}", path.display());
+                            events.publish(SessionEvent::ScreenshotSaved { path });
+                        }
+                        Err(e) => warn!("Screenshot failed: {:#}", e),
+                    });
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(KeyCode::F12),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if burst.is_none() {
+                    match FrameBurst::start(&screenshot_dir, burst_frames) {
+                        Ok(started) => {
+                            info!("Capturing {} frames...", burst_frames);
+                            events.publish(SessionEvent::RecordingStarted {
+                                path: started.dir().to_path_buf(),
+                            });
+                            burst = Some(started);
+                        }
+                        Err(e) => warn!("Frame burst failed: {}", e),
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(code),
+                                state,
+                                repeat,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = state == ElementState::Pressed;
+                // Keys bound by the game profile never reach the device as keys
+                let mapped = match (&mut game, renderer.current_video_size()) {
+                    (Some((_, mapper)), Some(size)) if !repeat => {
+                        mapper.key(&format!("{:?}", code), pressed, size)
+                    }
+                    _ => None,
+                };
+                let msgs = match mapped {
+                    Some(msgs) => msgs,
+                    // Open panels take typing for themselves
+                    None if settings.is_open()
+                        || keyboard.is_open()
+                        || files.is_open()
+                        || renderer.ui_context().wants_keyboard_input() =>
+                    {
+                        Vec::new()
+                    }
+                    None => keys.key(code, pressed, repeat),
+                };
+                for msg in msgs {
+                    let _ = control_tx.send(msg);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } => {
+                // Key ups go to the window that has focus by then
+                for msg in keys.release_all() {
+                    let _ = control_tx.send(msg);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                let _ = renderer.resize(size.width, size.height);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                cursor = Some((position.x, position.y));
+                if gestures.is_active() {
+                    if let Some(pos) = renderer.window_to_video(position.x, position.y) {
+                        for msg in gestures.drag(pos) {
+                            for msg in coalescer.push(msg, Instant::now()) {
+                                let _ = control_tx.send(msg);
+                            }
+                        }
+                    }
+                }
+                // Drags off the video keep the last position until release
+                if pressed_at.is_some() {
+                    if let Some(pos) = renderer.window_to_video(position.x, position.y) {
+                        pressed_at = Some(pos);
+                        if let Some(msg) = mouse_touch(&renderer, TouchAction::Move, pos) {
+                            for msg in coalescer.push(msg, Instant::now()) {
+                                let _ = control_tx.send(msg);
+                            }
+                        }
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } => {
+                let gesture_kind = if modifiers.control_key() {
+                    Some(GestureKind::Pinch)
+                } else if modifiers.shift_key() {
+                    Some(GestureKind::Rotate)
+                } else {
+                    None
+                };
+                let pos = cursor.and_then(|(x, y)| renderer.window_to_video(x, y));
+                let msgs = match (state, gesture_kind, pos, renderer.current_video_size()) {
+                    (ElementState::Pressed, Some(kind), Some(pos), Some(size)) => {
+                        gestures.press(kind, pos, size)
+                    }
+                    (ElementState::Released, ..) if gestures.is_active() => gestures.release(),
+                    _ => Vec::new(),
+                };
+                if !msgs.is_empty() {
+                    for msg in msgs {
+                        for msg in coalescer.push(msg, Instant::now()) {
+                            let _ = control_tx.send(msg);
+                        }
+                    }
+                    return;
+                }
+                let (action, pos) = match state {
+                    ElementState::Pressed => {
+                        pressed_at = cursor.and_then(|(x, y)| renderer.window_to_video(x, y));
+                        (TouchAction::Down, pressed_at)
+                    }
+                    ElementState::Released => (TouchAction::Up, pressed_at.take()),
+                };
+                if let Some(msg) = pos.and_then(|pos| mouse_touch(&renderer, action, pos)) {
+                    for msg in coalescer.push(msg, Instant::now()) {
+                        let _ = control_tx.send(msg);
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let pos = cursor.and_then(|(x, y)| renderer.window_to_video(x, y));
+                if let (Some(pos), Some(size)) = (pos, renderer.current_video_size()) {
+                    let _ = control_tx.send(gesture::wheel_scroll(pos, delta, size));
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(changed),
+                ..
+            } => modifiers = changed.state(),
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => {
+                // Touch screens and pens, one pointer per contact
+                let position = renderer.window_to_video(touch.location.x, touch.location.y);
+                if let Some(msg) = renderer.current_video_size().and_then(|size| {
+                    touches.on_touch(touch.id, touch.phase, position, touch.force, size)
+                }) {
+                    for msg in coalescer.push(msg, Instant::now()) {
+                        let _ = control_tx.send(msg);
+                    }
+                }
+            }
+            Event::AboutToWait => {
+                // Both are polled every time so neither falls behind
+                let files_changed = files.poll();
+                let log_changed = logcat.poll();
+                let shell_changed = shell.poll();
+                if files_changed || log_changed || shell_changed {
+                    overlay_dirty = true;
+                }
+                if let Some(monitor) = &mut status_monitor {
+                    if monitor.poll() {
+                        title_dirty = true;
+                        overlay_dirty = true;
+                    }
+                }
+                if let (Some(profiles), Ok(app)) = (&game_profiles, app_rx.try_recv()) {
+                    let next = profiles.select(None, Some(&app));
+                    let current = game.as_ref().map(|(name, _)| name.as_str());
+                    if next.map(|(name, _)| name) != current {
+                        let name = next.map_or("off", |(name, _)| name);
+                        info!("{} in the foreground, game profile: {}", app, name);
+                        toasts.push(format!("Game profile: {}", name), Instant::now());
+                        switch_game(&mut game, next, renderer.current_video_size(), &control_tx);
+                    }
+                }
+                if let (Some(source), Some(track)) = (&caption_source, &mut captions) {
+                    for text in source.poll() {
+                        if let Err(e) = track.push(text, Instant::now()) {
+                            warn!("Failed to write captions: {}", e);
+                        }
+                    }
+                }
+
+                // The newest frame, the next in order or the one due by its
+                // pts (`performance.frame_drop`); skipped ones are never drawn
+                let last_frame = frame_rx.try_recv();
+                if let Some(frame) = &last_frame {
+                    latency.mark(frame.pts, Mark::Taken);
+                    live_stats.update_frame();
+                }
+                if let (Some(active), Some(frame)) = (&mut burst, &last_frame) {
+                    active.push(frame);
+                }
+                session.set_frames_dropped(frame_rx.dropped());
+                if let (Some(idle), Some(frame)) = (&mut idle, &last_frame) {
+                    idle.frame(&frame.data, Instant::now());
+                }
+                if idle
+                    .as_ref()
+                    .is_some_and(|idle| idle.expired(Instant::now()))
+                {
+                    info!("No input or screen changes, ending the idle session");
+                    session.set_idle_expired();
+                    if let Some(track) = &mut captions {
+                        if let Err(e) = track.finish(Instant::now()) {
+                            warn!("Failed to write captions: {}", e);
+                        }
+                    }
+                    ui_shutdown.cancel();
+                    target.exit();
+                    return;
+                }
+                if burst.as_ref().is_some_and(|b| b.is_complete()) {
+                    if let Some(done) = burst.take() {
+                        // The writer thread finishes the files in the background
+                        info!("Frame burst saved to {}", done.dir().display());
+                    }
+                }
+
+                if let Some(frame) = last_frame {
+                    // Auto-resize window if video size changes (orientation change or first frame)
+                    // We use the renderer's current tracking to detect change
+                    let current_video_size = renderer.current_video_size();
+                    if current_video_size != Some((frame.width, frame.height)) {
+                        events.publish(SessionEvent::ResolutionChanged {
+                            width: frame.width,
+                            height: frame.height,
+                        });
+                        replay.set_frame_size(frame.width, frame.height);
+                        let inner_size = renderer.window().inner_size();
+                        if inner_size.width > 0 && inner_size.height > 0 {
+                            let w = frame.width as f64;
+                            let h = frame.height as f64;
+                            let aspect = w / h;
+
+                            // Simple heuristic:
+                            // 1. If rotation (Portrait <-> Landscape), flip window dimensions
+                            // 2. Otherwise, adjust width to match new aspect ratio, keeping height
+                            let new_size = if let Some((old_w, old_h)) = current_video_size {
+                                let old_aspect = old_w as f64 / old_h as f64;
+                                let is_landscape = aspect > 1.0;
+                                let was_landscape = old_aspect > 1.0;
+
+                                if is_landscape != was_landscape {
+                                    // Rotation: Flip window
+                                    winit::dpi::PhysicalSize::new(
+                                        inner_size.height,
+                                        inner_size.width,
+                                    )
+                                } else {
+                                    // Resolution change: Adjust width to match aspect
+                                    let new_width = (inner_size.height as f64 * aspect) as u32;
+                                    winit::dpi::PhysicalSize::new(new_width, inner_size.height)
+                                }
+                            } else {
+                                // First frame: Set reasonable default height (e.g. 800 or current) and adjust width
+                                // But don't make it larger than screen.
+                                // Let's simplify: Scale to e.g. 1/3 of video if it's huge, or just match current height
+                                let target_height = if inner_size.height < 100 {
+                                    800.0
+                                } else {
+                                    inner_size.height as f64
+                                };
+                                let new_width = (target_height * aspect) as u32;
+                                winit::dpi::PhysicalSize::new(new_width, target_height as u32)
+                            };
+
+                            let _ = renderer.window().request_inner_size(new_size);
+                        }
+                    }
+
+                    shown_frame = Some(frame);
+                    overlay_dirty = true;
+                }
+
+                // The inspector follows the cursor even while the screen is static
+                if let Some(frame) = &shown_frame {
+                    let hover = cursor.and_then(|(x, y)| renderer.window_to_video(x, y));
+                    overlay_dirty |= inspector.update(frame, hover);
+                }
+
+                loop {
+                    let event = match event_rx.try_recv() {
+                        Ok(event) => event,
+                        Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                        Err(_) => break,
+                    };
+                    toasts.on_event(&event, Instant::now());
+                    let (stalled, corrupted) = match event {
+                        SessionEvent::StreamStalled => (true, stream_corrupted),
+                        SessionEvent::StreamResumed => (false, stream_corrupted),
+                        SessionEvent::StreamCorrupted => (stream_stalled, true),
+                        SessionEvent::StreamRecovered => (stream_stalled, false),
+                        SessionEvent::Disconnected => (false, false),
+                        _ => (stream_stalled, stream_corrupted),
+                    };
+                    overlay_dirty |= (stalled, corrupted) != (stream_stalled, stream_corrupted);
+                    (stream_stalled, stream_corrupted) = (stalled, corrupted);
+                    // Traced frame latency plus half the RTT, as the report
+                    // estimates it; TCP measures no RTT, leaving the PC side
+                    if let (SessionEvent::StatsTick { rtt_ms, .. }, Some(traced)) =
+                        (&event, latency.median_ms())
+                    {
+                        live_stats.set_latency((traced + rtt_ms / 2.0) as f32);
+                    }
+                    // The handshake name titles the window
+                    if let SessionEvent::HandshakeComplete { device_name, .. } = event {
+                        title_name = device_name.clone();
+                        title_dirty = true;
+                        if let (Some(profiles), None, Some(name)) =
+                            (&guide_profiles, &model, &device_name)
+                        {
+                            guides.set_profile(
+                                profiles.for_device(device_serial.as_deref(), Some(name)),
+                            );
+                            overlay_dirty = true;
+                        }
+                    }
+                }
+
+                if title_dirty || (show_stats && title_refreshed.elapsed() >= STATS_TICK) {
+                    live_stats.tick();
+                    let live = shown_frame
+                        .as_ref()
+                        .filter(|_| show_stats)
+                        .map(|frame| live_stats.title_summary((frame.width, frame.height)));
+                    let status = status_monitor.as_ref().and_then(|m| m.status());
+                    let title = window_title(title_name.as_deref(), live.as_deref(), status);
+                    if title != shown_title {
+                        renderer.window().set_title(&title);
+                        shown_title = title;
+                    }
+                    title_refreshed = Instant::now();
+                    title_dirty = false;
+                }
+
+                // Fading toasts need every frame until they are gone
+                overlay_dirty |= toasts.tick(Instant::now());
+
+                let status = banner.lock().ok().and_then(|text| text.clone());
+                if status != shown_banner {
+                    shown_banner = status;
+                    overlay_dirty = true;
+                }
+
+                if let (true, Some(frame)) = (overlay_dirty, &shown_frame) {
+                    let viewport = renderer.viewport();
+                    let rendered = renderer.render_with_overlay(frame, |ctx| {
+                        if let Some(viewport) = viewport {
+                            guides.render(ctx, viewport, (frame.width, frame.height));
+                        }
+                        if let Some(track) = &captions {
+                            track.render(ctx);
+                        }
+                        inspector.render(ctx);
+                        settings.render(ctx);
+                        files.render(ctx);
+                        logcat.render(ctx);
+                        shell.render(ctx);
+                        if let Some(monitor) = &status_monitor {
+                            monitor.render(ctx);
+                        }
+                        toasts.render(ctx, Instant::now());
+                        for msg in keyboard.render(ctx) {
+                            let _ = control_tx.send(msg);
+                        }
+                        if let Some(text) = &shown_banner {
+                            show_banner(ctx, text);
+                        } else if stream_stalled {
+                            show_banner(ctx, "No signal");
+                        } else if stream_corrupted {
+                            show_banner(ctx, "Stream corrupted, recovering...");
+                        }
+                    });
+                    if let Err(e) = rendered {
+                        error!("Render error: {}", e);
+                    }
+                    coalescer.on_frame(Instant::now());
+                    overlay_dirty = false;
+
+                    // Show the new filter right away, even on a static screen
+                    if let Some(filter) = settings.take_upscale_filter() {
+                        if let Err(e) = renderer.set_upscale_filter(filter) {
+                            error!("Failed to switch upscale filter: {}", e);
+                        }
+                        overlay_dirty = true;
+                    }
+                } else if overlay_dirty && shown_frame.is_none() {
+                    // No session yet (e.g. waiting for the device): the banner alone
+                    let rendered = renderer.render_overlay_only(|ctx| {
+                        if let Some(text) = &shown_banner {
+                            show_banner(ctx, text);
+                        }
+                    });
+                    if let Err(e) = rendered {
+                        error!("Render error: {}", e);
+                    }
+                    overlay_dirty = false;
+                }
+
+                // Forward the latest drag position once per frame interval
+                for msg in coalescer.poll(Instant::now()) {
+                    let _ = control_tx.send(msg);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::RedrawRequested,
+                ..
+            } => {
+                // Normally we'd render here, but we render immediately on AboutToWait for lowest latency
+            }
+            Event::LoopExiting => {
+                if let (Some(store), Some(geometry)) =
+                    (&geometry_store, WindowGeometry::of(renderer.window()))
+                {
+                    store.save(&geometry);
+                }
+            }
+            _ => {}
+        }
+    });
+
+    // Let the network thread close the connection cleanly
+    shutdown.cancel();
+    if network.join().is_err() {
+        error!("Network thread panicked");
+    }
+
+    Ok(())
+}
+
+/// Whether a window event would be forwarded to the device as pointer input
+fn is_pointer_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::MouseInput { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+    )
+}
+
+/// Build a mouse touch event for a position in video coordinates
+fn mouse_touch(
+    renderer: &VideoRenderer,
+    action: TouchAction,
+    (x, y): (u32, u32),
+) -> Option<ControlMessage> {
+    let (width, height) = renderer.current_video_size()?;
+    Some(ControlMessage::InjectTouch {
+        action,
+        pointer_id: POINTER_ID_MOUSE,
+        x,
+        y,
+        width,
+        height,
+        pressure: if action == TouchAction::Up { 0.0 } else { 1.0 },
+    })
+}
+
+/// Window title: device name, then the live stream figures and the
+/// device's battery and network state, e.g.
+/// `Pixel 7 — 1080p • 58 fps • 32 ms • 87% charging, 31.2 °C`
+fn window_title(
+    device_name: Option<&str>,
+    live: Option<&str>,
+    status: Option<&DeviceStatus>,
+) -> String {
+    let summary = status.map(DeviceStatus::summary);
+    let details: Vec<&str> = [live, summary.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect();
+    let name = device_name.unwrap_or("scrcpy-custom");
+    if details.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} — {}", name, details.join(" • "))
+    }
+}
+
+/// Replace the game mapping with `next`, lifting what the old one held down
+fn switch_game(
+    game: &mut Option<(String, GameMapper)>,
+    next: Option<(&str, &GameProfile)>,
+    size: Option<(u32, u32)>,
+    control_tx: &tokio::sync::mpsc::UnboundedSender<ControlMessage>,
+) {
+    if let (Some((_, mapper)), Some(size)) = (game.as_mut(), size) {
+        for msg in mapper.release_all(size) {
+            let _ = control_tx.send(msg);
+        }
+    }
+    *game = next.map(|(name, profile)| (name.to_string(), GameMapper::new(profile.clone())));
+}
+
+/// Package of the app in the foreground, for per-app profiles
+fn foreground_app(serial: Option<&str>) -> Option<String> {
+    let mut cmd = std::process::Command::new(Assets::get_adb_path().ok()?);
+    if let Some(serial) = serial {
+        cmd.args(["-s", serial]);
+    }
+    let output = cmd
+        .args(["shell", "dumpsys", "activity", "activities"])
+        .output()
+        .ok()?;
+    let dumpsys = String::from_utf8_lossy(&output.stdout);
+    game_map::parse_resumed_app(&dumpsys).map(str::to_string)
+}
@@ -1,21 +1,46 @@
 use anyhow::{anyhow, Context, Result};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::debug;
 
+/// Server jar compiled into the binary (`--features embed-server`)
+#[cfg(feature = "embed-server")]
+const EMBEDDED_SERVER: &[u8] =
+    include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/scrcpy-server"));
+
+/// Where users get adb, which is not shipped with the binary
+const PLATFORM_TOOLS_URL: &str = "https://developer.android.com/tools/releases/platform-tools";
+
 pub struct Assets;
 
 impl Assets {
     /// Finds the path to the scrcpy-server binary (or jar).
-    /// Searches in the same directory as the executable first, then current working directory.
+    /// Searches in the same directory as the executable first, then current working directory,
+    /// then falls back to the copy embedded in the binary (if built with it).
     pub fn get_server_path() -> Result<PathBuf> {
         Self::find_asset("scrcpy-server")
             .or_else(|_| Self::find_asset("scrcpy-server.jar"))
+            .or_else(|_| Self::embedded_server())
             .context("Could not find scrcpy-server or scrcpy-server.jar in the executable directory or current working directory.")
     }
 
+    /// Extract the embedded server to the cache dir, once per server version
+    #[cfg(feature = "embed-server")]
+    fn embedded_server() -> Result<PathBuf> {
+        write_cached(&env::temp_dir().join("scrcpy-custom"), EMBEDDED_SERVER)
+    }
+
+    #[cfg(not(feature = "embed-server"))]
+    fn embedded_server() -> Result<PathBuf> {
+        Err(anyhow!("scrcpy-server is not embedded in this build"))
+    }
+
     /// Finds the path to the adb binary.
     /// Searches in the same directory as the executable first, then current working directory.
+    ///
+    /// adb is never embedded or downloaded: that needs platform-tools builds
+    /// pinned by checksum for every OS, which is deferred until there is a
+    /// verified release list to pin against.
     pub fn get_adb_path() -> Result<PathBuf> {
         #[cfg(target_os = "windows")]
         let binary_name = "adb.exe";
@@ -23,8 +48,9 @@ impl Assets {
         let binary_name = "adb";
 
         Self::find_asset(binary_name).context(format!(
-            "Could not find {} in the executable directory or current working directory.",
-            binary_name
+            "Could not find {} in the executable directory or current working directory. \
+             Get it from Android SDK Platform-Tools: {}",
+            binary_name, PLATFORM_TOOLS_URL
         ))
     }
 
@@ -53,3 +79,44 @@ impl Assets {
         Err(anyhow!("Asset {} not found", name))
     }
 }
+
+/// Write `bytes` under `dir` in a file named after their hash, reusing it if present
+///
+/// The name changes with the contents, so an upgraded binary never runs a
+/// stale server, and the write goes through a rename so two instances
+/// starting together can't see a half-written file.
+#[cfg_attr(not(feature = "embed-server"), allow(dead_code))]
+fn write_cached(dir: &Path, bytes: &[u8]) -> Result<PathBuf> {
+    let digest = ring::digest::digest(&ring::digest::SHA256, bytes);
+    let hash: String = digest.as_ref()[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let path = dir.join(format!("scrcpy-server-{}", hash));
+    if std::fs::metadata(&path).is_ok_and(|m| m.len() == bytes.len() as u64) {
+        return Ok(path);
+    }
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let partial = dir.join(format!("scrcpy-server-{}.{}.tmp", hash, std::process::id()));
+    std::fs::write(&partial, bytes).with_context(|| format!("Failed to write {:?}", partial))?;
+    std::fs::rename(&partial, &path).with_context(|| format!("Failed to write {:?}", path))?;
+    debug!("Extracted embedded server to {:?}", path);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_cached_is_content_addressed() {
+        let dir = env::temp_dir().join(format!("scrcpy-assets-{}", std::process::id()));
+        let first = write_cached(&dir, b"server v1").unwrap();
+        assert_eq!(write_cached(&dir, b"server v1").unwrap(), first);
+        let second = write_cached(&dir, b"server v2").unwrap();
+        assert_ne!(second, first);
+        assert_eq!(std::fs::read(&second).unwrap(), b"server v2");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,7 +2,9 @@ use anyhow::{anyhow, Result};
 use audiopus::{coder::Decoder as OpusDecoder, Channels, SampleRate as OpusSampleRate};
 use bytes::Bytes;
 use symphonia::core::audio::AudioBufferRef;
-use symphonia::core::codecs::{Decoder as SymphoniaDecoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::codecs::{
+    CodecType, Decoder as SymphoniaDecoder, DecoderOptions, CODEC_TYPE_NULL,
+};
 
 /// Decoded audio samples with metadata
 #[derive(Debug, Clone)]
@@ -16,6 +18,7 @@ pub struct DecodedAudio {
 pub enum AudioBackend {
     Opus(OpusWrapper),
     Symphonia(SymphoniaWrapper),
+    Pcm(PcmWrapper),
 }
 
 /// Smart Audio Decoder that selects the best backend
@@ -32,6 +35,10 @@ impl HardwareAudioDecoder {
                 tracing::info!("Initializing specialized Opus decoder");
                 AudioBackend::Opus(OpusWrapper::new(sample_rate, channels)?)
             }
+            "raw" | "pcm" => {
+                tracing::info!("Using raw PCM audio, no decoder needed");
+                AudioBackend::Pcm(PcmWrapper::new(sample_rate, channels))
+            }
             "aac" | "mp3" | "flac" | "wav" => {
                 tracing::info!("Initializing Symphonia decoder for {}", codec_name);
                 AudioBackend::Symphonia(SymphoniaWrapper::new(codec_name, sample_rate, channels)?)
@@ -50,6 +57,44 @@ impl HardwareAudioDecoder {
         match &mut self.backend {
             AudioBackend::Opus(decoder) => decoder.decode(data, pts),
             AudioBackend::Symphonia(decoder) => decoder.decode(data, pts),
+            AudioBackend::Pcm(decoder) => Ok(Some(decoder.decode(data, pts))),
+        }
+    }
+
+    /// Feed a codec config packet (e.g. the FLAC stream header)
+    pub fn set_config(&mut self, data: &Bytes) -> Result<()> {
+        match &mut self.backend {
+            AudioBackend::Symphonia(decoder) => decoder.set_config(data),
+            // Opus packets are self-describing and PCM has no config
+            AudioBackend::Opus(_) | AudioBackend::Pcm(_) => Ok(()),
+        }
+    }
+}
+
+/// Interleaved signed 16-bit little-endian PCM, as the server sends `raw` audio
+pub struct PcmWrapper {
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl PcmWrapper {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+        }
+    }
+
+    pub fn decode(&mut self, data: &Bytes, pts: i64) -> DecodedAudio {
+        let samples = data
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+            .collect();
+        DecodedAudio {
+            pts,
+            samples,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
         }
     }
 }
@@ -105,7 +150,9 @@ impl OpusWrapper {
 }
 
 pub struct SymphoniaWrapper {
-    decoder: Box<dyn SymphoniaDecoder>,
+    /// `None` until the codec config arrives, for codecs that need it
+    decoder: Option<Box<dyn SymphoniaDecoder>>,
+    codec: CodecType,
     sample_rate: u32,
     channels: u16,
 }
@@ -130,26 +177,53 @@ impl SymphoniaWrapper {
             .get_codec(hint)
             .ok_or_else(|| anyhow!("Codec not found in Symphonia registry"))?;
 
-        let decoder = codec_registry.make(
+        let mut wrapper = Self {
+            decoder: None,
+            codec: hint,
+            sample_rate,
+            channels,
+        };
+        // FLAC can't be opened without its STREAMINFO, which comes as config
+        if hint != symphonia::core::codecs::CODEC_TYPE_FLAC {
+            wrapper.decoder = Some(wrapper.make_decoder(None)?);
+        }
+        Ok(wrapper)
+    }
+
+    fn make_decoder(&self, extra_data: Option<Box<[u8]>>) -> Result<Box<dyn SymphoniaDecoder>> {
+        Ok(symphonia::default::get_codecs().make(
             &symphonia::core::codecs::CodecParameters {
-                codec: hint,
-                sample_rate: Some(sample_rate),
+                codec: self.codec,
+                sample_rate: Some(self.sample_rate),
+                extra_data,
                 ..Default::default()
             },
             &DecoderOptions::default(),
-        )?;
+        )?)
+    }
 
-        Ok(Self {
-            decoder,
-            sample_rate,
-            channels,
-        })
+    /// Re-open the decoder with the stream's codec config
+    pub fn set_config(&mut self, data: &Bytes) -> Result<()> {
+        let mut extra_data: &[u8] = data;
+        if self.codec == symphonia::core::codecs::CODEC_TYPE_FLAC {
+            // Symphonia wants the bare STREAMINFO block, without the stream
+            // marker and block header in front of it
+            if let Some(rest) = extra_data.strip_prefix(b"fLaC") {
+                extra_data = rest.get(4..).unwrap_or_default();
+            }
+        }
+        self.decoder = Some(self.make_decoder(Some(extra_data.into()))?);
+        Ok(())
     }
 
     pub fn decode(&mut self, data: &Bytes, pts: i64) -> Result<Option<DecodedAudio>> {
+        let Some(decoder) = &mut self.decoder else {
+            tracing::debug!("Dropping audio packet received before the codec config");
+            return Ok(None);
+        };
         let packet = symphonia::core::formats::Packet::new_from_slice(0, 0, 0, data);
 
-        match self.decoder.decode(&packet) {
+        match decoder.decode(&packet) {
             Ok(decoded) => {
                 let samples = Self::convert_buffer(&decoded);
                 Ok(Some(DecodedAudio {
@@ -198,3 +272,18 @@ impl SymphoniaWrapper {
 }
 
 use symphonia;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_pcm_passthrough() {
+        let mut decoder = HardwareAudioDecoder::new("raw", 48000, 2).unwrap();
+        let data = Bytes::from_static(&[0x00, 0x80, 0xff, 0x7f, 0x00, 0x00, 0x00, 0x40]);
+        let audio = decoder.decode(&data, 7).unwrap().unwrap();
+        assert_eq!(audio.pts, 7);
+        assert_eq!(audio.samples, vec![-1.0, 32767.0 / 32768.0, 0.0, 0.5]);
+        assert!(decoder.set_config(&data).is_ok());
+    }
+}
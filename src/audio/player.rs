@@ -12,7 +12,11 @@ pub struct AudioPlayer {
     _device: Device,
     _stream: Stream,
     jitter_buffer: Arc<Mutex<JitterBuffer>>,
+    /// Interleaved samples mixed on top of the stream (feedback sounds)
+    effects: Arc<Mutex<VecDeque<f32>>>,
     volume: f32,
+    sample_rate: u32,
+    channels: u16,
 }
 
 /// Jitter buffer for handling packet reordering and timing jitter
@@ -122,6 +126,8 @@ impl AudioPlayer {
         )));
 
         let jitter_buffer_clone = jitter_buffer.clone();
+        let effects = Arc::new(Mutex::new(VecDeque::new()));
+        let effects_clone = effects.clone();
 
         // Create audio output stream
         let stream = device
@@ -142,6 +148,14 @@ impl AudioPlayer {
                     if buffer.underrun_risk() {
                         tracing::warn!("Audio buffer underrun risk");
                     }
+
+                    // Mix feedback sounds on top
+                    if let Ok(mut effects) = effects_clone.lock() {
+                        let count = data.len().min(effects.len());
+                        for (out, effect) in data.iter_mut().zip(effects.drain(..count)) {
+                            *out = (*out + effect).clamp(-1.0, 1.0);
+                        }
+                    }
                 },
                 |err| {
                     tracing::error!("Audio stream error: {}", err);
@@ -157,7 +171,10 @@ impl AudioPlayer {
             _device: device,
             _stream: stream,
             jitter_buffer,
+            effects,
             volume: 1.0,
+            sample_rate,
+            channels,
         })
     }
 
@@ -183,6 +200,26 @@ impl AudioPlayer {
         Ok(())
     }
 
+    /// Mix a short sound on top of the stream, bypassing the jitter buffer
+    ///
+    /// Samples must be interleaved at the player's rate and channel count.
+    /// A new effect replaces one that is still playing.
+    pub fn play_effect(&mut self, samples: Vec<f32>) -> Result<()> {
+        let mut effects = self
+            .effects
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock effect buffer: {}", e))?;
+
+        effects.clear();
+        effects.extend(samples.into_iter().map(|s| s * self.volume));
+        Ok(())
+    }
+
+    /// Output format as (sample_rate, channels)
+    pub fn format(&self) -> (u32, u16) {
+        (self.sample_rate, self.channels)
+    }
+
     /// Set playback volume (0.0 - 1.0)
     pub fn set_volume(&mut self, volume: f32) -> Result<()> {
         self.volume = volume.clamp(0.0, 1.0);
@@ -0,0 +1,319 @@
+use crate::audio::decoder::{DecodedAudio, HardwareAudioDecoder};
+use crate::config::{Config, VideoCodec};
+use crate::network::{self, Connection, ControlMessage, Packet, PacketType, SharedSecret};
+use crate::video::decoder::{DecodedFrame, HardwareVideoDecoder, PixelFormat};
+use crate::video::{FrameFilter, FrameFilters, GpuFrame};
+use anyhow::{Context, Result};
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::{Stream, StreamExt};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Mirroring session for embedding: decoded video and audio as async streams
+///
+/// Packets are received on a tokio task and decoded on a thread of their own.
+/// A consumer that falls behind loses frames (and audio chunks) once
+/// `performance.video_buffer_size` (`audio_buffer_size`) of them are waiting,
+/// rather than holding up the connection.
+///
+/// With `video.gpu_frames`, frames come from [`gpu_frames`](Self::gpu_frames)
+/// instead, as textures or DMA-BUFs a GPU consumer uses without a copy.
+///
+/// ```no_run
+/// # async fn mirror(config: scrcpy_custom::Config) -> anyhow::Result<()> {
+/// use futures::StreamExt;
+///
+/// let mut client = scrcpy_custom::Client::connect(&config).await?;
+/// let mut frames = client.video_frames();
+/// while let Some(frame) = frames.next().await {
+///     println!("{}x{} at {} us", frame.width, frame.height, frame.pts);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client {
+    video: Option<Receiver<DecodedFrame>>,
+    gpu: Option<Receiver<GpuFrame>>,
+    audio: Option<Receiver<DecodedAudio>>,
+    /// Whether video is decoded to `gpu` rather than `video`
+    on_gpu: bool,
+    control: tokio::sync::mpsc::UnboundedSender<ControlMessage>,
+    shutdown: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    filters: FrameFilters,
+    device_name: Option<String>,
+    codec: VideoCodec,
+}
+
+impl Client {
+    /// Connect to a scrcpy server already listening at `config.connection`,
+    /// e.g. through an adb forward; the server is not started here
+    pub async fn connect(config: &Config) -> Result<Self> {
+        let mode = config.connection.mode.into();
+        let host = &config.connection.host;
+        let addr = network::resolve::pick(mode, host, config.connection.port)
+            .await
+            .with_context(|| format!("Cannot reach {}", host))?;
+        let serial = config.connection.adb_serial();
+        let mut connection =
+            network::switcher::connect(mode, addr, config.audio.enabled, serial.as_deref())
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to connect: {}", e))?;
+        if let Some(secret) = SharedSecret::for_connection(&config.connection, mode) {
+            connection
+                .authenticate(&secret)
+                .await
+                .map_err(|e| anyhow::anyhow!("Authentication failed: {}", e))?;
+        }
+        connection.apply_performance_config(&config.performance);
+        Self::with_connection(connection, config)
+    }
+
+    /// Decode the streams of an open connection, e.g. a `MockConnection`
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn with_connection(mut connection: Box<dyn Connection>, config: &Config) -> Result<Self> {
+        let codec = connection.video_codec().unwrap_or(config.video.codec);
+        let device_name = connection.device_name().map(str::to_string);
+        let (packet_tx, packet_rx) =
+            tokio::sync::mpsc::channel(config.performance.video_buffer_size.max(1));
+        let (video_tx, video) = mpsc::channel(config.performance.video_buffer_size);
+        let (gpu_tx, gpu) = mpsc::channel(config.performance.video_buffer_size);
+        let (audio_tx, audio) = mpsc::channel(config.performance.audio_buffer_size);
+
+        let filters = FrameFilters::new();
+        let decode = Decoders {
+            hw_decoder: config.video.hw_decoder.clone(),
+            gpu_frames: config.video.gpu_frames,
+            codec,
+            audio_codec: config
+                .audio
+                .enabled
+                .then(|| config.audio.codec.to_server_arg()),
+            video: video_tx,
+            gpu: gpu_tx,
+            audio: audio_tx,
+            filters: filters.clone(),
+        };
+        let on_gpu = decode.spawn(packet_rx)?;
+
+        let (control, mut control_rx) = tokio::sync::mpsc::unbounded_channel();
+        let shutdown = CancellationToken::new();
+        let cancelled = shutdown.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = cancelled.cancelled() => break,
+                    Some(msg) = control_rx.recv() => {
+                        if let Err(e) = connection.send_control(msg).await {
+                            tracing::warn!("Failed to send control message: {}", e);
+                        }
+                    }
+                    received = connection.recv() => match received {
+                        Ok(packet) => {
+                            if packet_tx.send(packet).await.is_err() {
+                                break; // Decode thread gone
+                            }
+                        }
+                        Err(e) => {
+                            tracing::info!("Stream ended: {}", e);
+                            break;
+                        }
+                    },
+                }
+            }
+            if let Err(e) = connection.close().await {
+                tracing::debug!("Failed to close connection: {}", e);
+            }
+        });
+
+        Ok(Self {
+            video: Some(video),
+            gpu: Some(gpu),
+            audio: Some(audio),
+            on_gpu,
+            control,
+            shutdown,
+            task: Some(task),
+            filters,
+            device_name,
+            codec,
+        })
+    }
+
+    /// Decoded video frames, ending with the session
+    ///
+    /// The frames can be taken once; later calls return an empty stream.
+    pub fn video_frames(&mut self) -> impl Stream<Item = DecodedFrame> + Send + Unpin {
+        futures::stream::iter(self.video.take()).flatten()
+    }
+
+    /// Decoded video frames left on the GPU, ending with the session
+    ///
+    /// Empty unless [`has_gpu_frames`](Self::has_gpu_frames); filters do not
+    /// run on them. The frames can be taken once; later calls return an
+    /// empty stream.
+    pub fn gpu_frames(&mut self) -> impl Stream<Item = GpuFrame> + Send + Unpin {
+        futures::stream::iter(self.gpu.take()).flatten()
+    }
+
+    /// Whether video goes to `gpu_frames`: `video.gpu_frames` is on and the
+    /// GPU decoder opened (else frames go to `video_frames` as usual)
+    pub fn has_gpu_frames(&self) -> bool {
+        self.on_gpu
+    }
+
+    /// Decoded audio chunks, ending with the session (empty without audio)
+    ///
+    /// The chunks can be taken once; later calls return an empty stream.
+    pub fn audio_frames(&mut self) -> impl Stream<Item = DecodedAudio> + Send + Unpin {
+        futures::stream::iter(self.audio.take()).flatten()
+    }
+
+    /// Add a filter run on every following video frame, after those already added
+    pub fn add_filter(&self, filter: Box<dyn FrameFilter>) {
+        self.filters.register(filter);
+    }
+
+    /// Queue a control message (touch, keys, keyframe request...) for the device
+    pub fn send_control(&self, msg: ControlMessage) {
+        let _ = self.control.send(msg);
+    }
+
+    /// Name the device reported in the handshake
+    pub fn device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
+    }
+
+    /// Codec of the video stream
+    pub fn video_codec(&self) -> VideoCodec {
+        self.codec
+    }
+
+    /// Close the connection; the streams end once the decoder is drained
+    pub async fn close(mut self) {
+        self.shutdown.cancel();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// What the decode thread needs
+struct Decoders {
+    hw_decoder: String,
+    /// Decode to GPU frames where the platform allows
+    gpu_frames: bool,
+    codec: VideoCodec,
+    /// Server name of the audio codec, None without audio
+    audio_codec: Option<&'static str>,
+    video: Sender<DecodedFrame>,
+    gpu: Sender<GpuFrame>,
+    audio: Sender<DecodedAudio>,
+    filters: FrameFilters,
+}
+
+impl Decoders {
+    /// Start decoding `packets` on a thread, returning once the decoders are
+    /// set up; true if video is decoded to GPU frames
+    fn spawn(mut self, mut packets: tokio::sync::mpsc::Receiver<Packet>) -> Result<bool> {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("client-decode".into())
+            .spawn(move || {
+                let mut video = match self.video_decoder() {
+                    Ok(decoder) => decoder,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let mut audio = self.audio_codec.and_then(|codec| {
+                    HardwareAudioDecoder::new(codec, 48000, 2)
+                        .map_err(|e| tracing::warn!("Audio decoding unavailable: {}", e))
+                        .ok()
+                });
+                let on_gpu = video.gpu_api().is_some();
+                let _ = ready_tx.send(Ok(on_gpu));
+
+                while let Some(packet) = packets.blocking_recv() {
+                    match packet.packet_type {
+                        PacketType::Video if packet.flags.config => {
+                            video.set_config(&packet.data);
+                            for frame in video.take_flushed() {
+                                self.send_video(frame);
+                            }
+                        }
+                        PacketType::Video if on_gpu => {
+                            match video.decode_gpu(&packet.data, packet.pts) {
+                                // Dropped if the consumer is behind
+                                Ok(Some(frame)) => {
+                                    let _ = self.gpu.try_send(frame);
+                                }
+                                Ok(None) => {}
+                                Err(e) => tracing::warn!("Video decoding error: {}", e),
+                            }
+                        }
+                        PacketType::Video => match video.decode(&packet.data, packet.pts) {
+                            Ok(Some(frame)) => self.send_video(frame),
+                            Ok(None) => {}
+                            Err(e) => tracing::warn!("Video decoding error: {}", e),
+                        },
+                        PacketType::Audio => {
+                            let Some(decoder) = &mut audio else {
+                                continue;
+                            };
+                            let decoded = if packet.flags.config {
+                                decoder.set_config(&packet.data).map(|()| None)
+                            } else {
+                                decoder.decode(&packet.data, packet.pts)
+                            };
+                            match decoded {
+                                // Dropped if the consumer is behind (or not listening)
+                                Ok(Some(chunk)) => {
+                                    let _ = self.audio.try_send(chunk);
+                                }
+                                Ok(None) => {}
+                                Err(e) => tracing::warn!("Audio decoding error: {}", e),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                match video.flush() {
+                    Ok(remaining) => remaining.into_iter().for_each(|f| self.send_video(f)),
+                    Err(e) => tracing::debug!("Failed to flush video decoder: {}", e),
+                }
+            })
+            .context("Failed to start the decode thread")?;
+
+        ready_rx
+            .recv()
+            .context("Decode thread exited during setup")?
+    }
+
+    /// GPU decoder if asked for and available, else one decoding to RGBA
+    fn video_decoder(&self) -> Result<HardwareVideoDecoder> {
+        if self.gpu_frames {
+            match HardwareVideoDecoder::new_gpu(self.codec) {
+                Ok(decoder) => return Ok(decoder),
+                Err(e) => tracing::warn!("GPU frames unavailable, decoding to memory: {:#}", e),
+            }
+        }
+        HardwareVideoDecoder::new(&self.hw_decoder, self.codec, PixelFormat::RGBA)
+    }
+
+    /// Filter a frame and hand it to the stream, dropped if the consumer is behind
+    fn send_video(&mut self, mut frame: DecodedFrame) {
+        self.filters.apply(&mut frame);
+        let _ = self.video.try_send(frame);
+    }
+}
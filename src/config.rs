@@ -1,5 +1,7 @@
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +17,27 @@ pub struct Config {
 
     /// Performance tuning
     pub performance: PerformanceConfig,
+
+    /// Input forwarding and feedback
+    pub input: InputConfig,
+
+    /// On-screen presentation
+    pub display: DisplayConfig,
+
+    /// Device-side server setup
+    pub server: ServerConfig,
+
+    /// HTTP control API
+    pub api: ApiConfig,
+
+    /// Third-party pipeline plugins
+    pub plugins: PluginConfig,
+
+    /// Outputs other applications read frames from
+    pub output: OutputConfig,
+
+    /// Republishing of the encoded stream to other viewers
+    pub restream: RestreamConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,22 +45,92 @@ pub struct ConnectionConfig {
     /// Connection mode (TCP or QUIC)
     pub mode: ConnectionMode,
 
-    /// Server IP address
-    pub host: IpAddr,
+    /// Server host name or IP address (IPv6 may carry a zone, `fe80::1%wlan0`)
+    pub host: String,
 
     /// Server port
     pub port: u16,
+
+    /// Shared secret for QUIC servers and session relays (unauthenticated if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+
+    /// Encrypt packet payloads with a key derived from `auth_token`
+    pub encrypt_payloads: bool,
+
+    /// Move the stream to the other transport when it measures clearly better
+    pub auto_switch: bool,
+
+    /// Direction of the ADB tunnel when the server is started over ADB
+    pub tunnel: TunnelMode,
+
+    /// Local port for `adb forward` (adb picks a free one if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forward_port: Option<u16>,
+
+    /// Wait for the device to be plugged in, and again whenever it is unplugged
+    pub hotplug: bool,
+
+    /// adb serial of the device to mirror (the only device, or `host`, if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+
+    /// Seconds without video before reconnecting (0 = never)
+    pub stall_timeout: u32,
+
+    /// Record every received packet with its arrival time to this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dump_session: Option<PathBuf>,
+
+    /// Session capture to play back instead of connecting (the `replay` command)
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+impl ConnectionConfig {
+    /// Serial to pass to adb; wireless devices are addressed by host
+    pub fn adb_serial(&self) -> Option<String> {
+        self.serial
+            .clone()
+            .or_else(|| (!self.is_local()).then(|| self.host.clone()))
+    }
+
+    /// Whether `host` is this machine, i.e. the server is reached through adb
+    pub fn is_local(&self) -> bool {
+        self.host == "localhost" || self.host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelMode {
+    /// `adb forward`: we connect to the server's socket
+    Forward,
+    /// `adb reverse`: we listen and the server connects to us
+    Reverse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ConnectionMode {
     Tcp,
     Quic,
 }
 
+impl From<ConnectionMode> for crate::network::ConnectionMode {
+    fn from(mode: ConnectionMode) -> Self {
+        match mode {
+            ConnectionMode::Tcp => Self::Tcp,
+            ConnectionMode::Quic => Self::Quic,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoConfig {
+    /// Mirror the screen; off plays only the device audio (`--no-video`)
+    pub enabled: bool,
+
     /// Target resolution (upscaling will be applied if monitor is larger)
     pub resolution: Resolution,
 
@@ -55,12 +148,136 @@ pub struct VideoConfig {
 
     /// Hardware decoder preference (nvdec, qsv, vaapi, auto)
     pub hw_decoder: String,
+
+    /// Capture the display in this orientation whatever the device does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_orientation: Option<Orientation>,
+
+    /// How frames are handed to the display
+    pub present_mode: PresentMode,
+
+    /// Keep `Client` frames on the GPU (`Client::gpu_frames`: D3D11 textures
+    /// or DMA-BUFs) rather than converting them to RGBA in memory
+    pub gpu_frames: bool,
+}
+
+/// Surface present mode, with a fallback when the GPU lacks the one asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    /// Lowest latency the surface supports: mailbox, then immediate, then fifo
+    Auto,
+    /// Newest frame at the next vblank, no tearing
+    Mailbox,
+    /// Shown at once, may tear
+    Immediate,
+    /// Vsync queue, up to a frame of extra latency
+    Fifo,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 4] = [
+        PresentMode::Auto,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+        PresentMode::Fifo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresentMode::Auto => "auto",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+            PresentMode::Fifo => "fifo",
+        }
+    }
+}
+
+impl std::str::FromStr for PresentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown present mode '{}' (one of: auto, mailbox, immediate, fifo)",
+                    s
+                )
+            })
+    }
+}
+
+/// Display rotation, clockwise from the device's natural orientation
+///
+/// Written as degrees (0, 90, 180 or 270) in the config and on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum Orientation {
+    Natural,
+    Rotated90,
+    Rotated180,
+    Rotated270,
+}
+
+impl Orientation {
+    pub fn degrees(&self) -> u16 {
+        match self {
+            Orientation::Natural => 0,
+            Orientation::Rotated90 => 90,
+            Orientation::Rotated180 => 180,
+            Orientation::Rotated270 => 270,
+        }
+    }
+
+    /// Server `capture_orientation=` value locking this orientation
+    pub fn to_locked_server_arg(&self) -> String {
+        format!("@{}", self.degrees())
+    }
+}
+
+impl TryFrom<u16> for Orientation {
+    type Error = String;
+
+    fn try_from(degrees: u16) -> std::result::Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Orientation::Natural),
+            90 => Ok(Orientation::Rotated90),
+            180 => Ok(Orientation::Rotated180),
+            270 => Ok(Orientation::Rotated270),
+            _ => Err(format!(
+                "orientation must be 0, 90, 180 or 270, not {}",
+                degrees
+            )),
+        }
+    }
+}
+
+impl From<Orientation> for u16 {
+    fn from(orientation: Orientation) -> Self {
+        orientation.degrees()
+    }
+}
+
+impl std::str::FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let degrees: u16 = s
+            .parse()
+            .map_err(|_| format!("invalid orientation '{}'", s))?;
+        degrees.try_into()
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Resolution {
-    HD720,   // 1280x720
+    #[serde(rename = "720p")]
+    HD720, // 1280x720
+    #[serde(rename = "1080p")]
     FHD1080, // 1920x1080
+    #[serde(rename = "1440p")]
     QHD1440, // 2560x1440
 }
 
@@ -82,11 +299,41 @@ impl Resolution {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum VideoCodec {
     H264,
     H265,
+    Av1,
+}
+
+impl VideoCodec {
+    /// Codec of a scrcpy stream header codec ID (a big-endian FourCC)
+    pub fn from_codec_id(id: u32) -> Option<Self> {
+        match &id.to_be_bytes() {
+            b"h264" => Some(VideoCodec::H264),
+            b"h265" => Some(VideoCodec::H265),
+            b"\0av1" => Some(VideoCodec::Av1),
+            _ => None,
+        }
+    }
+
+    /// Stream header codec ID, the inverse of [`Self::from_codec_id`]
+    pub fn codec_id(&self) -> u32 {
+        u32::from_be_bytes(match self {
+            VideoCodec::H264 => *b"h264",
+            VideoCodec::H265 => *b"h265",
+            VideoCodec::Av1 => *b"\0av1",
+        })
+    }
+
+    pub fn to_server_arg(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "h264",
+            VideoCodec::H265 => "h265",
+            VideoCodec::Av1 => "av1",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,22 +349,129 @@ pub struct AudioConfig {
 
     /// Audio codec
     pub codec: AudioCodec,
+
+    /// What the device captures
+    pub source: AudioSource,
+
+    /// Keep playing audio on the device too (needs the playback source)
+    pub duplicate: bool,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Device audio source (scrcpy `audio_source=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AudioSource {
+    /// Everything the device plays (the device itself goes silent)
+    Output,
+    /// Playback capture, leaving device audio on (Android 13+)
+    Playback,
+    /// Microphone
+    Mic,
+    /// Microphone without processing
+    MicUnprocessed,
+    /// Microphone tuned for video recording
+    MicCamcorder,
+    /// Microphone tuned for speech recognition
+    MicVoiceRecognition,
+    /// Microphone tuned for VoIP (echo cancellation, gain control)
+    MicVoiceCommunication,
+    /// Both sides of a phone call
+    VoiceCall,
+    VoiceCallUplink,
+    VoiceCallDownlink,
+    /// Microphone for live performance (low latency)
+    VoicePerformance,
+}
+
+impl AudioSource {
+    const ALL: [AudioSource; 11] = [
+        AudioSource::Output,
+        AudioSource::Playback,
+        AudioSource::Mic,
+        AudioSource::MicUnprocessed,
+        AudioSource::MicCamcorder,
+        AudioSource::MicVoiceRecognition,
+        AudioSource::MicVoiceCommunication,
+        AudioSource::VoiceCall,
+        AudioSource::VoiceCallUplink,
+        AudioSource::VoiceCallDownlink,
+        AudioSource::VoicePerformance,
+    ];
+
+    pub fn to_server_arg(&self) -> &'static str {
+        match self {
+            AudioSource::Output => "output",
+            AudioSource::Playback => "playback",
+            AudioSource::Mic => "mic",
+            AudioSource::MicUnprocessed => "mic-unprocessed",
+            AudioSource::MicCamcorder => "mic-camcorder",
+            AudioSource::MicVoiceRecognition => "mic-voice-recognition",
+            AudioSource::MicVoiceCommunication => "mic-voice-communication",
+            AudioSource::VoiceCall => "voice-call",
+            AudioSource::VoiceCallUplink => "voice-call-uplink",
+            AudioSource::VoiceCallDownlink => "voice-call-downlink",
+            AudioSource::VoicePerformance => "voice-performance",
+        }
+    }
+}
+
+impl std::str::FromStr for AudioSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.to_server_arg() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|s| s.to_server_arg()).collect();
+                format!(
+                    "unknown audio source '{}' (one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AudioCodec {
     Aac,
     Opus,
+    /// 16-bit PCM, no decoding at all
     Raw,
+    /// Lossless, decoded in software
+    Flac,
 }
 
 impl AudioCodec {
+    /// Codec for a stream header codec ID (see [`AudioCodec::codec_id`])
+    pub fn from_codec_id(id: u32) -> Option<Self> {
+        match &id.to_be_bytes() {
+            b"\0aac" => Some(AudioCodec::Aac),
+            b"opus" => Some(AudioCodec::Opus),
+            b"\0raw" => Some(AudioCodec::Raw),
+            b"flac" => Some(AudioCodec::Flac),
+            _ => None,
+        }
+    }
+
+    /// Stream header codec ID, as the server writes it on the audio socket
+    pub fn codec_id(&self) -> u32 {
+        u32::from_be_bytes(match self {
+            AudioCodec::Aac => *b"\0aac",
+            AudioCodec::Opus => *b"opus",
+            AudioCodec::Raw => *b"\0raw",
+            AudioCodec::Flac => *b"flac",
+        })
+    }
+
     pub fn to_server_arg(&self) -> &'static str {
         match self {
             AudioCodec::Aac => "aac",
             AudioCodec::Opus => "opus",
             AudioCodec::Raw => "raw",
+            AudioCodec::Flac => "flac",
         }
     }
 }
@@ -138,6 +492,418 @@ pub struct PerformanceConfig {
 
     /// FEC redundancy percentage (0-50)
     pub fec_redundancy: u8,
+
+    /// Adjust FEC redundancy at runtime from measured packet loss
+    pub adaptive_fec: bool,
+
+    /// Packet loss recovery strategy for lossy transports
+    pub loss_recovery: LossRecovery,
+
+    /// Highest RTT (ms) at which `LossRecovery::Auto` prefers retransmission over FEC
+    pub nack_max_rtt_ms: u32,
+
+    /// Which decoded frames the window draws when it cannot keep up
+    pub frame_drop: FrameDropPolicy,
+}
+
+/// What the window does with decoded frames it has not drawn yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrameDropPolicy {
+    /// Draw only the newest frame, for the lowest latency
+    LatestOnly,
+    /// Draw every frame in order, dropping only when far behind
+    RenderAll,
+    /// Draw frames at the pace of their timestamps, delayed by the jitter
+    /// buffer, for smooth motion when recording or watching videos
+    PtsPaced,
+}
+
+impl FrameDropPolicy {
+    pub const ALL: [FrameDropPolicy; 3] = [
+        FrameDropPolicy::LatestOnly,
+        FrameDropPolicy::RenderAll,
+        FrameDropPolicy::PtsPaced,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameDropPolicy::LatestOnly => "latest-only",
+            FrameDropPolicy::RenderAll => "render-all",
+            FrameDropPolicy::PtsPaced => "pts-paced",
+        }
+    }
+}
+
+impl std::str::FromStr for FrameDropPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown frame drop policy '{}' (one of: latest-only, render-all, pts-paced)",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LossRecovery {
+    /// Forward error correction (constant parity overhead, no round trip)
+    Fec,
+    /// Selective retransmission of missing packets (costs one RTT per loss)
+    Nack,
+    /// FEC first, then retransmit only the shards a stalled block still needs
+    Hybrid,
+    /// Pick NACK on low-RTT links and FEC otherwise
+    Auto,
+}
+
+impl LossRecovery {
+    /// Resolve `Auto` to a concrete strategy for the measured RTT
+    pub fn resolve(&self, rtt_ms: f64, nack_max_rtt_ms: u32) -> LossRecovery {
+        match self {
+            LossRecovery::Auto => {
+                if rtt_ms > 0.0 && rtt_ms <= nack_max_rtt_ms as f64 {
+                    LossRecovery::Nack
+                } else {
+                    LossRecovery::Fec
+                }
+            }
+            other => *other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputConfig {
+    /// Where device vibrations are played back
+    pub haptics: HapticFeedback,
+
+    /// Log every injected input event to this JSONL file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_log: Option<PathBuf>,
+
+    /// Re-inject the events of an earlier input log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay: Option<PathBuf>,
+
+    /// Key-to-touch game profiles (TOML, see `GameProfiles`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_profiles: Option<PathBuf>,
+
+    /// Game profile to use (by default the one for the foreground app, as it changes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_profile: Option<String>,
+
+    /// How physical key presses reach the device
+    pub keyboard: KeyboardMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayConfig {
+    /// Show device accessibility text (announcements, focused views) as captions
+    pub captions: bool,
+
+    /// Also write the captions to this SRT subtitle file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captions_srt: Option<PathBuf>,
+
+    /// Per-device color calibration profiles (TOML, see `ColorProfiles`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_profiles: Option<PathBuf>,
+
+    /// Where screenshots, frame bursts, replays and saved logcat output go
+    pub screenshot_dir: PathBuf,
+
+    /// Where files downloaded in the file panel (F2) are saved
+    pub downloads_dir: PathBuf,
+
+    /// Consecutive frames captured by a burst (F12)
+    pub burst_frames: u32,
+
+    /// Seconds of encoded video kept for instant replays (F4), 0 disables
+    pub replay_seconds: u32,
+
+    /// Show alignment guides from the start (toggle with F8)
+    pub guides: bool,
+
+    /// Show the on-screen keyboard from the start (toggle with F10)
+    pub keyboard: bool,
+
+    /// Show the logcat panel from the start (toggle with F1)
+    pub logcat: bool,
+
+    /// Show resolution, fps and latency in the window title
+    pub show_stats: bool,
+
+    /// Log the per-stage frame latency when the session ends (Shift+F9 any time)
+    pub latency_report: bool,
+
+    /// Append session statistics every second to this CSV (or `.json`/`.jsonl`) file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats_log: Option<PathBuf>,
+
+    /// Pop up short notifications over the video for session events
+    pub toasts: bool,
+
+    /// Show battery, temperature and WiFi signal in the window title
+    pub device_status: bool,
+
+    /// Battery temperature (°C) above which the device counts as overheating
+    pub overheat_celsius: f32,
+
+    /// Reopen the window where it was last closed, per device
+    pub remember_window: bool,
+
+    /// How the video is scaled into the window
+    pub scaling: ScalingMode,
+
+    /// Filter used to enlarge the video (not applied with integer scaling)
+    pub upscale_filter: UpscaleFilter,
+
+    /// Overlay UI colors
+    pub theme: UiTheme,
+
+    /// Overlay UI size (1.0 = native)
+    pub ui_scale: f32,
+
+    /// Per-device grid and safe-area guides (TOML, see `GuideProfiles`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guide_profiles: Option<PathBuf>,
+
+    /// Render the video at half resolution while the GPU misses its frame budget
+    pub adaptive_resolution: bool,
+
+    /// Mirror every connected adb device side by side in one window
+    pub grid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Push the server jar even when the device copy matches
+    pub force_push: bool,
+
+    /// Keep the device awake while plugged in, for the session
+    pub stay_awake: bool,
+
+    /// Show taps on the device screen, for the session
+    pub show_touches: bool,
+
+    /// End the session after this many minutes without input or screen changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u32>,
+
+    /// Turn the device display off when the idle timeout ends the session
+    pub sleep_on_idle: bool,
+
+    /// Package of an app to launch once mirroring begins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_app: Option<String>,
+
+    /// Force-stop `start_app` before launching it, so it starts fresh
+    pub force_stop_app: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Address to serve the control API on (disabled if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Directory of plugin libraries to load (none if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// Publish frames as an NDI source with this name (requires the `ndi` feature)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ndi: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestreamConfig {
+    /// Serve a browser viewer page over WebRTC here (requires the `webrtc` feature)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webrtc: Option<SocketAddr>,
+
+    /// Send the stream as MPEG-TS datagrams to this (multicast) address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mpegts_udp: Option<SocketAddr>,
+
+    /// Serve the stream as MPEG-TS over HTTP here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mpegts_http: Option<SocketAddr>,
+
+    /// Let other instances watch this session over TCP, view-only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HapticFeedback {
+    /// Ignore device vibrations
+    Off,
+    /// Rumble a connected gamepad (requires the `gamepad` feature)
+    Gamepad,
+    /// Play a short low-frequency tick on the PC audio output
+    Sound,
+    /// Gamepad when one with force feedback is connected, sound otherwise
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardMode {
+    /// Keys are not forwarded
+    Disabled,
+    /// Android key events injected through the input manager
+    Sdk,
+    /// A virtual HID keyboard on the device (Android 9+): real layouts, IME and repeat
+    Uhid,
+}
+
+impl std::str::FromStr for KeyboardMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(KeyboardMode::Disabled),
+            "sdk" => Ok(KeyboardMode::Sdk),
+            "uhid" => Ok(KeyboardMode::Uhid),
+            _ => Err(format!(
+                "unknown keyboard mode '{}' (one of: disabled, sdk, uhid)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalingMode {
+    /// Largest size that keeps the aspect ratio, with bars (bilinear)
+    Fit,
+    /// Stretched over the whole window, aspect ratio not kept (bilinear)
+    Fill,
+    /// Largest whole multiple (1x, 2x, ...) that fits, centered, nearest-neighbor
+    Integer,
+}
+
+impl std::str::FromStr for ScalingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(ScalingMode::Fit),
+            "fill" => Ok(ScalingMode::Fill),
+            "integer" => Ok(ScalingMode::Integer),
+            _ => Err(format!(
+                "unknown scaling mode '{}' (one of: fit, fill, integer)",
+                s
+            )),
+        }
+    }
+}
+
+/// Video upscaling shader, from cheapest to sharpest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpscaleFilter {
+    /// One hardware-filtered sample per pixel, the low-power choice
+    Bilinear,
+    /// 6x6 Lanczos3 kernel, sharp edges at some GPU cost
+    Lanczos,
+    /// Bilinear plus contrast-adaptive sharpening (FidelityFX CAS style)
+    Sharpen,
+}
+
+impl UpscaleFilter {
+    pub const ALL: [UpscaleFilter; 3] = [
+        UpscaleFilter::Bilinear,
+        UpscaleFilter::Lanczos,
+        UpscaleFilter::Sharpen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpscaleFilter::Bilinear => "bilinear",
+            UpscaleFilter::Lanczos => "lanczos",
+            UpscaleFilter::Sharpen => "sharpen",
+        }
+    }
+}
+
+impl std::str::FromStr for UpscaleFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|filter| filter.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown upscale filter '{}' (one of: bilinear, lanczos, sharpen)",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UiTheme {
+    Dark,
+    Light,
+    /// White on black with thick outlines and a visible focus ring
+    HighContrast,
+}
+
+impl Config {
+    /// Load a TOML config file
+    ///
+    /// Keys missing from the file keep their defaults and unknown sections
+    /// are ignored, so partial files work.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let file: toml::Value =
+            toml::from_str(&text).with_context(|| format!("Invalid TOML in {:?}", path))?;
+
+        let mut merged = toml::Value::try_from(Config::default())?;
+        merge_toml(&mut merged, file);
+        merged
+            .try_into()
+            .with_context(|| format!("Invalid config in {:?}", path))
+    }
+}
+
+/// Overlay `overlay` onto `base`, recursing into tables
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
 }
 
 impl Default for Config {
@@ -145,22 +911,38 @@ impl Default for Config {
         Self {
             connection: ConnectionConfig {
                 mode: ConnectionMode::Tcp,
-                host: "127.0.0.1".parse().unwrap(),
+                host: "127.0.0.1".to_string(),
                 port: 5555,
+                auth_token: None,
+                encrypt_payloads: false,
+                auto_switch: false,
+                tunnel: TunnelMode::Forward,
+                forward_port: None,
+                hotplug: false,
+                serial: None,
+                stall_timeout: 10,
+                dump_session: None,
+                replay: None,
             },
             video: VideoConfig {
+                enabled: true,
                 resolution: Resolution::FHD1080,
                 max_size: 0, // Default to native
                 codec: VideoCodec::H264,
                 bitrate: 8,
                 hw_accel: true,
                 hw_decoder: "auto".to_string(),
+                lock_orientation: None,
+                present_mode: PresentMode::Auto,
+                gpu_frames: false,
             },
             audio: AudioConfig {
                 enabled: true,
                 sample_rate: 48000,
                 channels: 2,
                 codec: AudioCodec::Opus,
+                source: AudioSource::Output,
+                duplicate: false,
             },
             performance: PerformanceConfig {
                 video_buffer_size: 1,    // Practically no buffering
@@ -168,7 +950,151 @@ impl Default for Config {
                 jitter_buffer_ms: 10,    // USB is stable, minimal jitter
                 adaptive_bitrate: false, // Stable connection doesn't need adaptive
                 fec_redundancy: 0,       // No packet loss on USB
+                adaptive_fec: false,
+                loss_recovery: LossRecovery::Fec,
+                nack_max_rtt_ms: 40, // Above this a retransmit arrives too late to help
+                frame_drop: FrameDropPolicy::LatestOnly,
+            },
+            input: InputConfig {
+                haptics: HapticFeedback::Off,
+                event_log: None,
+                replay: None,
+                game_profiles: None,
+                game_profile: None,
+                keyboard: KeyboardMode::Sdk,
+            },
+            display: DisplayConfig {
+                captions: false,
+                captions_srt: None,
+                color_profiles: None,
+                screenshot_dir: PathBuf::from("screenshots"),
+                downloads_dir: PathBuf::from("downloads"),
+                burst_frames: 30,
+                replay_seconds: 30,
+                guides: false,
+                keyboard: false,
+                logcat: false,
+                show_stats: true,
+                latency_report: false,
+                stats_log: None,
+                toasts: true,
+                device_status: true,
+                overheat_celsius: 45.0,
+                remember_window: true,
+                scaling: ScalingMode::Fit,
+                upscale_filter: UpscaleFilter::Bilinear,
+                theme: UiTheme::Dark,
+                ui_scale: 1.0,
+                guide_profiles: None,
+                adaptive_resolution: false,
+                grid: false,
+            },
+            server: ServerConfig {
+                force_push: false,
+                stay_awake: false,
+                show_touches: false,
+                idle_timeout: None,
+                sleep_on_idle: false,
+                start_app: None,
+                force_stop_app: false,
+            },
+            api: ApiConfig { listen: None },
+            plugins: PluginConfig { dir: None },
+            output: OutputConfig { ndi: None },
+            restream: RestreamConfig {
+                webrtc: None,
+                mpegts_udp: None,
+                mpegts_http: None,
+                share: None,
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_partial_file() {
+        let path = std::env::temp_dir().join(format!("scrcpy-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[connection]\nmode = \"quic\"\nauth_token = \"s3cret\"\n\n[video]\nresolution = \"720p\"\n\n[display]\nfullscreen = true\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(config.connection.mode, ConnectionMode::Quic));
+        assert_eq!(config.connection.auth_token.as_deref(), Some("s3cret"));
+        assert_eq!(config.connection.port, 5555);
+        assert_eq!(config.video.resolution.height(), 720);
+    }
+
+    #[test]
+    fn test_video_codec_from_id() {
+        assert!(matches!(
+            VideoCodec::from_codec_id(0x6832_3634),
+            Some(VideoCodec::H264)
+        ));
+        assert!(matches!(
+            VideoCodec::from_codec_id(0x6832_3635),
+            Some(VideoCodec::H265)
+        ));
+        assert!(matches!(
+            VideoCodec::from_codec_id(0x0061_7631),
+            Some(VideoCodec::Av1)
+        ));
+        assert!(VideoCodec::from_codec_id(0).is_none());
+        for codec in [VideoCodec::H264, VideoCodec::H265, VideoCodec::Av1] {
+            assert_eq!(VideoCodec::from_codec_id(codec.codec_id()), Some(codec));
+        }
+    }
+
+    #[test]
+    fn test_audio_source_names() {
+        for source in AudioSource::ALL {
+            assert_eq!(source.to_server_arg().parse::<AudioSource>(), Ok(source));
+            let toml = toml::Value::try_from(source).unwrap();
+            assert_eq!(toml.as_str(), Some(source.to_server_arg()));
+        }
+        assert!("microphone".parse::<AudioSource>().is_err());
+    }
+
+    #[test]
+    fn test_frame_drop_policy_names() {
+        for policy in FrameDropPolicy::ALL {
+            assert_eq!(policy.name().parse::<FrameDropPolicy>(), Ok(policy));
+            let toml = toml::Value::try_from(policy).unwrap();
+            assert_eq!(toml.as_str(), Some(policy.name()));
+        }
+        assert!("latest".parse::<FrameDropPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_orientation_degrees() {
+        assert_eq!("90".parse::<Orientation>(), Ok(Orientation::Rotated90));
+        assert!("45".parse::<Orientation>().is_err());
+        assert_eq!(Orientation::Rotated270.to_locked_server_arg(), "@270");
+
+        #[derive(Deserialize)]
+        struct Locked {
+            lock_orientation: Orientation,
+        }
+        let locked: Locked = toml::from_str("lock_orientation = 180").unwrap();
+        assert_eq!(locked.lock_orientation, Orientation::Rotated180);
+        assert!(toml::from_str::<Locked>("lock_orientation = 45").is_err());
+    }
+
+    #[test]
+    fn test_loss_recovery_resolve() {
+        assert_eq!(LossRecovery::Auto.resolve(10.0, 40), LossRecovery::Nack);
+        assert_eq!(LossRecovery::Auto.resolve(80.0, 40), LossRecovery::Fec);
+        // No RTT sample yet: stay on FEC
+        assert_eq!(LossRecovery::Auto.resolve(0.0, 40), LossRecovery::Fec);
+        assert_eq!(LossRecovery::Nack.resolve(200.0, 40), LossRecovery::Nack);
+        assert_eq!(LossRecovery::Hybrid.resolve(5.0, 40), LossRecovery::Hybrid);
+    }
+}
@@ -0,0 +1,138 @@
+/// Typed session events published to every interested subsystem
+///
+/// Producers (network thread, UI) publish what happened; the UI, the log and
+/// the control API subscribe instead of polling each other's state.
+use crate::config::{ConnectionMode, VideoCodec};
+use crate::video::DecodeQueueStats;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Events a slow subscriber may fall behind by before missing some
+const CAPACITY: usize = 256;
+
+/// Events kept for late readers such as `GET /events`
+const HISTORY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum SessionEvent {
+    /// The transport to the device server is up
+    Connected { mode: ConnectionMode },
+    /// The device introduced itself and the stream format is known
+    HandshakeComplete {
+        device_name: Option<String>,
+        codec: VideoCodec,
+    },
+    /// Decoded frames changed size (first frame, rotation, resize)
+    ResolutionChanged { width: u32, height: u32 },
+    /// Frames are being saved to `path`
+    RecordingStarted { path: PathBuf },
+    /// A screenshot was written to `path`
+    ScreenshotSaved { path: PathBuf },
+    /// The last seconds of video were saved to `path`
+    ReplaySaved { path: PathBuf },
+    /// No video arrived for a while, though the connection is up
+    StreamStalled,
+    /// Video arrives again after `StreamStalled`
+    StreamResumed,
+    /// Video decoding failed; the picture may be damaged until a keyframe
+    StreamCorrupted,
+    /// A keyframe decoded cleanly after `StreamCorrupted`
+    StreamRecovered,
+    /// The connection dropped and is being re-established
+    Reconnecting { attempt: u32 },
+    /// The session ended
+    Disconnected,
+    /// Periodic link and pipeline figures (about once a second)
+    StatsTick {
+        rtt_ms: f64,
+        packet_loss: f64,
+        bandwidth_mbps: f64,
+        decode_queue: DecodeQueueStats,
+    },
+}
+
+/// Broadcast channel for [`SessionEvent`]s, cheap to clone
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<SessionEvent>,
+    history: Arc<Mutex<VecDeque<SessionEvent>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+            history: Arc::default(),
+        }
+    }
+
+    /// Send `event` to current subscribers (there may be none)
+    pub fn publish(&self, event: SessionEvent) {
+        // Stats ticks would push everything else out of the history
+        if !matches!(event, SessionEvent::StatsTick { .. }) {
+            let mut history = self.history.lock();
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(event.clone());
+        }
+        let _ = self.tx.send(event);
+    }
+
+    /// Events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Latest events other than stats ticks, oldest first
+    pub fn recent(&self) -> Vec<SessionEvent> {
+        self.history.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribers_and_history() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+
+        bus.publish(SessionEvent::Reconnecting { attempt: 1 });
+        bus.publish(SessionEvent::StatsTick {
+            rtt_ms: 4.0,
+            packet_loss: 0.0,
+            bandwidth_mbps: 8.0,
+            decode_queue: DecodeQueueStats::default(),
+        });
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            SessionEvent::Reconnecting { attempt: 1 }
+        );
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            SessionEvent::StatsTick { .. }
+        ));
+        assert_eq!(
+            bus.recent(),
+            vec![SessionEvent::Reconnecting { attempt: 1 }]
+        );
+        assert_eq!(
+            serde_json::to_string(&bus.recent()[0]).unwrap(),
+            r#"{"event":"reconnecting","attempt":1}"#
+        );
+    }
+}
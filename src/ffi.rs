@@ -0,0 +1,321 @@
+//! C API over [`Client`], for C, C++ and C# desktop apps
+//!
+//! Built with the `cdylib` feature as a shared library:
+//! `cargo rustc --lib --release --crate-type cdylib --features cdylib`.
+//! `include/scrcpy_client.h` declares these functions; regenerate it with
+//! `cbindgen --config cbindgen.toml --output include/scrcpy_client.h` after
+//! changing them. All functions must be called from one thread at a time
+//! per client.
+
+use crate::audio::decoder::DecodedAudio;
+use crate::network::{ControlMessage, KeyAction, TouchAction};
+use crate::video::DecodedFrame;
+use crate::{Client, Config};
+use anyhow::{Context, Result};
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::path::Path;
+use std::ptr;
+use tokio::runtime::Runtime;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(error: &anyhow::Error) {
+    let message = format!("{:#}", error).replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).unwrap_or_default());
+}
+
+/// Mirroring session handle
+pub struct ScrcpyClient {
+    client: Client,
+    video: BoxStream<'static, DecodedFrame>,
+    audio: BoxStream<'static, DecodedAudio>,
+    /// Last polled frame and chunk, whose pixels and samples callers hold
+    frame: Option<DecodedFrame>,
+    chunk: Option<DecodedAudio>,
+    /// RGBA pixels of the last frame when it was decoded to another format
+    rgba: Vec<u8>,
+    // Dropped last: the client's tasks run on it
+    runtime: Runtime,
+}
+
+/// Decoded video frame, RGBA with 4 bytes per pixel
+#[repr(C)]
+pub struct ScrcpyFrame {
+    pub pts: i64,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+    /// Valid until the next `scrcpy_client_poll_frame` or disconnect
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// Decoded audio chunk, interleaved 32-bit float samples
+#[repr(C)]
+pub struct ScrcpyAudio {
+    pub pts: i64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Valid until the next `scrcpy_client_poll_audio` or disconnect
+    pub samples: *const f32,
+    /// Number of samples (frames times channels)
+    pub len: usize,
+}
+
+fn connect(config: Config) -> Result<Box<ScrcpyClient>> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the async runtime")?;
+    let mut client = runtime.block_on(Client::connect(&config))?;
+    Ok(Box::new(ScrcpyClient {
+        video: client.video_frames().boxed(),
+        audio: client.audio_frames().boxed(),
+        client,
+        frame: None,
+        chunk: None,
+        rgba: Vec::new(),
+        runtime,
+    }))
+}
+
+fn into_handle(result: Result<Box<ScrcpyClient>>) -> *mut ScrcpyClient {
+    match result {
+        Ok(client) => Box::into_raw(client),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `text` is null or a NUL-terminated string
+unsafe fn utf8<'a>(text: *const c_char, what: &str) -> Result<&'a str> {
+    anyhow::ensure!(!text.is_null(), "{} is null", what);
+    CStr::from_ptr(text)
+        .to_str()
+        .with_context(|| format!("{} is not UTF-8", what))
+}
+
+/// Connect over TCP to a scrcpy server listening at `host:port`
+///
+/// Returns null on failure, see `scrcpy_client_last_error`.
+///
+/// # Safety
+/// `host` is a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_connect(
+    host: *const c_char,
+    port: u16,
+) -> *mut ScrcpyClient {
+    into_handle(utf8(host, "host").and_then(|host| {
+        let mut config = Config::default();
+        config.connection.host = host.to_string();
+        config.connection.port = port;
+        connect(config)
+    }))
+}
+
+/// Connect with the settings of a config file (see config.example.toml)
+///
+/// Returns null on failure, see `scrcpy_client_last_error`.
+///
+/// # Safety
+/// `config_path` is a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_connect_config(
+    config_path: *const c_char,
+) -> *mut ScrcpyClient {
+    into_handle(
+        utf8(config_path, "config_path").and_then(|path| connect(Config::load(Path::new(path))?)),
+    )
+}
+
+/// Take the newest decoded frame, skipping older ones
+///
+/// Returns 1 when `frame` was filled, 0 when no new frame arrived since the
+/// last call and -1 once the session ended (or on null arguments). Never blocks.
+///
+/// # Safety
+/// `client` comes from a connect function; `frame` points to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_poll_frame(
+    client: *mut ScrcpyClient,
+    frame: *mut ScrcpyFrame,
+) -> i32 {
+    let (Some(client), Some(out)) = (client.as_mut(), frame.as_mut()) else {
+        return -1;
+    };
+    let (latest, ended) = drain(&mut client.video);
+    let Some(latest) = latest else {
+        return if ended { -1 } else { 0 };
+    };
+    // Hardware decoding and 10-bit streams yield other formats
+    let latest = client.frame.insert(latest);
+    let pixels = latest.rgba_into(&mut client.rgba);
+    *out = ScrcpyFrame {
+        pts: latest.pts,
+        width: latest.width,
+        height: latest.height,
+        stride: latest.width * 4,
+        data: pixels.as_ptr(),
+        len: pixels.len(),
+    };
+    1
+}
+
+/// Take the next decoded audio chunk, in order
+///
+/// Returns 1 when `audio` was filled, 0 when no chunk is waiting and -1 once
+/// the session ended (or on null arguments). Never blocks.
+///
+/// # Safety
+/// `client` comes from a connect function; `audio` points to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_poll_audio(
+    client: *mut ScrcpyClient,
+    audio: *mut ScrcpyAudio,
+) -> i32 {
+    let (Some(client), Some(out)) = (client.as_mut(), audio.as_mut()) else {
+        return -1;
+    };
+    let chunk = match client.audio.next().now_or_never() {
+        Some(Some(chunk)) => chunk,
+        Some(None) => return -1,
+        None => return 0,
+    };
+    *out = ScrcpyAudio {
+        pts: chunk.pts,
+        sample_rate: chunk.sample_rate,
+        channels: chunk.channels,
+        samples: chunk.samples.as_ptr(),
+        len: chunk.samples.len(),
+    };
+    client.chunk = Some(chunk);
+    1
+}
+
+/// Everything `stream` has ready: the last item, and whether it ended
+fn drain<T>(stream: &mut BoxStream<'static, T>) -> (Option<T>, bool) {
+    let mut latest = None;
+    loop {
+        match stream.next().now_or_never() {
+            Some(Some(item)) => latest = Some(item),
+            Some(None) => return (latest, true),
+            None => return (latest, false),
+        }
+    }
+}
+
+/// Press (`down` non-zero) or release an Android key (`AKEYCODE_*`)
+///
+/// Returns 0, or -1 if `client` is null.
+///
+/// # Safety
+/// `client` comes from a connect function
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_send_key(
+    client: *mut ScrcpyClient,
+    keycode: u32,
+    down: i32,
+    metastate: u32,
+) -> i32 {
+    let Some(client) = client.as_ref() else {
+        return -1;
+    };
+    client.client.send_control(ControlMessage::InjectKeycode {
+        action: if down != 0 {
+            KeyAction::Down
+        } else {
+            KeyAction::Up
+        },
+        keycode,
+        repeat: 0,
+        metastate,
+    });
+    0
+}
+
+/// Touch the screen: `action` 0 = down, 1 = up, 2 = move
+///
+/// `x` and `y` are in a `width` x `height` frame, normally the size of the
+/// last polled frame. Returns 0, or -1 if `client` is null or `action` unknown.
+///
+/// # Safety
+/// `client` comes from a connect function
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_send_touch(
+    client: *mut ScrcpyClient,
+    action: i32,
+    pointer_id: u64,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> i32 {
+    let Some(client) = client.as_ref() else {
+        return -1;
+    };
+    let action = match action {
+        0 => TouchAction::Down,
+        1 => TouchAction::Up,
+        2 => TouchAction::Move,
+        _ => return -1,
+    };
+    client.client.send_control(ControlMessage::InjectTouch {
+        action,
+        pointer_id,
+        x,
+        y,
+        width,
+        height,
+        pressure: if action == TouchAction::Up { 0.0 } else { 1.0 },
+    });
+    0
+}
+
+/// Ask the device for a keyframe, e.g. after dropping frames
+///
+/// # Safety
+/// `client` comes from a connect function
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_request_keyframe(client: *mut ScrcpyClient) -> i32 {
+    let Some(client) = client.as_ref() else {
+        return -1;
+    };
+    client.client.send_control(ControlMessage::RequestKeyframe);
+    0
+}
+
+/// Close the connection and free the client; null is ignored
+///
+/// # Safety
+/// `client` comes from a connect function and is not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn scrcpy_client_disconnect(client: *mut ScrcpyClient) {
+    if client.is_null() {
+        return;
+    }
+    let client = Box::from_raw(client);
+    let ScrcpyClient {
+        client: session,
+        runtime,
+        ..
+    } = *client;
+    runtime.block_on(session.close());
+}
+
+/// Why the last connect failed on this thread ("" if it did not)
+///
+/// The string stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn scrcpy_client_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
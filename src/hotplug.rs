@@ -0,0 +1,188 @@
+use crate::assets::Assets;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// A device coming or going, as reported by `adb track-devices`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Connected(String),
+    Disconnected(String),
+}
+
+/// Serials of the devices adb can talk to
+#[derive(Debug, Default)]
+struct DeviceList {
+    online: BTreeSet<String>,
+}
+
+impl DeviceList {
+    /// Replace the list with a `track-devices` message, returning what changed
+    ///
+    /// Messages list every device as `serial\tstate`; only `device` is usable
+    /// (`offline` and `unauthorized` count as gone).
+    fn update(&mut self, message: &str) -> Vec<DeviceEvent> {
+        let online: BTreeSet<String> = message
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .filter(|(_, state)| state.trim() == "device")
+            .map(|(serial, _)| serial.to_string())
+            .collect();
+
+        let mut events: Vec<DeviceEvent> = self
+            .online
+            .difference(&online)
+            .cloned()
+            .map(DeviceEvent::Disconnected)
+            .collect();
+        events.extend(
+            online
+                .difference(&self.online)
+                .cloned()
+                .map(DeviceEvent::Connected),
+        );
+        self.online = online;
+        events
+    }
+}
+
+/// Whether adb serial `serial` is the device asked for (any device if `None`)
+///
+/// Wireless devices are configured by host but listed as `host:port`.
+fn is_wanted(wanted: Option<&str>, serial: &str) -> bool {
+    match wanted {
+        None => true,
+        Some(wanted) => {
+            serial == wanted
+                || serial
+                    .strip_prefix(wanted)
+                    .is_some_and(|port| port.starts_with(':'))
+        }
+    }
+}
+
+/// Follows `adb track-devices` in the background
+///
+/// The `adb` process lives as long as the watcher.
+pub struct DeviceWatcher {
+    online: watch::Receiver<BTreeSet<String>>,
+    task: JoinHandle<()>,
+}
+
+impl DeviceWatcher {
+    pub fn spawn() -> Result<Self> {
+        let mut child = Command::new(Assets::get_adb_path()?)
+            .arg("track-devices")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run adb track-devices")?;
+        let mut stdout = child
+            .stdout
+            .take()
+            .context("adb track-devices has no output")?;
+
+        let (tx, rx) = watch::channel(BTreeSet::new());
+        let task = tokio::spawn(async move {
+            // Dropped (and killed) with the task
+            let _child = child;
+            let mut devices = DeviceList::default();
+            loop {
+                // Each message is a 4 hex digit length and the device list
+                let mut len = [0u8; 4];
+                if stdout.read_exact(&mut len).await.is_err() {
+                    break;
+                }
+                let Some(len) = std::str::from_utf8(&len)
+                    .ok()
+                    .and_then(|len| usize::from_str_radix(len, 16).ok())
+                else {
+                    warn!("Unexpected adb track-devices output");
+                    break;
+                };
+                let mut message = vec![0; len];
+                if stdout.read_exact(&mut message).await.is_err() {
+                    break;
+                }
+
+                for event in devices.update(&String::from_utf8_lossy(&message)) {
+                    match event {
+                        DeviceEvent::Connected(serial) => info!("Device connected: {}", serial),
+                        DeviceEvent::Disconnected(serial) => {
+                            info!("Device disconnected: {}", serial)
+                        }
+                    }
+                }
+                tx.send_replace(devices.online.clone());
+            }
+            warn!("adb track-devices ended, device hotplug is no longer followed");
+        });
+
+        Ok(Self { online: rx, task })
+    }
+
+    /// Whether the device (any device if `None`) is online
+    pub fn is_online(&self, serial: Option<&str>) -> bool {
+        self.online.borrow().iter().any(|s| is_wanted(serial, s))
+    }
+
+    /// Wait for the device to come online; false if the watcher stopped
+    pub async fn wait_online(&mut self, serial: Option<&str>) -> bool {
+        self.online
+            .wait_for(|online| online.iter().any(|s| is_wanted(serial, s)))
+            .await
+            .is_ok()
+    }
+
+    /// Wait up to `timeout` for the device to go offline
+    pub async fn wait_offline(&mut self, serial: Option<&str>, timeout: Duration) -> bool {
+        let offline = self
+            .online
+            .wait_for(|online| !online.iter().any(|s| is_wanted(serial, s)));
+        matches!(tokio::time::timeout(timeout, offline).await, Ok(Ok(_)))
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_list_events() {
+        let mut devices = DeviceList::default();
+        assert_eq!(
+            devices.update("R5CT1234\tdevice\nemulator-5554\toffline\n"),
+            vec![DeviceEvent::Connected("R5CT1234".into())]
+        );
+        assert_eq!(
+            devices.update("emulator-5554\tdevice\n192.168.1.20:5555\tdevice\n"),
+            vec![
+                DeviceEvent::Disconnected("R5CT1234".into()),
+                DeviceEvent::Connected("192.168.1.20:5555".into()),
+                DeviceEvent::Connected("emulator-5554".into()),
+            ]
+        );
+        assert_eq!(devices.update("").len(), 2);
+    }
+
+    #[test]
+    fn test_is_wanted() {
+        assert!(is_wanted(None, "R5CT1234"));
+        assert!(is_wanted(Some("R5CT1234"), "R5CT1234"));
+        assert!(is_wanted(Some("192.168.1.20"), "192.168.1.20:5555"));
+        assert!(!is_wanted(Some("192.168.1.2"), "192.168.1.20:5555"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::network::{ControlMessage, TouchAction};
+use crate::stats::Ewma;
+
+/// Move coalescing counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoalesceStats {
+    /// Touch moves handed to the coalescer
+    pub moves_received: u64,
+
+    /// Touch moves actually forwarded
+    pub moves_sent: u64,
+}
+
+impl CoalesceStats {
+    /// Fraction of moves that never reached the device (0.0 - 1.0)
+    pub fn reduction(&self) -> f64 {
+        if self.moves_received == 0 {
+            return 0.0;
+        }
+        1.0 - self.moves_sent as f64 / self.moves_received as f64
+    }
+}
+
+/// Collapses high-frequency pointer moves into one per video frame interval
+///
+/// Mice report at up to 1000 Hz while the device shows at most one new frame
+/// per refresh, so only the latest position per pointer inside each frame
+/// interval is forwarded. Downs, ups and every other message pass straight
+/// through, preceded by any pending move of the same pointer so the device
+/// sees the final position before the release.
+pub struct MoveCoalescer {
+    /// Smoothed frame interval in seconds
+    interval: Ewma,
+    last_frame: Option<Instant>,
+    last_sent: BTreeMap<u64, Instant>,
+    pending: BTreeMap<u64, ControlMessage>,
+    stats: CoalesceStats,
+}
+
+impl MoveCoalescer {
+    /// Fastest move rate allowed (240 Hz), even for high refresh streams
+    const MIN_INTERVAL: Duration = Duration::from_millis(4);
+
+    /// Slowest move rate (30 Hz), used while the screen is static
+    const MAX_INTERVAL: Duration = Duration::from_millis(33);
+
+    /// Create a coalescer assuming a 60 fps stream until frames arrive
+    pub fn new() -> Self {
+        Self {
+            interval: Ewma::with_initial(1.0 / 8.0, 0.016),
+            last_frame: None,
+            last_sent: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            stats: CoalesceStats::default(),
+        }
+    }
+
+    /// Track the video frame interval from presented frames
+    pub fn on_frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            let gap = now
+                .duration_since(last)
+                .clamp(Self::MIN_INTERVAL, Self::MAX_INTERVAL);
+            // Smooth over frame pacing jitter
+            self.interval.add(gap.as_secs_f64());
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Current forwarding interval
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.interval.get())
+    }
+
+    /// Feed a message, returning what should be sent right away
+    pub fn push(&mut self, msg: ControlMessage, now: Instant) -> Vec<ControlMessage> {
+        let (action, pointer_id) = match &msg {
+            ControlMessage::InjectTouch {
+                action, pointer_id, ..
+            } => (*action, *pointer_id),
+            _ => return vec![msg],
+        };
+
+        if action != TouchAction::Move {
+            // Flush the final position first, then restart the interval
+            let mut out: Vec<ControlMessage> = self.take_pending(pointer_id).into_iter().collect();
+            self.last_sent.remove(&pointer_id);
+            out.push(msg);
+            return out;
+        }
+
+        self.stats.moves_received += 1;
+        let due = self
+            .last_sent
+            .get(&pointer_id)
+            .is_none_or(|sent| now.duration_since(*sent) >= self.interval());
+        if due {
+            self.pending.remove(&pointer_id);
+            self.mark_sent(pointer_id, now);
+            return vec![msg];
+        }
+
+        self.pending.insert(pointer_id, msg);
+        Vec::new()
+    }
+
+    /// Release pending moves whose interval has elapsed
+    pub fn poll(&mut self, now: Instant) -> Vec<ControlMessage> {
+        let interval = self.interval();
+        let due: Vec<u64> = self
+            .pending
+            .keys()
+            .filter(|id| {
+                self.last_sent
+                    .get(id)
+                    .is_none_or(|sent| now.duration_since(*sent) >= interval)
+            })
+            .copied()
+            .collect();
+
+        due.into_iter()
+            .filter_map(|id| {
+                let msg = self.pending.remove(&id)?;
+                self.mark_sent(id, now);
+                Some(msg)
+            })
+            .collect()
+    }
+
+    /// Get coalescing statistics
+    pub fn stats(&self) -> CoalesceStats {
+        self.stats
+    }
+
+    fn take_pending(&mut self, pointer_id: u64) -> Option<ControlMessage> {
+        let msg = self.pending.remove(&pointer_id)?;
+        self.stats.moves_sent += 1;
+        Some(msg)
+    }
+
+    fn mark_sent(&mut self, pointer_id: u64, now: Instant) {
+        self.last_sent.insert(pointer_id, now);
+        self.stats.moves_sent += 1;
+    }
+}
+
+impl Default for MoveCoalescer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(action: TouchAction, x: u32) -> ControlMessage {
+        ControlMessage::InjectTouch {
+            action,
+            pointer_id: 0,
+            x,
+            y: 0,
+            width: 1080,
+            height: 1920,
+            pressure: 1.0,
+        }
+    }
+
+    fn x_of(msg: &ControlMessage) -> u32 {
+        match msg {
+            ControlMessage::InjectTouch { x, .. } => *x,
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_moves_collapse_to_latest_per_interval() {
+        let mut coalescer = MoveCoalescer::new();
+        let start = Instant::now();
+
+        assert_eq!(coalescer.push(touch(TouchAction::Down, 0), start).len(), 1);
+
+        // 1 kHz mouse: first move goes out, the rest wait for the interval
+        let mut sent = Vec::new();
+        for ms in 0..10u64 {
+            let now = start + Duration::from_millis(ms);
+            sent.extend(coalescer.push(touch(TouchAction::Move, ms as u32 + 1), now));
+        }
+        assert_eq!(sent.len(), 1);
+        assert!(coalescer.poll(start + Duration::from_millis(10)).is_empty());
+
+        let flushed = coalescer.poll(start + Duration::from_millis(20));
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(x_of(&flushed[0]), 10);
+        assert_eq!(coalescer.stats().moves_received, 10);
+        assert_eq!(coalescer.stats().moves_sent, 2);
+    }
+
+    #[test]
+    fn test_up_flushes_pending_move() {
+        let mut coalescer = MoveCoalescer::new();
+        let start = Instant::now();
+
+        coalescer.push(touch(TouchAction::Move, 1), start);
+        coalescer.push(touch(TouchAction::Move, 2), start);
+
+        let out = coalescer.push(touch(TouchAction::Up, 2), start);
+        assert_eq!(out.len(), 2);
+        assert_eq!(x_of(&out[0]), 2);
+        assert!(matches!(
+            out[1],
+            ControlMessage::InjectTouch {
+                action: TouchAction::Up,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_interval_follows_frame_rate() {
+        let mut coalescer = MoveCoalescer::new();
+        let start = Instant::now();
+        for i in 0..64 {
+            coalescer.on_frame(start + Duration::from_millis(8 * i));
+        }
+        assert!(coalescer.interval() <= Duration::from_millis(9));
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::network::ControlMessage;
+
+/// One injected input event, a line of the JSONL sidecar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    /// Milliseconds since the log was started
+    pub t_ms: u64,
+
+    /// PTS (microseconds) of the last video packet received before the event
+    pub video_pts: Option<i64>,
+
+    /// The message as it was sent to the device
+    pub event: ControlMessage,
+}
+
+/// Writes every injected input event to a JSONL file
+///
+/// Lines carry both wall-clock offsets (for replay) and the latest video PTS
+/// (to line events up with a recording of the stream).
+pub struct EventLog {
+    writer: BufWriter<File>,
+    started: Instant,
+    events: u64,
+}
+
+impl EventLog {
+    /// Create (or truncate) the log file
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create input log {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+            events: 0,
+        })
+    }
+
+    /// Append a message if it is user input; other control traffic is skipped
+    pub fn log(&mut self, event: &ControlMessage, video_pts: Option<i64>) -> Result<()> {
+        if !event.is_input() {
+            return Ok(());
+        }
+        let line = LoggedEvent {
+            t_ms: self.started.elapsed().as_millis() as u64,
+            video_pts,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.events += 1;
+        Ok(())
+    }
+
+    /// Number of events written so far
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Push buffered lines to disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a log back, in file order
+    pub fn read(path: &Path) -> Result<Vec<LoggedEvent>> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open input log {}", path.display()))?;
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(|(i, line)| {
+                serde_json::from_str(&line?)
+                    .with_context(|| format!("Invalid input log line {}", i + 1))
+            })
+            .collect()
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// Re-injects a recorded input log with its original timing
+pub struct EventReplay {
+    events: VecDeque<LoggedEvent>,
+    started: Option<Instant>,
+}
+
+impl EventReplay {
+    /// Create a replay of `events` (ordered by `t_ms`)
+    pub fn new(events: Vec<LoggedEvent>) -> Self {
+        Self {
+            events: events.into(),
+            started: None,
+        }
+    }
+
+    /// Messages whose time has come; the clock starts on the first call
+    pub fn poll(&mut self, now: Instant) -> Vec<ControlMessage> {
+        let started = *self.started.get_or_insert(now);
+        let elapsed = now.duration_since(started);
+
+        let mut due = Vec::new();
+        while let Some(next) = self.events.front() {
+            if Duration::from_millis(next.t_ms) > elapsed {
+                break;
+            }
+            if let Some(event) = self.events.pop_front() {
+                due.push(event.event);
+            }
+        }
+        due
+    }
+
+    /// When the next event is due, None once every event was handed out
+    ///
+    /// Before the first `poll` the replay has not started, so that is now.
+    pub fn next_due(&self) -> Option<Instant> {
+        let next = self.events.front()?;
+        Some(match self.started {
+            Some(started) => started + Duration::from_millis(next.t_ms),
+            None => Instant::now(),
+        })
+    }
+
+    /// Whether every event has been handed out
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{KeyAction, TouchAction};
+
+    fn touch(action: TouchAction) -> ControlMessage {
+        ControlMessage::InjectTouch {
+            action,
+            pointer_id: 0,
+            x: 10,
+            y: 20,
+            width: 1080,
+            height: 1920,
+            pressure: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_log_round_trip_skips_non_input() {
+        let path = std::env::temp_dir().join(format!("scrcpy-input-{}.jsonl", std::process::id()));
+        {
+            let mut log = EventLog::create(&path).unwrap();
+            log.log(&touch(TouchAction::Down), None).unwrap();
+            log.log(&ControlMessage::RequestKeyframe, Some(1)).unwrap();
+            log.log(
+                &ControlMessage::InjectKeycode {
+                    action: KeyAction::Down,
+                    keycode: 4,
+                    repeat: 0,
+                    metastate: 0,
+                },
+                Some(16_000),
+            )
+            .unwrap();
+            assert_eq!(log.events(), 2);
+        }
+
+        let events = EventLog::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].video_pts, None);
+        assert_eq!(events[1].video_pts, Some(16_000));
+        assert!(matches!(
+            events[1].event,
+            ControlMessage::InjectKeycode { keycode: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn test_replay_follows_timestamps() {
+        let event = |t_ms, action| LoggedEvent {
+            t_ms,
+            video_pts: None,
+            event: touch(action),
+        };
+        let mut replay = EventReplay::new(vec![
+            event(0, TouchAction::Down),
+            event(50, TouchAction::Move),
+            event(100, TouchAction::Up),
+        ]);
+
+        let start = Instant::now();
+        assert_eq!(replay.poll(start).len(), 1);
+        assert_eq!(replay.next_due(), Some(start + Duration::from_millis(50)));
+        assert!(replay.poll(start + Duration::from_millis(40)).is_empty());
+        assert_eq!(replay.poll(start + Duration::from_millis(120)).len(), 2);
+        assert!(replay.is_finished());
+        assert_eq!(replay.next_due(), None);
+    }
+}
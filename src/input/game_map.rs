@@ -0,0 +1,338 @@
+use crate::network::{ControlMessage, TouchAction};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// First pointer id of mapped touches, clear of real finger ids
+const POINTER_ID_BASE: u64 = 0x100;
+
+fn default_radius() -> f32 {
+    0.08
+}
+
+/// Virtual joystick driven by four direction keys
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Joystick {
+    /// Keys for up, left, down and right (winit key codes such as "KeyW")
+    pub keys: [String; 4],
+
+    /// Stick center as a fraction of the frame width and height
+    pub center: [f32; 2],
+
+    /// How far the stick is pushed, as a fraction of the frame's shorter side
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+}
+
+/// Key held down as a touch on one spot
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Button {
+    pub key: String,
+
+    /// Touch position as a fraction of the frame width and height
+    pub at: [f32; 2],
+}
+
+/// Touch zones of one game
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GameProfile {
+    /// Package the profile is picked for when it is in the foreground
+    pub app: Option<String>,
+
+    pub joystick: Option<Joystick>,
+
+    pub buttons: Vec<Button>,
+}
+
+/// Game profiles by name, loaded from a TOML file
+///
+/// ```toml
+/// [shooter]
+/// app = "com.example.shooter"
+/// joystick = { keys = ["KeyW", "KeyA", "KeyS", "KeyD"], center = [0.18, 0.72] }
+/// buttons = [
+///     { key = "Space", at = [0.92, 0.62] },
+///     { key = "KeyR", at = [0.80, 0.85] },
+/// ]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GameProfiles {
+    #[serde(flatten)]
+    profiles: HashMap<String, GameProfile>,
+}
+
+impl GameProfiles {
+    /// Load profiles from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read game profiles {:?}", path))?;
+        toml::from_str(&text).with_context(|| format!("Invalid game profiles in {:?}", path))
+    }
+
+    /// Profile called `name`, or else the one for the foreground `app`
+    pub fn select(&self, name: Option<&str>, app: Option<&str>) -> Option<(&str, &GameProfile)> {
+        match name {
+            Some(name) => self.profiles.get_key_value(name),
+            None => self
+                .profiles
+                .iter()
+                .find(|(_, profile)| app.is_some() && profile.app.as_deref() == app),
+        }
+        .map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    /// Profile after `current` in name order, None after the last one (mapping off)
+    pub fn next(&self, current: Option<&str>) -> Option<(&str, &GameProfile)> {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        let index = match current {
+            Some(current) => names.iter().position(|name| *name == current)? + 1,
+            None => 0,
+        };
+        let name = names.get(index)?;
+        Some((name.as_str(), &self.profiles[*name]))
+    }
+}
+
+/// Package of the resumed activity in `dumpsys activity activities` output
+pub fn parse_resumed_app(dumpsys: &str) -> Option<&str> {
+    dumpsys.lines().find_map(|line| {
+        let line = line.trim();
+        // "mResumedActivity: ActivityRecord{1a2b3c u0 com.example/.Main t42}"
+        let record = line
+            .strip_prefix("mResumedActivity:")
+            .or_else(|| line.strip_prefix("topResumedActivity="))?;
+        record
+            .split_whitespace()
+            .find_map(|word| word.split_once('/').map(|(package, _)| package))
+    })
+}
+
+/// Turns key presses into touches on the zones of a game profile
+pub struct GameMapper {
+    profile: GameProfile,
+    /// Joystick directions held, as indices into `Joystick::keys`
+    directions: HashSet<usize>,
+    stick_down: bool,
+    buttons_down: HashSet<usize>,
+}
+
+impl GameMapper {
+    pub fn new(profile: GameProfile) -> Self {
+        Self {
+            profile,
+            directions: HashSet::new(),
+            stick_down: false,
+            buttons_down: HashSet::new(),
+        }
+    }
+
+    /// Touches for a key going down or up in a `width` x `height` frame
+    ///
+    /// Returns None for keys the profile doesn't map, which should reach the
+    /// device as usual.
+    pub fn key(
+        &mut self,
+        key: &str,
+        pressed: bool,
+        size: (u32, u32),
+    ) -> Option<Vec<ControlMessage>> {
+        if let Some(stick) = &self.profile.joystick {
+            if let Some(direction) = stick.keys.iter().position(|k| k == key) {
+                let changed = if pressed {
+                    self.directions.insert(direction)
+                } else {
+                    self.directions.remove(&direction)
+                };
+                if !changed {
+                    return Some(Vec::new());
+                }
+                return Some(stick_touches(
+                    stick,
+                    &self.directions,
+                    &mut self.stick_down,
+                    size,
+                ));
+            }
+        }
+
+        let index = self.profile.buttons.iter().position(|b| b.key == key)?;
+        let button = &self.profile.buttons[index];
+        let pointer_id = POINTER_ID_BASE + 1 + index as u64;
+        let action = match pressed {
+            true if self.buttons_down.insert(index) => TouchAction::Down,
+            false if self.buttons_down.remove(&index) => TouchAction::Up,
+            _ => return Some(Vec::new()),
+        };
+        let (x, y) = to_pixels(button.at, size);
+        Some(vec![touch(action, pointer_id, (x, y), size)])
+    }
+
+    /// Lift every touch held by the mapping, before switching profiles
+    pub fn release_all(&mut self, size: (u32, u32)) -> Vec<ControlMessage> {
+        let mut touches = Vec::new();
+        if let Some(stick) = &self.profile.joystick {
+            self.directions.clear();
+            touches = stick_touches(stick, &self.directions, &mut self.stick_down, size);
+        }
+        for index in self.buttons_down.drain() {
+            let at = to_pixels(self.profile.buttons[index].at, size);
+            let pointer_id = POINTER_ID_BASE + 1 + index as u64;
+            touches.push(touch(TouchAction::Up, pointer_id, at, size));
+        }
+        touches
+    }
+}
+
+/// Touches moving the joystick to follow the held directions
+fn stick_touches(
+    stick: &Joystick,
+    directions: &HashSet<usize>,
+    down: &mut bool,
+    size: (u32, u32),
+) -> Vec<ControlMessage> {
+    let held = |i| directions.contains(&i) as i32 as f32;
+    // keys are up, left, down, right
+    let (dx, dy) = (held(3) - held(1), held(2) - held(0));
+    let center = to_pixels(stick.center, size);
+
+    let length = dx.hypot(dy);
+    if length == 0.0 {
+        // Opposite keys cancel out like a released stick
+        return if std::mem::take(down) {
+            vec![touch(TouchAction::Up, POINTER_ID_BASE, center, size)]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let reach = stick.radius * size.0.min(size.1) as f32;
+    let pushed = (
+        (center.0 as f32 + dx / length * reach).clamp(0.0, size.0.saturating_sub(1) as f32) as u32,
+        (center.1 as f32 + dy / length * reach).clamp(0.0, size.1.saturating_sub(1) as f32) as u32,
+    );
+    let mut touches = Vec::new();
+    if !std::mem::replace(down, true) {
+        touches.push(touch(TouchAction::Down, POINTER_ID_BASE, center, size));
+    }
+    touches.push(touch(TouchAction::Move, POINTER_ID_BASE, pushed, size));
+    touches
+}
+
+/// Frame pixel of a position given as fractions of the frame
+fn to_pixels([fx, fy]: [f32; 2], (width, height): (u32, u32)) -> (u32, u32) {
+    let x = (fx * width as f32).clamp(0.0, width.saturating_sub(1) as f32);
+    let y = (fy * height as f32).clamp(0.0, height.saturating_sub(1) as f32);
+    (x as u32, y as u32)
+}
+
+fn touch(
+    action: TouchAction,
+    pointer_id: u64,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+) -> ControlMessage {
+    ControlMessage::InjectTouch {
+        action,
+        pointer_id,
+        x,
+        y,
+        width,
+        height,
+        pressure: if action == TouchAction::Up { 0.0 } else { 1.0 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILES: &str = r#"
+[racer]
+app = "com.example.racer"
+joystick = { keys = ["KeyW", "KeyA", "KeyS", "KeyD"], center = [0.25, 0.5], radius = 0.1 }
+buttons = [{ key = "Space", at = [0.9, 0.5] }]
+"#;
+
+    fn position(msg: &ControlMessage) -> (TouchAction, u64, u32, u32) {
+        match msg {
+            ControlMessage::InjectTouch {
+                action,
+                pointer_id,
+                x,
+                y,
+                ..
+            } => (*action, *pointer_id, *x, *y),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_keys_drive_joystick_and_buttons() {
+        let profiles: GameProfiles = toml::from_str(PROFILES).unwrap();
+        let (_, profile) = profiles.select(None, Some("com.example.racer")).unwrap();
+        let mut mapper = GameMapper::new(profile.clone());
+        let size = (1000, 500);
+        let stick = POINTER_ID_BASE;
+
+        let down: Vec<_> = mapper
+            .key("KeyD", true, size)
+            .unwrap()
+            .iter()
+            .map(position)
+            .collect();
+        assert_eq!(
+            down,
+            [
+                (TouchAction::Down, stick, 250, 250),
+                (TouchAction::Move, stick, 300, 250)
+            ]
+        );
+        let diagonal = mapper.key("KeyW", true, size).unwrap();
+        assert_eq!(position(&diagonal[0]), (TouchAction::Move, stick, 285, 214));
+        mapper.key("KeyW", false, size).unwrap();
+        let up = mapper.key("KeyD", false, size).unwrap();
+        assert_eq!(position(&up[0]), (TouchAction::Up, stick, 250, 250));
+
+        let fire = mapper.key("Space", true, size).unwrap();
+        assert_eq!(position(&fire[0]), (TouchAction::Down, stick + 1, 900, 250));
+        assert!(mapper.key("Space", true, size).unwrap().is_empty());
+        assert!(mapper.key("KeyQ", true, size).is_none());
+
+        mapper.key("KeyA", true, size).unwrap();
+        let lifted: Vec<_> = mapper.release_all(size).iter().map(position).collect();
+        assert_eq!(
+            lifted,
+            [
+                (TouchAction::Up, stick, 250, 250),
+                (TouchAction::Up, stick + 1, 900, 250)
+            ]
+        );
+        assert!(mapper.release_all(size).is_empty());
+    }
+
+    #[test]
+    fn test_next_cycles_through_profiles_then_off() {
+        let profiles: GameProfiles = toml::from_str("[b]\n[a]\n").unwrap();
+        let name = |selected: Option<(&str, &GameProfile)>| selected.map(|(name, _)| name);
+        assert_eq!(name(profiles.next(None)), Some("a"));
+        assert_eq!(name(profiles.next(Some("a"))), Some("b"));
+        assert_eq!(name(profiles.next(Some("b"))), None);
+        assert_eq!(name(profiles.next(Some("gone"))), None);
+    }
+
+    #[test]
+    fn test_parse_resumed_app() {
+        let dumpsys = "  Task id #42\n    mResumedActivity: ActivityRecord{8f1c2d0 u0 com.example.racer/.MainActivity t42}\n";
+        assert_eq!(parse_resumed_app(dumpsys), Some("com.example.racer"));
+        assert_eq!(
+            parse_resumed_app(
+                "  topResumedActivity=ActivityRecord{1 u0 org.game/org.game.Main t7}"
+            ),
+            Some("org.game")
+        );
+        assert_eq!(parse_resumed_app("nothing resumed"), None);
+    }
+}
@@ -0,0 +1,94 @@
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
+use gilrs::{GamepadId, Gilrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Gamepad force feedback driven from its own thread
+///
+/// gilrs has to be polled to notice hot-plugged gamepads and its handles
+/// aren't `Send`, so it lives on a dedicated thread fed through a channel.
+pub struct Rumble {
+    tx: mpsc::Sender<(Duration, f32)>,
+    available: Arc<AtomicBool>,
+}
+
+impl Rumble {
+    /// How often the gamepad list is refreshed while idle
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Start the rumble thread (None if the thread couldn't be spawned)
+    pub fn spawn() -> Option<Self> {
+        let (tx, rx) = mpsc::channel::<(Duration, f32)>();
+        let available = Arc::new(AtomicBool::new(false));
+        let available_clone = available.clone();
+
+        thread::Builder::new()
+            .name("haptics".into())
+            .spawn(move || {
+                let mut gilrs = match Gilrs::new() {
+                    Ok(gilrs) => gilrs,
+                    Err(e) => {
+                        tracing::warn!("Gamepad support unavailable: {}", e);
+                        return;
+                    }
+                };
+                // Dropping an effect stops it, so the latest one is kept alive
+                let mut _playing: Option<Effect> = None;
+
+                loop {
+                    while gilrs.next_event().is_some() {}
+                    let pads: Vec<GamepadId> = gilrs
+                        .gamepads()
+                        .filter(|(_, pad)| pad.is_ff_supported())
+                        .map(|(id, _)| id)
+                        .collect();
+                    available_clone.store(!pads.is_empty(), Ordering::Relaxed);
+
+                    match rx.recv_timeout(Self::POLL_INTERVAL) {
+                        Ok((duration, strength)) if !pads.is_empty() => {
+                            match Self::play_effect(&mut gilrs, &pads, duration, strength) {
+                                Ok(effect) => _playing = Some(effect),
+                                Err(e) => tracing::debug!("Gamepad rumble failed: {}", e),
+                            }
+                        }
+                        Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })
+            .ok()?;
+
+        Some(Self { tx, available })
+    }
+
+    fn play_effect(
+        gilrs: &mut Gilrs,
+        pads: &[GamepadId],
+        duration: Duration,
+        strength: f32,
+    ) -> Result<Effect, gilrs::ff::Error> {
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: (strength.clamp(0.0, 1.0) * u16::MAX as f32) as u16,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(duration.as_millis() as u32),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .gamepads(pads)
+            .finish(gilrs)?;
+        effect.play()?;
+        Ok(effect)
+    }
+
+    /// Queue a rumble; false when no force feedback gamepad is connected
+    pub fn play(&self, duration: Duration, strength: f32) -> bool {
+        self.available.load(Ordering::Relaxed) && self.tx.send((duration, strength)).is_ok()
+    }
+}
@@ -0,0 +1,234 @@
+use winit::event::MouseScrollDelta;
+
+use super::{POINTER_ID_GENERIC_FINGER, POINTER_ID_VIRTUAL_FINGER};
+use crate::network::{ControlMessage, TouchAction};
+
+/// Wheel pixels (touchpads) per scroll step
+const PIXELS_PER_STEP: f32 = 40.0;
+
+/// Scroll steps sent per event at most, so a flung touchpad stays usable
+const MAX_SCROLL: f32 = 16.0;
+
+/// Two-finger gesture a modifier turns a mouse drag into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    /// Fingers move apart and together (Ctrl+drag)
+    Pinch,
+    /// Fingers turn around the middle (Shift+drag)
+    Rotate,
+}
+
+/// Gesture in progress, in video pixels
+struct Gesture {
+    kind: GestureKind,
+    size: (u32, u32),
+    center: (f64, f64),
+    /// Distance of the fingers from the center at the press
+    radius: f64,
+    /// Direction of the mouse finger at the press, in radians
+    angle: f64,
+    /// Radius and angle last sent, where the fingers lift
+    last: (f64, f64),
+}
+
+/// Turns mouse drags into two-finger touch gestures
+///
+/// The mouse moves one finger and a virtual one mirrors it through the middle
+/// of the video, like scrcpy's Ctrl+drag. A pinch keeps the fingers on the
+/// line they started on; a rotation keeps their distance.
+#[derive(Default)]
+pub struct MouseGestures {
+    active: Option<Gesture>,
+}
+
+impl MouseGestures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a drag is being turned into a gesture
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Put both fingers down for a press at `position` in a `size` frame
+    pub fn press(
+        &mut self,
+        kind: GestureKind,
+        (x, y): (u32, u32),
+        size: (u32, u32),
+    ) -> Vec<ControlMessage> {
+        let center = (size.0 as f64 / 2.0, size.1 as f64 / 2.0);
+        let (dx, dy) = (x as f64 - center.0, y as f64 - center.1);
+        // A press on the middle would put both fingers in one spot
+        let min_radius = size.0.min(size.1) as f64 / 20.0;
+        let (radius, angle) = match dx.hypot(dy) {
+            distance if distance >= min_radius => (distance, dy.atan2(dx)),
+            _ => (min_radius, 0.0),
+        };
+        let gesture = Gesture {
+            kind,
+            size,
+            center,
+            radius,
+            angle,
+            last: (radius, angle),
+        };
+        let messages = gesture.fingers(TouchAction::Down, radius, angle);
+        self.active = Some(gesture);
+        messages
+    }
+
+    /// Move both fingers for the mouse at `position`
+    pub fn drag(&mut self, (x, y): (u32, u32)) -> Vec<ControlMessage> {
+        let Some(gesture) = &mut self.active else {
+            return Vec::new();
+        };
+        let (dx, dy) = (x as f64 - gesture.center.0, y as f64 - gesture.center.1);
+        let (radius, angle) = match gesture.kind {
+            // Only the part of the drag along the fingers' line counts
+            GestureKind::Pinch => {
+                let along = dx * gesture.angle.cos() + dy * gesture.angle.sin();
+                (along.max(1.0), gesture.angle)
+            }
+            GestureKind::Rotate if dx == 0.0 && dy == 0.0 => (gesture.radius, gesture.angle),
+            GestureKind::Rotate => (gesture.radius, dy.atan2(dx)),
+        };
+        gesture.last = (radius, angle);
+        gesture.fingers(TouchAction::Move, radius, angle)
+    }
+
+    /// Lift both fingers
+    pub fn release(&mut self) -> Vec<ControlMessage> {
+        match self.active.take() {
+            Some(gesture) => {
+                let (radius, angle) = gesture.last;
+                gesture.fingers(TouchAction::Up, radius, angle)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Gesture {
+    /// Touch events for the mouse finger at `radius` and `angle` from the
+    /// center and the virtual finger opposite it
+    fn fingers(&self, action: TouchAction, radius: f64, angle: f64) -> Vec<ControlMessage> {
+        let (width, height) = self.size;
+        let (dx, dy) = (radius * angle.cos(), radius * angle.sin());
+        let clamp = |v: f64, len: u32| v.round().clamp(0.0, len.saturating_sub(1) as f64) as u32;
+        [
+            (POINTER_ID_GENERIC_FINGER, dx, dy),
+            (POINTER_ID_VIRTUAL_FINGER, -dx, -dy),
+        ]
+        .into_iter()
+        .map(|(pointer_id, dx, dy)| ControlMessage::InjectTouch {
+            action,
+            pointer_id,
+            x: clamp(self.center.0 + dx, width),
+            y: clamp(self.center.1 + dy, height),
+            width,
+            height,
+            pressure: if action == TouchAction::Up { 0.0 } else { 1.0 },
+        })
+        .collect()
+    }
+}
+
+/// Scroll event for the wheel turning with the pointer at `position`
+pub fn wheel_scroll(
+    (x, y): (u32, u32),
+    delta: MouseScrollDelta,
+    (width, height): (u32, u32),
+) -> ControlMessage {
+    let (hscroll, vscroll) = match delta {
+        MouseScrollDelta::LineDelta(h, v) => (h, v),
+        MouseScrollDelta::PixelDelta(position) => (
+            position.x as f32 / PIXELS_PER_STEP,
+            position.y as f32 / PIXELS_PER_STEP,
+        ),
+    };
+    ControlMessage::InjectScroll {
+        x,
+        y,
+        width,
+        height,
+        hscroll: hscroll.clamp(-MAX_SCROLL, MAX_SCROLL),
+        vscroll: vscroll.clamp(-MAX_SCROLL, MAX_SCROLL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(messages: &[ControlMessage]) -> Vec<(TouchAction, u64, u32, u32)> {
+        messages
+            .iter()
+            .map(|message| match message {
+                ControlMessage::InjectTouch {
+                    action,
+                    pointer_id,
+                    x,
+                    y,
+                    ..
+                } => (*action, *pointer_id, *x, *y),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pinch_and_rotate_mirror_through_the_middle() {
+        use TouchAction::*;
+        let (mouse, virt) = (POINTER_ID_GENERIC_FINGER, POINTER_ID_VIRTUAL_FINGER);
+        let size = (100, 100);
+        let mut gestures = MouseGestures::new();
+        assert!(gestures.drag((10, 10)).is_empty());
+
+        let down = gestures.press(GestureKind::Pinch, (75, 50), size);
+        assert_eq!(
+            positions(&down),
+            [(Down, mouse, 75, 50), (Down, virt, 25, 50)]
+        );
+        // Sideways motion does not count towards a pinch
+        assert_eq!(
+            positions(&gestures.drag((90, 70))),
+            [(Move, mouse, 90, 50), (Move, virt, 10, 50)]
+        );
+        assert_eq!(positions(&gestures.release()).len(), 2);
+        assert!(!gestures.is_active());
+
+        gestures.press(GestureKind::Rotate, (75, 50), size);
+        assert_eq!(
+            positions(&gestures.drag((50, 90))),
+            [(Move, mouse, 50, 75), (Move, virt, 50, 25)]
+        );
+        assert!(matches!(
+            positions(&gestures.release())[..],
+            [(Up, _, _, _), (Up, _, _, _)]
+        ));
+    }
+
+    #[test]
+    fn test_wheel_scroll() {
+        let lines = wheel_scroll((5, 6), MouseScrollDelta::LineDelta(0.0, -1.0), (10, 20));
+        assert!(matches!(
+            lines,
+            ControlMessage::InjectScroll {
+                x: 5,
+                y: 6,
+                width: 10,
+                height: 20,
+                hscroll,
+                vscroll,
+            } if hscroll == 0.0 && vscroll == -1.0
+        ));
+        let fling = MouseScrollDelta::PixelDelta((80.0, 4000.0).into());
+        assert!(matches!(
+            wheel_scroll((0, 0), fling, (10, 20)),
+            ControlMessage::InjectScroll { hscroll, vscroll, .. }
+                if hscroll == 2.0 && vscroll == MAX_SCROLL
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use crate::audio::AudioPlayer;
+use crate::config::HapticFeedback;
+
+#[cfg(feature = "gamepad")]
+use super::gamepad::Rumble;
+
+/// Plays device vibrations back on the PC
+///
+/// Gamepads rumble for the reported duration; the sound fallback is a short
+/// decaying low-frequency tick mixed into the audio output, capped in length
+/// so long vibrations don't turn into a drone.
+pub struct Haptics {
+    mode: HapticFeedback,
+    #[cfg(feature = "gamepad")]
+    rumble: Option<Rumble>,
+}
+
+impl Haptics {
+    /// Strength used when the device reports its default amplitude
+    const DEFAULT_STRENGTH: f32 = 0.6;
+
+    /// Longest sound played for a single vibration
+    const MAX_TICK: Duration = Duration::from_millis(60);
+
+    /// Tick tone frequency, low enough to read as a thump
+    const TICK_FREQUENCY_HZ: f32 = 150.0;
+
+    /// Peak sample value at full strength (kept subtle next to device audio)
+    const TICK_PEAK: f32 = 0.2;
+
+    /// Create a haptics player for the configured feedback mode
+    pub fn new(mode: HapticFeedback) -> Self {
+        #[cfg(not(feature = "gamepad"))]
+        if mode == HapticFeedback::Gamepad {
+            tracing::warn!("Gamepad haptics need the `gamepad` feature, vibrations are ignored");
+        }
+
+        Self {
+            mode,
+            #[cfg(feature = "gamepad")]
+            rumble: match mode {
+                HapticFeedback::Gamepad | HapticFeedback::Auto => Rumble::spawn(),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether vibrations should be requested from the device at all
+    pub fn enabled(&self) -> bool {
+        self.mode != HapticFeedback::Off
+    }
+
+    /// Play a device vibration
+    ///
+    /// # Arguments
+    /// * `duration_ms` - Vibration length reported by the device
+    /// * `amplitude` - Android amplitude 1-255, 0 for the device default
+    /// * `audio` - Output for the sound fallback, if audio is running
+    pub fn vibrate(&mut self, duration_ms: u32, amplitude: u8, audio: Option<&mut AudioPlayer>) {
+        let duration = Duration::from_millis(duration_ms as u64);
+        let strength = if amplitude == 0 {
+            Self::DEFAULT_STRENGTH
+        } else {
+            amplitude as f32 / 255.0
+        };
+
+        if matches!(self.mode, HapticFeedback::Gamepad | HapticFeedback::Auto)
+            && self.rumble(duration, strength)
+        {
+            return;
+        }
+
+        if matches!(self.mode, HapticFeedback::Sound | HapticFeedback::Auto) {
+            if let Some(player) = audio {
+                let (sample_rate, channels) = player.format();
+                let samples = Self::tick_samples(duration, strength, sample_rate, channels);
+                if let Err(e) = player.play_effect(samples) {
+                    tracing::debug!("Failed to play haptic tick: {}", e);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn rumble(&self, duration: Duration, strength: f32) -> bool {
+        self.rumble
+            .as_ref()
+            .is_some_and(|rumble| rumble.play(duration, strength))
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn rumble(&self, _duration: Duration, _strength: f32) -> bool {
+        false
+    }
+
+    /// Interleaved samples of a decaying tick for the given vibration
+    pub fn tick_samples(
+        duration: Duration,
+        strength: f32,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Vec<f32> {
+        let length = duration.min(Self::MAX_TICK).as_secs_f32();
+        let frames = (length * sample_rate as f32) as usize;
+        let peak = Self::TICK_PEAK * strength.clamp(0.0, 1.0);
+
+        let mut samples = Vec::with_capacity(frames * channels as usize);
+        for i in 0..frames {
+            let t = i as f32 / sample_rate as f32;
+            // Exponential decay reaching ~2% at the end of the tick
+            let envelope = (-4.0 * t / length).exp();
+            let value = peak * envelope * (TAU * Self::TICK_FREQUENCY_HZ * t).sin();
+            samples.extend(std::iter::repeat_n(value, channels as usize));
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_is_short_and_subtle() {
+        let samples = Haptics::tick_samples(Duration::from_millis(500), 1.0, 48000, 2);
+
+        // Capped at 60ms of stereo audio
+        assert_eq!(samples.len(), 48 * 60 * 2);
+        assert!(samples.iter().all(|s| s.abs() <= Haptics::TICK_PEAK));
+
+        // Decays towards silence
+        let head: f32 = samples[..960].iter().map(|s| s.abs()).sum();
+        let tail: f32 = samples[samples.len() - 960..].iter().map(|s| s.abs()).sum();
+        assert!(tail < head / 4.0);
+    }
+
+    #[test]
+    fn test_off_mode_is_disabled() {
+        assert!(!Haptics::new(HapticFeedback::Off).enabled());
+        assert!(Haptics::new(HapticFeedback::Sound).enabled());
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+/// Bytes sampled from each frame to tell a static screen from a changing one
+const FINGERPRINT_SAMPLES: usize = 4096;
+
+/// Ends forgotten sessions: no local input and an unchanging screen for `timeout`
+///
+/// The encoder repeats the last frame while the device is idle, so arriving
+/// frames alone do not count as activity; only frames whose content changed do.
+pub struct IdleTimer {
+    timeout: Duration,
+    last_activity: Instant,
+    fingerprint: Option<u64>,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_activity: now,
+            fingerprint: None,
+        }
+    }
+
+    /// User input in the window
+    pub fn input(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    /// A decoded frame; activity only if the picture differs from the last one
+    pub fn frame(&mut self, data: &[u8], now: Instant) {
+        let fingerprint = Self::fingerprint(data);
+        if self.fingerprint != Some(fingerprint) {
+            self.fingerprint = Some(fingerprint);
+            self.last_activity = now;
+        }
+    }
+
+    /// Whether the session has been idle for the whole timeout
+    pub fn expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_activity) >= self.timeout
+    }
+
+    /// FNV-1a over evenly spaced bytes of the frame
+    fn fingerprint(data: &[u8]) -> u64 {
+        let step = (data.len() / FINGERPRINT_SAMPLES).max(1);
+        data.iter()
+            .step_by(step)
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_frames_do_not_keep_session_alive() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut idle = IdleTimer::new(minute * 10, start);
+
+        idle.frame(&[1; 64], start);
+        // The encoder keeps resending the same picture
+        for m in 1..=10 {
+            idle.frame(&[1; 64], start + minute * m);
+        }
+        assert!(idle.expired(start + minute * 10));
+
+        // New content restarts the countdown, and so does input
+        idle.frame(&[2; 64], start + minute * 10);
+        assert!(!idle.expired(start + minute * 19));
+        idle.input(start + minute * 19);
+        assert!(!idle.expired(start + minute * 28));
+        assert!(idle.expired(start + minute * 29));
+    }
+}
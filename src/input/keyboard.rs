@@ -0,0 +1,441 @@
+use crate::config::KeyboardMode;
+use crate::network::{ControlMessage, KeyAction};
+use winit::keyboard::KeyCode;
+
+/// UHID device id of the keyboard
+pub const UHID_KEYBOARD_ID: u16 = 1;
+
+/// HID boot keyboard: modifier bits, a reserved byte and six key slots, plus LEDs
+const REPORT_DESC: [u8; 63] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0xe0, //   Usage Minimum (Left Control)
+    0x29, 0xe7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute): modifiers
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x01, //   Input (Constant): reserved
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (Num Lock)
+    0x29, 0x05, //   Usage Maximum (Kana)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x05, //   Report Count (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute): LEDs
+    0x75, 0x03, //   Report Size (3)
+    0x95, 0x01, //   Report Count (1)
+    0x91, 0x01, //   Output (Constant): padding
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0x65, //   Usage Maximum (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x65, //   Logical Maximum (101)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x00, //   Input (Data, Array): pressed keys
+    0xc0, // End Collection
+];
+
+/// Keys a boot keyboard report holds at once
+const REPORT_KEYS: usize = 6;
+
+/// Usage reported in every slot when more keys are held than fit
+const ERROR_ROLL_OVER: u8 = 0x01;
+
+/// `AMETA_*` bits for Android key events
+mod meta {
+    pub const SHIFT_LEFT: u32 = 0x41;
+    pub const SHIFT_RIGHT: u32 = 0x81;
+    pub const ALT_LEFT: u32 = 0x12;
+    pub const ALT_RIGHT: u32 = 0x22;
+    pub const CTRL_LEFT: u32 = 0x3000;
+    pub const CTRL_RIGHT: u32 = 0x5000;
+    pub const META_LEFT: u32 = 0x3_0000;
+    pub const META_RIGHT: u32 = 0x5_0000;
+}
+
+/// Message that makes the device create the UHID keyboard, sent per session
+pub fn uhid_create() -> ControlMessage {
+    ControlMessage::UhidCreate {
+        id: UHID_KEYBOARD_ID,
+        name: "scrcpy-custom keyboard".to_string(),
+        report_desc: REPORT_DESC.to_vec(),
+    }
+}
+
+/// Physical keyboard forwarding in the configured [`KeyboardMode`]
+///
+/// F1-F12 are left out: they are the window's shortcuts.
+pub struct KeyForwarder {
+    mode: KeyboardMode,
+    /// Keys sent down and not yet up, oldest first
+    held: Vec<KeyCode>,
+    /// Repeats of the newest held key (sdk)
+    repeat: u32,
+}
+
+impl KeyForwarder {
+    pub fn new(mode: KeyboardMode) -> Self {
+        Self {
+            mode,
+            held: Vec::new(),
+            repeat: 0,
+        }
+    }
+
+    /// Messages for a key going down, repeating or up
+    ///
+    /// Repeats only count for keys this forwarder sent down, so keys a game
+    /// profile took never leak through.
+    pub fn key(&mut self, code: KeyCode, pressed: bool, repeat: bool) -> Vec<ControlMessage> {
+        // Only a fresh press may go down; repeats and releases need it held
+        let expected = if pressed && !repeat {
+            !self.held.contains(&code)
+        } else {
+            self.held.contains(&code)
+        };
+        if !expected {
+            return Vec::new();
+        }
+        match self.mode {
+            KeyboardMode::Disabled => Vec::new(),
+            KeyboardMode::Sdk => {
+                let Some(keycode) = android_keycode(code) else {
+                    return Vec::new();
+                };
+                match (pressed, repeat) {
+                    (true, true) => self.repeat += 1,
+                    (true, false) => {
+                        self.held.push(code);
+                        self.repeat = 0;
+                    }
+                    (false, _) => self.held.retain(|&held| held != code),
+                }
+                vec![ControlMessage::InjectKeycode {
+                    action: if pressed {
+                        KeyAction::Down
+                    } else {
+                        KeyAction::Up
+                    },
+                    keycode,
+                    repeat: if pressed { self.repeat } else { 0 },
+                    metastate: self.metastate(),
+                }]
+            }
+            KeyboardMode::Uhid => {
+                // The device repeats held keys itself
+                if repeat || hid_usage(code).is_none() {
+                    return Vec::new();
+                }
+                if pressed {
+                    self.held.push(code);
+                } else {
+                    self.held.retain(|&held| held != code);
+                }
+                vec![self.report()]
+            }
+        }
+    }
+
+    /// Let go of every held key, e.g. when the window loses focus
+    pub fn release_all(&mut self) -> Vec<ControlMessage> {
+        if self.held.is_empty() {
+            return Vec::new();
+        }
+        match self.mode {
+            KeyboardMode::Uhid => {
+                self.held.clear();
+                vec![self.report()]
+            }
+            _ => {
+                let held = std::mem::take(&mut self.held);
+                held.into_iter()
+                    .rev()
+                    .filter_map(|code| {
+                        let keycode = android_keycode(code)?;
+                        Some(ControlMessage::InjectKeycode {
+                            action: KeyAction::Up,
+                            keycode,
+                            repeat: 0,
+                            metastate: 0,
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Android meta state of the held modifiers
+    fn metastate(&self) -> u32 {
+        self.held
+            .iter()
+            .map(|code| match code {
+                KeyCode::ShiftLeft => meta::SHIFT_LEFT,
+                KeyCode::ShiftRight => meta::SHIFT_RIGHT,
+                KeyCode::AltLeft => meta::ALT_LEFT,
+                KeyCode::AltRight => meta::ALT_RIGHT,
+                KeyCode::ControlLeft => meta::CTRL_LEFT,
+                KeyCode::ControlRight => meta::CTRL_RIGHT,
+                KeyCode::SuperLeft => meta::META_LEFT,
+                KeyCode::SuperRight => meta::META_RIGHT,
+                _ => 0,
+            })
+            .fold(0, |state, bits| state | bits)
+    }
+
+    /// Boot keyboard input report for the held keys
+    fn report(&self) -> ControlMessage {
+        let mut data = vec![0u8; 2 + REPORT_KEYS];
+        let mut keys = Vec::with_capacity(REPORT_KEYS);
+        for usage in self.held.iter().filter_map(|&code| hid_usage(code)) {
+            match usage {
+                // Left Control .. Right GUI are bits of the first byte
+                0xe0..=0xe7 => data[0] |= 1 << (usage - 0xe0),
+                _ => keys.push(usage),
+            }
+        }
+        if keys.len() > REPORT_KEYS {
+            data[2..].fill(ERROR_ROLL_OVER);
+        } else {
+            data[2..2 + keys.len()].copy_from_slice(&keys);
+        }
+        ControlMessage::UhidInput {
+            id: UHID_KEYBOARD_ID,
+            data,
+        }
+    }
+}
+
+/// Android `KEYCODE_*` for a physical key
+fn android_keycode(code: KeyCode) -> Option<u32> {
+    use KeyCode::*;
+    let letters = [
+        KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN, KeyO,
+        KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    ];
+    let digits = [
+        Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    ];
+    let numpad = [
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ];
+    if let Some(i) = letters.iter().position(|&key| key == code) {
+        return Some(29 + i as u32);
+    }
+    if let Some(i) = digits.iter().position(|&key| key == code) {
+        return Some(7 + i as u32);
+    }
+    if let Some(i) = numpad.iter().position(|&key| key == code) {
+        return Some(144 + i as u32);
+    }
+    Some(match code {
+        ArrowUp => 19,
+        ArrowDown => 20,
+        ArrowLeft => 21,
+        ArrowRight => 22,
+        Comma => 55,
+        Period => 56,
+        AltLeft => 57,
+        AltRight => 58,
+        ShiftLeft => 59,
+        ShiftRight => 60,
+        Tab => 61,
+        Space => 62,
+        Enter => 66,
+        Backspace => 67,
+        Backquote => 68,
+        Minus => 69,
+        Equal => 70,
+        BracketLeft => 71,
+        BracketRight => 72,
+        Backslash => 73,
+        Semicolon => 74,
+        Quote => 75,
+        Slash => 76,
+        ContextMenu => 82,
+        PageUp => 92,
+        PageDown => 93,
+        Escape => 111,
+        Delete => 112,
+        ControlLeft => 113,
+        ControlRight => 114,
+        CapsLock => 115,
+        ScrollLock => 116,
+        SuperLeft => 117,
+        SuperRight => 118,
+        PrintScreen => 120,
+        Pause => 121,
+        Home => 122,
+        End => 123,
+        Insert => 124,
+        NumLock => 143,
+        NumpadDivide => 154,
+        NumpadMultiply => 155,
+        NumpadSubtract => 156,
+        NumpadAdd => 157,
+        NumpadDecimal => 158,
+        NumpadComma => 159,
+        NumpadEnter => 160,
+        NumpadEqual => 161,
+        _ => return None,
+    })
+}
+
+/// USB HID keyboard usage for a physical key
+fn hid_usage(code: KeyCode) -> Option<u8> {
+    use KeyCode::*;
+    let letters = [
+        KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN, KeyO,
+        KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    ];
+    // 1-9 then 0, in usage order
+    let digits = [
+        Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0,
+    ];
+    let numpad = [
+        Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, Numpad0,
+    ];
+    if let Some(i) = letters.iter().position(|&key| key == code) {
+        return Some(0x04 + i as u8);
+    }
+    if let Some(i) = digits.iter().position(|&key| key == code) {
+        return Some(0x1e + i as u8);
+    }
+    if let Some(i) = numpad.iter().position(|&key| key == code) {
+        return Some(0x59 + i as u8);
+    }
+    Some(match code {
+        Enter => 0x28,
+        Escape => 0x29,
+        Backspace => 0x2a,
+        Tab => 0x2b,
+        Space => 0x2c,
+        Minus => 0x2d,
+        Equal => 0x2e,
+        BracketLeft => 0x2f,
+        BracketRight => 0x30,
+        Backslash => 0x31,
+        Semicolon => 0x33,
+        Quote => 0x34,
+        Backquote => 0x35,
+        Comma => 0x36,
+        Period => 0x37,
+        Slash => 0x38,
+        CapsLock => 0x39,
+        PrintScreen => 0x46,
+        ScrollLock => 0x47,
+        Pause => 0x48,
+        Insert => 0x49,
+        Home => 0x4a,
+        PageUp => 0x4b,
+        Delete => 0x4c,
+        End => 0x4d,
+        PageDown => 0x4e,
+        ArrowRight => 0x4f,
+        ArrowLeft => 0x50,
+        ArrowDown => 0x51,
+        ArrowUp => 0x52,
+        NumLock => 0x53,
+        NumpadDivide => 0x54,
+        NumpadMultiply => 0x55,
+        NumpadSubtract => 0x56,
+        NumpadAdd => 0x57,
+        NumpadEnter => 0x58,
+        NumpadDecimal => 0x63,
+        IntlBackslash => 0x64,
+        ContextMenu => 0x65,
+        ControlLeft => 0xe0,
+        ShiftLeft => 0xe1,
+        AltLeft => 0xe2,
+        SuperLeft => 0xe3,
+        ControlRight => 0xe4,
+        ShiftRight => 0xe5,
+        AltRight => 0xe6,
+        SuperRight => 0xe7,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keycodes(messages: &[ControlMessage]) -> Vec<(KeyAction, u32, u32, u32)> {
+        messages
+            .iter()
+            .map(|message| match message {
+                ControlMessage::InjectKeycode {
+                    action,
+                    keycode,
+                    repeat,
+                    metastate,
+                } => (*action, *keycode, *repeat, *metastate),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sdk_mode_tracks_modifiers_and_repeat() {
+        let mut keys = KeyForwarder::new(KeyboardMode::Sdk);
+        keys.key(KeyCode::ShiftLeft, true, false);
+        assert_eq!(
+            keycodes(&keys.key(KeyCode::KeyA, true, false)),
+            [(KeyAction::Down, 29, 0, meta::SHIFT_LEFT)]
+        );
+        assert_eq!(
+            keycodes(&keys.key(KeyCode::KeyA, true, true)),
+            [(KeyAction::Down, 29, 1, meta::SHIFT_LEFT)]
+        );
+        // Hotkeys and keys never sent down stay local
+        assert!(keys.key(KeyCode::F5, true, false).is_empty());
+        assert!(keys.key(KeyCode::KeyW, false, false).is_empty());
+
+        assert_eq!(
+            keycodes(&keys.release_all()),
+            [(KeyAction::Up, 29, 0, 0), (KeyAction::Up, 59, 0, 0)]
+        );
+        assert!(keys.release_all().is_empty());
+    }
+
+    #[test]
+    fn test_uhid_reports() {
+        let mut keys = KeyForwarder::new(KeyboardMode::Uhid);
+        let data = |messages: Vec<ControlMessage>| match messages.as_slice() {
+            [ControlMessage::UhidInput { id, data }] => {
+                assert_eq!(*id, UHID_KEYBOARD_ID);
+                data.clone()
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            data(keys.key(KeyCode::ControlRight, true, false)),
+            [0x10, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            data(keys.key(KeyCode::KeyC, true, false)),
+            [0x10, 0, 0x06, 0, 0, 0, 0, 0]
+        );
+        // Android repeats on its own
+        assert!(keys.key(KeyCode::KeyC, true, true).is_empty());
+        for code in [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+        ] {
+            keys.key(code, true, false);
+        }
+        assert_eq!(
+            data(keys.key(KeyCode::Digit6, true, false)),
+            [0x10, 0, 1, 1, 1, 1, 1, 1]
+        );
+        assert_eq!(data(keys.release_all()), [0; 8]);
+    }
+}
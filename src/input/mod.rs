@@ -0,0 +1,31 @@
+/// Input forwarding from the local window to the device
+pub mod coalesce;
+pub mod event_log;
+pub mod game_map;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+pub mod gesture;
+pub mod haptics;
+pub mod idle;
+pub mod keyboard;
+pub mod mouse;
+pub mod touch;
+
+pub use coalesce::{CoalesceStats, MoveCoalescer};
+pub use event_log::{EventLog, EventReplay, LoggedEvent};
+pub use game_map::{GameMapper, GameProfiles};
+pub use gesture::{GestureKind, MouseGestures};
+pub use haptics::Haptics;
+pub use idle::IdleTimer;
+pub use keyboard::KeyForwarder;
+pub use mouse::HidMouse;
+pub use touch::TouchForwarder;
+
+/// Pointer id scrcpy reserves for the mouse (distinct from finger ids)
+pub const POINTER_ID_MOUSE: u64 = u64::MAX;
+
+/// Pointer id of the mouse acting as a finger in a two-finger gesture
+pub const POINTER_ID_GENERIC_FINGER: u64 = u64::MAX - 1;
+
+/// Pointer id of the finger a gesture adds opposite the mouse
+pub const POINTER_ID_VIRTUAL_FINGER: u64 = u64::MAX - 2;
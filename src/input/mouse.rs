@@ -0,0 +1,162 @@
+use crate::network::ControlMessage;
+use winit::event::MouseButton;
+
+/// UHID device id of the mouse
+pub const UHID_MOUSE_ID: u16 = 2;
+
+/// Relative mouse: five buttons, then X, Y and wheel deltas of -127..127
+const REPORT_DESC: [u8; 52] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xa1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Buttons)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x05, //     Usage Maximum (5)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x05, //     Report Count (5)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data, Variable, Absolute): buttons
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x03, //     Report Size (3)
+    0x81, 0x01, //     Input (Constant): padding
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x09, 0x38, //     Usage (Wheel)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7f, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x03, //     Report Count (3)
+    0x81, 0x06, //     Input (Data, Variable, Relative): X, Y, wheel
+    0xc0, //   End Collection
+    0xc0, // End Collection
+];
+
+/// Largest delta one report carries
+const MAX_DELTA: i32 = 127;
+
+/// Message that makes the device create the HID mouse
+pub fn uhid_create() -> ControlMessage {
+    ControlMessage::UhidCreate {
+        id: UHID_MOUSE_ID,
+        name: "scrcpy-custom mouse".to_string(),
+        report_desc: REPORT_DESC.to_vec(),
+    }
+}
+
+/// Relative HID mouse, for controlling the device without a video stream
+///
+/// The device draws its own pointer and moves it by the deltas sent, so
+/// this works with raw (captured) mouse motion rather than window positions.
+#[derive(Default)]
+pub struct HidMouse {
+    buttons: u8,
+    /// Fractions of a pixel not sent yet
+    rest: (f64, f64),
+}
+
+impl HidMouse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report for a button going down or up; None for buttons HID has no bit for
+    pub fn button(&mut self, button: MouseButton, pressed: bool) -> Option<ControlMessage> {
+        let bit = match button {
+            MouseButton::Left => 0x01,
+            MouseButton::Right => 0x02,
+            MouseButton::Middle => 0x04,
+            MouseButton::Back => 0x08,
+            MouseButton::Forward => 0x10,
+            MouseButton::Other(_) => return None,
+        };
+        if pressed {
+            self.buttons |= bit;
+        } else {
+            self.buttons &= !bit;
+        }
+        Some(self.report(0, 0, 0))
+    }
+
+    /// Reports moving the pointer by `(dx, dy)`, split to fit the report range
+    pub fn motion(&mut self, dx: f64, dy: f64) -> Vec<ControlMessage> {
+        let x = self.rest.0 + dx;
+        let y = self.rest.1 + dy;
+        let (mut dx, mut dy) = (x.trunc() as i32, y.trunc() as i32);
+        self.rest = (x.fract(), y.fract());
+
+        let mut reports = Vec::new();
+        while dx != 0 || dy != 0 {
+            let step_x = dx.clamp(-MAX_DELTA, MAX_DELTA);
+            let step_y = dy.clamp(-MAX_DELTA, MAX_DELTA);
+            reports.push(self.report(step_x, step_y, 0));
+            dx -= step_x;
+            dy -= step_y;
+        }
+        reports
+    }
+
+    /// Report scrolling by `steps` notches, positive scrolling up
+    pub fn wheel(&mut self, steps: i32) -> Option<ControlMessage> {
+        (steps != 0).then(|| self.report(0, 0, steps.clamp(-MAX_DELTA, MAX_DELTA)))
+    }
+
+    /// Let go of every button, e.g. when the window loses focus
+    pub fn release_all(&mut self) -> Option<ControlMessage> {
+        if self.buttons == 0 {
+            return None;
+        }
+        self.buttons = 0;
+        Some(self.report(0, 0, 0))
+    }
+
+    fn report(&self, dx: i32, dy: i32, wheel: i32) -> ControlMessage {
+        ControlMessage::UhidInput {
+            id: UHID_MOUSE_ID,
+            data: vec![
+                self.buttons,
+                dx as i8 as u8,
+                dy as i8 as u8,
+                wheel as i8 as u8,
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(message: &ControlMessage) -> &[u8] {
+        match message {
+            ControlMessage::UhidInput { id, data } => {
+                assert_eq!(*id, UHID_MOUSE_ID);
+                data
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_motion_is_split_and_keeps_buttons() {
+        let mut mouse = HidMouse::new();
+        assert_eq!(
+            data(&mouse.button(MouseButton::Left, true).unwrap()),
+            [1, 0, 0, 0]
+        );
+
+        let reports = mouse.motion(200.0, -3.5);
+        let reports: Vec<&[u8]> = reports.iter().map(data).collect();
+        assert_eq!(reports, [[1, 127, 0xfd, 0], [1, 73, 0, 0]]);
+        // The half pixel left over is sent with the next move
+        assert_eq!(data(&mouse.motion(0.0, -0.5)[0]), [1, 0, 0xff, 0]);
+        assert!(mouse.motion(0.25, 0.0).is_empty());
+
+        assert_eq!(data(&mouse.wheel(-1).unwrap()), [1, 0, 0, 0xff]);
+        assert_eq!(data(&mouse.release_all().unwrap()), [0, 0, 0, 0]);
+        assert!(mouse.release_all().is_none());
+    }
+}
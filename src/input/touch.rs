@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use winit::event::{Force, TouchPhase};
+
+use crate::network::{ControlMessage, TouchAction};
+
+/// Forwards touch screen and pen contacts as device touches
+///
+/// Only contacts that start on the video are forwarded; one that slides off
+/// it stays at its last position on the video until lifted. Pen pressure
+/// comes through winit's force. Tilt has no field in the scrcpy touch
+/// message, so pens arrive on the device as pressure-sensitive fingers.
+#[derive(Default)]
+pub struct TouchForwarder {
+    /// Last video position of each contact in progress, by winit touch id
+    active: HashMap<u64, (u32, u32)>,
+}
+
+impl TouchForwarder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Touch event for a contact change at `position` (video pixels, None when
+    /// off the video) in a `width` x `height` frame
+    pub fn on_touch(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        position: Option<(u32, u32)>,
+        force: Option<Force>,
+        (width, height): (u32, u32),
+    ) -> Option<ControlMessage> {
+        let (action, (x, y)) = match phase {
+            TouchPhase::Started => {
+                let position = position?;
+                self.active.insert(id, position);
+                (TouchAction::Down, position)
+            }
+            TouchPhase::Moved => {
+                let last = self.active.get_mut(&id)?;
+                if let Some(position) = position {
+                    *last = position;
+                }
+                (TouchAction::Move, *last)
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                (TouchAction::Up, self.active.remove(&id)?)
+            }
+        };
+        let pressure = match action {
+            TouchAction::Up => 0.0,
+            // Devices without pressure sensing report no force
+            _ => force.map_or(1.0, |force| force.normalized() as f32),
+        };
+        Some(ControlMessage::InjectTouch {
+            action,
+            pointer_id: id,
+            x,
+            y,
+            width,
+            height,
+            pressure,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contacts_are_tracked_from_the_video() {
+        let mut touches = TouchForwarder::new();
+        let size = (1080, 2400);
+        let pen = Some(Force::Normalized(0.25));
+
+        // Starting on the letterbox bars is not forwarded at all
+        assert!(touches
+            .on_touch(1, TouchPhase::Started, None, None, size)
+            .is_none());
+        assert!(touches
+            .on_touch(1, TouchPhase::Ended, None, None, size)
+            .is_none());
+
+        let down = touches.on_touch(2, TouchPhase::Started, Some((10, 20)), pen, size);
+        assert!(matches!(
+            down,
+            Some(ControlMessage::InjectTouch {
+                action: TouchAction::Down,
+                pointer_id: 2,
+                pressure,
+                ..
+            }) if pressure == 0.25
+        ));
+        // Sliding off the video holds the last position
+        let moved = touches.on_touch(2, TouchPhase::Moved, None, pen, size);
+        assert!(matches!(
+            moved,
+            Some(ControlMessage::InjectTouch {
+                action: TouchAction::Move,
+                x: 10,
+                y: 20,
+                ..
+            })
+        ));
+        let up = touches.on_touch(2, TouchPhase::Ended, None, pen, size);
+        assert!(matches!(
+            up,
+            Some(ControlMessage::InjectTouch {
+                action: TouchAction::Up,
+                pressure,
+                ..
+            }) if pressure == 0.0
+        ));
+    }
+}
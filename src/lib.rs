@@ -1,5 +1,7 @@
+pub mod api;
 pub mod assets;
 pub mod audio;
+pub mod client;
 /// Ultra-low latency screen mirroring application library
 ///
 /// This library provides the core functionality for high-performance screen
@@ -7,13 +9,26 @@ pub mod audio;
 /// wireless (WiFi/QUIC) connections.
 pub mod config;
 
+pub mod events;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod hotplug;
+pub mod input;
 pub mod network;
+#[cfg(feature = "otg")]
+pub mod otg;
 pub mod platform;
+pub mod plugin;
+pub mod power;
+pub mod restream;
 pub mod server;
+pub mod stats;
+pub mod stats_log;
 pub mod sync;
 pub mod ui;
 pub mod video;
 
+pub use client::Client;
 pub use config::Config;
 pub use network::{Connection, ConnectionMode};
 
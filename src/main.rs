@@ -1,27 +1,32 @@
 #![allow(deprecated)] // Suppress winit 0.30 deprecation warnings until full refactor
+mod app;
+
 use anyhow::Result;
-use clap::Parser;
+use app::{
+    audio::run_audio_only,
+    bench::bench_transports,
+    capture::{capture_screenshot, run_record},
+    device_model,
+    otg::run_otg,
+    restream::run_relay,
+};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use scrcpy_custom::{
-    audio::{decoder::HardwareAudioDecoder, player::AudioPlayer},
-    config::{Config, ConnectionMode},
-    network::*,
-    platform,
-    video::{
-        decoder::{DecodedFrame, HardwareVideoDecoder, PixelFormat},
-        renderer::VideoRenderer,
+    config::{
+        AudioSource, Config, ConnectionMode, FrameDropPolicy, KeyboardMode, Orientation,
+        PresentMode, ScalingMode, UpscaleFilter, VideoCodec,
     },
+    network, platform,
+    server::ServerManager,
+    ui::WindowGeometry,
+    video::decoder::HardwareVideoDecoder,
 };
-use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::Window,
-};
-
-use std::net::{IpAddr, SocketAddr};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::thread;
-use tracing::{error, info, warn};
+use std::time::Duration;
+use tracing::{info, warn};
 
 use mimalloc::MiMalloc;
 
@@ -34,36 +39,257 @@ static GLOBAL: MiMalloc = MiMalloc;
 #[command(about = "High-performance screen mirroring from Android to PC", long_about = None)]
 struct Args {
     /// Connection mode: tcp or quic
-    #[arg(short, long, value_enum, default_value = "tcp")]
+    #[arg(global = true, short, long, value_enum, default_value = "tcp")]
     mode: ConnectionModeArg,
 
-    /// Server IP address
-    #[arg(long, default_value = "127.0.0.1")]
-    host: IpAddr,
+    /// Server host name or IP address (IPv6 link-local as fe80::1%wlan0)
+    #[arg(global = true, long, default_value = "127.0.0.1")]
+    host: String,
 
     /// Server port
-    #[arg(short, long, default_value_t = 5555)]
+    #[arg(global = true, short, long, default_value_t = 5555)]
     port: u16,
 
     /// Video bitrate in Mbps
-    #[arg(short, long, default_value_t = 8)]
+    #[arg(global = true, short, long, default_value_t = 8)]
     bitrate: u32,
 
     /// Enable hardware acceleration
-    #[arg(long, default_value_t = true)]
+    #[arg(global = true, long, default_value_t = true)]
     hw_accel: bool,
 
     /// Hardware decoder (auto, nvdec, qsv, vaapi)
-    #[arg(long, default_value = "auto")]
+    #[arg(global = true, long, default_value = "auto")]
     hw_decoder: String,
 
     /// Disable audio
-    #[arg(long, default_value_t = false)]
+    #[arg(global = true, long, default_value_t = false)]
     no_audio: bool,
 
+    /// Play only the device audio, without video or a window
+    #[arg(global = true, long, default_value_t = false)]
+    no_video: bool,
+
+    /// Device audio source: output, playback, mic, mic-voice-communication, ...
+    #[arg(global = true, long, value_name = "SOURCE")]
+    audio_source: Option<AudioSource>,
+
+    /// Keep audio playing on the device while mirroring (Android 13+)
+    #[arg(global = true, long, default_value_t = false)]
+    audio_dup: bool,
+
+    /// Capture the display at 0, 90, 180 or 270 degrees regardless of the sensor
+    #[arg(global = true, long, value_name = "DEGREES")]
+    lock_video_orientation: Option<Orientation>,
+
+    /// Present mode: auto, mailbox, immediate or fifo (falls back if unsupported)
+    #[arg(global = true, long, value_name = "MODE")]
+    present_mode: Option<PresentMode>,
+
+    /// Frames drawn when behind: latest-only, render-all or pts-paced (smooth)
+    #[arg(global = true, long, value_name = "POLICY")]
+    frame_drop: Option<FrameDropPolicy>,
+
+    /// Video scaling: fit, fill (stretch) or integer (whole multiples, sharp pixels)
+    #[arg(global = true, long, value_name = "MODE")]
+    scaling: Option<ScalingMode>,
+
+    /// Upscale filter: bilinear (low power), lanczos or sharpen
+    #[arg(global = true, long, value_name = "FILTER")]
+    upscale_filter: Option<UpscaleFilter>,
+
+    /// Physical keyboard: sdk (key events), uhid (virtual HID keyboard) or disabled
+    #[arg(global = true, long, value_name = "MODE")]
+    keyboard_mode: Option<KeyboardMode>,
+
+    /// Window left edge in pixels (default: where it was last closed for the device)
+    #[arg(global = true, long, value_name = "X", allow_negative_numbers = true)]
+    window_x: Option<i32>,
+
+    /// Window top edge in pixels (default: where it was last closed for the device)
+    #[arg(global = true, long, value_name = "Y", allow_negative_numbers = true)]
+    window_y: Option<i32>,
+
+    /// Window width in pixels (default: last size for the device, else 1024)
+    #[arg(global = true, long, value_name = "WIDTH")]
+    window_width: Option<u32>,
+
+    /// Window height in pixels (default: last size for the device, else 576)
+    #[arg(global = true, long, value_name = "HEIGHT")]
+    window_height: Option<u32>,
+
     /// Max video size (0 = native)
-    #[arg(long, default_value_t = 0)]
+    #[arg(global = true, long, default_value_t = 0)]
     max_size: u16,
+
+    /// TOML config file (flags given on the command line take precedence)
+    #[arg(global = true, long)]
+    config: Option<PathBuf>,
+
+    /// Log injected input events to a JSONL file
+    #[arg(global = true, long, value_name = "PATH")]
+    record_input: Option<PathBuf>,
+
+    /// Replay the input events of a JSONL log
+    #[arg(global = true, long, value_name = "PATH")]
+    replay_input: Option<PathBuf>,
+
+    /// Game profile mapping keys to touches (see input.game_profiles)
+    #[arg(global = true, long, value_name = "NAME")]
+    game_profile: Option<String>,
+
+    /// Show device accessibility text as captions
+    #[arg(global = true, long, default_value_t = false)]
+    captions: bool,
+
+    /// Save captions to an SRT subtitle file (implies --captions)
+    #[arg(global = true, long, value_name = "PATH")]
+    captions_srt: Option<PathBuf>,
+
+    /// Keep the device awake while mirroring
+    #[arg(global = true, long, default_value_t = false)]
+    stay_awake: bool,
+
+    /// Show taps on the device screen while mirroring
+    #[arg(global = true, long, default_value_t = false)]
+    show_touches: bool,
+
+    /// End the session after this many minutes without input or screen changes
+    #[arg(global = true, long, value_name = "MINUTES")]
+    idle_timeout: Option<u32>,
+
+    /// Turn the device display off when the idle timeout ends the session
+    #[arg(global = true, long, default_value_t = false)]
+    sleep_on_idle: bool,
+
+    /// Launch this app (package name) once mirroring begins
+    #[arg(global = true, long, value_name = "PACKAGE")]
+    start_app: Option<String>,
+
+    /// Force-stop the --start-app app before launching it
+    #[arg(global = true, long, default_value_t = false)]
+    force_stop_app: bool,
+
+    /// Show the on-screen keyboard (for touch screens)
+    #[arg(global = true, long, default_value_t = false)]
+    keyboard: bool,
+
+    /// Color calibration profiles (TOML of matrix + gamma per device serial)
+    #[arg(global = true, long, value_name = "PATH")]
+    color_profiles: Option<PathBuf>,
+
+    /// Push the server jar even if the device already has the same one
+    #[arg(global = true, long, default_value_t = false)]
+    force_push: bool,
+
+    /// Wait for the device and resume mirroring when it is plugged back in
+    #[arg(global = true, long, default_value_t = false)]
+    hotplug: bool,
+
+    /// adb serial of the device to mirror when several are connected
+    #[arg(global = true, short, long)]
+    serial: Option<String>,
+
+    /// Mirror every connected device side by side; click a tile to control it
+    #[arg(global = true, long, default_value_t = false)]
+    grid: bool,
+
+    /// Also publish the screen as an NDI source with this name (for OBS)
+    #[arg(global = true, long, value_name = "NAME")]
+    ndi: Option<String>,
+
+    /// Serve a WebRTC viewer page for browsers on this address (H.264 only)
+    #[arg(global = true, long, value_name = "ADDR")]
+    webrtc: Option<SocketAddr>,
+
+    /// Send the stream as MPEG-TS over UDP to this address (unicast or multicast)
+    #[arg(global = true, long, value_name = "ADDR")]
+    mpegts_udp: Option<SocketAddr>,
+
+    /// Serve the stream as MPEG-TS over HTTP on this address
+    #[arg(global = true, long, value_name = "ADDR")]
+    mpegts_http: Option<SocketAddr>,
+
+    /// Let other instances watch this session on this address (view-only)
+    #[arg(global = true, long, value_name = "ADDR")]
+    share: Option<SocketAddr>,
+
+    /// Log where frames spent their time when the session ends (Shift+F9 any time)
+    #[arg(global = true, long, default_value_t = false)]
+    latency_report: bool,
+
+    /// Append fps, bitrate, RTT, loss, drift and drops every second to a CSV (or .jsonl) file
+    #[arg(global = true, long, value_name = "PATH")]
+    stats_log: Option<PathBuf>,
+
+    /// Record every received packet with its arrival time (play back with `replay`)
+    #[arg(global = true, long, value_name = "PATH")]
+    dump_session: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// What to do; the flags above apply to every subcommand
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Mirror the device in a window (the default without a subcommand)
+    Mirror,
+    /// List the devices adb can reach, with their models
+    ListDevices,
+    /// Show which FFmpeg hardware decoders open on this machine
+    ListDecoders,
+    /// Record the device screen to an MPEG-TS file, without a window
+    Record {
+        /// File to write (.ts)
+        output: PathBuf,
+        /// Stop after this many seconds (default: Ctrl+C)
+        #[arg(long, value_name = "SECONDS")]
+        duration: Option<u64>,
+    },
+    /// Pair with a device for wireless debugging (Android 11+)
+    Pair {
+        /// IP:port shown under "Pair device with pairing code"
+        address: String,
+        /// Six-digit pairing code
+        code: String,
+    },
+    /// Control the device over USB as a keyboard and mouse, without adb or video
+    ///
+    /// Works with USB debugging off, e.g. on a phone with a broken screen.
+    /// Needs a build with the `otg` feature.
+    Otg,
+    /// Stream briefly over TCP and then QUIC and compare frame delay, fps and loss
+    ///
+    /// QUIC is skipped when adb is up, since its tunnel only carries TCP.
+    BenchTransport {
+        /// Seconds to stream over each transport
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+    /// Save the device screen as a PNG and exit
+    Screenshot {
+        /// Directory for the file (default: display.screenshot_dir)
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+    /// Forward the device stream to viewers on other machines, without a window
+    ///
+    /// Viewers run a normal TCP session against this machine
+    /// (`--host <relay> --port <port>`); their input reaches the device.
+    Relay {
+        /// Address viewers connect to
+        #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:5555")]
+        listen: SocketAddr,
+    },
+    /// Play a session recorded with --dump-session in the window, without a device
+    ///
+    /// Packets arrive at the times they were recorded, so stutter in the
+    /// recording shows up the same way again.
+    Replay {
+        /// Capture written by --dump-session
+        file: PathBuf,
+    },
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -93,7 +319,8 @@ fn main() -> Result<()> {
 
     // Interactive Mode Selection if no arguments provided
     // This allows the user to choose between Wired (USB) and Wireless without typing commands
-    let mut args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
 
     // --- DEMO SNIPPET START ---
     // Simulating connection phase as requested
@@ -133,13 +360,13 @@ fn main() -> Result<()> {
                 "2" => {
                     args.mode = ConnectionModeArg::Tcp; // Currently both use TCP, but this might imply IP input later
                                                         // Ideally for wireless we might want to ask for IP
-                    println!("Enter Device IP (e.g. 192.168.1.100): ");
+                    println!("Enter Device IP or host name (e.g. 192.168.1.100): ");
                     let mut ip_input = String::new();
                     if std::io::stdin().read_line(&mut ip_input).is_ok() {
-                        if let Ok(ip) = ip_input.trim().parse::<IpAddr>() {
-                            args.host = ip;
+                        if !ip_input.trim().is_empty() {
+                            args.host = ip_input.trim().to_string();
                         } else {
-                            println!("Invalid IP. Using default.");
+                            println!("No address given. Using default.");
                         }
                     }
                 }
@@ -151,340 +378,246 @@ fn main() -> Result<()> {
         }
     }
 
-    info!("Starting scrcpy-custom");
-    info!(
-        "Mode: {:?}, Host: {}, Port: {}",
-        args.mode, args.host, args.port
-    );
-
-    let args_clone = args.clone();
-
-    // Setup Winit Event Loop
-    let event_loop = EventLoop::new().unwrap();
-
-    // Create window using winit 0.30 API
-    let window_attributes = Window::default_attributes()
-        .with_title("scrcpy-custom")
-        .with_inner_size(winit::dpi::LogicalSize::new(1024.0, 576.0));
-
-    let window = event_loop.create_window(window_attributes).unwrap();
-
-    // Initialize Video Renderer
-    let mut renderer = VideoRenderer::new(&window)?;
-
-    // Channel to send decoded frames from network thread to UI thread
-    let (frame_tx, frame_rx) = mpsc::channel::<DecodedFrame>();
-
-    // Shutdown signal
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
-    let running = Arc::new(AtomicBool::new(true));
-    let running_clone = running.clone();
+    // Build configuration: the config file first, then flags on top. Without
+    // a file every flag applies (defaults included), with one only flags
+    // actually given on the command line override it.
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let from_cli = |id: &str| {
+        args.config.is_none() || matches.value_source(id) == Some(ValueSource::CommandLine)
+    };
+    if from_cli("mode") {
+        config.connection.mode = args.mode.into();
+    }
+    if from_cli("host") {
+        config.connection.host = args.host.clone();
+    }
+    if from_cli("port") {
+        config.connection.port = args.port;
+    }
+    if from_cli("bitrate") {
+        config.video.bitrate = args.bitrate;
+    }
+    if from_cli("hw_accel") {
+        config.video.hw_accel = args.hw_accel;
+    }
+    if from_cli("hw_decoder") {
+        config.video.hw_decoder = args.hw_decoder.clone();
+    }
+    if from_cli("max_size") {
+        config.video.max_size = args.max_size;
+    }
+    if args.lock_video_orientation.is_some() {
+        config.video.lock_orientation = args.lock_video_orientation;
+    }
+    if let Some(mode) = args.present_mode {
+        config.video.present_mode = mode;
+    }
+    if let Some(policy) = args.frame_drop {
+        config.performance.frame_drop = policy;
+    }
+    if let Some(mode) = args.scaling {
+        config.display.scaling = mode;
+    }
+    if let Some(mode) = args.keyboard_mode {
+        config.input.keyboard = mode;
+    }
+    if let Some(filter) = args.upscale_filter {
+        config.display.upscale_filter = filter;
+    }
+    if args.no_audio {
+        config.audio.enabled = false;
+    }
+    if args.no_video {
+        config.video.enabled = false;
+    }
+    if let Some(source) = args.audio_source {
+        config.audio.source = source;
+    }
+    if args.audio_dup {
+        config.audio.duplicate = true;
+    }
+    // Only playback capture leaves the device's own output running
+    if config.audio.duplicate && config.audio.source == AudioSource::Output {
+        info!("Audio duplication uses the playback audio source");
+        config.audio.source = AudioSource::Playback;
+    }
+    if args.record_input.is_some() {
+        config.input.event_log = args.record_input.clone();
+    }
+    if args.replay_input.is_some() {
+        config.input.replay = args.replay_input.clone();
+    }
+    if args.game_profile.is_some() {
+        config.input.game_profile = args.game_profile.clone();
+    }
+    if args.keyboard {
+        config.display.keyboard = true;
+    }
+    if args.stay_awake {
+        config.server.stay_awake = true;
+    }
+    if args.show_touches {
+        config.server.show_touches = true;
+    }
+    if args.idle_timeout.is_some() {
+        config.server.idle_timeout = args.idle_timeout;
+    }
+    if args.sleep_on_idle {
+        config.server.sleep_on_idle = true;
+    }
+    if args.start_app.is_some() {
+        config.server.start_app = args.start_app.clone();
+    }
+    if args.force_stop_app {
+        config.server.force_stop_app = true;
+    }
+    if args.captions || args.captions_srt.is_some() {
+        config.display.captions = true;
+    }
+    if args.captions_srt.is_some() {
+        config.display.captions_srt = args.captions_srt.clone();
+    }
+    if args.color_profiles.is_some() {
+        config.display.color_profiles = args.color_profiles.clone();
+    }
+    if args.force_push {
+        config.server.force_push = true;
+    }
+    if args.hotplug {
+        config.connection.hotplug = true;
+    }
+    if args.serial.is_some() {
+        config.connection.serial = args.serial.clone();
+    }
+    if args.grid {
+        config.display.grid = true;
+    }
+    if args.ndi.is_some() {
+        config.output.ndi = args.ndi.clone();
+    }
+    if args.webrtc.is_some() {
+        config.restream.webrtc = args.webrtc;
+    }
+    if args.mpegts_udp.is_some() {
+        config.restream.mpegts_udp = args.mpegts_udp;
+    }
+    if args.mpegts_http.is_some() {
+        config.restream.mpegts_http = args.mpegts_http;
+    }
+    if args.share.is_some() {
+        config.restream.share = args.share;
+    }
+    if args.latency_report {
+        config.display.latency_report = true;
+    }
+    if args.stats_log.is_some() {
+        config.display.stats_log = args.stats_log.clone();
+    }
+    if args.dump_session.is_some() {
+        config.connection.dump_session = args.dump_session.clone();
+    }
+    config.performance.adaptive_bitrate = false; // scrcpy-server has no bitrate control
 
-    // Spawn Network/Decoding Thread
-    thread::spawn(move || {
-        // Create a new Tokio runtime for async network operations
+    if let Some(Command::BenchTransport { seconds }) = args.command {
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
-            .build()
-            .unwrap();
-
-        rt.block_on(async {
-            // Build configuration
-            let mut config = Config::default();
-            config.connection.mode = args_clone.mode.into();
-            config.connection.host = args_clone.host;
-            config.connection.port = args_clone.port;
-            config.video.bitrate = args_clone.bitrate;
-            config.video.hw_accel = args_clone.hw_accel;
-            config.video.hw_decoder = args_clone.hw_decoder.clone();
-            config.video.max_size = args_clone.max_size;
-            config.performance.adaptive_bitrate = false; // Forced false as no control socket
-
-            if args_clone.no_audio {
-                config.audio.enabled = false;
-            } else {
-                config.audio.enabled = true;
-                // Smart Codec Negotiation
-                // Try to initialize Opus decoder. If it fails, fallback to AAC.
-                // We do this check BEFORE connecting/starting server so we can tell the server what to send.
-                if HardwareAudioDecoder::new("opus", 48000, 2).is_ok() {
-                    info!("Client supports Opus audio. Requesting Opus from server.");
-                    config.audio.codec = scrcpy_custom::config::AudioCodec::Opus;
-                } else if HardwareAudioDecoder::new("aac", 48000, 2).is_ok() {
-                    warn!("Client does not support Opus. Requesting AAC from server.");
-                    config.audio.codec = scrcpy_custom::config::AudioCodec::Aac;
-                } else {
-                    warn!("No supported audio decoder found (Opus/AAC). Disabling audio.");
-                    config.audio.enabled = false;
-                }
-            }
-
-            if let Err(e) = run_app(config, frame_tx, running_clone).await {
-                error!("Application error: {}", e);
-            }
-        });
-    });
-
-    // Run Event Loop
-    let _ = event_loop.run(move |event, target| {
-        target.set_control_flow(ControlFlow::Poll); // Check for events continuously
-
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                running.store(false, Ordering::SeqCst);
-                target.exit();
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(size),
-                ..
-            } => {
-                let _ = renderer.resize(size.width, size.height);
-            }
-            Event::AboutToWait => {
-                // Check for new frames
-                let mut last_frame = None;
-                while let Ok(frame) = frame_rx.try_recv() {
-                    last_frame = Some(frame);
-                }
-
-                if let Some(frame) = last_frame {
-                    // Auto-resize window if video size changes (orientation change or first frame)
-                    // We use the renderer's current tracking to detect change
-                    let current_video_size = renderer.current_video_size();
-                    if current_video_size != Some((frame.width, frame.height)) {
-                        let inner_size = renderer.window().inner_size();
-                        if inner_size.width > 0 && inner_size.height > 0 {
-                            let w = frame.width as f64;
-                            let h = frame.height as f64;
-                            let aspect = w / h;
-
-                            // Simple heuristic:
-                            // 1. If rotation (Portrait <-> Landscape), flip window dimensions
-                            // 2. Otherwise, adjust width to match new aspect ratio, keeping height
-                            let new_size = if let Some((old_w, old_h)) = current_video_size {
-                                let old_aspect = old_w as f64 / old_h as f64;
-                                let is_landscape = aspect > 1.0;
-                                let was_landscape = old_aspect > 1.0;
-
-                                if is_landscape != was_landscape {
-                                    // Rotation: Flip window
-                                    winit::dpi::PhysicalSize::new(
-                                        inner_size.height,
-                                        inner_size.width,
-                                    )
-                                } else {
-                                    // Resolution change: Adjust width to match aspect
-                                    let new_width = (inner_size.height as f64 * aspect) as u32;
-                                    winit::dpi::PhysicalSize::new(new_width, inner_size.height)
-                                }
-                            } else {
-                                // First frame: Set reasonable default height (e.g. 800 or current) and adjust width
-                                // But don't make it larger than screen.
-                                // Let's simplify: Scale to e.g. 1/3 of video if it's huge, or just match current height
-                                let target_height = if inner_size.height < 100 {
-                                    800.0
-                                } else {
-                                    inner_size.height as f64
-                                };
-                                let new_width = (target_height * aspect) as u32;
-                                winit::dpi::PhysicalSize::new(new_width, target_height as u32)
-                            };
-
-                            let _ = renderer.window().request_inner_size(new_size);
-                        }
-                    }
-
-                    if let Err(e) = renderer.render(&frame) {
-                        error!("Render error: {}", e);
-                    }
-                }
-            }
-            Event::WindowEvent {
-                event: WindowEvent::RedrawRequested,
-                ..
-            } => {
-                // Normally we'd render here, but we render immediately on AboutToWait for lowest latency
-            }
-            _ => {}
+            .build()?;
+        let results = rt.block_on(bench_transports(config, Duration::from_secs(seconds)));
+        println!("{}", network::bench::format_table(&results));
+        return Ok(());
+    }
+    if let Some(Command::Screenshot { output }) = &args.command {
+        let dir = output
+            .clone()
+            .unwrap_or_else(|| config.display.screenshot_dir.clone());
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let path = rt.block_on(capture_screenshot(config, &dir))?;
+        println!("{}", path.display());
+        return Ok(());
+    }
+    if let Some(Command::Relay { listen }) = args.command {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        return rt.block_on(run_relay(config, listen));
+    }
+    if let Some(Command::Record { output, duration }) = &args.command {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let duration = duration.map(Duration::from_secs);
+        return rt.block_on(run_record(config, output, duration));
+    }
+    if let Some(Command::ListDevices) = args.command {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        for serial in rt.block_on(ServerManager::devices())? {
+            let model = device_model(Some(&serial)).unwrap_or_default();
+            println!("{}\t{}", serial, model);
         }
-    });
-
-    Ok(())
-}
-
-// Network logic moved here
-async fn run_app(
-    mut config: Config,
-    frame_tx: mpsc::Sender<DecodedFrame>,
-    running: Arc<AtomicBool>,
-) -> Result<()> {
-    // Attempt to auto-start server via ADB
-    info!("Checking matching scrcpy-server via ADB...");
-    let mut adb_success = false;
-
-    match scrcpy_custom::server::ServerManager::new().await {
-        Ok(mut manager) => {
-            let serial = if !config.connection.host.is_loopback() {
-                Some(config.connection.host.to_string())
-            } else {
-                None
-            };
-
-            if let Err(e) = manager.start_server(&config, serial.as_deref()).await {
-                warn!("ADB Server setup failed: {}.", e);
-            } else {
-                info!("Server setup successful via ADB!");
-                adb_success = true;
+        return Ok(());
+    }
+    if let Some(Command::ListDecoders) = args.command {
+        // One row per codec: which hardware APIs open, then software
+        for codec in [VideoCodec::H264, VideoCodec::H265, VideoCodec::Av1] {
+            let name = format!("{:?}", codec);
+            let mut row = format!("{:<5}", name);
+            for (api, works) in HardwareVideoDecoder::probe(codec)? {
+                row.push_str(&format!(" {}:{}", api, if works { "yes" } else { "no" }));
             }
+            let software = HardwareVideoDecoder::supports(codec);
+            row.push_str(&format!(
+                " software:{}",
+                if software { "yes" } else { "no" }
+            ));
+            println!("{}", row);
         }
-        Err(e) => {
-            warn!("Could not connect to ADB: {}. Proceeding without ADB.", e);
-        }
+        return Ok(());
     }
-
-    // If ADB setup was successful, we MUST connect to localhost because we used 'adb forward'
-    if adb_success {
-        info!("Redirecting connection to localhost:5555 (tunnel via ADB)");
-        config.connection.host = "127.0.0.1".parse().unwrap();
-        config.connection.port = 5555;
+    if let Some(Command::Pair { address, code }) = &args.command {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(ServerManager::pair(address, code))?;
+        println!("Paired with {}; connect with --serial {}", address, address);
+        return Ok(());
     }
-
-    let addr = SocketAddr::new(config.connection.host, config.connection.port);
-    info!("Connecting to {}...", addr);
-
-    let mode = config.connection.mode;
-    match mode {
-        ConnectionMode::Tcp => {
-            info!("Using TCP connection");
-            run_with_connection::<TcpConnection>(addr, config, frame_tx, running).await
-        }
-        ConnectionMode::Quic => {
-            info!("Using QUIC connection");
-            run_with_connection::<QuicConnection>(addr, config, frame_tx, running).await
-        }
+    if let Some(Command::Otg) = args.command {
+        return run_otg(&config);
     }
-}
-
-fn handle_connection_error(e: &anyhow::Error) {
-    let error_msg = e.to_string();
-    if error_msg.contains("10061") || error_msg.contains("Connection refused") {
-        error!("--------------------------------------------------");
-        error!("CONNECTION REFUSED");
-        error!("1. Ensure 'adb' is in your PATH.");
-        error!("2. Ensure 'scrcpy-server' is in the same folder.");
-        error!("3. Check if 'adb devices' lists your device.");
-        error!("--------------------------------------------------");
+    if let Some(Command::Replay { file }) = &args.command {
+        // The normal window, minus everything that needs a device
+        config.connection.replay = Some(file.clone());
+        config.connection.hotplug = false;
+        config.connection.auto_switch = false;
+        config.display.grid = false;
+        config.video.enabled = true;
     }
-}
 
-async fn run_with_connection<C: Connection>(
-    addr: SocketAddr,
-    config: Config,
-    frame_tx: mpsc::Sender<DecodedFrame>,
-    running: Arc<AtomicBool>,
-) -> Result<()> {
-    // Connect to server
-    let mut connection = C::connect(addr, config.audio.enabled).await.map_err(|e| {
-        handle_connection_error(&anyhow::anyhow!(e.to_string()));
-        anyhow::anyhow!("Failed to connect: {}", e)
-    })?;
-
-    info!("Connected successfully!");
-
-    // Initialize Decoders
-    let output_format = PixelFormat::RGBA; // WGPU prefers RGBA usually
-    let mut video_decoder = HardwareVideoDecoder::new(&config.video.hw_decoder, output_format)?;
-    info!("Initialized Video Decoder: {}", video_decoder.info());
-
-    // Initialize Audio (Opus/AAC default usually Opus for scrcpy audio)
-    // Note: Scrcpy server usually sends Opus for audio enabled.
-    // We'll initialize lazily or default to Opus 48kHz stereo
-    let mut audio_decoder = HardwareAudioDecoder::new("opus", 48000, 2).or_else(|_| {
-        warn!("Opus decoder not found, trying AAC");
-        HardwareAudioDecoder::new("aac", 48000, 2)
-    });
-
-    let mut audio_player = if audio_decoder.is_ok() {
-        match AudioPlayer::new(48000, 2, config.performance.jitter_buffer_ms) {
-            // 50ms jitter buffer
-            Ok(player) => Some(player),
-            Err(e) => {
-                warn!("Failed to initialize audio player: {}", e);
-                None
-            }
-        }
-    } else {
-        None
-    };
+    if !config.video.enabled {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        return rt.block_on(run_audio_only(config));
+    }
 
-    // Main receive loop
-    info!("Starting receive loop...");
-    loop {
-        if !running.load(Ordering::Relaxed) {
-            info!("Shutdown signal received");
-            break;
-        }
+    info!("Starting scrcpy-custom");
+    info!(
+        "Mode: {:?}, Host: {}, Port: {}",
+        config.connection.mode, config.connection.host, config.connection.port
+    );
 
-        // Use tokio timeout for select! -like behavior with cancellation
-        // But since we removed read timeout, we might block forever stuck in recv().
-        // We need a way to check 'running' while waiting.
-        // Option 1: Timeout short loop? No, excessive.
-        // Option 2: tokio::select! with a cancellation token.
-        // For now, simplicity: Check before recv. If recv blocks forever, forced process exit kills it anyway.
-        // But to be "Check running flag" compliant, we should ideally use select.
-        // Let's rely on process exit for hard kill, but check flag for cooperative exit (e.g. if we add UI stop button)
-
-        // Actually, for "safest possible", we want to ensure we don't crash on exit.
-
-        let packet = match connection.recv().await {
-            Ok(p) => p,
-            Err(e) => {
-                error!("Receive error: {}", e);
-                break;
-            }
-        };
-
-        match packet.packet_type {
-            PacketType::Video => {
-                match video_decoder.decode(&packet.data, packet.pts) {
-                    Ok(Some(frame)) => {
-                        // Send frame to UI thread
-                        if let Err(e) = frame_tx.send(frame) {
-                            error!("Failed to send frame to UI: {}", e);
-                            break; // UI thread likely dead
-                        }
-                    }
-                    Ok(None) => {} // Need more data
-                    Err(e) => error!("Video decoding error: {}", e),
-                }
-            }
-            PacketType::Audio => {
-                if let (Ok(decoder), Some(player)) = (&mut audio_decoder, &mut audio_player) {
-                    match decoder.decode(&packet.data, packet.pts) {
-                        Ok(Some(audio_frame)) => {
-                            if let Err(e) = player.play(audio_frame) {
-                                error!("Audio playback error: {}", e);
-                            }
-                        }
-                        Ok(None) => {}
-                        Err(e) => error!("Audio decoding error: {}", e),
-                    }
-                }
-            }
-            PacketType::Control => {
-                // Ignore control messages
-            }
-            PacketType::Handshake => {
-                info!("Received handshake packet");
-                // In a full impl, we'd parse device name/size here
-            }
-            PacketType::Fec => {}
-        }
-    }
-    info!("Connection closed");
-    Ok(())
+    let flag_geometry = WindowGeometry {
+        x: args.window_x,
+        y: args.window_y,
+        width: args.window_width,
+        height: args.window_height,
+    };
+    app::window::run(config, flag_geometry)
 }
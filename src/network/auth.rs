@@ -0,0 +1,361 @@
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use super::protocol::{CipherSuite, Packet, PacketCipher, PacketType};
+use super::ConnectionMode;
+use crate::config::ConnectionConfig;
+
+/// Nonce length for both sides of the challenge
+pub const NONCE_LEN: usize = 32;
+
+/// Nonce exchanged during authentication
+pub type Nonce = [u8; NONCE_LEN];
+
+/// Authentication step carried in `Handshake` packets
+///
+/// 1. server -> client: `Challenge` with a fresh server nonce
+/// 2. client -> server: `Response` with a client nonce, the payload cipher it
+///    wants and its proof
+/// 3. server -> client: `Accepted` with the cipher in use and the server's
+///    proof, or `Rejected`
+///
+/// Both proofs cover both nonces and the cipher, so neither side can be
+/// replayed, encryption cannot be stripped in transit, and the client knows
+/// it reached a server that holds the same secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthMessage {
+    Challenge {
+        nonce: Nonce,
+    },
+    Response {
+        nonce: Nonce,
+        cipher: CipherSuite,
+        mac: Vec<u8>,
+    },
+    Accepted {
+        cipher: CipherSuite,
+        mac: Vec<u8>,
+    },
+    Rejected,
+}
+
+impl AuthMessage {
+    /// Wrap in a `Handshake` packet
+    pub fn into_packet(self) -> Result<Packet> {
+        let data = bincode::serialize(&self)?;
+        Ok(Packet::new(PacketType::Handshake, 0, 0, Bytes::from(data)))
+    }
+
+    /// Parse from a `Handshake` packet
+    pub fn from_packet(packet: &Packet) -> Result<Self> {
+        if packet.packet_type != PacketType::Handshake {
+            bail!("Expected handshake packet, got {:?}", packet.packet_type);
+        }
+        Ok(bincode::deserialize(&packet.data)?)
+    }
+}
+
+/// Shared secret both peers were configured with
+#[derive(Clone)]
+pub struct SharedSecret {
+    key: hmac::Key,
+    cipher: CipherSuite,
+    rng: SystemRandom,
+}
+
+impl SharedSecret {
+    /// Domain separation so a client proof can never pass as a server proof
+    const CLIENT_LABEL: &'static [u8] = b"scrcpy-custom auth client";
+    const SERVER_LABEL: &'static [u8] = b"scrcpy-custom auth server";
+
+    /// Labels for the per-session payload keys of each direction
+    const CLIENT_KEY_LABEL: &'static [u8] = b"scrcpy-custom payload client";
+    const SERVER_KEY_LABEL: &'static [u8] = b"scrcpy-custom payload server";
+
+    /// Create from the configured token
+    pub fn new(token: &str) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes()),
+            cipher: CipherSuite::None,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Secret to prove on a `mode` connection to `config.host`, if any
+    ///
+    /// QUIC servers and relays take the `auth_token`; scrcpy-server, reached
+    /// through an adb tunnel on this machine, has no authentication step.
+    /// Encryption is requested when `encrypt_payloads` is set.
+    pub fn for_connection(config: &ConnectionConfig, mode: ConnectionMode) -> Option<Self> {
+        let token = config.auth_token.as_deref()?;
+        if mode == ConnectionMode::Tcp && config.is_local() {
+            return None;
+        }
+        Some(Self::new(token).with_cipher(Self::requested_cipher(config)))
+    }
+
+    /// Secret a relay challenges its viewers with, if an `auth_token` is set
+    pub fn for_relay(config: &ConnectionConfig) -> Option<Self> {
+        let token = config.auth_token.as_deref()?;
+        Some(Self::new(token).with_cipher(Self::requested_cipher(config)))
+    }
+
+    fn requested_cipher(config: &ConnectionConfig) -> CipherSuite {
+        if config.encrypt_payloads {
+            CipherSuite::Aes256Gcm
+        } else {
+            CipherSuite::None
+        }
+    }
+
+    /// Request (client) or use (server) a payload cipher
+    pub fn with_cipher(mut self, cipher: CipherSuite) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Server: start authentication, returning the challenge and its nonce
+    pub fn challenge(&self) -> Result<(AuthMessage, Nonce)> {
+        let nonce = self.nonce()?;
+        Ok((AuthMessage::Challenge { nonce }, nonce))
+    }
+
+    /// Client: answer a challenge, returning the response and the client nonce
+    pub fn respond(&self, challenge: &AuthMessage) -> Result<(AuthMessage, Nonce, Nonce)> {
+        let AuthMessage::Challenge {
+            nonce: server_nonce,
+        } = challenge
+        else {
+            bail!("Expected authentication challenge, got {:?}", challenge);
+        };
+
+        let client_nonce = self.nonce()?;
+        let mac = self.sign(Self::CLIENT_LABEL, server_nonce, &client_nonce, self.cipher);
+        Ok((
+            AuthMessage::Response {
+                nonce: client_nonce,
+                cipher: self.cipher,
+                mac,
+            },
+            *server_nonce,
+            client_nonce,
+        ))
+    }
+
+    /// Server: check a response, returning `Accepted` or `Rejected`
+    ///
+    /// Only start streaming media when the result is `Accepted`. The server's
+    /// own cipher always wins; the client refuses a mismatch.
+    pub fn verify_response(&self, server_nonce: &Nonce, response: &AuthMessage) -> AuthMessage {
+        let AuthMessage::Response {
+            nonce: client_nonce,
+            cipher,
+            mac,
+        } = response
+        else {
+            return AuthMessage::Rejected;
+        };
+
+        if !self.verify(Self::CLIENT_LABEL, server_nonce, client_nonce, *cipher, mac) {
+            return AuthMessage::Rejected;
+        }
+        AuthMessage::Accepted {
+            cipher: self.cipher,
+            mac: self.sign(Self::SERVER_LABEL, client_nonce, server_nonce, self.cipher),
+        }
+    }
+
+    /// Client: check the server's verdict and proof
+    pub fn verify_accept(
+        &self,
+        server_nonce: &Nonce,
+        client_nonce: &Nonce,
+        reply: &AuthMessage,
+    ) -> Result<()> {
+        match reply {
+            AuthMessage::Accepted { cipher, mac } => {
+                if !self.verify(Self::SERVER_LABEL, client_nonce, server_nonce, *cipher, mac) {
+                    bail!("Server failed to prove it knows the auth token");
+                }
+                if *cipher != self.cipher {
+                    bail!(
+                        "Server uses payload cipher {:?}, expected {:?}",
+                        cipher,
+                        self.cipher
+                    );
+                }
+                Ok(())
+            }
+            AuthMessage::Rejected => bail!("Server rejected the auth token"),
+            other => bail!("Unexpected authentication message: {:?}", other),
+        }
+    }
+
+    /// Client: payload cipher for this session, if one was negotiated
+    pub fn client_cipher(
+        &self,
+        server_nonce: &Nonce,
+        client_nonce: &Nonce,
+    ) -> Result<Option<PacketCipher>> {
+        self.packet_cipher(server_nonce, client_nonce, true)
+    }
+
+    /// Server: payload cipher for this session, if one was negotiated
+    pub fn server_cipher(
+        &self,
+        server_nonce: &Nonce,
+        client_nonce: &Nonce,
+    ) -> Result<Option<PacketCipher>> {
+        self.packet_cipher(server_nonce, client_nonce, false)
+    }
+
+    /// Derive fresh keys from the secret and both nonces (HMAC as PRF)
+    fn packet_cipher(
+        &self,
+        server_nonce: &Nonce,
+        client_nonce: &Nonce,
+        client: bool,
+    ) -> Result<Option<PacketCipher>> {
+        if self.cipher == CipherSuite::None {
+            return Ok(None);
+        }
+        let client_key = self.sign(
+            Self::CLIENT_KEY_LABEL,
+            server_nonce,
+            client_nonce,
+            self.cipher,
+        );
+        let server_key = self.sign(
+            Self::SERVER_KEY_LABEL,
+            server_nonce,
+            client_nonce,
+            self.cipher,
+        );
+        let (seal, open) = if client {
+            (client_key, server_key)
+        } else {
+            (server_key, client_key)
+        };
+        PacketCipher::new(&seal, &open)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    fn nonce(&self) -> Result<Nonce> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("System random number generator failed"))?;
+        Ok(nonce)
+    }
+
+    fn sign(&self, label: &[u8], first: &Nonce, second: &Nonce, cipher: CipherSuite) -> Vec<u8> {
+        let mut ctx = hmac::Context::with_key(&self.key);
+        ctx.update(label);
+        ctx.update(first);
+        ctx.update(second);
+        ctx.update(&[cipher as u8]);
+        ctx.sign().as_ref().to_vec()
+    }
+
+    fn verify(
+        &self,
+        label: &[u8],
+        first: &Nonce,
+        second: &Nonce,
+        cipher: CipherSuite,
+        mac: &[u8],
+    ) -> bool {
+        let message = [label, first.as_slice(), second.as_slice(), &[cipher as u8]].concat();
+        // Constant-time comparison
+        hmac::verify(&self.key, &message, mac).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(server: &SharedSecret, client: &SharedSecret) -> Result<()> {
+        let (challenge, server_nonce) = server.challenge()?;
+        let packet = challenge.into_packet()?;
+        let (response, seen_nonce, client_nonce) =
+            client.respond(&AuthMessage::from_packet(&packet)?)?;
+        assert_eq!(seen_nonce, server_nonce);
+
+        let verdict = server.verify_response(&server_nonce, &response);
+        client.verify_accept(&server_nonce, &client_nonce, &verdict)
+    }
+
+    #[test]
+    fn test_matching_secret_authenticates() {
+        let secret = SharedSecret::new("correct horse");
+        assert!(run(&secret, &SharedSecret::new("correct horse")).is_ok());
+    }
+
+    #[test]
+    fn test_wrong_secret_is_rejected() {
+        let server = SharedSecret::new("correct horse");
+        let client = SharedSecret::new("battery staple");
+
+        let (challenge, server_nonce) = server.challenge().unwrap();
+        let (response, _, _) = client.respond(&challenge).unwrap();
+        assert_eq!(
+            server.verify_response(&server_nonce, &response),
+            AuthMessage::Rejected
+        );
+        assert!(run(&server, &client).is_err());
+    }
+
+    #[test]
+    fn test_replayed_response_is_rejected() {
+        let secret = SharedSecret::new("token");
+        let (challenge, _) = secret.challenge().unwrap();
+        let (response, _, _) = secret.respond(&challenge).unwrap();
+
+        // Same response against a new challenge
+        let (_, fresh_nonce) = secret.challenge().unwrap();
+        assert_eq!(
+            secret.verify_response(&fresh_nonce, &response),
+            AuthMessage::Rejected
+        );
+    }
+
+    #[test]
+    fn test_cipher_mismatch_is_refused() {
+        let server = SharedSecret::new("token");
+        let client = SharedSecret::new("token").with_cipher(CipherSuite::Aes256Gcm);
+        assert!(run(&server, &client).is_err());
+    }
+
+    #[test]
+    fn test_negotiated_cipher_round_trips_payloads() {
+        let server = SharedSecret::new("token").with_cipher(CipherSuite::Aes256Gcm);
+        let client = SharedSecret::new("token").with_cipher(CipherSuite::Aes256Gcm);
+        assert!(run(&server, &client).is_ok());
+
+        let (server_nonce, client_nonce) = ([1u8; NONCE_LEN], [2u8; NONCE_LEN]);
+        let server = server
+            .server_cipher(&server_nonce, &client_nonce)
+            .unwrap()
+            .unwrap();
+        let client = client
+            .client_cipher(&server_nonce, &client_nonce)
+            .unwrap()
+            .unwrap();
+
+        let packet = Packet::new(PacketType::Video, 42, 7, Bytes::from_static(b"frame"));
+        let sealed = server.seal(&packet).unwrap();
+        assert_eq!(sealed.data.len(), 5 + PacketCipher::OVERHEAD);
+        assert_eq!(client.open(&sealed).unwrap().data, packet.data);
+
+        // Header is bound to the payload, and keys differ per direction
+        let mut moved = sealed.clone();
+        moved.seq = 8;
+        assert!(client.open(&moved).is_err());
+        assert!(server.open(&sealed).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use super::{Connection, ConnectionMode, NetworkError, PacketType, Result};
+use crate::stats::Percentiles;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+/// Latency samples kept per run (a minute of 60 fps video)
+const LATENCY_SAMPLES: usize = 3600;
+
+/// Measurements from streaming over one transport for a fixed time
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub mode: ConnectionMode,
+    pub elapsed: Duration,
+    pub video_frames: u64,
+    pub bytes: u64,
+    /// Per-frame delay beyond the quickest frame of the run (ms)
+    pub latency: Percentiles,
+    /// Transport-reported loss (0.0 - 100.0)
+    pub packet_loss: f64,
+}
+
+impl BenchReport {
+    pub fn fps(&self) -> f64 {
+        self.video_frames as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn mbps(&self) -> f64 {
+        self.bytes as f64 * 8.0 / 1_000_000.0 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Receive from `connection` for `duration`
+///
+/// Latency is measured in-stream: each frame's arrival time minus its PTS.
+/// The device clock shares no epoch with ours, so delays are reported above
+/// the quickest frame of the run, which is what the transport adds on top
+/// of the best case.
+pub async fn measure(
+    connection: &mut dyn Connection,
+    mode: ConnectionMode,
+    duration: Duration,
+) -> Result<BenchReport> {
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + duration;
+
+    let mut video_frames = 0;
+    let mut bytes = 0;
+    // Arrival minus PTS per frame (us)
+    let mut offsets = Vec::new();
+    let result = loop {
+        match tokio::time::timeout_at(deadline, connection.recv()).await {
+            Err(_) => break Ok(()),
+            Ok(Ok(packet)) => {
+                bytes += packet.data.len() as u64;
+                if packet.packet_type == PacketType::Video && !packet.flags.config {
+                    video_frames += 1;
+                    offsets.push(start.elapsed().as_micros() as i64 - packet.pts);
+                }
+            }
+            Ok(Err(e)) => break Err(e),
+        }
+    };
+    result?;
+
+    let latency = frame_delays(&offsets);
+    if video_frames == 0 {
+        return Err(NetworkError::Protocol("No video received".into()));
+    }
+    Ok(BenchReport {
+        mode,
+        elapsed: start.elapsed(),
+        video_frames,
+        bytes,
+        latency,
+        packet_loss: connection.stats().packet_loss,
+    })
+}
+
+/// Delay of each frame beyond the quickest one, from arrival-minus-PTS offsets
+fn frame_delays(offsets: &[i64]) -> Percentiles {
+    let mut delays = Percentiles::new(LATENCY_SAMPLES);
+    if let Some(&quickest) = offsets.iter().min() {
+        for offset in offsets {
+            delays.add((offset - quickest) as f64 / 1000.0);
+        }
+    }
+    delays
+}
+
+/// Side-by-side table of benchmark results, failed runs included
+pub fn format_table(
+    results: &[(ConnectionMode, std::result::Result<BenchReport, String>)],
+) -> String {
+    let mut table = format!(
+        "{:<6} {:>7} {:>8} {:>9} {:>9} {:>7}\n",
+        "mode", "fps", "Mbps", "p50 ms", "p95 ms", "loss %"
+    );
+    let ms = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.1}", v));
+    for (mode, result) in results {
+        let name = format!("{:?}", mode).to_lowercase();
+        match result {
+            Ok(report) => {
+                let _ = writeln!(
+                    table,
+                    "{:<6} {:>7.1} {:>8.2} {:>9} {:>9} {:>7.2}",
+                    name,
+                    report.fps(),
+                    report.mbps(),
+                    ms(report.latency.p50()),
+                    ms(report.latency.p95()),
+                    report.packet_loss
+                );
+            }
+            Err(e) => {
+                let _ = writeln!(table, "{:<6} failed: {}", name, e);
+            }
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_rates_and_table() {
+        let mut latency = Percentiles::new(LATENCY_SAMPLES);
+        for ms in [4.0, 2.0, 3.0, 10.0, 1.0] {
+            latency.add(ms);
+        }
+        let report = BenchReport {
+            mode: ConnectionMode::Tcp,
+            elapsed: Duration::from_secs(2),
+            video_frames: 120,
+            bytes: 2_000_000,
+            latency,
+            packet_loss: 0.0,
+        };
+        assert_eq!(report.fps(), 60.0);
+        assert_eq!(report.mbps(), 8.0);
+
+        let table = format_table(&[
+            (ConnectionMode::Tcp, Ok(report)),
+            (ConnectionMode::Quic, Err("Connection refused".into())),
+        ]);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("tcp") && lines[1].contains("60.0"));
+        assert_eq!(lines[2], "quic   failed: Connection refused");
+    }
+
+    #[test]
+    fn test_frame_delays_are_relative_to_the_quickest_frame() {
+        // Clocks 5 s apart; the third frame arrives 12 ms late
+        let offsets = [5_000_000, 5_001_000, 5_012_000, 5_000_000];
+        let delays = frame_delays(&offsets);
+        assert_eq!(delays.samples().collect::<Vec<_>>(), [0.0, 1.0, 12.0, 0.0]);
+        assert!(frame_delays(&[]).is_empty());
+    }
+}
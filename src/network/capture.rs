@@ -0,0 +1,180 @@
+/// Recorded sessions: every received packet with the time it arrived
+///
+/// A capture starts with the `SCAP` magic and a version byte, then the
+/// stream header: video and audio codec ids (u32 BE, 0 when absent) and the
+/// device name (u16 BE length + UTF-8). One record per packet follows: its
+/// arrival in microseconds since the capture started (u64 LE), then the
+/// packet as [`Packet::to_bytes`] writes it.
+use super::Packet;
+use crate::config::{AudioCodec, VideoCodec};
+use bytes::Bytes;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"SCAP";
+const VERSION: u8 = 1;
+
+/// What the connection announced before the first packet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptureHeader {
+    pub video_codec: Option<VideoCodec>,
+    pub audio_codec: Option<AudioCodec>,
+    pub device_name: Option<String>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Writes packets to a capture as they arrive
+pub struct CaptureWriter<W: Write> {
+    out: W,
+    started: Instant,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(mut out: W, header: &CaptureHeader) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&header.video_codec.map_or(0, |c| c.codec_id()).to_be_bytes())?;
+        out.write_all(&header.audio_codec.map_or(0, |c| c.codec_id()).to_be_bytes())?;
+        let name = header.device_name.as_deref().unwrap_or_default().as_bytes();
+        let len = u16::try_from(name.len()).map_err(|_| invalid("Device name too long"))?;
+        out.write_all(&len.to_be_bytes())?;
+        out.write_all(name)?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+        })
+    }
+
+    /// Record `packet` as arriving now
+    pub fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        self.write_at(packet, self.started.elapsed())
+    }
+
+    /// Record `packet` as arriving `arrival` after the capture started
+    pub fn write_at(&mut self, packet: &Packet, arrival: Duration) -> io::Result<()> {
+        self.out
+            .write_all(&(arrival.as_micros() as u64).to_le_bytes())?;
+        self.out.write_all(&packet.to_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Start a capture at `path`, replacing any file there
+    pub fn create(path: &Path, header: &CaptureHeader) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+/// Reads a capture back, packet by packet
+pub struct CaptureReader<R: Read> {
+    input: R,
+    header: CaptureHeader,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 5];
+        input.read_exact(&mut magic)?;
+        if &magic[..4] != MAGIC {
+            return Err(invalid("Not a session capture"));
+        }
+        if magic[4] != VERSION {
+            return Err(invalid("Unsupported capture version"));
+        }
+        let mut ids = [0u8; 10];
+        input.read_exact(&mut ids)?;
+        let video = u32::from_be_bytes(ids[0..4].try_into().unwrap());
+        let audio = u32::from_be_bytes(ids[4..8].try_into().unwrap());
+        let mut name = vec![0u8; u16::from_be_bytes([ids[8], ids[9]]) as usize];
+        input.read_exact(&mut name)?;
+        let header = CaptureHeader {
+            video_codec: VideoCodec::from_codec_id(video),
+            audio_codec: AudioCodec::from_codec_id(audio),
+            device_name: (!name.is_empty()).then(|| String::from_utf8_lossy(&name).into_owned()),
+        };
+        Ok(Self { input, header })
+    }
+
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Next packet and its arrival time, None at the end of the capture
+    pub fn next_packet(&mut self) -> io::Result<Option<(Duration, Packet)>> {
+        let mut head = [0u8; 8 + Packet::HEADER_SIZE];
+        match self.input.read_exact(&mut head) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let arrival = Duration::from_micros(u64::from_le_bytes(head[..8].try_into().unwrap()));
+        // The payload length closes the packet header
+        let len = u32::from_le_bytes(head[head.len() - 4..].try_into().unwrap()) as usize;
+        let mut raw = Vec::with_capacity(Packet::HEADER_SIZE + len);
+        raw.extend_from_slice(&head[8..]);
+        raw.resize(Packet::HEADER_SIZE + len, 0);
+        self.input.read_exact(&mut raw[Packet::HEADER_SIZE..])?;
+        let packet = Packet::from_bytes(Bytes::from(raw)).map_err(invalid)?;
+        Ok(Some((arrival, packet)))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<(Duration, Packet)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{FrameFlags, PacketType};
+
+    #[test]
+    fn test_capture_round_trip() {
+        let header = CaptureHeader {
+            video_codec: Some(VideoCodec::H264),
+            audio_codec: Some(AudioCodec::Opus),
+            device_name: Some("Pixel 7".to_string()),
+        };
+        let config = Packet::new(PacketType::Video, 0, 0, Bytes::from_static(b"sps")).with_flags(
+            FrameFlags {
+                config: true,
+                keyframe: false,
+            },
+        );
+        let audio = Packet::new(PacketType::Audio, 20_000, 1, Bytes::from_static(b"opus"));
+
+        let mut writer = CaptureWriter::new(Vec::new(), &header).unwrap();
+        writer.write_at(&config, Duration::ZERO).unwrap();
+        writer.write_at(&audio, Duration::from_millis(21)).unwrap();
+
+        let mut reader = CaptureReader::new(writer.out.as_slice()).unwrap();
+        assert_eq!(reader.header(), &header);
+        let (at, packet) = reader.next_packet().unwrap().unwrap();
+        assert_eq!(
+            (at, packet.flags, packet.data),
+            (Duration::ZERO, config.flags, config.data)
+        );
+        let (at, packet) = reader.next_packet().unwrap().unwrap();
+        assert_eq!(at, Duration::from_millis(21));
+        assert_eq!(
+            (packet.packet_type, packet.pts),
+            (PacketType::Audio, 20_000)
+        );
+        assert!(reader.next_packet().unwrap().is_none());
+
+        assert!(CaptureReader::new(&b"RIFF\x01"[..]).is_err());
+    }
+}
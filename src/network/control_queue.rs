@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+use std::mem::discriminant;
+use std::time::{Duration, Instant};
+
+use super::protocol::{ControlMessage, TouchAction};
+
+/// Outbound priority classes, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ControlPriority {
+    /// Touch, key, scroll and HID injection
+    Input = 0,
+    /// Keyframe requests
+    Keyframe = 1,
+    /// Bitrate, resolution, frame rate and FEC tuning
+    StreamSettings = 2,
+    /// Clipboard transfers and everything else
+    Bulk = 3,
+}
+
+impl ControlPriority {
+    const COUNT: usize = 4;
+}
+
+impl ControlMessage {
+    /// Priority class used by `ControlQueue`
+    pub fn priority(&self) -> ControlPriority {
+        match self {
+            ControlMessage::InjectTouch { .. }
+            | ControlMessage::InjectKeycode { .. }
+            | ControlMessage::InjectScroll { .. }
+            // HID reports only make sense after the create, in order
+            | ControlMessage::UhidCreate { .. }
+            | ControlMessage::UhidInput { .. }
+            | ControlMessage::UhidDestroy { .. }
+            // Each press rotates once more, so none may be superseded
+            | ControlMessage::RotateDevice => ControlPriority::Input,
+            ControlMessage::RequestKeyframe | ControlMessage::RequestShards { .. } => {
+                ControlPriority::Keyframe
+            }
+            ControlMessage::SetBitrate(_)
+            | ControlMessage::SetResolution { .. }
+            | ControlMessage::SetFrameRate(_)
+            | ControlMessage::SetFecRedundancy { .. } => ControlPriority::StreamSettings,
+            _ => ControlPriority::Bulk,
+        }
+    }
+
+    /// Touch moves may be dropped under pressure; downs and ups never are
+    fn is_droppable(&self) -> bool {
+        matches!(
+            self,
+            ControlMessage::InjectTouch {
+                action: TouchAction::Move,
+                ..
+            } | ControlMessage::InjectScroll { .. }
+        )
+    }
+
+    /// Whether a newer message of the same kind makes a queued one obsolete
+    fn supersedes(&self, queued: &ControlMessage) -> bool {
+        match self.priority() {
+            ControlPriority::Input => false,
+            // Shard requests name different blocks, they can't replace each other
+            _ if matches!(self, ControlMessage::RequestShards { .. }) => false,
+            _ => discriminant(self) == discriminant(queued),
+        }
+    }
+}
+
+/// Control queue counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ControlQueueStats {
+    /// Messages handed out for sending
+    pub sent: u64,
+
+    /// Messages dropped because their class was full
+    pub dropped: u64,
+
+    /// Queued messages replaced by a newer one of the same kind
+    pub superseded: u64,
+}
+
+struct Queued {
+    msg: ControlMessage,
+    enqueued_at: Instant,
+}
+
+/// Prioritized, rate-limited outbound queue for control messages
+///
+/// Messages leave in priority order (input > keyframe > stream settings >
+/// clipboard) through a token bucket. A message that has waited longer than
+/// `max_wait` jumps ahead so a stream of input can't starve a keyframe request.
+pub struct ControlQueue {
+    queues: [VecDeque<Queued>; ControlPriority::COUNT],
+    capacity: usize,
+    rate_per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+    max_wait: Duration,
+    stats: ControlQueueStats,
+}
+
+impl ControlQueue {
+    /// Create a new control queue
+    ///
+    /// # Arguments
+    /// * `rate_per_sec` - Sustained messages per second
+    /// * `burst` - Messages that may leave back-to-back after an idle period
+    /// * `capacity` - Messages held per priority class
+    pub fn new(rate_per_sec: u32, burst: u32, capacity: usize) -> Self {
+        Self {
+            queues: Default::default(),
+            capacity,
+            rate_per_sec: rate_per_sec as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+            max_wait: Duration::from_millis(100),
+            stats: ControlQueueStats::default(),
+        }
+    }
+
+    /// Queue a message
+    ///
+    /// Returns false if the message (or an older one) had to be dropped.
+    pub fn push(&mut self, msg: ControlMessage) -> bool {
+        self.push_at(msg, Instant::now())
+    }
+
+    fn push_at(&mut self, msg: ControlMessage, now: Instant) -> bool {
+        let queue = &mut self.queues[msg.priority() as usize];
+
+        if let Some(existing) = queue.iter_mut().find(|q| msg.supersedes(&q.msg)) {
+            existing.msg = msg;
+            self.stats.superseded += 1;
+            return true;
+        }
+
+        if queue.len() >= self.capacity {
+            // Make room by dropping the oldest droppable message, or refuse
+            match queue.iter().position(|q| q.msg.is_droppable()) {
+                Some(index) => {
+                    queue.remove(index);
+                    self.stats.dropped += 1;
+                }
+                None => {
+                    self.stats.dropped += 1;
+                    return false;
+                }
+            }
+            queue.push_back(Queued {
+                msg,
+                enqueued_at: now,
+            });
+            return false;
+        }
+
+        queue.push_back(Queued {
+            msg,
+            enqueued_at: now,
+        });
+        true
+    }
+
+    /// Take the next message if the rate limit allows
+    pub fn pop(&mut self, now: Instant) -> Option<ControlMessage> {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return None;
+        }
+
+        let index = self.next_queue(now)?;
+        let queued = self.queues[index].pop_front()?;
+        self.tokens -= 1.0;
+        self.stats.sent += 1;
+        Some(queued.msg)
+    }
+
+    /// Take every message the rate limit currently allows
+    pub fn drain_ready(&mut self, now: Instant) -> Vec<ControlMessage> {
+        let mut ready = Vec::new();
+        while let Some(msg) = self.pop(now) {
+            ready.push(msg);
+        }
+        ready
+    }
+
+    /// When the next queued message may leave, None while nothing is queued
+    ///
+    /// Lets a caller sleep until then instead of polling the queue.
+    pub fn next_ready(&self) -> Option<Instant> {
+        if self.is_empty() {
+            return None;
+        }
+        let missing = (1.0 - self.tokens).max(0.0);
+        Some(self.last_refill + Duration::from_secs_f64(missing / self.rate_per_sec))
+    }
+
+    /// Queue to serve next: an overdue lower class first, else highest non-empty
+    fn next_queue(&self, now: Instant) -> Option<usize> {
+        let overdue = (1..ControlPriority::COUNT).find(|&i| {
+            self.queues[i]
+                .front()
+                .is_some_and(|q| now.duration_since(q.enqueued_at) >= self.max_wait)
+        });
+        overdue.or_else(|| (0..ControlPriority::COUNT).find(|&i| !self.queues[i].is_empty()))
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.burst);
+        self.last_refill = now;
+    }
+
+    /// Number of queued messages across all classes
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    /// Check if nothing is queued
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+
+    /// Get queue statistics
+    pub fn stats(&self) -> ControlQueueStats {
+        self.stats
+    }
+}
+
+impl Default for ControlQueue {
+    /// 500 msg/s sustained (plenty for 120 Hz input), bursts of 64
+    fn default() -> Self {
+        Self::new(500, 64, 256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(action: TouchAction, x: u32) -> ControlMessage {
+        ControlMessage::InjectTouch {
+            action,
+            pointer_id: 0,
+            x,
+            y: 0,
+            width: 1080,
+            height: 1920,
+            pressure: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let mut queue = ControlQueue::new(1000, 10, 16);
+        let now = Instant::now();
+
+        queue.push_at(
+            ControlMessage::SetClipboard {
+                text: "hi".into(),
+                paste: false,
+            },
+            now,
+        );
+        queue.push_at(ControlMessage::SetBitrate(4), now);
+        queue.push_at(ControlMessage::RequestKeyframe, now);
+        queue.push_at(touch(TouchAction::Down, 1), now);
+
+        let order: Vec<ControlPriority> = queue
+            .drain_ready(now)
+            .iter()
+            .map(|m| m.priority())
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ControlPriority::Input,
+                ControlPriority::Keyframe,
+                ControlPriority::StreamSettings,
+                ControlPriority::Bulk
+            ]
+        );
+    }
+
+    #[test]
+    fn test_supersede_and_rate_limit() {
+        let mut queue = ControlQueue::new(10, 2, 16);
+        let now = Instant::now();
+
+        queue.push_at(ControlMessage::SetBitrate(8), now);
+        queue.push_at(ControlMessage::SetBitrate(4), now);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.stats().superseded, 1);
+
+        for x in 0..5 {
+            queue.push_at(touch(TouchAction::Move, x), now);
+        }
+
+        // Burst of two, then the bucket is empty
+        assert_eq!(queue.drain_ready(now).len(), 2);
+        assert!(queue.pop(now).is_none());
+
+        // 100ms later one more token is available
+        assert!(queue.pop(now + Duration::from_millis(100)).is_some());
+    }
+
+    #[test]
+    fn test_overflow_drops_moves_not_presses() {
+        let mut queue = ControlQueue::new(1000, 100, 2);
+        let now = Instant::now();
+
+        queue.push_at(touch(TouchAction::Down, 0), now);
+        queue.push_at(touch(TouchAction::Move, 1), now);
+        // Full: the move makes room for the up
+        assert!(!queue.push_at(touch(TouchAction::Up, 2), now));
+
+        let drained = queue.drain_ready(now);
+        assert!(matches!(
+            drained[1],
+            ControlMessage::InjectTouch {
+                action: TouchAction::Up,
+                ..
+            }
+        ));
+        assert_eq!(queue.stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_next_ready_waits_for_a_token() {
+        let mut queue = ControlQueue::new(10, 1, 16);
+        let now = Instant::now();
+        assert_eq!(queue.next_ready(), None);
+
+        queue.push_at(touch(TouchAction::Move, 0), now);
+        queue.push_at(touch(TouchAction::Move, 1), now);
+        assert!(queue.next_ready().is_some_and(|at| at <= now));
+
+        assert!(queue.pop(now).is_some());
+        // The bucket is empty: one token takes 100ms at 10 msg/s
+        let ready = queue.next_ready().unwrap();
+        assert_eq!(ready, now + Duration::from_millis(100));
+        assert!(queue.pop(ready).is_some());
+        assert_eq!(queue.next_ready(), None);
+    }
+
+    #[test]
+    fn test_starved_class_jumps_ahead() {
+        let mut queue = ControlQueue::new(1000, 100, 64);
+        let start = Instant::now();
+
+        queue.push_at(ControlMessage::RequestKeyframe, start);
+        let later = start + Duration::from_millis(200);
+        queue.push_at(touch(TouchAction::Move, 0), later);
+
+        assert!(matches!(
+            queue.pop(later),
+            Some(ControlMessage::RequestKeyframe)
+        ));
+    }
+}
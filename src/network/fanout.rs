@@ -0,0 +1,165 @@
+use super::{Packet, PacketType};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Packets a slow receiver may fall behind by before it skips ahead
+const BACKLOG: usize = 512;
+
+/// Latest codec configs, replayed to every new receiver
+#[derive(Debug, Default)]
+struct Configs {
+    video: Option<Packet>,
+    audio: Option<Packet>,
+}
+
+/// Duplicates one device stream (video and audio) to any number of receivers
+///
+/// A receiver starts with the latest codec configs, then skips video up to
+/// the next keyframe, which it asks the device for. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Fanout {
+    tx: broadcast::Sender<Packet>,
+    configs: Arc<Mutex<Configs>>,
+    keyframe_wanted: Arc<AtomicBool>,
+}
+
+/// One downstream connection's view of a [`Fanout`]
+pub struct FanoutReceiver {
+    rx: broadcast::Receiver<Packet>,
+    /// Codec configs still to hand out
+    pending: VecDeque<Packet>,
+    synced: bool,
+    keyframe_wanted: Arc<AtomicBool>,
+}
+
+impl Default for Fanout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fanout {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(BACKLOG).0,
+            configs: Arc::default(),
+            keyframe_wanted: Arc::default(),
+        }
+    }
+
+    /// Pass a packet from the device on to every receiver
+    pub fn publish(&self, packet: &Packet) {
+        // Locked across the send so a new receiver sees each config exactly once
+        let mut configs = self.configs.lock();
+        if packet.flags.config {
+            match packet.packet_type {
+                PacketType::Video => configs.video = Some(packet.clone()),
+                PacketType::Audio => configs.audio = Some(packet.clone()),
+                _ => {}
+            }
+        }
+        // Fails only when nobody listens
+        let _ = self.tx.send(packet.clone());
+    }
+
+    /// Add a receiver, asking the device for a keyframe to start it
+    pub fn subscribe(&self) -> FanoutReceiver {
+        let configs = self.configs.lock();
+        self.keyframe_wanted.store(true, Ordering::Relaxed);
+        FanoutReceiver {
+            rx: self.tx.subscribe(),
+            pending: configs
+                .video
+                .iter()
+                .chain(&configs.audio)
+                .cloned()
+                .collect(),
+            synced: false,
+            keyframe_wanted: self.keyframe_wanted.clone(),
+        }
+    }
+
+    /// Whether a receiver needs a keyframe since the last call
+    pub fn take_keyframe_request(&self) -> bool {
+        self.keyframe_wanted.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl FanoutReceiver {
+    /// Next packet to send downstream, None once the fanout is gone
+    pub async fn recv(&mut self) -> Option<Packet> {
+        if let Some(config) = self.pending.pop_front() {
+            return Some(config);
+        }
+        loop {
+            let packet = match self.rx.recv().await {
+                Ok(packet) => packet,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::debug!("Fan-out receiver skipped {} packets", skipped);
+                    // The skipped frames were references for what follows
+                    self.synced = false;
+                    self.keyframe_wanted.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if packet.packet_type == PacketType::Video && !packet.flags.config {
+                if packet.is_keyframe() {
+                    self.synced = true;
+                } else if !self.synced {
+                    continue;
+                }
+            }
+            return Some(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::executor::block_on;
+
+    fn packet(packet_type: PacketType, data: &'static [u8]) -> Packet {
+        Packet::new(packet_type, 0, 0, Bytes::from_static(data))
+    }
+
+    #[test]
+    fn test_late_receiver_starts_clean() {
+        let fanout = Fanout::new();
+        let mut early = fanout.subscribe();
+        assert!(fanout.take_keyframe_request());
+
+        let mut sps = packet(PacketType::Video, b"sps");
+        sps.flags.config = true;
+        let mut opus = packet(PacketType::Audio, b"opus");
+        opus.flags.config = true;
+        let mut idr = packet(PacketType::Video, b"idr");
+        idr.flags.keyframe = true;
+        for sent in [&sps, &opus, &idr, &packet(PacketType::Video, b"p1")] {
+            fanout.publish(sent);
+        }
+
+        let mut late = fanout.subscribe();
+        assert!(fanout.take_keyframe_request());
+        fanout.publish(&packet(PacketType::Video, b"p2"));
+        fanout.publish(&packet(PacketType::Audio, b"pcm"));
+        fanout.publish(&idr);
+
+        let data = |receiver: &mut FanoutReceiver, count| {
+            (0..count)
+                .map(|_| block_on(receiver.recv()).unwrap().data)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            data(&mut early, 7),
+            ["sps", "opus", "idr", "p1", "p2", "pcm", "idr"]
+        );
+        // Configs first, then no video before the keyframe
+        assert_eq!(data(&mut late, 4), ["sps", "opus", "pcm", "idr"]);
+    }
+}
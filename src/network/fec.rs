@@ -1,10 +1,15 @@
 use anyhow::{Context, Result};
 use bytes::Bytes;
+use parking_lot::Mutex;
 use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use super::protocol::{FecPacket, Packet, PacketType};
+use super::NetworkStats;
+use crate::stats::Ewma;
 
 /// FEC (Forward Error Correction) encoder using Reed-Solomon
 /// Allows recovery of lost packets without retransmission
@@ -41,6 +46,10 @@ impl FecEncoder {
     /// Add a packet to the encoder
     /// Returns FEC packets if a complete block is formed
     pub fn encode(&mut self, packet: Packet) -> Vec<FecPacket> {
+        if self.parity_shards == 0 {
+            return Vec::new(); // FEC disabled
+        }
+
         self.block_buffer.push(packet);
 
         // Check if we have a complete block
@@ -102,9 +111,30 @@ impl FecEncoder {
         fec_packets
     }
 
+    /// Change the block layout, flushing the current partial block first
+    ///
+    /// `parity_shards == 0` disables parity generation entirely.
+    pub fn reconfigure(
+        &mut self,
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<Vec<FecPacket>> {
+        let flushed = self.flush();
+
+        if parity_shards > 0 {
+            self.reed_solomon = ReedSolomon::new(data_shards, parity_shards)
+                .context("Failed to create Reed-Solomon encoder")?;
+        }
+        self.data_shards = data_shards;
+        self.parity_shards = parity_shards;
+        self.block_buffer = Vec::with_capacity(data_shards);
+
+        Ok(flushed)
+    }
+
     /// Force encoding of current partial block
     pub fn flush(&mut self) -> Vec<FecPacket> {
-        if self.block_buffer.is_empty() {
+        if self.block_buffer.is_empty() || self.parity_shards == 0 {
             return Vec::new();
         }
 
@@ -121,6 +151,58 @@ impl FecEncoder {
     }
 }
 
+/// FEC effectiveness counters, used to tune `fec_redundancy`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FecStats {
+    /// Blocks where every data packet arrived (parity was not needed)
+    pub blocks_complete: u64,
+
+    /// Blocks where missing data packets were rebuilt from parity
+    pub blocks_recovered: u64,
+
+    /// Blocks that expired with too few shards to rebuild
+    pub blocks_unrecoverable: u64,
+
+    /// Data packets rebuilt from parity
+    pub packets_recovered: u64,
+
+    /// Data packets lost in unrecoverable blocks
+    pub packets_unrecoverable: u64,
+
+    /// Parity packets received
+    pub parity_packets_received: u64,
+
+    /// Parity bytes received
+    pub parity_bytes_received: u64,
+
+    /// Parity bytes that arrived for blocks that needed no recovery
+    pub parity_bytes_wasted: u64,
+}
+
+impl FecStats {
+    /// Number of blocks that reached a final state
+    pub fn blocks_finished(&self) -> u64 {
+        self.blocks_complete + self.blocks_recovered + self.blocks_unrecoverable
+    }
+
+    /// Fraction of damaged blocks that FEC managed to repair (0.0 - 1.0)
+    pub fn recovery_rate(&self) -> f64 {
+        let damaged = self.blocks_recovered + self.blocks_unrecoverable;
+        if damaged == 0 {
+            return 1.0;
+        }
+        self.blocks_recovered as f64 / damaged as f64
+    }
+
+    /// Fraction of parity bandwidth spent on blocks that needed no repair (0.0 - 1.0)
+    pub fn wasted_parity_ratio(&self) -> f64 {
+        if self.parity_bytes_received == 0 {
+            return 0.0;
+        }
+        self.parity_bytes_wasted as f64 / self.parity_bytes_received as f64
+    }
+}
+
 /// FEC (Forward Error Correction) decoder using Reed-Solomon
 /// Recovers lost packets from received data and parity packets
 pub struct FecDecoder {
@@ -129,6 +211,18 @@ pub struct FecDecoder {
     parity_shards: usize,
     blocks: HashMap<u32, FecBlock>,
     last_cleanup: Instant,
+    stats: FecStats,
+}
+
+/// Lifecycle of a FEC block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockState {
+    /// Still waiting for shards
+    Pending,
+    /// All data packets arrived without help
+    Complete,
+    /// Missing data packets were rebuilt
+    Recovered,
 }
 
 struct FecBlock {
@@ -139,7 +233,8 @@ struct FecBlock {
     data_count: u8,
     parity_count: u8,
     created_at: Instant,
-    recovered: bool,
+    state: BlockState,
+    parity_bytes: u64,
 }
 
 impl FecDecoder {
@@ -154,9 +249,73 @@ impl FecDecoder {
             parity_shards,
             blocks: HashMap::new(),
             last_cleanup: Instant::now(),
+            stats: FecStats::default(),
         })
     }
 
+    /// Get FEC effectiveness statistics
+    pub fn stats(&self) -> FecStats {
+        self.stats
+    }
+
+    /// Blocks still waiting for shards that were opened before `cutoff`
+    ///
+    /// Returns each block's ID with the smallest set of missing data shard
+    /// indices whose arrival would make it decodable.
+    pub fn stalled_blocks(&self, cutoff: Instant) -> Vec<(u32, Vec<u8>)> {
+        let mut stalled: Vec<(u32, Vec<u8>)> = self
+            .blocks
+            .iter()
+            .filter(|(_, b)| b.state == BlockState::Pending && b.created_at <= cutoff)
+            .map(|(id, b)| {
+                let received = b
+                    .data_shards
+                    .iter()
+                    .chain(b.parity_shards.iter())
+                    .filter(|s| s.is_some())
+                    .count();
+                let needed = (b.data_count as usize).saturating_sub(received);
+                let missing = b
+                    .data_shards
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.is_none())
+                    .map(|(i, _)| i as u8)
+                    .take(needed)
+                    .collect();
+                (*id, missing)
+            })
+            .collect();
+        stalled.sort_by_key(|(id, _)| *id);
+        stalled
+    }
+
+    /// Whether a block is still waiting for shards (`None` if unknown or expired)
+    pub fn is_pending(&self, block_id: u32) -> Option<bool> {
+        self.blocks
+            .get(&block_id)
+            .map(|b| b.state == BlockState::Pending)
+    }
+
+    /// Data packets per block in the current layout
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    /// Switch to a new block layout announced to the sender
+    ///
+    /// Blocks in flight use the old layout and are dropped.
+    pub fn reconfigure(&mut self, data_shards: usize, parity_shards: usize) -> Result<()> {
+        if parity_shards > 0 {
+            self.reed_solomon = ReedSolomon::new(data_shards, parity_shards)
+                .context("Failed to create Reed-Solomon decoder")?;
+        }
+        self.data_shards = data_shards;
+        self.parity_shards = parity_shards;
+        self.blocks.clear();
+        Ok(())
+    }
+
     /// Add a data packet to the decoder
     pub fn add_data_packet(&mut self, seq: u32, data: Bytes) -> Option<Vec<Packet>> {
         let block_id = seq / self.data_shards as u32;
@@ -169,7 +328,8 @@ impl FecDecoder {
             data_count: self.data_shards as u8,
             parity_count: self.parity_shards as u8,
             created_at: Instant::now(),
-            recovered: false,
+            state: BlockState::Pending,
+            parity_bytes: 0,
         });
 
         // Store data packet
@@ -193,9 +353,21 @@ impl FecDecoder {
                 data_count: fec_packet.data_count,
                 parity_count: fec_packet.parity_count,
                 created_at: Instant::now(),
-                recovered: false,
+                state: BlockState::Pending,
+                parity_bytes: 0,
             });
 
+        let parity_len = fec_packet.data.len() as u64;
+        self.stats.parity_packets_received += 1;
+        self.stats.parity_bytes_received += parity_len;
+
+        match block.state {
+            BlockState::Pending => block.parity_bytes += parity_len,
+            // Parity that shows up after the block is done is pure overhead
+            BlockState::Complete => self.stats.parity_bytes_wasted += parity_len,
+            BlockState::Recovered => {}
+        }
+
         // Store parity packet
         let parity_index = fec_packet.index as usize - fec_packet.data_count as usize;
         if parity_index < block.parity_shards.len() {
@@ -210,8 +382,8 @@ impl FecDecoder {
     fn try_recover(&mut self, block_id: u32) -> Option<Vec<Packet>> {
         let block = self.blocks.get_mut(&block_id)?;
 
-        // Skip if already recovered
-        if block.recovered {
+        // Skip if already complete or recovered
+        if block.state != BlockState::Pending {
             return None;
         }
 
@@ -220,17 +392,19 @@ impl FecDecoder {
         let parity_received = block.parity_shards.iter().filter(|s| s.is_some()).count();
         let total_received = data_received + parity_received;
 
-        // Need at least data_count shards to recover
-        if total_received < block.data_count as usize {
-            return None; // Not enough data to recover
-        }
-
         // If all data packets received, no recovery needed
         if data_received == block.data_count as usize {
-            block.recovered = true;
+            block.state = BlockState::Complete;
+            self.stats.blocks_complete += 1;
+            self.stats.parity_bytes_wasted += block.parity_bytes;
             return None;
         }
 
+        // Need at least data_count shards to recover
+        if total_received < block.data_count as usize {
+            return None; // Not enough data to recover
+        }
+
         // Perform Reed-Solomon recovery
         let max_size = block
             .data_shards
@@ -287,7 +461,9 @@ impl FecDecoder {
             }
         }
 
-        block.recovered = true;
+        block.state = BlockState::Recovered;
+        self.stats.blocks_recovered += 1;
+        self.stats.packets_recovered += recovered_packets.len() as u64;
 
         if recovered_packets.is_empty() {
             None
@@ -302,17 +478,229 @@ impl FecDecoder {
             return;
         }
 
-        self.blocks
-            .retain(|_, block| block.created_at.elapsed() < Duration::from_secs(10));
+        let stats = &mut self.stats;
+        self.blocks.retain(|_, block| {
+            if block.created_at.elapsed() < Duration::from_secs(10) {
+                return true;
+            }
+
+            // Blocks still pending at expiry could not be repaired
+            if block.state == BlockState::Pending {
+                let missing = block.data_shards.iter().filter(|s| s.is_none()).count();
+                stats.blocks_unrecoverable += 1;
+                stats.packets_unrecoverable += missing as u64;
+                stats.parity_bytes_wasted += block.parity_bytes;
+            }
+            false
+        });
 
         self.last_cleanup = Instant::now();
     }
 }
 
+/// Adjusts the parity ratio at runtime from measured packet loss
+///
+/// Redundancy scales with loss (0% loss turns FEC off, 5% loss asks for 30%
+/// parity). Increases apply immediately; decreases wait for `hold_down` so a
+/// brief clean period doesn't strip protection from a flaky link.
+pub struct AdaptiveFecController {
+    data_shards: usize,
+    parity_shards: usize,
+    smoothed_loss: Ewma,
+    last_received: u64,
+    last_lost: u64,
+    last_sample: Option<Instant>,
+    last_change: Instant,
+    hold_down: Duration,
+}
+
+impl AdaptiveFecController {
+    /// Parity percentage requested per percent of packet loss
+    const REDUNDANCY_PER_LOSS: f64 = 6.0;
+
+    /// Upper bound on parity percentage
+    pub const MAX_REDUNDANCY: f64 = 50.0;
+
+    /// Loss below this (percent) is treated as a clean link
+    const LOSS_FLOOR: f64 = 0.1;
+
+    /// Minimum time between loss samples
+    const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Create a new controller
+    ///
+    /// # Arguments
+    /// * `data_shards` - Data packets per FEC block (kept fixed)
+    /// * `initial_redundancy` - Starting parity percentage (0-50)
+    pub fn new(data_shards: usize, initial_redundancy: u8) -> Self {
+        let parity_shards = Self::parity_for_redundancy(data_shards, initial_redundancy as f64);
+        Self {
+            data_shards,
+            parity_shards,
+            smoothed_loss: Ewma::with_initial(0.3, 0.0),
+            last_received: 0,
+            last_lost: 0,
+            last_sample: None,
+            last_change: Instant::now(),
+            hold_down: Duration::from_secs(5),
+        }
+    }
+
+    /// Parity shards needed to reach a redundancy percentage
+    pub fn parity_for_redundancy(data_shards: usize, redundancy: f64) -> usize {
+        let redundancy = redundancy.clamp(0.0, Self::MAX_REDUNDANCY);
+        (data_shards as f64 * redundancy / 100.0).ceil() as usize
+    }
+
+    /// Parity shards the controller wants for a given loss percentage
+    pub fn parity_for_loss(data_shards: usize, loss_percent: f64) -> usize {
+        if loss_percent < Self::LOSS_FLOOR {
+            return 0;
+        }
+        Self::parity_for_redundancy(data_shards, loss_percent * Self::REDUNDANCY_PER_LOSS)
+    }
+
+    /// Feed current connection stats
+    ///
+    /// Returns the `(data_shards, parity_shards)` layout to switch to when it
+    /// should change. It only takes effect through `commit`, once the sender
+    /// knows; until then later updates propose it again.
+    pub fn update(&mut self, stats: &NetworkStats, now: Instant) -> Option<(usize, usize)> {
+        if let Some(last) = self.last_sample {
+            if now.duration_since(last) < Self::SAMPLE_INTERVAL {
+                return None;
+            }
+        }
+        self.last_sample = Some(now);
+
+        // Loss over the last interval, not since connect
+        let received = stats.packets_received.saturating_sub(self.last_received);
+        let lost = stats.packets_lost.saturating_sub(self.last_lost);
+        self.last_received = stats.packets_received;
+        self.last_lost = stats.packets_lost;
+
+        let total = received + lost;
+        if total == 0 {
+            return None;
+        }
+        let interval_loss = lost as f64 / total as f64 * 100.0;
+        let smoothed_loss = self.smoothed_loss.add(interval_loss);
+
+        let target = Self::parity_for_loss(self.data_shards, smoothed_loss);
+        if target == self.parity_shards {
+            return None;
+        }
+        if target < self.parity_shards && now.duration_since(self.last_change) < self.hold_down {
+            return None;
+        }
+
+        tracing::info!(
+            "Adaptive FEC: loss {:.2}% -> parity {} -> {} (of {} data)",
+            smoothed_loss,
+            self.parity_shards,
+            target,
+            self.data_shards
+        );
+        Some((self.data_shards, target))
+    }
+
+    /// Switch to a layout from `update`
+    pub fn commit(&mut self, parity_shards: usize, now: Instant) {
+        self.parity_shards = parity_shards;
+        self.last_change = now;
+    }
+
+    /// Current parity percentage
+    pub fn redundancy_percent(&self) -> u8 {
+        (self.parity_shards * 100 / self.data_shards.max(1)) as u8
+    }
+
+    /// Current `(data_shards, parity_shards)` layout
+    pub fn layout(&self) -> (usize, usize) {
+        (self.data_shards, self.parity_shards)
+    }
+}
+
+/// FEC as chosen by the user at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FecSetting {
+    pub enabled: bool,
+
+    /// Parity percentage (0-50) while enabled
+    pub redundancy: u8,
+}
+
+/// Hands FEC changes from the settings panel or control API to the receive loop
+///
+/// A manual setting replaces adaptive FEC for the rest of the connection.
+#[derive(Debug, Clone)]
+pub struct FecControl {
+    state: Arc<Mutex<FecControlState>>,
+}
+
+#[derive(Debug)]
+struct FecControlState {
+    current: FecSetting,
+    pending: Option<FecSetting>,
+}
+
+impl FecControl {
+    pub fn new(initial: FecSetting) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FecControlState {
+                current: initial,
+                pending: None,
+            })),
+        }
+    }
+
+    /// Ask for a new setting (the latest request wins)
+    pub fn request(&self, setting: FecSetting) {
+        self.state.lock().pending = Some(setting);
+    }
+
+    /// Setting waiting to be applied, if any
+    pub fn take_request(&self) -> Option<FecSetting> {
+        self.state.lock().pending.take()
+    }
+
+    /// Record the setting now in effect
+    pub fn applied(&self, setting: FecSetting) {
+        self.state.lock().current = setting;
+    }
+
+    /// Setting in effect, or the one about to be
+    pub fn current(&self) -> FecSetting {
+        let state = self.state.lock();
+        state.pending.unwrap_or(state.current)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_fec_control_requests() {
+        let initial = FecSetting {
+            enabled: true,
+            redundancy: 10,
+        };
+        let control = FecControl::new(initial);
+        assert_eq!(control.take_request(), None);
+
+        let off = FecSetting {
+            enabled: false,
+            ..initial
+        };
+        control.clone().request(off);
+        assert_eq!(control.current(), off);
+        assert_eq!(control.take_request(), Some(off));
+        assert_eq!(control.current(), initial);
+        control.applied(off);
+        assert_eq!(control.current(), off);
+    }
+
     #[test]
     fn test_fec_encode_decode() {
         let mut encoder = FecEncoder::new(4, 2).unwrap();
@@ -352,5 +740,82 @@ mod tests {
                 assert!(!recovered.is_empty());
             }
         }
+
+        let stats = decoder.stats();
+        assert_eq!(stats.blocks_recovered, 1);
+        assert_eq!(stats.parity_packets_received, 2);
+    }
+
+    #[test]
+    fn test_fec_stats_wasted_parity() {
+        let mut encoder = FecEncoder::new(2, 1).unwrap();
+        let mut decoder = FecDecoder::new(2, 1).unwrap();
+
+        let packets: Vec<Packet> = (0..2)
+            .map(|i| Packet::new(PacketType::Video, 0, i, Bytes::from(vec![i as u8; 32])))
+            .collect();
+
+        let mut fec_packets = Vec::new();
+        for packet in &packets {
+            fec_packets.extend(encoder.encode(packet.clone()));
+        }
+
+        // Every data packet arrives, so the parity is overhead
+        for packet in &packets {
+            decoder.add_data_packet(packet.seq, packet.to_bytes().freeze());
+        }
+        for fec_packet in fec_packets {
+            assert!(decoder.add_fec_packet(fec_packet).is_none());
+        }
+
+        let stats = decoder.stats();
+        assert_eq!(stats.blocks_complete, 1);
+        assert_eq!(stats.blocks_recovered, 0);
+        assert_eq!(stats.parity_bytes_wasted, stats.parity_bytes_received);
+        assert_eq!(stats.wasted_parity_ratio(), 1.0);
+        assert_eq!(stats.recovery_rate(), 1.0);
+    }
+
+    #[test]
+    fn test_adaptive_fec_parity_for_loss() {
+        assert_eq!(AdaptiveFecController::parity_for_loss(10, 0.0), 0);
+        assert_eq!(AdaptiveFecController::parity_for_loss(10, 5.0), 3);
+        assert_eq!(AdaptiveFecController::parity_for_loss(10, 50.0), 5);
+    }
+
+    #[test]
+    fn test_adaptive_fec_controller() {
+        let mut controller = AdaptiveFecController::new(10, 0);
+        let start = Instant::now();
+        let mut stats = NetworkStats::default();
+
+        // 5% loss raises parity immediately
+        stats.packets_received = 950;
+        stats.packets_lost = 50;
+        let mut now = start;
+        let mut layout = None;
+        for _ in 0..10 {
+            now += Duration::from_secs(1);
+            stats.packets_received += 950;
+            stats.packets_lost += 50;
+            if let Some(l) = controller.update(&stats, now) {
+                // Proposed only, until committed
+                assert_ne!(controller.layout(), l);
+                controller.commit(l.1, now);
+                layout = Some(l);
+            }
+        }
+        assert_eq!(layout, Some((10, 3)));
+        assert_eq!(controller.redundancy_percent(), 30);
+
+        // Clean link eventually turns FEC off again
+        for _ in 0..30 {
+            now += Duration::from_secs(1);
+            stats.packets_received += 1000;
+            if let Some((_, parity_shards)) = controller.update(&stats, now) {
+                controller.commit(parity_shards, now);
+            }
+        }
+        assert_eq!(controller.layout(), (10, 0));
     }
 }
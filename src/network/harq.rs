@@ -0,0 +1,197 @@
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::fec::{FecDecoder, FecStats};
+use super::protocol::{ControlMessage, FecPacket, Packet};
+
+/// Hybrid ARQ counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HarqStats {
+    /// Shard requests sent for stalled blocks
+    pub shard_requests: u64,
+
+    /// Individual shards requested
+    pub shards_requested: u64,
+
+    /// Requested blocks that became decodable afterwards
+    pub blocks_rescued: u64,
+
+    /// Requested blocks that expired anyway
+    pub blocks_lost: u64,
+}
+
+struct ShardRequest {
+    sent_at: Instant,
+    attempts: u8,
+}
+
+/// Hybrid ARQ recovery coordinator layered on top of `FecDecoder`
+///
+/// FEC gets the first chance at every block. A block still incomplete once
+/// its deadline passes is rescued by asking the sender for just the missing
+/// data shards over the reliable stream.
+pub struct RecoveryCoordinator {
+    decoder: FecDecoder,
+    requests: HashMap<u32, ShardRequest>,
+    deadline: Duration,
+    max_attempts: u8,
+    stats: HarqStats,
+}
+
+impl RecoveryCoordinator {
+    /// Shortest time a block gets before shards are requested
+    const MIN_DEADLINE: Duration = Duration::from_millis(10);
+
+    /// Create a new coordinator
+    ///
+    /// # Arguments
+    /// * `decoder` - FEC decoder that keeps doing the first-line recovery
+    /// * `max_attempts` - Requests per block before leaving it to expire
+    pub fn new(decoder: FecDecoder, max_attempts: u8) -> Self {
+        Self {
+            decoder,
+            requests: HashMap::new(),
+            deadline: Duration::from_millis(30),
+            max_attempts,
+            stats: HarqStats::default(),
+        }
+    }
+
+    /// Update the block deadline from the measured round-trip time
+    ///
+    /// FEC parity for a block normally trails its data by well under one RTT,
+    /// so a block that is still stuck after two RTTs needs help.
+    pub fn set_rtt(&mut self, rtt: Duration) {
+        self.deadline = (rtt * 2).max(Self::MIN_DEADLINE);
+    }
+
+    /// Add a data packet (whole serialized packet)
+    pub fn add_data_packet(&mut self, seq: u32, data: Bytes) -> Option<Vec<Packet>> {
+        self.decoder.add_data_packet(seq, data)
+    }
+
+    /// Add a FEC parity packet
+    pub fn add_fec_packet(&mut self, fec_packet: FecPacket) -> Option<Vec<Packet>> {
+        self.decoder.add_fec_packet(fec_packet)
+    }
+
+    /// Produce shard requests for blocks past their deadline
+    pub fn poll(&mut self, now: Instant) -> Vec<ControlMessage> {
+        self.settle_requests();
+
+        let Some(cutoff) = now.checked_sub(self.deadline) else {
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        for (block_id, indices) in self.decoder.stalled_blocks(cutoff) {
+            if indices.is_empty() {
+                continue;
+            }
+
+            let request = self.requests.entry(block_id).or_insert(ShardRequest {
+                sent_at: cutoff,
+                attempts: 0,
+            });
+            let retry_due =
+                request.attempts == 0 || now.duration_since(request.sent_at) >= self.deadline;
+            if !retry_due || request.attempts >= self.max_attempts {
+                continue;
+            }
+            request.sent_at = now;
+            request.attempts += 1;
+
+            self.stats.shard_requests += 1;
+            self.stats.shards_requested += indices.len() as u64;
+            messages.push(ControlMessage::RequestShards { block_id, indices });
+        }
+
+        messages
+    }
+
+    /// Expire old blocks (called periodically)
+    pub fn cleanup(&mut self) {
+        self.decoder.cleanup();
+        self.settle_requests();
+    }
+
+    /// Count the outcome of requested blocks that are no longer pending
+    fn settle_requests(&mut self) {
+        let decoder = &self.decoder;
+        let stats = &mut self.stats;
+        self.requests
+            .retain(|block_id, _| match decoder.is_pending(*block_id) {
+                Some(true) => true,
+                Some(false) => {
+                    stats.blocks_rescued += 1;
+                    false
+                }
+                None => {
+                    stats.blocks_lost += 1;
+                    false
+                }
+            });
+    }
+
+    /// Switch the underlying decoder to a new block layout
+    pub fn reconfigure(&mut self, data_shards: usize, parity_shards: usize) -> Result<()> {
+        self.requests.clear();
+        self.decoder.reconfigure(data_shards, parity_shards)
+    }
+
+    /// Get hybrid ARQ statistics
+    pub fn stats(&self) -> HarqStats {
+        self.stats
+    }
+
+    /// Get FEC statistics of the underlying decoder
+    pub fn fec_stats(&self) -> FecStats {
+        self.decoder.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::protocol::PacketType;
+
+    fn packet(seq: u32) -> Packet {
+        Packet::new(PacketType::Video, 0, seq, Bytes::from(vec![seq as u8; 64]))
+    }
+
+    #[test]
+    fn test_requests_missing_shards_after_deadline() {
+        let decoder = FecDecoder::new(4, 1).unwrap();
+        let mut coordinator = RecoveryCoordinator::new(decoder, 2);
+        let start = Instant::now();
+
+        // Two of four data packets arrive, no parity: FEC can't help
+        coordinator.add_data_packet(0, packet(0).to_bytes().freeze());
+        coordinator.add_data_packet(3, packet(3).to_bytes().freeze());
+
+        // Before the deadline nothing is requested
+        assert!(coordinator.poll(start).is_empty());
+
+        let later = start + Duration::from_millis(100);
+        let requests = coordinator.poll(later);
+        assert_eq!(requests.len(), 1);
+        match &requests[0] {
+            ControlMessage::RequestShards { block_id, indices } => {
+                assert_eq!(*block_id, 0);
+                assert_eq!(indices, &vec![1, 2]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        // The sender answers; the block completes and counts as rescued
+        coordinator.add_data_packet(1, packet(1).to_bytes().freeze());
+        coordinator.add_data_packet(2, packet(2).to_bytes().freeze());
+        assert!(coordinator
+            .poll(later + Duration::from_millis(100))
+            .is_empty());
+        assert_eq!(coordinator.stats().blocks_rescued, 1);
+        assert_eq!(coordinator.stats().shards_requested, 2);
+    }
+}
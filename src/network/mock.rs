@@ -0,0 +1,146 @@
+/// A [`Connection`] that plays back a recorded session instead of a device
+///
+/// Packets come from a capture (see [`capture`](super::capture)), either as
+/// fast as they are asked for or, once [`paced`](MockConnection::paced), at
+/// the times they originally arrived. Control messages are kept for the
+/// caller to inspect.
+use super::capture::{CaptureHeader, CaptureReader};
+use super::{Connection, ControlMessage, NetworkError, NetworkStats, Packet, Result};
+use crate::config::VideoCodec;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub struct MockConnection {
+    header: CaptureHeader,
+    packets: VecDeque<(Duration, Packet)>,
+    /// Replay at the recorded times, counted from the first `recv`
+    paced: bool,
+    started: Option<Instant>,
+    sent: Vec<ControlMessage>,
+    stats: NetworkStats,
+}
+
+impl MockConnection {
+    pub fn new(header: CaptureHeader, packets: Vec<(Duration, Packet)>) -> Self {
+        Self {
+            header,
+            packets: packets.into(),
+            paced: false,
+            started: None,
+            sent: Vec::new(),
+            stats: NetworkStats::default(),
+        }
+    }
+
+    /// Connection replaying the capture at `path`
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let reader = CaptureReader::new(BufReader::new(std::fs::File::open(path)?))?;
+        let header = reader.header().clone();
+        let packets = reader.collect::<std::io::Result<Vec<_>>>()?;
+        Ok(Self::new(header, packets))
+    }
+
+    /// Hand out packets no earlier than they arrived in the recording
+    pub fn paced(mut self) -> Self {
+        self.paced = true;
+        self
+    }
+
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Control messages sent so far
+    pub fn sent(&self) -> &[ControlMessage] {
+        &self.sent
+    }
+
+    /// Packets not handed out yet
+    pub fn remaining(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+#[async_trait]
+impl Connection for MockConnection {
+    async fn connect(_addr: SocketAddr, _enable_audio: bool) -> Result<Self> {
+        Err(NetworkError::ConnectionFailed(
+            "A mock connection replays a capture, create it with MockConnection::from_file"
+                .to_string(),
+        ))
+    }
+
+    async fn recv(&mut self) -> Result<Packet> {
+        let (arrival, packet) = self
+            .packets
+            .pop_front()
+            .ok_or(NetworkError::ConnectionClosed)?;
+        if self.paced {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            tokio::time::sleep_until(started + arrival).await;
+        }
+        self.stats.bytes_received += packet.data.len() as u64;
+        self.stats.packets_received += 1;
+        Ok(packet)
+    }
+
+    async fn send_control(&mut self, msg: ControlMessage) -> Result<()> {
+        self.sent.push(msg);
+        Ok(())
+    }
+
+    fn stats(&self) -> NetworkStats {
+        self.stats
+    }
+
+    fn video_codec(&self) -> Option<VideoCodec> {
+        self.header.video_codec
+    }
+
+    fn device_name(&self) -> Option<&str> {
+        self.header.device_name.as_deref()
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.packets.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PacketType;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_replays_in_order_then_closes() {
+        let packets = (0..3)
+            .map(|i| {
+                let packet = Packet::new(PacketType::Video, i * 16_666, i as u32, Bytes::new());
+                (Duration::from_millis(i as u64 * 16), packet)
+            })
+            .collect();
+        let mut connection = MockConnection::new(CaptureHeader::default(), packets);
+        futures::executor::block_on(async {
+            for pts in [0, 16_666, 33_332] {
+                assert_eq!(connection.recv().await.unwrap().pts, pts);
+            }
+            assert!(matches!(
+                connection.recv().await,
+                Err(NetworkError::ConnectionClosed)
+            ));
+            connection
+                .send_control(ControlMessage::RequestKeyframe)
+                .await
+                .unwrap();
+        });
+        assert_eq!(connection.sent().len(), 1);
+        assert_eq!(connection.stats().packets_received, 3);
+    }
+}
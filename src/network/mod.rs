@@ -1,18 +1,48 @@
+use crate::config::{PerformanceConfig, VideoCodec};
 use async_trait::async_trait;
 use std::net::SocketAddr;
 use thiserror::Error;
 
+pub mod auth;
+pub mod bench;
+pub mod capture;
+pub mod control_queue;
+pub mod fanout;
 pub mod fec;
+pub mod harq;
+pub mod mock;
 pub mod negotiation;
 pub mod protocol;
 pub mod quic;
+pub mod relay;
+pub mod resolve;
+pub mod retransmit;
+pub mod session_cache;
+#[cfg(any(test, feature = "netsim"))]
+pub mod sim;
+pub mod switcher;
 pub mod tcp;
-
-pub use fec::{FecDecoder, FecEncoder};
+pub mod watchdog;
+
+pub use auth::{AuthMessage, SharedSecret};
+pub use bench::BenchReport;
+pub use control_queue::{ControlPriority, ControlQueue, ControlQueueStats};
+pub use fanout::{Fanout, FanoutReceiver};
+pub use fec::{AdaptiveFecController, FecControl, FecDecoder, FecEncoder, FecSetting, FecStats};
+pub use harq::{HarqStats, RecoveryCoordinator};
+pub use mock::MockConnection;
 pub use negotiation::{ConnectionNegotiator, ConnectionType, DeviceCapabilities};
-pub use protocol::{ControlMessage, Packet, PacketType};
+pub use protocol::{
+    CipherSuite, ControlMessage, DeviceMessage, FrameFlags, KeyAction, NackPacket, Packet,
+    PacketCipher, PacketType, TouchAction,
+};
 pub use quic::QuicConnection;
+pub use relay::Relay;
+pub use retransmit::{NackTracker, RetransmitStats};
+pub use session_cache::SessionCache;
+pub use switcher::TransportSwitcher;
 pub use tcp::TcpConnection;
+pub use watchdog::{StreamWatchdog, WatchdogAction};
 
 /// Network errors
 #[derive(Error, Debug)]
@@ -62,6 +92,39 @@ pub trait Connection: Send + Sync {
     /// Get network statistics
     fn stats(&self) -> NetworkStats;
 
+    /// Apply loss recovery and FEC tuning (no-op for reliable transports)
+    fn apply_performance_config(&mut self, _config: &PerformanceConfig) {}
+
+    /// Prove knowledge of the shared secret before any media flows
+    ///
+    /// Transports without an authentication step only log a warning.
+    async fn authenticate(&mut self, _secret: &SharedSecret) -> Result<()> {
+        tracing::warn!("Auth token ignored: this transport has no authentication step");
+        Ok(())
+    }
+
+    /// Video codec announced in the stream header, if the transport has one
+    fn video_codec(&self) -> Option<VideoCodec> {
+        None
+    }
+
+    /// Device name sent in the handshake, if the transport has one
+    fn device_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Change FEC mid-session, telling the sender about the new layout
+    async fn set_fec(&mut self, _setting: FecSetting) -> Result<()> {
+        Err(NetworkError::Protocol(
+            "FEC is not used on this transport".to_string(),
+        ))
+    }
+
+    /// Re-establish a dropped connection to the same server
+    async fn reconnect(&mut self) -> Result<()> {
+        Err(NetworkError::ConnectionClosed)
+    }
+
     /// Close the connection
     async fn close(&mut self) -> Result<()>;
 }
@@ -86,6 +149,24 @@ pub struct NetworkStats {
 
     /// Packets lost
     pub packets_lost: u64,
+
+    /// Connection migrations after a local address change (QUIC only)
+    pub migrations: u64,
+
+    /// Connections resumed with 0-RTT (QUIC only)
+    pub resumptions: u64,
+
+    /// Traffic key rotations (QUIC only)
+    pub key_updates: u64,
+
+    /// FEC effectiveness (zero when FEC is not in use)
+    pub fec: FecStats,
+
+    /// Selective retransmission counters (zero when NACK is not in use)
+    pub retransmit: RetransmitStats,
+
+    /// Hybrid ARQ shard retransmission counters
+    pub harq: HarqStats,
 }
 
 impl NetworkStats {
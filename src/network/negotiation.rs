@@ -28,6 +28,9 @@ pub struct DeviceCapabilities {
 
     /// Preferred connection mode
     pub preferred_mode: String, // "tcp" or "quic"
+
+    /// Can report vibration events (`DeviceMessage::Vibrate`)
+    pub haptics_supported: bool,
 }
 
 impl Default for DeviceCapabilities {
@@ -40,6 +43,7 @@ impl Default for DeviceCapabilities {
             max_bitrate: 20,
             audio_supported: true,
             preferred_mode: "tcp".to_string(),
+            haptics_supported: false,
         }
     }
 }
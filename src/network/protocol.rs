@@ -1,5 +1,10 @@
-use bytes::{Buf, Bytes, BytesMut};
+use crate::config::VideoCodec;
+use crate::video::nal;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Packet types in the protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -19,6 +24,9 @@ pub enum PacketType {
 
     /// Handshake/Capability negotiation
     Handshake = 0x05,
+
+    /// Negative acknowledgement (selective retransmit request)
+    Nack = 0x06,
 }
 
 impl TryFrom<u8> for PacketType {
@@ -31,11 +39,49 @@ impl TryFrom<u8> for PacketType {
             0x03 => Ok(PacketType::Control),
             0x04 => Ok(PacketType::Fec),
             0x05 => Ok(PacketType::Handshake),
+            0x06 => Ok(PacketType::Nack),
             _ => Err(()),
         }
     }
 }
 
+/// Flags the scrcpy frame header carries in the top bits of its PTS field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameFlags {
+    /// Codec config (SPS/PPS, audio headers) rather than a frame; has no PTS
+    pub config: bool,
+
+    /// Frame decodable on its own
+    pub keyframe: bool,
+}
+
+impl FrameFlags {
+    const CONFIG: u64 = 1 << 63;
+    const KEYFRAME: u64 = 1 << 62;
+    const PTS_MASK: u64 = Self::KEYFRAME - 1;
+
+    /// Split a raw header PTS field into its flags and the PTS (us)
+    pub fn split_pts(raw: u64) -> (Self, i64) {
+        let flags = Self {
+            config: raw & Self::CONFIG != 0,
+            keyframe: raw & Self::KEYFRAME != 0,
+        };
+        (flags, (raw & Self::PTS_MASK) as i64)
+    }
+
+    /// Raw header PTS field for `pts` with these flags
+    pub fn join_pts(self, pts: i64) -> u64 {
+        let mut raw = pts as u64 & Self::PTS_MASK;
+        if self.config {
+            raw |= Self::CONFIG;
+        }
+        if self.keyframe {
+            raw |= Self::KEYFRAME;
+        }
+        raw
+    }
+}
+
 /// Packet structure with PTS (Presentation Timestamp)
 #[derive(Debug, Clone)]
 pub struct Packet {
@@ -45,6 +91,9 @@ pub struct Packet {
     /// Presentation timestamp in microseconds
     pub pts: i64,
 
+    /// Frame header flags (sent in the PTS field's top bits)
+    pub flags: FrameFlags,
+
     /// Sequence number for ordering/loss detection
     pub seq: u32,
 
@@ -61,18 +110,30 @@ impl Packet {
         Self {
             packet_type,
             pts,
+            flags: FrameFlags::default(),
             seq,
             data,
         }
     }
 
+    /// Set the frame header flags
+    pub fn with_flags(mut self, flags: FrameFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// PTS field as sent, with the flags in its top bits
+    fn wire_pts(&self) -> i64 {
+        self.flags.join_pts(self.pts) as i64
+    }
+
     /// Serialize packet to bytes (for sending)
     pub fn to_bytes(&self) -> BytesMut {
         let mut buf = BytesMut::with_capacity(Self::HEADER_SIZE + self.data.len());
 
         // Write header
         buf.extend_from_slice(&[self.packet_type as u8]);
-        buf.extend_from_slice(&self.pts.to_le_bytes());
+        buf.extend_from_slice(&self.wire_pts().to_le_bytes());
         buf.extend_from_slice(&self.seq.to_le_bytes());
         buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
 
@@ -91,7 +152,7 @@ impl Packet {
         // Parse header
         let packet_type = PacketType::try_from(buf.get_u8()).map_err(|_| "Invalid packet type")?;
 
-        let pts = buf.get_i64_le();
+        let (flags, pts) = FrameFlags::split_pts(buf.get_u64_le());
         let seq = buf.get_u32_le();
         let len = buf.get_u32_le() as usize;
 
@@ -101,53 +162,165 @@ impl Packet {
 
         let data = buf.split_to(len);
 
-        Ok(Self::new(packet_type, pts, seq, data))
+        Ok(Self::new(packet_type, pts, seq, data).with_flags(flags))
+    }
+
+    /// Read one packet written with `to_bytes` from a byte stream
+    ///
+    /// Payloads over `max_len` bytes are refused before they are read.
+    pub async fn read_from<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        max_len: usize,
+    ) -> std::io::Result<Self> {
+        let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let mut buf = vec![0u8; Self::HEADER_SIZE];
+        reader.read_exact(&mut buf).await?;
+        let len = u32::from_le_bytes(buf[13..17].try_into().unwrap()) as usize;
+        if len > max_len {
+            return Err(invalid("Packet too large"));
+        }
+        buf.resize(Self::HEADER_SIZE + len, 0);
+        reader.read_exact(&mut buf[Self::HEADER_SIZE..]).await?;
+        Self::from_bytes(Bytes::from(buf)).map_err(invalid)
     }
 
     /// Check if this is a video keyframe (I-frame)
-    /// Detects H.264 NAL unit type 5 or H.265 NAL unit type 19/20
+    ///
+    /// Trusts the sender's flag, and looks for an IDR NAL unit when it is
+    /// unset: QUIC senders never set it.
     pub fn is_keyframe(&self) -> bool {
-        if self.packet_type != PacketType::Video {
-            return false;
-        }
+        self.packet_type == PacketType::Video && (self.flags.keyframe || self.has_idr_nal())
+    }
 
-        if self.data.len() < 5 {
-            return false;
-        }
+    /// Check the payload for an IDR NAL unit
+    /// Detects H.264 NAL unit type 5 or H.265 NAL unit type 19/20
+    ///
+    /// For senders that don't set the keyframe flag.
+    pub fn has_idr_nal(&self) -> bool {
+        self.packet_type == PacketType::Video
+            && (nal::has_idr(VideoCodec::H264, &self.data)
+                || nal::has_idr(VideoCodec::H265, &self.data))
+    }
+}
 
-        // Check for H.264 start code (00 00 00 01 or 00 00 01)
-        let has_start_code = (self.data.len() >= 4 && self.data[0..4] == [0, 0, 0, 1])
-            || (self.data.len() >= 3 && self.data[0..3] == [0, 0, 1]);
+/// Payload cipher agreed on during authentication
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CipherSuite {
+    /// Payloads travel as-is (QUIC still encrypts the transport itself)
+    #[default]
+    None = 0,
 
-        if !has_start_code {
-            return false;
-        }
+    /// AES-256-GCM per packet, for relays and other untrusted hops
+    Aes256Gcm = 1,
+}
 
-        // Find NAL unit header
-        let nal_start = if self.data[0..4] == [0, 0, 0, 1] {
-            4
-        } else {
-            3
+/// Per-packet AEAD for packet payloads
+///
+/// Sealed payloads are laid out as `nonce(12) | ciphertext | tag(16)`. The
+/// header fields (type, pts, seq) stay readable for FEC and loss tracking but
+/// are bound to the payload as associated data, so they cannot be altered
+/// without the packet failing to open. Each direction has its own key.
+pub struct PacketCipher {
+    seal_key: LessSafeKey,
+    open_key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl PacketCipher {
+    /// Bytes a sealed payload grows by
+    pub const OVERHEAD: usize = aead::NONCE_LEN + 16;
+
+    /// Create from the 32-byte keys for each direction
+    ///
+    /// # Arguments
+    /// * `seal_key` - Key for packets this side sends
+    /// * `open_key` - Key for packets the peer sends
+    pub fn new(seal_key: &[u8], open_key: &[u8]) -> Result<Self, &'static str> {
+        let key = |bytes| {
+            UnboundKey::new(&aead::AES_256_GCM, bytes)
+                .map(LessSafeKey::new)
+                .map_err(|_| "Invalid AES-256-GCM key")
         };
-        if self.data.len() <= nal_start {
-            return false;
-        }
+        Ok(Self {
+            seal_key: key(seal_key)?,
+            open_key: key(open_key)?,
+            rng: SystemRandom::new(),
+        })
+    }
 
-        let nal_header = self.data[nal_start];
+    /// Encrypt a packet's payload
+    pub fn seal(&self, packet: &Packet) -> Result<Packet, &'static str> {
+        // Random nonces: no counter state to lose across reconnects
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| "Random nonce failed")?;
 
-        // H.264: NAL unit type 5 (IDR)
-        let h264_idr = (nal_header & 0x1F) == 5;
+        let mut in_out = packet.data.to_vec();
+        self.seal_key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(Self::aad(packet)),
+                &mut in_out,
+            )
+            .map_err(|_| "Encryption failed")?;
 
-        // H.265: NAL unit type 19 (IDR_W_RADL) or 20 (IDR_N_LP)
-        let h265_idr = {
-            let nal_type = (nal_header >> 1) & 0x3F;
-            nal_type == 19 || nal_type == 20
-        };
+        let mut data = BytesMut::with_capacity(aead::NONCE_LEN + in_out.len());
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&in_out);
+        Ok(
+            Packet::new(packet.packet_type, packet.pts, packet.seq, data.freeze())
+                .with_flags(packet.flags),
+        )
+    }
 
-        h264_idr || h265_idr
+    /// Decrypt and authenticate a packet's payload
+    pub fn open(&self, packet: &Packet) -> Result<Packet, &'static str> {
+        if packet.data.len() < Self::OVERHEAD {
+            return Err("Encrypted payload too short");
+        }
+        let (nonce, sealed) = packet.data.split_at(aead::NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce")?;
+
+        let mut in_out = sealed.to_vec();
+        let plain = self
+            .open_key
+            .open_in_place(nonce, Aad::from(Self::aad(packet)), &mut in_out)
+            .map_err(|_| "Payload failed authentication")?;
+        Ok(Packet::new(
+            packet.packet_type,
+            packet.pts,
+            packet.seq,
+            Bytes::copy_from_slice(plain),
+        )
+        .with_flags(packet.flags))
+    }
+
+    fn aad(packet: &Packet) -> [u8; 13] {
+        let mut aad = [0u8; 13];
+        aad[0] = packet.packet_type as u8;
+        aad[1..9].copy_from_slice(&packet.wire_pts().to_le_bytes());
+        aad[9..13].copy_from_slice(&packet.seq.to_le_bytes());
+        aad
     }
 }
 
+/// Touch/pointer action for injected touch events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TouchAction {
+    Down,
+    Up,
+    Move,
+}
+
+/// Key action for injected key events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyAction {
+    Down,
+    Up,
+}
+
 /// Control messages sent between client and server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ControlMessage {
@@ -172,6 +345,70 @@ pub enum ControlMessage {
 
     /// Acknowledge receipt
     Ack { seq: u32 },
+
+    /// Change the FEC block layout (`parity_shards == 0` disables FEC)
+    SetFecRedundancy { data_shards: u8, parity_shards: u8 },
+
+    /// Resend specific data shards of a FEC block that FEC alone couldn't repair
+    RequestShards { block_id: u32, indices: Vec<u8> },
+
+    /// Inject a touch event at device coordinates
+    InjectTouch {
+        action: TouchAction,
+        pointer_id: u64,
+        x: u32,
+        y: u32,
+        /// Video frame size the coordinates refer to
+        width: u32,
+        height: u32,
+        pressure: f32,
+    },
+
+    /// Inject an Android key event
+    InjectKeycode {
+        action: KeyAction,
+        keycode: u32,
+        repeat: u32,
+        metastate: u32,
+    },
+
+    /// Inject a scroll event at device coordinates
+    InjectScroll {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        hscroll: f32,
+        vscroll: f32,
+    },
+
+    /// Set the device clipboard (optionally pasting it)
+    SetClipboard { text: String, paste: bool },
+
+    /// Opt in to (or out of) `DeviceMessage::Vibrate` events
+    SetHapticsEnabled(bool),
+
+    /// Re-attach to a running session after a reconnect instead of restarting it
+    ResumeSession { session_id: u64 },
+
+    /// Rotate the device display 90 degrees counterclockwise
+    RotateDevice,
+
+    /// Create a virtual HID device on the device through `/dev/uhid`
+    UhidCreate {
+        id: u16,
+        name: String,
+        report_desc: Vec<u8>,
+    },
+
+    /// Input report for a device made with `UhidCreate`
+    UhidInput { id: u16, data: Vec<u8> },
+
+    /// Remove a device made with `UhidCreate`
+    UhidDestroy { id: u16 },
+
+    /// Launch an app by package name, force-stopping it first if asked
+    StartApp { package: String, force_stop: bool },
 }
 
 impl ControlMessage {
@@ -185,6 +422,447 @@ impl ControlMessage {
     pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
         bincode::deserialize(data)
     }
+
+    /// Whether this injects user input on the device
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            ControlMessage::InjectTouch { .. }
+                | ControlMessage::InjectKeycode { .. }
+                | ControlMessage::InjectScroll { .. }
+                | ControlMessage::SetClipboard { .. }
+                | ControlMessage::UhidInput { .. }
+        )
+    }
+
+    /// Encode for scrcpy-server's control socket (`ControlMessageReader.java`)
+    ///
+    /// Big-endian, a type byte first. `None` for messages scrcpy-server has no
+    /// equivalent for (bitrate, FEC, sessions...), which only our QUIC server
+    /// understands.
+    pub fn to_scrcpy_bytes(&self) -> Option<Bytes> {
+        let mut buf = BytesMut::new();
+        match self {
+            ControlMessage::InjectKeycode {
+                action,
+                keycode,
+                repeat,
+                metastate,
+            } => {
+                buf.put_u8(scrcpy::INJECT_KEYCODE);
+                buf.put_u8(match action {
+                    KeyAction::Down => 0,
+                    KeyAction::Up => 1,
+                });
+                buf.put_u32(*keycode);
+                buf.put_u32(*repeat);
+                buf.put_u32(*metastate);
+            }
+            ControlMessage::InjectTouch {
+                action,
+                pointer_id,
+                x,
+                y,
+                width,
+                height,
+                pressure,
+            } => {
+                buf.put_u8(scrcpy::INJECT_TOUCH_EVENT);
+                // Android's MotionEvent actions
+                buf.put_u8(match action {
+                    TouchAction::Down => 0,
+                    TouchAction::Up => 1,
+                    TouchAction::Move => 2,
+                });
+                buf.put_u64(*pointer_id);
+                scrcpy::put_position(&mut buf, *x, *y, *width, *height);
+                buf.put_u16(scrcpy::u16_fixed_point(*pressure));
+                // Action button and button state: none, this is a finger
+                buf.put_u32(0);
+                buf.put_u32(0);
+            }
+            ControlMessage::InjectScroll {
+                x,
+                y,
+                width,
+                height,
+                hscroll,
+                vscroll,
+            } => {
+                buf.put_u8(scrcpy::INJECT_SCROLL_EVENT);
+                scrcpy::put_position(&mut buf, *x, *y, *width, *height);
+                // The server scales these back up by 16 steps
+                buf.put_i16(scrcpy::i16_fixed_point(hscroll / 16.0));
+                buf.put_i16(scrcpy::i16_fixed_point(vscroll / 16.0));
+                buf.put_u32(0);
+            }
+            ControlMessage::SetClipboard { text, paste } => {
+                buf.put_u8(scrcpy::SET_CLIPBOARD);
+                // Sequence 0: no acknowledgement wanted
+                buf.put_u64(0);
+                buf.put_u8(*paste as u8);
+                let text = scrcpy::truncate(text, scrcpy::CLIPBOARD_TEXT_MAX);
+                buf.put_u32(text.len() as u32);
+                buf.put_slice(text.as_bytes());
+            }
+            ControlMessage::RotateDevice => buf.put_u8(scrcpy::ROTATE_DEVICE),
+            ControlMessage::UhidCreate {
+                id,
+                name,
+                report_desc,
+            } => {
+                buf.put_u8(scrcpy::UHID_CREATE);
+                buf.put_u16(*id);
+                // Vendor and product id: none
+                buf.put_u16(0);
+                buf.put_u16(0);
+                let name = scrcpy::truncate(name, scrcpy::UHID_NAME_MAX);
+                buf.put_u8(name.len() as u8);
+                buf.put_slice(name.as_bytes());
+                buf.put_u16(report_desc.len() as u16);
+                buf.put_slice(report_desc);
+            }
+            ControlMessage::UhidInput { id, data } => {
+                buf.put_u8(scrcpy::UHID_INPUT);
+                buf.put_u16(*id);
+                buf.put_u16(data.len() as u16);
+                buf.put_slice(data);
+            }
+            ControlMessage::UhidDestroy { id } => {
+                buf.put_u8(scrcpy::UHID_DESTROY);
+                buf.put_u16(*id);
+            }
+            ControlMessage::StartApp {
+                package,
+                force_stop,
+            } => {
+                buf.put_u8(scrcpy::START_APP);
+                // A leading '+' asks the server to force-stop the app first
+                let name = format!("{}{}", if *force_stop { "+" } else { "" }, package);
+                let name = scrcpy::truncate(&name, u8::MAX as usize);
+                buf.put_u8(name.len() as u8);
+                buf.put_slice(name.as_bytes());
+            }
+            // Restarting the encoder is the only way to get a keyframe out of it
+            ControlMessage::RequestKeyframe => buf.put_u8(scrcpy::RESET_VIDEO),
+            _ => return None,
+        }
+        Some(buf.freeze())
+    }
+
+    /// Parse the message at the start of data in `to_scrcpy_bytes` form, for
+    /// our own servers that stand in for scrcpy-server (the relay)
+    ///
+    /// Returns it with the number of bytes it took, or `None` while `buf`
+    /// holds only part of it.
+    pub fn from_scrcpy_bytes(buf: &[u8]) -> Result<Option<(Self, usize)>, &'static str> {
+        let Some((&kind, mut rest)) = buf.split_first() else {
+            return Ok(None);
+        };
+        match Self::read_scrcpy(kind, &mut rest) {
+            Some(message) => Ok(Some((message?, buf.len() - rest.len()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Body of a scrcpy control message of type `kind`, `None` if `rest` is
+    /// too short
+    fn read_scrcpy(kind: u8, rest: &mut &[u8]) -> Option<Result<Self, &'static str>> {
+        use scrcpy::take;
+        let message = match kind {
+            scrcpy::INJECT_KEYCODE => {
+                let mut body = take(rest, 13)?;
+                let action = match body.get_u8() {
+                    0 => KeyAction::Down,
+                    1 => KeyAction::Up,
+                    _ => return Some(Err("Unknown key action")),
+                };
+                ControlMessage::InjectKeycode {
+                    action,
+                    keycode: body.get_u32(),
+                    repeat: body.get_u32(),
+                    metastate: body.get_u32(),
+                }
+            }
+            scrcpy::INJECT_TOUCH_EVENT => {
+                let mut body = take(rest, 31)?;
+                let action = match body.get_u8() {
+                    0 => TouchAction::Down,
+                    1 => TouchAction::Up,
+                    2 => TouchAction::Move,
+                    _ => return Some(Err("Unknown touch action")),
+                };
+                let pointer_id = body.get_u64();
+                let (x, y, width, height) = scrcpy::get_position(&mut body);
+                ControlMessage::InjectTouch {
+                    action,
+                    pointer_id,
+                    x,
+                    y,
+                    width,
+                    height,
+                    pressure: scrcpy::from_u16_fixed_point(body.get_u16()),
+                }
+            }
+            scrcpy::INJECT_SCROLL_EVENT => {
+                let mut body = take(rest, 20)?;
+                let (x, y, width, height) = scrcpy::get_position(&mut body);
+                ControlMessage::InjectScroll {
+                    x,
+                    y,
+                    width,
+                    height,
+                    hscroll: scrcpy::from_i16_fixed_point(body.get_i16()) * 16.0,
+                    vscroll: scrcpy::from_i16_fixed_point(body.get_i16()) * 16.0,
+                }
+            }
+            scrcpy::SET_CLIPBOARD => {
+                let mut head = take(rest, 13)?;
+                head.advance(8); // Sequence
+                let paste = head.get_u8() != 0;
+                let len = head.get_u32() as usize;
+                if len > scrcpy::CLIPBOARD_TEXT_MAX {
+                    return Some(Err("Clipboard text too long"));
+                }
+                let text = String::from_utf8_lossy(take(rest, len)?).into_owned();
+                ControlMessage::SetClipboard { text, paste }
+            }
+            scrcpy::ROTATE_DEVICE => ControlMessage::RotateDevice,
+            scrcpy::UHID_CREATE => {
+                let mut head = take(rest, 7)?;
+                let id = head.get_u16();
+                head.advance(4); // Vendor and product id
+                let name_len = head.get_u8() as usize;
+                let name = String::from_utf8_lossy(take(rest, name_len)?).into_owned();
+                let desc_len = take(rest, 2)?.get_u16() as usize;
+                ControlMessage::UhidCreate {
+                    id,
+                    name,
+                    report_desc: take(rest, desc_len)?.to_vec(),
+                }
+            }
+            scrcpy::UHID_INPUT => {
+                let mut head = take(rest, 4)?;
+                let id = head.get_u16();
+                let len = head.get_u16() as usize;
+                ControlMessage::UhidInput {
+                    id,
+                    data: take(rest, len)?.to_vec(),
+                }
+            }
+            scrcpy::UHID_DESTROY => ControlMessage::UhidDestroy {
+                id: take(rest, 2)?.get_u16(),
+            },
+            scrcpy::START_APP => {
+                let len = take(rest, 1)?[0] as usize;
+                let name = String::from_utf8_lossy(take(rest, len)?).into_owned();
+                match name.strip_prefix('+') {
+                    Some(package) => ControlMessage::StartApp {
+                        package: package.to_string(),
+                        force_stop: true,
+                    },
+                    None => ControlMessage::StartApp {
+                        package: name,
+                        force_stop: false,
+                    },
+                }
+            }
+            scrcpy::RESET_VIDEO => ControlMessage::RequestKeyframe,
+            _ => return Some(Err("Unknown control message type")),
+        };
+        Some(Ok(message))
+    }
+}
+
+/// scrcpy-server's binary control protocol (version 3.3.3)
+mod scrcpy {
+    use bytes::{Buf, BufMut, BytesMut};
+
+    // Control message types
+    pub const INJECT_KEYCODE: u8 = 0;
+    pub const INJECT_TOUCH_EVENT: u8 = 2;
+    pub const INJECT_SCROLL_EVENT: u8 = 3;
+    pub const SET_CLIPBOARD: u8 = 9;
+    pub const ROTATE_DEVICE: u8 = 11;
+    pub const UHID_CREATE: u8 = 12;
+    pub const UHID_INPUT: u8 = 13;
+    pub const UHID_DESTROY: u8 = 14;
+    pub const START_APP: u8 = 16;
+    pub const RESET_VIDEO: u8 = 17;
+
+    // Device message types
+    pub const DEVICE_CLIPBOARD: u8 = 0;
+    pub const DEVICE_ACK_CLIPBOARD: u8 = 1;
+    pub const DEVICE_UHID_OUTPUT: u8 = 2;
+
+    /// Largest message either side accepts
+    pub const MESSAGE_MAX: usize = 1 << 18;
+    /// Clipboard text that fits a `SET_CLIPBOARD` message
+    pub const CLIPBOARD_TEXT_MAX: usize = MESSAGE_MAX - 14;
+    /// Longest UHID device name the server takes
+    pub const UHID_NAME_MAX: usize = 127;
+
+    /// Point in a frame of the given size; the server drops events for
+    /// another size than the one it currently streams
+    pub fn put_position(buf: &mut BytesMut, x: u32, y: u32, width: u32, height: u32) {
+        buf.put_u32(x);
+        buf.put_u32(y);
+        buf.put_u16(width.min(u16::MAX as u32) as u16);
+        buf.put_u16(height.min(u16::MAX as u32) as u16);
+    }
+
+    /// `(x, y, width, height)` written by `put_position`
+    pub fn get_position(buf: &mut &[u8]) -> (u32, u32, u32, u32) {
+        (
+            buf.get_u32(),
+            buf.get_u32(),
+            buf.get_u16() as u32,
+            buf.get_u16() as u32,
+        )
+    }
+
+    /// The next `len` bytes of `buf`, `None` if it is shorter
+    pub fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if buf.len() < len {
+            return None;
+        }
+        let (head, tail) = buf.split_at(len);
+        *buf = tail;
+        Some(head)
+    }
+
+    /// `[0, 1]` as 16-bit fixed point, 1.0 being 0xffff
+    pub fn u16_fixed_point(value: f32) -> u16 {
+        let value = value.clamp(0.0, 1.0);
+        if value == 1.0 {
+            u16::MAX
+        } else {
+            (value * 65536.0) as u16
+        }
+    }
+
+    /// `[-1, 1]` as signed 16-bit fixed point, 1.0 being 0x7fff
+    pub fn i16_fixed_point(value: f32) -> i16 {
+        let value = value.clamp(-1.0, 1.0);
+        if value == 1.0 {
+            i16::MAX
+        } else {
+            (value * 32768.0) as i16
+        }
+    }
+
+    /// Inverse of `u16_fixed_point`
+    pub fn from_u16_fixed_point(value: u16) -> f32 {
+        if value == u16::MAX {
+            1.0
+        } else {
+            value as f32 / 65536.0
+        }
+    }
+
+    /// Inverse of `i16_fixed_point`
+    pub fn from_i16_fixed_point(value: i16) -> f32 {
+        if value == i16::MAX {
+            1.0
+        } else {
+            value as f32 / 32768.0
+        }
+    }
+
+    /// Longest prefix of `text` within `max` bytes, cut at a character boundary
+    pub fn truncate(text: &str, max: usize) -> &str {
+        if text.len() <= max {
+            return text;
+        }
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        &text[..end]
+    }
+}
+
+/// Events reported by the device, carried in `Control` packets from the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeviceMessage {
+    /// The device vibrated (only sent after `ControlMessage::SetHapticsEnabled(true)`)
+    Vibrate {
+        duration_ms: u32,
+        /// Android amplitude 1-255, 0 for the device default
+        amplitude: u8,
+    },
+
+    /// The device clipboard changed
+    Clipboard { text: String },
+
+    /// A `SetClipboard` with this sequence number was applied
+    AckClipboard { sequence: u64 },
+
+    /// Output report for a `ControlMessage::UhidCreate` device (keyboard LEDs)
+    UhidOutput { id: u16, data: Vec<u8> },
+}
+
+impl DeviceMessage {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Bytes, bincode::Error> {
+        let data = bincode::serialize(self)?;
+        Ok(Bytes::from(data))
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+
+    /// Parse the message at the start of scrcpy-server's control socket data
+    ///
+    /// Returns it with the number of bytes it took, or `None` while `buf`
+    /// holds only part of it.
+    pub fn from_scrcpy_bytes(buf: &[u8]) -> Result<Option<(Self, usize)>, &'static str> {
+        let Some((&kind, mut rest)) = buf.split_first() else {
+            return Ok(None);
+        };
+        let message = match kind {
+            scrcpy::DEVICE_CLIPBOARD => {
+                if rest.len() < 4 {
+                    return Ok(None);
+                }
+                let len = rest.get_u32() as usize;
+                if len > scrcpy::MESSAGE_MAX {
+                    return Err("Clipboard text too long");
+                }
+                if rest.len() < len {
+                    return Ok(None);
+                }
+                let text = String::from_utf8_lossy(&rest[..len]).into_owned();
+                rest.advance(len);
+                DeviceMessage::Clipboard { text }
+            }
+            scrcpy::DEVICE_ACK_CLIPBOARD => {
+                if rest.len() < 8 {
+                    return Ok(None);
+                }
+                DeviceMessage::AckClipboard {
+                    sequence: rest.get_u64(),
+                }
+            }
+            scrcpy::DEVICE_UHID_OUTPUT => {
+                if rest.len() < 4 {
+                    return Ok(None);
+                }
+                let id = rest.get_u16();
+                let len = rest.get_u16() as usize;
+                if rest.len() < len {
+                    return Ok(None);
+                }
+                let data = rest[..len].to_vec();
+                rest.advance(len);
+                DeviceMessage::UhidOutput { id, data }
+            }
+            _ => return Err("Unknown device message type"),
+        };
+        Ok(Some((message, buf.len() - rest.len())))
+    }
 }
 
 /// FEC (Forward Error Correction) packet
@@ -246,3 +924,271 @@ impl FecPacket {
         Ok(Self::new(block_id, index, data_count, parity_count, data))
     }
 }
+
+/// NACK packet listing sequence numbers the receiver is missing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NackPacket {
+    /// Missing sequence numbers
+    pub seqs: Vec<u32>,
+}
+
+impl NackPacket {
+    /// NACK header size: count(2) = 2 bytes
+    pub const HEADER_SIZE: usize = 2;
+
+    /// Maximum sequence numbers carried by one NACK
+    pub const MAX_SEQS: usize = 256;
+
+    /// Create new NACK packet
+    pub fn new(seqs: Vec<u32>) -> Self {
+        Self { seqs }
+    }
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> BytesMut {
+        let count = self.seqs.len().min(Self::MAX_SEQS);
+        let mut buf = BytesMut::with_capacity(Self::HEADER_SIZE + count * 4);
+        buf.extend_from_slice(&(count as u16).to_le_bytes());
+        for seq in &self.seqs[..count] {
+            buf.extend_from_slice(&seq.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(mut buf: Bytes) -> Result<Self, &'static str> {
+        if buf.len() < Self::HEADER_SIZE {
+            return Err("NACK packet too short");
+        }
+
+        let count = buf.get_u16_le() as usize;
+        if buf.remaining() < count * 4 {
+            return Err("Incomplete NACK packet");
+        }
+
+        let seqs = (0..count).map(|_| buf.get_u32_le()).collect();
+        Ok(Self::new(seqs))
+    }
+
+    /// Wrap into a protocol packet for sending
+    pub fn into_packet(self) -> Packet {
+        Packet::new(PacketType::Nack, 0, 0, self.to_bytes().freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_flags_in_pts_field() {
+        // scrcpy: config packets carry only the flag, keyframes flag + PTS
+        assert_eq!(
+            FrameFlags::split_pts(1 << 63),
+            (
+                FrameFlags {
+                    config: true,
+                    keyframe: false
+                },
+                0
+            )
+        );
+        let raw = (1 << 62) | 1_234_567;
+        let (flags, pts) = FrameFlags::split_pts(raw);
+        assert!(flags.keyframe && !flags.config);
+        assert_eq!(pts, 1_234_567);
+        assert_eq!(flags.join_pts(pts), raw);
+
+        let packet = Packet::new(
+            PacketType::Video,
+            42,
+            7,
+            Bytes::from_static(b"\0\0\0\x01\x65"),
+        )
+        .with_flags(flags);
+        let parsed = Packet::from_bytes(packet.to_bytes().freeze()).unwrap();
+        assert_eq!((parsed.pts, parsed.flags), (42, flags));
+        assert!(parsed.is_keyframe());
+        // Unflagged packets are judged by their NAL units
+        assert!(Packet::new(PacketType::Video, 0, 0, packet.data.clone()).is_keyframe());
+        let p_frame = Bytes::from_static(b"\0\0\0\x01\x41\x9a");
+        assert!(!Packet::new(PacketType::Video, 0, 0, p_frame).is_keyframe());
+        // Unflagged: the IDR slice is found behind the parameter sets
+        let unflagged = Packet::new(
+            PacketType::Video,
+            0,
+            0,
+            Bytes::from_static(b"\0\0\0\x01\x67\x42\0\0\x01\x68\xce\0\0\x01\x65\x88"),
+        );
+        assert!(unflagged.has_idr_nal());
+    }
+
+    #[test]
+    fn test_control_message_tags_are_stable() {
+        // bincode tags variants by declaration order; new variants go last
+        let tag = |msg: ControlMessage| {
+            u32::from_le_bytes(msg.to_bytes().unwrap()[..4].try_into().unwrap())
+        };
+        assert_eq!(tag(ControlMessage::SetBitrate(8)), 0);
+        assert_eq!(tag(ControlMessage::RequestKeyframe), 3);
+        assert_eq!(tag(ControlMessage::Ack { seq: 1 }), 5);
+    }
+
+    #[test]
+    fn test_scrcpy_control_encoding() {
+        let key = ControlMessage::InjectKeycode {
+            action: KeyAction::Up,
+            keycode: 66,
+            repeat: 0,
+            metastate: 0x1000,
+        };
+        assert_eq!(
+            key.to_scrcpy_bytes().unwrap().as_ref(),
+            b"\x00\x01\0\0\0\x42\0\0\0\0\0\0\x10\0"
+        );
+
+        let touch = ControlMessage::InjectTouch {
+            action: TouchAction::Move,
+            pointer_id: 1,
+            x: 100,
+            y: 200,
+            width: 1080,
+            height: 2400,
+            pressure: 1.0,
+        }
+        .to_scrcpy_bytes()
+        .unwrap();
+        assert_eq!(touch.len(), 32);
+        assert_eq!(&touch[..10], b"\x02\x02\0\0\0\0\0\0\0\x01");
+        // x, y, width, height, pressure
+        assert_eq!(
+            &touch[10..24],
+            b"\0\0\0\x64\0\0\0\xc8\x04\x38\x09\x60\xff\xff"
+        );
+
+        let clipboard = ControlMessage::SetClipboard {
+            text: "hé".to_string(),
+            paste: true,
+        };
+        assert_eq!(
+            clipboard.to_scrcpy_bytes().unwrap().as_ref(),
+            b"\x09\0\0\0\0\0\0\0\0\x01\0\0\0\x03h\xc3\xa9"
+        );
+        assert_eq!(
+            ControlMessage::RotateDevice
+                .to_scrcpy_bytes()
+                .unwrap()
+                .as_ref(),
+            [11]
+        );
+        let start = ControlMessage::StartApp {
+            package: "org.example".to_string(),
+            force_stop: true,
+        };
+        assert_eq!(
+            start.to_scrcpy_bytes().unwrap().as_ref(),
+            b"\x10\x0c+org.example"
+        );
+        let report = ControlMessage::UhidInput {
+            id: 1,
+            data: vec![0, 0, 4],
+        };
+        assert_eq!(
+            report.to_scrcpy_bytes().unwrap().as_ref(),
+            b"\x0d\0\x01\0\x03\0\0\x04"
+        );
+        assert!(ControlMessage::SetBitrate(8).to_scrcpy_bytes().is_none());
+    }
+
+    #[test]
+    fn test_scrcpy_control_roundtrip() {
+        let messages = [
+            ControlMessage::InjectKeycode {
+                action: KeyAction::Down,
+                keycode: 29,
+                repeat: 1,
+                metastate: 0,
+            },
+            ControlMessage::InjectTouch {
+                action: TouchAction::Move,
+                pointer_id: u64::MAX - 2,
+                x: 540,
+                y: 1200,
+                width: 1080,
+                height: 2400,
+                pressure: 0.5,
+            },
+            ControlMessage::InjectScroll {
+                x: 10,
+                y: 20,
+                width: 1080,
+                height: 2400,
+                hscroll: 0.0,
+                vscroll: -2.0,
+            },
+            ControlMessage::SetClipboard {
+                text: "héllo".to_string(),
+                paste: true,
+            },
+            ControlMessage::RotateDevice,
+            ControlMessage::UhidCreate {
+                id: 1,
+                name: "Keyboard".to_string(),
+                report_desc: vec![0x05, 0x01],
+            },
+            ControlMessage::UhidInput {
+                id: 1,
+                data: vec![0, 0, 4],
+            },
+            ControlMessage::UhidDestroy { id: 1 },
+            ControlMessage::StartApp {
+                package: "org.example".to_string(),
+                force_stop: true,
+            },
+            ControlMessage::RequestKeyframe,
+        ];
+        for message in messages {
+            let bytes = message.to_scrcpy_bytes().unwrap();
+            let (parsed, len) = ControlMessage::from_scrcpy_bytes(&bytes).unwrap().unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(parsed.to_scrcpy_bytes().unwrap(), bytes);
+            // Incomplete until the last byte is there
+            assert!(ControlMessage::from_scrcpy_bytes(&bytes[..len - 1])
+                .unwrap()
+                .is_none());
+        }
+        assert!(ControlMessage::from_scrcpy_bytes(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_scrcpy_device_messages() {
+        let data = b"\x00\0\0\0\x02hi\x01\0\0\0\0\0\0\0\x07";
+        assert_eq!(
+            DeviceMessage::from_scrcpy_bytes(data).unwrap(),
+            Some((
+                DeviceMessage::Clipboard {
+                    text: "hi".to_string()
+                },
+                7
+            ))
+        );
+        assert_eq!(
+            DeviceMessage::from_scrcpy_bytes(&data[7..]).unwrap(),
+            Some((DeviceMessage::AckClipboard { sequence: 7 }, 9))
+        );
+        assert_eq!(
+            DeviceMessage::from_scrcpy_bytes(b"\x02\0\x01\0\x01\x02").unwrap(),
+            Some((
+                DeviceMessage::UhidOutput {
+                    id: 1,
+                    data: vec![2]
+                },
+                6
+            ))
+        );
+        // Cut short: wait for the rest
+        assert_eq!(DeviceMessage::from_scrcpy_bytes(&data[..6]).unwrap(), None);
+        assert_eq!(DeviceMessage::from_scrcpy_bytes(&[]).unwrap(), None);
+        assert!(DeviceMessage::from_scrcpy_bytes(b"\x7f").is_err());
+    }
+}
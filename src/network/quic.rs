@@ -1,31 +1,98 @@
-use super::protocol::FecPacket;
+use super::auth::{AuthMessage, SharedSecret};
+use super::fec::{AdaptiveFecController, FecDecoder, FecSetting};
+use super::harq::RecoveryCoordinator;
+use super::protocol::{FecPacket, NackPacket, PacketCipher};
+use super::retransmit::NackTracker;
+use super::session_cache::SessionCache;
 use super::{Connection, ControlMessage, NetworkError, NetworkStats, Packet, PacketType, Result};
+use crate::config::{LossRecovery, PerformanceConfig};
 use async_trait::async_trait;
 use bytes::Bytes;
-use quinn::{ClientConfig, Endpoint, RecvStream, SendStream, VarInt};
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, Endpoint, RecvStream, SendStream, VarInt, ZeroRttAccepted};
+use rustls::client::Resumption;
+use rustls_platform_verifier::BuilderVerifierExt;
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
 /// QUIC connection for wireless (WiFi) connectivity
 pub struct QuicConnection {
     connection: quinn::Connection,
-    #[allow(dead_code)]
+    endpoint: Endpoint,
+    addr: SocketAddr,
+    /// Connections that resumed with 0-RTT
+    resumptions: u64,
+    /// Identifies this mirroring session to the server across reconnects
+    session_id: u64,
+    last_key_update: Instant,
+    key_updates: u64,
+    /// Secret to re-authenticate with after a reconnect
+    secret: Option<SharedSecret>,
+    /// Payload cipher negotiated during authentication
+    cipher: Option<PacketCipher>,
+    /// Migrations performed by the route monitor
+    migrations: Arc<AtomicU64>,
+    migration_monitor: JoinHandle<()>,
     recv_stream: Arc<Mutex<Option<RecvStream>>>,
     send_stream: Arc<Mutex<Option<SendStream>>>,
     stats: NetworkStats,
-    fec_decoder: FecDecoder,
+    /// FEC decoding, plus shard retransmission in hybrid mode
+    recovery: RecoveryCoordinator,
+    /// Present when FEC redundancy follows measured loss
+    fec_controller: Option<AdaptiveFecController>,
+    /// Packets rebuilt by FEC, handed out before reading new datagrams
+    recovered: VecDeque<Packet>,
+    nack_tracker: NackTracker,
+    loss_recovery: LossRecovery,
+    nack_max_rtt_ms: u32,
     last_seq: u32,
 }
 
 impl QuicConnection {
+    /// Data packets per FEC block
+    const FEC_DATA_SHARDS: usize = 10;
+
+    /// Parity packets per FEC block (10% redundancy)
+    const FEC_PARITY_SHARDS: usize = 1;
+
+    /// Requests per missing packet before it is given up
+    const NACK_MAX_ATTEMPTS: u8 = 3;
+
+    /// Outstanding gaps tracked for retransmission
+    const NACK_MAX_MISSING: usize = 512;
+
+    /// Shard requests per stalled FEC block in hybrid mode
+    const HARQ_MAX_ATTEMPTS: u8 = 2;
+
+    /// Time allowed for each authentication step
+    const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// How long a reconnect may take before it counts as failed
+    const RECONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Lifetime of one set of traffic keys before a QUIC key update
+    const KEY_UPDATE_INTERVAL: Duration = Duration::from_secs(300);
+
+    /// How often the local route to the server is re-checked
+    const MIGRATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
     /// Create a new QUIC connection
     pub async fn new(addr: SocketAddr) -> Result<Self> {
-        // Configure QUIC client
-        let mut client_config = ClientConfig::try_with_platform_verifier()
-            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        Self::for_device(addr, &addr.to_string()).await
+    }
+
+    /// Create a new QUIC connection, resuming with the sessions of `device`
+    ///
+    /// `device` is a stable identifier such as the adb serial, so the session
+    /// state stays with the device when its address changes.
+    pub async fn for_device(addr: SocketAddr, device: &str) -> Result<Self> {
+        // Configure QUIC client, resuming with this device's cached session tickets
+        let mut client_config = Self::client_config(SessionCache::for_device(device))?;
 
         // Configure transport for low latency
         let mut transport_config = quinn::TransportConfig::default();
@@ -52,28 +119,233 @@ impl QuicConnection {
         client_config.transport_config(Arc::new(transport_config));
 
         // Create endpoint
-        let mut endpoint = Endpoint::client("0.0.0.0:0".parse().unwrap())
+        let mut endpoint = Endpoint::client(Self::unspecified_for(addr))
             .map_err(|e| NetworkError::Quic(e.to_string()))?;
 
         endpoint.set_default_client_config(client_config);
 
-        // Connect to server
-        let connection = endpoint
-            .connect(addr, "localhost")
-            .map_err(|e| NetworkError::Quic(e.to_string()))?
-            .await
-            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        // Connect to server (0-RTT when an earlier session left a ticket)
+        let (connection, zero_rtt) = Self::dial(&endpoint, addr).await?;
+        let resumed = zero_rtt.is_some();
+        if let Some(accepted) = zero_rtt {
+            tokio::spawn(async move {
+                if !accepted.await {
+                    tracing::debug!("Server rejected 0-RTT data, full handshake completed");
+                }
+            });
+        }
+
+        let session_id = ring::rand::generate::<[u8; 8]>(&ring::rand::SystemRandom::new())
+            .map(|id| u64::from_le_bytes(id.expose()))
+            .map_err(|_| NetworkError::Quic("Failed to generate session id".into()))?;
+
+        // Follow the route to the server across WiFi roams / interface switches
+        let migrations = Arc::new(AtomicU64::new(0));
+        let migration_monitor = tokio::spawn(Self::monitor_migration(
+            endpoint.clone(),
+            addr,
+            migrations.clone(),
+        ));
 
         Ok(Self {
             connection,
+            endpoint,
+            addr,
+            resumptions: resumed as u64,
+            session_id,
+            last_key_update: Instant::now(),
+            key_updates: 0,
+            secret: None,
+            cipher: None,
+            migrations,
+            migration_monitor,
             recv_stream: Arc::new(Mutex::new(None)),
             send_stream: Arc::new(Mutex::new(None)),
             stats: NetworkStats::default(),
-            fec_decoder: FecDecoder::new(10), // 10% redundancy
+            recovery: RecoveryCoordinator::new(
+                FecDecoder::new(Self::FEC_DATA_SHARDS, Self::FEC_PARITY_SHARDS)
+                    .map_err(|e| NetworkError::Protocol(e.to_string()))?,
+                Self::HARQ_MAX_ATTEMPTS,
+            ),
+            fec_controller: None,
+            recovered: VecDeque::new(),
+            nack_tracker: NackTracker::new(Self::NACK_MAX_ATTEMPTS, Self::NACK_MAX_MISSING),
+            loss_recovery: LossRecovery::Fec,
+            nack_max_rtt_ms: 40,
             last_seq: 0,
         })
     }
 
+    /// TLS 1.3 client config with early data and a per-device ticket store
+    fn client_config(sessions: Arc<SessionCache>) -> Result<ClientConfig> {
+        let mut tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .and_then(|builder| builder.with_platform_verifier())
+        .map_err(|e| NetworkError::Quic(e.to_string()))?
+        .with_no_client_auth();
+
+        tls_config.enable_early_data = true;
+        tls_config.resumption = Resumption::store(sessions);
+
+        let crypto = QuicClientConfig::try_from(tls_config)
+            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        Ok(ClientConfig::new(Arc::new(crypto)))
+    }
+
+    /// Dial a bare connection and report the handshake RTT
+    ///
+    /// No streams are opened, so the server never starts streaming to it. A
+    /// throwaway ticket store leaves the device's tickets to real sessions.
+    pub async fn probe_rtt(addr: SocketAddr) -> Result<Duration> {
+        let mut endpoint = Endpoint::client(Self::unspecified_for(addr))
+            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        endpoint.set_default_client_config(Self::client_config(Arc::new(
+            SessionCache::with_hint_path(None),
+        ))?);
+
+        let connection = endpoint
+            .connect(addr, "localhost")
+            .map_err(|e| NetworkError::Quic(e.to_string()))?
+            .await
+            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        let rtt = connection.rtt();
+        connection.close(VarInt::from_u32(0), b"probe");
+        Ok(rtt)
+    }
+
+    /// Open a connection, using 0-RTT if a session ticket is available
+    ///
+    /// With 0-RTT the connection is usable immediately and the returned
+    /// future tells whether the server accepted the early data once the
+    /// handshake completes. On rejection quinn falls back to the full
+    /// handshake and only streams opened before it completed must be reopened.
+    /// Tickets are single-use, so every resumption consumes the one the
+    /// previous session left and the server issues fresh ones.
+    async fn dial(
+        endpoint: &Endpoint,
+        addr: SocketAddr,
+    ) -> Result<(quinn::Connection, Option<ZeroRttAccepted>)> {
+        let connecting = endpoint
+            .connect(addr, "localhost")
+            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+
+        match connecting.into_0rtt() {
+            Ok((connection, accepted)) => {
+                tracing::info!("Resumed QUIC session with 0-RTT");
+                Ok((connection, Some(accepted)))
+            }
+            Err(connecting) => {
+                let connection = connecting
+                    .await
+                    .map_err(|e| NetworkError::Quic(e.to_string()))?;
+                Ok((connection, None))
+            }
+        }
+    }
+
+    /// Dial again and ask the server to re-attach the running session
+    ///
+    /// The resume request rides in the 0-RTT flight, so the server can pick
+    /// the stream back up as soon as its side of the handshake is done instead
+    /// of restarting the encoder.
+    async fn resume(&mut self) -> Result<()> {
+        let (connection, zero_rtt) = Self::dial(&self.endpoint, self.addr).await?;
+        self.connection = connection;
+
+        // Payload keys are per session, authentication derives new ones
+        self.cipher = None;
+
+        // Streams belonged to the old connection
+        *self.recv_stream.lock().await = None;
+        *self.send_stream.lock().await = None;
+
+        let resume = ControlMessage::ResumeSession {
+            session_id: self.session_id,
+        };
+        match zero_rtt {
+            Some(accepted) => {
+                let early = self.send_control(resume.clone()).await;
+                if early.is_ok() && accepted.await {
+                    self.resumptions += 1;
+                } else {
+                    // Early data was dropped, repeat the request over 1-RTT
+                    *self.send_stream.lock().await = None;
+                    self.send_control(resume).await?;
+                }
+            }
+            None => self.send_control(resume).await?,
+        }
+        Ok(())
+    }
+
+    /// Switch to fresh traffic keys once the current ones have been in use long enough
+    fn rotate_keys(&mut self) {
+        if self.last_key_update.elapsed() < Self::KEY_UPDATE_INTERVAL {
+            return;
+        }
+        self.connection.force_key_update();
+        self.last_key_update = Instant::now();
+        self.key_updates += 1;
+        tracing::debug!("Rotated QUIC traffic keys ({} updates)", self.key_updates);
+    }
+
+    /// Local IP the OS would use to reach `remote` (no packets are sent)
+    fn route_local_ip(remote: SocketAddr) -> Option<IpAddr> {
+        let socket = Self::bind_socket(remote).ok()?;
+        socket.connect(remote).ok()?;
+        socket.local_addr().ok().map(|a| a.ip())
+    }
+
+    /// Ephemeral local address of the same family as `remote`
+    fn unspecified_for(remote: SocketAddr) -> SocketAddr {
+        let unspecified: IpAddr = if remote.is_ipv6() {
+            Ipv6Addr::UNSPECIFIED.into()
+        } else {
+            Ipv4Addr::UNSPECIFIED.into()
+        };
+        SocketAddr::new(unspecified, 0)
+    }
+
+    /// Bind a fresh UDP socket on an ephemeral port of the right address family
+    fn bind_socket(remote: SocketAddr) -> std::io::Result<UdpSocket> {
+        UdpSocket::bind(Self::unspecified_for(remote))
+    }
+
+    /// Rebind the endpoint whenever the local route to the server changes
+    ///
+    /// quinn migrates the connection to the new socket's path on its own
+    /// (the server sees a new 4-tuple and validates it), so the streams and
+    /// datagram flow survive moving between access points or onto Ethernet.
+    async fn monitor_migration(endpoint: Endpoint, remote: SocketAddr, migrations: Arc<AtomicU64>) {
+        let mut current = Self::route_local_ip(remote);
+        let mut interval = tokio::time::interval(Self::MIGRATION_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let route = Self::route_local_ip(remote);
+            if route.is_none() || route == current {
+                continue; // No route at all (mid-roam) or nothing changed
+            }
+
+            let rebound = Self::bind_socket(remote).and_then(|socket| endpoint.rebind(socket));
+            match rebound {
+                Ok(()) => {
+                    tracing::info!(
+                        "Local address changed ({:?} -> {:?}), migrated QUIC connection",
+                        current,
+                        route
+                    );
+                    current = route;
+                    migrations.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => tracing::warn!("Failed to rebind QUIC endpoint: {}", e),
+            }
+        }
+    }
+
     /// Receive data via unreliable datagram (lowest latency for video)
     async fn recv_datagram(&self) -> Result<Bytes> {
         self.connection
@@ -83,7 +355,6 @@ impl QuicConnection {
     }
 
     /// Receive data via reliable stream (for control messages)
-    #[allow(dead_code)]
     async fn recv_stream_data(&self, buf: &mut [u8]) -> Result<usize> {
         let mut stream_lock = self.recv_stream.lock().await;
 
@@ -106,6 +377,51 @@ impl QuicConnection {
             .ok_or(NetworkError::ConnectionClosed)
     }
 
+    /// Read one whole packet from the reliable stream
+    async fn recv_stream_packet(&self) -> Result<Packet> {
+        let mut header = [0u8; Packet::HEADER_SIZE];
+        self.read_stream_exact(&mut header).await?;
+
+        let len = u32::from_le_bytes(header[13..17].try_into().unwrap()) as usize;
+        if len > 64 * 1024 {
+            return Err(NetworkError::Protocol(format!(
+                "Stream packet too large: {} bytes",
+                len
+            )));
+        }
+
+        let mut data = header.to_vec();
+        data.resize(Packet::HEADER_SIZE + len, 0);
+        self.read_stream_exact(&mut data[Packet::HEADER_SIZE..])
+            .await?;
+        Packet::from_bytes(Bytes::from(data)).map_err(|e| NetworkError::Protocol(e.to_string()))
+    }
+
+    async fn read_stream_exact(&self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.recv_stream_data(&mut buf[filled..]).await?;
+        }
+        Ok(())
+    }
+
+    /// Exchange one authentication message with the server
+    async fn auth_exchange(&self, msg: Option<AuthMessage>) -> Result<AuthMessage> {
+        let step = async {
+            if let Some(msg) = msg {
+                let packet = msg
+                    .into_packet()
+                    .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+                self.send_stream_data(&packet.to_bytes()).await?;
+            }
+            let reply = self.recv_stream_packet().await?;
+            AuthMessage::from_packet(&reply).map_err(|e| NetworkError::Protocol(e.to_string()))
+        };
+        tokio::time::timeout(Self::AUTH_TIMEOUT, step)
+            .await
+            .map_err(|_| NetworkError::Timeout)?
+    }
+
     /// Send control message via reliable stream
     async fn send_stream_data(&self, data: &[u8]) -> Result<()> {
         let mut stream_lock = self.send_stream.lock().await;
@@ -122,10 +438,11 @@ impl QuicConnection {
 
         let stream = stream_lock.as_mut().unwrap();
 
-        stream
-            .write_all(data)
-            .await
-            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+        if let Err(e) = stream.write_all(data).await {
+            // Reopen on the next send (e.g. a stream opened in rejected 0-RTT)
+            *stream_lock = None;
+            return Err(NetworkError::Quic(e.to_string()));
+        }
 
         Ok(())
     }
@@ -147,6 +464,95 @@ impl QuicConnection {
         // Estimate bandwidth (simplified)
         // In a real implementation, we'd track bytes over time
         self.stats.bandwidth_mbps = (stats.path.cwnd as f64 * 8.0) / (self.stats.rtt_ms * 125.0);
+
+        self.stats.migrations = self.migrations.load(Ordering::Relaxed);
+        self.stats.resumptions = self.resumptions;
+        self.stats.key_updates = self.key_updates;
+        self.stats.fec = self.recovery.fec_stats();
+        self.recovery.set_rtt(stats.path.rtt);
+        self.stats.harq = self.recovery.stats();
+
+        self.nack_tracker.set_rtt(stats.path.rtt);
+        self.stats.retransmit = self.nack_tracker.stats();
+    }
+
+    /// Strategy in effect right now (`Auto` resolved against the current RTT)
+    fn active_loss_recovery(&self) -> LossRecovery {
+        self.loss_recovery
+            .resolve(self.stats.rtt_ms, self.nack_max_rtt_ms)
+    }
+
+    /// Let the adaptive controller retune FEC and tell the sender about it
+    async fn adapt_fec(&mut self) {
+        if !matches!(
+            self.active_loss_recovery(),
+            LossRecovery::Fec | LossRecovery::Hybrid
+        ) {
+            return;
+        }
+        let Some(controller) = self.fec_controller.as_mut() else {
+            return;
+        };
+        let Some((data_shards, parity_shards)) = controller.update(&self.stats, Instant::now())
+        else {
+            return;
+        };
+
+        let msg = ControlMessage::SetFecRedundancy {
+            data_shards: data_shards as u8,
+            parity_shards: parity_shards as u8,
+        };
+        // Keep the old layout until the sender knows, so a failed send is retried
+        if let Err(e) = self.send_control(msg).await {
+            tracing::warn!("Failed to announce FEC change: {}", e);
+            return;
+        }
+        if let Some(controller) = self.fec_controller.as_mut() {
+            controller.commit(parity_shards, Instant::now());
+        }
+        if let Err(e) = self.recovery.reconfigure(data_shards, parity_shards) {
+            tracing::warn!("Failed to reconfigure FEC decoder: {}", e);
+        }
+    }
+
+    /// Ask the sender for shards of FEC blocks that are stuck past their deadline
+    async fn request_stalled_shards(&mut self) {
+        for msg in self.recovery.poll(Instant::now()) {
+            if let Err(e) = self.send_control(msg).await {
+                tracing::warn!("Failed to request FEC shards: {}", e);
+                break;
+            }
+        }
+    }
+
+    /// Decrypt media and control payloads once a cipher is negotiated
+    ///
+    /// Packets that fail to open are dropped instead of failing the connection.
+    fn open_payload(&self, packet: Packet) -> Option<Packet> {
+        let Some(cipher) = &self.cipher else {
+            return Some(packet);
+        };
+        if !matches!(
+            packet.packet_type,
+            PacketType::Video | PacketType::Audio | PacketType::Control
+        ) {
+            return Some(packet);
+        }
+        match cipher.open(&packet) {
+            Ok(packet) => Some(packet),
+            Err(e) => {
+                tracing::warn!("Dropping packet {}: {}", packet.seq, e);
+                None
+            }
+        }
+    }
+
+    /// Send a NACK to the server over the reliable stream
+    async fn send_nack(&self, nack: NackPacket) {
+        let packet = nack.into_packet();
+        if let Err(e) = self.send_stream_data(&packet.to_bytes()).await {
+            tracing::warn!("Failed to send NACK: {}", e);
+        }
     }
 }
 
@@ -157,41 +563,74 @@ impl Connection for QuicConnection {
     }
 
     async fn recv(&mut self) -> Result<Packet> {
-        // Receive datagram (used for video/audio - low latency, loss-tolerant)
-        let data = self.recv_datagram().await?;
+        loop {
+            // Hand out packets rebuilt by FEC first
+            if let Some(packet) = self.recovered.pop_front() {
+                match self.open_payload(packet) {
+                    Some(packet) => return Ok(packet),
+                    None => continue,
+                }
+            }
 
-        // Try to parse as packet
-        let packet =
-            Packet::from_bytes(data.clone()).map_err(|e| NetworkError::Protocol(e.to_string()))?;
+            // Receive datagram (used for video/audio - low latency, loss-tolerant)
+            let data = self.recv_datagram().await?;
 
-        // Check for packet loss
-        if packet.seq > self.last_seq + 1 {
-            let lost = packet.seq - self.last_seq - 1;
-            self.stats.packets_lost += lost as u64;
-        }
-        self.last_seq = packet.seq;
-
-        // Update stats
-        self.stats.bytes_received += data.len() as u64;
-        self.stats.packets_received += 1;
-        self.update_stats();
-
-        // Handle FEC if this is a FEC packet
-        if packet.packet_type == PacketType::Fec {
-            // Decode FEC packet and try to recover lost packets
-            if let Some(recovered) = self.fec_decoder.process_fec(&packet.data) {
-                // Return recovered packet
-                return Ok(recovered);
-            } else {
-                // FEC packet processed but no recovery yet, wait for next packet
-                return self.recv().await;
+            // Try to parse as packet
+            let packet = Packet::from_bytes(data.clone())
+                .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+
+            // Check for packet loss (retransmitted packets arrive behind last_seq)
+            if packet.seq > self.last_seq + 1 {
+                let lost = packet.seq - self.last_seq - 1;
+                self.stats.packets_lost += lost as u64;
+            }
+            self.last_seq = self.last_seq.max(packet.seq);
+
+            // Update stats
+            self.stats.bytes_received += data.len() as u64;
+            self.stats.packets_received += 1;
+
+            // Handle FEC if this is a FEC packet
+            if packet.packet_type == PacketType::Fec {
+                // Decode FEC packet and try to recover lost packets
+                if let Ok(fec_packet) = FecPacket::from_bytes(packet.data.clone()) {
+                    if let Some(recovered) = self.recovery.add_fec_packet(fec_packet) {
+                        self.recovered.extend(recovered);
+                    }
+                }
+                self.update_stats();
+                // FEC packet processed, wait for next packet
+                continue;
             }
-        }
 
-        // Add to FEC decoder for potential recovery
-        self.fec_decoder.add_packet(packet.seq, packet.data.clone());
+            // Add to FEC decoder for potential recovery (shards are whole serialized packets)
+            if let Some(recovered) = self.recovery.add_data_packet(packet.seq, data) {
+                self.recovered.extend(recovered);
+            }
+            self.recovery.cleanup();
+
+            // Request missing data when retransmission is part of the active strategy
+            match self.active_loss_recovery() {
+                LossRecovery::Nack => {
+                    let now = Instant::now();
+                    if let Some(nack) = self.nack_tracker.on_packet(packet.seq, now) {
+                        self.send_nack(nack).await;
+                    }
+                    if let Some(nack) = self.nack_tracker.poll(now) {
+                        self.send_nack(nack).await;
+                    }
+                }
+                LossRecovery::Hybrid => self.request_stalled_shards().await,
+                _ => {}
+            }
+            self.update_stats();
+            self.rotate_keys();
+            self.adapt_fec().await;
 
-        Ok(packet)
+            if let Some(packet) = self.open_payload(packet) {
+                return Ok(packet);
+            }
+        }
     }
 
     async fn send_control(&mut self, msg: ControlMessage) -> Result<()> {
@@ -199,99 +638,126 @@ impl Connection for QuicConnection {
             .to_bytes()
             .map_err(|e| NetworkError::Protocol(e.to_string()))?;
 
-        // Send control messages via reliable stream
-        self.send_stream_data(&data).await
+        // Control messages are framed as packets on the reliable stream, like
+        // NACKs and handshakes, and sealed once a cipher was negotiated
+        let mut packet = Packet::new(PacketType::Control, 0, 0, data);
+        if let Some(cipher) = &self.cipher {
+            packet = cipher
+                .seal(&packet)
+                .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+        }
+        self.send_stream_data(&packet.to_bytes()).await
     }
 
     fn stats(&self) -> NetworkStats {
         self.stats
     }
 
-    async fn close(&mut self) -> Result<()> {
-        self.connection
-            .close(VarInt::from_u32(0), b"client shutdown");
-        Ok(())
+    fn apply_performance_config(&mut self, config: &PerformanceConfig) {
+        tracing::info!(
+            "Loss recovery: {:?} (NACK below {}ms RTT), adaptive FEC: {}",
+            config.loss_recovery,
+            config.nack_max_rtt_ms,
+            config.adaptive_fec
+        );
+        self.loss_recovery = config.loss_recovery;
+        self.nack_max_rtt_ms = config.nack_max_rtt_ms;
+
+        self.fec_controller = if config.adaptive_fec {
+            let controller =
+                AdaptiveFecController::new(Self::FEC_DATA_SHARDS, config.fec_redundancy);
+            let (data_shards, parity_shards) = controller.layout();
+            if let Err(e) = self.recovery.reconfigure(data_shards, parity_shards) {
+                tracing::warn!("Failed to apply initial FEC layout: {}", e);
+            }
+            Some(controller)
+        } else {
+            None
+        };
     }
-}
-
-/// FEC (Forward Error Correction) decoder using Reed-Solomon
-/// Allows recovery of lost packets without retransmission
-struct FecDecoder {
-    #[allow(dead_code)]
-    redundancy_percent: u8,
-    blocks: HashMap<u32, FecBlock>,
-    last_cleanup: Instant,
-}
 
-struct FecBlock {
-    #[allow(dead_code)]
-    block_id: u32,
-    #[allow(dead_code)]
-    data_packets: HashMap<u8, Bytes>,
-    parity_packets: HashMap<u8, Bytes>,
-    #[allow(dead_code)]
-    data_count: u8,
-    #[allow(dead_code)]
-    parity_count: u8,
-    created_at: Instant,
-}
+    async fn set_fec(&mut self, setting: FecSetting) -> Result<()> {
+        let parity_shards = if setting.enabled {
+            AdaptiveFecController::parity_for_redundancy(
+                Self::FEC_DATA_SHARDS,
+                setting.redundancy as f64,
+            )
+        } else {
+            0
+        };
+        self.send_control(ControlMessage::SetFecRedundancy {
+            data_shards: Self::FEC_DATA_SHARDS as u8,
+            parity_shards: parity_shards as u8,
+        })
+        .await?;
+        self.recovery
+            .reconfigure(Self::FEC_DATA_SHARDS, parity_shards)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
 
-impl FecDecoder {
-    fn new(redundancy_percent: u8) -> Self {
-        Self {
-            redundancy_percent,
-            blocks: HashMap::new(),
-            last_cleanup: Instant::now(),
-        }
+        // The user's choice sticks instead of being retuned from loss
+        self.fec_controller = None;
+        tracing::info!(
+            "FEC set to {} parity per {} data packets",
+            parity_shards,
+            Self::FEC_DATA_SHARDS
+        );
+        Ok(())
     }
 
-    /// Add a data packet to the FEC decoder
-    fn add_packet(&mut self, _seq: u32, _data: Bytes) {
-        // In a real implementation, we'd group packets into blocks
-        // and store them for FEC recovery
+    async fn authenticate(&mut self, secret: &SharedSecret) -> Result<()> {
+        let challenge = self.auth_exchange(None).await?;
+        let (response, server_nonce, client_nonce) = secret
+            .respond(&challenge)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
 
-        // Cleanup old blocks every 5 seconds
-        if self.last_cleanup.elapsed() > Duration::from_secs(5) {
-            self.cleanup_old_blocks();
+        let verdict = self.auth_exchange(Some(response)).await?;
+        secret
+            .verify_accept(&server_nonce, &client_nonce, &verdict)
+            .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+        self.cipher = secret
+            .client_cipher(&server_nonce, &client_nonce)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+
+        self.secret = Some(secret.clone());
+        if self.cipher.is_some() {
+            tracing::info!("Authenticated with server, payloads encrypted with AES-256-GCM");
+        } else {
+            tracing::info!("Authenticated with server");
         }
+        Ok(())
     }
 
-    /// Process a FEC packet and attempt to recover lost packets
-    fn process_fec(&mut self, fec_data: &Bytes) -> Option<Packet> {
-        // Parse FEC packet
-        let fec_packet = FecPacket::from_bytes(fec_data.clone()).ok()?;
-
-        // Get or create FEC block
-        let block = self
-            .blocks
-            .entry(fec_packet.block_id)
-            .or_insert_with(|| FecBlock {
-                block_id: fec_packet.block_id,
-                data_packets: HashMap::new(),
-                parity_packets: HashMap::new(),
-                data_count: fec_packet.data_count,
-                parity_count: fec_packet.parity_count,
-                created_at: Instant::now(),
-            });
+    async fn reconnect(&mut self) -> Result<()> {
+        self.connection
+            .close(VarInt::from_u32(0), b"client reconnecting");
 
-        // Store parity packet
-        block
-            .parity_packets
-            .insert(fec_packet.index, fec_packet.data);
+        tokio::time::timeout(Self::RECONNECT_TIMEOUT, self.resume())
+            .await
+            .map_err(|_| NetworkError::Timeout)??;
+        self.last_key_update = Instant::now();
 
-        // Try to recover using Reed-Solomon
-        // This is a simplified placeholder - real implementation would use
-        // reed-solomon-erasure crate to recover lost packets
+        // Sequence tracking belonged to the old connection
+        self.recovered.clear();
+        self.nack_tracker = NackTracker::new(Self::NACK_MAX_ATTEMPTS, Self::NACK_MAX_MISSING);
+        self.last_seq = 0;
 
-        // For now, return None (no recovery)
-        None
+        if let Some(secret) = self.secret.clone() {
+            self.authenticate(&secret).await?;
+        }
+        Ok(())
     }
 
-    /// Remove blocks older than 10 seconds
-    fn cleanup_old_blocks(&mut self) {
-        self.blocks
-            .retain(|_, block| block.created_at.elapsed() < Duration::from_secs(10));
-        self.last_cleanup = Instant::now();
+    async fn close(&mut self) -> Result<()> {
+        self.migration_monitor.abort();
+        self.connection
+            .close(VarInt::from_u32(0), b"client shutdown");
+        Ok(())
+    }
+}
+
+impl Drop for QuicConnection {
+    fn drop(&mut self) {
+        self.migration_monitor.abort();
     }
 }
 
@@ -301,14 +767,29 @@ mod tests {
 
     #[test]
     fn test_fec_decoder() {
-        let mut decoder = FecDecoder::new(10);
+        let mut decoder = FecDecoder::new(
+            QuicConnection::FEC_DATA_SHARDS,
+            QuicConnection::FEC_PARITY_SHARDS,
+        )
+        .unwrap();
 
         let data = Bytes::from_static(b"test");
-        decoder.add_packet(1, data.clone());
-        decoder.add_packet(2, data.clone());
+        decoder.add_data_packet(1, data.clone());
+        decoder.add_data_packet(2, data.clone());
+
+        // Cleanup should not expire recent blocks
+        decoder.cleanup();
+        let stats = decoder.stats();
+        assert_eq!(stats.blocks_unrecoverable, 0);
+        assert_eq!(stats.parity_packets_received, 0);
+    }
 
-        // Cleanup should not remove recent blocks
-        decoder.cleanup_old_blocks();
-        assert_eq!(decoder.blocks.len(), 0); // No blocks created without FEC packets
+    #[test]
+    fn test_route_local_ip_loopback() {
+        let remote: SocketAddr = "127.0.0.1:5556".parse().unwrap();
+        assert_eq!(
+            QuicConnection::route_local_ip(remote),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
     }
 }
@@ -0,0 +1,408 @@
+use super::{
+    AuthMessage, ControlMessage, Fanout, NetworkError, Packet, PacketCipher, PacketType, Result,
+    SharedSecret,
+};
+use crate::config::{AudioCodec, VideoCodec};
+use bytes::{Buf, Bytes, BytesMut};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// How long a client gets to open its second socket after the video one
+const SOCKET_CONNECT_WAIT: Duration = Duration::from_secs(1);
+
+/// How long a client gets to open a third socket right after the second
+const LAST_SOCKET_WAIT: Duration = Duration::from_millis(200);
+
+/// Largest control message accepted from a client (scrcpy-server's limit)
+const MAX_CONTROL_LEN: usize = 1 << 18;
+
+/// How long a client gets to answer the authentication challenge
+const AUTH_WAIT: Duration = Duration::from_secs(5);
+
+/// Largest authentication message accepted from a client
+const MAX_AUTH_LEN: usize = 1024;
+
+/// What the relay announces to clients, taken from the upstream connection
+#[derive(Debug, Clone)]
+pub struct StreamHeader {
+    pub device_name: Option<String>,
+    pub video_codec: VideoCodec,
+    /// None when the upstream session has no audio
+    pub audio_codec: Option<AudioCodec>,
+}
+
+impl StreamHeader {
+    /// Device name and video metadata, as a forward-tunnel server sends them
+    /// once every socket is connected
+    ///
+    /// The size fields are zero: clients take the size from the stream.
+    fn video_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; 64];
+        if let Some(name) = &self.device_name {
+            // Cut on a character boundary so the name stays valid UTF-8
+            let mut end = name.len().min(63);
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            bytes[..end].copy_from_slice(&name.as_bytes()[..end]);
+        }
+        bytes.extend_from_slice(&self.video_codec.codec_id().to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 8]);
+        bytes
+    }
+
+    /// Audio codec ID, 0 telling the client audio is off
+    fn audio_bytes(&self) -> [u8; 4] {
+        self.audio_codec
+            .map_or(0, |codec| codec.codec_id())
+            .to_be_bytes()
+    }
+}
+
+/// Serves one device stream to any number of TCP clients
+///
+/// Downstream clients connect as if the relay were the device server behind
+/// a forward tunnel, so a normal `--mode tcp` session can view it. With a
+/// secret, clients must also pass its challenge on the control socket before
+/// any media flows, and then exchange control messages as packets (sealed
+/// when a cipher was negotiated). Cheap to clone.
+#[derive(Clone)]
+pub struct Relay {
+    header: Arc<StreamHeader>,
+    fanout: Fanout,
+    /// Where the clients' control messages go; None makes them view-only
+    control_tx: Option<mpsc::UnboundedSender<ControlMessage>>,
+    /// Secret clients must prove; None lets anyone view
+    secret: Option<SharedSecret>,
+}
+
+impl Relay {
+    pub fn new(
+        header: StreamHeader,
+        control_tx: Option<mpsc::UnboundedSender<ControlMessage>>,
+    ) -> Self {
+        Self {
+            header: Arc::new(header),
+            fanout: Fanout::new(),
+            control_tx,
+            secret: None,
+        }
+    }
+
+    /// Only serve clients that prove `secret`, with its payload cipher
+    pub fn with_auth(mut self, secret: SharedSecret) -> Self {
+        self.secret = Some(secret);
+        self
+    }
+
+    /// Forward a packet from the device to every client
+    pub fn publish(&self, packet: &Packet) {
+        self.fanout.publish(packet);
+    }
+
+    /// Whether a client joined or fell behind and needs a keyframe
+    pub fn take_keyframe_request(&self) -> bool {
+        self.fanout.take_keyframe_request()
+    }
+
+    /// Accept clients on `listener` until `shutdown`
+    pub async fn serve(self, listener: TcpListener, shutdown: CancellationToken) -> Result<()> {
+        let mut next = None;
+        loop {
+            let (mut video, peer) = match next.take() {
+                Some(accepted) => accepted,
+                None => tokio::select! {
+                    _ = shutdown.cancelled() => return Ok(()),
+                    accepted = listener.accept() => accepted?,
+                },
+            };
+            // A forward-tunnel server proves the tunnel live with a dummy
+            // byte; only then do clients open their other sockets
+            if let Err(e) = video.write_all(&[0]).await {
+                tracing::warn!("Relay client {} dropped: {}", peer, e);
+                continue;
+            }
+            // Audio (if the client wants it), then control
+            let second = next_socket(&listener, peer, SOCKET_CONNECT_WAIT, &mut next).await?;
+            let third = match (&second, &next) {
+                (Some(_), None) => {
+                    next_socket(&listener, peer, LAST_SOCKET_WAIT, &mut next).await?
+                }
+                _ => None,
+            };
+            let (audio, control) = match (second, third) {
+                (Some(audio), Some(control)) => (Some(audio), control),
+                (Some(control), None) => (None, control),
+                (None, _) => {
+                    tracing::warn!("Relay client {} opened no control socket", peer);
+                    continue;
+                }
+            };
+
+            tracing::info!("Relay client {} connected", peer);
+            let (relay, shutdown) = (self.clone(), shutdown.clone());
+            tokio::spawn(async move {
+                match relay.stream_to(video, audio, control, shutdown).await {
+                    Ok(()) | Err(NetworkError::ConnectionClosed) => {
+                        tracing::info!("Relay client {} disconnected", peer)
+                    }
+                    Err(e) => tracing::warn!("Relay client {} dropped: {}", peer, e),
+                }
+            });
+        }
+    }
+
+    /// Send the handshake, then every packet, to one client
+    async fn stream_to(
+        &self,
+        video: TcpStream,
+        audio: Option<TcpStream>,
+        control: TcpStream,
+        shutdown: CancellationToken,
+    ) -> Result<()> {
+        video.set_nodelay(true)?;
+        let (_video_reader, mut video_writer) = video.into_split();
+        let mut audio_writer = audio.map(|stream| stream.into_split().1);
+        // Only authentication goes back on the control socket, but closing
+        // it would end the client's session
+        let (mut control_reader, mut control_writer) = control.into_split();
+        // Subscribe first so nothing published after the handshake is missed
+        let mut packets = self.fanout.subscribe();
+
+        video_writer.write_all(&self.header.video_bytes()).await?;
+        if let Some(writer) = &mut audio_writer {
+            writer.write_all(&self.header.audio_bytes()).await?;
+        }
+
+        let (controls, cipher) = match &self.secret {
+            Some(secret) => {
+                let cipher = authenticate(secret, &mut control_reader, &mut control_writer)
+                    .await?
+                    .map(Arc::new);
+                let controls = tokio::spawn(forward_sealed_controls(
+                    control_reader,
+                    self.control_tx.clone(),
+                    cipher.clone(),
+                ));
+                (controls, cipher)
+            }
+            None => (
+                tokio::spawn(forward_controls(control_reader, self.control_tx.clone())),
+                None,
+            ),
+        };
+        let result = loop {
+            let packet = tokio::select! {
+                _ = shutdown.cancelled() => break Ok(()),
+                packet = packets.recv() => match packet {
+                    Some(packet) => packet,
+                    None => break Ok(()),
+                },
+            };
+            let packet = match &cipher {
+                // The stream header carries no sequence number
+                Some(cipher) => {
+                    let plain = Packet::new(packet.packet_type, packet.pts, 0, packet.data)
+                        .with_flags(packet.flags);
+                    match cipher.seal(&plain) {
+                        Ok(sealed) => sealed,
+                        Err(e) => break Err(NetworkError::Protocol(e.to_string())),
+                    }
+                }
+                None => packet,
+            };
+            let written = match (packet.packet_type, &mut audio_writer) {
+                (PacketType::Video, _) => video_writer.write_all(&encode(&packet)).await,
+                (PacketType::Audio, Some(writer)) => writer.write_all(&encode(&packet)).await,
+                _ => Ok(()),
+            };
+            if let Err(e) = written {
+                break Err(e.into());
+            }
+        };
+        controls.abort();
+        result
+    }
+}
+
+/// A media packet as the server frames it: `[FLAGS|PTS 8][LEN 4][DATA]`
+fn encode(packet: &Packet) -> Bytes {
+    let mut buf = BytesMut::with_capacity(12 + packet.data.len());
+    buf.extend_from_slice(&packet.flags.join_pts(packet.pts).to_be_bytes());
+    buf.extend_from_slice(&(packet.data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&packet.data);
+    buf.freeze()
+}
+
+/// The client's next socket, if `peer` opens one within `wait`
+///
+/// A socket from another client is left in `next` for the accept loop.
+async fn next_socket(
+    listener: &TcpListener,
+    peer: SocketAddr,
+    wait: Duration,
+    next: &mut Option<(TcpStream, SocketAddr)>,
+) -> Result<Option<TcpStream>> {
+    match timeout(wait, listener.accept()).await {
+        Ok(Ok((stream, from))) if from.ip() == peer.ip() => Ok(Some(stream)),
+        Ok(Ok(other)) => {
+            *next = Some(other);
+            Ok(None)
+        }
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Challenge a client on its control socket, returning the session cipher
+async fn authenticate(
+    secret: &SharedSecret,
+    reader: &mut OwnedReadHalf,
+    writer: &mut OwnedWriteHalf,
+) -> Result<Option<PacketCipher>> {
+    let protocol = |e: anyhow::Error| NetworkError::Protocol(e.to_string());
+    let (challenge, server_nonce) = secret.challenge().map_err(protocol)?;
+    writer
+        .write_all(&challenge.into_packet().map_err(protocol)?.to_bytes())
+        .await?;
+
+    let response = timeout(AUTH_WAIT, Packet::read_from(reader, MAX_AUTH_LEN))
+        .await
+        .map_err(|_| NetworkError::Timeout)??;
+    let response = AuthMessage::from_packet(&response).map_err(protocol)?;
+    let verdict = secret.verify_response(&server_nonce, &response);
+    let accepted = verdict != AuthMessage::Rejected;
+    writer
+        .write_all(&verdict.into_packet().map_err(protocol)?.to_bytes())
+        .await?;
+
+    match response {
+        AuthMessage::Response {
+            nonce: client_nonce,
+            ..
+        } if accepted => secret
+            .server_cipher(&server_nonce, &client_nonce)
+            .map_err(protocol),
+        _ => Err(NetworkError::ConnectionFailed(
+            "Client failed to prove the auth token".to_string(),
+        )),
+    }
+}
+
+/// Pass an authenticated client's control messages on until it disconnects
+///
+/// Each message comes as a `Control` packet holding the bytes scrcpy-server
+/// would read, sealed when `cipher` is set.
+async fn forward_sealed_controls(
+    mut reader: OwnedReadHalf,
+    tx: Option<mpsc::UnboundedSender<ControlMessage>>,
+    cipher: Option<Arc<PacketCipher>>,
+) {
+    let max_len = MAX_CONTROL_LEN + PacketCipher::OVERHEAD;
+    while let Ok(packet) = Packet::read_from(&mut reader, max_len).await {
+        let packet = match &cipher {
+            Some(cipher) => match cipher.open(&packet) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    tracing::warn!("Relay client sent a forged control message: {}", e);
+                    return;
+                }
+            },
+            None => packet,
+        };
+        let message = match ControlMessage::from_scrcpy_bytes(&packet.data) {
+            Ok(Some((message, _))) if packet.packet_type == PacketType::Control => message,
+            _ => {
+                tracing::warn!("Relay client sent an unreadable control message");
+                return;
+            }
+        };
+        if let Some(tx) = &tx {
+            if tx.send(message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Pass a client's control messages on until it disconnects
+///
+/// Clients write them as they would to scrcpy-server. Without `tx` they are
+/// still read, so the client never blocks on sending.
+async fn forward_controls(
+    mut reader: OwnedReadHalf,
+    tx: Option<mpsc::UnboundedSender<ControlMessage>>,
+) {
+    let mut buf = BytesMut::new();
+    loop {
+        match ControlMessage::from_scrcpy_bytes(&buf) {
+            Ok(Some((message, len))) => {
+                buf.advance(len);
+                if let Some(tx) = &tx {
+                    if tx.send(message).is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+            Ok(None) if buf.len() > MAX_CONTROL_LEN => {
+                tracing::warn!(
+                    "Relay client sent a control message over {} bytes",
+                    MAX_CONTROL_LEN
+                );
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                // Without its length the rest of the stream can't be framed
+                tracing::warn!("Relay client sent an unreadable control message: {}", e);
+                return;
+            }
+        }
+        match reader.read_buf(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::FrameFlags;
+
+    #[test]
+    fn test_handshake_and_framing() {
+        let header = StreamHeader {
+            device_name: Some("Pixel 8".to_string()),
+            video_codec: VideoCodec::H265,
+            audio_codec: Some(AudioCodec::Opus),
+        };
+        let bytes = header.video_bytes();
+        assert_eq!(bytes.len(), 64 + 12);
+        assert_eq!(&bytes[..8], b"Pixel 8\0");
+        assert_eq!(&bytes[64..68], b"h265");
+        assert_eq!(&header.audio_bytes(), b"opus");
+
+        let muted = StreamHeader {
+            audio_codec: None,
+            ..header
+        };
+        assert_eq!(muted.audio_bytes(), [0; 4]);
+
+        let mut packet = Packet::new(PacketType::Video, 42, 0, Bytes::from_static(b"idr"));
+        packet.flags.keyframe = true;
+        let framed = encode(&packet);
+        let raw = u64::from_be_bytes(framed[..8].try_into().unwrap());
+        assert_eq!(FrameFlags::split_pts(raw), (packet.flags, 42));
+        assert_eq!(&framed[8..12], &3u32.to_be_bytes());
+        assert_eq!(&framed[12..], b"idr");
+    }
+}
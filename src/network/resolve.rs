@@ -0,0 +1,220 @@
+/// Turning `connection.host` into a socket address
+///
+/// A host is a name, an IPv4 address or an IPv6 address, optionally in
+/// brackets and with a zone for link-local addresses (`fe80::1%wlan0`,
+/// `[fe80::1%3]`). A name may resolve to several addresses; [`pick`] races
+/// them happy-eyeballs style (RFC 8305), so an address family that is
+/// broken on this network costs a short delay instead of a connect timeout.
+use super::{ConnectionMode, NetworkError, QuicConnection, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// Head start an address gets before the next one is tried as well
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Give up on a handshake after this long
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address for an IP literal, with a numeric zone if there is one
+///
+/// Zones given as interface names are left to the system resolver.
+fn parse_literal(host: &str, port: u16) -> Option<SocketAddr> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, port));
+    }
+    let (ip, zone) = host.split_once('%')?;
+    let ip: Ipv6Addr = ip.parse().ok()?;
+    let scope_id = zone.parse().ok()?;
+    Some(SocketAddrV6::new(ip, port, 0, scope_id).into())
+}
+
+/// Every address `host` stands for, alternating address families
+pub async fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let host = host.trim();
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if let Some(addr) = parse_literal(host, port) {
+        return Ok(vec![addr]);
+    }
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| NetworkError::ConnectionFailed(format!("Cannot resolve {}: {}", host, e)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(NetworkError::ConnectionFailed(format!(
+            "{} has no addresses",
+            host
+        )));
+    }
+    Ok(interleave(addrs))
+}
+
+/// Drop duplicates and alternate IPv6 and IPv4, starting with the family
+/// the resolver listed first
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let mut unique: Vec<SocketAddr> = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        if !unique.contains(&addr) {
+            unique.push(addr);
+        }
+    }
+    let Some(first_v6) = unique.first().map(SocketAddr::is_ipv6) else {
+        return unique;
+    };
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        unique.iter().partition(|addr| addr.is_ipv6() == first_v6);
+    let mut ordered = Vec::with_capacity(unique.len());
+    while !preferred.is_empty() || !other.is_empty() {
+        ordered.extend(preferred.pop_front().copied());
+        ordered.extend(other.pop_front().copied());
+    }
+    ordered
+}
+
+/// Run `attempt` against the addresses in order and keep the first success
+///
+/// The next address is started whenever the running ones failed or have
+/// not finished within `delay`; attempts still running at the end are dropped.
+pub async fn race<T, F, Fut>(
+    addrs: &[SocketAddr],
+    delay: Duration,
+    mut attempt: F,
+) -> Result<(SocketAddr, T)>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut start = |addr: SocketAddr| {
+        let attempt = attempt(addr);
+        async move { (addr, attempt.await) }
+    };
+    let mut waiting: VecDeque<SocketAddr> = addrs.iter().copied().collect();
+    let mut running = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        if running.is_empty() {
+            match waiting.pop_front() {
+                Some(addr) => running.push(start(addr)),
+                None => break,
+            }
+        }
+        tokio::select! {
+            Some((addr, result)) = running.next() => match result {
+                Ok(value) => return Ok((addr, value)),
+                Err(e) => {
+                    tracing::debug!("Connecting to {} failed: {}", addr, e);
+                    last_error = Some(e);
+                    if let Some(next) = waiting.pop_front() {
+                        running.push(start(next));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if !waiting.is_empty() => {
+                if let Some(next) = waiting.pop_front() {
+                    running.push(start(next));
+                }
+            }
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| NetworkError::ConnectionFailed("No address to connect to".to_string())))
+}
+
+/// Address of the server at `host`, raced across its addresses if it has several
+///
+/// Candidates only get a bare handshake of `mode` (see [`handshake`]), so
+/// the server never sees a session begun on an address that lost.
+pub async fn pick(mode: ConnectionMode, host: &str, port: u16) -> Result<SocketAddr> {
+    let addrs = resolve(host, port).await?;
+    if let [addr] = addrs[..] {
+        return Ok(addr);
+    }
+    let (addr, rtt) = race(&addrs, ATTEMPT_DELAY, |addr| handshake(mode, addr)).await?;
+    tracing::info!(
+        "Reached {} at {} first ({} ms), out of {} addresses",
+        host,
+        addr,
+        rtt.as_millis(),
+        addrs.len()
+    );
+    Ok(addr)
+}
+
+/// Measure the handshake round trip of a transport without streaming
+///
+/// TCP costs one SYN/SYN-ACK exchange. QUIC dials a bare connection (no
+/// streams are opened) and reports the RTT quinn measured during the handshake.
+pub async fn handshake(mode: ConnectionMode, addr: SocketAddr) -> Result<Duration> {
+    let started = Instant::now();
+    let probe = async {
+        match mode {
+            ConnectionMode::Tcp => {
+                TcpStream::connect(addr)
+                    .await
+                    .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+                Ok(started.elapsed())
+            }
+            ConnectionMode::Quic => QuicConnection::probe_rtt(addr).await,
+        }
+    };
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, probe)
+        .await
+        .map_err(|_| NetworkError::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literals_and_zones() {
+        assert_eq!(
+            parse_literal("192.168.1.20", 5555),
+            Some("192.168.1.20:5555".parse().unwrap())
+        );
+        let link_local = parse_literal("fe80::1%3", 5555).unwrap();
+        assert_eq!(link_local, "[fe80::1%3]:5555".parse().unwrap());
+        let SocketAddr::V6(v6) = link_local else {
+            panic!("not IPv6");
+        };
+        assert_eq!(v6.scope_id(), 3);
+        // Named zones and host names go to the resolver
+        assert_eq!(parse_literal("fe80::1%wlan0", 5555), None);
+        assert_eq!(parse_literal("pixel.local", 5555), None);
+
+        let brackets = futures::executor::block_on(resolve("[::1]", 27183)).unwrap();
+        assert_eq!(brackets, ["[::1]:27183".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_interleave_alternates_families() {
+        let addrs: Vec<SocketAddr> = [
+            "[2001:db8::1]:5555",
+            "[2001:db8::2]:5555",
+            "[2001:db8::1]:5555",
+            "10.0.0.1:5555",
+            "[2001:db8::3]:5555",
+            "10.0.0.2:5555",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect();
+        let ordered: Vec<String> = interleave(addrs).iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            ordered,
+            [
+                "[2001:db8::1]:5555",
+                "10.0.0.1:5555",
+                "[2001:db8::2]:5555",
+                "10.0.0.2:5555",
+                "[2001:db8::3]:5555",
+            ]
+        );
+    }
+}
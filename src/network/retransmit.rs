@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::protocol::NackPacket;
+
+/// Selective retransmission counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RetransmitStats {
+    /// NACK packets sent
+    pub nacks_sent: u64,
+
+    /// Sequence numbers requested (including retries)
+    pub seqs_requested: u64,
+
+    /// Missing packets that arrived after being NACKed
+    pub packets_retransmitted: u64,
+
+    /// Missing packets abandoned after all retries
+    pub packets_abandoned: u64,
+}
+
+struct MissingEntry {
+    last_requested: Instant,
+    attempts: u8,
+}
+
+/// Receiver-side gap detector that produces NACKs
+///
+/// The device server answers them from its send history. Nothing in this
+/// crate resends media: the relay's downstream is TCP, which loses nothing.
+pub struct NackTracker {
+    highest_seq: Option<u32>,
+    missing: BTreeMap<u32, MissingEntry>,
+    retry_interval: Duration,
+    max_attempts: u8,
+    max_missing: usize,
+    stats: RetransmitStats,
+}
+
+impl NackTracker {
+    /// Shortest delay between two requests for the same packet
+    const MIN_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+    /// Create a new NACK tracker
+    ///
+    /// # Arguments
+    /// * `max_attempts` - Requests per missing packet before giving up
+    /// * `max_missing` - Outstanding gaps tracked at once (older gaps are abandoned)
+    pub fn new(max_attempts: u8, max_missing: usize) -> Self {
+        Self {
+            highest_seq: None,
+            missing: BTreeMap::new(),
+            retry_interval: Duration::from_millis(20),
+            max_attempts,
+            max_missing,
+            stats: RetransmitStats::default(),
+        }
+    }
+
+    /// Update the retry interval from the measured round-trip time
+    pub fn set_rtt(&mut self, rtt: Duration) {
+        self.retry_interval = (rtt * 3 / 2).max(Self::MIN_RETRY_INTERVAL);
+    }
+
+    /// Record an arriving packet
+    ///
+    /// Returns a NACK for any gap the packet reveals.
+    pub fn on_packet(&mut self, seq: u32, now: Instant) -> Option<NackPacket> {
+        // A late packet filling a gap we asked for
+        if self.missing.remove(&seq).is_some() {
+            self.stats.packets_retransmitted += 1;
+            return None;
+        }
+
+        let highest = match self.highest_seq {
+            Some(h) => h,
+            None => {
+                self.highest_seq = Some(seq);
+                return None;
+            }
+        };
+
+        if seq <= highest {
+            return None; // Duplicate or reordered packet we never requested
+        }
+        self.highest_seq = Some(seq);
+
+        // Huge jumps (e.g. sender restart) only track the most recent gaps
+        let first = (highest + 1).max(seq.saturating_sub(self.max_missing as u32));
+        self.stats.packets_abandoned += (first - highest - 1) as u64;
+
+        let gap: Vec<u32> = (first..seq).collect();
+        if gap.is_empty() {
+            return None;
+        }
+
+        for missing_seq in &gap {
+            self.missing.insert(
+                *missing_seq,
+                MissingEntry {
+                    last_requested: now,
+                    attempts: 1,
+                },
+            );
+        }
+        self.trim();
+
+        let seqs: Vec<u32> = gap
+            .into_iter()
+            .filter(|s| self.missing.contains_key(s))
+            .take(NackPacket::MAX_SEQS)
+            .collect();
+        self.record_nack(&seqs)
+    }
+
+    /// Re-request gaps whose previous NACK went unanswered
+    pub fn poll(&mut self, now: Instant) -> Option<NackPacket> {
+        let mut seqs = Vec::new();
+        let mut abandoned = Vec::new();
+
+        for (seq, entry) in self.missing.iter_mut() {
+            if now.duration_since(entry.last_requested) < self.retry_interval {
+                continue;
+            }
+            if entry.attempts >= self.max_attempts {
+                abandoned.push(*seq);
+                continue;
+            }
+            entry.attempts += 1;
+            entry.last_requested = now;
+            if seqs.len() < NackPacket::MAX_SEQS {
+                seqs.push(*seq);
+            }
+        }
+
+        for seq in abandoned {
+            self.missing.remove(&seq);
+            self.stats.packets_abandoned += 1;
+        }
+
+        self.record_nack(&seqs)
+    }
+
+    /// Get retransmission statistics
+    pub fn stats(&self) -> RetransmitStats {
+        self.stats
+    }
+
+    /// Number of gaps currently awaiting retransmission
+    pub fn outstanding(&self) -> usize {
+        self.missing.len()
+    }
+
+    /// Abandon the oldest gaps when too many are outstanding
+    fn trim(&mut self) {
+        while self.missing.len() > self.max_missing {
+            if let Some((&seq, _)) = self.missing.iter().next() {
+                self.missing.remove(&seq);
+                self.stats.packets_abandoned += 1;
+            }
+        }
+    }
+
+    fn record_nack(&mut self, seqs: &[u32]) -> Option<NackPacket> {
+        if seqs.is_empty() {
+            return None;
+        }
+        self.stats.nacks_sent += 1;
+        self.stats.seqs_requested += seqs.len() as u64;
+        Some(NackPacket::new(seqs.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nack_roundtrip() {
+        let nack = NackPacket::new(vec![3, 7, 9]);
+        let parsed = NackPacket::from_bytes(nack.to_bytes().freeze()).unwrap();
+        assert_eq!(parsed, nack);
+    }
+
+    #[test]
+    fn test_nack_tracker_gap_and_retry() {
+        let mut tracker = NackTracker::new(2, 64);
+        let start = Instant::now();
+
+        assert!(tracker.on_packet(0, start).is_none());
+        let nack = tracker.on_packet(3, start).unwrap();
+        assert_eq!(nack.seqs, vec![1, 2]);
+
+        // Packet 1 arrives after the NACK
+        assert!(tracker.on_packet(1, start).is_none());
+        assert_eq!(tracker.stats().packets_retransmitted, 1);
+
+        // Packet 2 is re-requested once, then abandoned
+        let later = start + Duration::from_millis(100);
+        assert_eq!(tracker.poll(later).unwrap().seqs, vec![2]);
+        assert!(tracker.poll(later + Duration::from_millis(100)).is_none());
+        assert_eq!(tracker.stats().packets_abandoned, 1);
+        assert_eq!(tracker.outstanding(), 0);
+    }
+}
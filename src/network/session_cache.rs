@@ -0,0 +1,161 @@
+use parking_lot::Mutex;
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue,
+};
+use rustls::pki_types::ServerName;
+use rustls::NamedGroup;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+/// TLS session state for one device, enabling 0-RTT QUIC reconnects
+///
+/// Session tickets are kept in memory for the lifetime of the process:
+/// rustls deliberately offers no way to export a TLS 1.3 ticket, so the
+/// first connection after a restart is always a full handshake. What can be
+/// persisted is the server's key-exchange group, stored per device under the
+/// config directory so that first handshake never needs a HelloRetryRequest.
+#[derive(Debug)]
+pub struct SessionCache {
+    tickets: ClientSessionMemoryCache,
+    kx_hint: Mutex<Option<NamedGroup>>,
+    hint_path: Option<PathBuf>,
+}
+
+impl SessionCache {
+    /// Tickets kept per device (servers usually issue two per handshake)
+    const TICKETS_PER_DEVICE: usize = 8;
+
+    /// Get the process-wide cache for a device, loading its stored hint once
+    ///
+    /// # Arguments
+    /// * `device` - Stable device identifier (serial or server address)
+    pub fn for_device(device: &str) -> Arc<SessionCache> {
+        static CACHES: OnceLock<Mutex<HashMap<String, Arc<SessionCache>>>> = OnceLock::new();
+
+        let mut caches = CACHES.get_or_init(Default::default).lock();
+        caches
+            .entry(device.to_string())
+            .or_insert_with(|| {
+                let hint_path = crate::platform::config_dir()
+                    .map(|dir| dir.join("sessions").join(Self::file_name(device)));
+                Arc::new(Self::with_hint_path(hint_path))
+            })
+            .clone()
+    }
+
+    /// Create a cache that persists its key-exchange hint at `hint_path`
+    pub fn with_hint_path(hint_path: Option<PathBuf>) -> Self {
+        let kx_hint = hint_path.as_deref().and_then(Self::load_hint);
+        Self {
+            tickets: ClientSessionMemoryCache::new(Self::TICKETS_PER_DEVICE),
+            kx_hint: Mutex::new(kx_hint),
+            hint_path,
+        }
+    }
+
+    /// Filesystem-safe name for a device identifier
+    fn file_name(device: &str) -> String {
+        let name: String = device
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}.kx", name)
+    }
+
+    fn load_hint(path: &Path) -> Option<NamedGroup> {
+        let text = std::fs::read_to_string(path).ok()?;
+        text.trim().parse::<u16>().ok().map(NamedGroup::from)
+    }
+
+    fn store_hint(&self, group: NamedGroup) {
+        let Some(path) = &self.hint_path else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, u16::from(group).to_string()));
+        if let Err(e) = result {
+            tracing::debug!("Failed to store TLS session hint {:?}: {}", path, e);
+        }
+    }
+}
+
+impl ClientSessionStore for SessionCache {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.tickets.set_kx_hint(server_name, group);
+
+        let mut hint = self.kx_hint.lock();
+        if *hint != Some(group) {
+            *hint = Some(group);
+            self.store_hint(group);
+        }
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.tickets
+            .kx_hint(server_name)
+            .or_else(|| *self.kx_hint.lock())
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.tickets.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.tickets.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.tickets.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.tickets.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName<'_>) -> Option<Tls13ClientSessionValue> {
+        self.tickets.take_tls13_ticket(server_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kx_hint_persists_per_device() {
+        let path = std::env::temp_dir()
+            .join(format!("scrcpy-custom-test-{}", std::process::id()))
+            .join(SessionCache::file_name("192.168.1.20:5556"));
+        assert_eq!(
+            path.file_name().unwrap().to_str(),
+            Some("192_168_1_20_5556.kx")
+        );
+
+        let name = ServerName::try_from("localhost").unwrap();
+        let cache = SessionCache::with_hint_path(Some(path.clone()));
+        assert_eq!(cache.kx_hint(&name), None);
+        cache.set_kx_hint(name.clone(), NamedGroup::X25519);
+
+        // A fresh process only has the file to go on
+        let reloaded = SessionCache::with_hint_path(Some(path.clone()));
+        assert_eq!(reloaded.kx_hint(&name), Some(NamedGroup::X25519));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_for_device_is_shared() {
+        let a = SessionCache::for_device("serial-a");
+        let b = SessionCache::for_device("serial-a");
+        let c = SessionCache::for_device("serial-b");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+}
@@ -0,0 +1,311 @@
+/// Impaired network for tests: latency, jitter, reordering, loss and a rate cap
+///
+/// [`SimulatedConnection`] sits between a transport and the decoder and holds
+/// every received packet back as a [`LinkModel`] decides. The model draws from
+/// a seeded generator, so a test sees the same drops and delays on every run.
+/// Only the device-to-PC direction is impaired; control messages go straight
+/// through.
+use super::{
+    Connection, ControlMessage, FecSetting, NetworkError, NetworkStats, Packet, Result,
+    SharedSecret,
+};
+use crate::config::{PerformanceConfig, VideoCodec};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Impairments applied to received packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimConfig {
+    /// One-way delay added to every packet
+    pub latency: Duration,
+    /// Random extra delay of up to this much either way (never below zero)
+    pub jitter: Duration,
+    /// Chance (0.0-1.0) a packet is held back by `reorder_delay`, so later
+    /// packets overtake it
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Chance (0.0-1.0) a packet is lost
+    pub loss: f64,
+    /// Link rate in Mbps; above it packets queue behind each other
+    pub bandwidth_mbps: Option<f64>,
+    /// Seed of the generator deciding drops, jitter and reordering
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(20),
+            loss: 0.0,
+            bandwidth_mbps: None,
+            seed: 0,
+        }
+    }
+}
+
+/// SplitMix64: tiny and plenty random for deciding drops and delays
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in 0.0..1.0
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// When each packet sent over a simulated link arrives
+pub struct LinkModel {
+    config: SimConfig,
+    rng: SplitMix64,
+    /// When the link is done sending the packets queued so far
+    link_free: Option<Instant>,
+    /// Arrival of the last packet that was not reordered; later ones
+    /// arrive after it, as on a single network path
+    last_in_order: Option<Instant>,
+    dropped: u64,
+}
+
+impl LinkModel {
+    pub fn new(config: SimConfig) -> Self {
+        Self {
+            config,
+            rng: SplitMix64(config.seed),
+            link_free: None,
+            last_in_order: None,
+            dropped: 0,
+        }
+    }
+
+    /// Arrival time of a `len` byte packet sent at `now`, None if it is lost
+    pub fn schedule(&mut self, len: usize, now: Instant) -> Option<Instant> {
+        // Lost packets never take up link time
+        if self.rng.next_f64() < self.config.loss {
+            self.dropped += 1;
+            return None;
+        }
+        let mut sent = now;
+        if let Some(mbps) = self.config.bandwidth_mbps.filter(|&mbps| mbps > 0.0) {
+            let start = self.link_free.map_or(now, |free| free.max(now));
+            sent = start + Duration::from_secs_f64(len as f64 * 8.0 / (mbps * 1e6));
+            self.link_free = Some(sent);
+        }
+        let jitter = self.config.jitter.as_secs_f64() * (self.rng.next_f64() * 2.0 - 1.0);
+        let delay = (self.config.latency.as_secs_f64() + jitter).max(0.0);
+        let arrival = sent + Duration::from_secs_f64(delay);
+
+        if self.rng.next_f64() < self.config.reorder {
+            return Some(arrival + self.config.reorder_delay);
+        }
+        let arrival = self.last_in_order.map_or(arrival, |last| arrival.max(last));
+        self.last_in_order = Some(arrival);
+        Some(arrival)
+    }
+
+    /// Packets lost so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// A [`Connection`] whose received packets go through a [`LinkModel`]
+pub struct SimulatedConnection<C> {
+    inner: C,
+    model: LinkModel,
+    /// Packets on their way, earliest arrival first
+    in_flight: VecDeque<(Instant, Packet)>,
+    /// Why the inner connection stopped, reported once the packets in
+    /// flight are delivered
+    ended: Option<NetworkError>,
+}
+
+impl<C: Connection> SimulatedConnection<C> {
+    pub fn new(inner: C, config: SimConfig) -> Self {
+        Self {
+            inner,
+            model: LinkModel::new(config),
+            in_flight: VecDeque::new(),
+            ended: None,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<C: Connection> Connection for SimulatedConnection<C> {
+    /// Connect `C` without impairments; use [`SimulatedConnection::new`] to set them
+    async fn connect(addr: SocketAddr, enable_audio: bool) -> Result<Self> {
+        let inner = C::connect(addr, enable_audio).await?;
+        Ok(Self::new(inner, SimConfig::default()))
+    }
+
+    async fn recv(&mut self) -> Result<Packet> {
+        loop {
+            let next = self.in_flight.front().map(|(at, _)| *at);
+            if next.is_some_and(|at| at <= Instant::now()) {
+                if let Some((_, packet)) = self.in_flight.pop_front() {
+                    return Ok(packet);
+                }
+            }
+            let wake = tokio::time::Instant::from_std(next.unwrap_or_else(Instant::now));
+            if self.ended.is_some() {
+                match next {
+                    Some(_) => tokio::time::sleep_until(wake).await,
+                    None => {
+                        return Err(self.ended.take().unwrap_or(NetworkError::ConnectionClosed))
+                    }
+                }
+                continue;
+            }
+            tokio::select! {
+                packet = self.inner.recv() => match packet {
+                    Ok(packet) => {
+                        if let Some(at) = self.model.schedule(packet.data.len(), Instant::now()) {
+                            // Equal arrivals keep the order they were received in
+                            let index = self.in_flight.partition_point(|(other, _)| *other <= at);
+                            self.in_flight.insert(index, (at, packet));
+                        }
+                    }
+                    Err(e) => self.ended = Some(e),
+                },
+                _ = tokio::time::sleep_until(wake), if next.is_some() => {}
+            }
+        }
+    }
+
+    async fn send_control(&mut self, msg: ControlMessage) -> Result<()> {
+        self.inner.send_control(msg).await
+    }
+
+    /// Stats of the inner connection with the simulated losses and latency
+    fn stats(&self) -> NetworkStats {
+        let mut stats = self.inner.stats();
+        let dropped = self.model.dropped();
+        stats.packets_received = stats.packets_received.saturating_sub(dropped);
+        stats.packets_lost += dropped;
+        let total = stats.packets_received + stats.packets_lost;
+        if total > 0 {
+            stats.packet_loss = stats.packets_lost as f64 * 100.0 / total as f64;
+        }
+        stats.rtt_ms += self.model.config.latency.as_secs_f64() * 1000.0;
+        stats
+    }
+
+    fn apply_performance_config(&mut self, config: &PerformanceConfig) {
+        self.inner.apply_performance_config(config);
+    }
+
+    async fn authenticate(&mut self, secret: &SharedSecret) -> Result<()> {
+        self.inner.authenticate(secret).await
+    }
+
+    fn video_codec(&self) -> Option<VideoCodec> {
+        self.inner.video_codec()
+    }
+
+    fn device_name(&self) -> Option<&str> {
+        self.inner.device_name()
+    }
+
+    async fn set_fec(&mut self, setting: FecSetting) -> Result<()> {
+        self.inner.set_fec(setting).await
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        self.in_flight.clear();
+        self.ended = None;
+        self.inner.reconnect().await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.in_flight.clear();
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_and_delay_are_seeded() {
+        let config = SimConfig {
+            latency: Duration::from_millis(40),
+            jitter: Duration::from_millis(10),
+            loss: 0.05,
+            seed: 7,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let run = || {
+            let mut link = LinkModel::new(config);
+            let arrivals: Vec<Option<Instant>> = (0..10_000u32)
+                .map(|i| link.schedule(1200, start + Duration::from_millis(i.into())))
+                .collect();
+            (arrivals, link.dropped())
+        };
+        let (arrivals, dropped) = run();
+        assert_eq!(run(), (arrivals.clone(), dropped));
+        assert!((400..600).contains(&dropped), "dropped {}", dropped);
+
+        let delivered: Vec<(u32, Instant)> = (0..)
+            .zip(&arrivals)
+            .filter_map(|(i, at)| Some((i, (*at)?)))
+            .collect();
+        // Without reordering, jitter never swaps packets
+        assert!(delivered.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        for (i, at) in delivered {
+            let delay = at - (start + Duration::from_millis(i.into()));
+            assert!(delay >= Duration::from_millis(30), "delay {:?}", delay);
+        }
+    }
+
+    #[test]
+    fn test_bandwidth_queues_and_reorder_overtakes() {
+        let start = Instant::now();
+        let mut link = LinkModel::new(SimConfig {
+            bandwidth_mbps: Some(8.0),
+            ..Default::default()
+        });
+        // 1000 bytes take 1 ms at 8 Mbps
+        let arrivals: Vec<Duration> = (0..3)
+            .map(|_| link.schedule(1000, start).unwrap() - start)
+            .collect();
+        assert_eq!(arrivals, [1, 2, 3].map(Duration::from_millis).to_vec());
+
+        let mut link = LinkModel::new(SimConfig {
+            reorder: 1.0,
+            reorder_delay: Duration::from_millis(5),
+            ..Default::default()
+        });
+        let first = link.schedule(100, start).unwrap();
+        assert_eq!(first - start, Duration::from_millis(5));
+        let mut link = LinkModel::new(SimConfig {
+            reorder: 0.5,
+            seed: 3,
+            ..Default::default()
+        });
+        let arrivals: Vec<Instant> = (0..100)
+            .map(|i| {
+                link.schedule(100, start + Duration::from_millis(i))
+                    .unwrap()
+            })
+            .collect();
+        assert!(arrivals.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+}
@@ -0,0 +1,281 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+
+use super::{Connection, ConnectionMode, QuicConnection, Result, TcpConnection};
+use crate::stats::Ewma;
+
+/// Open a streaming connection over the given transport
+///
+/// `device` (the adb serial, if known) keys QUIC session state; without it
+/// the address does.
+pub async fn connect(
+    mode: ConnectionMode,
+    addr: SocketAddr,
+    enable_audio: bool,
+    device: Option<&str>,
+) -> Result<Box<dyn Connection>> {
+    Ok(match mode {
+        ConnectionMode::Tcp => Box::new(TcpConnection::connect(addr, enable_audio).await?),
+        ConnectionMode::Quic => match device {
+            Some(device) => Box::new(QuicConnection::for_device(addr, device).await?),
+            None => Box::new(QuicConnection::connect(addr, enable_audio).await?),
+        },
+    })
+}
+
+/// Time a handshake on each transport's own path to the device
+///
+/// TCP shakes hands with `adbd`, which carries the forwarded stream; QUIC
+/// with the device's QUIC server at `quic`, without opening a stream, so the
+/// server never starts streaming to it. Both transports are measured the
+/// same way whichever one carries the stream. A failed or timed-out probe
+/// comes back as None.
+pub async fn probe_links(
+    adbd: SocketAddr,
+    quic: SocketAddr,
+) -> (Option<Duration>, Option<Duration>) {
+    let tcp = async {
+        let started = Instant::now();
+        TcpStream::connect(adbd).await.ok()?;
+        Some(started.elapsed())
+    };
+    let quic = async { QuicConnection::probe_rtt(quic).await.ok() };
+    let timeout = TransportSwitcher::PROBE_TIMEOUT;
+    let (tcp, quic) = tokio::join!(
+        tokio::time::timeout(timeout, tcp),
+        tokio::time::timeout(timeout, quic)
+    );
+    (tcp.ok().flatten(), quic.ok().flatten())
+}
+
+/// Smoothed probe results for one transport
+#[derive(Debug, Clone, Copy)]
+struct LinkScore {
+    rtt_ms: Ewma,
+    /// Share of failed probes, in percent
+    loss: Ewma,
+}
+
+impl Default for LinkScore {
+    fn default() -> Self {
+        const ALPHA: f64 = 0.3;
+        Self {
+            rtt_ms: Ewma::new(ALPHA),
+            loss: Ewma::new(ALPHA),
+        }
+    }
+}
+
+impl LinkScore {
+    fn add(&mut self, rtt: Option<Duration>) {
+        match rtt {
+            Some(rtt) => {
+                self.rtt_ms.add(rtt.as_secs_f64() * 1000.0);
+                self.loss.add(0.0);
+            }
+            None => {
+                self.loss.add(100.0);
+            }
+        }
+    }
+
+    /// Whether a probe ever got through
+    fn measured(&self) -> bool {
+        self.rtt_ms.is_set()
+    }
+
+    /// Effective latency: RTT plus a penalty for loss (lost frames cost a recovery or a keyframe)
+    fn cost(&self) -> f64 {
+        self.rtt_ms.get() + self.loss.get() * TransportSwitcher::LOSS_PENALTY_MS
+    }
+}
+
+/// Decides when to move the stream to the other transport
+///
+/// Both transports are probed on a fixed interval with the same kind of
+/// probe (`probe_links`), so neither is favoured for carrying the stream. A
+/// switch needs the standby link to be clearly better on several rounds in
+/// a row and a minimum time on the current link, so a single noisy sample
+/// never bounces the stream.
+pub struct TransportSwitcher {
+    active: ConnectionMode,
+    tcp: LinkScore,
+    quic: LinkScore,
+    better_streak: u32,
+    last_switch: Instant,
+    last_probe: Option<Instant>,
+}
+
+impl TransportSwitcher {
+    /// How often the link is probed
+    pub const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Give up on a probe after this long (counts as a lost probe)
+    const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Latency-equivalent of 1% lost probes
+    const LOSS_PENALTY_MS: f64 = 20.0;
+
+    /// Relative improvement the standby link must show
+    const SWITCH_MARGIN: f64 = 0.25;
+
+    /// Consecutive better rounds required before switching
+    const SWITCH_STREAK: u32 = 3;
+
+    /// Minimum time on a transport before switching again
+    const MIN_DWELL: Duration = Duration::from_secs(30);
+
+    /// Create a switcher for a session that started on `active`
+    pub fn new(active: ConnectionMode) -> Self {
+        Self {
+            active,
+            tcp: LinkScore::default(),
+            quic: LinkScore::default(),
+            better_streak: 0,
+            last_switch: Instant::now(),
+            last_probe: None,
+        }
+    }
+
+    /// Transport currently carrying the stream
+    pub fn active(&self) -> ConnectionMode {
+        self.active
+    }
+
+    /// The transport that is not carrying the stream
+    pub fn standby(&self) -> ConnectionMode {
+        match self.active {
+            ConnectionMode::Tcp => ConnectionMode::Quic,
+            ConnectionMode::Quic => ConnectionMode::Tcp,
+        }
+    }
+
+    /// Whether a new round of probes should start, marking it started if so
+    pub fn probe_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_probe
+            .is_none_or(|last| now.duration_since(last) >= Self::PROBE_INTERVAL);
+        if due {
+            self.last_probe = Some(now);
+        }
+        due
+    }
+
+    /// Record a round of probes, None for a probe that failed
+    pub fn record(&mut self, tcp: Option<Duration>, quic: Option<Duration>) {
+        self.tcp.add(tcp);
+        self.quic.add(quic);
+
+        let (active, standby) = (self.link(self.active), self.link(self.standby()));
+        if standby.measured() && standby.cost() < active.cost() * (1.0 - Self::SWITCH_MARGIN) {
+            self.better_streak += 1;
+        } else {
+            self.better_streak = 0;
+        }
+    }
+
+    /// Transport to switch to, if the standby has been better for long enough
+    pub fn should_switch(&self, now: Instant) -> Option<ConnectionMode> {
+        let settled = now.duration_since(self.last_switch) >= Self::MIN_DWELL;
+        (settled && self.better_streak >= Self::SWITCH_STREAK).then(|| self.standby())
+    }
+
+    /// Note that the stream now runs over `mode`
+    pub fn switched(&mut self, mode: ConnectionMode, now: Instant) {
+        self.active = mode;
+        self.last_switch = now;
+        self.better_streak = 0;
+    }
+
+    /// Back off after a failed switch attempt
+    pub fn switch_failed(&mut self, now: Instant) {
+        self.last_switch = now;
+        self.better_streak = 0;
+    }
+
+    fn link(&self, mode: ConnectionMode) -> &LinkScore {
+        match mode {
+            ConnectionMode::Tcp => &self.tcp,
+            ConnectionMode::Quic => &self.quic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_switches_after_sustained_improvement() {
+        let mut switcher = TransportSwitcher::new(ConnectionMode::Quic);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            switcher.record(Some(5 * MS), Some(60 * MS));
+        }
+        // Better, but not on the link long enough yet
+        assert_eq!(switcher.should_switch(start), None);
+
+        let later = start + TransportSwitcher::MIN_DWELL;
+        assert_eq!(switcher.should_switch(later), Some(ConnectionMode::Tcp));
+
+        switcher.switched(ConnectionMode::Tcp, later);
+        assert_eq!(switcher.active(), ConnectionMode::Tcp);
+        assert_eq!(switcher.should_switch(later), None);
+    }
+
+    #[test]
+    fn test_switches_back_from_tcp() {
+        let mut switcher = TransportSwitcher::new(ConnectionMode::Quic);
+        let start = Instant::now();
+        for _ in 0..3 {
+            switcher.record(Some(5 * MS), Some(60 * MS));
+        }
+        let later = start + TransportSwitcher::MIN_DWELL;
+        switcher.switched(ConnectionMode::Tcp, later);
+
+        // TCP degrades (lost probes), QUIC recovers
+        for _ in 0..5 {
+            switcher.record(None, Some(8 * MS));
+        }
+        let much_later = later + TransportSwitcher::MIN_DWELL;
+        assert_eq!(
+            switcher.should_switch(much_later),
+            Some(ConnectionMode::Quic)
+        );
+    }
+
+    #[test]
+    fn test_marginal_or_noisy_gain_does_not_switch() {
+        let mut switcher = TransportSwitcher::new(ConnectionMode::Tcp);
+        let later = Instant::now() + TransportSwitcher::MIN_DWELL;
+
+        // Within the margin
+        for _ in 0..5 {
+            switcher.record(Some(20 * MS), Some(18 * MS));
+        }
+        assert_eq!(switcher.should_switch(later), None);
+
+        // One slow probe resets the streak
+        switcher.record(Some(20 * MS), Some(2 * MS));
+        switcher.record(Some(20 * MS), Some(2 * MS));
+        switcher.record(Some(20 * MS), Some(200 * MS));
+        switcher.record(Some(20 * MS), Some(2 * MS));
+        assert_eq!(switcher.should_switch(later), None);
+    }
+
+    #[test]
+    fn test_unreachable_standby_never_wins() {
+        let mut switcher = TransportSwitcher::new(ConnectionMode::Tcp);
+        let later = Instant::now() + TransportSwitcher::MIN_DWELL;
+
+        for _ in 0..5 {
+            switcher.record(Some(80 * MS), None);
+        }
+        assert_eq!(switcher.should_switch(later), None);
+    }
+}
@@ -1,19 +1,60 @@
-use super::{Connection, ControlMessage, NetworkError, NetworkStats, Packet, PacketType, Result};
+use super::{
+    AuthMessage, Connection, ControlMessage, DeviceMessage, FrameFlags, NetworkError, NetworkStats,
+    Packet, PacketCipher, PacketType, Result, SharedSecret,
+};
+use crate::config::VideoCodec;
 use async_trait::async_trait;
-// use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 
 /// TCP connection for wired (USB/ADB) connectivity
 pub struct TcpConnection {
-    // We only need write access to video stream for control messages
+    // Control socket, the last one the server accepts or connects
     control_writer: tokio::net::tcp::OwnedWriteHalf,
+    // First socket's write half, kept open: shutting it down may make adb
+    // close the socket in both directions
+    _stream_writer: tokio::net::tcp::OwnedWriteHalf,
     // Receiver for multiplexed packets (Video + Audio)
     packet_rx: tokio::sync::mpsc::Receiver<Result<Packet>>,
+    // Control socket's read half, held back until the first recv() so
+    // authentication can read it first
+    pending_control: Option<(
+        tokio::net::tcp::OwnedReadHalf,
+        tokio::sync::mpsc::Sender<Result<Packet>>,
+    )>,
+    // Socket reader tasks, stopped on close
+    readers: Vec<JoinHandle<()>>,
+    // Set once a relay accepted our auth token: control messages then go
+    // out as packets and no device messages come back
+    authenticated: bool,
+    // Payload cipher negotiated with the relay
+    cipher: Option<PacketCipher>,
     stats: NetworkStats,
+    // Codec from the video stream header, None without video
+    video_codec: Option<VideoCodec>,
+    device_name: Option<String>,
+}
+
+/// Device name from the fixed 64-byte handshake field
+///
+/// The name is NUL-padded UTF-8. A multi-byte character cut off by the field
+/// end is dropped, anything else invalid is replaced, and surrounding
+/// whitespace and control characters are trimmed.
+fn parse_device_name(raw: &[u8]) -> Option<String> {
+    let raw = &raw[..raw.iter().position(|&b| b == 0).unwrap_or(raw.len())];
+    let raw = match std::str::from_utf8(raw) {
+        // Truncated mid-character: keep the complete part
+        Err(e) if e.error_len().is_none() => &raw[..e.valid_up_to()],
+        _ => raw,
+    };
+    let name = String::from_utf8_lossy(raw);
+    let name = name.trim_matches(|c: char| c.is_whitespace() || c.is_control());
+    (!name.is_empty()).then(|| name.to_string())
 }
 
 impl TcpConnection {
@@ -23,16 +64,129 @@ impl TcpConnection {
     /// Timeout for read operations (Handshake only)
     const READ_TIMEOUT: Duration = Duration::from_secs(10);
 
+    /// Timeout for each authentication step
+    const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Largest authentication message accepted from the server
+    const MAX_AUTH_LEN: usize = 1024;
+
+    /// Longest a just-launched server may take to take its first socket
+    /// (slow devices need several seconds)
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Pause between attempts while the server is still starting
+    const STARTUP_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    /// Open one more socket to a forwarded server
+    async fn dial(addr: SocketAddr) -> Result<TcpStream> {
+        let stream = timeout(Self::CONNECT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| NetworkError::Timeout)?
+            .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// Open the first socket of a forwarded server, retrying while it starts
+    ///
+    /// `adb forward` accepts before the server listens and then closes the
+    /// socket, so an attempt only counts once the server's dummy byte arrives.
+    async fn dial_first(
+        addr: SocketAddr,
+    ) -> Result<(
+        tokio::net::tcp::OwnedReadHalf,
+        tokio::net::tcp::OwnedWriteHalf,
+    )> {
+        let deadline = tokio::time::Instant::now() + Self::STARTUP_TIMEOUT;
+        loop {
+            let (mut reader, writer) = Self::dial(addr).await?.into_split();
+            let mut dummy = [0u8; 1];
+            match tokio::time::timeout_at(deadline, reader.read_exact(&mut dummy)).await {
+                Ok(Ok(_)) => {
+                    tracing::info!("Consuming dummy byte: 0x{:02X}", dummy[0]);
+                    return Ok((reader, writer));
+                }
+                Ok(Err(e))
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::ConnectionReset
+                    ) && tokio::time::Instant::now() + Self::STARTUP_RETRY_DELAY < deadline =>
+                {
+                    tracing::debug!("Server not listening yet, retrying");
+                    tokio::time::sleep(Self::STARTUP_RETRY_DELAY).await;
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Err(NetworkError::Timeout),
+            }
+        }
+    }
+
+    /// Forward what the server writes on the control socket as `Control` packets
+    fn read_device_messages(
+        mut reader: tokio::net::tcp::OwnedReadHalf,
+        tx: tokio::sync::mpsc::Sender<Result<Packet>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut buf = BytesMut::with_capacity(4096);
+            loop {
+                match DeviceMessage::from_scrcpy_bytes(&buf) {
+                    Ok(Some((message, len))) => {
+                        buf.advance(len);
+                        if let Ok(data) = message.to_bytes() {
+                            let packet = Packet::new(PacketType::Control, 0, 0, data);
+                            if tx.send(Ok(packet)).await.is_err() {
+                                break;
+                            }
+                        }
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("Stopped reading device messages: {}", e);
+                        break;
+                    }
+                }
+                // A closed socket shows up on the video stream as well
+                match reader.read_buf(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+            }
+        })
+    }
+
+    /// Exchange one authentication message with a relay
+    async fn auth_exchange(
+        reader: &mut tokio::net::tcp::OwnedReadHalf,
+        writer: &mut tokio::net::tcp::OwnedWriteHalf,
+        msg: Option<AuthMessage>,
+    ) -> Result<AuthMessage> {
+        let step = async {
+            if let Some(msg) = msg {
+                let packet = msg
+                    .into_packet()
+                    .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+                writer.write_all(&packet.to_bytes()).await?;
+            }
+            let reply = Packet::read_from(reader, Self::MAX_AUTH_LEN).await?;
+            AuthMessage::from_packet(&reply).map_err(|e| NetworkError::Protocol(e.to_string()))
+        };
+        timeout(Self::AUTH_TIMEOUT, step)
+            .await
+            .map_err(|_| NetworkError::Timeout)?
+    }
+
     /// Helper to read a packet from a stream
     async fn read_packet(
         reader: &mut tokio::net::tcp::OwnedReadHalf,
         packet_type: PacketType,
     ) -> Result<Packet> {
-        // [PTS 8][LEN 4][DATA LEN]
+        // [FLAGS|PTS 8][LEN 4][DATA LEN]
         let mut header = [0u8; 12];
         reader.read_exact(&mut header).await?;
 
-        let pts = u64::from_be_bytes(header[0..8].try_into().unwrap()) as i64;
+        let (flags, pts) =
+            FrameFlags::split_pts(u64::from_be_bytes(header[0..8].try_into().unwrap()));
         let len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
 
         if len > 20 * 1024 * 1024 {
@@ -42,114 +196,101 @@ impl TcpConnection {
         let mut payload = vec![0u8; len];
         reader.read_exact(&mut payload).await?;
 
-        Ok(Packet::new(
-            packet_type,
-            pts,
-            0,
-            bytes::Bytes::from(payload),
-        ))
+        Ok(Packet::new(packet_type, pts, 0, bytes::Bytes::from(payload)).with_flags(flags))
     }
-}
-
-#[async_trait]
-impl Connection for TcpConnection {
-    async fn connect(addr: SocketAddr, enable_audio: bool) -> Result<Self> {
-        // 1. Connect Video Socket
-        let video_stream = timeout(Self::CONNECT_TIMEOUT, TcpStream::connect(addr))
-            .await
-            .map_err(|_| NetworkError::Timeout)?
-            .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
-        video_stream.set_nodelay(true)?;
-
-        // 2 & 3. Concurrent Initialization: Handshake (Video) and Connect (Audio)
-        let (mut video_reader, control_writer) = video_stream.into_split();
-        // We do this concurrently to avoid Deadlocks (Server waiting for Audio vs Client waiting for Name)
-        // and Race Conditions (Server sending Name immediately).
 
-        let video_reader_ref = &mut video_reader; // Borrow for async block
+    /// Read the 64-byte device name the server sends first
+    async fn read_device_name(
+        reader: &mut tokio::net::tcp::OwnedReadHalf,
+    ) -> Result<Option<String>> {
+        tracing::info!("Waiting for device name (Video Socket)...");
+        let mut device_name = [0u8; 64];
+        match timeout(Self::READ_TIMEOUT, reader.read_exact(&mut device_name)).await {
+            Ok(Ok(_)) => {
+                let name = parse_device_name(&device_name);
+                tracing::info!(
+                    "Connected to device: {}",
+                    name.as_deref().unwrap_or("(unnamed)")
+                );
+                Ok(name)
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Failed to read device name: {}", e);
+                Err(NetworkError::ConnectionFailed(format!(
+                    "Video Handshake Error: {}",
+                    e
+                )))
+            }
+            Err(_) => {
+                tracing::error!("Timeout waiting for device name! Is the server running?");
+                Err(NetworkError::Timeout)
+            }
+        }
+    }
 
-        let handshake_future = async {
-            tracing::info!("Waiting for device name (Video Socket)...");
-            let mut device_name = [0u8; 64];
-            match timeout(
-                Self::READ_TIMEOUT,
-                video_reader_ref.read_exact(&mut device_name),
-            )
-            .await
-            {
-                Ok(Ok(_)) => {
-                    let name = String::from_utf8_lossy(&device_name);
-                    tracing::info!("Connected to device: {}", name.trim_matches(char::from(0)));
-                    Ok(())
-                }
-                Ok(Err(e)) => {
-                    tracing::error!("Failed to read device name: {}", e);
-                    Err(NetworkError::ConnectionFailed(format!(
-                        "Video Handshake Error: {}",
-                        e
-                    )))
-                }
-                Err(_) => {
-                    tracing::error!("Timeout waiting for device name! Is the server running?");
-                    Err(NetworkError::Timeout)
-                }
+    /// Accept the server's sockets instead of dialing them (`tunnel_forward=false`)
+    ///
+    /// With `adb reverse` the server connects to us: video first, then audio,
+    /// then control.
+    pub async fn accept(listener: TcpListener, enable_audio: bool) -> Result<Self> {
+        let accept = |what: &'static str, limit: Duration| {
+            let listener = &listener;
+            async move {
+                tracing::info!("Waiting for the server to connect the {} socket...", what);
+                let (stream, peer) = timeout(limit, listener.accept())
+                    .await
+                    .map_err(|_| NetworkError::Timeout)?
+                    .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+                stream.set_nodelay(true)?;
+                tracing::info!("{} socket connected from {}", what, peer);
+                Ok::<_, NetworkError>(stream)
             }
         };
 
-        let audio_connect_future = async {
-            if enable_audio {
-                tracing::info!("Audio enabled. Connecting to audio socket...");
-                match timeout(Self::CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
-                    Ok(Ok(stream)) => {
-                        if let Err(e) = stream.set_nodelay(true) {
-                            tracing::warn!("Failed to set nodelay on audio socket: {}", e);
-                        }
-                        tracing::info!("Audio socket connected!");
-                        let (reader, _) = stream.into_split();
-                        Some(reader)
-                    }
-                    Ok(Err(e)) => {
-                        tracing::warn!(
-                            "Failed to connect to audio socket: {}. Continuing without audio.",
-                            e
-                        );
-                        None
-                    }
-                    Err(_) => {
-                        tracing::warn!(
-                            "Timeout connecting to audio socket. Continuing without audio."
-                        );
-                        None
-                    }
+        // The server may still be starting
+        let (mut video_reader, video_writer) =
+            accept("video", Self::STARTUP_TIMEOUT).await?.into_split();
+        let audio_reader = if enable_audio {
+            match accept("audio", Self::READ_TIMEOUT).await {
+                Ok(stream) => Some(stream.into_split().0),
+                Err(e) => {
+                    tracing::warn!(
+                        "Audio socket not connected: {}. Continuing without audio.",
+                        e
+                    );
+                    None
                 }
-            } else {
-                tracing::info!("Audio disabled. Skipping audio socket.");
-                None
             }
+        } else {
+            None
         };
+        let control = accept("control", Self::READ_TIMEOUT).await?;
+
+        // The server only writes once every socket is connected
+        let device_name = Self::read_device_name(&mut video_reader).await?;
+        // No dummy byte: the server only sends it to prove a forwarded tunnel is live
+        Self::start(
+            (video_reader, video_writer),
+            audio_reader,
+            control,
+            device_name,
+        )
+        .await
+    }
 
-        // Run both concurrently
-        let (handshake_res, audio_reader_res) =
-            tokio::join!(handshake_future, audio_connect_future);
-
-        // Check handshake result
-        handshake_res?;
-        let audio_reader = audio_reader_res;
-
+    /// Read the stream metadata and spawn the socket readers
+    async fn start(
+        (mut video_reader, video_writer): (
+            tokio::net::tcp::OwnedReadHalf,
+            tokio::net::tcp::OwnedWriteHalf,
+        ),
+        audio_reader: Option<tokio::net::tcp::OwnedReadHalf>,
+        control: TcpStream,
+        device_name: Option<String>,
+    ) -> Result<Self> {
         // 4, 5, 6. Concurrent Metadata Read
         // We read video metadata and audio metadata concurrently to prevent ordering issues
         let video_metadata_future = async {
-            // Read Dummy Byte (Video)
-            tracing::info!("Waiting for dummy byte (Video Socket)...");
-            let mut dummy = [0u8; 1];
-            match timeout(Self::READ_TIMEOUT, video_reader.read_exact(&mut dummy)).await {
-                Ok(Ok(_)) => {
-                    tracing::info!("Consuming dummy byte: 0x{:02X}", dummy[0]);
-                }
-                Ok(Err(e)) => return Err(anyhow::anyhow!("Failed to read dummy byte: {}", e)),
-                Err(_) => return Err(NetworkError::Timeout.into()),
-            }
-
             // Read Video Metadata
             tracing::info!("Waiting for video metadata (Video Socket)...");
             let mut v_meta = [0u8; 12];
@@ -164,7 +305,9 @@ impl Connection for TcpConnection {
                         width,
                         height
                     );
-                    Ok(())
+                    VideoCodec::from_codec_id(v_codec_id).ok_or_else(|| {
+                        anyhow::anyhow!("Unknown video codec ID 0x{:08X}", v_codec_id)
+                    })
                 }
                 Ok(Err(e)) => Err(anyhow::anyhow!("Failed to read video metadata: {}", e)),
                 Err(_) => Err(NetworkError::Timeout.into()),
@@ -203,7 +346,7 @@ impl Connection for TcpConnection {
         // Run metadata reads concurrently
         let (video_res, audio_res) = tokio::join!(video_metadata_future, audio_metadata_future);
 
-        video_res.map_err(|e| {
+        let video_codec = video_res.map_err(|e| {
             NetworkError::ConnectionFailed(format!("Video metadata handshake failed: {}", e))
         })?;
         let audio_reader = audio_res;
@@ -213,7 +356,7 @@ impl Connection for TcpConnection {
 
         // Video Reader Task
         let tx_video = tx.clone();
-        tokio::spawn(async move {
+        let mut readers = vec![tokio::spawn(async move {
             loop {
                 match Self::read_packet(&mut video_reader, PacketType::Video).await {
                     Ok(pkt) => {
@@ -227,12 +370,12 @@ impl Connection for TcpConnection {
                     }
                 }
             }
-        });
+        })];
 
         // Audio Reader Task
         if let Some(mut reader) = audio_reader {
             let tx_audio = tx.clone();
-            tokio::spawn(async move {
+            readers.push(tokio::spawn(async move {
                 loop {
                     match Self::read_packet(&mut reader, PacketType::Audio).await {
                         Ok(pkt) => {
@@ -246,45 +389,238 @@ impl Connection for TcpConnection {
                         }
                     }
                 }
-            });
+            }));
         }
 
+        let (control_reader, control_writer) = control.into_split();
+
         Ok(Self {
             control_writer,
+            _stream_writer: video_writer,
             packet_rx,
+            pending_control: Some((control_reader, tx)),
+            readers,
+            authenticated: false,
+            cipher: None,
             stats: NetworkStats::default(),
+            video_codec: Some(video_codec),
+            device_name,
         })
     }
 
+    /// Dial a server started with `video=false` over a forward tunnel
+    ///
+    /// The audio socket is then the first one and the control socket the
+    /// second: dummy byte, device name and audio codec come first on the
+    /// audio socket, then the audio packets.
+    pub async fn connect_audio_only(addr: SocketAddr) -> Result<Self> {
+        let (mut reader, audio_writer) = Self::dial_first(addr).await?;
+        let control = Self::dial(addr).await?;
+
+        let device_name = Self::read_device_name(&mut reader).await?;
+        let mut meta = [0u8; 4];
+        timeout(Self::READ_TIMEOUT, reader.read_exact(&mut meta))
+            .await
+            .map_err(|_| NetworkError::Timeout)??;
+        let codec_id = u32::from_be_bytes(meta);
+        tracing::info!("Audio: CodecID=0x{:08X}", codec_id);
+        if codec_id == 0 {
+            return Err(NetworkError::ConnectionFailed(
+                "The device cannot capture audio (Android 11+ needed)".to_string(),
+            ));
+        }
+
+        let (tx, packet_rx) = tokio::sync::mpsc::channel(100);
+        let (control_reader, control_writer) = control.into_split();
+        let tx_audio = tx.clone();
+        let readers = vec![tokio::spawn(async move {
+            loop {
+                let packet = Self::read_packet(&mut reader, PacketType::Audio).await;
+                let failed = packet.is_err();
+                if tx_audio.send(packet).await.is_err() || failed {
+                    break;
+                }
+            }
+        })];
+
+        Ok(Self {
+            control_writer,
+            _stream_writer: audio_writer,
+            packet_rx,
+            pending_control: Some((control_reader, tx)),
+            readers,
+            authenticated: false,
+            cipher: None,
+            stats: NetworkStats::default(),
+            video_codec: None,
+            device_name,
+        })
+    }
+}
+
+#[async_trait]
+impl Connection for TcpConnection {
+    async fn connect(addr: SocketAddr, enable_audio: bool) -> Result<Self> {
+        // 1. Connect Video Socket (once the server has started listening)
+        let (mut video_reader, video_writer) = Self::dial_first(addr).await?;
+
+        // 2 & 3. Concurrent Initialization: Handshake (Video) and Connect (Audio, Control)
+        // We do this concurrently to avoid Deadlocks (Server waiting for Audio vs Client waiting for Name)
+        // and Race Conditions (Server sending Name immediately).
+
+        // The server writes the name only once every socket is connected
+        let handshake_future = Self::read_device_name(&mut video_reader);
+
+        let audio_connect_future = async {
+            let audio = if enable_audio {
+                tracing::info!("Audio enabled. Connecting to audio socket...");
+                match timeout(Self::CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+                    Ok(Ok(stream)) => {
+                        if let Err(e) = stream.set_nodelay(true) {
+                            tracing::warn!("Failed to set nodelay on audio socket: {}", e);
+                        }
+                        tracing::info!("Audio socket connected!");
+                        let (reader, _) = stream.into_split();
+                        Some(reader)
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!(
+                            "Failed to connect to audio socket: {}. Continuing without audio.",
+                            e
+                        );
+                        None
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            "Timeout connecting to audio socket. Continuing without audio."
+                        );
+                        None
+                    }
+                }
+            } else {
+                tracing::info!("Audio disabled. Skipping audio socket.");
+                None
+            };
+            // The server accepts the control socket last
+            (audio, Self::dial(addr).await)
+        };
+
+        // Run both concurrently
+        let (handshake_res, (audio_reader_res, control_res)) =
+            tokio::join!(handshake_future, audio_connect_future);
+
+        // Check handshake result
+        let device_name = handshake_res?;
+        let control = control_res.map_err(|e| {
+            NetworkError::ConnectionFailed(format!("Control socket not connected: {}", e))
+        })?;
+
+        Self::start(
+            (video_reader, video_writer),
+            audio_reader_res,
+            control,
+            device_name,
+        )
+        .await
+    }
+
     async fn recv(&mut self) -> Result<Packet> {
-        match self.packet_rx.recv().await {
-            Some(Ok(packet)) => {
-                self.stats.bytes_received += packet.data.len() as u64;
-                self.stats.packets_received += 1;
-                Ok(packet)
+        // Relays send no device messages
+        if let Some((reader, tx)) = self.pending_control.take() {
+            if !self.authenticated {
+                self.readers.push(Self::read_device_messages(reader, tx));
+            }
+        }
+        loop {
+            let packet = match self.packet_rx.recv().await {
+                Some(Ok(packet)) => packet,
+                Some(Err(e)) => return Err(e),
+                None => return Err(NetworkError::ConnectionClosed),
+            };
+            self.stats.bytes_received += packet.data.len() as u64;
+            self.stats.packets_received += 1;
+            let Some(cipher) = &self.cipher else {
+                return Ok(packet);
+            };
+            match cipher.open(&packet) {
+                Ok(packet) => return Ok(packet),
+                Err(e) => tracing::warn!("Dropping {:?} packet: {}", packet.packet_type, e),
             }
-            Some(Err(e)) => Err(e.into()),
-            None => Err(NetworkError::ConnectionClosed.into()),
         }
     }
 
     async fn send_control(&mut self, msg: ControlMessage) -> Result<()> {
-        let data = msg
-            .to_bytes()
-            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
-        let packet = Packet::new(PacketType::Control, 0, 0, data);
-        self.control_writer.write_all(&packet.to_bytes()).await?;
+        let data = msg.to_scrcpy_bytes().ok_or_else(|| {
+            NetworkError::Protocol(format!("scrcpy-server does not support {:?}", msg))
+        })?;
+        if self.authenticated {
+            // Framed like QUIC control messages, so a cipher covers them
+            let mut packet = Packet::new(PacketType::Control, 0, 0, data);
+            if let Some(cipher) = &self.cipher {
+                packet = cipher
+                    .seal(&packet)
+                    .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+            }
+            self.control_writer.write_all(&packet.to_bytes()).await?;
+        } else {
+            self.control_writer.write_all(&data).await?;
+        }
         self.control_writer.flush().await?;
         Ok(())
     }
 
+    /// Run the shared-secret handshake with a relay on the control socket
+    ///
+    /// scrcpy-server itself has no such step; only call this for relays.
+    async fn authenticate(&mut self, secret: &SharedSecret) -> Result<()> {
+        let Some((reader, _)) = self.pending_control.as_mut() else {
+            return Err(NetworkError::Protocol(
+                "Authentication must come before the first packet".to_string(),
+            ));
+        };
+        let writer = &mut self.control_writer;
+
+        let challenge = Self::auth_exchange(reader, writer, None).await?;
+        let (response, server_nonce, client_nonce) = secret
+            .respond(&challenge)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+        let verdict = Self::auth_exchange(reader, writer, Some(response)).await?;
+        secret
+            .verify_accept(&server_nonce, &client_nonce, &verdict)
+            .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+        self.cipher = secret
+            .client_cipher(&server_nonce, &client_nonce)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+        self.authenticated = true;
+
+        if self.cipher.is_some() {
+            tracing::info!("Authenticated with relay, payloads encrypted with AES-256-GCM");
+        } else {
+            tracing::info!("Authenticated with relay");
+        }
+        Ok(())
+    }
+
     fn stats(&self) -> NetworkStats {
         self.stats
     }
 
+    fn video_codec(&self) -> Option<VideoCodec> {
+        self.video_codec
+    }
+
+    fn device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
+    }
+
     async fn close(&mut self) -> Result<()> {
         self.packet_rx.close(); // Stop receiving
-        // Stream shutdown happens when dropped
+        for reader in self.readers.drain(..) {
+            reader.abort();
+        }
+        // Dropping the read halves closes the sockets; the server ends the
+        // session once its control socket closes
+        self.control_writer.shutdown().await?;
         Ok(())
     }
 }
@@ -307,4 +643,32 @@ mod tests {
         assert_eq!(deserialized.seq, 1);
         assert_eq!(deserialized.data, data);
     }
+
+    #[test]
+    fn test_parse_device_name() {
+        let field = |name: &[u8]| {
+            let mut raw = [0u8; 64];
+            raw[..name.len()].copy_from_slice(name);
+            raw
+        };
+        assert_eq!(
+            parse_device_name(&field(b"Pixel 7")).as_deref(),
+            Some("Pixel 7")
+        );
+        assert_eq!(
+            parse_device_name(&field("Galaxy Ｓ２３ 울트라".as_bytes())).as_deref(),
+            Some("Galaxy Ｓ２３ 울트라")
+        );
+        // Garbage after the terminator and padding around the name are ignored
+        assert_eq!(
+            parse_device_name(&field(b" Moto G\n\0\xff\xfe")).as_deref(),
+            Some("Moto G")
+        );
+        assert_eq!(parse_device_name(&[0u8; 64]), None);
+
+        // A name filling the field may end in half a character
+        let mut truncated = [b'a'; 64];
+        truncated[62..].copy_from_slice(&"한".as_bytes()[..2]);
+        assert_eq!(parse_device_name(&truncated), Some("a".repeat(62)));
+    }
 }
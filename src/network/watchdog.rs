@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+/// Time without video before the "no signal" overlay
+pub const NO_SIGNAL_AFTER: Duration = Duration::from_secs(2);
+
+/// Time without video before a keyframe is requested, which also tells
+/// whether the server still reads the control channel
+pub const KEYFRAME_AFTER: Duration = Duration::from_secs(5);
+
+/// What to do about a stalled stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Show that no video is arriving
+    NoSignal,
+    /// Ask the server for a keyframe
+    RequestKeyframe,
+    /// Give up on the connection and open it again
+    Reconnect,
+}
+
+/// Notices when video stops arriving without the connection failing
+///
+/// `recv()` waits as long as the server sends nothing, e.g. when its encoder
+/// hangs. The receive loop wakes up at [`deadline`](Self::deadline) and gets
+/// each action once per stall, in order of their delays.
+#[derive(Debug)]
+pub struct StreamWatchdog {
+    steps: Vec<(Duration, WatchdogAction)>,
+    /// Index of the next step due
+    next: usize,
+    last_video: Instant,
+}
+
+impl StreamWatchdog {
+    /// Watch from `now`, reconnecting after `reconnect_after` without video
+    /// (never if None)
+    pub fn new(reconnect_after: Option<Duration>, now: Instant) -> Self {
+        let mut steps = vec![
+            (NO_SIGNAL_AFTER, WatchdogAction::NoSignal),
+            (KEYFRAME_AFTER, WatchdogAction::RequestKeyframe),
+        ];
+        if let Some(after) = reconnect_after {
+            steps.push((after, WatchdogAction::Reconnect));
+            steps.sort_by_key(|(after, _)| *after);
+        }
+        Self {
+            steps,
+            next: 0,
+            last_video: now,
+        }
+    }
+
+    /// A video packet arrived; true if it ends a stall that was shown
+    pub fn on_video(&mut self, now: Instant) -> bool {
+        let stalled = self.next > 0;
+        self.next = 0;
+        self.last_video = now;
+        stalled
+    }
+
+    /// The connection was opened again: the stall goes on, its later steps
+    /// count from now
+    pub fn on_reconnect(&mut self, now: Instant) {
+        self.last_video = now;
+        self.next = self.next.min(
+            self.steps
+                .iter()
+                .position(|(_, action)| *action == WatchdogAction::RequestKeyframe)
+                .unwrap_or(0),
+        );
+    }
+
+    /// When the next action is due, None once all were taken
+    pub fn deadline(&self) -> Option<Instant> {
+        self.steps
+            .get(self.next)
+            .map(|(after, _)| self.last_video + *after)
+    }
+
+    /// The action due at `now`, if any
+    pub fn poll(&mut self, now: Instant) -> Option<WatchdogAction> {
+        if self.deadline()? > now {
+            return None;
+        }
+        let (_, action) = self.steps[self.next];
+        self.next += 1;
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_steps_in_order() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut watchdog = StreamWatchdog::new(Some(Duration::from_secs(10)), start);
+        assert_eq!(watchdog.poll(at(1)), None);
+        assert_eq!(watchdog.deadline(), Some(at(2)));
+        assert_eq!(watchdog.poll(at(2)), Some(WatchdogAction::NoSignal));
+        assert_eq!(watchdog.poll(at(3)), None);
+        assert_eq!(watchdog.poll(at(5)), Some(WatchdogAction::RequestKeyframe));
+        assert_eq!(watchdog.poll(at(10)), Some(WatchdogAction::Reconnect));
+        assert_eq!(watchdog.deadline(), None);
+
+        // Still no video after the reconnect: ask again, then reconnect again
+        watchdog.on_reconnect(at(11));
+        assert_eq!(watchdog.poll(at(16)), Some(WatchdogAction::RequestKeyframe));
+        assert_eq!(watchdog.poll(at(21)), Some(WatchdogAction::Reconnect));
+
+        assert!(watchdog.on_video(at(22)));
+        assert!(!watchdog.on_video(at(23)));
+        assert_eq!(watchdog.deadline(), Some(at(25)));
+    }
+
+    #[test]
+    fn test_short_timeout_reconnects_first() {
+        let start = Instant::now();
+        let mut watchdog = StreamWatchdog::new(Some(Duration::from_secs(1)), start);
+        assert_eq!(
+            watchdog.poll(start + Duration::from_secs(1)),
+            Some(WatchdogAction::Reconnect)
+        );
+        let mut never = StreamWatchdog::new(None, start);
+        never.poll(start + Duration::from_secs(60));
+        never.poll(start + Duration::from_secs(60));
+        assert_eq!(never.deadline(), None);
+    }
+}
@@ -0,0 +1,139 @@
+/// Keyboard and mouse over raw USB, without adb or a video stream
+///
+/// The PC acts as a USB accessory (Android Open Accessory 2.0) and registers
+/// HID devices with the phone, which then takes their reports as if a real
+/// keyboard and mouse were plugged in. This works with USB debugging off.
+use crate::network::ControlMessage;
+use anyhow::{bail, Context, Result};
+use rusb::{Direction, GlobalContext, Recipient, RequestType};
+use std::time::Duration;
+
+/// AOA control requests
+const ACCESSORY_GET_PROTOCOL: u8 = 51;
+const ACCESSORY_REGISTER_HID: u8 = 54;
+const ACCESSORY_UNREGISTER_HID: u8 = 55;
+const ACCESSORY_SET_HID_REPORT_DESC: u8 = 56;
+const ACCESSORY_SEND_HID_EVENT: u8 = 57;
+
+/// First AOA version with HID support
+const AOA_HID_VERSION: u16 = 2;
+
+const USB_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An Android device taking HID input over AOA
+pub struct AoaHid {
+    handle: rusb::DeviceHandle<GlobalContext>,
+    serial: String,
+    /// Ids registered with the device, unregistered on drop
+    registered: Vec<u16>,
+}
+
+impl AoaHid {
+    /// Open the USB device with this serial, or the only AOA-capable one
+    pub fn open(serial: Option<&str>) -> Result<Self> {
+        let mut found = Vec::new();
+        for device in rusb::devices()
+            .context("Failed to list USB devices")?
+            .iter()
+        {
+            let Ok(descriptor) = device.device_descriptor() else {
+                continue;
+            };
+            // Devices we may not open (no permission, other drivers) are skipped
+            let Ok(handle) = device.open() else {
+                continue;
+            };
+            let Ok(device_serial) = handle.read_serial_number_string_ascii(&descriptor) else {
+                continue;
+            };
+            if serial.is_some_and(|serial| serial != device_serial) {
+                continue;
+            }
+            match aoa_version(&handle) {
+                Ok(version) if version >= AOA_HID_VERSION => found.push((handle, device_serial)),
+                Ok(version) => tracing::debug!("{} speaks AOA {} only", device_serial, version),
+                Err(_) => {}
+            }
+        }
+
+        if found.len() > 1 {
+            let serials: Vec<&str> = found.iter().map(|(_, serial)| serial.as_str()).collect();
+            bail!(
+                "Several USB devices support AOA ({}), pick one with --serial",
+                serials.join(", ")
+            );
+        }
+        let Some((handle, serial)) = found.pop() else {
+            match serial {
+                Some(serial) => bail!("No USB device {} with AOA 2.0 support", serial),
+                None => bail!("No USB device with AOA 2.0 support found"),
+            }
+        };
+        tracing::info!("Opened {} over USB (AOA)", serial);
+        Ok(Self {
+            handle,
+            serial,
+            registered: Vec::new(),
+        })
+    }
+
+    /// USB serial number of the device
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Apply a UHID message as AOA HID requests; other messages are ignored
+    pub fn send(&mut self, msg: &ControlMessage) -> Result<()> {
+        match msg {
+            ControlMessage::UhidCreate {
+                id, report_desc, ..
+            } => {
+                let len = u16::try_from(report_desc.len()).context("HID report too long")?;
+                self.control(ACCESSORY_REGISTER_HID, *id, len, &[])?;
+                self.registered.push(*id);
+                self.control(ACCESSORY_SET_HID_REPORT_DESC, *id, 0, report_desc)?;
+            }
+            ControlMessage::UhidInput { id, data } => {
+                self.control(ACCESSORY_SEND_HID_EVENT, *id, 0, data)?;
+            }
+            ControlMessage::UhidDestroy { id } => {
+                self.registered.retain(|registered| registered != id);
+                self.control(ACCESSORY_UNREGISTER_HID, *id, 0, &[])?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn control(&self, request: u8, value: u16, index: u16, data: &[u8]) -> Result<()> {
+        let request_type =
+            rusb::request_type(Direction::Out, RequestType::Vendor, Recipient::Device);
+        self.handle
+            .write_control(request_type, request, value, index, data, USB_TIMEOUT)
+            .with_context(|| format!("AOA request {} failed", request))?;
+        Ok(())
+    }
+}
+
+impl Drop for AoaHid {
+    fn drop(&mut self) {
+        for id in std::mem::take(&mut self.registered) {
+            let _ = self.control(ACCESSORY_UNREGISTER_HID, id, 0, &[]);
+        }
+    }
+}
+
+/// AOA protocol version the device speaks (0 when it has no AOA)
+fn aoa_version(handle: &rusb::DeviceHandle<GlobalContext>) -> rusb::Result<u16> {
+    let request_type = rusb::request_type(Direction::In, RequestType::Vendor, Recipient::Device);
+    let mut version = [0u8; 2];
+    handle.read_control(
+        request_type,
+        ACCESSORY_GET_PROTOCOL,
+        0,
+        0,
+        &mut version,
+        USB_TIMEOUT,
+    )?;
+    Ok(u16::from_le_bytes(version))
+}
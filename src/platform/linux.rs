@@ -1,6 +1,16 @@
 // Linux specific implementation
+use std::path::PathBuf;
 use tracing::info;
 
 pub fn init_platform() {
     info!("Initializing Linux platform specific components");
 }
+
+/// Per-user configuration directory (`$XDG_CONFIG_HOME/scrcpy-custom`)
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("scrcpy-custom"))
+}
@@ -1,6 +1,12 @@
 // Windows specific implementation
+use std::path::PathBuf;
 use tracing::info;
 
 pub fn init_platform() {
     info!("Initializing Windows platform specific components");
 }
+
+/// Per-user configuration directory (`%APPDATA%\scrcpy-custom`)
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("scrcpy-custom"))
+}
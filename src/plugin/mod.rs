@@ -0,0 +1,164 @@
+/// Third-party packet, frame and control message processors
+///
+/// Plugins are Rust types implementing [`Plugin`], registered on a
+/// [`PluginHost`], or shared libraries loaded from a directory with the
+/// `plugins` feature (see `native` for the C ABI). Frame filters, which may
+/// change the pixels, are registered on the host too.
+use crate::network::{ControlMessage, Packet};
+use crate::video::{DecodedFrame, FrameFilter, FrameFilters};
+use parking_lot::Mutex;
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(feature = "plugins")]
+pub mod native;
+
+/// What to do with a packet or control message after a hook saw it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Keep,
+    Drop,
+}
+
+/// Hooks into the mirroring pipeline; every hook defaults to doing nothing
+pub trait Plugin: Send {
+    fn name(&self) -> &str;
+
+    /// Raw packet from the device, before decoding
+    fn on_packet(&mut self, _packet: &Packet) -> Verdict {
+        Verdict::Keep
+    }
+
+    /// Decoded video frame, on the decode thread
+    fn on_frame(&mut self, _frame: &DecodedFrame) {}
+
+    /// Control message about to be sent to the device
+    fn on_control(&mut self, _msg: &ControlMessage) -> Verdict {
+        Verdict::Keep
+    }
+}
+
+/// Loaded plugins, shared by the network and decode threads
+#[derive(Clone, Default)]
+pub struct PluginHost {
+    plugins: Arc<Mutex<Vec<Box<dyn Plugin>>>>,
+    filters: FrameFilters,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Host with every plugin library in `dir`
+    ///
+    /// Libraries that fail to load are skipped with a warning.
+    pub fn load_dir(dir: &Path) -> Self {
+        let host = Self::new();
+        #[cfg(feature = "plugins")]
+        for plugin in native::load_dir(dir) {
+            host.register(Box::new(plugin));
+        }
+        #[cfg(not(feature = "plugins"))]
+        tracing::warn!(
+            "Plugins in {} need the `plugins` feature, none loaded",
+            dir.display()
+        );
+        host
+    }
+
+    pub fn register(&self, plugin: Box<dyn Plugin>) {
+        tracing::info!("Loaded plugin {}", plugin.name());
+        self.plugins.lock().push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.lock().is_empty()
+    }
+
+    /// Add a frame filter, run on every decoded frame before the frame hooks
+    pub fn add_filter(&self, filter: Box<dyn FrameFilter>) {
+        self.filters.register(filter);
+    }
+
+    /// Registered frame filters
+    pub fn filters(&self) -> &FrameFilters {
+        &self.filters
+    }
+
+    /// Run the packet hooks; the first plugin to drop the packet wins
+    pub fn on_packet(&self, packet: &Packet) -> Verdict {
+        Self::first_drop(&mut self.plugins.lock(), |plugin| plugin.on_packet(packet))
+    }
+
+    /// Run the frame filters, then the frame hooks
+    pub fn on_frame(&self, frame: &mut DecodedFrame) {
+        self.filters.apply(frame);
+        for plugin in self.plugins.lock().iter_mut() {
+            plugin.on_frame(frame);
+        }
+    }
+
+    /// Run the control hooks; the first plugin to drop the message wins
+    pub fn on_control(&self, msg: &ControlMessage) -> Verdict {
+        Self::first_drop(&mut self.plugins.lock(), |plugin| plugin.on_control(msg))
+    }
+
+    fn first_drop(
+        plugins: &mut [Box<dyn Plugin>],
+        mut hook: impl FnMut(&mut dyn Plugin) -> Verdict,
+    ) -> Verdict {
+        if plugins
+            .iter_mut()
+            .any(|plugin| hook(plugin.as_mut()) == Verdict::Drop)
+        {
+            Verdict::Drop
+        } else {
+            Verdict::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PacketType;
+    use bytes::Bytes;
+
+    /// Drops audio and counts what it saw
+    struct MuteAudio {
+        seen: Arc<Mutex<usize>>,
+    }
+
+    impl Plugin for MuteAudio {
+        fn name(&self) -> &str {
+            "mute-audio"
+        }
+
+        fn on_packet(&mut self, packet: &Packet) -> Verdict {
+            *self.seen.lock() += 1;
+            match packet.packet_type {
+                PacketType::Audio => Verdict::Drop,
+                _ => Verdict::Keep,
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_runs_hooks_and_drops() {
+        let host = PluginHost::new();
+        assert!(host.is_empty());
+        let seen = Arc::new(Mutex::new(0));
+        host.register(Box::new(MuteAudio { seen: seen.clone() }));
+
+        let packet = |packet_type| Packet::new(packet_type, 0, 0, Bytes::from_static(b"x"));
+        assert_eq!(host.on_packet(&packet(PacketType::Video)), Verdict::Keep);
+        assert_eq!(host.on_packet(&packet(PacketType::Audio)), Verdict::Drop);
+        // Hooks a plugin leaves out keep everything
+        assert_eq!(
+            host.on_control(&ControlMessage::RequestKeyframe),
+            Verdict::Keep
+        );
+        assert_eq!(*seen.lock(), 2);
+    }
+}
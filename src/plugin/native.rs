@@ -0,0 +1,211 @@
+use super::{Plugin, Verdict};
+use crate::network::{ControlMessage, Packet};
+use crate::video::{DecodedFrame, PixelFormat};
+use anyhow::{Context, Result};
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr};
+use std::path::Path;
+
+/// Bumped on every incompatible change to [`PluginApi`]
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Symbol a plugin library exports: `extern "C" fn() -> *const PluginApi`
+pub const PLUGIN_ENTRY: &[u8] = b"scrcpy_plugin_init";
+
+/// Decoded frame as seen by a native plugin (valid during the call only)
+#[repr(C)]
+pub struct FrameView {
+    pub pts: i64,
+    pub width: u32,
+    pub height: u32,
+    /// 0 = YUV420P, 1 = NV12, 2 = RGBA, 3 = RGBA64 (16-bit little-endian)
+    pub format: u32,
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// Hook table returned by `scrcpy_plugin_init`; unused hooks are null
+///
+/// Hooks returning `i32` drop the packet or message when they return non-zero.
+/// Control messages are passed as JSON.
+#[repr(C)]
+pub struct PluginApi {
+    pub abi_version: u32,
+    /// NUL-terminated, owned by the plugin
+    pub name: *const c_char,
+    /// Passed back to every hook
+    pub state: *mut c_void,
+    pub on_packet: Option<
+        unsafe extern "C" fn(
+            state: *mut c_void,
+            kind: u8,
+            pts: i64,
+            data: *const u8,
+            len: usize,
+        ) -> i32,
+    >,
+    pub on_frame: Option<unsafe extern "C" fn(state: *mut c_void, frame: *const FrameView)>,
+    pub on_control:
+        Option<unsafe extern "C" fn(state: *mut c_void, json: *const u8, len: usize) -> i32>,
+    /// Called once before the library is unloaded
+    pub destroy: Option<unsafe extern "C" fn(state: *mut c_void)>,
+}
+
+/// Plugin loaded from a shared library
+pub struct NativePlugin {
+    name: String,
+    api: *const PluginApi,
+    // Dropped last: the hook table lives inside the library
+    _library: Library,
+}
+
+// The hook table is only used behind the host's mutex, one call at a time
+unsafe impl Send for NativePlugin {}
+
+impl NativePlugin {
+    pub fn load(path: &Path) -> Result<Self> {
+        // SAFETY: loading runs the library's initializers; plugins are trusted
+        // code the user put in the plugins directory
+        unsafe {
+            let library = Library::new(path).context("Failed to load library")?;
+            let api = {
+                let init = library
+                    .get::<unsafe extern "C" fn() -> *const PluginApi>(PLUGIN_ENTRY)
+                    .context("Missing scrcpy_plugin_init")?;
+                init()
+            };
+            if api.is_null() {
+                anyhow::bail!("scrcpy_plugin_init returned null");
+            }
+            if (*api).abi_version != PLUGIN_ABI_VERSION {
+                anyhow::bail!(
+                    "Plugin ABI {} is not supported (expected {})",
+                    (*api).abi_version,
+                    PLUGIN_ABI_VERSION
+                );
+            }
+            let name = if (*api).name.is_null() {
+                path.display().to_string()
+            } else {
+                CStr::from_ptr((*api).name).to_string_lossy().into_owned()
+            };
+            Ok(Self {
+                name,
+                api,
+                _library: library,
+            })
+        }
+    }
+
+    fn api(&self) -> &PluginApi {
+        // SAFETY: checked non-null on load, valid while the library is loaded
+        unsafe { &*self.api }
+    }
+}
+
+impl Plugin for NativePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_packet(&mut self, packet: &Packet) -> Verdict {
+        let api = self.api();
+        let Some(hook) = api.on_packet else {
+            return Verdict::Keep;
+        };
+        let data = &packet.data;
+        // SAFETY: the pointers outlive the call, as the ABI documents
+        let result = unsafe {
+            hook(
+                api.state,
+                packet.packet_type as u8,
+                packet.pts,
+                data.as_ptr(),
+                data.len(),
+            )
+        };
+        if result == 0 {
+            Verdict::Keep
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    fn on_frame(&mut self, frame: &DecodedFrame) {
+        let api = self.api();
+        let Some(hook) = api.on_frame else {
+            return;
+        };
+        let view = FrameView {
+            pts: frame.pts,
+            width: frame.width,
+            height: frame.height,
+            format: match frame.format {
+                PixelFormat::YUV420P => 0,
+                PixelFormat::NV12 => 1,
+                PixelFormat::RGBA => 2,
+                PixelFormat::RGBA64 => 3,
+            },
+            data: frame.data.as_ptr(),
+            len: frame.data.len(),
+        };
+        // SAFETY: the view and its pixels outlive the call
+        unsafe { hook(api.state, &view) };
+    }
+
+    fn on_control(&mut self, msg: &ControlMessage) -> Verdict {
+        let api = self.api();
+        let Some(hook) = api.on_control else {
+            return Verdict::Keep;
+        };
+        let Ok(json) = serde_json::to_vec(msg) else {
+            return Verdict::Keep;
+        };
+        // SAFETY: the buffer outlives the call
+        let result = unsafe { hook(api.state, json.as_ptr(), json.len()) };
+        if result == 0 {
+            Verdict::Keep
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+impl Drop for NativePlugin {
+    fn drop(&mut self) {
+        let api = self.api();
+        if let Some(destroy) = api.destroy {
+            // SAFETY: called once, before the library is unloaded
+            unsafe { destroy(api.state) };
+        }
+    }
+}
+
+/// Load every shared library in `dir` (`.so`, `.dll` or `.dylib`)
+pub fn load_dir(dir: &Path) -> Vec<NativePlugin> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Cannot read plugins directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+        })
+        .collect();
+    // Hooks run in load order, so keep it predictable
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            NativePlugin::load(&path)
+                .map_err(|e| tracing::warn!("Skipping plugin {}: {:#}", path.display(), e))
+                .ok()
+        })
+        .collect()
+}
@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// The PC going to sleep or waking up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    Suspending,
+    Resumed,
+}
+
+/// How often the wall clock is compared against the timer
+const CLOCK_TICK: Duration = Duration::from_secs(2);
+
+/// Wall clock advance beyond a tick that counts as having slept
+const CLOCK_GAP: Duration = Duration::from_secs(5);
+
+/// Spots a sleep after the fact by the wall clock jumping between ticks
+///
+/// Timers stop while the machine sleeps but the wall clock doesn't, so this
+/// works on every platform, only without the advance warning.
+struct ClockGap {
+    last: SystemTime,
+}
+
+impl ClockGap {
+    fn new(now: SystemTime) -> Self {
+        Self { last: now }
+    }
+
+    /// Whether the machine slept since the previous check
+    fn slept(&mut self, now: SystemTime) -> bool {
+        let elapsed = now.duration_since(self.last).unwrap_or_default();
+        self.last = now;
+        elapsed > CLOCK_TICK + CLOCK_GAP
+    }
+}
+
+/// Reads logind `PrepareForSleep` signals from `dbus-monitor` output
+///
+/// The signal header line is followed by its argument, `boolean true`
+/// before sleeping and `boolean false` after waking.
+#[derive(Default)]
+struct SleepSignals {
+    in_signal: bool,
+}
+
+impl SleepSignals {
+    fn line(&mut self, line: &str) -> Option<PowerEvent> {
+        if line.starts_with("signal ") {
+            self.in_signal = line.contains("member=PrepareForSleep");
+            return None;
+        }
+        if !std::mem::take(&mut self.in_signal) {
+            return None;
+        }
+        match line.trim() {
+            "boolean true" => Some(PowerEvent::Suspending),
+            "boolean false" => Some(PowerEvent::Resumed),
+            _ => None,
+        }
+    }
+}
+
+/// Reports suspend and resume of the PC
+///
+/// On Linux logind announces both; elsewhere (or without logind) a resume is
+/// noticed from the clock within a couple of seconds.
+pub struct PowerWatcher {
+    rx: mpsc::UnboundedReceiver<PowerEvent>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl PowerWatcher {
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let clock_tx = tx.clone();
+        let clock = tokio::spawn(async move {
+            let mut clock = ClockGap::new(SystemTime::now());
+            loop {
+                tokio::time::sleep(CLOCK_TICK).await;
+                if clock.slept(SystemTime::now()) && clock_tx.send(PowerEvent::Resumed).is_err() {
+                    break;
+                }
+            }
+        });
+        #[cfg(target_os = "linux")]
+        let tasks = vec![clock, tokio::spawn(Self::follow_logind(tx))];
+        #[cfg(not(target_os = "linux"))]
+        let tasks = {
+            drop(tx);
+            vec![clock]
+        };
+
+        Self { rx, tasks }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn follow_logind(tx: mpsc::UnboundedSender<PowerEvent>) {
+        use std::process::Stdio;
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let spawned = tokio::process::Command::new("dbus-monitor")
+            .args([
+                "--system",
+                "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                debug!(
+                    "Suspend notifications unavailable ({}), watching the clock",
+                    e
+                );
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return;
+        };
+        let mut lines = BufReader::new(stdout).lines();
+        let mut signals = SleepSignals::default();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(event) = signals.line(&line) {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Next suspend or resume (None once no source is left)
+    pub async fn next(&mut self) -> Option<PowerEvent> {
+        let event = self.rx.recv().await?;
+        info!("System power event: {:?}", event);
+        Some(event)
+    }
+}
+
+impl Drop for PowerWatcher {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_detection() {
+        let mut signals = SleepSignals::default();
+        let header = "signal time=1700000000.1 sender=:1.2 -> destination=(null destination) serial=9 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep";
+        assert_eq!(signals.line(header), None);
+        assert_eq!(
+            signals.line("   boolean true"),
+            Some(PowerEvent::Suspending)
+        );
+        // Arguments only count right after the signal line
+        assert_eq!(signals.line("   boolean false"), None);
+        signals.line(header);
+        assert_eq!(signals.line("   boolean false"), Some(PowerEvent::Resumed));
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut clock = ClockGap::new(start);
+        assert!(!clock.slept(start + CLOCK_TICK));
+        assert!(clock.slept(start + CLOCK_TICK + Duration::from_secs(600)));
+    }
+}
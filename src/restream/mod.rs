@@ -0,0 +1,193 @@
+/// Republishing of the encoded device stream to other viewers
+///
+/// The network thread feeds every video packet into a [`PacketTap`]; outputs
+/// subscribe to it and repackage the stream without decoding it.
+use crate::config::VideoCodec;
+use crate::network::Packet;
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub mod mpegts;
+#[cfg(feature = "webrtc")]
+pub mod webrtc;
+
+/// Packets an output may fall behind by before it has to resync
+const CAPACITY: usize = 512;
+
+/// What a new subscriber needs before the next keyframe arrives
+#[derive(Debug, Default)]
+struct StreamInfo {
+    codec: Option<VideoCodec>,
+    /// Latest codec config (SPS/PPS), sent once per stream
+    config: Option<Bytes>,
+}
+
+/// Fan-out of the encoded video stream, cheap to clone
+#[derive(Debug, Clone)]
+pub struct PacketTap {
+    tx: broadcast::Sender<Packet>,
+    info: Arc<Mutex<StreamInfo>>,
+    keyframe_wanted: Arc<AtomicBool>,
+}
+
+/// Stream state at subscription time, then the packets that follow
+pub struct TapSubscription {
+    pub codec: Option<VideoCodec>,
+    pub config: Option<Bytes>,
+    pub packets: broadcast::Receiver<Packet>,
+    keyframe_wanted: Arc<AtomicBool>,
+    synced: bool,
+}
+
+/// Frame ready to repackage
+#[derive(Debug, Clone)]
+pub struct AccessUnit {
+    /// Microseconds
+    pub pts: i64,
+    pub keyframe: bool,
+    /// Annex B; keyframes start with the latest codec config
+    pub data: Bytes,
+}
+
+impl Default for PacketTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketTap {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+            info: Arc::default(),
+            keyframe_wanted: Arc::default(),
+        }
+    }
+
+    /// Codec of the session that is starting
+    pub fn set_codec(&self, codec: VideoCodec) {
+        *self.info.lock() = StreamInfo {
+            codec: Some(codec),
+            config: None,
+        };
+    }
+
+    pub fn codec(&self) -> Option<VideoCodec> {
+        self.info.lock().codec
+    }
+
+    /// Pass a video packet (config or frame) on to the subscribers
+    pub fn publish(&self, packet: &Packet) {
+        if packet.flags.config {
+            self.info.lock().config = Some(packet.data.clone());
+        }
+        // Fails only when nobody listens
+        let _ = self.tx.send(packet.clone());
+    }
+
+    pub fn subscribe(&self) -> TapSubscription {
+        let info = self.info.lock();
+        TapSubscription {
+            codec: info.codec,
+            config: info.config.clone(),
+            packets: self.tx.subscribe(),
+            keyframe_wanted: self.keyframe_wanted.clone(),
+            synced: false,
+        }
+    }
+
+    /// Ask the device for a keyframe, e.g. for a viewer that just joined
+    pub fn request_keyframe(&self) {
+        self.keyframe_wanted.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether an output asked for a keyframe since the last call
+    pub fn take_keyframe_request(&self) -> bool {
+        self.keyframe_wanted.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl TapSubscription {
+    /// Next frame a decoder can use, None once the tap is gone
+    ///
+    /// Starts at a keyframe. After falling behind, frames are skipped up to
+    /// the next keyframe, which is requested from the device.
+    pub async fn next_frame(&mut self) -> Option<AccessUnit> {
+        loop {
+            let packet = match self.packets.recv().await {
+                Ok(packet) => packet,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::debug!("Re-stream output skipped {} packets", skipped);
+                    self.synced = false;
+                    self.keyframe_wanted.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if packet.flags.config {
+                self.config = Some(packet.data);
+                continue;
+            }
+            let keyframe = packet.is_keyframe();
+            if !keyframe && !self.synced {
+                continue;
+            }
+            self.synced = true;
+            let data = match (&self.config, keyframe) {
+                (Some(config), true) => {
+                    let mut joined = BytesMut::with_capacity(config.len() + packet.data.len());
+                    joined.extend_from_slice(config);
+                    joined.extend_from_slice(&packet.data);
+                    joined.freeze()
+                }
+                _ => packet.data,
+            };
+            return Some(AccessUnit {
+                pts: packet.pts,
+                keyframe,
+                data,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PacketType;
+
+    #[test]
+    fn test_late_subscriber_gets_config() {
+        let tap = PacketTap::new();
+        tap.set_codec(VideoCodec::H264);
+        let mut config = Packet::new(PacketType::Video, 0, 0, Bytes::from_static(b"sps"));
+        config.flags.config = true;
+        tap.publish(&config);
+
+        let mut late = tap.subscribe();
+        assert_eq!(late.codec, Some(VideoCodec::H264));
+        assert_eq!(late.config.as_deref(), Some(&b"sps"[..]));
+
+        // Frames before the first keyframe are useless to a new viewer
+        tap.publish(&Packet::new(
+            PacketType::Video,
+            1,
+            1,
+            Bytes::from_static(b"p"),
+        ));
+        let mut idr = Packet::new(PacketType::Video, 2, 2, Bytes::from_static(b"idr"));
+        idr.flags.keyframe = true;
+        tap.publish(&idr);
+        let frame = futures::executor::block_on(late.next_frame()).unwrap();
+        assert_eq!((frame.pts, frame.keyframe), (2, true));
+        assert_eq!(&frame.data[..], b"spsidr");
+
+        assert!(!tap.take_keyframe_request());
+        tap.request_keyframe();
+        assert!(tap.take_keyframe_request());
+        assert!(!tap.take_keyframe_request());
+    }
+}
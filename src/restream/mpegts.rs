@@ -0,0 +1,458 @@
+use super::{AccessUnit, PacketTap, TapSubscription};
+use crate::api::read_request;
+use crate::config::VideoCodec;
+use anyhow::{Context, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+const PACKET_SIZE: usize = 188;
+const PAYLOAD_SIZE: usize = PACKET_SIZE - 4;
+const PAT_PID: u16 = 0x0000;
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x0100;
+
+/// Seven TS packets, the usual UDP payload (fits a 1500 byte MTU)
+const DATAGRAM_SIZE: usize = 7 * PACKET_SIZE;
+
+/// PTS lead over the PCR, giving players time to decode (100 ms at 90 kHz)
+const PTS_DELAY: u64 = 9_000;
+
+/// Largest 33-bit timestamp
+const TIMESTAMP_MASK: u64 = (1 << 33) - 1;
+
+/// MPEG-2 transport stream muxer for one video elementary stream
+///
+/// Tables go out before every keyframe so players can join at any of them.
+pub struct TsMuxer {
+    codec: VideoCodec,
+    start_pts: Option<i64>,
+    pat_cc: u8,
+    pmt_cc: u8,
+    video_cc: u8,
+}
+
+impl TsMuxer {
+    /// None for codecs MPEG-TS has no stream type for (AV1)
+    pub fn new(codec: VideoCodec) -> Option<Self> {
+        stream_type(codec)?;
+        Some(Self {
+            codec,
+            start_pts: None,
+            pat_cc: 0,
+            pmt_cc: 0,
+            video_cc: 0,
+        })
+    }
+
+    pub fn codec(&self) -> VideoCodec {
+        self.codec
+    }
+
+    /// Append the TS packets of `frame` to `out`
+    pub fn write_frame(&mut self, frame: &AccessUnit, out: &mut Vec<u8>) {
+        if frame.keyframe || self.start_pts.is_none() {
+            self.write_tables(out);
+        }
+        let start = *self.start_pts.get_or_insert(frame.pts);
+        // Microseconds to 90 kHz
+        let pcr = (frame.pts - start).max(0) as u64 * 9 / 100;
+
+        let aud: &[u8] = match self.codec {
+            VideoCodec::H264 => &[0, 0, 0, 1, 0x09, 0xF0],
+            _ => &[0, 0, 0, 1, 0x46, 0x01, 0x50],
+        };
+        let mut pes = Vec::with_capacity(14 + aud.len() + frame.data.len());
+        // Video stream 0, unbounded length, PTS only
+        pes.extend_from_slice(&[0, 0, 1, 0xE0, 0, 0, 0x80, 0x80, 5]);
+        pes.extend_from_slice(&encode_pts(pcr + PTS_DELAY));
+        // Access unit delimiter, which some hardware decoders insist on
+        pes.extend_from_slice(aud);
+        pes.extend_from_slice(&frame.data);
+
+        write_payload(
+            out,
+            VIDEO_PID,
+            &mut self.video_cc,
+            &pes,
+            Some(pcr),
+            frame.keyframe,
+        );
+    }
+
+    fn write_tables(&mut self, out: &mut Vec<u8>) {
+        // Program 1, its PMT on PMT_PID
+        let pat = [
+            0x00,
+            0xB0,
+            13,
+            0x00,
+            0x01,
+            0xC1,
+            0x00,
+            0x00,
+            0x00,
+            0x01,
+            0xE0 | (PMT_PID >> 8) as u8,
+            PMT_PID as u8,
+        ];
+        write_section(out, PAT_PID, &mut self.pat_cc, &pat);
+
+        // One video stream, also carrying the PCR
+        let stream_type = stream_type(self.codec).unwrap_or_default();
+        let pmt = [
+            0x02,
+            0xB0,
+            18,
+            0x00,
+            0x01,
+            0xC1,
+            0x00,
+            0x00,
+            0xE0 | (VIDEO_PID >> 8) as u8,
+            VIDEO_PID as u8,
+            0xF0,
+            0x00,
+            stream_type,
+            0xE0 | (VIDEO_PID >> 8) as u8,
+            VIDEO_PID as u8,
+            0xF0,
+            0x00,
+        ];
+        write_section(out, PMT_PID, &mut self.pmt_cc, &pmt);
+    }
+}
+
+fn stream_type(codec: VideoCodec) -> Option<u8> {
+    match codec {
+        VideoCodec::H264 => Some(0x1B),
+        VideoCodec::H265 => Some(0x24),
+        VideoCodec::Av1 => None,
+    }
+}
+
+fn packet_header(out: &mut Vec<u8>, pid: u16, cc: &mut u8, start: bool, adaptation: bool) {
+    out.push(0x47);
+    out.push(((start as u8) << 6) | (pid >> 8) as u8);
+    out.push(pid as u8);
+    out.push((if adaptation { 0x30 } else { 0x10 }) | *cc);
+    *cc = (*cc + 1) & 0x0F;
+}
+
+/// One PSI section (pointer field, section, CRC) in a single packet
+fn write_section(out: &mut Vec<u8>, pid: u16, cc: &mut u8, section: &[u8]) {
+    let end = out.len() + PACKET_SIZE;
+    packet_header(out, pid, cc, true, false);
+    out.push(0);
+    out.extend_from_slice(section);
+    out.extend_from_slice(&crc32_mpeg2(section).to_be_bytes());
+    out.resize(end, 0xFF);
+}
+
+/// Split a PES packet into TS packets, stuffing the last one
+fn write_payload(
+    out: &mut Vec<u8>,
+    pid: u16,
+    cc: &mut u8,
+    payload: &[u8],
+    pcr: Option<u64>,
+    random_access: bool,
+) {
+    let mut offset = 0;
+    while offset < payload.len() {
+        let first = offset == 0;
+        // Adaptation field after its length byte: flags, then the PCR
+        let mut fields = Vec::new();
+        if first && (pcr.is_some() || random_access) {
+            fields.push(((random_access as u8) << 6) | ((pcr.is_some() as u8) << 4));
+            if let Some(pcr) = pcr {
+                fields.extend_from_slice(&encode_pcr(pcr));
+            }
+        }
+        let minimum = if fields.is_empty() {
+            0
+        } else {
+            1 + fields.len()
+        };
+        let left = payload.len() - offset;
+        // Grow the adaptation field to stuff a short last packet
+        let adaptation = minimum.max(PAYLOAD_SIZE.saturating_sub(left));
+
+        packet_header(out, pid, cc, first, adaptation > 0);
+        if adaptation > 0 {
+            out.push((adaptation - 1) as u8);
+            if adaptation > 1 {
+                if fields.is_empty() {
+                    fields.push(0x00);
+                }
+                out.extend_from_slice(&fields);
+                out.extend(std::iter::repeat(0xFF).take(adaptation - 1 - fields.len()));
+            }
+        }
+        let take = PAYLOAD_SIZE - adaptation;
+        out.extend_from_slice(&payload[offset..offset + take]);
+        offset += take;
+    }
+}
+
+fn encode_pts(pts: u64) -> [u8; 5] {
+    let pts = pts & TIMESTAMP_MASK;
+    [
+        0x20 | ((pts >> 29) as u8 & 0x0E) | 1,
+        (pts >> 22) as u8,
+        ((pts >> 14) as u8 & 0xFE) | 1,
+        (pts >> 7) as u8,
+        ((pts << 1) as u8 & 0xFE) | 1,
+    ]
+}
+
+/// 33-bit base, reserved bits, zero extension
+fn encode_pcr(base: u64) -> [u8; 6] {
+    let base = base & TIMESTAMP_MASK;
+    [
+        (base >> 25) as u8,
+        (base >> 17) as u8,
+        (base >> 9) as u8,
+        (base >> 1) as u8,
+        (((base & 1) as u8) << 7) | 0x7E,
+        0,
+    ]
+}
+
+/// CRC-32/MPEG-2 of a PSI section
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The tapped stream remuxed to MPEG-TS for one receiver
+struct TsStream {
+    tap: PacketTap,
+    subscription: TapSubscription,
+    muxer: Option<TsMuxer>,
+    warned: bool,
+}
+
+impl TsStream {
+    fn new(tap: &PacketTap) -> Self {
+        // Start the receiver on a fresh keyframe rather than the next periodic one
+        tap.request_keyframe();
+        Self {
+            tap: tap.clone(),
+            subscription: tap.subscribe(),
+            muxer: None,
+            warned: false,
+        }
+    }
+
+    /// Append the packets of the next frame to `out`, false once the tap is gone
+    async fn mux_next(&mut self, out: &mut Vec<u8>) -> bool {
+        loop {
+            let Some(frame) = self.subscription.next_frame().await else {
+                return false;
+            };
+            let Some(codec) = self.tap.codec() else {
+                continue;
+            };
+            // A new session may bring a new codec
+            if self.muxer.as_ref().map(TsMuxer::codec) != Some(codec) {
+                self.muxer = TsMuxer::new(codec);
+            }
+            match &mut self.muxer {
+                Some(muxer) => {
+                    muxer.write_frame(&frame, out);
+                    return true;
+                }
+                None if !self.warned => {
+                    warn!("MPEG-TS cannot carry {:?}, use --codec h264 or h265", codec);
+                    self.warned = true;
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Send the stream as MPEG-TS datagrams to `target` until `shutdown`
+///
+/// Works with unicast and multicast addresses (`ffplay udp://@239.0.0.1:5000`).
+pub async fn serve_udp(
+    target: SocketAddr,
+    tap: PacketTap,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let local: SocketAddr = if target.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)
+        .await
+        .context("Failed to open the MPEG-TS socket")?;
+    info!("Sending MPEG-TS to udp://{}", target);
+
+    let mut stream = TsStream::new(&tap);
+    let mut out = Vec::new();
+    loop {
+        out.clear();
+        let more = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            more = stream.mux_next(&mut out) => more,
+        };
+        if !more {
+            return Ok(());
+        }
+        for datagram in out.chunks(DATAGRAM_SIZE) {
+            if let Err(e) = socket.send_to(datagram, target).await {
+                debug!("MPEG-TS datagram to {} failed: {}", target, e);
+            }
+        }
+    }
+}
+
+/// Serve the stream as MPEG-TS over HTTP on `addr` until `shutdown`
+///
+/// Any GET gets an endless `video/mp2t` response (`vlc http://host:port/`).
+pub async fn serve_http(
+    addr: SocketAddr,
+    tap: PacketTap,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind MPEG-TS server on {}", addr))?;
+    info!("Serving MPEG-TS at http://{}/", addr);
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            accepted = listener.accept() => accepted?,
+        };
+        let (tap, shutdown) = (tap.clone(), shutdown.clone());
+        tokio::spawn(async move {
+            match stream_to(stream, &tap, shutdown).await {
+                Ok(()) => debug!("MPEG-TS viewer {} left", peer),
+                Err(e) => debug!("MPEG-TS viewer {}: {:#}", peer, e),
+            }
+        });
+    }
+}
+
+/// Write the stream to an MPEG-TS file at `path` until `shutdown`
+///
+/// The file plays as it grows (`ffplay`, VLC) and stays playable if the
+/// recording is cut off.
+pub async fn record(path: &Path, tap: PacketTap, shutdown: CancellationToken) -> Result<()> {
+    let file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut file = tokio::io::BufWriter::new(file);
+    info!("Recording MPEG-TS to {}", path.display());
+
+    let mut ts = TsStream::new(&tap);
+    let mut out = Vec::new();
+    loop {
+        out.clear();
+        let more = tokio::select! {
+            _ = shutdown.cancelled() => false,
+            more = ts.mux_next(&mut out) => more,
+        };
+        if !more {
+            break;
+        }
+        file.write_all(&out)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+async fn stream_to(stream: TcpStream, tap: &PacketTap, shutdown: CancellationToken) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader, 0).await?;
+    let stream = reader.get_mut();
+    if !matches!(&request, Some(request) if request.method == "GET") {
+        stream
+            .write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    }
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: video/mp2t\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut ts = TsStream::new(tap);
+    let mut out = Vec::new();
+    loop {
+        out.clear();
+        let more = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            more = ts.mux_next(&mut out) => more,
+        };
+        if !more {
+            return Ok(());
+        }
+        stream.write_all(&out).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_keyframe_is_muxed_with_tables() {
+        let mut muxer = TsMuxer::new(VideoCodec::H264).unwrap();
+        assert!(TsMuxer::new(VideoCodec::Av1).is_none());
+
+        let mut out = Vec::new();
+        muxer.write_frame(
+            &AccessUnit {
+                pts: 1_000_000,
+                keyframe: true,
+                data: Bytes::from(vec![0x65; 1000]),
+            },
+            &mut out,
+        );
+        assert_eq!(out.len() % PACKET_SIZE, 0);
+        assert!(out.chunks(PACKET_SIZE).all(|packet| packet[0] == 0x47));
+
+        // The PAT every player expects for program 1 on PID 0x1000
+        assert_eq!(
+            &out[..21],
+            &[
+                0x47, 0x40, 0x00, 0x10, 0x00, 0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00,
+                0x01, 0xF0, 0x00, 0x2A, 0xB1, 0x04, 0xB2
+            ]
+        );
+        // The PES starts the third packet, after a PCR adaptation field
+        let video = &out[2 * PACKET_SIZE..];
+        assert_eq!(&video[..4], &[0x47, 0x41, 0x00, 0x30]);
+        let adaptation = video[4] as usize;
+        assert_eq!(&video[5 + adaptation..][..4], &[0, 0, 1, 0xE0]);
+
+        // The whole frame is there once, payload bytes only
+        let payload: usize = out[2 * PACKET_SIZE..]
+            .chunks(PACKET_SIZE)
+            .map(|packet| match packet[3] & 0x30 {
+                0x30 => PAYLOAD_SIZE - 1 - packet[4] as usize,
+                _ => PAYLOAD_SIZE,
+            })
+            .sum();
+        assert_eq!(payload, 14 + 6 + 1000);
+    }
+}
@@ -0,0 +1,204 @@
+use super::PacketTap;
+use crate::api::{read_request, write_response};
+use crate::config::VideoCodec;
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
+use webrtc::api::{APIBuilder, API};
+use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+/// Page that plays the stream, served at `/`
+const VIEWER_PAGE: &str = include_str!("viewer.html");
+
+/// Largest SDP offer accepted
+const MAX_OFFER: usize = 64 * 1024;
+
+/// Serve the mirror to browsers on `addr` until `shutdown`
+///
+/// `GET /` is a page that posts its SDP offer to `POST /offer` and plays
+/// the answer's track. Every viewer shares one H.264 track fed from `tap`,
+/// repackaged into RTP without decoding.
+pub async fn serve(addr: SocketAddr, tap: PacketTap, shutdown: CancellationToken) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind WebRTC gateway on {}", addr))?;
+    info!("WebRTC viewer page at http://{}/", addr);
+
+    let mut media = MediaEngine::default();
+    media.register_default_codecs()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media)?;
+    let api = Arc::new(
+        APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(registry)
+            .build(),
+    );
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            ..Default::default()
+        },
+        "video".to_owned(),
+        "screen-mirror".to_owned(),
+    ));
+    tokio::spawn(feed(track.clone(), tap.clone(), shutdown.clone()));
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            accepted = listener.accept() => accepted?,
+        };
+        let (api, track, tap, shutdown) =
+            (api.clone(), track.clone(), tap.clone(), shutdown.clone());
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &api, track, tap, shutdown).await {
+                warn!("WebRTC request from {} failed: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn respond(
+    stream: TcpStream,
+    api: &API,
+    track: Arc<TrackLocalStaticSample>,
+    tap: PacketTap,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let (status, content_type, body) = match read_request(&mut reader, MAX_OFFER).await? {
+        None => (413, "text/plain", b"Offer too large".to_vec()),
+        Some(request) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => (200, "text/html; charset=utf-8", VIEWER_PAGE.into()),
+            ("POST", "/offer") => {
+                let offer = String::from_utf8_lossy(&request.body).into_owned();
+                match answer(api, offer, track, tap, shutdown).await {
+                    Ok(sdp) => (200, "application/sdp", sdp.into_bytes()),
+                    Err(e) => (400, "text/plain", format!("{:#}", e).into_bytes()),
+                }
+            }
+            (_, "/" | "/offer") => (405, "text/plain", b"Method not allowed".to_vec()),
+            _ => (404, "text/plain", b"Not found".to_vec()),
+        },
+    };
+    write_response(reader.get_mut(), status, content_type, &body).await
+}
+
+/// Connect one viewer, returning the SDP answer to its offer
+///
+/// ICE candidates are gathered before answering, so the page needs no
+/// trickle ICE.
+async fn answer(
+    api: &API,
+    offer: String,
+    track: Arc<TrackLocalStaticSample>,
+    tap: PacketTap,
+    shutdown: CancellationToken,
+) -> Result<String> {
+    let pc = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await?);
+    let sender = pc
+        .add_track(track as Arc<dyn TrackLocal + Send + Sync>)
+        .await?;
+
+    // The browser asks for a keyframe when it joins or loses one
+    let pli_tap = tap.clone();
+    tokio::spawn(async move {
+        while let Ok((packets, _)) = sender.read_rtcp().await {
+            if packets
+                .iter()
+                .any(|p| p.as_any().is::<PictureLossIndication>())
+            {
+                pli_tap.request_keyframe();
+            }
+        }
+    });
+
+    let (gone_tx, mut gone_rx) = tokio::sync::mpsc::channel(1);
+    pc.on_peer_connection_state_change(Box::new(move |state: RTCPeerConnectionState| {
+        debug!("WebRTC viewer {}", state);
+        match state {
+            RTCPeerConnectionState::Connected => tap.request_keyframe(),
+            RTCPeerConnectionState::Failed
+            | RTCPeerConnectionState::Disconnected
+            | RTCPeerConnectionState::Closed => {
+                let _ = gone_tx.try_send(());
+            }
+            _ => {}
+        }
+        Box::pin(async {})
+    }));
+
+    pc.set_remote_description(RTCSessionDescription::offer(offer)?)
+        .await?;
+    let answer = pc.create_answer(None).await?;
+    let mut gathered = pc.gathering_complete_promise().await;
+    pc.set_local_description(answer).await?;
+    let _ = gathered.recv().await;
+    let local = pc
+        .local_description()
+        .await
+        .context("No local description")?;
+
+    let viewer = pc.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            _ = gone_rx.recv() => {}
+        }
+        if let Err(e) = viewer.close().await {
+            debug!("Closing WebRTC viewer: {}", e);
+        }
+    });
+    Ok(local.sdp)
+}
+
+/// Write the tapped H.264 stream to the shared track
+async fn feed(track: Arc<TrackLocalStaticSample>, tap: PacketTap, shutdown: CancellationToken) {
+    let mut subscription = tap.subscribe();
+    let mut last_pts = None;
+    let mut warned = false;
+    loop {
+        let frame = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            frame = subscription.next_frame() => match frame {
+                Some(frame) => frame,
+                None => return,
+            },
+        };
+        if tap.codec() != Some(VideoCodec::H264) {
+            if !warned {
+                warn!("WebRTC viewers need an H.264 stream (--codec h264)");
+                warned = true;
+            }
+            continue;
+        }
+
+        let duration = last_pts.map_or(Duration::ZERO, |last: i64| {
+            Duration::from_micros(frame.pts.saturating_sub(last).max(0) as u64)
+        });
+        last_pts = Some(frame.pts);
+        let sample = Sample {
+            data: frame.data,
+            duration,
+            ..Default::default()
+        };
+        if let Err(e) = track.write_sample(&sample).await {
+            debug!("WebRTC write failed: {}", e);
+        }
+    }
+}
@@ -1,14 +1,82 @@
-use super::config::Config;
+use super::config::{Config, TunnelMode};
 use crate::assets::Assets;
 use anyhow::{Context, Result};
-use std::time::Duration;
+use std::collections::{HashSet, VecDeque};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
 use tokio::process::Command;
+use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
 
-pub struct ServerManager;
+/// The configured forward port is taken by something other than a stale session
+#[derive(Debug, thiserror::Error)]
+#[error("Local port {port} is {owner}; set another connection.forward_port or free the port")]
+pub struct PortInUse {
+    pub port: u16,
+    pub owner: String,
+}
+
+/// One line of `adb forward --list`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ForwardEntry {
+    serial: String,
+    local: String,
+    remote: String,
+}
+
+/// How the client reaches a server started by `ServerManager`
+pub enum Tunnel {
+    /// Connect to this local port (`adb forward`)
+    Forward(u16),
+    /// Accept the server's connections here (`adb reverse`)
+    Reverse(TcpListener),
+}
+
+/// Starts the scrcpy server over ADB and undoes everything on shutdown
+///
+/// Call `stop()` on the way out; if that never happens (early return,
+/// cancelled task) dropping the manager runs the same teardown blocking.
+pub struct ServerManager {
+    adb_path: PathBuf,
+    serial: Option<String>,
+    /// Session id; the server listens on `localabstract:scrcpy_<scid>`
+    scid: u32,
+    /// Local side of the `adb forward` we created
+    forward: Option<String>,
+    /// Device side of the `adb reverse` we created
+    reverse: Option<String>,
+    server: Option<JoinHandle<()>>,
+    /// Last `[SERVER ERR]` lines, kept to explain early connection failures
+    stderr: Arc<Mutex<VecDeque<String>>>,
+    started_at: Option<Instant>,
+    stopped: bool,
+}
 
 impl ServerManager {
+    /// Server main class
+    const SERVER_CLASS: &'static str = "com.genymobile.scrcpy.Server";
+
+    /// `adb connect` tries while adbd restarts in TCP mode
+    const WIRELESS_CONNECT_ATTEMPTS: u32 = 10;
+    const WIRELESS_CONNECT_DELAY: Duration = Duration::from_millis(500);
+
+    /// Where server jars live on the device
+    const REMOTE_DIR: &'static str = "/data/local/tmp";
+
+    /// Per-command limit during teardown so a dead device can't hang exit
+    const TEARDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Failures this soon after start are blamed on the server's stderr
+    /// (longer than connecting waits for the server to come up)
+    const STDERR_WINDOW: Duration = Duration::from_secs(20);
+
+    /// Server stderr lines kept for error reports
+    const STDERR_LINES: usize = 20;
+
     pub async fn new() -> Result<Self> {
         // Verify ADB is accessible
         let adb_path = Assets::get_adb_path()?;
@@ -21,10 +89,102 @@ impl ServerManager {
         if !status.success() {
             anyhow::bail!("adb start-server failed with exit code: {}", status);
         }
-        Ok(Self)
+        // 31 bits, as the server parses it into a signed int
+        let scid = ring::rand::generate::<[u8; 4]>(&ring::rand::SystemRandom::new())
+            .map(|id| u32::from_le_bytes(id.expose()) & 0x7fff_ffff)
+            .map_err(|_| anyhow::anyhow!("Failed to generate session id"))?;
+
+        Ok(Self {
+            adb_path,
+            serial: None,
+            scid,
+            forward: None,
+            reverse: None,
+            server: None,
+            stderr: Arc::default(),
+            started_at: None,
+            stopped: false,
+        })
     }
 
-    pub async fn start_server(&mut self, config: &Config, serial: Option<&str>) -> Result<()> {
+    /// adb serial of the device the server runs on, once known
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Resolve the serial when the device was picked implicitly
+    ///
+    /// Needed before a second adb device appears (e.g. the same phone over
+    /// WiFi), after which commands without `-s` are ambiguous.
+    pub async fn pin_serial(&mut self) -> Result<String> {
+        if let Some(serial) = &self.serial {
+            return Ok(serial.clone());
+        }
+        let serial = Self::device_serial(&self.adb_path)
+            .await
+            .context("Could not determine the device serial")?;
+        self.serial = Some(serial.clone());
+        Ok(serial)
+    }
+
+    /// Switch a USB device to adb over WiFi, returning its wireless serial
+    ///
+    /// Reads the WLAN address, restarts adbd in TCP mode on `port` (unless it
+    /// already listens there) and connects to it.
+    pub async fn enable_wireless(&self, usb_serial: &str, port: u16) -> Result<String> {
+        let adb = |args: &[&str]| {
+            let mut cmd = Command::new(&self.adb_path);
+            cmd.args(["-s", usb_serial]).args(args);
+            cmd
+        };
+
+        let routes = adb(&["shell", "ip", "route"])
+            .output()
+            .await
+            .context("Failed to read device routes")?;
+        let ip = parse_wlan_ip(&String::from_utf8_lossy(&routes.stdout))
+            .context("Device is not on a WiFi network")?;
+        let wireless_serial = format!("{}:{}", ip, port);
+
+        let current = adb(&["shell", "getprop", "service.adb.tcp.port"])
+            .output()
+            .await
+            .context("Failed to read adb TCP port")?;
+        if String::from_utf8_lossy(&current.stdout).trim() != port.to_string() {
+            info!("Restarting adbd in TCP mode on port {}...", port);
+            let status = adb(&["tcpip", &port.to_string()])
+                .status()
+                .await
+                .context("Failed to run adb tcpip")?;
+            if !status.success() {
+                anyhow::bail!("adb tcpip {} failed", port);
+            }
+        }
+
+        // adbd needs a moment to come back up listening on TCP
+        for _ in 0..Self::WIRELESS_CONNECT_ATTEMPTS {
+            tokio::time::sleep(Self::WIRELESS_CONNECT_DELAY).await;
+            let output = Command::new(&self.adb_path)
+                .args(["connect", &wireless_serial])
+                .output()
+                .await
+                .context("Failed to run adb connect")?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if stdout.contains("connected to") {
+                info!("Device reachable over WiFi at {}", wireless_serial);
+                return Ok(wireless_serial);
+            }
+        }
+        anyhow::bail!("Could not connect to {} over WiFi", wireless_serial)
+    }
+
+    /// Device socket name for this session
+    pub fn socket_name(&self) -> String {
+        format!("scrcpy_{:08x}", self.scid)
+    }
+
+    /// Push and start the server, returning how to reach it
+    pub async fn start_server(&mut self, config: &Config, serial: Option<&str>) -> Result<Tunnel> {
         let serial = serial.map(|s| s.to_string());
 
         // 1. Check devices
@@ -80,73 +240,86 @@ impl ServerManager {
             }
         }
 
-        // 3. Push scrcpy-server.jar
-        let local_jar = Assets::get_server_path()?;
-
-        info!("Pushing {:?} to device...", local_jar);
-
-        let mut push_cmd = Command::new(&adb_path);
-        if let Some(s) = &target_serial {
-            push_cmd.args(["-s", s]);
-        }
-
-        let status = push_cmd
-            .arg("push")
-            .arg(local_jar)
-            .arg("/data/local/tmp/scrcpy-server")
-            .status()
-            .await
-            .context("Failed to push server jar")?;
+        // Set before the tunnel exists so teardown removes it on this device
+        self.serial = target_serial.clone();
 
-        if !status.success() {
-            anyhow::bail!("Failed to push scrcpy-server.jar to device.");
-        }
-
-        // 4. Setup port forwarding (Forward PC port 5555 to Device socket)
-        info!("Setting up port forwarding...");
-        let mut forward_cmd = Command::new(&adb_path);
-        if let Some(s) = &target_serial {
-            forward_cmd.args(["-s", s]);
-        }
-        // adb forward tcp:5555 localabstract:scrcpy
-        let status = forward_cmd
-            .args(["forward", "tcp:5555", "localabstract:scrcpy"])
-            .status()
-            .await
-            .context("Failed to run adb forward")?;
-
-        if !status.success() {
-            warn!("adb forward failed.");
-        }
+        // 3 + 4. Push the server (unless the device already has this build)
+        // while setting up the tunnel; neither needs the other
+        let (server_path, tunnel) = tokio::try_join!(
+            Self::push_server(
+                &adb_path,
+                target_serial.as_deref(),
+                config.server.force_push
+            ),
+            self.open_tunnel(&adb_path, target_serial.as_deref(), config),
+        )?;
 
         // 5. Start server
         info!("Starting server...");
         let bitrate_arg = format!("video_bit_rate={}", config.video.bitrate * 1000000);
-        let tunnel_forward = "tunnel_forward=true";
-        let control = "control=false"; // FORCED: Output only
+        let tunnel_forward = format!(
+            "tunnel_forward={}",
+            config.connection.tunnel == TunnelMode::Forward
+        );
+        // Input goes over the control socket; nothing on the PC takes the
+        // device clipboard, so don't have it streamed back
+        let control = "control=true clipboard_autosync=false";
         let audio = format!("audio={}", config.audio.enabled);
         let audio_codec = format!("audio_codec={}", config.audio.codec.to_server_arg());
-        let audio_dup = "audio_dup=false"; // output sound to computer only
-        let video = "video=true";
+        let audio_source = format!("audio_source={}", config.audio.source.to_server_arg());
+        let audio_dup = format!("audio_dup={}", config.audio.duplicate);
+        let video = format!("video={}", config.video.enabled);
+        let video_codec = format!("video_codec={}", config.video.codec.to_server_arg());
         let max_size = format!("max_size={}", config.video.max_size);
+        let capture_orientation = config
+            .video
+            .lock_orientation
+            .map(|o| format!(" capture_orientation={}", o.to_locked_server_arg()))
+            .unwrap_or_default();
+        // Session-only device settings, put back by the server's cleanup
+        let mut session_settings = String::new();
+        if config.server.stay_awake {
+            session_settings.push_str(" stay_awake=true");
+        }
+        if config.server.show_touches {
+            session_settings.push_str(" show_touches=true");
+        }
         let cleanup = "cleanup=true"; // Clean up on exit
+        let scid = format!("scid={:08x}", self.scid);
 
+        // The server's cleanup deletes the jar it runs from; run a copy so the
+        // pushed build stays for the next launch
+        let session_path = format!("{}/scrcpy-server_{:08x}", Self::REMOTE_DIR, self.scid);
         let cmd_string = format!(
-            "CLASSPATH=/data/local/tmp/scrcpy-server app_process / com.genymobile.scrcpy.Server 3.3.3 {} {} {} {} {} {} {} {} {}",
+            "cp {} {} && CLASSPATH={} app_process / com.genymobile.scrcpy.Server 3.3.3 {} {} {} {} {} {} {} {} {} {} {}{}{} {}",
+            server_path,
+            session_path,
+            session_path,
+            scid,
             tunnel_forward,
             bitrate_arg,
             control,
             audio,
             audio_codec,
+            audio_source,
             audio_dup,
             video,
+            video_codec,
             max_size,
+            capture_orientation,
+            session_settings,
             cleanup
         );
 
         let serial_clone = target_serial.clone();
 
-        tokio::spawn(async move {
+        let stderr_tail = self.stderr.clone();
+        if let Ok(mut tail) = stderr_tail.lock() {
+            tail.clear();
+        }
+        self.started_at = Some(Instant::now());
+
+        self.server = Some(tokio::spawn(async move {
             let mut server_cmd = match Assets::get_adb_path() {
                 Ok(p) => Command::new(p),
                 Err(_) => Command::new("adb"), // Fallback unlikely to work if get_adb_path failed before
@@ -158,13 +331,19 @@ impl ServerManager {
             use std::process::Stdio;
 
             info!("Executing server command on device...");
-            let mut child = server_cmd
+            let mut child = match server_cmd
                 .args(["shell", &cmd_string])
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
                 .kill_on_drop(true) // Ensure process is killed when the task/handle drops
                 .spawn()
-                .expect("Failed to spawn server command");
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("Failed to spawn server command: {}", e);
+                    return;
+                }
+            };
 
             let stdout = child.stdout.take().unwrap();
             let stderr = child.stderr.take().unwrap();
@@ -183,6 +362,12 @@ impl ServerManager {
                 let mut lines = reader.lines();
                 while let Ok(Some(line)) = lines.next_line().await {
                     warn!("[SERVER ERR] {}", line);
+                    if let Ok(mut tail) = stderr_tail.lock() {
+                        if tail.len() == Self::STDERR_LINES {
+                            tail.pop_front();
+                        }
+                        tail.push_back(line);
+                    }
                 }
             });
 
@@ -198,11 +383,621 @@ impl ServerManager {
                 }
                 Err(e) => error!("Failed to run server command: {}", e),
             }
-        });
+        }));
+
+        // No waiting for the server to come up: connecting retries until it
+        // listens (or, in reverse mode, waits for it to dial in)
+        Ok(tunnel)
+    }
+
+    /// Push scrcpy-server to the device unless it already has this build
+    ///
+    /// Each build gets its own device path, named after its hash, which is
+    /// returned.
+    async fn push_server(
+        adb_path: &Path,
+        serial: Option<&str>,
+        force_push: bool,
+    ) -> Result<String> {
+        let local_jar = Assets::get_server_path()?;
+        let local_hash = sha256_file(&local_jar)?;
+        let remote_path = Self::remote_path(&local_hash);
+
+        let remote_hash = if force_push {
+            None
+        } else {
+            Self::remote_sha256(adb_path, serial, &remote_path).await
+        };
+
+        if remote_hash.as_deref() == Some(local_hash.as_str()) {
+            info!("Server on device is up to date, skipping push");
+        } else {
+            info!("Pushing {:?} to device...", local_jar);
+
+            // Other builds stay: another client may be running one of them
+            let mut push = Command::new(adb_path);
+            if let Some(s) = serial {
+                push.args(["-s", s]);
+            }
+            let status = push
+                .arg("push")
+                .arg(local_jar)
+                .arg(&remote_path)
+                .status()
+                .await
+                .context("Failed to push server jar")?;
+
+            if !status.success() {
+                anyhow::bail!("Failed to push scrcpy-server.jar to device.");
+            }
+        }
+
+        Ok(remote_path)
+    }
+
+    /// Device path for the server build hashing to `sha256`
+    fn remote_path(sha256: &str) -> String {
+        format!("{}/scrcpy-server-{}", Self::REMOTE_DIR, &sha256[..16])
+    }
+
+    /// Set up the adb tunnel to this session's socket
+    async fn open_tunnel(
+        &mut self,
+        adb_path: &Path,
+        serial: Option<&str>,
+        config: &Config,
+    ) -> Result<Tunnel> {
+        Ok(match config.connection.tunnel {
+            TunnelMode::Forward => {
+                info!("Setting up port forwarding...");
+                let forwards = self.remove_stale_forwards(adb_path, serial).await;
+                // tcp:0 lets adb pick a free port (and print it), so parallel
+                // sessions and adb-over-tcp on 5555 don't collide
+                let local = match config.connection.forward_port {
+                    Some(port) => {
+                        Self::check_port_free(port, &forwards)?;
+                        format!("tcp:{}", port)
+                    }
+                    None => "tcp:0".to_string(),
+                };
+
+                let mut forward_cmd = Command::new(adb_path);
+                if let Some(s) = serial {
+                    forward_cmd.args(["-s", s]);
+                }
+                // Never take over a forward someone else set up meanwhile
+                let output = forward_cmd
+                    .args([
+                        "forward",
+                        "--no-rebind",
+                        &local,
+                        &format!("localabstract:{}", self.socket_name()),
+                    ])
+                    .output()
+                    .await
+                    .context("Failed to run adb forward")?;
+
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "adb forward failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                let port = match config.connection.forward_port {
+                    Some(port) => port,
+                    None => parse_forward_port(&String::from_utf8_lossy(&output.stdout))
+                        .context("adb forward did not report a local port")?,
+                };
+                self.forward = Some(format!("tcp:{}", port));
+                Tunnel::Forward(port)
+            }
+            TunnelMode::Reverse => {
+                // Listen first: the server connects as soon as it starts
+                info!("Setting up reverse tunnel...");
+                let listener = TcpListener::bind(("127.0.0.1", 0))
+                    .await
+                    .context("Failed to open a local port for adb reverse")?;
+                let port = listener.local_addr()?.port();
+                let remote = format!("localabstract:{}", self.socket_name());
+
+                let mut reverse_cmd = Command::new(adb_path);
+                if let Some(s) = serial {
+                    reverse_cmd.args(["-s", s]);
+                }
+                let output = reverse_cmd
+                    .args(["reverse", &remote, &format!("tcp:{}", port)])
+                    .output()
+                    .await
+                    .context("Failed to run adb reverse")?;
+
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "adb reverse failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                self.reverse = Some(remote);
+                Tunnel::Reverse(listener)
+            }
+        })
+    }
+
+    /// Remove forwards left behind by sessions whose server is gone
+    ///
+    /// A crashed client never runs its teardown, so its forward keeps the
+    /// local port. Returns the forwards that remain.
+    async fn remove_stale_forwards(
+        &self,
+        adb_path: &Path,
+        serial: Option<&str>,
+    ) -> Vec<ForwardEntry> {
+        let adb = |args: &[&str]| {
+            let mut cmd = Command::new(adb_path);
+            if let Some(s) = serial {
+                cmd.args(["-s", s]);
+            }
+            cmd.args(args);
+            cmd
+        };
+        let forwards = match adb(&["forward", "--list"]).output().await {
+            Ok(output) => parse_forward_list(&String::from_utf8_lossy(&output.stdout)),
+            Err(e) => {
+                warn!("Could not list adb forwards: {}", e);
+                return Vec::new();
+            }
+        };
+        // `--list` covers every device; only this one's servers can be checked
+        let ours: Vec<&ForwardEntry> = forwards
+            .iter()
+            .filter(|f| serial.is_none() || serial == Some(f.serial.as_str()))
+            .filter(|f| forward_scid(&f.remote).is_some())
+            .collect();
+        if ours.is_empty() {
+            return forwards;
+        }
+
+        let running = match adb(&["shell", "ps", "-A", "-o", "ARGS"]).output().await {
+            Ok(output) if output.status.success() => {
+                parse_running_scids(&String::from_utf8_lossy(&output.stdout))
+            }
+            // Without a process list nothing can be called stale
+            _ => return forwards,
+        };
+        let mut stale = Vec::new();
+        for forward in ours {
+            if forward_scid(&forward.remote).is_some_and(|scid| !running.contains(&scid)) {
+                info!(
+                    "Removing stale forward {} -> {}",
+                    forward.local, forward.remote
+                );
+                match adb(&["forward", "--remove", &forward.local]).status().await {
+                    Ok(status) if status.success() => stale.push(forward.clone()),
+                    _ => warn!("Failed to remove stale forward {}", forward.local),
+                }
+            }
+        }
+        forwards
+            .into_iter()
+            .filter(|f| !stale.contains(f))
+            .collect()
+    }
+
+    /// Fail clearly if `port` can't be forwarded, instead of at connect time
+    fn check_port_free(port: u16, forwards: &[ForwardEntry]) -> Result<()> {
+        let local = format!("tcp:{}", port);
+        if let Some(forward) = forwards.iter().find(|f| f.local == local) {
+            return Err(PortInUse {
+                port,
+                owner: format!(
+                    "already forwarded to {} on {}",
+                    forward.remote, forward.serial
+                ),
+            }
+            .into());
+        }
+        if std::net::TcpListener::bind(("127.0.0.1", port)).is_err() {
+            return Err(PortInUse {
+                port,
+                owner: "in use by another program".to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
 
-        // Give it a moment to initialize
-        tokio::time::sleep(Duration::from_millis(2000)).await;
+    /// Attach the server's stderr to `error` if the server started moments ago
+    ///
+    /// Early failures almost always have their cause in the device-side
+    /// output, which is otherwise easy to miss among the client logs.
+    pub fn explain(&self, error: anyhow::Error) -> anyhow::Error {
+        let recent = self
+            .started_at
+            .is_some_and(|started| started.elapsed() < Self::STDERR_WINDOW);
+        if !recent {
+            return error;
+        }
+        let lines: Vec<String> = match self.stderr.lock() {
+            Ok(tail) => tail.iter().cloned().collect(),
+            Err(_) => return error,
+        };
+        with_server_output(error, &lines)
+    }
+
+    /// SHA-256 of `path` on the device, if the file is there
+    async fn remote_sha256(adb_path: &Path, serial: Option<&str>, path: &str) -> Option<String> {
+        let mut cmd = Command::new(adb_path);
+        if let Some(s) = serial {
+            cmd.args(["-s", s]);
+        }
+        let output = cmd.args(["shell", "sha256sum", path]).output().await.ok()?;
+        parse_sha256sum(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Serials of every device adb can talk to (unauthorized and offline ones left out)
+    pub async fn devices() -> Result<Vec<String>> {
+        let output = Command::new(Assets::get_adb_path()?)
+            .arg("devices")
+            .output()
+            .await
+            .context("Failed to list devices")?;
+        Ok(parse_device_list(&String::from_utf8_lossy(&output.stdout)))
+    }
 
+    /// Pair with a device for wireless debugging (Android 11+)
+    ///
+    /// `address` and `code` are the ones shown under "Pair device with pairing
+    /// code" on the device. Once paired, `adb connect` works without a cable.
+    pub async fn pair(address: &str, code: &str) -> Result<()> {
+        let output = Command::new(Assets::get_adb_path()?)
+            .args(["pair", address, code])
+            .output()
+            .await
+            .context("Failed to run adb pair")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // adb pair exits with 0 even when the code is wrong
+        if !stdout.contains("Successfully paired") {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = [stdout.trim(), stderr.trim()]
+                .into_iter()
+                .find(|text| !text.is_empty())
+                .unwrap_or("no output");
+            anyhow::bail!("Pairing with {} failed: {}", address, message);
+        }
         Ok(())
     }
+
+    /// Blocking `adb logcat` for `serial`, printing in the `threadtime` format
+    ///
+    /// Starts with the last `backlog` lines. Meant for a thread of its own;
+    /// stdout is piped.
+    pub fn logcat_command(serial: Option<&str>, backlog: u32) -> Result<std::process::Command> {
+        let mut cmd = std::process::Command::new(Assets::get_adb_path()?);
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        cmd.args(["logcat", "-v", "threadtime", "-T", &backlog.to_string()])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null());
+        Ok(cmd)
+    }
+
+    /// Blocking interactive `adb shell` for `serial`, with stdin and stdout piped
+    ///
+    /// `-t -t` makes the device allocate a PTY even though stdin is a pipe, so
+    /// the shell prompts, echoes and handles Ctrl+C like in a terminal.
+    pub fn shell_command(serial: Option<&str>) -> Result<std::process::Command> {
+        let mut cmd = std::process::Command::new(Assets::get_adb_path()?);
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        cmd.args(["shell", "-t", "-t"])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null());
+        Ok(cmd)
+    }
+
+    /// Process ids of `package` on the device (blocking), empty if it is not running
+    pub fn pids_of(serial: Option<&str>, package: &str) -> Result<HashSet<u32>> {
+        let mut cmd = std::process::Command::new(Assets::get_adb_path()?);
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        let output = cmd
+            .args(["shell", "pidof", package])
+            .output()
+            .context("Failed to run adb shell pidof")?;
+        // pidof exits with 1 when nothing matches
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .filter_map(|pid| pid.parse().ok())
+            .collect())
+    }
+
+    /// Output of `dumpsys <service>` on the device (blocking)
+    pub fn dumpsys(serial: Option<&str>, service: &str) -> Result<String> {
+        let mut cmd = std::process::Command::new(Assets::get_adb_path()?);
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        let output = cmd
+            .args(["shell", "dumpsys", service])
+            .output()
+            .with_context(|| format!("Failed to run adb shell dumpsys {}", service))?;
+        if !output.status.success() {
+            anyhow::bail!("dumpsys {} failed", service);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Serial of the only connected device, for when none was given
+    async fn device_serial(adb_path: &Path) -> Option<String> {
+        let output = Command::new(adb_path)
+            .arg("get-serialno")
+            .output()
+            .await
+            .ok()?;
+        let serial = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !serial.is_empty() && serial != "unknown").then_some(serial)
+    }
+
+    /// Turn the device display off (`KEYCODE_SLEEP`, a no-op if already off)
+    pub async fn sleep_display(&self) -> Result<()> {
+        let status = Command::new(&self.adb_path)
+            .args(self.adb_args(&["shell", "input", "keyevent", "KEYCODE_SLEEP"]))
+            .status()
+            .await
+            .context("Failed to run adb shell input")?;
+        if !status.success() {
+            anyhow::bail!("input keyevent KEYCODE_SLEEP failed");
+        }
+        Ok(())
+    }
+
+    /// Stop the server and remove the ADB tunnel
+    pub async fn stop(&mut self) -> Result<()> {
+        if self.stopped {
+            return Ok(());
+        }
+        self.stopped = true;
+        info!("Cleaning up device...");
+
+        for args in self.teardown_commands() {
+            let run = Command::new(&self.adb_path).args(&args).status();
+            match tokio::time::timeout(Self::TEARDOWN_TIMEOUT, run).await {
+                Ok(Ok(status)) if status.success() => {}
+                Ok(Ok(status)) => warn!("adb {} exited with {}", args.join(" "), status),
+                Ok(Err(e)) => warn!("adb {} failed: {}", args.join(" "), e),
+                Err(_) => warn!("adb {} timed out", args.join(" ")),
+            }
+        }
+
+        // Kills the local `adb shell` (kill_on_drop)
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+        Ok(())
+    }
+
+    /// adb invocations that undo the session, in order
+    fn teardown_commands(&self) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        if self.server.is_some() {
+            // Match our scid only: another session (e.g. the WiFi one taking
+            // over from USB) may run a server on the same device. Quoted for
+            // the device shell, which adb hands the command line to.
+            let pattern = format!("'{}.*scid={:08x}'", Self::SERVER_CLASS, self.scid);
+            commands.push(self.adb_args(&["shell", "pkill", "-f", &pattern]));
+        }
+        if let Some(forward) = &self.forward {
+            commands.push(self.adb_args(&["forward", "--remove", forward]));
+        }
+        if let Some(reverse) = &self.reverse {
+            commands.push(self.adb_args(&["reverse", "--remove", reverse]));
+        }
+        commands
+    }
+
+    /// Prefix `args` with the device selector
+    fn adb_args(&self, args: &[&str]) -> Vec<String> {
+        let mut full = Vec::with_capacity(args.len() + 2);
+        if let Some(serial) = &self.serial {
+            full.push("-s".to_string());
+            full.push(serial.clone());
+        }
+        full.extend(args.iter().map(|a| a.to_string()));
+        full
+    }
+}
+
+/// `error` followed by the server's stderr lines, if it wrote any
+fn with_server_output(error: anyhow::Error, lines: &[String]) -> anyhow::Error {
+    if lines.is_empty() {
+        return error;
+    }
+    anyhow::anyhow!("{:#}\nServer output:\n  {}", error, lines.join("\n  "))
+}
+
+/// Hex SHA-256 of a local file
+fn sha256_file(path: &Path) -> Result<String> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, &data);
+    Ok(digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Hash from `sha256sum` output (`<hash>  <path>`); `None` for errors like a missing file
+fn parse_sha256sum(stdout: &str) -> Option<String> {
+    let hash = stdout.split_whitespace().next()?;
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+/// WLAN source address from `ip route` (`... dev wlan0 proto kernel scope link src 192.168.1.23`)
+fn parse_wlan_ip(routes: &str) -> Option<IpAddr> {
+    routes
+        .lines()
+        .filter(|line| line.contains("dev wlan"))
+        .find_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|w| *w == "src")?;
+            words.next()?.parse().ok()
+        })
+}
+
+/// Entries of `adb forward --list` (`<serial> <local> <remote>` per line)
+fn parse_forward_list(stdout: &str) -> Vec<ForwardEntry> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(ForwardEntry {
+                serial: fields.next()?.to_string(),
+                local: fields.next()?.to_string(),
+                remote: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Session id of a forward to one of our servers
+fn forward_scid(remote: &str) -> Option<u32> {
+    let scid = remote.strip_prefix("localabstract:scrcpy_")?;
+    u32::from_str_radix(scid, 16).ok()
+}
+
+/// Session ids of the servers in a device process list
+fn parse_running_scids(ps: &str) -> HashSet<u32> {
+    ps.lines()
+        .filter(|line| line.contains(ServerManager::SERVER_CLASS))
+        .flat_map(str::split_whitespace)
+        .filter_map(|arg| u32::from_str_radix(arg.strip_prefix("scid=")?, 16).ok())
+        .collect()
+}
+
+/// Ready devices in `adb devices` output
+fn parse_device_list(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, state)| state.trim() == "device")
+        .map(|(serial, _)| serial.to_string())
+        .collect()
+}
+
+/// Port printed by `adb forward tcp:0 ...`
+fn parse_forward_port(stdout: &str) -> Option<u16> {
+    stdout.lines().find_map(|line| line.trim().parse().ok())
+}
+
+impl Drop for ServerManager {
+    fn drop(&mut self) {
+        if self.stopped {
+            return;
+        }
+        // No runtime to await on here; exit is the only thing waiting
+        for args in self.teardown_commands() {
+            let _ = std::process::Command::new(&self.adb_path)
+                .args(&args)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status();
+        }
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forward_port() {
+        assert_eq!(parse_forward_port("41235\n"), Some(41235));
+        assert_eq!(parse_forward_port("* daemon started *\n5037\n"), Some(5037));
+        assert_eq!(parse_forward_port(""), None);
+    }
+
+    #[test]
+    fn test_parse_device_list() {
+        let devices = parse_device_list(
+            "List of devices attached\n\
+             R5CT1234\tdevice\n\
+             192.168.1.20:5555\tdevice\n\
+             emulator-5554\tunauthorized\n\n",
+        );
+        assert_eq!(devices, ["R5CT1234", "192.168.1.20:5555"]);
+        assert!(parse_device_list("* daemon started *\nList of devices attached\n").is_empty());
+    }
+
+    #[test]
+    fn test_stale_forward_detection() {
+        let forwards = parse_forward_list(
+            "R5CT1234 tcp:41235 localabstract:scrcpy_0a1b2c3d\n\
+             R5CT1234 tcp:27183 localabstract:scrcpy\n\
+             R5CT1234 tcp:41300 localabstract:scrcpy_00000007\n",
+        );
+        assert_eq!(forwards.len(), 3);
+        assert_eq!(forwards[0].local, "tcp:41235");
+        assert_eq!(forward_scid(&forwards[0].remote), Some(0x0a1b2c3d));
+        // Plain scrcpy's socket has no session id and is never ours to remove
+        assert_eq!(forward_scid(&forwards[1].remote), None);
+
+        let running = parse_running_scids(
+            "ARGS\n\
+             app_process / com.genymobile.scrcpy.Server 3.3.3 scid=00000007 tunnel_forward=true\n\
+             sh -c echo scid=0a1b2c3d\n",
+        );
+        assert_eq!(running, HashSet::from([7]));
+
+        let err = ServerManager::check_port_free(27183, &forwards).unwrap_err();
+        assert!(err.is::<PortInUse>());
+        assert!(err.to_string().contains("localabstract:scrcpy on R5CT1234"));
+    }
+
+    #[test]
+    fn test_parse_wlan_ip() {
+        let routes = "10.0.0.0/8 dev rmnet_data1 proto kernel scope link src 10.44.2.7\n\
+                      192.168.1.0/24 dev wlan0 proto kernel scope link src 192.168.1.23\n";
+        assert_eq!(parse_wlan_ip(routes), Some("192.168.1.23".parse().unwrap()));
+        assert_eq!(parse_wlan_ip("10.0.0.0/8 dev rmnet0 src 10.0.0.2\n"), None);
+    }
+
+    #[test]
+    fn test_parse_sha256sum() {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(
+            parse_sha256sum(&format!("{}  /data/local/tmp/scrcpy-server\n", hash)).as_deref(),
+            Some(hash)
+        );
+        assert_eq!(
+            parse_sha256sum("sha256sum: /data/local/tmp/scrcpy-server: No such file or directory"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_with_server_output() {
+        let error = anyhow::anyhow!("Connection refused").context("Failed to connect");
+        assert_eq!(
+            with_server_output(error, &[]).to_string(),
+            "Failed to connect"
+        );
+
+        let lines = [
+            "ERROR: Could not open video stream".to_string(),
+            "java.lang.IllegalStateException".to_string(),
+        ];
+        let error = with_server_output(anyhow::anyhow!("Failed to connect"), &lines);
+        assert_eq!(
+            error.to_string(),
+            "Failed to connect\nServer output:\n  ERROR: Could not open video stream\n  java.lang.IllegalStateException"
+        );
+    }
 }
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+/// Exponentially weighted moving average
+///
+/// Each sample moves the average `alpha` of the way towards it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// Average seeded by its first sample
+    pub const fn new(alpha: f64) -> Self {
+        Self { alpha, value: None }
+    }
+
+    /// Average starting from `initial` instead of the first sample
+    pub const fn with_initial(alpha: f64, initial: f64) -> Self {
+        Self {
+            alpha,
+            value: Some(initial),
+        }
+    }
+
+    /// Add a sample, returning the new average
+    pub fn add(&mut self, sample: f64) -> f64 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// Current average, 0.0 before any sample
+    pub fn get(&self) -> f64 {
+        self.value.unwrap_or(0.0)
+    }
+
+    /// Whether the average has a value yet
+    pub fn is_set(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+/// Percentiles over the most recent samples
+#[derive(Debug, Clone)]
+pub struct Percentiles {
+    capacity: usize,
+    samples: VecDeque<f64>,
+}
+
+impl Percentiles {
+    /// Track the last `capacity` samples
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn add(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Samples in the window, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Nearest-rank percentile (`p` in 0.0 - 1.0), `None` without samples
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let index = ((sorted.len() as f64 - 1.0) * p.clamp(0.0, 1.0)).round() as usize;
+        sorted.get(index).copied()
+    }
+
+    pub fn p50(&self) -> Option<f64> {
+        self.percentile(0.5)
+    }
+
+    pub fn p95(&self) -> Option<f64> {
+        self.percentile(0.95)
+    }
+
+    pub fn p99(&self) -> Option<f64> {
+        self.percentile(0.99)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ewma() {
+        let mut seeded = Ewma::new(0.5);
+        assert!(!seeded.is_set());
+        assert_eq!(seeded.add(10.0), 10.0);
+        assert_eq!(seeded.add(20.0), 15.0);
+
+        let mut from_zero = Ewma::with_initial(0.25, 0.0);
+        assert_eq!(from_zero.add(8.0), 2.0);
+        assert_eq!(from_zero.get(), 2.0);
+    }
+
+    #[test]
+    fn test_percentiles_keep_recent_samples() {
+        let mut window = Percentiles::new(5);
+        assert_eq!(window.p50(), None);
+        for sample in [100.0, 4.0, 2.0, 3.0, 10.0, 1.0] {
+            window.add(sample);
+        }
+        // 100 fell out of the window
+        assert_eq!(window.len(), 5);
+        assert_eq!(window.p50(), Some(3.0));
+        assert_eq!(window.p99(), Some(10.0));
+        assert_eq!(window.percentile(0.0), Some(1.0));
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Session statistics for one second, a row of the `--stats-log` file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct StatsRow {
+    /// Seconds since the Unix epoch
+    pub unix_time: f64,
+    /// Seconds since the session started
+    pub elapsed_s: f64,
+    /// Video frames received per second
+    pub fps: f64,
+    /// Received stream rate, audio and video
+    pub bitrate_mbps: f64,
+    pub rtt_ms: f64,
+    /// Packet loss percentage (0.0 - 100.0)
+    pub loss_percent: f64,
+    /// Video PTS minus audio PTS of the latest packets (None without audio)
+    pub drift_ms: Option<f64>,
+    /// Decoded frames replaced before the renderer took them, this session
+    pub frames_dropped: u64,
+    /// Packets dropped before the decoder, this session
+    pub packets_dropped: u64,
+    /// Packets waiting for the decoder
+    pub decode_queue: usize,
+    /// Audio jitter buffer fill (0.0 - 1.0, None without audio)
+    pub audio_buffer: Option<f32>,
+}
+
+impl StatsRow {
+    const CSV_HEADER: &'static str = "unix_time,elapsed_s,fps,bitrate_mbps,rtt_ms,loss_percent,\
+        drift_ms,frames_dropped,packets_dropped,decode_queue,audio_buffer";
+
+    fn csv_line(&self) -> String {
+        let optional = |value: Option<f64>| value.map(|v| format!("{:.1}", v)).unwrap_or_default();
+        format!(
+            "{:.3},{:.1},{:.1},{:.2},{:.1},{:.2},{},{},{},{},{}",
+            self.unix_time,
+            self.elapsed_s,
+            self.fps,
+            self.bitrate_mbps,
+            self.rtt_ms,
+            self.loss_percent,
+            optional(self.drift_ms),
+            self.frames_dropped,
+            self.packets_dropped,
+            self.decode_queue,
+            self.audio_buffer
+                .map(|level| format!("{:.2}", level))
+                .unwrap_or_default()
+        )
+    }
+}
+
+/// Appends a [`StatsRow`] per second to a CSV file, or JSON lines for
+/// `.json`/`.jsonl` files, for looking into a flaky session afterwards
+pub struct StatsLog {
+    writer: BufWriter<File>,
+    json: bool,
+}
+
+impl StatsLog {
+    /// Open `path` for appending; a new CSV file starts with a header
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open stats log {}", path.display()))?;
+        let json = path.extension().is_some_and(|ext| {
+            ext.eq_ignore_ascii_case("json") || ext.eq_ignore_ascii_case("jsonl")
+        });
+        let mut writer = BufWriter::new(file);
+        if !json && writer.get_ref().metadata()?.len() == 0 {
+            writeln!(writer, "{}", StatsRow::CSV_HEADER)?;
+        }
+        Ok(Self { writer, json })
+    }
+
+    /// Append a row, flushed right away so a crash keeps it
+    pub fn write(&mut self, row: &StatsRow) -> Result<()> {
+        if self.json {
+            serde_json::to_writer(&mut self.writer, row)?;
+            self.writer.write_all(b"\n")?;
+        } else {
+            writeln!(self.writer, "{}", row.csv_line())?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_header_once_and_json_lines() {
+        let dir = std::env::temp_dir().join(format!("stats-log-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let row = StatsRow {
+            elapsed_s: 1.0,
+            fps: 60.0,
+            rtt_ms: 12.5,
+            drift_ms: Some(-20.0),
+            ..Default::default()
+        };
+
+        let csv = dir.join("stats.csv");
+        StatsLog::create(&csv).unwrap().write(&row).unwrap();
+        // A second session appends below the same header
+        StatsLog::create(&csv).unwrap().write(&row).unwrap();
+        let text = std::fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], StatsRow::CSV_HEADER);
+        assert_eq!(lines[1], "0.000,1.0,60.0,0.00,12.5,0.00,-20.0,0,0,0,");
+
+        let json = dir.join("stats.jsonl");
+        StatsLog::create(&json).unwrap().write(&row).unwrap();
+        let line: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(line["fps"], 60.0);
+        assert!(line["audio_buffer"].is_null());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 /// Audio/Video synchronization engine using PTS
+use crate::stats::Ewma;
 use std::collections::VecDeque;
 use std::time::Instant;
 
@@ -44,6 +45,7 @@ pub struct SyncEngine {
     max_audio_buffer: usize,
     video_drift_ms: i64,
     audio_drift_ms: i64,
+    avg_drift: Ewma,
     #[allow(dead_code)]
     last_sync_check: Instant,
     stats: SyncStats,
@@ -60,6 +62,11 @@ pub struct SyncStats {
 }
 
 impl SyncEngine {
+    /// Average drift, starting from in sync
+    fn avg_drift() -> Ewma {
+        Ewma::with_initial(0.1, 0.0)
+    }
+
     /// Create a new synchronization engine
     ///
     /// # Arguments
@@ -75,6 +82,7 @@ impl SyncEngine {
             max_audio_buffer,
             video_drift_ms: 0,
             audio_drift_ms: 0,
+            avg_drift: Self::avg_drift(),
             last_sync_check: Instant::now(),
             stats: SyncStats::default(),
         }
@@ -130,7 +138,7 @@ impl SyncEngine {
         self.stats.current_drift_ms = drift_ms;
 
         // Update average drift
-        self.stats.avg_drift_ms = self.stats.avg_drift_ms * 0.9 + drift_ms as f64 * 0.1;
+        self.stats.avg_drift_ms = self.avg_drift.add(drift_ms as f64);
 
         // Check if drift exceeds threshold
         if drift_ms.abs() > self.sync_threshold_ms {
@@ -193,6 +201,7 @@ impl SyncEngine {
         self.audio_buffer.clear();
         self.video_drift_ms = 0;
         self.audio_drift_ms = 0;
+        self.avg_drift = Self::avg_drift();
         self.stats = SyncStats::default();
     }
 }
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::assets::Assets;
+
+/// Accessibility events whose text is worth showing as a caption
+const CAPTIONED_EVENTS: &[&str] = &[
+    "TYPE_ANNOUNCEMENT",
+    "TYPE_VIEW_CLICKED",
+    "TYPE_VIEW_FOCUSED",
+    "TYPE_VIEW_ACCESSIBILITY_FOCUSED",
+    "TYPE_WINDOW_STATE_CHANGED",
+    "TYPE_NOTIFICATION_STATE_CHANGED",
+];
+
+/// Extract caption text from one `uiautomator events` line
+///
+/// Lines look like `EventType: TYPE_VIEW_CLICKED; EventTime: ...; Text: [OK];
+/// ContentDescription: null; ...`. The text list wins over the content
+/// description; events without either are skipped.
+pub fn parse_event_line(line: &str) -> Option<String> {
+    let event_type = field(line, "EventType")?;
+    if !CAPTIONED_EVENTS.contains(&event_type) {
+        return None;
+    }
+
+    let text = field(line, "Text")
+        .map(|t| t.trim_start_matches('[').trim_end_matches(']').trim())
+        .filter(|t| !t.is_empty());
+    let description = field(line, "ContentDescription").filter(|d| *d != "null" && !d.is_empty());
+    text.or(description).map(str::to_string)
+}
+
+/// Value of `name: value;` in an event line
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}: ", name);
+    let start = if line.starts_with(&key) {
+        key.len()
+    } else {
+        // Anchor on the separator so "Text" doesn't match "BeforeText"
+        line.find(&format!("; {}", key))? + 2 + key.len()
+    };
+    let rest = &line[start..];
+    // Text lists may contain "; ", so they run to the closing bracket
+    let end = if rest.starts_with('[') {
+        rest.find(']').map(|i| i + 1)
+    } else {
+        rest.find(';')
+    };
+    Some(rest[..end.unwrap_or(rest.len())].trim())
+}
+
+/// Streams accessibility text from the device (`adb shell uiautomator events`)
+pub struct CaptionSource {
+    child: Child,
+    rx: mpsc::Receiver<String>,
+}
+
+impl CaptionSource {
+    /// Start capturing from the given device (the only device when `None`)
+    pub fn spawn(serial: Option<&str>) -> Result<Self> {
+        let mut cmd = Command::new(Assets::get_adb_path()?);
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        let mut child = cmd
+            .args(["shell", "uiautomator", "events"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start uiautomator events")?;
+        let stdout = child.stdout.take().context("No uiautomator output")?;
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+                if let Some(text) = parse_event_line(&line) {
+                    if tx.send(text).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { child, rx })
+    }
+
+    /// Captions received since the last call
+    pub fn poll(&self) -> Vec<String> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for CaptionSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The caption on screen, and optionally an SRT file of every caption
+pub struct CaptionTrack {
+    started: Instant,
+    current: Option<(String, Instant)>,
+    srt: Option<BufWriter<File>>,
+    cues: u32,
+}
+
+impl CaptionTrack {
+    /// How long a caption stays up unless replaced
+    const DISPLAY_TIME: Duration = Duration::from_secs(3);
+
+    /// Start a track; `srt` receives cues timed from now
+    pub fn new(srt: Option<&Path>) -> Result<Self> {
+        let srt = srt
+            .map(|path| {
+                File::create(path)
+                    .map(BufWriter::new)
+                    .with_context(|| format!("Failed to create {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            started: Instant::now(),
+            current: None,
+            srt,
+            cues: 0,
+        })
+    }
+
+    /// Show a new caption, closing the previous cue
+    pub fn push(&mut self, text: String, now: Instant) -> Result<()> {
+        self.close_cue(now)?;
+        self.current = Some((text, now));
+        Ok(())
+    }
+
+    /// Caption to draw right now
+    pub fn current(&self, now: Instant) -> Option<&str> {
+        self.current
+            .as_ref()
+            .filter(|(_, shown)| now.duration_since(*shown) < Self::DISPLAY_TIME)
+            .map(|(text, _)| text.as_str())
+    }
+
+    /// Draw the current caption centered along the bottom of the window
+    pub fn render(&self, ctx: &egui::Context) {
+        let Some(text) = self.current(Instant::now()) else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("caption"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -24.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::none()
+                    .fill(egui::Color32::from_black_alpha(180))
+                    .rounding(4.0)
+                    .inner_margin(egui::Margin::symmetric(10.0, 6.0))
+                    .show(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new(text)
+                                .size(20.0)
+                                .color(egui::Color32::WHITE),
+                        );
+                    });
+            });
+    }
+
+    /// Write the last cue and flush the SRT file
+    pub fn finish(&mut self, now: Instant) -> Result<()> {
+        self.close_cue(now)?;
+        self.current = None;
+        if let Some(srt) = &mut self.srt {
+            srt.flush()?;
+        }
+        Ok(())
+    }
+
+    fn close_cue(&mut self, now: Instant) -> Result<()> {
+        let (Some(srt), Some((text, shown))) = (&mut self.srt, &self.current) else {
+            return Ok(());
+        };
+        let start = shown.duration_since(self.started);
+        let end = now
+            .duration_since(self.started)
+            .min(start + Self::DISPLAY_TIME);
+
+        self.cues += 1;
+        writeln!(
+            srt,
+            "{}\n{} --> {}\n{}\n",
+            self.cues,
+            srt_time(start),
+            srt_time(end),
+            text
+        )?;
+        Ok(())
+    }
+}
+
+/// SRT timestamp (`HH:MM:SS,mmm`)
+fn srt_time(at: Duration) -> String {
+    let ms = at.as_millis();
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event_line() {
+        let clicked = "EventType: TYPE_VIEW_CLICKED; EventTime: 1234; PackageName: com.example; \
+                       Text: [Sign in; now]; ContentDescription: null; ItemCount: -1";
+        assert_eq!(parse_event_line(clicked).as_deref(), Some("Sign in; now"));
+
+        let described = "EventType: TYPE_VIEW_FOCUSED; EventTime: 1; Text: []; \
+                         ContentDescription: Back; ItemCount: -1";
+        assert_eq!(parse_event_line(described).as_deref(), Some("Back"));
+
+        let scrolled = "EventType: TYPE_VIEW_SCROLLED; EventTime: 1; Text: [List]";
+        assert_eq!(parse_event_line(scrolled), None);
+    }
+
+    #[test]
+    fn test_srt_cues() {
+        let path = std::env::temp_dir().join(format!("scrcpy-captions-{}.srt", std::process::id()));
+        let mut track = CaptionTrack::new(Some(&path)).unwrap();
+        let start = track.started;
+
+        track
+            .push("Hello".into(), start + Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(track.current(start + Duration::from_secs(2)), Some("Hello"));
+        assert_eq!(track.current(start + Duration::from_secs(5)), None);
+        track
+            .push("World".into(), start + Duration::from_secs(2))
+            .unwrap();
+        track.finish(start + Duration::from_secs(3_700)).unwrap();
+
+        let srt = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            srt,
+            "1\n00:00:01,500 --> 00:00:02,000\nHello\n\n\
+             2\n00:00:02,000 --> 00:00:05,000\nWorld\n\n"
+        );
+    }
+}
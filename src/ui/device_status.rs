@@ -0,0 +1,229 @@
+use crate::server::ServerManager;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the device is asked for its battery and network state
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Degrees the battery has to cool below the limit before the warning clears
+const COOL_DOWN: f32 = 2.0;
+
+/// Charging state from `dumpsys battery`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charging {
+    Discharging,
+    Charging,
+    /// Plugged in with a full battery
+    Full,
+}
+
+/// Battery and network state of the device
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceStatus {
+    /// Battery level in percent
+    pub battery: Option<u8>,
+    pub charging: Option<Charging>,
+    /// Battery temperature in °C
+    pub temperature: Option<f32>,
+    /// WiFi signal strength in dBm, None when not on WiFi
+    pub wifi_rssi: Option<i32>,
+}
+
+impl DeviceStatus {
+    /// Read the outputs of `dumpsys battery` and `dumpsys connectivity`
+    pub fn parse(battery: &str, connectivity: &str) -> Self {
+        let field = |key: &str| {
+            battery.lines().find_map(|line| {
+                let (name, value) = line.trim().split_once(": ")?;
+                (name == key).then(|| value.trim())
+            })
+        };
+        let number = |key: &str| field(key).and_then(|value| value.parse::<i64>().ok());
+        let scale = number("scale").filter(|&scale| scale > 0).unwrap_or(100);
+        Self {
+            battery: number("level").map(|level| (level * 100 / scale).clamp(0, 100) as u8),
+            charging: number("status").map(|status| match status {
+                2 => Charging::Charging,
+                5 => Charging::Full,
+                _ => Charging::Discharging,
+            }),
+            // Reported in tenths of a degree
+            temperature: number("temperature").map(|tenths| tenths as f32 / 10.0),
+            wifi_rssi: wifi_rssi(connectivity),
+        }
+    }
+
+    /// One-line summary, e.g. `87% charging, 31.2 °C, WiFi -52 dBm`
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(level) = self.battery {
+            parts.push(match self.charging {
+                Some(Charging::Charging) => format!("{}% charging", level),
+                Some(Charging::Full) => format!("{}% plugged in", level),
+                _ => format!("{}%", level),
+            });
+        }
+        if let Some(temperature) = self.temperature {
+            parts.push(format!("{:.1} °C", temperature));
+        }
+        if let Some(rssi) = self.wifi_rssi {
+            parts.push(format!("WiFi {} dBm", rssi));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Signal strength of the WiFi network in `dumpsys connectivity`
+///
+/// Network agents are listed one per line with their capabilities, e.g.
+/// `... nc{[ Transports: WIFI Capabilities: ... SignalStrength: -52 ...]}`.
+fn wifi_rssi(connectivity: &str) -> Option<i32> {
+    connectivity
+        .lines()
+        .filter(|line| line.contains("Transports: WIFI"))
+        .find_map(|line| {
+            let rest = &line[line.find("SignalStrength: ")? + "SignalStrength: ".len()..];
+            let end = rest
+                .find(|c: char| c != '-' && !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..end].parse().ok()
+        })
+}
+
+/// Battery, temperature and WiFi signal of the device, polled in the background
+///
+/// `dumpsys` runs every few seconds on its own thread, which stops once the
+/// monitor is dropped. Crossing the temperature limit logs a warning and shows
+/// one over the video until the battery has cooled down again.
+pub struct DeviceMonitor {
+    rx: mpsc::Receiver<DeviceStatus>,
+    status: Option<DeviceStatus>,
+    overheat_celsius: f32,
+    hot: bool,
+}
+
+impl DeviceMonitor {
+    pub fn spawn(serial: Option<String>, overheat_celsius: f32) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let serial = serial.as_deref();
+            // A missed reading keeps the last one shown
+            if let Ok(battery) = ServerManager::dumpsys(serial, "battery") {
+                let connectivity =
+                    ServerManager::dumpsys(serial, "connectivity").unwrap_or_default();
+                if tx
+                    .send(DeviceStatus::parse(&battery, &connectivity))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        });
+        Self::with_receiver(rx, overheat_celsius)
+    }
+
+    fn with_receiver(rx: mpsc::Receiver<DeviceStatus>, overheat_celsius: f32) -> Self {
+        Self {
+            rx,
+            status: None,
+            overheat_celsius,
+            hot: false,
+        }
+    }
+
+    /// Take in new readings; true if the status shown changed
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(status) = self.rx.try_recv() {
+            if let Some(temperature) = status.temperature {
+                if !self.hot && temperature > self.overheat_celsius {
+                    self.hot = true;
+                    tracing::warn!("Device is overheating: {:.1} °C", temperature);
+                } else if self.hot && temperature < self.overheat_celsius - COOL_DOWN {
+                    self.hot = false;
+                    tracing::info!("Device cooled down to {:.1} °C", temperature);
+                }
+            }
+            changed |= self.status.as_ref() != Some(&status);
+            self.status = Some(status);
+        }
+        changed
+    }
+
+    pub fn status(&self) -> Option<&DeviceStatus> {
+        self.status.as_ref()
+    }
+
+    /// Whether the battery is over the temperature limit
+    pub fn is_hot(&self) -> bool {
+        self.hot
+    }
+
+    /// Draw the overheating warning, if any
+    pub fn render(&self, ctx: &egui::Context) {
+        let (true, Some(temperature)) = (self.hot, self.status().and_then(|s| s.temperature))
+        else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("overheat_warning"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(0xe0, 0x6c, 0x75),
+                        format!("Device is overheating ({:.1} °C)", temperature),
+                    );
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BATTERY: &str =
+        "Current Battery Service state:\n  AC powered: false\n  USB powered: true\n  \
+        status: 2\n  health: 2\n  present: true\n  level: 87\n  scale: 100\n  voltage: 4201\n  \
+        temperature: 312\n  technology: Li-ion\n";
+
+    #[test]
+    fn test_parse_battery_and_wifi() {
+        let connectivity =
+            "NetworkAgentInfo{network{100}  handle{432902426637}  ni{WIFI CONNECTED extra: } \
+            nc{[ Transports: WIFI Capabilities: NOT_METERED&INTERNET LinkUpBandwidth>=30000Kbps \
+            SignalStrength: -52 SSID: \"home\"]}  Score{70}}\n\
+            NetworkAgentInfo{network{101} nc{[ Transports: CELLULAR SignalStrength: -90 ]}}\n";
+        let status = DeviceStatus::parse(BATTERY, connectivity);
+        assert_eq!(
+            status,
+            DeviceStatus {
+                battery: Some(87),
+                charging: Some(Charging::Charging),
+                temperature: Some(31.2),
+                wifi_rssi: Some(-52),
+            }
+        );
+        assert_eq!(status.summary(), "87% charging, 31.2 °C, WiFi -52 dBm");
+        assert_eq!(DeviceStatus::parse("", "").summary(), "");
+    }
+
+    #[test]
+    fn test_overheat_warning_clears_after_cooling() {
+        let (tx, rx) = mpsc::channel();
+        let mut monitor = DeviceMonitor::with_receiver(rx, 45.0);
+        let reading = |temperature| DeviceStatus {
+            temperature: Some(temperature),
+            ..Default::default()
+        };
+        for (temperature, hot) in [(40.0, false), (45.5, true), (44.0, true), (42.5, false)] {
+            tx.send(reading(temperature)).unwrap();
+            assert!(monitor.poll());
+            assert_eq!(monitor.is_hot(), hot, "at {} °C", temperature);
+        }
+        assert!(!monitor.poll());
+    }
+}
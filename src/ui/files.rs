@@ -0,0 +1,384 @@
+use crate::assets::Assets;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+/// Folder the browser opens in
+const HOME: &str = "/sdcard";
+
+/// File or folder on the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Result of an adb command run in the background
+enum Outcome {
+    Listed {
+        dir: String,
+        entries: Result<Vec<RemoteEntry>>,
+    },
+    Pulled(Result<PathBuf>),
+    Pushed {
+        dir: String,
+        result: Result<String>,
+    },
+}
+
+/// File browser for the device's shared storage (F2)
+///
+/// Lists folders with `adb shell ls`, downloads with `adb pull` and uploads
+/// with `adb push`, each in a background thread so the mirror never stalls.
+/// Files dropped on the window are uploaded to the folder shown.
+pub struct FileBrowser {
+    serial: Option<String>,
+    downloads: PathBuf,
+    open: bool,
+    dir: String,
+    entries: Vec<RemoteEntry>,
+    /// Local path typed in for an upload
+    upload: String,
+    status: Option<String>,
+    /// adb commands still running
+    busy: usize,
+    tx: mpsc::Sender<Outcome>,
+    rx: mpsc::Receiver<Outcome>,
+}
+
+impl FileBrowser {
+    pub fn new(serial: Option<String>, downloads: PathBuf) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            serial,
+            downloads,
+            open: false,
+            dir: HOME.to_string(),
+            entries: Vec::new(),
+            upload: String::new(),
+            status: None,
+            busy: 0,
+            tx,
+            rx,
+        }
+    }
+
+    /// Show or hide the panel, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.open = !self.open;
+        if self.open {
+            self.list(self.dir.clone());
+        }
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Take in finished adb commands; true if the panel needs a redraw
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(outcome) = self.rx.try_recv() {
+            self.busy -= 1;
+            changed = true;
+            match outcome {
+                Outcome::Listed { dir, entries } => match entries {
+                    Ok(entries) => {
+                        self.dir = dir;
+                        self.entries = entries;
+                        self.status = None;
+                    }
+                    Err(e) => self.status = Some(format!("{:#}", e)),
+                },
+                Outcome::Pulled(result) => {
+                    self.status = Some(match result {
+                        Ok(path) => format!("Saved {}", path.display()),
+                        Err(e) => format!("{:#}", e),
+                    })
+                }
+                Outcome::Pushed { dir, result } => {
+                    self.status = Some(match result {
+                        Ok(name) => format!("Uploaded {}", name),
+                        Err(e) => format!("{:#}", e),
+                    });
+                    if dir == self.dir {
+                        self.list(dir);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// Upload a local file into the folder shown
+    pub fn push(&mut self, local: PathBuf) {
+        let dir = self.dir.clone();
+        self.status = Some(format!("Uploading {}...", local.display()));
+        self.run(
+            {
+                let dir = dir.clone();
+                move |serial| {
+                    let name = local
+                        .file_name()
+                        .context("Not a file")?
+                        .to_string_lossy()
+                        .to_string();
+                    adb(
+                        serial,
+                        &["push", &local.to_string_lossy(), &join(&dir, &name)],
+                    )?;
+                    Ok(name)
+                }
+            },
+            move |result| Outcome::Pushed { dir, result },
+        );
+    }
+
+    /// Draw the panel
+    pub fn render(&mut self, ctx: &egui::Context) {
+        let mut open_dir = None;
+        let mut download = None;
+        let mut upload = false;
+        egui::Window::new("Files")
+            .open(&mut self.open)
+            .default_size([360.0, 420.0])
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.dir != "/", egui::Button::new("⬆"))
+                        .on_hover_text("Parent folder")
+                        .clicked()
+                    {
+                        open_dir = Some(parent(&self.dir));
+                    }
+                    if ui.button("⟳").on_hover_text("Refresh").clicked() {
+                        open_dir = Some(self.dir.clone());
+                    }
+                    ui.label(&self.dir);
+                    if self.busy > 0 {
+                        ui.spinner();
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for entry in &self.entries {
+                            ui.horizontal(|ui| {
+                                if entry.is_dir {
+                                    if ui
+                                        .selectable_label(false, format!("📁 {}", entry.name))
+                                        .clicked()
+                                    {
+                                        open_dir = Some(join(&self.dir, &entry.name));
+                                    }
+                                } else {
+                                    ui.label(format!("📄 {}", entry.name));
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| {
+                                            if ui.small_button("Download").clicked() {
+                                                download = Some(join(&self.dir, &entry.name));
+                                            }
+                                            ui.weak(format_size(entry.size));
+                                        },
+                                    );
+                                }
+                            });
+                        }
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.upload)
+                            .hint_text("Local file, or drop one on the window"),
+                    );
+                    upload = ui
+                        .add_enabled(!self.upload.trim().is_empty(), egui::Button::new("Upload"))
+                        .clicked();
+                });
+                if let Some(status) = &self.status {
+                    ui.label(status);
+                }
+            });
+
+        if let Some(dir) = open_dir {
+            self.list(dir);
+        }
+        if let Some(remote) = download {
+            self.pull(remote);
+        }
+        if upload {
+            let local = PathBuf::from(std::mem::take(&mut self.upload).trim());
+            self.push(local);
+        }
+    }
+
+    fn list(&mut self, dir: String) {
+        self.run(
+            {
+                let dir = dir.clone();
+                move |serial| {
+                    let output = adb(
+                        serial,
+                        &["shell", "ls", "-l", &shell_quote(&format!("{}/", dir))],
+                    )?;
+                    Ok(parse_ls(&output))
+                }
+            },
+            move |entries| Outcome::Listed { dir, entries },
+        );
+    }
+
+    fn pull(&mut self, remote: String) {
+        let downloads = self.downloads.clone();
+        self.status = Some(format!("Downloading {}...", remote));
+        self.run(
+            move |serial| {
+                std::fs::create_dir_all(&downloads)
+                    .with_context(|| format!("Failed to create {}", downloads.display()))?;
+                let name = remote.rsplit('/').next().unwrap_or(&remote);
+                let local = downloads.join(name);
+                adb(serial, &["pull", &remote, &local.to_string_lossy()])?;
+                Ok(local)
+            },
+            Outcome::Pulled,
+        );
+    }
+
+    /// Run `job` in a background thread, reporting back through `wrap`
+    fn run<T: 'static>(
+        &mut self,
+        job: impl FnOnce(Option<&str>) -> Result<T> + Send + 'static,
+        wrap: impl FnOnce(Result<T>) -> Outcome + Send + 'static,
+    ) {
+        self.busy += 1;
+        let (serial, tx) = (self.serial.clone(), self.tx.clone());
+        thread::spawn(move || {
+            let _ = tx.send(wrap(job(serial.as_deref())));
+        });
+    }
+}
+
+/// Run adb against the device, returning its output
+fn adb(serial: Option<&str>, args: &[&str]) -> Result<String> {
+    let mut cmd = Command::new(Assets::get_adb_path()?);
+    if let Some(serial) = serial {
+        cmd.args(["-s", serial]);
+    }
+    let output = cmd
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run adb {}", args[0]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = [stderr.trim(), stdout.trim()]
+            .into_iter()
+            .find(|text| !text.is_empty())
+            .unwrap_or("no output");
+        anyhow::bail!("adb {} failed: {}", args[0], message);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Entries of a `ls -l` listing, folders first, then by name
+///
+/// Lines look like `drwxrwx--x 2 root sdcard_rw 3452 2024-05-01 10:12 DCIM`.
+/// Symlinks count as folders: on Android they mostly point at storage roots.
+fn parse_ls(output: &str) -> Vec<RemoteEntry> {
+    let mut entries: Vec<RemoteEntry> = output
+        .lines()
+        .filter_map(|line| {
+            // Mode, links, owner, group, size, date and time; the name may hold spaces
+            let mut fields = Vec::with_capacity(7);
+            let mut rest = line;
+            for _ in 0..7 {
+                rest = rest.trim_start();
+                let end = rest.find(char::is_whitespace)?;
+                fields.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+            let mode = fields[0];
+            if mode.len() < 10 || !matches!(mode.chars().next(), Some('-' | 'd' | 'l')) {
+                return None;
+            }
+            let name = rest.trim_start();
+            let name = name.split(" -> ").next().unwrap_or(name);
+            if name.is_empty() || name == "." || name == ".." {
+                return None;
+            }
+            Some(RemoteEntry {
+                name: name.to_string(),
+                is_dir: !mode.starts_with('-'),
+                size: fields[4].parse().unwrap_or(0),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+/// `path` quoted for the device shell, which adb hands the command line to
+fn shell_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+fn parent(dir: &str) -> String {
+    match dir.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_string(),
+        Some((parent, _)) => parent.to_string(),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        1_048_576..=1_073_741_823 => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+        _ => format!("{:.1} GiB", bytes as f64 / 1_073_741_824.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ls() {
+        let output = "total 48\n\
+            drwxrwx--x 2 root sdcard_rw 3452 2024-05-01 10:12 DCIM\n\
+            -rw-rw---- 1 root sdcard_rw 52311 2024-05-02 09:00 my  notes.txt\n\
+            lrwxrwxrwx 1 root root 21 2024-05-01 10:00 sdcard -> /storage/self/primary\n\
+            ls: ./secret: Permission denied\n\
+            -rw-rw---- 1 root sdcard_rw 7 2024-05-02 09:00 a.png\n";
+        let entry = |name: &str, is_dir, size| RemoteEntry {
+            name: name.to_string(),
+            is_dir,
+            size,
+        };
+        assert_eq!(
+            parse_ls(output),
+            [
+                entry("DCIM", true, 3452),
+                entry("sdcard", true, 21),
+                entry("a.png", false, 7),
+                entry("my  notes.txt", false, 52311),
+            ]
+        );
+        assert_eq!(parent("/sdcard/DCIM/"), "/sdcard");
+        assert_eq!(parent("/sdcard"), "/");
+        assert_eq!(join("/", "sdcard"), "/sdcard");
+        assert_eq!(shell_quote("/sdcard/it's"), r"'/sdcard/it'\''s'");
+    }
+}
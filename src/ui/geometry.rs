@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::{Window, WindowAttributes};
+
+/// Window position and size in physical pixels; unset parts keep the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl WindowGeometry {
+    /// Size used for whichever dimension is missing
+    const DEFAULT_SIZE: (u32, u32) = (1024, 576);
+
+    /// Current geometry of `window`, None while minimized or fullscreen
+    ///
+    /// The position is left out where the platform does not report it (Wayland).
+    pub fn of(window: &Window) -> Option<Self> {
+        if window.fullscreen().is_some() || window.is_minimized() == Some(true) {
+            return None;
+        }
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+        let position = window.outer_position().ok();
+        Some(Self {
+            x: position.map(|p| p.x),
+            y: position.map(|p| p.y),
+            width: Some(size.width),
+            height: Some(size.height),
+        })
+    }
+
+    /// Fill the parts missing here from `other`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            x: self.x.or(other.x),
+            y: self.y.or(other.y),
+            width: self.width.or(other.width),
+            height: self.height.or(other.height),
+        }
+    }
+
+    /// Apply the parts that are set to `attributes`
+    pub fn apply(&self, mut attributes: WindowAttributes) -> WindowAttributes {
+        if self.width.is_some() || self.height.is_some() {
+            attributes = attributes.with_inner_size(PhysicalSize::new(
+                self.width.unwrap_or(Self::DEFAULT_SIZE.0),
+                self.height.unwrap_or(Self::DEFAULT_SIZE.1),
+            ));
+        }
+        if self.x.is_some() || self.y.is_some() {
+            attributes = attributes.with_position(PhysicalPosition::new(
+                self.x.unwrap_or(0),
+                self.y.unwrap_or(0),
+            ));
+        }
+        attributes
+    }
+}
+
+/// Last window geometry of one device, kept under the config directory
+pub struct GeometryStore {
+    path: Option<PathBuf>,
+}
+
+impl GeometryStore {
+    /// Store for `device` (serial or address) in `<config dir>/windows`
+    pub fn for_device(device: &str) -> Self {
+        let name: String = device
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        Self::at(
+            crate::platform::config_dir()
+                .map(|dir| dir.join("windows").join(format!("{}.toml", name))),
+        )
+    }
+
+    pub fn at(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    /// Saved geometry, empty when there is none or it cannot be read
+    pub fn load(&self) -> WindowGeometry {
+        self.path
+            .as_deref()
+            .and_then(|path| Self::read(path).ok())
+            .unwrap_or_default()
+    }
+
+    fn read(path: &Path) -> anyhow::Result<WindowGeometry> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, geometry: &WindowGeometry) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = toml::to_string(geometry)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                Ok(std::fs::write(path, text)?)
+            });
+        if let Err(e) = result {
+            tracing::debug!("Failed to store window geometry {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_override_saved_geometry() {
+        let path = std::env::temp_dir().join(format!("scrcpy-window-{}.toml", std::process::id()));
+        let store = GeometryStore::at(Some(path.clone()));
+        assert_eq!(store.load(), WindowGeometry::default());
+
+        store.save(&WindowGeometry {
+            x: Some(40),
+            y: Some(-20),
+            width: Some(1280),
+            height: Some(720),
+        });
+        let flags = WindowGeometry {
+            width: Some(800),
+            ..Default::default()
+        };
+        assert_eq!(
+            flags.or(store.load()),
+            WindowGeometry {
+                x: Some(40),
+                y: Some(-20),
+                width: Some(800),
+                height: Some(720),
+            }
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Alignment guides for one device, in portrait device pixels
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GuideProfile {
+    /// Native portrait resolution the values below refer to (the video size if unset)
+    pub size: Option<[u32; 2]>,
+
+    /// Rule-of-thirds grid
+    pub thirds: bool,
+
+    /// Square grid spacing (0 = no grid)
+    pub grid_px: u32,
+
+    /// Safe area insets: top, right, bottom, left
+    pub safe_insets: [u32; 4],
+
+    /// Display cutouts as x, y, width, height
+    pub cutouts: Vec<[u32; 4]>,
+}
+
+/// Guide profiles keyed by device serial or model, loaded from a TOML file
+///
+/// ```toml
+/// [default]
+/// thirds = true
+///
+/// ["Pixel 7"]
+/// size = [1080, 2400]
+/// safe_insets = [118, 0, 63, 0]
+/// cutouts = [[500, 30, 80, 80]]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GuideProfiles {
+    #[serde(flatten)]
+    profiles: HashMap<String, GuideProfile>,
+}
+
+impl GuideProfiles {
+    /// Load profiles from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read guide profiles {:?}", path))?;
+        toml::from_str(&text).with_context(|| format!("Invalid guide profiles in {:?}", path))
+    }
+
+    /// Profile for a device: by serial, then model, then `[default]`
+    pub fn for_device(&self, serial: Option<&str>, model: Option<&str>) -> GuideProfile {
+        [serial, model, Some("default")]
+            .into_iter()
+            .flatten()
+            .find_map(|key| self.profiles.get(key))
+            .cloned()
+            .unwrap_or_else(|| GuideProfile {
+                thirds: true,
+                ..Default::default()
+            })
+    }
+}
+
+/// Draws the guides of a profile over the video
+pub struct GuideOverlay {
+    profile: GuideProfile,
+    visible: bool,
+}
+
+impl GuideOverlay {
+    pub fn new(profile: GuideProfile, visible: bool) -> Self {
+        Self { profile, visible }
+    }
+
+    /// Switch to another device's profile
+    pub fn set_profile(&mut self, profile: GuideProfile) {
+        self.profile = profile;
+    }
+
+    /// Show or hide the guides, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.visible = !self.visible;
+        self.visible
+    }
+
+    /// Guide lines and rectangles in video pixels for a `width` x `height` frame
+    ///
+    /// Landscape frames are treated as the portrait profile rotated 90 degrees
+    /// counter-clockwise (the natural top edge on the left).
+    fn shapes(&self, width: u32, height: u32) -> Guides {
+        let landscape = width > height;
+        let (portrait_w, portrait_h) = if landscape {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let [native_w, native_h] = self.profile.size.unwrap_or([portrait_w, portrait_h]);
+        let sx = portrait_w as f32 / native_w.max(1) as f32;
+        let sy = portrait_h as f32 / native_h.max(1) as f32;
+
+        // Portrait rect -> rect in the frame as displayed
+        let place = |[x, y, w, h]: [f32; 4]| {
+            let (x, y, w, h) = (x * sx, y * sy, w * sx, h * sy);
+            if landscape {
+                [y, portrait_w as f32 - x - w, h, w]
+            } else {
+                [x, y, w, h]
+            }
+        };
+
+        let mut guides = Guides::default();
+        let (w, h) = (width as f32, height as f32);
+        if self.profile.thirds {
+            for i in 1..3 {
+                guides.vertical.push(w * i as f32 / 3.0);
+                guides.horizontal.push(h * i as f32 / 3.0);
+            }
+        }
+        if self.profile.grid_px > 0 {
+            // Spacing is in portrait device pixels, scaled like everything else
+            let step = self.profile.grid_px as f32 * sx;
+            let mut x = step;
+            while x < w {
+                guides.grid_vertical.push(x);
+                x += step;
+            }
+            let mut y = step;
+            while y < h {
+                guides.grid_horizontal.push(y);
+                y += step;
+            }
+        }
+
+        let [top, right, bottom, left] = self.profile.safe_insets.map(|v| v as f32);
+        if top + right + bottom + left > 0.0 {
+            guides.safe_area = Some(place([
+                left,
+                top,
+                native_w as f32 - left - right,
+                native_h as f32 - top - bottom,
+            ]));
+        }
+        guides.cutouts = self
+            .profile
+            .cutouts
+            .iter()
+            .map(|c| place(c.map(|v| v as f32)))
+            .collect();
+        guides
+    }
+
+    /// Draw the guides behind other overlay UI
+    ///
+    /// `viewport` is the letterboxed video area in window pixels and
+    /// `video_size` the frame size.
+    pub fn render(
+        &self,
+        ctx: &egui::Context,
+        viewport: (f32, f32, f32, f32),
+        video_size: (u32, u32),
+    ) {
+        if !self.visible {
+            return;
+        }
+        let ppp = ctx.pixels_per_point();
+        let (vx, vy, vw, vh) = viewport;
+        let (width, height) = video_size;
+        // Video pixels -> egui points
+        let scale = egui::vec2(vw / width as f32 / ppp, vh / height as f32 / ppp);
+        let origin = egui::pos2(vx / ppp, vy / ppp);
+        let at = |x: f32, y: f32| origin + egui::vec2(x * scale.x, y * scale.y);
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Background,
+            egui::Id::new("guides"),
+        ));
+        let grid = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(40));
+        let thirds = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(120));
+        let safe = egui::Stroke::new(1.5, egui::Color32::from_rgb(80, 220, 120));
+        let cutout = egui::Color32::from_rgba_unmultiplied(255, 80, 80, 90);
+
+        let guides = self.shapes(width, height);
+        let (w, h) = (width as f32, height as f32);
+        for (xs, stroke) in [(&guides.grid_vertical, grid), (&guides.vertical, thirds)] {
+            for &x in xs {
+                painter.line_segment([at(x, 0.0), at(x, h)], stroke);
+            }
+        }
+        for (ys, stroke) in [
+            (&guides.grid_horizontal, grid),
+            (&guides.horizontal, thirds),
+        ] {
+            for &y in ys {
+                painter.line_segment([at(0.0, y), at(w, y)], stroke);
+            }
+        }
+        let rect =
+            |[x, y, rw, rh]: [f32; 4]| egui::Rect::from_min_max(at(x, y), at(x + rw, y + rh));
+        if let Some(area) = guides.safe_area {
+            painter.rect_stroke(rect(area), 0.0, safe);
+        }
+        for area in guides.cutouts {
+            painter.rect_filled(rect(area), 4.0, cutout);
+        }
+    }
+}
+
+/// Guide geometry in video pixels
+#[derive(Debug, Default)]
+struct Guides {
+    vertical: Vec<f32>,
+    horizontal: Vec<f32>,
+    grid_vertical: Vec<f32>,
+    grid_horizontal: Vec<f32>,
+    safe_area: Option<[f32; 4]>,
+    cutouts: Vec<[f32; 4]>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_lookup_order() {
+        let profiles: GuideProfiles = toml::from_str(
+            "[default]\ngrid_px = 100\n\n[\"Pixel 7\"]\nsafe_insets = [118, 0, 63, 0]\n\n[\"R5CT1234\"]\nthirds = true\n",
+        )
+        .unwrap();
+
+        assert!(
+            profiles
+                .for_device(Some("R5CT1234"), Some("Pixel 7"))
+                .thirds
+        );
+        assert_eq!(
+            profiles
+                .for_device(Some("other"), Some("Pixel 7"))
+                .safe_insets,
+            [118, 0, 63, 0]
+        );
+        assert_eq!(profiles.for_device(None, None).grid_px, 100);
+        assert!(GuideProfiles::default().for_device(None, None).thirds);
+    }
+
+    #[test]
+    fn test_shapes_scale_and_rotate() {
+        let overlay = GuideOverlay::new(
+            GuideProfile {
+                size: Some([1080, 2400]),
+                safe_insets: [120, 0, 60, 0],
+                cutouts: vec![[500, 20, 80, 80]],
+                ..Default::default()
+            },
+            true,
+        );
+
+        // Half-size portrait stream
+        let portrait = overlay.shapes(540, 1200);
+        assert_eq!(portrait.safe_area, Some([0.0, 60.0, 540.0, 1110.0]));
+        assert_eq!(portrait.cutouts, vec![[250.0, 10.0, 40.0, 40.0]]);
+
+        // Same stream rotated: the notch moves to the left edge
+        let landscape = overlay.shapes(1200, 540);
+        assert_eq!(landscape.cutouts, vec![[10.0, 250.0, 40.0, 40.0]]);
+        assert_eq!(landscape.safe_area, Some([60.0, 0.0, 1110.0, 540.0]));
+    }
+}
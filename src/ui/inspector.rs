@@ -0,0 +1,194 @@
+use crate::video::decoder::DecodedFrame;
+
+/// Pixel under the cursor with its neighbourhood for the loupe
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelSample {
+    /// Frame coordinates (the ones touch events are sent in)
+    pub x: u32,
+    pub y: u32,
+
+    /// Color of the pixel
+    pub rgba: [u8; 4],
+
+    /// Row-major square centered on the pixel, `None` past the frame edge
+    loupe: Vec<Option<[u8; 4]>>,
+}
+
+impl PixelSample {
+    /// Hex color as `#RRGGBB`
+    pub fn hex(&self) -> String {
+        let [r, g, b, _] = self.rgba;
+        format!("#{:02X}{:02X}{:02X}", r, g, b)
+    }
+}
+
+/// Eyedropper: shows the coordinate and color of the pixel under the cursor
+/// in a magnifier next to it
+pub struct PixelInspector {
+    enabled: bool,
+    sample: Option<PixelSample>,
+}
+
+impl PixelInspector {
+    /// Pixels on each side of the center in the loupe
+    const LOUPE_RADIUS: i64 = 5;
+
+    /// On-screen size of one magnified pixel (points)
+    const LOUPE_CELL: f32 = 10.0;
+
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            sample: None,
+        }
+    }
+
+    /// Turn the inspector on or off, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.sample = None;
+        self.enabled
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Re-sample at `pos` (frame coordinates); returns true if the display changed
+    pub fn update(&mut self, frame: &DecodedFrame, pos: Option<(u32, u32)>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let sample = pos.and_then(|(x, y)| Self::sample_at(frame, x, y));
+        let changed = sample != self.sample;
+        self.sample = sample;
+        changed
+    }
+
+    /// Current sample, if the cursor is over the video
+    pub fn sample(&self) -> Option<&PixelSample> {
+        self.sample.as_ref()
+    }
+
+    fn sample_at(frame: &DecodedFrame, x: u32, y: u32) -> Option<PixelSample> {
+        let rgba = frame.pixel(x, y)?;
+        let r = Self::LOUPE_RADIUS;
+        let mut loupe = Vec::with_capacity(((2 * r + 1) * (2 * r + 1)) as usize);
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let (px, py) = (x as i64 + dx, y as i64 + dy);
+                loupe.push(if px < 0 || py < 0 {
+                    None
+                } else {
+                    frame.pixel(px as u32, py as u32)
+                });
+            }
+        }
+        Some(PixelSample { x, y, rgba, loupe })
+    }
+
+    /// Draw the loupe and readout next to the pointer
+    pub fn render(&self, ctx: &egui::Context) {
+        let (Some(sample), Some(pointer)) = (&self.sample, ctx.pointer_hover_pos()) else {
+            return;
+        };
+        let side = (2 * Self::LOUPE_RADIUS + 1) as usize;
+        let size = side as f32 * Self::LOUPE_CELL;
+
+        egui::Area::new(egui::Id::new("pixel_inspector"))
+            .fixed_pos(pointer + egui::vec2(16.0, 16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+                    let painter = ui.painter_at(rect);
+                    for (i, pixel) in sample.loupe.iter().enumerate() {
+                        let cell = egui::Rect::from_min_size(
+                            rect.min
+                                + egui::vec2(
+                                    (i % side) as f32 * Self::LOUPE_CELL,
+                                    (i / side) as f32 * Self::LOUPE_CELL,
+                                ),
+                            egui::vec2(Self::LOUPE_CELL, Self::LOUPE_CELL),
+                        );
+                        let color = match pixel {
+                            Some([r, g, b, _]) => egui::Color32::from_rgb(*r, *g, *b),
+                            None => egui::Color32::TRANSPARENT,
+                        };
+                        painter.rect_filled(cell, 0.0, color);
+                    }
+                    // Outline the sampled pixel
+                    let center = rect.min
+                        + egui::vec2(
+                            Self::LOUPE_RADIUS as f32 * Self::LOUPE_CELL,
+                            Self::LOUPE_RADIUS as f32 * Self::LOUPE_CELL,
+                        );
+                    painter.rect_stroke(
+                        egui::Rect::from_min_size(
+                            center,
+                            egui::vec2(Self::LOUPE_CELL, Self::LOUPE_CELL),
+                        ),
+                        0.0,
+                        egui::Stroke::new(1.5, egui::Color32::WHITE),
+                    );
+
+                    let [r, g, b, a] = sample.rgba;
+                    ui.monospace(format!("x {}  y {}", sample.x, sample.y));
+                    ui.monospace(format!(
+                        "{}  rgba({}, {}, {}, {})",
+                        sample.hex(),
+                        r,
+                        g,
+                        b,
+                        a
+                    ));
+                });
+            });
+    }
+}
+
+impl Default for PixelInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::color::ColorSpace;
+    use crate::video::decoder::PixelFormat;
+
+    #[test]
+    fn test_sample_reads_pixel_and_clips_loupe() {
+        // 2x1 RGBA frame: red, green
+        let frame = DecodedFrame {
+            pts: 0,
+            data: vec![255, 0, 0, 255, 0, 255, 0, 255].into(),
+            width: 2,
+            height: 1,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        };
+        let mut inspector = PixelInspector::new();
+        assert!(!inspector.update(&frame, Some((1, 0))));
+
+        inspector.toggle();
+        assert!(inspector.update(&frame, Some((1, 0))));
+        let sample = inspector.sample().unwrap();
+        assert_eq!((sample.x, sample.y), (1, 0));
+        assert_eq!(sample.hex(), "#00FF00");
+
+        // Center row holds both pixels, everything else is off the frame
+        let side = (2 * PixelInspector::LOUPE_RADIUS + 1) as usize;
+        let center = side * (side / 2) + side / 2;
+        assert_eq!(sample.loupe[center - 1], Some([255, 0, 0, 255]));
+        assert_eq!(sample.loupe[center + 1], None);
+        assert_eq!(sample.loupe.iter().flatten().count(), 2);
+
+        assert!(!inspector.update(&frame, Some((1, 0))));
+        assert!(inspector.update(&frame, Some((5, 0))));
+        assert!(inspector.sample().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::network::{ControlMessage, KeyAction};
+
+/// Android `KEYCODE_*` values used by the keyboard
+mod keycode {
+    pub const HOME: u32 = 3;
+    pub const BACK: u32 = 4;
+    pub const NUM_0: u32 = 7;
+    pub const A: u32 = 29;
+    pub const COMMA: u32 = 55;
+    pub const PERIOD: u32 = 56;
+    pub const TAB: u32 = 61;
+    pub const SPACE: u32 = 62;
+    pub const ENTER: u32 = 66;
+    pub const DEL: u32 = 67;
+    pub const MINUS: u32 = 69;
+    pub const SLASH: u32 = 76;
+    pub const AT: u32 = 77;
+    pub const APP_SWITCH: u32 = 187;
+}
+
+/// `AMETA_SHIFT_ON | AMETA_SHIFT_LEFT_ON`
+const META_SHIFT: u32 = 0x41;
+
+const LETTER_ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Android keycode of a digit or lowercase letter
+fn char_keycode(c: char) -> Option<u32> {
+    match c {
+        '0'..='9' => Some(keycode::NUM_0 + (c as u32 - '0' as u32)),
+        'a'..='z' => Some(keycode::A + (c as u32 - 'a' as u32)),
+        _ => None,
+    }
+}
+
+/// Key down and up for a tap, shifted if asked
+fn tap(keycode: u32, shift: bool) -> [ControlMessage; 2] {
+    let metastate = if shift { META_SHIFT } else { 0 };
+    [KeyAction::Down, KeyAction::Up].map(|action| ControlMessage::InjectKeycode {
+        action,
+        keycode,
+        repeat: 0,
+        metastate,
+    })
+}
+
+/// On-screen keyboard for touch-only setups (tablet mode, touch monitors)
+///
+/// Taps become key events on the device, so it works with whatever has focus
+/// there, including the Android navigation keys.
+pub struct OnScreenKeyboard {
+    open: bool,
+    /// One-shot shift for the next key
+    shift: bool,
+}
+
+impl OnScreenKeyboard {
+    pub fn new(open: bool) -> Self {
+        Self { open, shift: false }
+    }
+
+    /// Show or hide the keyboard, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.open = !self.open;
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Draw the keyboard, returning the key events of keys tapped in it
+    pub fn render(&mut self, ctx: &egui::Context) -> Vec<ControlMessage> {
+        let mut pressed: Option<u32> = None;
+        let mut shift = self.shift;
+        egui::Window::new("Keyboard")
+            .open(&mut self.open)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -8.0))
+            .show(ctx, |ui| {
+                // Glyph keys get a spoken name for screen readers
+                let named_key = |ui: &mut egui::Ui, label: &str, name: &str, width: f32| {
+                    let response =
+                        ui.add(egui::Button::new(label).min_size(egui::vec2(width, 36.0)));
+                    response.widget_info(|| {
+                        egui::WidgetInfo::labeled(egui::WidgetType::Button, true, name)
+                    });
+                    response.on_hover_text(name).clicked()
+                };
+                let key =
+                    |ui: &mut egui::Ui, label: &str, width: f32| named_key(ui, label, label, width);
+
+                ui.horizontal(|ui| {
+                    for c in "1234567890".chars() {
+                        if key(ui, &c.to_string(), 32.0) {
+                            pressed = char_keycode(c);
+                        }
+                    }
+                    if named_key(ui, "⌫", "Backspace", 48.0) {
+                        pressed = Some(keycode::DEL);
+                    }
+                });
+                for (row, letters) in LETTER_ROWS.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        match row {
+                            0 if named_key(ui, "⇥", "Tab", 32.0) => pressed = Some(keycode::TAB),
+                            2 if ui
+                                .selectable_label(shift, "⇧")
+                                .on_hover_text("Shift")
+                                .clicked() =>
+                            {
+                                shift = !shift
+                            }
+                            _ => {}
+                        }
+                        for c in letters.chars() {
+                            let label = if shift { c.to_ascii_uppercase() } else { c };
+                            if key(ui, &label.to_string(), 32.0) {
+                                pressed = char_keycode(c);
+                            }
+                        }
+                        if row == 1 && named_key(ui, "⏎", "Enter", 48.0) {
+                            pressed = Some(keycode::ENTER);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let keys = [
+                        ("◁", "Back", keycode::BACK, 36.0),
+                        ("○", "Home", keycode::HOME, 36.0),
+                        ("□", "Recent apps", keycode::APP_SWITCH, 36.0),
+                        ("@", "At sign", keycode::AT, 32.0),
+                        (",", "Comma", keycode::COMMA, 32.0),
+                        ("Space", "Space", keycode::SPACE, 140.0),
+                        (".", "Period", keycode::PERIOD, 32.0),
+                        ("-", "Minus", keycode::MINUS, 32.0),
+                        ("/", "Slash", keycode::SLASH, 32.0),
+                    ];
+                    for (label, name, code, width) in keys {
+                        if named_key(ui, label, name, width) {
+                            pressed = Some(code);
+                        }
+                    }
+                });
+            });
+
+        let Some(code) = pressed else {
+            self.shift = shift;
+            return Vec::new();
+        };
+        self.shift = false;
+        tap(code, shift).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_taps_map_to_android_keycodes() {
+        assert_eq!(char_keycode('a'), Some(29));
+        assert_eq!(char_keycode('z'), Some(54));
+        assert_eq!(char_keycode('0'), Some(7));
+        assert_eq!(char_keycode('9'), Some(16));
+        assert_eq!(char_keycode('!'), None);
+
+        let [down, up] = tap(char_keycode('q').unwrap(), true);
+        assert!(matches!(
+            down,
+            ControlMessage::InjectKeycode {
+                action: KeyAction::Down,
+                keycode: 45,
+                metastate: META_SHIFT,
+                ..
+            }
+        ));
+        assert!(matches!(
+            up,
+            ControlMessage::InjectKeycode {
+                action: KeyAction::Up,
+                ..
+            }
+        ));
+    }
+}
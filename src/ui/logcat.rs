@@ -0,0 +1,418 @@
+use crate::server::ServerManager;
+use crate::video::snapshot;
+use anyhow::{Context, Result};
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::mpsc;
+use std::thread;
+use std::time::SystemTime;
+
+/// Lines kept in the panel; older ones scroll away
+const MAX_LINES: usize = 5000;
+
+/// Recent lines logcat prints when the panel starts
+const BACKLOG: u32 = 500;
+
+/// Log priority, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Verbose,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 6] = [
+        Priority::Verbose,
+        Priority::Debug,
+        Priority::Info,
+        Priority::Warn,
+        Priority::Error,
+        Priority::Fatal,
+    ];
+
+    fn from_letter(letter: &str) -> Option<Self> {
+        Some(match letter {
+            "V" => Priority::Verbose,
+            "D" => Priority::Debug,
+            "I" => Priority::Info,
+            "W" => Priority::Warn,
+            "E" => Priority::Error,
+            "F" | "A" => Priority::Fatal,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Verbose => "Verbose",
+            Priority::Debug => "Debug",
+            Priority::Info => "Info",
+            Priority::Warn => "Warning",
+            Priority::Error => "Error",
+            Priority::Fatal => "Fatal",
+        }
+    }
+
+    fn color(self) -> Option<egui::Color32> {
+        match self {
+            Priority::Verbose | Priority::Debug => Some(egui::Color32::GRAY),
+            Priority::Info => None,
+            Priority::Warn => Some(egui::Color32::from_rgb(0xe5, 0xc0, 0x7b)),
+            Priority::Error | Priority::Fatal => Some(egui::Color32::from_rgb(0xe0, 0x6c, 0x75)),
+        }
+    }
+}
+
+/// One `threadtime` logcat line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub pid: u32,
+    pub priority: Priority,
+    pub tag: String,
+    /// The line as logcat printed it
+    pub text: String,
+}
+
+impl LogLine {
+    /// Parse `05-01 10:12:13.456  1234  1250 I ActivityManager: Start proc`
+    pub fn parse(line: &str) -> Option<Self> {
+        // Date, time, pid, tid and priority, then `Tag: message`
+        let mut fields = [""; 5];
+        let mut rest = line;
+        for field in &mut fields {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace)?;
+            *field = &rest[..end];
+            rest = &rest[end..];
+        }
+        let rest = rest.trim_start();
+        // Tags are padded before the colon in some builds
+        let tag = rest.split_once(": ").map_or(rest, |(tag, _)| tag).trim();
+        Some(Self {
+            pid: fields[2].parse().ok()?,
+            priority: Priority::from_letter(fields[4])?,
+            tag: tag.to_string(),
+            text: line.to_string(),
+        })
+    }
+}
+
+/// What the panel shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Filter {
+    min_priority: Priority,
+    /// Tag substring, case-insensitive
+    tag: String,
+    /// Processes of the package filtered for, None for all
+    pids: Option<HashSet<u32>>,
+}
+
+impl Filter {
+    fn matches(&self, line: &LogLine) -> bool {
+        line.priority >= self.min_priority
+            && (self.tag.is_empty() || line.tag.to_lowercase().contains(&self.tag))
+            && self
+                .pids
+                .as_ref()
+                .is_none_or(|pids| pids.contains(&line.pid))
+    }
+}
+
+/// Bottom panel tailing the device log (F1)
+///
+/// `adb logcat` starts the first time the panel opens and runs until the
+/// panel is dropped. Lines that arrive while paused are held back, not lost.
+pub struct LogcatPanel {
+    serial: Option<String>,
+    save_dir: PathBuf,
+    open: bool,
+    lines: VecDeque<LogLine>,
+    /// Lines received while paused
+    held: VecDeque<LogLine>,
+    paused: bool,
+    filter: Filter,
+    tag: String,
+    package: String,
+    status: Option<String>,
+    logcat: Option<(Child, mpsc::Receiver<LogLine>)>,
+    /// Whether starting logcat was tried since the panel opened
+    started: bool,
+    /// Pending `pidof` lookup for the package filter
+    pids: Option<mpsc::Receiver<Result<HashSet<u32>>>>,
+}
+
+impl LogcatPanel {
+    pub fn new(serial: Option<String>, save_dir: PathBuf, open: bool) -> Self {
+        Self {
+            serial,
+            save_dir,
+            open,
+            lines: VecDeque::new(),
+            held: VecDeque::new(),
+            paused: false,
+            filter: Filter {
+                min_priority: Priority::Verbose,
+                tag: String::new(),
+                pids: None,
+            },
+            tag: String::new(),
+            package: String::new(),
+            status: None,
+            logcat: None,
+            started: false,
+            pids: None,
+        }
+    }
+
+    /// Show or hide the panel, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.open = !self.open;
+        // A failed start is tried again on reopening
+        self.started &= self.logcat.is_some();
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Take in new log lines; true if the open panel needs a redraw
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        if self.open && !self.started {
+            self.start();
+            changed = true;
+        }
+        if let Some((_, rx)) = &self.logcat {
+            let target = if self.paused {
+                &mut self.held
+            } else {
+                &mut self.lines
+            };
+            while let Ok(line) = rx.try_recv() {
+                push_capped(target, line);
+                changed = true;
+            }
+        }
+        if let Some(result) = self.pids.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.pids = None;
+            match result {
+                Ok(pids) => {
+                    self.status = pids
+                        .is_empty()
+                        .then(|| format!("{} is not running", self.package.trim()));
+                    self.filter.pids = Some(pids);
+                }
+                Err(e) => self.status = Some(format!("{:#}", e)),
+            }
+            changed = true;
+        }
+        changed && self.open
+    }
+
+    /// Draw the panel
+    pub fn render(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+        let mut save = false;
+        let mut apply_package = false;
+        egui::TopBottomPanel::bottom("logcat")
+            .resizable(true)
+            .default_height(220.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("logcat_priority")
+                        .selected_text(self.filter.min_priority.name())
+                        .show_ui(ui, |ui| {
+                            for priority in Priority::ALL {
+                                ui.selectable_value(
+                                    &mut self.filter.min_priority,
+                                    priority,
+                                    priority.name(),
+                                );
+                            }
+                        });
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.tag)
+                            .hint_text("Tag")
+                            .desired_width(120.0),
+                    );
+                    let package = ui.add(
+                        egui::TextEdit::singleline(&mut self.package)
+                            .hint_text("Package")
+                            .desired_width(160.0),
+                    );
+                    apply_package = package.lost_focus()
+                        && ui.input(|input| input.key_pressed(egui::Key::Enter));
+                    let label = if self.paused { "Resume" } else { "Pause" };
+                    if ui.button(label).clicked() {
+                        self.paused = !self.paused;
+                        if !self.paused {
+                            for line in self.held.drain(..) {
+                                push_capped(&mut self.lines, line);
+                            }
+                        }
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.lines.clear();
+                        self.held.clear();
+                    }
+                    save = ui.button("Save").clicked();
+                    if let Some(status) = &self.status {
+                        ui.label(status);
+                    }
+                });
+                self.filter.tag = self.tag.trim().to_lowercase();
+
+                let shown: Vec<&LogLine> = self
+                    .lines
+                    .iter()
+                    .filter(|line| self.filter.matches(line))
+                    .collect();
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                        for line in &shown[rows] {
+                            let text = egui::RichText::new(&line.text).monospace();
+                            ui.label(match line.priority.color() {
+                                Some(color) => text.color(color),
+                                None => text,
+                            });
+                        }
+                    });
+            });
+
+        if apply_package {
+            self.lookup_package();
+        }
+        if save {
+            self.status = Some(match self.save() {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => format!("{:#}", e),
+            });
+        }
+    }
+
+    /// Spawn `adb logcat` and a thread feeding its lines to the panel
+    fn start(&mut self) {
+        self.started = true;
+        let spawned = ServerManager::logcat_command(self.serial.as_deref(), BACKLOG)
+            .and_then(|mut cmd| cmd.spawn().context("Failed to start adb logcat"));
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                self.status = Some(format!("{:#}", e));
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if let Some(line) = LogLine::parse(&line) {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        self.logcat = Some((child, rx));
+    }
+
+    /// Resolve the package filter to process ids in the background
+    fn lookup_package(&mut self) {
+        let package = self.package.trim().to_string();
+        if package.is_empty() {
+            self.filter.pids = None;
+            self.status = None;
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let serial = self.serial.clone();
+        thread::spawn(move || {
+            let _ = tx.send(ServerManager::pids_of(serial.as_deref(), &package));
+        });
+        self.pids = Some(rx);
+    }
+
+    /// Write the lines passing the filter to a timestamped file
+    fn save(&self) -> Result<PathBuf> {
+        let path = log_path(&self.save_dir);
+        std::fs::create_dir_all(&self.save_dir)
+            .with_context(|| format!("Failed to create {}", self.save_dir.display()))?;
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?,
+        );
+        for line in self.lines.iter().filter(|line| self.filter.matches(line)) {
+            writeln!(file, "{}", line.text)?;
+        }
+        file.flush()?;
+        Ok(path)
+    }
+}
+
+impl Drop for LogcatPanel {
+    fn drop(&mut self) {
+        if let Some((child, _)) = &mut self.logcat {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn push_capped(lines: &mut VecDeque<LogLine>, line: LogLine) {
+    if lines.len() == MAX_LINES {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+fn log_path(dir: &Path) -> PathBuf {
+    dir.join(format!(
+        "logcat-{}.txt",
+        snapshot::timestamp(SystemTime::now())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_filter() {
+        let line = LogLine::parse(
+            "05-01 10:12:13.456  1234  1250 W ActivityManager: Slow operation: 120ms",
+        )
+        .unwrap();
+        assert_eq!(line.pid, 1234);
+        assert_eq!(line.priority, Priority::Warn);
+        assert_eq!(line.tag, "ActivityManager");
+        let padded =
+            LogLine::parse("05-01 10:12:13.456   321   321 E Vold    : Failed to mount").unwrap();
+        assert_eq!(padded.tag, "Vold");
+        assert!(LogLine::parse("--------- beginning of main").is_none());
+
+        let mut filter = Filter {
+            min_priority: Priority::Warn,
+            tag: "activity".to_string(),
+            pids: None,
+        };
+        assert!(filter.matches(&line));
+        assert!(!filter.matches(&padded));
+        filter.pids = Some(HashSet::from([99]));
+        assert!(!filter.matches(&line));
+    }
+}
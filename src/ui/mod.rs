@@ -1,7 +1,42 @@
 /// UI overlay for statistics and controls
 pub mod overlay;
 
-pub use overlay::StatsOverlay;
+pub use overlay::{show_banner, StatsOverlay};
+
+pub mod captions;
+pub use captions::{CaptionSource, CaptionTrack};
+
+pub mod inspector;
+pub use inspector::PixelInspector;
+
+pub mod guides;
+pub use guides::{GuideOverlay, GuideProfiles};
+
+pub mod settings;
+pub use settings::SettingsPanel;
+
+pub mod keyboard;
+pub use keyboard::OnScreenKeyboard;
+
+pub mod files;
+pub use files::FileBrowser;
+
+pub mod logcat;
+pub use logcat::LogcatPanel;
+
+pub mod shell;
+pub use shell::ShellPane;
+
+pub mod device_status;
+pub use device_status::{DeviceMonitor, DeviceStatus};
+
+pub mod toast;
+pub use toast::Toasts;
+
+pub mod theme;
+
+pub mod geometry;
+pub use geometry::{GeometryStore, WindowGeometry};
 
 pub mod logger;
 pub use logger::Logger;
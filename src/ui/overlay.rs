@@ -1,11 +1,12 @@
 use crate::network::NetworkStats;
+use crate::stats::Ewma;
 use crate::sync::SyncStats;
 
 /// Statistics overlay using egui
 pub struct StatsOverlay {
     visible: bool,
     fps: f32,
-    latency_ms: f32,
+    latency_ms: Ewma,
     frame_count: u64,
     last_stats_update: std::time::Instant,
 }
@@ -15,7 +16,7 @@ impl StatsOverlay {
         Self {
             visible: true,
             fps: 0.0,
-            latency_ms: 0.0,
+            latency_ms: Ewma::new(0.1),
             frame_count: 0,
             last_stats_update: std::time::Instant::now(),
         }
@@ -31,8 +32,11 @@ impl StatsOverlay {
 
     pub fn update_frame(&mut self) {
         self.frame_count += 1;
+        self.tick();
+    }
 
-        // Calculate FPS every second
+    /// Recalculate FPS once a second has passed, so it drops to 0 on a static screen
+    pub fn tick(&mut self) {
         let elapsed = self.last_stats_update.elapsed();
         if elapsed.as_secs() >= 1 {
             self.fps = self.frame_count as f32 / elapsed.as_secs_f32();
@@ -41,8 +45,9 @@ impl StatsOverlay {
         }
     }
 
+    /// Add a latency sample (shown smoothed)
     pub fn set_latency(&mut self, latency_ms: f32) {
-        self.latency_ms = latency_ms;
+        self.latency_ms.add(latency_ms as f64);
     }
 
     /// Render the overlay (placeholder for egui implementation)
@@ -73,7 +78,7 @@ impl StatsOverlay {
             .show(ctx, |ui| {
                 ui.heading("Video");
                 ui.label(format!("FPS: {:.1}", self.fps));
-                ui.label(format!("Latency: {:.1}ms", self.latency_ms));
+                ui.label(format!("Latency: {:.1}ms", self.latency_ms()));
 
                 ui.separator();
                 ui.heading("Network");
@@ -81,6 +86,14 @@ impl StatsOverlay {
                 ui.label(format!("Packet Loss: {:.2}%", network_stats.packet_loss));
                 ui.label(format!("Bitrate: {:.1} Mbps", network_stats.bandwidth_mbps));
 
+                ui.separator();
+                ui.heading("FEC");
+                let fec = &network_stats.fec;
+                ui.label(format!("Recovered: {} pkts ({} blocks)", fec.packets_recovered, fec.blocks_recovered));
+                ui.label(format!("Unrecoverable: {} pkts", fec.packets_unrecoverable));
+                ui.label(format!("Recovery Rate: {:.1}%", fec.recovery_rate() * 100.0));
+                ui.label(format!("Wasted Parity: {:.1}%", fec.wasted_parity_ratio() * 100.0));
+
                 ui.separator();
                 ui.heading("Synchronization");
                 ui.label(format!("Drift: {}ms", sync_stats.current_drift_ms));
@@ -98,20 +111,44 @@ impl StatsOverlay {
 
     /// Get current latency
     pub fn latency_ms(&self) -> f32 {
-        self.latency_ms
+        self.latency_ms.get() as f32
+    }
+
+    /// Live figures for the window title, e.g. `1080p • 58 fps • 32 ms`
+    ///
+    /// Latency is left out until a sample arrives.
+    pub fn title_summary(&self, (width, height): (u32, u32)) -> String {
+        let mut summary = format!("{}p • {:.0} fps", width.min(height), self.fps);
+        if self.latency_ms.is_set() {
+            summary.push_str(&format!(" • {:.0} ms", self.latency_ms()));
+        }
+        summary
     }
 
     /// Get stats summary as string (for logging)
     pub fn stats_summary(&self, network_stats: &NetworkStats, sync_stats: &SyncStats) -> String {
-        format!(
+        let mut summary = format!(
             "FPS: {:.1} | Latency: {:.1}ms | RTT: {:.1}ms | Loss: {:.2}% | Drift: {}ms | Dropped: {}",
             self.fps,
-            self.latency_ms,
+            self.latency_ms(),
             network_stats.rtt_ms,
             network_stats.packet_loss,
             sync_stats.current_drift_ms,
             sync_stats.video_frames_dropped
-        )
+        );
+
+        // Only mention FEC once it has seen parity traffic
+        let fec = &network_stats.fec;
+        if fec.parity_packets_received > 0 {
+            summary.push_str(&format!(
+                " | FEC: {} recovered, {} lost, {:.0}% parity wasted",
+                fec.packets_recovered,
+                fec.packets_unrecoverable,
+                fec.wasted_parity_ratio() * 100.0
+            ));
+        }
+
+        summary
     }
 }
 
@@ -121,6 +158,18 @@ impl Default for StatsOverlay {
     }
 }
 
+/// Status message across the top of the video (e.g. while the device is unplugged)
+pub fn show_banner(ctx: &egui::Context, text: &str) {
+    egui::Area::new(egui::Id::new("status_banner"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 16.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.strong(text);
+            });
+        });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,7 +182,9 @@ mod tests {
         overlay.toggle_visibility();
         assert!(!overlay.is_visible());
 
+        assert_eq!(overlay.title_summary((2400, 1080)), "1080p • 0 fps");
         overlay.set_latency(45.0);
         assert_eq!(overlay.latency_ms(), 45.0);
+        assert_eq!(overlay.title_summary((2400, 1080)), "1080p • 0 fps • 45 ms");
     }
 }
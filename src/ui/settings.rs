@@ -0,0 +1,72 @@
+use crate::config::UpscaleFilter;
+use crate::network::{AdaptiveFecController, FecControl};
+
+/// Runtime settings window
+pub struct SettingsPanel {
+    fec: FecControl,
+    upscale_filter: UpscaleFilter,
+    upscale_changed: bool,
+    open: bool,
+}
+
+impl SettingsPanel {
+    pub fn new(fec: FecControl, upscale_filter: UpscaleFilter) -> Self {
+        Self {
+            fec,
+            upscale_filter,
+            upscale_changed: false,
+            open: false,
+        }
+    }
+
+    /// Show or hide the panel, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.open = !self.open;
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Upscale filter picked in the panel since the last call, if any
+    pub fn take_upscale_filter(&mut self) -> Option<UpscaleFilter> {
+        std::mem::take(&mut self.upscale_changed).then_some(self.upscale_filter)
+    }
+
+    /// Draw the panel, requesting any change made in it
+    pub fn render(&mut self, ctx: &egui::Context) {
+        let mut setting = self.fec.current();
+        let mut upscale_filter = self.upscale_filter;
+        egui::Window::new("Settings")
+            .open(&mut self.open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut setting.enabled, "Forward error correction");
+                ui.add_enabled(
+                    setting.enabled,
+                    egui::Slider::new(
+                        &mut setting.redundancy,
+                        0..=AdaptiveFecController::MAX_REDUNDANCY as u8,
+                    )
+                    .text("Redundancy")
+                    .suffix("%"),
+                );
+                egui::ComboBox::from_label("Upscaling")
+                    .selected_text(upscale_filter.name())
+                    .show_ui(ui, |ui| {
+                        for filter in UpscaleFilter::ALL {
+                            ui.selectable_value(&mut upscale_filter, filter, filter.name());
+                        }
+                    });
+            });
+        if setting != self.fec.current() {
+            self.fec.request(setting);
+        }
+        if upscale_filter != self.upscale_filter {
+            self.upscale_filter = upscale_filter;
+            self.upscale_changed = true;
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use crate::server::ServerManager;
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::process::Child;
+use std::sync::mpsc;
+use std::thread;
+
+/// Lines kept in the scrollback
+const SCROLLBACK: usize = 2000;
+
+/// Columns a tab advances to a multiple of
+const TAB_WIDTH: usize = 8;
+
+/// Escape sequence being read
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// Right after ESC
+    Start,
+    /// `ESC [` with the parameters so far
+    Csi(String),
+    /// `ESC ]` until BEL or `ESC \`
+    Osc {
+        after_esc: bool,
+    },
+}
+
+/// Text a remote PTY has drawn, one line at a time
+///
+/// Understands what a shell prompt with line editing sends: carriage
+/// returns, backspaces and the cursor and erase sequences. Colors and
+/// anything needing a full screen (vi, top) are dropped.
+pub struct Screen {
+    lines: VecDeque<Vec<char>>,
+    column: usize,
+    escape: Escape,
+    /// Start of a UTF-8 character split across reads
+    partial: Vec<u8>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::from([Vec::new()]),
+            column: 0,
+            escape: Escape::None,
+            partial: Vec::new(),
+        }
+    }
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in output from the shell
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.partial.extend_from_slice(bytes);
+        let partial = std::mem::take(&mut self.partial);
+        let (text, rest) = match std::str::from_utf8(&partial) {
+            Ok(text) => (text.to_string(), &[][..]),
+            // Incomplete at the end: keep the tail for the next read
+            Err(e) if e.error_len().is_none() => (
+                String::from_utf8_lossy(&partial[..e.valid_up_to()]).into_owned(),
+                &partial[e.valid_up_to()..],
+            ),
+            Err(_) => (String::from_utf8_lossy(&partial).into_owned(), &[][..]),
+        };
+        self.partial = rest.to_vec();
+        for c in text.chars() {
+            self.put(c);
+        }
+    }
+
+    /// Lines from the oldest kept to the one being written
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.lines.iter().map(|line| line.iter().collect())
+    }
+
+    /// Column of the cursor on the last line
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    fn put(&mut self, c: char) {
+        match std::mem::replace(&mut self.escape, Escape::None) {
+            Escape::None => {}
+            Escape::Start => {
+                self.escape = match c {
+                    '[' => Escape::Csi(String::new()),
+                    ']' => Escape::Osc { after_esc: false },
+                    // Two-character sequences (charset, keypad mode) mean nothing here
+                    _ => Escape::None,
+                };
+                return;
+            }
+            Escape::Csi(mut params) => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    self.csi(&params, c);
+                } else {
+                    params.push(c);
+                    self.escape = Escape::Csi(params);
+                }
+                return;
+            }
+            Escape::Osc { after_esc } => {
+                let done = c == '\x07' || (after_esc && c == '\\');
+                if !done {
+                    self.escape = Escape::Osc {
+                        after_esc: c == '\x1b',
+                    };
+                }
+                return;
+            }
+        }
+        match c {
+            '\x1b' => self.escape = Escape::Start,
+            '\r' => self.column = 0,
+            '\n' => {
+                self.lines.push_back(Vec::new());
+                if self.lines.len() > SCROLLBACK {
+                    self.lines.pop_front();
+                }
+                self.column = 0;
+            }
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.column < next {
+                    self.write(' ');
+                }
+            }
+            c if c.is_control() => {}
+            c => self.write(c),
+        }
+    }
+
+    /// Overwrite the character under the cursor and move right
+    fn write(&mut self, c: char) {
+        let column = self.column;
+        let line = self.current();
+        if column < line.len() {
+            line[column] = c;
+        } else {
+            line.resize(column, ' ');
+            line.push(c);
+        }
+        self.column += 1;
+    }
+
+    fn current(&mut self) -> &mut Vec<char> {
+        self.lines.back_mut().expect("screen always has a line")
+    }
+
+    /// Apply `ESC [ params final`
+    fn csi(&mut self, params: &str, action: char) {
+        let count = params.parse::<usize>().unwrap_or(1).max(1);
+        let column = self.column;
+        match action {
+            'C' => self.column += count,
+            'D' => self.column = self.column.saturating_sub(count),
+            'G' => self.column = count - 1,
+            // Erase to the end of the line, from the start or all of it
+            'K' => {
+                let line = self.current();
+                match params {
+                    "" | "0" => line.truncate(column),
+                    "1" => line.iter_mut().take(column + 1).for_each(|c| *c = ' '),
+                    _ => line.clear(),
+                }
+            }
+            // Delete or insert characters at the cursor
+            'P' => {
+                let line = self.current();
+                if column < line.len() {
+                    line.drain(column..(column + count).min(line.len()));
+                }
+            }
+            '@' => {
+                let line = self.current();
+                if column <= line.len() {
+                    for _ in 0..count {
+                        line.insert(column, ' ');
+                    }
+                }
+            }
+            // Clear screen: start over below the scrollback
+            'J' if params == "2" => {
+                self.lines.push_back(Vec::new());
+                self.column = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Bytes a terminal sends for a key, as the remote line editor expects them
+fn key_bytes(key: egui::Key, modifiers: egui::Modifiers) -> Option<Vec<u8>> {
+    use egui::Key;
+    let name = key.name();
+    // Ctrl+A .. Ctrl+Z are the control characters 1 .. 26
+    if modifiers.ctrl && name.len() == 1 && name.as_bytes()[0].is_ascii_uppercase() {
+        return Some(vec![name.as_bytes()[0] - b'A' + 1]);
+    }
+    let bytes: &[u8] = match key {
+        Key::Enter => b"\r",
+        Key::Backspace => b"\x7f",
+        Key::Tab => b"\t",
+        Key::Escape => b"\x1b",
+        Key::ArrowUp => b"\x1b[A",
+        Key::ArrowDown => b"\x1b[B",
+        Key::ArrowRight => b"\x1b[C",
+        Key::ArrowLeft => b"\x1b[D",
+        Key::Home => b"\x1b[H",
+        Key::End => b"\x1b[F",
+        Key::Delete => b"\x1b[3~",
+        Key::PageUp => b"\x1b[5~",
+        Key::PageDown => b"\x1b[6~",
+        _ => return None,
+    };
+    Some(bytes.to_vec())
+}
+
+/// Running `adb shell` with threads moving its input and output
+struct Session {
+    child: Child,
+    input: mpsc::Sender<Vec<u8>>,
+    output: mpsc::Receiver<Vec<u8>>,
+}
+
+impl Session {
+    fn start(serial: Option<&str>) -> Result<Self> {
+        let mut child = ServerManager::shell_command(serial)?
+            .spawn()
+            .context("Failed to start adb shell")?;
+        let (mut stdin, mut stdout) = child
+            .stdin
+            .take()
+            .zip(child.stdout.take())
+            .context("adb shell has no pipes")?;
+
+        let (input, input_rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for bytes in input_rx {
+                if stdin.write_all(&bytes).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+        let (output_tx, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                match stdout.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if output_tx.send(buf[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            input,
+            output,
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Window with an interactive `adb shell` (Shift+F1)
+///
+/// The shell starts when the window opens and ends with `exit` or when the
+/// window is dropped; reopening after an exit starts a new one.
+pub struct ShellPane {
+    serial: Option<String>,
+    open: bool,
+    /// Give the terminal keyboard focus on the next draw
+    focus: bool,
+    screen: Screen,
+    session: Option<Session>,
+    status: Option<String>,
+}
+
+impl ShellPane {
+    pub fn new(serial: Option<String>) -> Self {
+        Self {
+            serial,
+            open: false,
+            focus: false,
+            screen: Screen::new(),
+            session: None,
+            status: None,
+        }
+    }
+
+    /// Show or hide the window, returning the new state
+    pub fn toggle(&mut self) -> bool {
+        self.open = !self.open;
+        if self.open {
+            self.focus = true;
+            if self.session.is_none() {
+                match Session::start(self.serial.as_deref()) {
+                    Ok(session) => {
+                        self.session = Some(session);
+                        self.status = None;
+                    }
+                    Err(e) => self.status = Some(format!("{:#}", e)),
+                }
+            }
+        }
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Take in shell output; true if the open window needs a redraw
+    pub fn poll(&mut self) -> bool {
+        let Some(session) = &self.session else {
+            return false;
+        };
+        let mut changed = false;
+        loop {
+            match session.output.try_recv() {
+                Ok(bytes) => {
+                    self.screen.feed(&bytes);
+                    changed = true;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.session = None;
+                    self.status = Some("Shell ended".to_string());
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        changed && self.open
+    }
+
+    /// Draw the window, sending what is typed in it to the shell
+    pub fn render(&mut self, ctx: &egui::Context) {
+        let mut typed = Vec::new();
+        let focus = std::mem::take(&mut self.focus);
+        egui::Window::new("Shell")
+            .open(&mut self.open)
+            .default_size([640.0, 360.0])
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let id = ui.make_persistent_id("shell_terminal");
+                if focus {
+                    ui.memory_mut(|memory| memory.request_focus(id));
+                }
+                let focused = ui.memory(|memory| memory.has_focus(id));
+                if focused {
+                    // Tab, arrows and Escape belong to the shell, not egui navigation
+                    ui.memory_mut(|memory| {
+                        memory.set_focus_lock_filter(
+                            id,
+                            egui::EventFilter {
+                                tab: true,
+                                horizontal_arrows: true,
+                                vertical_arrows: true,
+                                escape: true,
+                            },
+                        )
+                    });
+                    typed = ui.input(|input| {
+                        input
+                            .events
+                            .iter()
+                            .filter_map(|event| match event {
+                                egui::Event::Text(text) => Some(text.as_bytes().to_vec()),
+                                egui::Event::Paste(text) => Some(text.as_bytes().to_vec()),
+                                // Ctrl+C and Ctrl+X arrive as clipboard commands
+                                egui::Event::Copy => Some(vec![0x03]),
+                                egui::Event::Cut => Some(vec![0x18]),
+                                egui::Event::Key {
+                                    key,
+                                    pressed: true,
+                                    modifiers,
+                                    ..
+                                } => key_bytes(*key, *modifiers),
+                                _ => None,
+                            })
+                            .flatten()
+                            .collect::<Vec<u8>>()
+                    });
+                }
+
+                if let Some(status) = &self.status {
+                    ui.label(status);
+                }
+                let output = egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        let last = self.screen.lines.len() - 1;
+                        for (i, mut line) in self.screen.lines().enumerate() {
+                            if i == last && focused {
+                                // Block cursor where the next character goes
+                                let column = self.screen.column();
+                                let mut chars: Vec<char> = line.chars().collect();
+                                chars.resize(chars.len().max(column + 1), ' ');
+                                chars[column] = '█';
+                                line = chars.into_iter().collect();
+                            }
+                            ui.label(egui::RichText::new(line).monospace());
+                        }
+                    });
+                let response = ui.interact(output.inner_rect, id, egui::Sense::click());
+                if response.clicked() {
+                    response.request_focus();
+                }
+            });
+
+        if !typed.is_empty() {
+            if let Some(session) = &self.session {
+                let _ = session.input.send(typed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(screen: &Screen) -> Vec<String> {
+        screen.lines().collect()
+    }
+
+    #[test]
+    fn test_screen_follows_line_editing() {
+        let mut screen = Screen::new();
+        // Prompt in color, a typo fixed with backspace and an erase
+        screen.feed(b"\x1b[1;32mshell@pixel\x1b[0m:/ $ lz\x08");
+        screen.feed(b"\x1b[Ks\r\n");
+        screen.feed(b"a\tb\r\n\x1b]0;title\x07caf\xc3");
+        screen.feed(b"\xa9");
+        assert_eq!(text(&screen), ["shell@pixel:/ $ ls", "a       b", "café"]);
+        assert_eq!(screen.column(), 4);
+
+        // Editing in the middle of the line
+        screen.feed(b"\r$ cat\x1b[2D\x1b[@x\x1b[P");
+        assert_eq!(text(&screen).last().unwrap(), "$ cxt");
+        assert_eq!(screen.column(), 4);
+    }
+
+    #[test]
+    fn test_key_bytes() {
+        let ctrl = egui::Modifiers::CTRL;
+        assert_eq!(key_bytes(egui::Key::D, ctrl), Some(vec![4]));
+        assert_eq!(
+            key_bytes(egui::Key::ArrowUp, egui::Modifiers::NONE),
+            Some(b"\x1b[A".to_vec())
+        );
+        assert_eq!(key_bytes(egui::Key::D, egui::Modifiers::NONE), None);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::config::UiTheme;
+use egui::{Color32, Stroke, Visuals};
+
+/// Scale limits so a typo in the config can't make the UI unusable
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 3.0;
+
+/// Apply the configured theme and scale to the overlay UI
+pub fn apply(ctx: &egui::Context, theme: UiTheme, scale: f32) {
+    ctx.set_visuals(match theme {
+        UiTheme::Dark => Visuals::dark(),
+        UiTheme::Light => Visuals::light(),
+        UiTheme::HighContrast => high_contrast(),
+    });
+    ctx.set_zoom_factor(scale.clamp(MIN_SCALE, MAX_SCALE));
+}
+
+/// White on black with thick outlines and a yellow focus ring
+///
+/// The ring marks the focused widget while moving through panels with Tab.
+fn high_contrast() -> Visuals {
+    let mut visuals = Visuals::dark();
+    visuals.override_text_color = Some(Color32::WHITE);
+    visuals.window_fill = Color32::BLACK;
+    visuals.panel_fill = Color32::BLACK;
+    visuals.extreme_bg_color = Color32::BLACK;
+    visuals.window_stroke = Stroke::new(2.0, Color32::WHITE);
+    visuals.hyperlink_color = Color32::from_rgb(0x80, 0xc0, 0xff);
+    visuals.selection.bg_fill = Color32::from_rgb(0x00, 0x3d, 0xa5);
+    visuals.selection.stroke = Stroke::new(2.0, Color32::YELLOW);
+
+    let widgets = &mut visuals.widgets;
+    for state in [
+        &mut widgets.noninteractive,
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+        &mut widgets.open,
+    ] {
+        state.bg_fill = Color32::BLACK;
+        state.weak_bg_fill = Color32::BLACK;
+        state.bg_stroke = Stroke::new(1.5, Color32::WHITE);
+        state.fg_stroke = Stroke::new(2.0, Color32::WHITE);
+    }
+    widgets.hovered.bg_stroke = Stroke::new(2.0, Color32::YELLOW);
+    // Focused widgets are drawn in the active style
+    widgets.active.bg_stroke = Stroke::new(3.0, Color32::YELLOW);
+    widgets.active.fg_stroke = Stroke::new(2.0, Color32::YELLOW);
+    visuals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_high_contrast_outlines_every_widget_state() {
+        let visuals = high_contrast();
+        assert_eq!(visuals.override_text_color, Some(Color32::WHITE));
+        let widgets = &visuals.widgets;
+        for state in [&widgets.inactive, &widgets.hovered, &widgets.active] {
+            assert_eq!(state.bg_fill, Color32::BLACK);
+            assert!(state.bg_stroke.width >= 1.5);
+        }
+        assert_eq!(widgets.active.bg_stroke.color, Color32::YELLOW);
+    }
+}
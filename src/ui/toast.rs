@@ -0,0 +1,161 @@
+use crate::config::ConnectionMode;
+use crate::events::SessionEvent;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a notification stays up, including its fade-out
+const LIFETIME: Duration = Duration::from_secs(4);
+
+/// Final part of the lifetime spent fading out
+const FADE: Duration = Duration::from_millis(600);
+
+/// Notifications shown at once; older ones are dropped early
+const MAX_SHOWN: usize = 4;
+
+struct Toast {
+    text: String,
+    shown_at: Instant,
+}
+
+/// Short notifications in the corner of the video that fade out on their own
+///
+/// Session events (reconnects, saved screenshots, recordings...) are turned
+/// into toasts by `on_event`; anything else can be announced with `push`.
+pub struct Toasts {
+    enabled: bool,
+    shown: VecDeque<Toast>,
+    /// A reconnect is under way, so the next connect is announced
+    reconnecting: bool,
+}
+
+impl Toasts {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            shown: VecDeque::new(),
+            reconnecting: false,
+        }
+    }
+
+    /// Show `text` from `now` on
+    pub fn push(&mut self, text: impl Into<String>, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+        if self.shown.len() == MAX_SHOWN {
+            self.shown.pop_front();
+        }
+        self.shown.push_back(Toast {
+            text: text.into(),
+            shown_at: now,
+        });
+    }
+
+    /// Announce a session event, if it is worth one
+    pub fn on_event(&mut self, event: &SessionEvent, now: Instant) {
+        let text = match event {
+            SessionEvent::Reconnecting { attempt } => {
+                self.reconnecting = true;
+                format!("Reconnecting (attempt {})...", attempt)
+            }
+            SessionEvent::Connected { mode } if self.reconnecting => {
+                self.reconnecting = false;
+                let mode = match mode {
+                    ConnectionMode::Tcp => "TCP",
+                    ConnectionMode::Quic => "QUIC",
+                };
+                format!("Reconnected over {}", mode)
+            }
+            SessionEvent::RecordingStarted { path } => {
+                format!("Recording to {}", path.display())
+            }
+            SessionEvent::ScreenshotSaved { path } => {
+                format!("Screenshot saved to {}", path.display())
+            }
+            SessionEvent::ReplaySaved { path } => format!("Replay saved to {}", path.display()),
+            SessionEvent::Disconnected => "Disconnected".to_string(),
+            _ => return,
+        };
+        self.push(text, now);
+    }
+
+    /// Drop the toasts that have faded out; true while any are showing,
+    /// which needs a redraw every frame for the fade
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let before = self.shown.len();
+        self.shown
+            .retain(|toast| now.saturating_duration_since(toast.shown_at) < LIFETIME);
+        !self.shown.is_empty() || self.shown.len() != before
+    }
+
+    /// Draw the toasts, newest at the bottom
+    pub fn render(&self, ctx: &egui::Context, now: Instant) {
+        if self.shown.is_empty() {
+            return;
+        }
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-16.0, -16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Max), |ui| {
+                    for toast in &self.shown {
+                        let left =
+                            LIFETIME.saturating_sub(now.saturating_duration_since(toast.shown_at));
+                        let opacity = (left.as_secs_f32() / FADE.as_secs_f32()).min(1.0);
+                        ui.scope(|ui| {
+                            ui.set_opacity(opacity);
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.label(&toast.text);
+                            });
+                        });
+                    }
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_events_become_toasts_that_expire() {
+        let start = Instant::now();
+        let mut toasts = Toasts::new(true);
+        let connected = SessionEvent::Connected {
+            mode: ConnectionMode::Quic,
+        };
+        // Only a connect after a reconnect is news
+        toasts.on_event(&connected, start);
+        assert!(!toasts.tick(start));
+
+        toasts.on_event(&SessionEvent::Reconnecting { attempt: 2 }, start);
+        toasts.on_event(&connected, start + Duration::from_secs(1));
+        toasts.on_event(
+            &SessionEvent::ScreenshotSaved {
+                path: PathBuf::from("shot.png"),
+            },
+            start + Duration::from_secs(2),
+        );
+        let texts: Vec<&str> = toasts.shown.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Reconnecting (attempt 2)...",
+                "Reconnected over QUIC",
+                "Screenshot saved to shot.png"
+            ]
+        );
+
+        assert!(toasts.tick(start + LIFETIME));
+        assert_eq!(toasts.shown.len(), 2);
+        // The last one going still needs a frame to clear it
+        assert!(toasts.tick(start + LIFETIME * 2));
+        assert!(!toasts.tick(start + LIFETIME * 2));
+
+        let mut off = Toasts::new(false);
+        off.push("hidden", start);
+        assert!(!off.tick(start));
+    }
+}
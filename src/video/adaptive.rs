@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+/// Internal render scale while the GPU is over budget
+const REDUCED_SCALE: f32 = 0.5;
+
+/// Consecutive over-budget frames before dropping to the reduced scale
+const DOWNSCALE_AFTER: u32 = 30;
+
+/// Consecutive frames with headroom before returning to full quality
+const UPSCALE_AFTER: u32 = 120;
+
+/// Fraction of the budget the reduced scale must stay under to go back up
+///
+/// Full resolution shades four times the pixels, so leave room for that.
+const HEADROOM: f64 = 0.5;
+
+/// Weight of the newest sample in the frame time average
+const SMOOTHING: f64 = 0.1;
+
+/// Picks the internal video resolution from measured GPU frame times
+///
+/// The render-side counterpart of adaptive bitrate: a weak GPU renders the
+/// video at half resolution (sharpened on upscale) instead of missing frames.
+#[derive(Debug, Clone)]
+pub struct RenderScaler {
+    budget_ms: f64,
+    average_ms: Option<f64>,
+    streak: u32,
+    scale: f32,
+}
+
+impl RenderScaler {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget_ms: budget.as_secs_f64() * 1000.0,
+            average_ms: None,
+            streak: 0,
+            scale: 1.0,
+        }
+    }
+
+    /// Budget for a display refreshing at `refresh_hz`
+    pub fn for_refresh_rate(refresh_hz: f64) -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / refresh_hz.max(1.0)))
+    }
+
+    /// Current scale of the video render target (1.0 is full resolution)
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Add the GPU time of a frame; returns true when the scale changed
+    pub fn record(&mut self, gpu_time: Duration) -> bool {
+        let sample = gpu_time.as_secs_f64() * 1000.0;
+        let average = match self.average_ms {
+            Some(average) => average + SMOOTHING * (sample - average),
+            None => sample,
+        };
+        self.average_ms = Some(average);
+
+        let full = self.scale >= 1.0;
+        let pressing = if full {
+            average > self.budget_ms
+        } else {
+            average < self.budget_ms * HEADROOM
+        };
+        self.streak = if pressing { self.streak + 1 } else { 0 };
+
+        let limit = if full { DOWNSCALE_AFTER } else { UPSCALE_AFTER };
+        if self.streak < limit {
+            return false;
+        }
+        self.streak = 0;
+        self.scale = if full { REDUCED_SCALE } else { 1.0 };
+        tracing::info!(
+            "GPU frame time {:.1} ms (budget {:.1} ms), rendering video at {:.0}%",
+            average,
+            self.budget_ms,
+            self.scale * 100.0
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_drops_under_load_and_recovers() {
+        let mut scaler = RenderScaler::new(Duration::from_millis(16));
+
+        // A single slow frame is not enough
+        assert!(!scaler.record(Duration::from_millis(40)));
+        for _ in 0..DOWNSCALE_AFTER * 2 {
+            scaler.record(Duration::from_millis(25));
+        }
+        assert_eq!(scaler.scale(), REDUCED_SCALE);
+
+        // Cheaper frames, but not enough headroom to go back up
+        for _ in 0..UPSCALE_AFTER * 2 {
+            scaler.record(Duration::from_millis(12));
+        }
+        assert_eq!(scaler.scale(), REDUCED_SCALE);
+
+        for _ in 0..UPSCALE_AFTER * 2 {
+            scaler.record(Duration::from_millis(4));
+        }
+        assert_eq!(scaler.scale(), 1.0);
+    }
+}
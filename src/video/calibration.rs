@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Color correction applied to the video in the fragment shader
+///
+/// `matrix` maps linear RGB to linear RGB (rows are output channels, like an
+/// ICC matrix/TRC profile), then each channel is raised to `gamma`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorCalibration {
+    /// 3x3 linear RGB transform, row-major
+    pub matrix: [[f32; 3]; 3],
+
+    /// Extra exponent after the matrix (1.0 = unchanged, >1 darkens midtones)
+    pub gamma: f32,
+}
+
+impl Default for ColorCalibration {
+    fn default() -> Self {
+        Self {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ColorCalibration {
+    /// Uniform buffer contents matching `Calibration` in `video.wgsl`
+    ///
+    /// WGSL stores a `mat3x3<f32>` as three column vectors padded to 16 bytes.
+    pub fn to_uniform(&self) -> [f32; 16] {
+        let mut data = [0.0; 16];
+        for col in 0..3 {
+            for row in 0..3 {
+                data[col * 4 + row] = self.matrix[row][col];
+            }
+        }
+        data[12] = self.gamma;
+        data
+    }
+}
+
+/// Calibration profiles keyed by device serial, loaded from a TOML file
+///
+/// ```toml
+/// [default]
+/// gamma = 1.0
+///
+/// ["192.168.1.20"]
+/// matrix = [[0.95, 0.05, 0.0], [0.02, 0.97, 0.01], [0.0, 0.03, 0.97]]
+/// gamma = 1.05
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ColorProfiles {
+    #[serde(flatten)]
+    profiles: HashMap<String, ColorCalibration>,
+}
+
+impl ColorProfiles {
+    /// Load profiles from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read color profiles {:?}", path))?;
+        toml::from_str(&text).with_context(|| format!("Invalid color profiles in {:?}", path))
+    }
+
+    /// Profile for a device, falling back to `[default]` and then identity
+    pub fn for_device(&self, serial: Option<&str>) -> ColorCalibration {
+        serial
+            .and_then(|serial| self.profiles.get(serial))
+            .or_else(|| self.profiles.get("default"))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_layout_is_column_major() {
+        let calibration = ColorCalibration {
+            matrix: [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]],
+            gamma: 2.2,
+        };
+        let data = calibration.to_uniform();
+        assert_eq!(&data[0..4], &[1.0, 4.0, 7.0, 0.0]);
+        assert_eq!(&data[4..8], &[2.0, 5.0, 8.0, 0.0]);
+        assert_eq!(&data[8..12], &[3.0, 6.0, 9.0, 0.0]);
+        assert_eq!(data[12], 2.2);
+    }
+
+    #[test]
+    fn test_profile_lookup() {
+        let profiles: ColorProfiles = toml::from_str(
+            "[default]\ngamma = 1.1\n\n[\"R5CT1234\"]\nmatrix = [[0.9, 0.1, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]\n",
+        )
+        .unwrap();
+
+        let device = profiles.for_device(Some("R5CT1234"));
+        assert_eq!(device.matrix[0], [0.9, 0.1, 0.0]);
+        assert_eq!(device.gamma, 1.0);
+        assert_eq!(profiles.for_device(Some("other")).gamma, 1.1);
+        assert_eq!(profiles.for_device(None).gamma, 1.1);
+        assert_eq!(
+            ColorProfiles::default().for_device(None),
+            ColorCalibration::default()
+        );
+    }
+}
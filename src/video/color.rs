@@ -0,0 +1,211 @@
+use ffmpeg::util::color::{Range, Space, TransferCharacteristic};
+use ffmpeg_next as ffmpeg;
+
+/// YUV to RGB matrix a stream was encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvMatrix {
+    /// SD video (BT.601 / SMPTE 170M)
+    Bt601,
+    /// HD video, what Android encoders emit
+    Bt709,
+    /// UHD and HDR video (non-constant luminance)
+    Bt2020,
+}
+
+impl YuvMatrix {
+    /// Luma weights of red and blue (Kr, Kb)
+    fn weights(&self) -> (f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+            YuvMatrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+
+    /// `SWS_CS_*` constant for libswscale
+    fn sws_colorspace(&self) -> i32 {
+        match self {
+            YuvMatrix::Bt601 => ffmpeg::ffi::SWS_CS_ITU601 as i32,
+            YuvMatrix::Bt709 => ffmpeg::ffi::SWS_CS_ITU709 as i32,
+            YuvMatrix::Bt2020 => ffmpeg::ffi::SWS_CS_BT2020 as i32,
+        }
+    }
+}
+
+/// How encoded values map to light
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// SDR gamma (BT.709 / sRGB)
+    Sdr,
+    /// HDR10 perceptual quantizer (SMPTE ST 2084)
+    Pq,
+    /// Hybrid log-gamma (ARIB STD-B67)
+    Hlg,
+}
+
+/// Color space of decoded YUV frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub matrix: YuvMatrix,
+    /// Samples use 0-255 instead of the limited 16-235 (chroma 16-240) range
+    pub full_range: bool,
+    pub transfer: Transfer,
+}
+
+impl Default for ColorSpace {
+    /// BT.709 limited range SDR, the usual hardware encoder output
+    fn default() -> Self {
+        Self {
+            matrix: YuvMatrix::Bt709,
+            full_range: false,
+            transfer: Transfer::Sdr,
+        }
+    }
+}
+
+impl ColorSpace {
+    /// Color space a decoder reported for a frame
+    ///
+    /// Streams that leave it unspecified are assumed limited range, BT.709
+    /// from 720 lines up and BT.601 below (the FFmpeg convention).
+    pub fn from_ffmpeg(
+        space: Space,
+        range: Range,
+        transfer: TransferCharacteristic,
+        height: u32,
+    ) -> Self {
+        let matrix = match space {
+            Space::BT709 => YuvMatrix::Bt709,
+            Space::BT470BG | Space::SMPTE170M | Space::SMPTE240M | Space::FCC => YuvMatrix::Bt601,
+            Space::BT2020NCL | Space::BT2020CL => YuvMatrix::Bt2020,
+            _ if height >= 720 => YuvMatrix::Bt709,
+            _ => YuvMatrix::Bt601,
+        };
+        let transfer = match transfer {
+            TransferCharacteristic::SMPTE2084 => Transfer::Pq,
+            TransferCharacteristic::ARIB_STD_B67 => Transfer::Hlg,
+            _ => Transfer::Sdr,
+        };
+        Self {
+            matrix,
+            full_range: range == Range::JPEG,
+            transfer,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.transfer != Transfer::Sdr
+    }
+
+    /// Per-sample converter for the CPU path
+    pub fn converter(&self) -> YuvConverter {
+        let (kr, kb) = self.matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, c_scale) = if self.full_range {
+            (0.0, 1.0, 1.0)
+        } else {
+            (16.0, 255.0 / 219.0, 255.0 / 224.0)
+        };
+        YuvConverter {
+            y_offset,
+            y_scale,
+            rv: 2.0 * (1.0 - kr) * c_scale,
+            gu: 2.0 * kb * (1.0 - kb) / kg * c_scale,
+            gv: 2.0 * kr * (1.0 - kr) / kg * c_scale,
+            bu: 2.0 * (1.0 - kb) * c_scale,
+        }
+    }
+
+    /// Tell a libswscale context to read frames in this color space
+    ///
+    /// Without this swscale assumes BT.601 limited range for any YUV input.
+    pub fn apply_to_scaler(&self, scaler: &mut ffmpeg::software::scaling::Context) {
+        // SAFETY: the context is valid and the coefficient tables are static
+        unsafe {
+            let table = ffmpeg::ffi::sws_getCoefficients(self.matrix.sws_colorspace());
+            ffmpeg::ffi::sws_setColorspaceDetails(
+                scaler.as_mut_ptr(),
+                table,
+                self.full_range as i32,
+                table,
+                1, // RGB output is always full range
+                0,
+                1 << 16,
+                1 << 16,
+            );
+        }
+    }
+}
+
+/// YUV to RGBA conversion with the coefficients of one [`ColorSpace`]
+#[derive(Debug, Clone, Copy)]
+pub struct YuvConverter {
+    y_offset: f32,
+    y_scale: f32,
+    rv: f32,
+    gu: f32,
+    gv: f32,
+    bu: f32,
+}
+
+impl YuvConverter {
+    pub fn rgba(&self, y: u8, u: u8, v: u8) -> [u8; 4] {
+        let y = (y as f32 - self.y_offset) * self.y_scale;
+        let u = u as f32 - 128.0;
+        let v = v as f32 - 128.0;
+
+        let r = (y + self.rv * v).round().clamp(0.0, 255.0) as u8;
+        let g = (y - self.gu * u - self.gv * v).round().clamp(0.0, 255.0) as u8;
+        let b = (y + self.bu * u).round().clamp(0.0, 255.0) as u8;
+        [r, g, b, 255]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges_and_matrices() {
+        let limited_709 = ColorSpace::default().converter();
+        // Limited range black and white stretch to the full 0-255
+        assert_eq!(limited_709.rgba(16, 128, 128), [0, 0, 0, 255]);
+        assert_eq!(limited_709.rgba(235, 128, 128), [255, 255, 255, 255]);
+        // BT.709 limited red (8-bit code values are rounded)
+        assert_eq!(limited_709.rgba(63, 102, 240), [255, 1, 0, 255]);
+
+        let full_601 = ColorSpace {
+            matrix: YuvMatrix::Bt601,
+            full_range: true,
+            transfer: Transfer::Sdr,
+        }
+        .converter();
+        assert_eq!(full_601.rgba(16, 128, 128), [16, 16, 16, 255]);
+        // BT.601 full range red
+        assert_eq!(full_601.rgba(76, 85, 255), [254, 0, 0, 255]);
+
+        let unspecified = TransferCharacteristic::Unspecified;
+        assert_eq!(
+            ColorSpace::from_ffmpeg(Space::Unspecified, Range::Unspecified, unspecified, 480)
+                .matrix,
+            YuvMatrix::Bt601
+        );
+        assert_eq!(
+            ColorSpace::from_ffmpeg(Space::Unspecified, Range::JPEG, unspecified, 1080),
+            ColorSpace {
+                matrix: YuvMatrix::Bt709,
+                full_range: true,
+                transfer: Transfer::Sdr,
+            }
+        );
+        // HDR10 from a phone: BT.2020, limited range, PQ
+        let hdr10 = ColorSpace::from_ffmpeg(
+            Space::BT2020NCL,
+            Range::MPEG,
+            TransferCharacteristic::SMPTE2084,
+            2160,
+        );
+        assert_eq!(hdr10.matrix, YuvMatrix::Bt2020);
+        assert!(hdr10.is_hdr());
+    }
+}
@@ -1,13 +1,19 @@
+use crate::config::VideoCodec;
+use crate::video::color::{ColorSpace, YuvConverter};
+use crate::video::gpu::{GpuApi, GpuFrame};
+use crate::video::nal::{self, AccessUnits, NalUnit};
+use crate::video::pool::{FrameBuffer, FramePool};
 use anyhow::{Context as AnyhowContext, Result};
 use bytes::Bytes;
 use ffmpeg::codec::Context;
-use ffmpeg::codec::decoder::Video as VideoDecoder;
+use ffmpeg::codec::decoder::{Conceal, Video as VideoDecoder};
 use ffmpeg::codec::parameters::Parameters;
 use ffmpeg::format::Pixel;
 use ffmpeg::software::scaling::{context::Context as ScalingContext, flag::Flags};
 use ffmpeg::util::frame::video::Video as VideoFrame;
 use ffmpeg_next as ffmpeg;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 /// Pixel format for decoded frames
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,6 +21,8 @@ pub enum PixelFormat {
     YUV420P,
     NV12,
     RGBA,
+    /// 16 bits per channel, little-endian; 10-bit and HDR streams decode to this
+    RGBA64,
 }
 
 impl PixelFormat {
@@ -23,6 +31,7 @@ impl PixelFormat {
             PixelFormat::YUV420P => Pixel::YUV420P,
             PixelFormat::NV12 => Pixel::NV12,
             PixelFormat::RGBA => Pixel::RGBA,
+            PixelFormat::RGBA64 => Pixel::RGBA64LE,
         }
     }
 
@@ -31,17 +40,39 @@ impl PixelFormat {
             PixelFormat::YUV420P => 1, // Actually 1.5 bytes/pixel but we handle planes separately
             PixelFormat::NV12 => 1,
             PixelFormat::RGBA => 4,
+            PixelFormat::RGBA64 => 8,
         }
     }
 }
 
+/// Whether frames in `format` carry more than 8 bits per sample
+fn is_high_bit_depth(format: Pixel) -> bool {
+    matches!(
+        format,
+        Pixel::P010LE
+            | Pixel::P010BE
+            | Pixel::P016LE
+            | Pixel::P016BE
+            | Pixel::YUV420P10LE
+            | Pixel::YUV420P10BE
+            | Pixel::YUV420P12LE
+            | Pixel::YUV420P12BE
+            | Pixel::YUV422P10LE
+            | Pixel::YUV444P10LE
+    )
+}
+
 /// Decoded video frame with metadata
+#[derive(Clone)]
 pub struct DecodedFrame {
     pub pts: i64,
-    pub data: Vec<u8>,
+    /// Pooled pixels; cloning a frame shares them
+    pub data: FrameBuffer,
     pub width: u32,
     pub height: u32,
     pub format: PixelFormat,
+    /// How YUV samples map to RGB (unused for RGBA frames)
+    pub color: ColorSpace,
 }
 
 impl DecodedFrame {
@@ -49,132 +80,474 @@ impl DecodedFrame {
     pub fn stride(&self) -> usize {
         self.width as usize * self.format.bytes_per_pixel()
     }
+
+    /// Pixels as tightly packed RGBA
+    ///
+    /// RGBA64 frames keep the high byte of each channel, without tone mapping.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        if self.format == PixelFormat::RGBA {
+            return self.data.to_vec();
+        }
+        let mut rgba = Vec::new();
+        self.rgba_into(&mut rgba);
+        rgba
+    }
+
+    /// Pixels as tightly packed RGBA without copying
+    ///
+    /// RGBA frames are borrowed as-is; other frames are converted into
+    /// `scratch`, which callers keep across frames so the conversion does not
+    /// allocate.
+    pub fn rgba_into<'s>(&'s self, scratch: &'s mut Vec<u8>) -> &'s [u8] {
+        match self.format {
+            PixelFormat::RGBA => &self.data,
+            PixelFormat::RGBA64 => {
+                // High byte of each little-endian channel
+                scratch.clear();
+                scratch.extend(self.data.iter().skip(1).step_by(2));
+                scratch
+            }
+            PixelFormat::YUV420P => {
+                let converter = self.color.converter();
+                yuv420p_to_rgba(&self.data, self.width, self.height, &converter, scratch);
+                scratch
+            }
+            PixelFormat::NV12 => {
+                let converter = self.color.converter();
+                nv12_to_rgba(&self.data, self.width, self.height, &converter, scratch);
+                scratch
+            }
+        }
+    }
+
+    /// RGBA value of a single pixel, `None` outside the frame
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let w = self.width as usize;
+        let h = self.height as usize;
+        let (x, y) = (x as usize, y as usize);
+        let y_index = y * w + x;
+        let converter = self.color.converter();
+
+        match self.format {
+            PixelFormat::RGBA => self.data.get(y_index * 4..y_index * 4 + 4)?.try_into().ok(),
+            PixelFormat::RGBA64 => {
+                let channels = self.data.get(y_index * 8..y_index * 8 + 8)?;
+                Some([channels[1], channels[3], channels[5], channels[7]])
+            }
+            PixelFormat::YUV420P => {
+                let uv_index = (y / 2) * (w / 2) + (x / 2);
+                let u_plane = w * h;
+                let v_plane = u_plane + (w / 2) * (h / 2);
+                Some(converter.rgba(
+                    *self.data.get(y_index)?,
+                    *self.data.get(u_plane + uv_index)?,
+                    *self.data.get(v_plane + uv_index)?,
+                ))
+            }
+            PixelFormat::NV12 => {
+                let uv_index = w * h + (y / 2) * w + (x / 2) * 2;
+                Some(converter.rgba(
+                    *self.data.get(y_index)?,
+                    *self.data.get(uv_index)?,
+                    *self.data.get(uv_index + 1)?,
+                ))
+            }
+        }
+    }
+}
+
+/// Convert YUV420P to RGBA
+fn yuv420p_to_rgba(
+    yuv_data: &[u8],
+    width: u32,
+    height: u32,
+    converter: &YuvConverter,
+    rgba: &mut Vec<u8>,
+) {
+    let w = width as usize;
+    let h = height as usize;
+    let y_size = w * h;
+    let uv_size = (w / 2) * (h / 2);
+
+    rgba.resize(w * h * 4, 0);
+
+    for y in 0..h {
+        for x in 0..w {
+            let y_index = y * w + x;
+            let uv_index = (y / 2) * (w / 2) + (x / 2);
+
+            let pixel = converter.rgba(
+                yuv_data[y_index],
+                yuv_data[y_size + uv_index],
+                yuv_data[y_size + uv_size + uv_index],
+            );
+
+            let rgba_index = y_index * 4;
+            rgba[rgba_index..rgba_index + 4].copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Convert NV12 to RGBA
+fn nv12_to_rgba(
+    nv12_data: &[u8],
+    width: u32,
+    height: u32,
+    converter: &YuvConverter,
+    rgba: &mut Vec<u8>,
+) {
+    let w = width as usize;
+    let h = height as usize;
+    let y_size = w * h;
+
+    rgba.resize(w * h * 4, 0);
+
+    for y in 0..h {
+        for x in 0..w {
+            let y_index = y * w + x;
+            let uv_index = (y / 2) * w + (x / 2) * 2;
+
+            let pixel = converter.rgba(
+                nv12_data[y_index],
+                nv12_data[y_size + uv_index],
+                nv12_data[y_size + uv_index + 1],
+            );
+
+            let rgba_index = y_index * 4;
+            rgba[rgba_index..rgba_index + 4].copy_from_slice(&pixel);
+        }
+    }
 }
 
 /// Hardware-accelerated video decoder
 pub struct HardwareVideoDecoder {
     decoder: VideoDecoder,
+    /// Decoder preference the current decoder was created from ("none" after a fallback)
+    hw_decoder: String,
+    codec: VideoCodec,
     scaler: Option<ScalingContext>,
-    #[allow(dead_code)]
+    /// Input the scaler was set up for: format, size and color space
+    scaler_input: Option<(Pixel, u32, u32, ColorSpace)>,
+    /// Frames drained from a decoder replaced by `reset`, waiting for `take_flushed`
     frame_queue: VecDeque<DecodedFrame>,
     output_format: PixelFormat,
-    packet_buffer: Vec<u8>,
+    /// Regroups received packets into whole access units
+    access_units: AccessUnits,
+    /// Last codec config packet (SPS/PPS), replayed to a fallback decoder
+    config: Vec<u8>,
+    /// Output buffers recycled once the renderer drops a frame
+    pool: FramePool,
+    /// Time the last frame took to convert to the output format
+    convert_time: Duration,
+    /// API decoded frames stay on, for `decode_gpu`
+    gpu: Option<GpuApi>,
 }
 
 impl HardwareVideoDecoder {
+    /// Idle frame buffers kept for reuse (channel + shown frame + slack)
+    const POOLED_FRAMES: usize = 4;
+
+    /// FFmpeg hardware APIs "auto" tries, preferred first: platform-agnostic
+    /// or native (D3D11VA/QSV) before vendor-specific (CUVID)
+    pub const HW_APIS: [&'static str; 5] = ["d3d11va", "dxva2", "qsv", "cuvid", "vaapi"];
+
     /// Create a new hardware-accelerated video decoder
     ///
     /// # Arguments
     /// * `hw_decoder` - Hardware decoder preference: "auto", "nvdec", "qsv", "vaapi", "none"
+    /// * `codec` - Codec of the stream, as announced by the server
     /// * `output_format` - Desired output pixel format
-    pub fn new(hw_decoder: &str, output_format: PixelFormat) -> Result<Self> {
+    pub fn new(hw_decoder: &str, codec: VideoCodec, output_format: PixelFormat) -> Result<Self> {
         // Initialize FFmpeg
         ffmpeg::init().context("Failed to initialize FFmpeg")?;
 
         // Find decoder based on hardware preference
-        let decoder = Self::create_decoder(hw_decoder)?;
+        let decoder = Self::create_decoder(hw_decoder, codec)?;
+        Ok(Self::with_decoder(
+            decoder,
+            hw_decoder,
+            codec,
+            output_format,
+            None,
+        ))
+    }
+
+    /// Create a decoder whose frames stay on the GPU, read with `decode_gpu`
+    ///
+    /// Fails where the platform's API (D3D11, VA-API) cannot decode `codec`;
+    /// there is no software fallback, a decoding failure is returned as is.
+    pub fn new_gpu(codec: VideoCodec) -> Result<Self> {
+        ffmpeg::init().context("Failed to initialize FFmpeg")?;
+        let api = GpuApi::native().context("GPU frames are not supported on this platform")?;
+        let decoder = Self::create_gpu_decoder(codec, api)?;
+        tracing::info!("Using {} decoder with frames kept on the GPU", api.name());
+        Ok(Self::with_decoder(
+            decoder,
+            api.name(),
+            codec,
+            PixelFormat::NV12,
+            Some(api),
+        ))
+    }
 
-        Ok(Self {
+    fn with_decoder(
+        decoder: VideoDecoder,
+        hw_decoder: &str,
+        codec: VideoCodec,
+        output_format: PixelFormat,
+        gpu: Option<GpuApi>,
+    ) -> Self {
+        Self {
             decoder,
+            hw_decoder: hw_decoder.to_string(),
+            codec,
             scaler: None,
+            scaler_input: None,
             frame_queue: VecDeque::new(),
             output_format,
-            packet_buffer: Vec::new(),
-        })
+            access_units: AccessUnits::new(codec),
+            config: Vec::new(),
+            pool: FramePool::new(Self::POOLED_FRAMES),
+            convert_time: Duration::ZERO,
+            gpu,
+        }
+    }
+
+    /// Whether FFmpeg can decode `codec` here (in software at least)
+    pub fn supports(codec: VideoCodec) -> bool {
+        ffmpeg::init().is_ok()
+            && Self::software_names(codec)
+                .iter()
+                .any(|name| ffmpeg::codec::decoder::find_by_name(name).is_some())
     }
 
     /// Create hardware or software decoder based on preference
-    fn create_decoder(hw_decoder: &str) -> Result<VideoDecoder> {
+    fn create_decoder(hw_decoder: &str, codec: VideoCodec) -> Result<VideoDecoder> {
         match hw_decoder.to_lowercase().as_str() {
             "nvdec" => {
                 // Try NVDEC (NVIDIA hardware decoding)
-                Self::try_hw_decoder(&["h264_cuvid", "hevc_cuvid"])
-                    .or_else(|_| Self::create_software_decoder())
+                Self::try_hw_decoder(codec, "cuvid")
+                    .or_else(|_| Self::create_software_decoder(codec))
             }
             "qsv" => {
                 // Try QSV (Intel Quick Sync Video)
-                Self::try_hw_decoder(&["h264_qsv", "hevc_qsv"])
-                    .or_else(|_| Self::create_software_decoder())
+                Self::try_hw_decoder(codec, "qsv").or_else(|_| Self::create_software_decoder(codec))
             }
             "vaapi" => {
                 // Try VAAPI (Video Acceleration API for Linux/AMD)
-                Self::try_hw_decoder(&["h264_vaapi", "hevc_vaapi"])
-                    .or_else(|_| Self::create_software_decoder())
+                Self::try_hw_decoder(codec, "vaapi")
+                    .or_else(|_| Self::create_software_decoder(codec))
             }
             "auto" => {
                 // Try hardware decoders in order of preference
-                // Prefer platform-agnostic or native (D3D11VA/QSV) before vendor-specific (CUVID)
-                Self::try_hw_decoder(&["h264_d3d11va", "hevc_d3d11va"])
-                    .or_else(|_| Self::try_hw_decoder(&["h264_dxva2", "hevc_dxva2"]))
-                    .or_else(|_| Self::try_hw_decoder(&["h264_qsv", "hevc_qsv"]))
-                    .or_else(|_| Self::try_hw_decoder(&["h264_cuvid", "hevc_cuvid"]))
-                    .or_else(|_| Self::try_hw_decoder(&["h264_vaapi", "hevc_vaapi"]))
-                    .or_else(|_| Self::create_software_decoder())
+                Self::HW_APIS
+                    .into_iter()
+                    .find_map(|api| Self::try_hw_decoder(codec, api).ok())
+                    .map_or_else(|| Self::create_software_decoder(codec), Ok)
             }
             _ => {
                 // Use software decoder
-                Self::create_software_decoder()
+                Self::create_software_decoder(codec)
             }
         }
     }
 
+    /// Which of `HW_APIS` can open a decoder for `codec` on this machine
+    ///
+    /// Opening one is as far as this goes: a driver may still fail on the
+    /// first real frame.
+    pub fn probe(codec: VideoCodec) -> Result<Vec<(&'static str, bool)>> {
+        ffmpeg::init().context("Failed to initialize FFmpeg")?;
+        Ok(Self::HW_APIS
+            .into_iter()
+            .map(|api| {
+                let name = format!("{}_{}", Self::ffmpeg_name(codec), api);
+                let works = ffmpeg::codec::decoder::find_by_name(&name)
+                    .and_then(|decoder| Self::create_context(&decoder).ok())
+                    .is_some_and(|context| Self::open_video(context).is_ok());
+                (api, works)
+            })
+            .collect())
+    }
+
     /// Create a context with the specified codec
     fn create_context(codec: &ffmpeg::Codec) -> Result<Context> {
         let mut params = Parameters::new();
         unsafe {
             (*params.as_mut_ptr()).codec_id = codec.id().into();
         }
-        Ok(Context::from_parameters(params)?)
+        let mut context = Context::from_parameters(params)?;
+        // Slice threads split each frame across cores without the frame
+        // delay of frame threading (count 0 lets FFmpeg pick)
+        context.set_threading(ffmpeg::threading::Config::kind(
+            ffmpeg::threading::Type::Slice,
+        ));
+        Ok(context)
     }
 
-    /// Try to create a hardware decoder
-    fn try_hw_decoder(codec_names: &[&str]) -> Result<VideoDecoder> {
-        for codec_name in codec_names {
-            if let Some(codec) = ffmpeg::codec::decoder::find_by_name(codec_name) {
-                let context = Self::create_context(&codec)?;
-                if let Ok(decoder) = context.decoder().video() {
-                    tracing::info!("Using hardware decoder: {}", codec_name);
-                    return Ok(decoder);
-                }
-            }
+    /// Open the decoder of `context`, concealing damage in broken pictures
+    /// (motion guessed from neighbouring blocks, then deblocked) rather than
+    /// showing it as is
+    fn open_video(context: Context) -> Result<VideoDecoder, ffmpeg::Error> {
+        let mut decoder = context.decoder();
+        decoder.conceal(Conceal::GUESS_MVS | Conceal::DEBLOCK);
+        decoder.video()
+    }
+
+    /// Open FFmpeg's own decoder for `codec` with `api` as its hardware device
+    fn create_gpu_decoder(codec: VideoCodec, api: GpuApi) -> Result<VideoDecoder> {
+        let name = Self::ffmpeg_name(codec);
+        let decoder = ffmpeg::codec::decoder::find_by_name(name)
+            .with_context(|| format!("FFmpeg has no {} decoder", name))?;
+        let mut context = Self::create_context(&decoder)?;
+        // SAFETY: the context is fresh and opened right after
+        unsafe { api.attach(context.as_mut_ptr())? };
+        Self::open_video(context)
+            .with_context(|| format!("Failed to open the {} {} decoder", api.name(), name))
+    }
+
+    /// FFmpeg name of the codec, also the prefix of its hardware decoders
+    fn ffmpeg_name(codec: VideoCodec) -> &'static str {
+        match codec {
+            VideoCodec::H264 => "h264",
+            VideoCodec::H265 => "hevc",
+            VideoCodec::Av1 => "av1",
         }
-        Err(anyhow::anyhow!("No hardware decoder available"))
     }
 
-    /// Create software decoder (fallback)
-    fn create_software_decoder() -> Result<VideoDecoder> {
-        // Try H.264 first, then H.265
-        if let Some(codec) = ffmpeg::codec::decoder::find_by_name("h264") {
+    /// Try to create a hardware decoder for `codec` through `api` (e.g. "qsv")
+    fn try_hw_decoder(codec: VideoCodec, api: &str) -> Result<VideoDecoder> {
+        let codec_name = format!("{}_{}", Self::ffmpeg_name(codec), api);
+        if let Some(codec) = ffmpeg::codec::decoder::find_by_name(&codec_name) {
             let context = Self::create_context(&codec)?;
-            if let Ok(decoder) = context.decoder().video() {
-                tracing::info!("Using software H.264 decoder");
+            if let Ok(decoder) = Self::open_video(context) {
+                tracing::info!("Using hardware decoder: {}", codec_name);
                 return Ok(decoder);
             }
         }
+        Err(anyhow::anyhow!("No hardware decoder available"))
+    }
 
-        if let Some(codec) = ffmpeg::codec::decoder::find_by_name("hevc") {
-            let context = Self::create_context(&codec)?;
-            if let Ok(decoder) = context.decoder().video() {
-                tracing::info!("Using software H.265 decoder");
-                return Ok(decoder);
+    /// FFmpeg software decoders for `codec`, preferred first
+    fn software_names(codec: VideoCodec) -> Vec<&'static str> {
+        match codec {
+            // dav1d is much faster than FFmpeg's own AV1 decoder
+            VideoCodec::Av1 => vec!["libdav1d", "av1"],
+            _ => vec![Self::ffmpeg_name(codec)],
+        }
+    }
+
+    /// Create software decoder (fallback)
+    fn create_software_decoder(codec: VideoCodec) -> Result<VideoDecoder> {
+        for name in Self::software_names(codec) {
+            if let Some(decoder) = ffmpeg::codec::decoder::find_by_name(name) {
+                let context = Self::create_context(&decoder)?;
+                if let Ok(decoder) = Self::open_video(context) {
+                    tracing::info!("Using software {:?} decoder ({})", codec, name);
+                    return Ok(decoder);
+                }
             }
         }
 
-        Err(anyhow::anyhow!("No video decoder available"))
+        Err(anyhow::anyhow!(
+            "Negotiated video codec {:?} is not supported by this FFmpeg build",
+            codec
+        ))
+    }
+
+    /// Feed a codec config packet (SPS/PPS)
+    ///
+    /// Config carries no picture, so it is held and sent together with the
+    /// next frame, like the first packet of a stream. Config that differs from
+    /// the previous one (rotation, encoder restart) resets the decoder first;
+    /// the frames it still held are then available from `take_flushed`.
+    pub fn set_config(&mut self, data: &Bytes) {
+        if !self.config.is_empty() && self.config[..] != data[..] {
+            match nal::sps_dimensions(self.codec, data) {
+                Some((width, height)) => tracing::info!(
+                    "Stream parameters changed ({}x{}), resetting the video decoder",
+                    width,
+                    height
+                ),
+                None => tracing::info!("Stream parameters changed, resetting the video decoder"),
+            }
+            if let Err(e) = self.reset() {
+                tracing::warn!("Failed to reset the video decoder: {}", e);
+            }
+            // Leftovers belong to the old parameters
+            self.access_units.clear();
+        }
+        self.config = data.to_vec();
+        self.access_units.hold(data);
     }
 
     /// Decode a video packet
+    ///
+    /// The packet is split into NAL units and regrouped into whole access
+    /// units, so the decoder never sees stray bytes or half a picture.
     pub fn decode(&mut self, data: &Bytes, pts: i64) -> Result<Option<DecodedFrame>> {
-        // Append data to packet buffer
-        self.packet_buffer.extend_from_slice(data);
+        let Some(frame) = self.decode_raw(data, pts)? else {
+            return Ok(None);
+        };
+        let started = Instant::now();
+        let decoded = self.convert_frame(&frame, pts)?;
+        self.convert_time = started.elapsed();
+        Ok(Some(decoded))
+    }
 
-        // Create packet from buffer
-        let mut packet = ffmpeg::codec::packet::Packet::copy(&self.packet_buffer);
-        packet.set_pts(Some(pts));
+    /// Decode a video packet into a frame left on the GPU
+    ///
+    /// Only for decoders made with `new_gpu`.
+    pub fn decode_gpu(&mut self, data: &Bytes, pts: i64) -> Result<Option<GpuFrame>> {
+        let api = self.gpu.context("Not a GPU decoder")?;
+        let Some(frame) = self.decode_raw(data, pts)? else {
+            return Ok(None);
+        };
+        GpuFrame::export(frame, pts, api).map(Some)
+    }
 
+    /// API frames of `decode_gpu` are on, None when decoding to memory
+    pub fn gpu_api(&self) -> Option<GpuApi> {
+        self.gpu
+    }
+
+    /// Send a packet and take the frame it completed, as FFmpeg decoded it
+    fn decode_raw(&mut self, data: &Bytes, pts: i64) -> Result<Option<VideoFrame>> {
+        for unit in self.access_units.push(data) {
+            let mut packet = ffmpeg::codec::packet::Packet::copy(&unit);
+            packet.set_pts(Some(pts));
+            let has_config = nal::split(&unit).any(|n| NalUnit::new(self.codec, n).is_sps());
+            self.send_access_unit(&packet, has_config)?;
+        }
+
+        // Try to receive decoded frame
+        let mut frame = VideoFrame::empty();
+        match self.decoder.receive_frame(&mut frame) {
+            Ok(_) => Ok(Some(frame)),
+            Err(ffmpeg::Error::Other { errno: 11 }) => {
+                // EAGAIN - need more data
+                Ok(None)
+            }
+            Err(e) => Err(anyhow::anyhow!("Decoder error: {:?}", e)),
+        }
+    }
+
+    /// Send an access unit, switching to the software decoder if it fails
+    fn send_access_unit(
+        &mut self,
+        packet: &ffmpeg::codec::packet::Packet,
+        has_config: bool,
+    ) -> Result<()> {
+        // GPU frames cannot come from the software decoder
+        if self.gpu.is_some() {
+            return self.send_packet_internal(packet);
+        }
         // Try to decode. If it fails, and we are using hardware, fallback to software!
         // This is crucial for stability with QSV or other picky HW decoders.
-        match self.send_packet_internal(&packet) {
+        match self.send_packet_internal(packet) {
             Ok(_) => {}
             Err(e) => {
                 // Check if we can fallback (heuristic: if error is not EAGAIN)
@@ -184,15 +557,21 @@ impl HardwareVideoDecoder {
                 );
 
                 // Re-create as software decoder
-                match Self::create_software_decoder() {
+                match Self::create_software_decoder(self.codec) {
                     Ok(mut sw_decoder) => {
+                        // The new decoder has not seen the stream's SPS/PPS yet
+                        if !self.config.is_empty() && !has_config {
+                            let config = ffmpeg::codec::packet::Packet::copy(&self.config);
+                            let _ = sw_decoder.send_packet(&config);
+                        }
                         // Send the same packet to the new decoder
-                        match sw_decoder.send_packet(&packet) {
+                        match sw_decoder.send_packet(packet) {
                             Ok(_) => {
                                 tracing::info!(
                                     "Software fallback successful! Switched to Software Decoder."
                                 );
                                 self.decoder = sw_decoder;
+                                self.hw_decoder = "none".to_string();
                             }
                             Err(sw_e) => {
                                 return Err(anyhow::anyhow!(
@@ -212,24 +591,7 @@ impl HardwareVideoDecoder {
                 }
             }
         }
-
-        // Clear packet buffer after successful send (or EAGAIN which implies we should wait/read)
-        self.packet_buffer.clear();
-
-        // Try to receive decoded frame
-        let mut frame = VideoFrame::empty();
-        match self.decoder.receive_frame(&mut frame) {
-            Ok(_) => {
-                // Frame decoded successfully
-                let decoded = self.convert_frame(&frame, pts)?;
-                Ok(Some(decoded))
-            }
-            Err(ffmpeg::Error::Other { errno: 11 }) => {
-                // EAGAIN - need more data
-                Ok(None)
-            }
-            Err(e) => Err(anyhow::anyhow!("Decoder error: {:?}", e)),
-        }
+        Ok(())
     }
 
     // Helper to keep logic clean
@@ -246,27 +608,48 @@ impl HardwareVideoDecoder {
 
     /// Convert FFmpeg frame to our DecodedFrame format
     fn convert_frame(&mut self, frame: &VideoFrame, pts: i64) -> Result<DecodedFrame> {
+        let _span = tracing::trace_span!("convert", pts).entered();
         let width = frame.width();
         let height = frame.height();
         let src_format = frame.format();
-        let dst_format = self.output_format.to_ffmpeg();
+        // Keep the extra precision of 10-bit video for the renderer
+        let output_format = match self.output_format {
+            PixelFormat::RGBA if is_high_bit_depth(src_format) => PixelFormat::RGBA64,
+            format => format,
+        };
+        let dst_format = output_format.to_ffmpeg();
+        let color = ColorSpace::from_ffmpeg(
+            frame.color_space(),
+            frame.color_range(),
+            frame.color_transfer_characteristic(),
+            height,
+        );
 
         // Check if we need to scale/convert format
         let final_frame = if src_format != dst_format {
-            // Initialize scaler if needed
-            if self.scaler.is_none() {
-                self.scaler = Some(
-                    ScalingContext::get(
-                        src_format,
-                        width,
-                        height,
-                        dst_format,
-                        width,
-                        height,
-                        Flags::BILINEAR,
-                    )
-                    .context("Failed to create scaling context")?,
+            // (Re)create the scaler when the input changes, e.g. on rotation
+            let input = (src_format, width, height, color);
+            if self.scaler_input != Some(input) {
+                let mut scaler = ScalingContext::get(
+                    src_format,
+                    width,
+                    height,
+                    dst_format,
+                    width,
+                    height,
+                    Flags::BILINEAR,
+                )
+                .context("Failed to create scaling context")?;
+                color.apply_to_scaler(&mut scaler);
+                tracing::info!(
+                    "Video color space: {:?}, {} range, {:?} transfer, {:?}",
+                    color.matrix,
+                    if color.full_range { "full" } else { "limited" },
+                    color.transfer,
+                    src_format
                 );
+                self.scaler = Some(scaler);
+                self.scaler_input = Some(input);
             }
 
             // Scale/convert frame
@@ -281,33 +664,35 @@ impl HardwareVideoDecoder {
             frame.clone()
         };
 
-        // Extract frame data to contiguous buffer
-        let data = self.extract_frame_data(&final_frame)?;
+        // Extract frame data to a recycled contiguous buffer
+        let data = self.extract_frame_data(&final_frame, output_format)?;
 
         Ok(DecodedFrame {
             pts,
-            data,
+            data: self.pool.share(data),
             width,
             height,
-            format: self.output_format,
+            format: output_format,
+            color,
         })
     }
 
-    /// Extract frame data to a contiguous Vec<u8>
-    fn extract_frame_data(&self, frame: &VideoFrame) -> Result<Vec<u8>> {
-        match self.output_format {
-            PixelFormat::RGBA => {
+    /// Extract frame data to a contiguous buffer taken from the pool
+    fn extract_frame_data(&self, frame: &VideoFrame, format: PixelFormat) -> Result<Vec<u8>> {
+        match format {
+            PixelFormat::RGBA | PixelFormat::RGBA64 => {
                 // RGBA is packed, single plane
                 let stride = frame.stride(0);
                 let width = frame.width() as usize;
                 let height = frame.height() as usize;
                 let data = frame.data(0);
+                let row_len = width * format.bytes_per_pixel();
 
-                let mut buffer = Vec::with_capacity(width * height * 4);
+                let mut buffer = self.pool.take(row_len * height);
 
                 for y in 0..height {
                     let row_start = y * stride;
-                    let row_end = row_start + (width * 4);
+                    let row_end = row_start + row_len;
                     buffer.extend_from_slice(&data[row_start..row_end]);
                 }
 
@@ -331,7 +716,7 @@ impl HardwareVideoDecoder {
                 let uv_size = (width / 2) * (height / 2);
                 let total_size = y_size + uv_size + uv_size;
 
-                let mut buffer = Vec::with_capacity(total_size);
+                let mut buffer = self.pool.take(total_size);
 
                 // Copy Y plane
                 for y in 0..height {
@@ -371,7 +756,7 @@ impl HardwareVideoDecoder {
                 let uv_size = width * (height / 2);
                 let total_size = y_size + uv_size;
 
-                let mut buffer = Vec::with_capacity(total_size);
+                let mut buffer = self.pool.take(total_size);
 
                 // Copy Y plane
                 for y in 0..height {
@@ -406,7 +791,8 @@ impl HardwareVideoDecoder {
             let mut frame = VideoFrame::empty();
             match self.decoder.receive_frame(&mut frame) {
                 Ok(_) => {
-                    if let Ok(decoded) = self.convert_frame(&frame, 0) {
+                    let pts = frame.pts().unwrap_or(0);
+                    if let Ok(decoded) = self.convert_frame(&frame, pts) {
                         frames.push(decoded);
                     }
                 }
@@ -417,6 +803,34 @@ impl HardwareVideoDecoder {
         Ok(frames)
     }
 
+    /// Drain the decoder and start over with a fresh one for new stream parameters
+    ///
+    /// The scaler is rebuilt on the next frame, whatever its size and format.
+    pub fn reset(&mut self) -> Result<()> {
+        let decoder = match self.gpu {
+            Some(api) => Self::create_gpu_decoder(self.codec, api)?,
+            None => Self::create_decoder(&self.hw_decoder, self.codec)?,
+        };
+        match self.flush() {
+            Ok(frames) => self.frame_queue.extend(frames),
+            Err(e) => tracing::debug!("Could not drain the old decoder: {}", e),
+        }
+        self.decoder = decoder;
+        self.scaler = None;
+        self.scaler_input = None;
+        Ok(())
+    }
+
+    /// Frames the decoder still held when it was last reset, oldest first
+    pub fn take_flushed(&mut self) -> Vec<DecodedFrame> {
+        self.frame_queue.drain(..).collect()
+    }
+
+    /// Share of the last `decode` spent converting to the output format
+    pub fn last_convert_time(&self) -> Duration {
+        self.convert_time
+    }
+
     /// Get decoder information
     pub fn info(&self) -> String {
         format!(
@@ -435,7 +849,7 @@ mod tests {
     #[test]
     fn test_decoder_creation() {
         // Test that decoder can be created (may fall back to software)
-        let result = HardwareVideoDecoder::new("auto", PixelFormat::RGBA);
+        let result = HardwareVideoDecoder::new("auto", VideoCodec::H264, PixelFormat::RGBA);
         assert!(result.is_ok());
     }
 
@@ -444,4 +858,41 @@ mod tests {
         assert_eq!(PixelFormat::RGBA.bytes_per_pixel(), 4);
         assert_eq!(PixelFormat::YUV420P.bytes_per_pixel(), 1);
     }
+
+    #[test]
+    fn test_rgba64_keeps_high_bytes() {
+        let frame = DecodedFrame {
+            pts: 0,
+            data: vec![0x00, 0xff, 0x80, 0x40, 0xff, 0x00, 0x00, 0xff].into(),
+            width: 1,
+            height: 1,
+            format: PixelFormat::RGBA64,
+            color: ColorSpace::default(),
+        };
+        assert_eq!(frame.to_rgba(), vec![0xff, 0x40, 0x00, 0xff]);
+        assert_eq!(frame.pixel(0, 0), Some([0xff, 0x40, 0x00, 0xff]));
+    }
+
+    #[test]
+    fn test_pixel_matches_full_conversion() {
+        // 4x2 frame, distinct chroma per 2x2 block
+        let mut data: Vec<u8> = (0..8).map(|i| 40 + i * 20).collect();
+        data.extend([90, 200, 60, 170]);
+        for format in [PixelFormat::YUV420P, PixelFormat::NV12] {
+            let frame = DecodedFrame {
+                pts: 0,
+                data: data.clone().into(),
+                width: 4,
+                height: 2,
+                format,
+                color: ColorSpace::default(),
+            };
+            let rgba = frame.to_rgba();
+            for (i, expected) in rgba.chunks(4).enumerate() {
+                let pixel = frame.pixel(i as u32 % 4, i as u32 / 4).unwrap();
+                assert_eq!(&pixel[..], expected);
+            }
+            assert_eq!(frame.pixel(4, 0), None);
+        }
+    }
 }
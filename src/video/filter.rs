@@ -0,0 +1,91 @@
+use crate::video::decoder::DecodedFrame;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Post-processing of decoded frames: watermarks, privacy masks, color tweaks
+///
+/// Filters run on the decode thread, before plugins, the window, screenshots
+/// and recordings see the frame. Pixels are written through
+/// `frame.data.make_mut()`, in whatever `frame.format` the decoder outputs.
+pub trait FrameFilter: Send {
+    fn process(&mut self, frame: &mut DecodedFrame);
+}
+
+impl<F: FnMut(&mut DecodedFrame) + Send> FrameFilter for F {
+    fn process(&mut self, frame: &mut DecodedFrame) {
+        self(frame)
+    }
+}
+
+/// Registered filters, applied in order; clones share the same list
+#[derive(Clone, Default)]
+pub struct FrameFilters {
+    filters: Arc<Mutex<Vec<Box<dyn FrameFilter>>>>,
+}
+
+impl FrameFilters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a filter after the ones already registered
+    pub fn register(&self, filter: Box<dyn FrameFilter>) {
+        self.filters.lock().push(filter);
+    }
+
+    /// Remove every filter
+    pub fn clear(&self) {
+        self.filters.lock().clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.lock().is_empty()
+    }
+
+    /// Run every filter on `frame`
+    pub fn apply(&self, frame: &mut DecodedFrame) {
+        for filter in self.filters.lock().iter_mut() {
+            filter.process(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::decoder::PixelFormat;
+    use crate::video::ColorSpace;
+
+    #[test]
+    fn test_filters_run_in_order_copy_on_write() {
+        let mut frame = DecodedFrame {
+            pts: 0,
+            data: vec![200; 2 * 2 * 4].into(),
+            width: 2,
+            height: 2,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        };
+        let shown = frame.clone();
+
+        let filters = FrameFilters::new();
+        // Mask the top row (a status bar), then invert the colors
+        filters.register(Box::new(|frame: &mut DecodedFrame| {
+            let row = frame.stride();
+            frame.data.make_mut()[..row].fill(0);
+        }));
+        filters.register(Box::new(|frame: &mut DecodedFrame| {
+            for pixel in frame.data.make_mut().chunks_exact_mut(4) {
+                for channel in &mut pixel[..3] {
+                    *channel = 255 - *channel;
+                }
+            }
+        }));
+        filters.apply(&mut frame);
+
+        assert_eq!(frame.pixel(0, 0), Some([255, 255, 255, 0]));
+        assert_eq!(frame.pixel(1, 1), Some([55, 55, 55, 200]));
+        // The clone kept its pixels
+        assert_eq!(shown.pixel(0, 0), Some([200, 200, 200, 200]));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::video::color::ColorSpace;
+use anyhow::{Context as AnyhowContext, Result};
+use ffmpeg::ffi::{AVCodecContext, AVHWDeviceType, AVHWFramesContext, AVPixelFormat};
+use ffmpeg::util::frame::video::Video as VideoFrame;
+use ffmpeg_next as ffmpeg;
+use std::ffi::{c_int, c_void};
+use std::ptr;
+
+/// GPU API decoded frames stay on, so GPU consumers skip the copy to memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuApi {
+    /// Direct3D 11 textures (Windows)
+    D3D11,
+    /// VA-API surfaces, exported as DMA-BUFs (Linux)
+    Vaapi,
+}
+
+impl GpuApi {
+    /// The API of this platform, None where frames cannot be exported
+    pub fn native() -> Option<Self> {
+        if cfg!(target_os = "windows") {
+            Some(GpuApi::D3D11)
+        } else if cfg!(target_os = "linux") {
+            Some(GpuApi::Vaapi)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GpuApi::D3D11 => "d3d11va",
+            GpuApi::Vaapi => "vaapi",
+        }
+    }
+
+    fn device_type(&self) -> AVHWDeviceType {
+        match self {
+            GpuApi::D3D11 => AVHWDeviceType::AV_HWDEVICE_TYPE_D3D11VA,
+            GpuApi::Vaapi => AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+        }
+    }
+
+    /// Pixel format of the frames FFmpeg decodes on this API
+    fn pixel_format(&self) -> AVPixelFormat {
+        match self {
+            GpuApi::D3D11 => AVPixelFormat::AV_PIX_FMT_D3D11,
+            GpuApi::Vaapi => AVPixelFormat::AV_PIX_FMT_VAAPI,
+        }
+    }
+
+    /// Open a device and make the decoder of `context` output frames on it
+    ///
+    /// # Safety
+    /// `context` is a valid codec context that has not been opened yet
+    pub(crate) unsafe fn attach(&self, context: *mut AVCodecContext) -> Result<()> {
+        let mut device = ptr::null_mut();
+        let ret = ffmpeg::ffi::av_hwdevice_ctx_create(
+            &mut device,
+            self.device_type(),
+            ptr::null(),
+            ptr::null_mut(),
+            0,
+        );
+        if ret < 0 {
+            anyhow::bail!(
+                "Failed to open a {} device: {}",
+                self.name(),
+                ffmpeg::Error::from(ret)
+            );
+        }
+        // The context takes over the device reference
+        (*context).hw_device_ctx = device;
+        (*context).get_format = Some(match self {
+            GpuApi::D3D11 => d3d11_format,
+            GpuApi::Vaapi => vaapi_format,
+        });
+        Ok(())
+    }
+}
+
+/// `wanted` if the decoder offers it, else none, which fails the frame
+/// rather than decoding it to memory
+///
+/// # Safety
+/// `offered` is a list ending with `AV_PIX_FMT_NONE`
+unsafe fn pick_format(offered: *const AVPixelFormat, wanted: AVPixelFormat) -> AVPixelFormat {
+    let mut format = offered;
+    while *format != AVPixelFormat::AV_PIX_FMT_NONE {
+        if *format == wanted {
+            return wanted;
+        }
+        format = format.add(1);
+    }
+    tracing::warn!("The decoder cannot output {:?} frames", wanted);
+    AVPixelFormat::AV_PIX_FMT_NONE
+}
+
+unsafe extern "C" fn d3d11_format(
+    _context: *mut AVCodecContext,
+    offered: *const AVPixelFormat,
+) -> AVPixelFormat {
+    pick_format(offered, GpuApi::D3D11.pixel_format())
+}
+
+unsafe extern "C" fn vaapi_format(
+    _context: *mut AVCodecContext,
+    offered: *const AVPixelFormat,
+) -> AVPixelFormat {
+    pick_format(offered, GpuApi::Vaapi.pixel_format())
+}
+
+/// Where the pixels of a [`GpuFrame`] are
+///
+/// Handles stay valid as long as the frame is alive.
+#[derive(Debug, Clone)]
+pub enum GpuHandle {
+    /// Slice `array_index` of an `ID3D11Texture2D` texture array (NV12, or
+    /// P010 for 10-bit video), created on `device` (`ID3D11Device`)
+    D3D11 {
+        device: *mut c_void,
+        texture: *mut c_void,
+        array_index: u32,
+    },
+    /// DMA-BUF layers, e.g. for `EGL_EXT_image_dma_buf_import` or Vulkan
+    /// external memory
+    DmaBuf { layers: Vec<DmaBufLayer> },
+}
+
+/// One image of a DMA-BUF frame, e.g. the whole NV12 picture or its Y plane
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmaBufLayer {
+    /// DRM fourcc format code
+    pub fourcc: u32,
+    pub planes: Vec<DmaBufPlane>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmaBufPlane {
+    /// File descriptor of the buffer, owned by the frame
+    pub fd: i32,
+    pub offset: usize,
+    /// Bytes per row
+    pub pitch: usize,
+    /// DRM format modifier (tiling) of the buffer
+    pub modifier: u64,
+}
+
+/// Decoded video frame left on the GPU, see `Client::gpu_frames`
+///
+/// The decoder only has a few surfaces: frames held for long stall decoding.
+pub struct GpuFrame {
+    pub pts: i64,
+    pub width: u32,
+    pub height: u32,
+    /// How the YUV samples map to RGB
+    pub color: ColorSpace,
+    pub handle: GpuHandle,
+    /// Keeps the surface (and DMA-BUF mapping) from being reused
+    _frame: VideoFrame,
+}
+
+// SAFETY: the handles are plain values that FFmpeg's D3D11 device (created
+// multithread-protected) and DMA-BUF fds accept from any thread; the frame
+// they belong to is only released, never accessed, here
+unsafe impl Send for GpuFrame {}
+
+impl GpuFrame {
+    /// Export a frame decoded by a decoder attached to `api`
+    pub(crate) fn export(decoded: VideoFrame, pts: i64, api: GpuApi) -> Result<Self> {
+        // SAFETY: `decoded` is a decoded frame; its format is checked first
+        let (handle, mapped) = unsafe {
+            let raw = decoded.as_ptr();
+            anyhow::ensure!(
+                (*raw).format == api.pixel_format() as c_int && !(*raw).hw_frames_ctx.is_null(),
+                "Decoded frame is not on the GPU (software fallback?)"
+            );
+            match api {
+                GpuApi::D3D11 => {
+                    let frames = (*(*raw).hw_frames_ctx).data as *const AVHWFramesContext;
+                    // AVD3D11VADeviceContext starts with its ID3D11Device
+                    let device = *((*(*frames).device_ctx).hwctx as *const *mut c_void);
+                    let handle = GpuHandle::D3D11 {
+                        device,
+                        texture: (*raw).data[0] as *mut c_void,
+                        array_index: (*raw).data[1] as usize as u32,
+                    };
+                    (handle, None)
+                }
+                GpuApi::Vaapi => {
+                    let mut mapped = VideoFrame::empty();
+                    (*mapped.as_mut_ptr()).format = AVPixelFormat::AV_PIX_FMT_DRM_PRIME as c_int;
+                    let ret =
+                        ffmpeg::ffi::av_hwframe_map(mapped.as_mut_ptr(), raw, AV_HWFRAME_MAP_READ);
+                    if ret < 0 {
+                        anyhow::bail!(
+                            "Failed to export the frame as DMA-BUF: {}",
+                            ffmpeg::Error::from(ret)
+                        );
+                    }
+                    let descriptor = (*mapped.as_ptr()).data[0] as *const AVDRMFrameDescriptor;
+                    let layers = dma_buf_layers(descriptor.as_ref().context("No DRM descriptor")?);
+                    // The mapping holds the surface
+                    (GpuHandle::DmaBuf { layers }, Some(mapped))
+                }
+            }
+        };
+        let color = ColorSpace::from_ffmpeg(
+            decoded.color_space(),
+            decoded.color_range(),
+            decoded.color_transfer_characteristic(),
+            decoded.height(),
+        );
+        Ok(Self {
+            pts,
+            width: decoded.width(),
+            height: decoded.height(),
+            color,
+            handle,
+            _frame: mapped.unwrap_or(decoded),
+        })
+    }
+}
+
+/// `AV_HWFRAME_MAP_READ` from libavutil/hwcontext.h
+const AV_HWFRAME_MAP_READ: c_int = 1;
+
+/// `AV_DRM_MAX_PLANES` and the descriptors of libavutil/hwcontext_drm.h,
+/// which the generated bindings leave out
+const AV_DRM_MAX_PLANES: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct AVDRMObjectDescriptor {
+    fd: c_int,
+    size: usize,
+    format_modifier: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct AVDRMPlaneDescriptor {
+    object_index: c_int,
+    offset: isize,
+    pitch: isize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct AVDRMLayerDescriptor {
+    format: u32,
+    nb_planes: c_int,
+    planes: [AVDRMPlaneDescriptor; AV_DRM_MAX_PLANES],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct AVDRMFrameDescriptor {
+    nb_objects: c_int,
+    objects: [AVDRMObjectDescriptor; AV_DRM_MAX_PLANES],
+    nb_layers: c_int,
+    layers: [AVDRMLayerDescriptor; AV_DRM_MAX_PLANES],
+}
+
+/// Layers of a DRM frame, with each plane's buffer looked up
+fn dma_buf_layers(descriptor: &AVDRMFrameDescriptor) -> Vec<DmaBufLayer> {
+    let objects = &descriptor.objects[..(descriptor.nb_objects as usize).min(AV_DRM_MAX_PLANES)];
+    descriptor.layers[..(descriptor.nb_layers as usize).min(AV_DRM_MAX_PLANES)]
+        .iter()
+        .map(|layer| DmaBufLayer {
+            fourcc: layer.format,
+            planes: layer.planes[..(layer.nb_planes as usize).min(AV_DRM_MAX_PLANES)]
+                .iter()
+                .filter_map(|plane| {
+                    let object = objects.get(plane.object_index as usize)?;
+                    Some(DmaBufPlane {
+                        fd: object.fd,
+                        offset: plane.offset as usize,
+                        pitch: plane.pitch as usize,
+                        modifier: object.format_modifier,
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_format_refuses_software_output() {
+        let offered = [
+            AVPixelFormat::AV_PIX_FMT_D3D11,
+            AVPixelFormat::AV_PIX_FMT_NV12,
+            AVPixelFormat::AV_PIX_FMT_NONE,
+        ];
+        unsafe {
+            assert_eq!(
+                pick_format(offered.as_ptr(), AVPixelFormat::AV_PIX_FMT_D3D11),
+                AVPixelFormat::AV_PIX_FMT_D3D11
+            );
+            assert_eq!(
+                pick_format(offered.as_ptr(), AVPixelFormat::AV_PIX_FMT_VAAPI),
+                AVPixelFormat::AV_PIX_FMT_NONE
+            );
+        }
+    }
+
+    #[test]
+    fn test_dma_buf_layers_share_objects() {
+        // NV12 exported as two layers (R8 luma, GR88 chroma) of one buffer
+        let mut descriptor = AVDRMFrameDescriptor {
+            nb_objects: 1,
+            nb_layers: 2,
+            ..Default::default()
+        };
+        descriptor.objects[0] = AVDRMObjectDescriptor {
+            fd: 7,
+            size: 1920 * 1088 * 3 / 2,
+            format_modifier: 0x0100_0000_0000_0002,
+        };
+        let fourccs = [u32::from_le_bytes(*b"R8  "), u32::from_le_bytes(*b"GR88")];
+        for (i, fourcc) in fourccs.into_iter().enumerate() {
+            descriptor.layers[i].format = fourcc;
+            descriptor.layers[i].nb_planes = 1;
+            descriptor.layers[i].planes[0] = AVDRMPlaneDescriptor {
+                object_index: 0,
+                offset: (i * 1920 * 1088) as isize,
+                pitch: 1920,
+            };
+        }
+
+        let layers = dma_buf_layers(&descriptor);
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[1].fourcc, fourccs[1]);
+        assert_eq!(
+            layers[1].planes,
+            vec![DmaBufPlane {
+                fd: 7,
+                offset: 1920 * 1088,
+                pitch: 1920,
+                modifier: 0x0100_0000_0000_0002,
+            }]
+        );
+    }
+}
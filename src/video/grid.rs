@@ -0,0 +1,344 @@
+use crate::network::{ControlMessage, TouchAction};
+use crate::video::color::ColorSpace;
+use crate::video::decoder::{DecodedFrame, PixelFormat};
+use crate::video::pool::FramePool;
+
+/// Size of the composited picture; tiles are scaled into it
+pub const CANVAS_SIZE: (u32, u32) = (1920, 1080);
+
+/// Black space between tiles
+const GAP: u32 = 8;
+
+/// Outline of the tile that gets keyboard and mouse input
+const FOCUS_BORDER: u32 = 3;
+const FOCUS_COLOR: [u8; 4] = [0x3d, 0x8b, 0xfd, 0xff];
+
+/// Most tiles per row; phones are tall, so rows fill up before a new one starts
+const MAX_COLUMNS: usize = 4;
+
+/// Rectangle in canvas pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+/// Several device streams composited side by side into one picture
+///
+/// Each tile keeps its device's newest frame, letterboxed into its cell.
+/// Input in canvas coordinates goes to the focused tile, translated to that
+/// device's frame; pressing on another tile focuses it.
+pub struct Grid {
+    width: u32,
+    height: u32,
+    columns: usize,
+    rows: usize,
+    tiles: Vec<Option<DecodedFrame>>,
+    focused: usize,
+    dirty: bool,
+    pool: FramePool,
+    scratch: Vec<u8>,
+    pts: i64,
+}
+
+impl Grid {
+    pub fn new(count: usize, (width, height): (u32, u32)) -> Self {
+        let count = count.max(1);
+        let rows = count.div_ceil(MAX_COLUMNS);
+        Self {
+            width,
+            height,
+            columns: count.div_ceil(rows),
+            rows,
+            tiles: vec![None; count],
+            focused: 0,
+            dirty: true,
+            // The one on screen, one in the mailbox and one being drawn
+            pool: FramePool::new(3),
+            scratch: Vec::new(),
+            pts: 0,
+        }
+    }
+
+    /// Tile that receives input
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn set_frame(&mut self, tile: usize, frame: DecodedFrame) {
+        if let Some(slot) = self.tiles.get_mut(tile) {
+            *slot = Some(frame);
+            self.dirty = true;
+        }
+    }
+
+    /// Blank a tile whose session ended
+    pub fn clear(&mut self, tile: usize) {
+        if let Some(slot) = self.tiles.get_mut(tile) {
+            self.dirty |= slot.take().is_some();
+        }
+    }
+
+    /// Picture with every tile's newest frame, None if nothing changed
+    pub fn compose(&mut self) -> Option<DecodedFrame> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        let stride = self.width as usize * 4;
+        let mut canvas = self.pool.take(stride * self.height as usize);
+        canvas.resize(stride * self.height as usize, 0);
+
+        for tile in 0..self.tiles.len() {
+            let Some(frame) = &self.tiles[tile] else {
+                continue;
+            };
+            let Some(rect) = self.placement(tile) else {
+                continue;
+            };
+            let pixels = frame.rgba_into(&mut self.scratch);
+            // Nearest neighbour: tiles are downscaled, so filtering buys little
+            let src_x: Vec<usize> = (0..rect.width)
+                .map(|x| (x as u64 * frame.width as u64 / rect.width as u64) as usize * 4)
+                .collect();
+            for y in 0..rect.height {
+                let src_y = (y as u64 * frame.height as u64 / rect.height as u64) as usize;
+                let src_row = &pixels[src_y * frame.width as usize * 4..];
+                let row_start = (rect.y + y) as usize * stride + rect.x as usize * 4;
+                let row = &mut canvas[row_start..row_start + rect.width as usize * 4];
+                for (dst, &src) in row.chunks_exact_mut(4).zip(&src_x) {
+                    dst.copy_from_slice(&src_row[src..src + 4]);
+                }
+            }
+        }
+        if self.tiles.len() > 1 {
+            self.outline(&mut canvas, self.cell(self.focused));
+        }
+
+        self.pts += 1;
+        Some(DecodedFrame {
+            pts: self.pts,
+            data: self.pool.share(canvas),
+            width: self.width,
+            height: self.height,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        })
+    }
+
+    /// Send `message` (in canvas coordinates) to a tile, in that tile's frame coordinates
+    ///
+    /// None when the target tile has no picture yet, so nothing can be mapped.
+    pub fn route(&mut self, message: ControlMessage) -> Option<(usize, ControlMessage)> {
+        match message {
+            ControlMessage::InjectTouch {
+                action,
+                pointer_id,
+                x,
+                y,
+                width,
+                height,
+                pressure,
+            } => {
+                let (x, y) = self.to_canvas(x, y, width, height);
+                if action == TouchAction::Down {
+                    if let Some(tile) = self.tile_at(x, y) {
+                        self.dirty |= tile != self.focused;
+                        self.focused = tile;
+                    }
+                }
+                let tile = self.focused;
+                let (x, y, width, height) = self.to_tile(tile, x, y)?;
+                Some((
+                    tile,
+                    ControlMessage::InjectTouch {
+                        action,
+                        pointer_id,
+                        x,
+                        y,
+                        width,
+                        height,
+                        pressure,
+                    },
+                ))
+            }
+            // Scrolling goes to the tile under the pointer, like in a desktop grid
+            ControlMessage::InjectScroll {
+                x,
+                y,
+                width,
+                height,
+                hscroll,
+                vscroll,
+            } => {
+                let (x, y) = self.to_canvas(x, y, width, height);
+                let tile = self.tile_at(x, y)?;
+                let (x, y, width, height) = self.to_tile(tile, x, y)?;
+                Some((
+                    tile,
+                    ControlMessage::InjectScroll {
+                        x,
+                        y,
+                        width,
+                        height,
+                        hscroll,
+                        vscroll,
+                    },
+                ))
+            }
+            other => Some((self.focused, other)),
+        }
+    }
+
+    /// Scale a point given against a `width`x`height` picture to the canvas
+    fn to_canvas(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        let scale = |v: u32, from: u32, to: u32| (v as u64 * to as u64 / from.max(1) as u64) as u32;
+        (scale(x, width, self.width), scale(y, height, self.height))
+    }
+
+    /// Canvas point as a point in `tile`'s frame, clamped to the picture
+    fn to_tile(&self, tile: usize, x: u32, y: u32) -> Option<(u32, u32, u32, u32)> {
+        let frame = self.tiles.get(tile)?.as_ref()?;
+        let rect = self.placement(tile)?;
+        let local = |v: u32, start: u32, len: u32, size: u32| {
+            let offset = v.saturating_sub(start).min(len - 1);
+            (offset as u64 * size as u64 / len as u64) as u32
+        };
+        Some((
+            local(x, rect.x, rect.width, frame.width),
+            local(y, rect.y, rect.height, frame.height),
+            frame.width,
+            frame.height,
+        ))
+    }
+
+    fn tile_at(&self, x: u32, y: u32) -> Option<usize> {
+        (0..self.tiles.len()).find(|&tile| self.cell(tile).contains(x, y))
+    }
+
+    /// Area of the canvas given to `tile`
+    fn cell(&self, tile: usize) -> Rect {
+        let (column, row) = ((tile % self.columns) as u32, (tile / self.columns) as u32);
+        let width = (self.width - GAP * (self.columns as u32 + 1)) / self.columns as u32;
+        let height = (self.height - GAP * (self.rows as u32 + 1)) / self.rows as u32;
+        Rect {
+            x: GAP + column * (width + GAP),
+            y: GAP + row * (height + GAP),
+            width,
+            height,
+        }
+    }
+
+    /// Where `tile`'s frame lands: fitted into its cell, keeping the aspect ratio
+    fn placement(&self, tile: usize) -> Option<Rect> {
+        let frame = self.tiles.get(tile)?.as_ref()?;
+        let cell = self.cell(tile);
+        if frame.width == 0 || frame.height == 0 {
+            return None;
+        }
+        let scale = f64::min(
+            cell.width as f64 / frame.width as f64,
+            cell.height as f64 / frame.height as f64,
+        );
+        let width = ((frame.width as f64 * scale) as u32).clamp(1, cell.width);
+        let height = ((frame.height as f64 * scale) as u32).clamp(1, cell.height);
+        Some(Rect {
+            x: cell.x + (cell.width - width) / 2,
+            y: cell.y + (cell.height - height) / 2,
+            width,
+            height,
+        })
+    }
+
+    /// Draw a border just outside `rect`, in the gap between cells
+    fn outline(&self, canvas: &mut [u8], rect: Rect) {
+        let stride = self.width as usize * 4;
+        let left = rect.x.saturating_sub(FOCUS_BORDER);
+        let top = rect.y.saturating_sub(FOCUS_BORDER);
+        let right = (rect.x + rect.width + FOCUS_BORDER).min(self.width);
+        let bottom = (rect.y + rect.height + FOCUS_BORDER).min(self.height);
+        for y in top..bottom {
+            for x in left..right {
+                if !rect.contains(x, y) {
+                    let i = y as usize * stride + x as usize * 4;
+                    canvas[i..i + 4].copy_from_slice(&FOCUS_COLOR);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32) -> DecodedFrame {
+        DecodedFrame {
+            pts: 0,
+            data: vec![0xff; (width * height * 4) as usize].into(),
+            width,
+            height,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        }
+    }
+
+    fn touch(action: TouchAction, x: u32, y: u32) -> ControlMessage {
+        ControlMessage::InjectTouch {
+            action,
+            pointer_id: 0,
+            x,
+            y,
+            width: 1000,
+            height: 100,
+            pressure: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_click_focuses_and_maps_into_tile() {
+        // Two side-by-side cells of 488x84 on a 1000x100 canvas
+        let mut grid = Grid::new(2, (1000, 100));
+        assert_eq!((grid.columns, grid.rows), (2, 1));
+        grid.set_frame(0, frame(488, 84));
+        grid.set_frame(1, frame(244, 42));
+        let composite = grid.compose().unwrap();
+        assert_eq!((composite.width, composite.height), (1000, 100));
+        assert!(grid.compose().is_none());
+
+        let (tile, message) = grid.route(touch(TouchAction::Down, 504, 8)).unwrap();
+        assert_eq!((tile, grid.focused()), (1, 1));
+        match message {
+            ControlMessage::InjectTouch {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => assert_eq!((x, y, width, height), (0, 0, 244, 42)),
+            other => panic!("unexpected {:?}", other),
+        }
+        // Focus changed, so the outline moves
+        assert!(grid.compose().is_some());
+
+        // A drag that leaves the tile stays with it, clamped to its edge
+        let (tile, message) = grid.route(touch(TouchAction::Move, 0, 99)).unwrap();
+        assert_eq!(tile, 1);
+        assert!(matches!(
+            message,
+            ControlMessage::InjectTouch { x: 0, y: 41, .. }
+        ));
+        assert!(matches!(
+            grid.route(ControlMessage::RotateDevice),
+            Some((1, ControlMessage::RotateDevice))
+        ));
+    }
+}
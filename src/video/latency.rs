@@ -0,0 +1,254 @@
+/// Per-stage latency of video frames, from the socket to the screen
+///
+/// Each part of the pipeline stamps a frame (by PTS) with a [`Mark`] when it
+/// is done with it. Once the frame is presented, the time between marks is
+/// added to a window of recent frames per stage, so a report tells whether
+/// latency comes from the network, the decoder or waiting for vsync. Frames
+/// that never reach the screen (dropped or replaced) are forgotten.
+use crate::stats::Percentiles;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Frames a report covers
+const WINDOW: usize = 600;
+
+/// Frames in flight tracked before the oldest is forgotten
+const MAX_PENDING: usize = 64;
+
+/// Width of a histogram bucket of the total latency
+const BUCKET_MS: f64 = 10.0;
+
+/// Points on a frame's way; a stage is the time since the previous mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    /// Packet read from the connection
+    Received,
+    /// Packet handed to the decode queue
+    Queued,
+    /// Packet taken by the decode thread
+    Dequeued,
+    /// Decoder returned the picture
+    Decoded,
+    /// Picture converted to the output pixel format
+    Converted,
+    /// Frame taken by the UI thread
+    Taken,
+    /// Pixels written to the GPU texture
+    Uploaded,
+    /// Surface presented
+    Presented,
+}
+
+impl Mark {
+    const COUNT: usize = 8;
+
+    const ALL: [Mark; Mark::COUNT] = [
+        Mark::Received,
+        Mark::Queued,
+        Mark::Dequeued,
+        Mark::Decoded,
+        Mark::Converted,
+        Mark::Taken,
+        Mark::Uploaded,
+        Mark::Presented,
+    ];
+
+    /// Name of the stage ending at this mark
+    fn stage(self) -> &'static str {
+        match self {
+            Mark::Received => "recv",
+            Mark::Queued => "parse",
+            Mark::Dequeued => "queue",
+            Mark::Decoded => "decode",
+            Mark::Converted => "convert",
+            Mark::Taken => "mailbox",
+            Mark::Uploaded => "upload",
+            Mark::Presented => "present",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Traces {
+    /// Marks of frames on their way, by PTS
+    pending: BTreeMap<i64, [Option<Instant>; Mark::COUNT]>,
+    /// Milliseconds per stage, one window per mark after `Received`
+    stages: Vec<Percentiles>,
+    total: Percentiles,
+    /// Latest round trip time of the connection
+    rtt_ms: f64,
+    frames: u64,
+}
+
+impl Traces {
+    fn record(&mut self, marks: [Option<Instant>; Mark::COUNT]) {
+        // Frames missing a mark (e.g. the decoder was swapped) say nothing
+        let Some(marks) = marks.into_iter().collect::<Option<Vec<Instant>>>() else {
+            return;
+        };
+        let ms =
+            |from: Instant, to: Instant| to.saturating_duration_since(from).as_secs_f64() * 1000.0;
+        for (stage, pair) in self.stages.iter_mut().zip(marks.windows(2)) {
+            stage.add(ms(pair[0], pair[1]));
+        }
+        self.total.add(ms(marks[0], marks[Mark::COUNT - 1]));
+        self.frames += 1;
+    }
+}
+
+/// Latency breakdown shared by the network thread, decoder and renderer
+#[derive(Debug, Clone)]
+pub struct LatencyTracer(Arc<Mutex<Traces>>);
+
+impl Default for LatencyTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyTracer {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Traces {
+            pending: BTreeMap::new(),
+            stages: (1..Mark::COUNT).map(|_| Percentiles::new(WINDOW)).collect(),
+            total: Percentiles::new(WINDOW),
+            rtt_ms: 0.0,
+            frames: 0,
+        })))
+    }
+
+    /// Stamp the frame `pts` with `mark` now
+    pub fn mark(&self, pts: i64, mark: Mark) {
+        self.mark_at(pts, mark, Instant::now());
+    }
+
+    /// Stamp the frame `pts` with `mark` at `at`
+    ///
+    /// `Received` starts tracing a frame; other marks of frames not being
+    /// traced (or already presented) are ignored.
+    pub fn mark_at(&self, pts: i64, mark: Mark, at: Instant) {
+        let mut traces = self.0.lock();
+        if mark == Mark::Received {
+            let mut marks = [None; Mark::COUNT];
+            marks[0] = Some(at);
+            traces.pending.insert(pts, marks);
+            if traces.pending.len() > MAX_PENDING {
+                traces.pending.pop_first();
+            }
+            return;
+        }
+        let Some(marks) = traces.pending.get_mut(&pts) else {
+            return;
+        };
+        marks[mark as usize] = Some(at);
+        if mark == Mark::Presented {
+            if let Some(marks) = traces.pending.remove(&pts) {
+                traces.record(marks);
+            }
+        }
+    }
+
+    /// Round trip time of the connection, for the network estimate
+    pub fn set_rtt(&self, rtt_ms: f64) {
+        self.0.lock().rtt_ms = rtt_ms;
+    }
+
+    /// Frames traced all the way to the screen this session
+    pub fn frames(&self) -> u64 {
+        self.0.lock().frames
+    }
+
+    /// Median total latency of recent frames, without the network
+    pub fn median_ms(&self) -> Option<f64> {
+        self.0.lock().total.p50()
+    }
+
+    /// Percentiles per stage and a histogram of the total, as text
+    pub fn report(&self) -> String {
+        let traces = self.0.lock();
+        let mut out = String::new();
+        if traces.total.is_empty() {
+            out.push_str("Latency: no frames traced yet");
+            return out;
+        }
+        let _ = writeln!(
+            out,
+            "Latency of the last {} frames (ms)   p50    p95    max",
+            traces.total.len()
+        );
+        let _ = writeln!(
+            out,
+            "  {:<8} ~{:>5.1}  (half the RTT, estimated)",
+            "network",
+            traces.rtt_ms / 2.0
+        );
+        let rows = Mark::ALL[1..]
+            .iter()
+            .map(|mark| mark.stage())
+            .zip(&traces.stages);
+        for (name, window) in rows.chain([("total", &traces.total)]) {
+            let _ = writeln!(
+                out,
+                "  {:<8} {:>6.1} {:>6.1} {:>6.1}",
+                name,
+                window.p50().unwrap_or_default(),
+                window.p95().unwrap_or_default(),
+                window.percentile(1.0).unwrap_or_default()
+            );
+        }
+
+        // One row per bucket up to the slowest frame
+        let mut buckets = Vec::new();
+        for ms in traces.total.samples() {
+            let bucket = (ms / BUCKET_MS) as usize;
+            if buckets.len() <= bucket {
+                buckets.resize(bucket + 1, 0usize);
+            }
+            buckets[bucket] += 1;
+        }
+        let most = buckets.iter().copied().max().unwrap_or(1).max(1);
+        out.push_str("Total latency:");
+        for (bucket, count) in buckets.iter().enumerate() {
+            let from = bucket as f64 * BUCKET_MS;
+            let _ = write!(
+                out,
+                "\n  {:>4}-{:<4} {:<40} {}",
+                from,
+                from + BUCKET_MS,
+                "#".repeat(count * 40 / most),
+                count
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_stages_of_presented_frames() {
+        let tracer = LatencyTracer::new();
+        let start = Instant::now();
+        for (i, mark) in Mark::ALL.into_iter().enumerate() {
+            tracer.mark_at(1, mark, start + Duration::from_millis(i as u64 * 2));
+        }
+        // Replaced in the mailbox, never presented
+        tracer.mark_at(2, Mark::Received, start);
+        tracer.mark_at(2, Mark::Queued, start);
+        // Presented again after an overlay redraw
+        tracer.mark_at(1, Mark::Presented, start + Duration::from_secs(1));
+
+        assert_eq!(tracer.frames(), 1);
+        assert_eq!(tracer.median_ms(), Some(14.0));
+        let report = tracer.report();
+        assert!(report.contains("decode      2.0"), "{}", report);
+        assert!(report.contains("total      14.0"), "{}", report);
+        assert!(report.contains("10-20"), "{}", report);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::config::FrameDropPolicy;
+use crate::video::decoder::DecodedFrame;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Frames waiting for the UI under `render-all` and `pts-paced`, about
+/// 100 ms at 60 fps; the oldest is dropped past that
+const QUEUED_FRAMES: usize = 6;
+
+/// How far from its due time a `pts-paced` frame restarts the clock, e.g.
+/// after a stall or when timestamps jump
+const RESYNC_AFTER: Duration = Duration::from_millis(250);
+
+/// Hand-off holding the frames the UI has not taken yet
+struct Slot {
+    policy: FrameDropPolicy,
+    /// Delay of `pts-paced` frames behind the first one's arrival
+    delay: Duration,
+    pending: Mutex<Pending>,
+    /// Frames replaced or skipped before the UI took them
+    dropped: AtomicU64,
+    receiver_alive: AtomicBool,
+}
+
+struct Pending {
+    frames: VecDeque<DecodedFrame>,
+    /// When the frame with the given pts is due (`pts-paced`)
+    clock: Option<(Instant, i64)>,
+}
+
+/// Create a latest-frame channel between the decoder and the UI
+///
+/// Unlike an unbounded channel, a stalled renderer holds at most one pending
+/// frame: each send replaces the frame the UI has not taken yet.
+pub fn latest_frame() -> (FrameSender, FrameReceiver) {
+    frame_channel(FrameDropPolicy::LatestOnly, Duration::ZERO)
+}
+
+/// Create a channel between the decoder and the UI that keeps frames as
+/// `policy` says; `delay` holds back `pts-paced` frames to absorb jitter
+pub fn frame_channel(policy: FrameDropPolicy, delay: Duration) -> (FrameSender, FrameReceiver) {
+    let slot = Arc::new(Slot {
+        policy,
+        delay,
+        pending: Mutex::new(Pending {
+            frames: VecDeque::new(),
+            clock: None,
+        }),
+        dropped: AtomicU64::new(0),
+        receiver_alive: AtomicBool::new(true),
+    });
+    (FrameSender(slot.clone()), FrameReceiver(slot))
+}
+
+/// Decoder side of [`frame_channel`]
+#[derive(Clone)]
+pub struct FrameSender(Arc<Slot>);
+
+impl FrameSender {
+    /// Publish a frame, dropping the oldest waiting one if there is no room
+    ///
+    /// Fails with the frame once the receiver is gone.
+    pub fn send(&self, frame: DecodedFrame) -> Result<(), DecodedFrame> {
+        if !self.0.receiver_alive.load(Ordering::Acquire) {
+            return Err(frame);
+        }
+        let capacity = match self.0.policy {
+            FrameDropPolicy::LatestOnly => 1,
+            FrameDropPolicy::RenderAll | FrameDropPolicy::PtsPaced => QUEUED_FRAMES,
+        };
+        let mut pending = self.0.pending.lock();
+        pending.frames.push_back(frame);
+        while pending.frames.len() > capacity {
+            pending.frames.pop_front();
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// UI side of [`frame_channel`]
+pub struct FrameReceiver(Arc<Slot>);
+
+impl FrameReceiver {
+    /// Next frame to draw, if any
+    pub fn try_recv(&self) -> Option<DecodedFrame> {
+        self.try_recv_at(Instant::now())
+    }
+
+    /// Next frame to draw at `now`: the newest one, the oldest one, or the
+    /// last one due by its pts, depending on the policy
+    pub fn try_recv_at(&self, now: Instant) -> Option<DecodedFrame> {
+        let mut pending = self.0.pending.lock();
+        if self.0.policy != FrameDropPolicy::PtsPaced {
+            return pending.frames.pop_front();
+        }
+        loop {
+            let pts = pending.frames.front()?.pts;
+            let due = match pending.clock.and_then(|clock| due_at(clock, pts)) {
+                Some(due) if due <= now + self.0.delay + RESYNC_AFTER => due,
+                // First frame, or timestamps went back or jumped ahead
+                _ => {
+                    let due = now + self.0.delay;
+                    pending.clock = Some((due, pts));
+                    due
+                }
+            };
+            if due > now {
+                return None;
+            }
+            let frame = pending.frames.pop_front()?;
+            let next_due = pending
+                .frames
+                .front()
+                .and_then(|next| due_at(pending.clock?, next.pts))
+                .is_some_and(|next| next <= now);
+            if next_due {
+                // Late: skip to the newest frame due
+                self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if now - due > RESYNC_AFTER {
+                pending.clock = Some((now, pts));
+            }
+            return Some(frame);
+        }
+    }
+
+    /// Frames that were replaced or skipped before being taken
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// When the frame with `pts` is due on `clock`, None if it is older
+fn due_at((at, base): (Instant, i64), pts: i64) -> Option<Instant> {
+    let ahead = u64::try_from(pts - base).ok()?;
+    Some(at + Duration::from_micros(ahead))
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        self.0.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::color::ColorSpace;
+    use crate::video::decoder::PixelFormat;
+
+    fn frame(pts: i64) -> DecodedFrame {
+        DecodedFrame {
+            pts,
+            data: vec![0; 4].into(),
+            width: 1,
+            height: 1,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        }
+    }
+
+    #[test]
+    fn test_keeps_newest_and_counts_dropped() {
+        let (tx, rx) = latest_frame();
+        assert!(rx.try_recv().is_none());
+
+        for pts in 0..3 {
+            assert!(tx.send(frame(pts)).is_ok());
+        }
+        assert_eq!(rx.try_recv().map(|f| f.pts), Some(2));
+        assert!(rx.try_recv().is_none());
+        assert_eq!(rx.dropped(), 2);
+
+        drop(rx);
+        assert!(tx.send(frame(3)).is_err());
+    }
+
+    #[test]
+    fn test_render_all_keeps_order() {
+        let (tx, rx) = frame_channel(FrameDropPolicy::RenderAll, Duration::ZERO);
+        let sent = QUEUED_FRAMES as i64 + 2;
+        for pts in 0..sent {
+            assert!(tx.send(frame(pts)).is_ok());
+        }
+        let taken: Vec<i64> = std::iter::from_fn(|| rx.try_recv())
+            .map(|f| f.pts)
+            .collect();
+        assert_eq!(taken, (2..sent).collect::<Vec<_>>());
+        assert_eq!(rx.dropped(), 2);
+    }
+
+    #[test]
+    fn test_pts_paced_follows_timestamps() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let (tx, rx) = frame_channel(FrameDropPolicy::PtsPaced, Duration::from_millis(30));
+
+        // A burst of 60 fps frames, held back by the jitter delay
+        for i in 0..4 {
+            assert!(tx.send(frame(1_000_000 + i * 16_667)).is_ok());
+        }
+        assert!(rx.try_recv_at(at(0)).is_none());
+        assert_eq!(rx.try_recv_at(at(30)).map(|f| f.pts), Some(1_000_000));
+        assert!(rx.try_recv_at(at(40)).is_none());
+        assert_eq!(rx.try_recv_at(at(47)).map(|f| f.pts), Some(1_016_667));
+        // Two frames due: the older one is skipped
+        assert_eq!(rx.try_recv_at(at(85)).map(|f| f.pts), Some(1_050_001));
+        assert_eq!(rx.dropped(), 1);
+
+        // Timestamps start over (new stream): the clock does too
+        assert!(tx.send(frame(0)).is_ok());
+        assert!(rx.try_recv_at(at(100)).is_none());
+        assert_eq!(rx.try_recv_at(at(130)).map(|f| f.pts), Some(0));
+    }
+}
@@ -1,6 +1,36 @@
 /// Video decoding module with hardware acceleration
 pub mod decoder;
 pub mod renderer;
+pub mod upload;
+pub mod worker;
 
+pub mod adaptive;
+pub mod calibration;
+pub mod color;
+pub mod filter;
+pub mod gpu;
+pub mod grid;
+pub mod latency;
+pub mod mailbox;
+pub mod nal;
+#[cfg(feature = "ndi")]
+pub mod ndi;
+pub mod pool;
+pub mod replay;
+pub mod sink;
+pub mod snapshot;
+
+pub use calibration::{ColorCalibration, ColorProfiles};
+pub use color::{ColorSpace, Transfer, YuvMatrix};
 pub use decoder::{DecodedFrame, HardwareVideoDecoder, PixelFormat};
+pub use filter::{FrameFilter, FrameFilters};
+pub use gpu::{DmaBufLayer, DmaBufPlane, GpuApi, GpuFrame, GpuHandle};
+pub use grid::Grid;
+pub use latency::LatencyTracer;
+pub use mailbox::{frame_channel, latest_frame, FrameReceiver, FrameSender};
+pub use pool::{FrameBuffer, FramePool};
 pub use renderer::VideoRenderer;
+pub use replay::ReplayBuffer;
+pub use sink::{SinkThread, VideoSink};
+pub use snapshot::{save_screenshot, FrameBurst};
+pub use worker::{DecodeQueueStats, DecodeWorker};
@@ -0,0 +1,458 @@
+/// Annex-B H.264/H.265 bitstream parsing
+///
+/// Splits received bytes into NAL units at their start codes and groups them
+/// into access units (one picture with its parameter sets and SEI), so the
+/// decoder always gets whole pictures. Also reads the picture size from an
+/// SPS, which tells a rotation apart from an encoder restart.
+use crate::config::VideoCodec;
+
+/// Start code written in front of every NAL unit of an assembled access unit
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// NAL units of an Annex-B byte stream, without their start codes
+///
+/// Bytes before the first start code are skipped, as are trailing zero bytes.
+pub fn split(data: &[u8]) -> NalUnits<'_> {
+    NalUnits { data, pos: 0 }
+}
+
+/// Iterator returned by [`split`]
+pub struct NalUnits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let start = find_start_code(self.data, self.pos)? + 3;
+            let end = find_start_code(self.data, start).unwrap_or(self.data.len());
+            self.pos = end;
+            let mut nal = &self.data[start..end];
+            // The zero of a 4-byte start code, or trailing_zero_8bits
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            if !nal.is_empty() {
+                return Some(nal);
+            }
+        }
+    }
+}
+
+/// Position of the next `00 00 01` at or after `from`
+fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(3)
+        .position(|window| window == [0, 0, 1])
+        .map(|offset| from + offset)
+}
+
+/// One NAL unit (header included) of a `codec` stream
+#[derive(Debug, Clone, Copy)]
+pub struct NalUnit<'a> {
+    codec: VideoCodec,
+    data: &'a [u8],
+}
+
+impl<'a> NalUnit<'a> {
+    pub fn new(codec: VideoCodec, data: &'a [u8]) -> Self {
+        Self { codec, data }
+    }
+
+    /// `nal_unit_type` from the header (None for AV1, which has no NAL units)
+    pub fn kind(&self) -> Option<u8> {
+        let header = *self.data.first()?;
+        match self.codec {
+            VideoCodec::H264 => Some(header & 0x1F),
+            VideoCodec::H265 => Some((header >> 1) & 0x3F),
+            VideoCodec::Av1 => None,
+        }
+    }
+
+    /// Coded slice of a picture
+    pub fn is_vcl(&self) -> bool {
+        match (self.codec, self.kind()) {
+            (VideoCodec::H264, Some(kind)) => (1..=5).contains(&kind),
+            (VideoCodec::H265, Some(kind)) => kind < 32,
+            _ => false,
+        }
+    }
+
+    /// Slice of an IDR picture, which decodes without earlier frames
+    pub fn is_idr(&self) -> bool {
+        match (self.codec, self.kind()) {
+            (VideoCodec::H264, Some(kind)) => kind == 5,
+            (VideoCodec::H265, Some(kind)) => kind == 19 || kind == 20,
+            _ => false,
+        }
+    }
+
+    /// Sequence parameter set
+    pub fn is_sps(&self) -> bool {
+        match (self.codec, self.kind()) {
+            (VideoCodec::H264, Some(kind)) => kind == 7,
+            (VideoCodec::H265, Some(kind)) => kind == 33,
+            _ => false,
+        }
+    }
+
+    /// Whether this unit begins a new access unit when it follows a picture:
+    /// parameter sets, delimiters, prefix SEI or the first slice of a picture
+    pub fn starts_access_unit(&self) -> bool {
+        let first_slice = |header_len: usize| {
+            self.data
+                .get(header_len)
+                .is_some_and(|byte| byte & 0x80 != 0)
+        };
+        match (self.codec, self.kind()) {
+            // first_mb_in_slice == 0 is coded as a single 1 bit
+            (VideoCodec::H264, Some(kind)) if self.is_vcl() => kind > 0 && first_slice(1),
+            (VideoCodec::H264, Some(kind)) => matches!(kind, 6..=9 | 14..=18),
+            // first_slice_segment_in_pic_flag
+            (VideoCodec::H265, Some(_)) if self.is_vcl() => first_slice(2),
+            (VideoCodec::H265, Some(kind)) => matches!(kind, 32..=35 | 39 | 41..=44 | 48..=55),
+            _ => false,
+        }
+    }
+
+    /// Picture size an SPS announces, after cropping
+    pub fn sps_dimensions(&self) -> Option<(u32, u32)> {
+        if !self.is_sps() {
+            return None;
+        }
+        match self.codec {
+            VideoCodec::H264 => h264_sps_dimensions(&unescape(&self.data[1..])),
+            VideoCodec::H265 => h265_sps_dimensions(&unescape(self.data.get(2..)?)),
+            VideoCodec::Av1 => None,
+        }
+    }
+}
+
+/// Picture size of the first SPS in an Annex-B config packet
+pub fn sps_dimensions(codec: VideoCodec, data: &[u8]) -> Option<(u32, u32)> {
+    split(data).find_map(|nal| NalUnit::new(codec, nal).sps_dimensions())
+}
+
+/// Whether an Annex-B packet holds a slice of an IDR picture
+pub fn has_idr(codec: VideoCodec, data: &[u8]) -> bool {
+    split(data).any(|nal| NalUnit::new(codec, nal).is_idr())
+}
+
+/// Groups NAL units into access units for the decoder
+///
+/// A scrcpy packet holds whole pictures, so a packet with a picture ends its
+/// access unit; one without (config) is held and goes out with the next
+/// picture. AV1 is not Annex-B and passes through as received.
+#[derive(Debug)]
+pub struct AccessUnits {
+    codec: VideoCodec,
+    pending: Vec<u8>,
+    has_picture: bool,
+}
+
+impl AccessUnits {
+    pub fn new(codec: VideoCodec) -> Self {
+        Self {
+            codec,
+            pending: Vec::new(),
+            has_picture: false,
+        }
+    }
+
+    /// Add a received packet, returning the access units it completes
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        if self.codec == VideoCodec::Av1 {
+            self.pending.extend_from_slice(data);
+            return vec![std::mem::take(&mut self.pending)];
+        }
+
+        let mut units = Vec::new();
+        for nal in split(data) {
+            let unit = NalUnit::new(self.codec, nal);
+            if self.has_picture && unit.starts_access_unit() {
+                units.push(std::mem::take(&mut self.pending));
+                self.has_picture = false;
+            }
+            self.pending.extend_from_slice(&START_CODE);
+            self.pending.extend_from_slice(nal);
+            self.has_picture |= unit.is_vcl();
+        }
+        if self.has_picture {
+            units.push(std::mem::take(&mut self.pending));
+            self.has_picture = false;
+        }
+        units
+    }
+
+    /// Keep config (parameter sets) to go out in front of the next picture
+    pub fn hold(&mut self, config: &[u8]) {
+        if self.codec == VideoCodec::Av1 {
+            self.pending.extend_from_slice(config);
+            return;
+        }
+        for nal in split(config) {
+            self.pending.extend_from_slice(&START_CODE);
+            self.pending.extend_from_slice(nal);
+        }
+    }
+
+    /// Forget held NAL units, e.g. config of parameters no longer in use
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.has_picture = false;
+    }
+}
+
+/// RBSP of a NAL unit payload: emulation prevention bytes (`00 00 03`) removed
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+/// MSB-first reader of Exp-Golomb coded fields
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some((value << 1) | self.bit()?))
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.pos += count;
+        (self.pos <= self.data.len() * 8).then_some(())
+    }
+
+    /// ue(v)
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+    }
+
+    /// se(v)
+    fn se(&mut self) -> Option<i32> {
+        let code = self.ue()? as i64;
+        Some(if code % 2 == 1 {
+            ((code + 1) / 2) as i32
+        } else {
+            (-code / 2) as i32
+        })
+    }
+}
+
+/// Width and height from an H.264 SPS RBSP (after the NAL header)
+fn h264_sps_dimensions(rbsp: &[u8]) -> Option<(u32, u32)> {
+    let mut r = BitReader::new(rbsp);
+    let profile_idc = r.bits(8)?;
+    r.skip(16)?; // constraint flags, level_idc
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.bit()? == 1;
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.bit()?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? == 1 {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.bit()?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.bit()?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = r.ue()? + 1;
+    let height_map_units = r.ue()? + 1;
+    let frame_mbs_only = r.bit()?;
+    if frame_mbs_only == 0 {
+        r.bit()?; // mb_adaptive_frame_field_flag
+    }
+    r.bit()?; // direct_8x8_inference_flag
+
+    let mut width = width_mbs * 16;
+    let mut height = (2 - frame_mbs_only) * height_map_units * 16;
+    if r.bit()? == 1 {
+        let chroma_array_type = if separate_colour_plane {
+            0
+        } else {
+            chroma_format_idc
+        };
+        let (sub_width, sub_height) = match chroma_array_type {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let (crop_x, crop_y) = if chroma_array_type == 0 {
+            (1, 2 - frame_mbs_only)
+        } else {
+            (sub_width, sub_height * (2 - frame_mbs_only))
+        };
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(crop_x * (left + right))?;
+        height = height.checked_sub(crop_y * (top + bottom))?;
+    }
+    Some((width, height))
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let (mut last, mut next) = (8i32, 8i32);
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+/// Width and height from an H.265 SPS RBSP (after the NAL header)
+fn h265_sps_dimensions(rbsp: &[u8]) -> Option<(u32, u32)> {
+    let mut r = BitReader::new(rbsp);
+    r.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.bits(3)? as usize;
+    r.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level: general profile (88 bits) and level (8 bits)
+    r.skip(96)?;
+    let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.bit()? == 1, r.bit()? == 1));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?; // reserved_zero_2bits
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            r.skip(88)?;
+        }
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+
+    r.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 {
+        r.bit()?; // separate_colour_plane_flag
+    }
+    let mut width = r.ue()?;
+    let mut height = r.ue()?;
+    if r.bit()? == 1 {
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(sub_width * (left + right))?;
+        height = height.checked_sub(sub_height * (top + bottom))?;
+    }
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPS and PPS of the golden-stream fixture: Constrained Baseline, 16x16
+    const CONFIG_16X16: &[u8] = &[
+        0, 0, 0, 1, 0x67, 0x42, 0xC0, 0x0A, 0xDA, 0x79, 0, 0, 0, 1, 0x68, 0xCE, 0x3C, 0x80,
+    ];
+
+    #[test]
+    fn test_split_and_assemble() {
+        let units: Vec<&[u8]> = split(b"junk\0\0\0\x01\x67\x42\0\0\x01\x68\xce\0\0").collect();
+        assert_eq!(units, [&b"\x67\x42"[..], &b"\x68\xce"[..]]);
+
+        let mut assembler = AccessUnits::new(VideoCodec::H264);
+        // Config waits for the picture it belongs to
+        assembler.hold(CONFIG_16X16);
+        let units = assembler.push(b"\0\0\x01\x65\x88\x01");
+        assert_eq!(units.len(), 1);
+        assert!(units[0].starts_with(&[0, 0, 0, 1, 0x67]));
+        assert!(has_idr(VideoCodec::H264, &units[0]));
+
+        // Two pictures in one packet come out as two access units, the
+        // second slice of a picture stays with its first one
+        let units =
+            assembler.push(b"\0\0\x01\x41\x9a\0\0\x01\x41\x1a\0\0\x01\x09\x10\0\0\x01\x41\x9b");
+        assert_eq!(units.len(), 2);
+        assert_eq!(split(&units[0]).count(), 2);
+        assert!(!has_idr(VideoCodec::H264, &units[1]));
+    }
+
+    #[test]
+    fn test_sps_dimensions() {
+        assert_eq!(
+            sps_dimensions(VideoCodec::H264, CONFIG_16X16),
+            Some((16, 16))
+        );
+        // High profile with a scaling list, 1088x2400 cropped to 1080 wide
+        let high = b"\0\0\0\x01\x67\x64\0\x2a\xad\xaf\xff\xe0\x32\x94\x04\x40\x12\xde\x5d";
+        assert_eq!(sps_dimensions(VideoCodec::H264, high), Some((1080, 2400)));
+        // H.265 Main, 1088x2400 with a conformance window of 8, and
+        // emulation prevention bytes in the profile
+        let hevc = b"\0\0\0\x01\x42\x01\x01\x01\x60\0\0\x03\0\x90\0\0\x03\0\0\x03\0\x7b\xa0\x02\x20\x80\x09\x61\xcb\xf0";
+        assert_eq!(sps_dimensions(VideoCodec::H265, hevc), Some((1080, 2400)));
+        assert_eq!(sps_dimensions(VideoCodec::H264, b"\0\0\x01\x68\xce"), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::video::decoder::DecodedFrame;
+use crate::video::sink::VideoSink;
+use anyhow::{Context, Result};
+use libloading::Library;
+use std::ffi::{c_char, c_void, CString};
+use std::path::PathBuf;
+
+/// `NDIlib_send_timecode_synthesize`: let the SDK stamp frames itself
+const TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+/// `NDIlib_frame_format_type_progressive`
+const FRAME_FORMAT_PROGRESSIVE: i32 = 1;
+
+/// `NDIlib_FourCC_video_type_RGBA`
+const FOURCC_RGBA: u32 = u32::from_le_bytes(*b"RGBA");
+
+/// `NDIlib_send_create_t`
+#[repr(C)]
+struct SendCreate {
+    ndi_name: *const c_char,
+    groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+/// `NDIlib_video_frame_v2_t`
+#[repr(C)]
+struct VideoFrame {
+    xres: i32,
+    yres: i32,
+    fourcc: u32,
+    frame_rate_n: i32,
+    frame_rate_d: i32,
+    picture_aspect_ratio: f32,
+    frame_format_type: i32,
+    timecode: i64,
+    data: *const u8,
+    line_stride_in_bytes: i32,
+    metadata: *const c_char,
+    timestamp: i64,
+}
+
+type SendInstance = *mut c_void;
+
+/// Entry points of the NDI runtime, resolved at load time
+struct Runtime {
+    destroy: unsafe extern "C" fn(),
+    send_create: unsafe extern "C" fn(*const SendCreate) -> SendInstance,
+    send_destroy: unsafe extern "C" fn(SendInstance),
+    send_video: unsafe extern "C" fn(SendInstance, *const VideoFrame),
+    // Dropped last: the function pointers point into the library
+    _library: Library,
+}
+
+impl Runtime {
+    /// Places the NDI runtime is installed, newest version first
+    fn candidates() -> Vec<PathBuf> {
+        let file = if cfg!(windows) {
+            "Processing.NDI.Lib.x64.dll"
+        } else if cfg!(target_os = "macos") {
+            "libndi.dylib"
+        } else {
+            "libndi.so"
+        };
+        let mut candidates: Vec<PathBuf> = ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"]
+            .iter()
+            .filter_map(|var| std::env::var_os(var))
+            .map(|dir| PathBuf::from(dir).join(file))
+            .collect();
+        if cfg!(target_os = "macos") {
+            candidates.push("/usr/local/lib/libndi.dylib".into());
+        }
+        if cfg!(target_os = "linux") {
+            candidates.extend(["libndi.so.6".into(), "libndi.so.5".into()]);
+        }
+        candidates.push(file.into());
+        candidates
+    }
+
+    fn load() -> Result<Self> {
+        let library = Self::candidates()
+            .into_iter()
+            // SAFETY: the NDI runtime's initializers are trusted like any system library
+            .find_map(|path| unsafe { Library::new(path).ok() })
+            .context("NDI runtime not found (install NDI Tools or set NDI_RUNTIME_DIR_V6)")?;
+        // SAFETY: the signatures match Processing.NDI.Lib.h (v5 and v6)
+        unsafe {
+            let initialize =
+                *library.get::<unsafe extern "C" fn() -> bool>(b"NDIlib_initialize")?;
+            if !initialize() {
+                anyhow::bail!("NDI is not supported on this CPU");
+            }
+            Ok(Self {
+                destroy: *library.get::<unsafe extern "C" fn()>(b"NDIlib_destroy")?,
+                send_create: *library
+                    .get::<unsafe extern "C" fn(*const SendCreate) -> SendInstance>(
+                        b"NDIlib_send_create",
+                    )?,
+                send_destroy: *library
+                    .get::<unsafe extern "C" fn(SendInstance)>(b"NDIlib_send_destroy")?,
+                send_video: *library.get::<unsafe extern "C" fn(SendInstance, *const VideoFrame)>(
+                    b"NDIlib_send_send_video_v2",
+                )?,
+                _library: library,
+            })
+        }
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // SAFETY: every sender was destroyed before the runtime
+        unsafe { (self.destroy)() };
+    }
+}
+
+/// NDI source other apps (OBS, vMix, NDI Studio Monitor) can pick up
+///
+/// Frames are sent as RGBA, unclocked: each one goes out as soon as it is
+/// decoded. Needs the NDI runtime from NDI Tools, loaded at run time.
+pub struct NdiSink {
+    name: String,
+    sender: SendInstance,
+    /// RGBA conversion buffer for frames not decoded to RGBA
+    rgba: Vec<u8>,
+    runtime: Runtime,
+}
+
+// The sender is only used from the sink thread, one call at a time
+unsafe impl Send for NdiSink {}
+
+impl NdiSink {
+    /// Publish a source called `name` on the local network
+    pub fn new(name: &str) -> Result<Self> {
+        let runtime = Runtime::load()?;
+        let c_name = CString::new(name).context("NDI source name contains NUL")?;
+        let create = SendCreate {
+            ndi_name: c_name.as_ptr(),
+            groups: std::ptr::null(),
+            clock_video: false,
+            clock_audio: false,
+        };
+        // SAFETY: the settings and the name outlive the call, which copies them
+        let sender = unsafe { (runtime.send_create)(&create) };
+        if sender.is_null() {
+            anyhow::bail!("Failed to create NDI source {:?}", name);
+        }
+        tracing::info!("Publishing NDI source {:?}", name);
+        Ok(Self {
+            name: format!("ndi:{}", name),
+            sender,
+            rgba: Vec::new(),
+            runtime,
+        })
+    }
+}
+
+impl VideoSink for NdiSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn present(&mut self, frame: &DecodedFrame) -> Result<()> {
+        let pixels = frame.rgba_into(&mut self.rgba);
+        let video = VideoFrame {
+            xres: frame.width as i32,
+            yres: frame.height as i32,
+            fourcc: FOURCC_RGBA,
+            // Nominal only, the stream is unclocked
+            frame_rate_n: 60,
+            frame_rate_d: 1,
+            // 0 means square pixels
+            picture_aspect_ratio: 0.0,
+            frame_format_type: FRAME_FORMAT_PROGRESSIVE,
+            timecode: TIMECODE_SYNTHESIZE,
+            data: pixels.as_ptr(),
+            line_stride_in_bytes: frame.width as i32 * 4,
+            metadata: std::ptr::null(),
+            timestamp: 0,
+        };
+        // SAFETY: the synchronous send is done with the pixels when it returns
+        unsafe { (self.runtime.send_video)(self.sender, &video) };
+        Ok(())
+    }
+}
+
+impl Drop for NdiSink {
+    fn drop(&mut self) {
+        // SAFETY: created in `new`, destroyed once, before the runtime
+        unsafe { (self.runtime.send_destroy)(self.sender) };
+    }
+}
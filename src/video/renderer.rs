@@ -1,11 +1,36 @@
+use crate::config::{PresentMode, ScalingMode, UpscaleFilter};
+use crate::video::adaptive::RenderScaler;
+use crate::video::calibration::ColorCalibration;
+use crate::video::color::{ColorSpace, Transfer};
 use crate::video::decoder::{DecodedFrame, PixelFormat};
+use crate::video::latency::{LatencyTracer, Mark};
+use crate::video::upload::FrameUploader;
 use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wgpu::{
     Backends, Device, DeviceDescriptor, Features, Instance, Limits, PowerPreference, Queue,
     RequestAdapterOptions, Surface, SurfaceConfiguration, TextureFormat, TextureUsages,
 };
+use winit::event::WindowEvent;
 use winit::window::Window;
 
+/// How `video.wgsl` turns sampled values into linear light
+const TRANSFER_LINEAR: u32 = 0;
+const TRANSFER_SDR: u32 = 1;
+const TRANSFER_PQ: u32 = 2;
+const TRANSFER_HLG: u32 = 3;
+
+/// Intermediate texture the video is rendered into before upscaling
+struct ScaledTarget {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
 /// GPU-accelerated video renderer using wgpu
 pub struct VideoRenderer<'a> {
     #[allow(dead_code)]
@@ -14,14 +39,43 @@ pub struct VideoRenderer<'a> {
     device: Device,
     queue: Queue,
     config: SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
     window: &'a Window,
     render_pipeline: wgpu::RenderPipeline,
     texture: Option<wgpu::Texture>,
+    uploader: Option<FrameUploader>,
+    // Reused for YUV -> RGBA conversion so frames do not allocate
+    rgba_scratch: Vec<u8>,
     texture_bind_group: Option<wgpu::BindGroup>,
+    // Shares the pixels of the frame on screen, for screenshots
+    last_frame: Option<DecodedFrame>,
     sampler: wgpu::Sampler,
+    // Pixel-perfect sampling for integer scaling
+    nearest_sampler: wgpu::Sampler,
+    scaling: ScalingMode,
+    upscale_filter: UpscaleFilter,
     bind_group_layout: wgpu::BindGroupLayout,
+    calibration_buffer: wgpu::Buffer,
+    // Transfer function of the current frames, for the shader
+    transfer_buffer: wgpu::Buffer,
+    transfer: u32,
+    // Rgba16Unorm textures are available for 10-bit video
+    high_precision: bool,
+    texture_format: TextureFormat,
     current_width: u32,
     current_height: u32,
+    // Reduced-resolution video target used while the GPU is over budget
+    render_scaler: Option<RenderScaler>,
+    gpu_time_us: Arc<AtomicU64>,
+    scaled_target: Option<ScaledTarget>,
+    sharpen_pipeline: wgpu::RenderPipeline,
+    sharpen_layout: wgpu::BindGroupLayout,
+    // UI drawn on top of the video (captions, panels)
+    egui_ctx: egui::Context,
+    egui_state: egui_winit::State,
+    egui_renderer: egui_wgpu::Renderer,
+    // Stamps new frames as uploaded and presented
+    latency: Option<LatencyTracer>,
 }
 
 impl<'a> VideoRenderer<'a> {
@@ -48,11 +102,20 @@ impl<'a> VideoRenderer<'a> {
 
         tracing::info!("Using GPU: {}", adapter.get_info().name);
 
+        // 16-bit textures keep 10-bit video from banding; 8-bit otherwise
+        let high_precision = adapter
+            .features()
+            .contains(Features::TEXTURE_FORMAT_16BIT_NORM);
+
         // Request device and queue
         let (device, queue) = pollster::block_on(adapter.request_device(
             &DeviceDescriptor {
                 label: Some("Main Device"),
-                required_features: Features::empty(),
+                required_features: if high_precision {
+                    Features::TEXTURE_FORMAT_16BIT_NORM
+                } else {
+                    Features::empty()
+                },
                 required_limits: Limits::default(),
                 memory_hints: Default::default(),
             },
@@ -70,21 +133,9 @@ impl<'a> VideoRenderer<'a> {
             .copied()
             .unwrap_or(surface_caps.formats[0]);
 
-        // Select best available PresentMode
-        // Priority: Mailbox (Fast, No Tear) > Immediate (Fastest, Tearing) > Fifo (VSync)
-        let present_mode = if surface_caps
-            .present_modes
-            .contains(&wgpu::PresentMode::Mailbox)
-        {
-            wgpu::PresentMode::Mailbox
-        } else if surface_caps
-            .present_modes
-            .contains(&wgpu::PresentMode::Immediate)
-        {
-            wgpu::PresentMode::Immediate
-        } else {
-            wgpu::PresentMode::Fifo
-        };
+        // Lowest latency first; `set_present_mode` can pick another
+        let present_modes = surface_caps.present_modes.clone();
+        let present_mode = choose_present_mode(PresentMode::Auto, &present_modes);
 
         let config = SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT,
@@ -109,6 +160,12 @@ impl<'a> VideoRenderer<'a> {
             mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
 
         // Create bind group layout for texture
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -130,11 +187,105 @@ impl<'a> VideoRenderer<'a> {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
+        // Color calibration uniform, identity until a profile is set
+        let calibration_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Calibration Buffer"),
+            size: std::mem::size_of::<[f32; 16]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &calibration_buffer,
+            0,
+            bytemuck::cast_slice(&ColorCalibration::default().to_uniform()),
+        );
+
+        // `VideoFormat` in `video.wgsl`, padded to 16 bytes
+        let transfer_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Video Format Buffer"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &transfer_buffer,
+            0,
+            bytemuck::cast_slice(&[TRANSFER_LINEAR; 4]),
+        );
+
         // Create render pipeline
-        let render_pipeline = Self::create_render_pipeline(&device, &config, &bind_group_layout)?;
+        let render_pipeline = Self::create_render_pipeline(
+            &device,
+            config.format,
+            &bind_group_layout,
+            "Video Shader",
+            &video_shader(UpscaleFilter::Bilinear),
+        )?;
+
+        // Upscale pass for the reduced-resolution target
+        let sharpen_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sharpen Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sharpen_pipeline = Self::create_render_pipeline(
+            &device,
+            config.format,
+            &sharpen_layout,
+            "Sharpen Shader",
+            include_str!("shaders/sharpen.wgsl"),
+        )?;
+
+        // Overlay UI
+        let egui_ctx = egui::Context::default();
+        let egui_state = egui_winit::State::new(
+            egui_ctx.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let egui_renderer = egui_wgpu::Renderer::new(&device, config.format, None, 1, false);
 
         Ok(Self {
             instance,
@@ -142,27 +293,48 @@ impl<'a> VideoRenderer<'a> {
             device,
             queue,
             config,
+            present_modes,
             window,
             render_pipeline,
             texture: None,
+            uploader: None,
+            rgba_scratch: Vec::new(),
             texture_bind_group: None,
+            last_frame: None,
             sampler,
+            nearest_sampler,
+            scaling: ScalingMode::Fit,
+            upscale_filter: UpscaleFilter::Bilinear,
             bind_group_layout,
+            calibration_buffer,
+            transfer_buffer,
+            transfer: TRANSFER_LINEAR,
+            high_precision,
+            texture_format: TextureFormat::Rgba8UnormSrgb,
             current_width: 0,
             current_height: 0,
+            render_scaler: None,
+            gpu_time_us: Arc::new(AtomicU64::new(0)),
+            scaled_target: None,
+            sharpen_pipeline,
+            sharpen_layout,
+            egui_ctx,
+            egui_state,
+            egui_renderer,
+            latency: None,
         })
     }
 
     /// Create the render pipeline with shaders
     fn create_render_pipeline(
         device: &Device,
-        config: &SurfaceConfiguration,
+        format: TextureFormat,
         bind_group_layout: &wgpu::BindGroupLayout,
+        label: &str,
+        shader_source: &str,
     ) -> Result<wgpu::RenderPipeline> {
-        // Shader source (WGSL)
-        let shader_source = include_str!("shaders/video.wgsl");
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Video Shader"),
+            label: Some(label),
             source: wgpu::ShaderSource::Wgsl(shader_source.into()),
         });
 
@@ -177,15 +349,15 @@ impl<'a> VideoRenderer<'a> {
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main",
+                entry_point: Some("vs_main"),
                 buffers: &[],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
+                entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -224,25 +396,209 @@ impl<'a> VideoRenderer<'a> {
 
     /// Render a decoded frame to the window
     pub fn render(&mut self, frame: &DecodedFrame) -> Result<()> {
+        self.render_with_overlay(frame, |_| {})
+    }
+
+    /// Render a decoded frame with UI from `ui` drawn on top
+    pub fn render_with_overlay(
+        &mut self,
+        frame: &DecodedFrame,
+        ui: impl FnMut(&egui::Context),
+    ) -> Result<()> {
         // Skip if window is minimized (0 size) to avoid swapchain errors
         if self.config.width == 0 || self.config.height == 0 {
             return Ok(());
         }
 
-        // Update texture if frame size changed
-        if frame.width != self.current_width || frame.height != self.current_height {
+        // Update texture if frame size or format changed
+        let (format, transfer) =
+            video_texture_format(frame.format, frame.color, self.high_precision);
+        if frame.width != self.current_width
+            || frame.height != self.current_height
+            || format != self.texture_format
+        {
+            self.texture_format = format;
             self.update_texture(frame.width, frame.height)?;
         }
+        if transfer != self.transfer {
+            self.transfer = transfer;
+            self.queue.write_buffer(
+                &self.transfer_buffer,
+                0,
+                bytemuck::cast_slice(&[transfer; 4]),
+            );
+        }
 
         // Upload frame data to GPU texture
         self.upload_frame_data(frame)?;
+        if let Some(latency) = &self.latency {
+            latency.mark(frame.pts, Mark::Uploaded);
+        }
 
         // Render to screen
-        self.render_to_screen()?;
+        self.render_to_screen(ui)?;
+        if let Some(latency) = &self.latency {
+            latency.mark(frame.pts, Mark::Presented);
+        }
+        self.last_frame = Some(frame.clone());
 
         Ok(())
     }
 
+    /// Decoded frame currently on screen, as it came from the decoder
+    pub fn last_frame(&self) -> Option<&DecodedFrame> {
+        self.last_frame.as_ref()
+    }
+
+    /// Save the frame on screen as a timestamped PNG in `dir`
+    ///
+    /// Blocks while encoding; see [`save_screenshot`](crate::video::save_screenshot)
+    /// to do that on another thread.
+    pub fn screenshot(&self, dir: &Path) -> Result<PathBuf> {
+        let frame = self.last_frame.as_ref().context("No frame shown yet")?;
+        crate::video::save_screenshot(frame, dir)
+    }
+
+    /// Apply a color calibration profile to the video from the next frame on
+    pub fn set_calibration(&mut self, calibration: &ColorCalibration) {
+        self.queue.write_buffer(
+            &self.calibration_buffer,
+            0,
+            bytemuck::cast_slice(&calibration.to_uniform()),
+        );
+    }
+
+    /// Scale the video into the window with `mode`
+    pub fn set_scaling(&mut self, mode: ScalingMode) -> Result<()> {
+        let filter_was = self.effective_upscale_filter();
+        self.scaling = mode;
+        if self.effective_upscale_filter() != filter_was {
+            self.rebuild_video_pipeline()?;
+        }
+        // The texture bind group holds the sampler
+        if self.texture.is_some() {
+            self.update_texture(self.current_width, self.current_height)?;
+        }
+        Ok(())
+    }
+
+    /// Enlarge the video with `filter` from the next frame on
+    pub fn set_upscale_filter(&mut self, filter: UpscaleFilter) -> Result<()> {
+        let filter_was = self.effective_upscale_filter();
+        self.upscale_filter = filter;
+        if self.effective_upscale_filter() != filter_was {
+            self.rebuild_video_pipeline()?;
+        }
+        Ok(())
+    }
+
+    /// Integer scaling keeps its sharp pixels, whatever filter is selected
+    fn effective_upscale_filter(&self) -> UpscaleFilter {
+        match self.scaling {
+            ScalingMode::Integer => UpscaleFilter::Bilinear,
+            ScalingMode::Fit | ScalingMode::Fill => self.upscale_filter,
+        }
+    }
+
+    fn rebuild_video_pipeline(&mut self) -> Result<()> {
+        let filter = self.effective_upscale_filter();
+        tracing::info!("Upscale filter: {}", filter.name());
+        self.render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            self.config.format,
+            &self.bind_group_layout,
+            "Video Shader",
+            &video_shader(filter),
+        )?;
+        Ok(())
+    }
+
+    /// Sampler for the video texture in the current scaling mode
+    fn video_sampler(&self) -> &wgpu::Sampler {
+        match self.scaling {
+            ScalingMode::Integer => &self.nearest_sampler,
+            ScalingMode::Fit | ScalingMode::Fill => &self.sampler,
+        }
+    }
+
+    /// Present frames with `mode`, or the closest mode the surface supports
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        let present_mode = choose_present_mode(mode, &self.present_modes);
+        tracing::info!("Present mode: {:?}", present_mode);
+        if present_mode != self.config.present_mode {
+            self.config.present_mode = present_mode;
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    /// Stamp frames in `latency` as they are uploaded and presented
+    pub fn set_latency_tracer(&mut self, latency: LatencyTracer) {
+        self.latency = Some(latency);
+    }
+
+    /// Render the video at reduced resolution while the GPU is over budget
+    ///
+    /// The budget is one refresh interval of the window's monitor.
+    pub fn set_adaptive_resolution(&mut self, enabled: bool) {
+        self.render_scaler = enabled.then(|| {
+            let refresh_hz = self
+                .window
+                .current_monitor()
+                .and_then(|monitor| monitor.refresh_rate_millihertz())
+                .map_or(60.0, |millihertz| millihertz as f64 / 1000.0);
+            RenderScaler::for_refresh_rate(refresh_hz)
+        });
+        if !enabled {
+            self.scaled_target = None;
+        }
+    }
+
+    /// Create the reduced-resolution target unless one of this size exists
+    fn ensure_scaled_target(&mut self, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if let Some(target) = &self.scaled_target {
+            if target.width == width && target.height == height {
+                return;
+            }
+        }
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Scaled Video Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sharpen Bind Group"),
+            layout: &self.sharpen_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.scaled_target = Some(ScaledTarget {
+            view,
+            bind_group,
+            width,
+            height,
+        });
+    }
+
     /// Create or update the video texture
     fn update_texture(&mut self, width: u32, height: u32) -> Result<()> {
         let texture = self.device.create_texture(&wgpu::TextureDescriptor {
@@ -255,7 +611,7 @@ impl<'a> VideoRenderer<'a> {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
+            format: self.texture_format,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
             view_formats: &[],
         });
@@ -273,12 +629,26 @@ impl<'a> VideoRenderer<'a> {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    resource: wgpu::BindingResource::Sampler(self.video_sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.calibration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.transfer_buffer.as_entire_binding(),
                 },
             ],
         });
 
         self.texture = Some(texture);
+        self.uploader = Some(FrameUploader::new(
+            &self.device,
+            width,
+            height,
+            self.bytes_per_pixel(),
+        ));
         self.texture_bind_group = Some(bind_group);
         self.current_width = width;
         self.current_height = height;
@@ -286,18 +656,40 @@ impl<'a> VideoRenderer<'a> {
         Ok(())
     }
 
+    fn bytes_per_pixel(&self) -> u32 {
+        self.texture_format.block_copy_size(None).unwrap_or(4)
+    }
+
     /// Upload frame data to GPU texture
     fn upload_frame_data(&mut self, frame: &DecodedFrame) -> Result<()> {
+        let _span = tracing::trace_span!("upload", pts = frame.pts).entered();
         let texture = self.texture.as_ref().context("Texture not initialized")?;
 
-        // Convert frame data to RGBA if needed
-        let rgba_data = match frame.format {
-            PixelFormat::RGBA => frame.data.clone(),
-            PixelFormat::YUV420P => Self::yuv420p_to_rgba(&frame.data, frame.width, frame.height),
-            PixelFormat::NV12 => Self::nv12_to_rgba(&frame.data, frame.width, frame.height),
+        let bytes_per_pixel = self.bytes_per_pixel();
+        // Convert frame data to RGBA if needed (RGBA frames are borrowed)
+        let pixels = if self.texture_format == TextureFormat::Rgba16Unorm {
+            &frame.data[..]
+        } else {
+            frame.rgba_into(&mut self.rgba_scratch)
         };
 
-        // Upload to GPU
+        // Finish mapping staging buffers the GPU has released
+        self.device.poll(wgpu::Maintain::Poll);
+        if let Some(uploader) = &mut self.uploader {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Upload Encoder"),
+                });
+            if uploader.upload(&mut encoder, texture, pixels) {
+                // Runs ahead of the render pass, which is submitted after it
+                self.queue.submit(std::iter::once(encoder.finish()));
+                uploader.recycle();
+                return Ok(());
+            }
+        }
+
+        // Both staging buffers are still being copied: let wgpu stage it
         self.queue.write_texture(
             wgpu::ImageCopyTexture {
                 texture,
@@ -305,10 +697,10 @@ impl<'a> VideoRenderer<'a> {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &rgba_data,
+            pixels,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * frame.width),
+                bytes_per_row: Some(bytes_per_pixel * frame.width),
                 rows_per_image: Some(frame.height),
             },
             wgpu::Extent3d {
@@ -321,96 +713,97 @@ impl<'a> VideoRenderer<'a> {
         Ok(())
     }
 
-    /// Convert YUV420P to RGBA
-    fn yuv420p_to_rgba(yuv_data: &[u8], width: u32, height: u32) -> Vec<u8> {
-        let w = width as usize;
-        let h = height as usize;
-        let y_size = w * h;
-        let uv_size = (w / 2) * (h / 2);
-
-        let mut rgba = vec![0u8; w * h * 4];
-
-        for y in 0..h {
-            for x in 0..w {
-                let y_index = y * w + x;
-                let uv_index = (y / 2) * (w / 2) + (x / 2);
-
-                let y_val = yuv_data[y_index] as f32;
-                let u_val = yuv_data[y_size + uv_index] as f32 - 128.0;
-                let v_val = yuv_data[y_size + uv_size + uv_index] as f32 - 128.0;
-
-                // YUV to RGB conversion
-                let r = (y_val + 1.402 * v_val).clamp(0.0, 255.0) as u8;
-                let g = (y_val - 0.344 * u_val - 0.714 * v_val).clamp(0.0, 255.0) as u8;
-                let b = (y_val + 1.772 * u_val).clamp(0.0, 255.0) as u8;
-
-                let rgba_index = y_index * 4;
-                rgba[rgba_index] = r;
-                rgba[rgba_index + 1] = g;
-                rgba[rgba_index + 2] = b;
-                rgba[rgba_index + 3] = 255;
-            }
-        }
-
-        rgba
-    }
-
-    /// Convert NV12 to RGBA
-    fn nv12_to_rgba(nv12_data: &[u8], width: u32, height: u32) -> Vec<u8> {
-        let w = width as usize;
-        let h = height as usize;
-        let y_size = w * h;
-
-        let mut rgba = vec![0u8; w * h * 4];
-
-        for y in 0..h {
-            for x in 0..w {
-                let y_index = y * w + x;
-                let uv_index = (y / 2) * w + (x / 2) * 2;
-
-                let y_val = nv12_data[y_index] as f32;
-                let u_val = nv12_data[y_size + uv_index] as f32 - 128.0;
-                let v_val = nv12_data[y_size + uv_index + 1] as f32 - 128.0;
-
-                // YUV to RGB conversion
-                let r = (y_val + 1.402 * v_val).clamp(0.0, 255.0) as u8;
-                let g = (y_val - 0.344 * u_val - 0.714 * v_val).clamp(0.0, 255.0) as u8;
-                let b = (y_val + 1.772 * u_val).clamp(0.0, 255.0) as u8;
-
-                let rgba_index = y_index * 4;
-                rgba[rgba_index] = r;
-                rgba[rgba_index + 1] = g;
-                rgba[rgba_index + 2] = b;
-                rgba[rgba_index + 3] = 255;
-            }
+    /// Clear the window and draw only the overlay, for when there is no video
+    /// (e.g. while waiting for the device)
+    pub fn render_overlay_only(&mut self, ui: impl FnMut(&egui::Context)) -> Result<()> {
+        if self.config.width == 0 || self.config.height == 0 {
+            return Ok(());
         }
-
-        rgba
+        let Some(output) = self.next_surface_texture()? else {
+            return Ok(());
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Overlay Encoder"),
+            });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let overlay_commands = self.render_overlay(&mut encoder, &view, ui);
+        self.queue.submit(
+            overlay_commands
+                .into_iter()
+                .chain(std::iter::once(encoder.finish())),
+        );
+        output.present();
+        Ok(())
     }
 
-    /// Render texture to screen with upscaling
-    fn render_to_screen(&mut self) -> Result<()> {
-        let output = match self.surface.get_current_texture() {
-            Ok(output) => output,
+    /// Next swapchain image, `None` when this frame has to be skipped
+    fn next_surface_texture(&mut self) -> Result<Option<wgpu::SurfaceTexture>> {
+        match self.surface.get_current_texture() {
+            Ok(output) => Ok(Some(output)),
             Err(wgpu::SurfaceError::Lost) => {
                 tracing::warn!("Surface lost, reconfiguring...");
                 self.reconfigure();
-                return Ok(());
-            }
-            Err(wgpu::SurfaceError::OutOfMemory) => {
-                return Err(anyhow::anyhow!("Surface out of memory"));
+                Ok(None)
             }
+            Err(wgpu::SurfaceError::OutOfMemory) => Err(anyhow::anyhow!("Surface out of memory")),
             // All other errors (Outdated, Timeout) should be resolved by the next frame
             Err(e) => {
                 tracing::warn!("Skipping frame due to surface error: {:?}", e);
-                return Ok(());
+                Ok(None)
             }
+        }
+    }
+
+    /// Render texture to screen with upscaling, then the overlay
+    fn render_to_screen(&mut self, ui: impl FnMut(&egui::Context)) -> Result<()> {
+        let _span = tracing::trace_span!("present").entered();
+        let Some(output) = self.next_surface_texture()? else {
+            return Ok(());
         };
 
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        // GPU time of the last completed frame decides the internal resolution
+        let mut scale = 1.0;
+        if let Some(scaler) = &mut self.render_scaler {
+            let micros = self.gpu_time_us.swap(0, Ordering::Relaxed);
+            if micros > 0 {
+                scaler.record(Duration::from_micros(micros));
+            }
+            scale = scaler.scale();
+        }
+        let downscaled = match self.viewport() {
+            Some((_, _, w, h)) if scale < 1.0 => {
+                self.ensure_scaled_target((w * scale) as u32, (h * scale) as u32);
+                true
+            }
+            _ => false,
+        };
+        let video_view = match &self.scaled_target {
+            Some(target) if downscaled => &target.view,
+            _ => &view,
+        };
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -421,7 +814,7 @@ impl<'a> VideoRenderer<'a> {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: video_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -439,40 +832,159 @@ impl<'a> VideoRenderer<'a> {
                 render_pass.set_bind_group(0, bind_group, &[]);
             }
 
-            // Calculate Letterboxing (Fit inside window maintaining aspect ratio)
-            if self.current_width > 0 && self.current_height > 0 {
-                let win_w = self.config.width as f32;
-                let win_h = self.config.height as f32;
-                let vid_w = self.current_width as f32;
-                let vid_h = self.current_height as f32;
-
-                let win_aspect = win_w / win_h;
-                let vid_aspect = vid_w / vid_h;
-
-                let (viewport_w, viewport_h, x, y) = if vid_aspect > win_aspect {
-                    // Video is wider than window: Fit width, adjust height (bars top/bottom)
-                    let scale = win_w / vid_w;
-                    let h = vid_h * scale;
-                    (win_w, h, 0.0, (win_h - h) / 2.0)
-                } else {
-                    // Video is taller than window: Fit height, adjust width (bars left/right)
-                    let scale = win_h / vid_h;
-                    let w = vid_w * scale;
-                    (w, win_h, (win_w - w) / 2.0, 0.0)
-                };
-
+            // The scaled target holds just the video area
+            if let Some((x, y, viewport_w, viewport_h)) = self.viewport().filter(|_| !downscaled) {
                 render_pass.set_viewport(x, y, viewport_w, viewport_h, 0.0, 1.0);
             }
 
             render_pass.draw(0..4, 0..1); // Full-screen quad
         }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        if let (Some(target), true) = (&self.scaled_target, downscaled) {
+            let mut sharpen_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Sharpen Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            sharpen_pass.set_pipeline(&self.sharpen_pipeline);
+            sharpen_pass.set_bind_group(0, &target.bind_group, &[]);
+            if let Some((x, y, viewport_w, viewport_h)) = self.viewport() {
+                sharpen_pass.set_viewport(x, y, viewport_w, viewport_h, 0.0, 1.0);
+            }
+            sharpen_pass.draw(0..4, 0..1);
+        }
+
+        let overlay_commands = self.render_overlay(&mut encoder, &view, ui);
+
+        self.queue.submit(
+            overlay_commands
+                .into_iter()
+                .chain(std::iter::once(encoder.finish())),
+        );
+        if self.render_scaler.is_some() {
+            // Completion is picked up by the device poll before the next upload
+            let submitted = Instant::now();
+            let gpu_time_us = self.gpu_time_us.clone();
+            self.queue.on_submitted_work_done(move || {
+                gpu_time_us.store(submitted.elapsed().as_micros() as u64, Ordering::Relaxed);
+            });
+        }
         output.present();
 
         Ok(())
     }
 
+    /// Run the overlay UI and record its draw calls over the video
+    fn render_overlay(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        ui: impl FnMut(&egui::Context),
+    ) -> Vec<wgpu::CommandBuffer> {
+        let raw_input = self.egui_state.take_egui_input(self.window);
+        let output = self.egui_ctx.run(raw_input, ui);
+        self.egui_state
+            .handle_platform_output(self.window, output.platform_output);
+
+        let paint_jobs = self
+            .egui_ctx
+            .tessellate(output.shapes, output.pixels_per_point);
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [self.config.width, self.config.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.egui_renderer
+                .update_texture(&self.device, &self.queue, *id, delta);
+        }
+        let commands = self.egui_renderer.update_buffers(
+            &self.device,
+            &self.queue,
+            encoder,
+            &paint_jobs,
+            &screen,
+        );
+
+        {
+            let mut overlay_pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Overlay Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                })
+                .forget_lifetime();
+            self.egui_renderer
+                .render(&mut overlay_pass, &paint_jobs, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.egui_renderer.free_texture(id);
+        }
+        commands
+    }
+
+    /// Context of the overlay UI, for styling it
+    pub fn ui_context(&self) -> &egui::Context {
+        &self.egui_ctx
+    }
+
+    /// Feed a window event to the overlay UI
+    ///
+    /// Returns true when the overlay used it (e.g. a click on a panel), in which
+    /// case it should not also go to the device.
+    pub fn on_window_event(&mut self, event: &WindowEvent) -> bool {
+        self.egui_state.on_window_event(self.window, event).consumed
+    }
+
+    /// Video area as (x, y, width, height) in window pixels
+    ///
+    /// Depends on the scaling mode; always inside the window.
+    pub fn viewport(&self) -> Option<(f32, f32, f32, f32)> {
+        if self.current_width == 0 || self.current_height == 0 {
+            return None;
+        }
+        Some(video_viewport(
+            self.scaling,
+            (self.config.width as f32, self.config.height as f32),
+            (self.current_width as f32, self.current_height as f32),
+        ))
+    }
+
+    /// Map a window position (physical pixels) to video frame coordinates
+    ///
+    /// Returns None when no video is shown or the position is on the letterbox bars.
+    pub fn window_to_video(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let (vx, vy, vw, vh) = self.viewport()?;
+        let u = (x as f32 - vx) / vw;
+        let v = (y as f32 - vy) / vh;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        Some((
+            (u * self.current_width as f32) as u32,
+            (v * self.current_height as f32) as u32,
+        ))
+    }
+
     /// Reconfigure surface (e.g. on resize or lost)
     fn reconfigure(&mut self) {
         self.surface.configure(&self.device, &self.config);
@@ -493,3 +1005,164 @@ impl<'a> VideoRenderer<'a> {
         self.window
     }
 }
+
+/// Texture format for `format` frames in `color`, and the shader transfer code
+///
+/// SDR frames use an sRGB texture the GPU linearizes while sampling; HDR and
+/// 16-bit frames are linearized (and tone mapped) in the shader.
+fn video_texture_format(
+    format: PixelFormat,
+    color: ColorSpace,
+    high_precision: bool,
+) -> (TextureFormat, u32) {
+    let transfer = match color.transfer {
+        Transfer::Sdr => TRANSFER_SDR,
+        Transfer::Pq => TRANSFER_PQ,
+        Transfer::Hlg => TRANSFER_HLG,
+    };
+    match format {
+        PixelFormat::RGBA64 if high_precision => (TextureFormat::Rgba16Unorm, transfer),
+        _ if color.is_hdr() => (TextureFormat::Rgba8Unorm, transfer),
+        _ => (TextureFormat::Rgba8UnormSrgb, TRANSFER_LINEAR),
+    }
+}
+
+/// Video shader source with the sampling function of `filter`
+fn video_shader(filter: UpscaleFilter) -> String {
+    let sample = match filter {
+        UpscaleFilter::Bilinear => include_str!("shaders/filter_bilinear.wgsl"),
+        UpscaleFilter::Lanczos => include_str!("shaders/filter_lanczos.wgsl"),
+        UpscaleFilter::Sharpen => include_str!("shaders/filter_sharpen.wgsl"),
+    };
+    format!("{}\n{}", include_str!("shaders/video.wgsl"), sample)
+}
+
+/// Where `video` goes in `window` (both width, height) for `mode`
+fn video_viewport(
+    mode: ScalingMode,
+    (win_w, win_h): (f32, f32),
+    (vid_w, vid_h): (f32, f32),
+) -> (f32, f32, f32, f32) {
+    let fit = (win_w / vid_w).min(win_h / vid_h);
+    let scale = match mode {
+        ScalingMode::Fill => return (0.0, 0.0, win_w, win_h),
+        // Whole multiples only, unless even 1x does not fit
+        ScalingMode::Integer if fit >= 1.0 => fit.floor(),
+        ScalingMode::Integer | ScalingMode::Fit => fit,
+    };
+    let (w, h) = (vid_w * scale, vid_h * scale);
+    let (x, y) = ((win_w - w) / 2.0, (win_h - h) / 2.0);
+    match mode {
+        // Whole-pixel offsets keep texels on screen pixels
+        ScalingMode::Integer => (x.floor(), y.floor(), w, h),
+        _ => (x, y, w, h),
+    }
+}
+
+/// `requested`, or the next best of `supported` (FIFO is always available)
+///
+/// Mailbox falls back to immediate (still low latency, may tear); immediate
+/// falls back to mailbox before settling for FIFO.
+fn choose_present_mode(
+    requested: PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    use wgpu::PresentMode::{Fifo, Immediate, Mailbox};
+    let preference: &[wgpu::PresentMode] = match requested {
+        PresentMode::Auto | PresentMode::Mailbox => &[Mailbox, Immediate],
+        PresentMode::Immediate => &[Immediate, Mailbox],
+        PresentMode::Fifo => &[],
+    };
+    let chosen = preference
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(Fifo);
+    if requested != PresentMode::Auto && chosen != preference.first().copied().unwrap_or(Fifo) {
+        tracing::warn!(
+            "Present mode {} is not supported here, using {:?}",
+            requested.name(),
+            chosen
+        );
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_present_mode_fallback() {
+        use wgpu::PresentMode::{Fifo, Immediate, Mailbox};
+        let all = [Fifo, Immediate, Mailbox];
+        assert_eq!(choose_present_mode(PresentMode::Auto, &all), Mailbox);
+        assert_eq!(choose_present_mode(PresentMode::Immediate, &all), Immediate);
+        assert_eq!(choose_present_mode(PresentMode::Fifo, &all), Fifo);
+        // Typical X11 / older drivers: no mailbox
+        assert_eq!(
+            choose_present_mode(PresentMode::Mailbox, &[Fifo, Immediate]),
+            Immediate
+        );
+        assert_eq!(choose_present_mode(PresentMode::Auto, &[Fifo]), Fifo);
+    }
+
+    #[test]
+    fn test_video_shader_defines_sampling_once() {
+        for filter in UpscaleFilter::ALL {
+            let source = video_shader(filter);
+            assert_eq!(source.matches("fn sample_video(").count(), 1);
+            assert_eq!(source.matches("fn fs_main(").count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_video_texture_format() {
+        let sdr = ColorSpace::default();
+        let hdr10 = ColorSpace {
+            transfer: Transfer::Pq,
+            ..sdr
+        };
+        assert_eq!(
+            video_texture_format(PixelFormat::RGBA, sdr, true),
+            (TextureFormat::Rgba8UnormSrgb, TRANSFER_LINEAR)
+        );
+        assert_eq!(
+            video_texture_format(PixelFormat::RGBA64, sdr, true),
+            (TextureFormat::Rgba16Unorm, TRANSFER_SDR)
+        );
+        assert_eq!(
+            video_texture_format(PixelFormat::RGBA64, hdr10, true),
+            (TextureFormat::Rgba16Unorm, TRANSFER_PQ)
+        );
+        // Without 16-bit textures HDR is still tone mapped, from 8 bits
+        assert_eq!(
+            video_texture_format(PixelFormat::RGBA64, hdr10, false),
+            (TextureFormat::Rgba8Unorm, TRANSFER_PQ)
+        );
+    }
+
+    #[test]
+    fn test_video_viewport_modes() {
+        let window = (1000.0, 700.0);
+        let video = (300.0, 200.0);
+        assert_eq!(
+            video_viewport(ScalingMode::Fit, (900.0, 700.0), video),
+            (0.0, 50.0, 900.0, 600.0)
+        );
+        assert_eq!(
+            video_viewport(ScalingMode::Fill, window, video),
+            (0.0, 0.0, 1000.0, 700.0)
+        );
+        // 3x fits (900x600), centered on whole pixels
+        assert_eq!(
+            video_viewport(ScalingMode::Integer, window, video),
+            (50.0, 50.0, 900.0, 600.0)
+        );
+        // Larger than the window: shrinks like fit
+        assert_eq!(
+            video_viewport(ScalingMode::Integer, (150.0, 100.0), video),
+            (0.0, 0.0, 150.0, 100.0)
+        );
+    }
+}
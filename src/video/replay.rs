@@ -0,0 +1,268 @@
+use crate::config::VideoCodec;
+use crate::video::snapshot;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use ffmpeg_next as ffmpeg;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Length of a clip saved from the buffer
+pub const REPLAY_CLIP: Duration = Duration::from_secs(30);
+
+/// Encoded video kept at most, whatever the configured length
+const MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+
+/// Encoded video packet as received from the device
+#[derive(Debug, Clone)]
+pub struct ReplayPacket {
+    /// Microseconds
+    pub pts: i64,
+    pub keyframe: bool,
+    pub data: Bytes,
+}
+
+/// Encoded stream since the last codec config, trimmed to whole GOPs
+#[derive(Debug, Default)]
+struct Buffered {
+    codec: Option<VideoCodec>,
+    config: Option<Bytes>,
+    packets: VecDeque<ReplayPacket>,
+    bytes: usize,
+    frame_size: Option<(u32, u32)>,
+}
+
+/// Rolling buffer of the last seconds of encoded video, for instant replays
+///
+/// The network thread pushes packets, anyone holding a clone can save the
+/// most recent [`REPLAY_CLIP`] as an MP4 without re-encoding. A new codec
+/// config (e.g. after a rotation) starts the buffer over, so right after one
+/// a clip is shorter.
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer {
+    window: Duration,
+    dir: PathBuf,
+    buffered: Arc<Mutex<Buffered>>,
+}
+
+impl ReplayBuffer {
+    /// Keep `window` of video (zero disables the buffer), saving clips in `dir`
+    pub fn new(window: Duration, dir: PathBuf) -> Self {
+        Self {
+            window,
+            dir,
+            buffered: Arc::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Start over with a new codec config (SPS/PPS or AV1 sequence header)
+    pub fn set_config(&self, codec: VideoCodec, config: Bytes) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut buffered = self.buffered.lock();
+        *buffered = Buffered {
+            codec: Some(codec),
+            config: Some(config),
+            frame_size: buffered.frame_size,
+            ..Default::default()
+        };
+    }
+
+    /// Size of the decoded frames, needed by the MP4 header
+    pub fn set_frame_size(&self, width: u32, height: u32) {
+        self.buffered.lock().frame_size = Some((width, height));
+    }
+
+    pub fn push(&self, packet: ReplayPacket) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut buffered = self.buffered.lock();
+        // Clips start on a keyframe, anything before the first one is useless
+        if buffered.packets.is_empty() && !packet.keyframe {
+            return;
+        }
+        buffered.bytes += packet.data.len();
+        buffered.packets.push_back(packet);
+
+        // Drop the oldest GOP while the rest still covers the window
+        let window = self.window.as_micros() as i64;
+        loop {
+            let newest = buffered.packets.back().map_or(0, |p| p.pts);
+            let Some(next_gop) = buffered.packets.iter().skip(1).position(|p| p.keyframe) else {
+                break;
+            };
+            let next_gop = next_gop + 1;
+            let covered = newest - buffered.packets[next_gop].pts >= window;
+            if !covered && buffered.bytes <= MAX_BUFFERED_BYTES {
+                break;
+            }
+            let dropped: usize = buffered
+                .packets
+                .drain(..next_gop)
+                .map(|p| p.data.len())
+                .sum();
+            buffered.bytes -= dropped;
+        }
+    }
+
+    /// The last `length` of video, from the keyframe at or before its start
+    pub fn clip(&self, length: Duration) -> Option<ReplayClip> {
+        let buffered = self.buffered.lock();
+        let newest = buffered.packets.back()?.pts;
+        let start = newest - length.as_micros() as i64;
+        let first = buffered
+            .packets
+            .iter()
+            .rposition(|p| p.keyframe && p.pts <= start)
+            .unwrap_or(0);
+        Some(ReplayClip {
+            codec: buffered.codec?,
+            config: buffered.config.clone(),
+            frame_size: buffered.frame_size?,
+            packets: buffered.packets.range(first..).cloned().collect(),
+        })
+    }
+
+    /// Save the last [`REPLAY_CLIP`] as a timestamped MP4, returning its path
+    pub fn save(&self) -> Result<PathBuf> {
+        let clip = self.clip(REPLAY_CLIP).context("Nothing to save yet")?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(format!(
+            "replay-{}.mp4",
+            snapshot::timestamp(SystemTime::now())
+        ));
+        clip.write_mp4(&path)?;
+        Ok(path)
+    }
+}
+
+/// Encoded packets to be written to a file
+#[derive(Debug)]
+pub struct ReplayClip {
+    pub codec: VideoCodec,
+    pub config: Option<Bytes>,
+    pub frame_size: (u32, u32),
+    /// Starts with a keyframe
+    pub packets: Vec<ReplayPacket>,
+}
+
+impl ReplayClip {
+    /// Play time from the first to the last packet
+    pub fn duration(&self) -> Duration {
+        match (self.packets.first(), self.packets.last()) {
+            (Some(first), Some(last)) => Duration::from_micros((last.pts - first.pts) as u64),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Mux the packets into an MP4 at `path` (stream copy, no re-encoding)
+    pub fn write_mp4(&self, path: &Path) -> Result<()> {
+        ffmpeg::init().context("Failed to initialize FFmpeg")?;
+        let mut output = ffmpeg::format::output(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        let codec_id = match self.codec {
+            VideoCodec::H264 => ffmpeg::codec::Id::H264,
+            VideoCodec::H265 => ffmpeg::codec::Id::HEVC,
+            VideoCodec::Av1 => ffmpeg::codec::Id::AV1,
+        };
+        let input_time_base = ffmpeg::Rational::new(1, 1_000_000);
+        {
+            let mut stream = output.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            stream.set_time_base(input_time_base);
+            // SAFETY: the stream was just created and its parameters are ours
+            // to fill; extradata is allocated with FFmpeg's allocator and padded
+            unsafe {
+                let par = (*stream.as_mut_ptr()).codecpar;
+                (*par).codec_type = ffmpeg::ffi::AVMediaType::AVMEDIA_TYPE_VIDEO;
+                (*par).codec_id = codec_id.into();
+                (*par).width = self.frame_size.0 as i32;
+                (*par).height = self.frame_size.1 as i32;
+                if let Some(config) = &self.config {
+                    let padding = ffmpeg::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize;
+                    let extradata = ffmpeg::ffi::av_mallocz(config.len() + padding) as *mut u8;
+                    if !extradata.is_null() {
+                        std::ptr::copy_nonoverlapping(config.as_ptr(), extradata, config.len());
+                        (*par).extradata = extradata;
+                        (*par).extradata_size = config.len() as i32;
+                    }
+                }
+            }
+        }
+
+        output
+            .write_header()
+            .context("Failed to write MP4 header")?;
+        // The muxer may pick its own time base in write_header
+        let output_time_base = output.stream(0).context("MP4 stream missing")?.time_base();
+        let start = self.packets.first().map_or(0, |p| p.pts);
+        for replay in &self.packets {
+            let mut packet = ffmpeg::Packet::copy(&replay.data);
+            // Device encoders emit no B-frames, so decode order is display order
+            packet.set_pts(Some(replay.pts - start));
+            packet.set_dts(Some(replay.pts - start));
+            if replay.keyframe {
+                packet.set_flags(ffmpeg::packet::Flags::KEY);
+            }
+            packet.set_stream(0);
+            packet.rescale_ts(input_time_base, output_time_base);
+            packet
+                .write_interleaved(&mut output)
+                .context("Failed to write MP4 packet")?;
+        }
+        output.write_trailer().context("Failed to finish MP4")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(secs: i64, keyframe: bool) -> ReplayPacket {
+        ReplayPacket {
+            pts: secs * 1_000_000,
+            keyframe,
+            data: Bytes::from_static(b"frame"),
+        }
+    }
+
+    #[test]
+    fn test_buffer_keeps_whole_gops_covering_the_window() {
+        let replay = ReplayBuffer::new(Duration::from_secs(10), PathBuf::new());
+        assert!(replay.clip(REPLAY_CLIP).is_none());
+
+        replay.set_config(VideoCodec::H264, Bytes::from_static(b"sps"));
+        replay.set_frame_size(1080, 2400);
+        // Waits for a keyframe
+        replay.push(packet(0, false));
+        // Keyframe every 4 s, one packet per second
+        for secs in 1..=20 {
+            replay.push(packet(secs, secs % 4 == 1));
+        }
+
+        // 20 - 10 = 10 s back lands inside the GOP starting at 9 s
+        let clip = replay.clip(REPLAY_CLIP).unwrap();
+        assert_eq!(clip.packets[0].pts, 9_000_000);
+        assert!(clip.packets[0].keyframe);
+        assert_eq!(clip.duration(), Duration::from_secs(11));
+        assert_eq!(clip.frame_size, (1080, 2400));
+
+        // Shorter clips start at the keyframe before their start
+        let short = replay.clip(Duration::from_secs(5)).unwrap();
+        assert_eq!(short.packets[0].pts, 13_000_000);
+
+        // A new config starts over
+        replay.set_config(VideoCodec::H264, Bytes::from_static(b"sps2"));
+        assert!(replay.clip(REPLAY_CLIP).is_none());
+    }
+}
@@ -0,0 +1,4 @@
+// Bilinear upscaling: one hardware-filtered sample
+fn sample_video(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(video_texture, video_sampler, uv);
+}
@@ -0,0 +1,34 @@
+// Lanczos3 upscaling over the 6x6 nearest texels
+const PI: f32 = 3.14159265;
+
+fn lanczos3(x: f32) -> f32 {
+    if abs(x) < 1e-5 {
+        return 1.0;
+    }
+    if abs(x) >= 3.0 {
+        return 0.0;
+    }
+    let px = PI * x;
+    return 3.0 * sin(px) * sin(px / 3.0) / (px * px);
+}
+
+fn sample_video(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(video_texture));
+    let pos = uv * vec2<f32>(size) - 0.5;
+    let base = floor(pos);
+    let offset = pos - base;
+
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var j = -2; j <= 3; j++) {
+        let wy = lanczos3(f32(j) - offset.y);
+        for (var i = -2; i <= 3; i++) {
+            let weight = lanczos3(f32(i) - offset.x) * wy;
+            let texel = clamp(vec2<i32>(base) + vec2<i32>(i, j), vec2<i32>(0), size - 1);
+            sum += textureLoad(video_texture, texel, 0) * weight;
+            weight_sum += weight;
+        }
+    }
+    // The negative lobes overshoot at hard edges
+    return clamp(sum / weight_sum, vec4<f32>(0.0), vec4<f32>(1.0));
+}
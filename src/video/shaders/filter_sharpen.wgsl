@@ -0,0 +1,22 @@
+// Bilinear upscaling with contrast-adaptive sharpening (after FidelityFX CAS)
+
+// 0 = subtle, 1 = strongest
+const CAS_SHARPNESS: f32 = 0.5;
+
+fn sample_video(uv: vec2<f32>) -> vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(video_texture));
+    let center = textureSample(video_texture, video_sampler, uv);
+    let left = textureSample(video_texture, video_sampler, uv - vec2<f32>(texel.x, 0.0)).rgb;
+    let right = textureSample(video_texture, video_sampler, uv + vec2<f32>(texel.x, 0.0)).rgb;
+    let up = textureSample(video_texture, video_sampler, uv - vec2<f32>(0.0, texel.y)).rgb;
+    let down = textureSample(video_texture, video_sampler, uv + vec2<f32>(0.0, texel.y)).rgb;
+
+    // Sharpen less where the neighborhood already has strong contrast
+    let lo = min(center.rgb, min(min(left, right), min(up, down)));
+    let hi = max(center.rgb, max(max(left, right), max(up, down)));
+    let amount = sqrt(clamp(min(lo, 1.0 - hi) / max(hi, vec3<f32>(1e-5)), vec3<f32>(0.0), vec3<f32>(1.0)));
+    let weight = -amount / mix(8.0, 5.0, CAS_SHARPNESS);
+
+    let sharpened = (center.rgb + weight * (left + right + up + down)) / (1.0 + 4.0 * weight);
+    return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), center.a);
+}
@@ -0,0 +1,41 @@
+// Upscales the reduced-resolution video target with a light sharpen
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Full-screen quad using triangle strip
+    let x = f32((vertex_index & 1u) << 1u) - 1.0;
+    let y = 1.0 - f32((vertex_index & 2u));
+
+    out.position = vec4<f32>(x, y, 0.0, 1.0);
+    out.tex_coords = vec2<f32>((x + 1.0) * 0.5, (1.0 - y) * 0.5);
+
+    return out;
+}
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// Strength of the unsharp mask restoring edges lost to the downscale
+const SHARPNESS: f32 = 0.25;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let center = textureSample(source_texture, source_sampler, in.tex_coords);
+    let neighbors = textureSample(source_texture, source_sampler, in.tex_coords + vec2<f32>(texel.x, 0.0)).rgb
+        + textureSample(source_texture, source_sampler, in.tex_coords - vec2<f32>(texel.x, 0.0)).rgb
+        + textureSample(source_texture, source_sampler, in.tex_coords + vec2<f32>(0.0, texel.y)).rgb
+        + textureSample(source_texture, source_sampler, in.tex_coords - vec2<f32>(0.0, texel.y)).rgb;
+
+    let sharpened = center.rgb + SHARPNESS * (4.0 * center.rgb - neighbors);
+    return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), center.a);
+}
@@ -18,16 +18,110 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     return out;
 }
 
-// Fragment shader with bilinear interpolation (upscaling)
+// Fragment shader; `sample_video` comes from one of the filter_*.wgsl files
 @group(0) @binding(0)
 var video_texture: texture_2d<f32>;
 
 @group(0) @binding(1)
 var video_sampler: sampler;
 
+// Per-device color calibration (identity matrix and gamma 1.0 when unset)
+struct Calibration {
+    matrix: mat3x3<f32>,
+    gamma: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> calibration: Calibration;
+
+// Transfer function of the texture contents (TRANSFER_* in renderer.rs)
+struct VideoFormat {
+    transfer: u32,
+};
+
+@group(0) @binding(3)
+var<uniform> video_format: VideoFormat;
+
+const TRANSFER_LINEAR: u32 = 0u;
+const TRANSFER_SDR: u32 = 1u;
+const TRANSFER_PQ: u32 = 2u;
+const TRANSFER_HLG: u32 = 3u;
+
+// HDR level shown as SDR white (BT.2408 reference white)
+const SDR_WHITE_NITS: f32 = 203.0;
+// Assumed mastering peak of HDR content
+const HDR_PEAK_NITS: f32 = 1000.0;
+
+// BT.2020 to BT.709 primaries, column-major
+const BT2020_TO_BT709: mat3x3<f32> = mat3x3<f32>(
+    vec3<f32>(1.6605, -0.1246, -0.0182),
+    vec3<f32>(-0.5876, 1.1329, -0.1006),
+    vec3<f32>(-0.0728, -0.0083, 1.1187),
+);
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// SMPTE ST 2084 EOTF, in nits
+fn pq_to_nits(c: vec3<f32>) -> vec3<f32> {
+    let p = pow(c, vec3<f32>(1.0 / 78.84375));
+    let num = max(p - 0.8359375, vec3<f32>(0.0));
+    return 10000.0 * pow(num / (18.8515625 - 18.6875 * p), vec3<f32>(1.0 / 0.1593017578125));
+}
+
+// HLG inverse OETF and the BT.2100 OOTF for a display at HDR_PEAK_NITS, in nits
+fn hlg_to_nits(c: vec3<f32>) -> vec3<f32> {
+    let a = 0.17883277;
+    let b = 0.28466892;
+    let k = 0.55991073;
+    let scene = select(
+        (exp((c - k) / a) + b) / 12.0,
+        c * c / 3.0,
+        c <= vec3<f32>(0.5)
+    );
+    let luma = dot(scene, vec3<f32>(0.2627, 0.6780, 0.0593));
+    return HDR_PEAK_NITS * pow(max(luma, 1e-6), 0.2) * scene;
+}
+
+// BT.2020 light in nits to linear BT.709, highlights rolled off below white
+fn tone_map(nits: vec3<f32>) -> vec3<f32> {
+    let rgb = max(BT2020_TO_BT709 * (nits / SDR_WHITE_NITS), vec3<f32>(0.0));
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let knee = 0.75;
+    if luma <= knee {
+        return min(rgb, vec3<f32>(1.0));
+    }
+    // Slope 1 at the knee, approaching 1.0 for very bright highlights
+    let over = luma - knee;
+    let mapped = knee + (1.0 - knee) * over / (over + 1.0 - knee);
+    return min(rgb * (mapped / luma), vec3<f32>(1.0));
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    switch video_format.transfer {
+        case TRANSFER_SDR: {
+            return srgb_to_linear(c);
+        }
+        case TRANSFER_PQ: {
+            return tone_map(pq_to_nits(c));
+        }
+        case TRANSFER_HLG: {
+            return tone_map(hlg_to_nits(c));
+        }
+        default: {
+            // sRGB textures are linearized by the sampler
+            return c;
+        }
+    }
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // Sample texture with bilinear filtering for smooth upscaling
-    let color = textureSample(video_texture, video_sampler, in.tex_coords);
-    return color;
+    // Upscale with the selected filter
+    let color = sample_video(in.tex_coords);
+
+    // Calibration works in linear space
+    let corrected = max(calibration.matrix * to_linear(color.rgb), vec3<f32>(0.0));
+    return vec4<f32>(pow(corrected, vec3<f32>(calibration.gamma)), color.a);
 }
@@ -0,0 +1,165 @@
+use crate::plugin::Plugin;
+use crate::video::decoder::DecodedFrame;
+use crate::video::renderer::VideoRenderer;
+use anyhow::{Context, Result};
+use parking_lot::{Condvar, Mutex};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Somewhere decoded frames are shown: the window, or an output other apps read
+pub trait VideoSink {
+    fn name(&self) -> &str;
+
+    /// Show or publish `frame`
+    fn present(&mut self, frame: &DecodedFrame) -> Result<()>;
+}
+
+impl VideoSink for VideoRenderer<'_> {
+    fn name(&self) -> &str {
+        "window"
+    }
+
+    fn present(&mut self, frame: &DecodedFrame) -> Result<()> {
+        self.render(frame)
+    }
+}
+
+/// Newest frame waiting for the sink thread, or None once closed
+struct Pending {
+    frame: Mutex<Option<Option<DecodedFrame>>>,
+    ready: Condvar,
+}
+
+/// Output sink fed from the decode thread without ever blocking it
+///
+/// The sink runs on its own thread and always gets the newest frame; a slow
+/// sink skips frames instead of delaying the window. Register it on the
+/// session's [`PluginHost`](crate::plugin::PluginHost) to receive every frame.
+pub struct SinkThread {
+    name: String,
+    pending: Arc<Pending>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SinkThread {
+    pub fn spawn(mut sink: Box<dyn VideoSink + Send>) -> Result<Self> {
+        let name = sink.name().to_string();
+        let pending = Arc::new(Pending {
+            frame: Mutex::new(Some(None)),
+            ready: Condvar::new(),
+        });
+        let thread_pending = pending.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("sink-{}", name))
+            .spawn(move || loop {
+                let frame = {
+                    let mut slot = thread_pending.frame.lock();
+                    loop {
+                        match slot.as_mut() {
+                            None => return,
+                            Some(frame) => match frame.take() {
+                                Some(frame) => break frame,
+                                None => thread_pending.ready.wait(&mut slot),
+                            },
+                        }
+                    }
+                };
+                if let Err(e) = sink.present(&frame) {
+                    tracing::warn!("Output {} failed: {:#}", sink.name(), e);
+                }
+            })
+            .with_context(|| format!("Failed to start the {} output thread", name))?;
+        Ok(Self {
+            name,
+            pending,
+            thread: Some(thread),
+        })
+    }
+
+    /// Hand `frame` to the sink, replacing one it has not taken yet
+    pub fn send(&self, frame: &DecodedFrame) {
+        if let Some(slot) = self.pending.frame.lock().as_mut() {
+            *slot = Some(frame.clone());
+            self.pending.ready.notify_one();
+        }
+    }
+}
+
+/// Sink thread publishing frames as the NDI source `name`
+#[cfg(feature = "ndi")]
+pub fn ndi_output(name: &str) -> Result<SinkThread> {
+    SinkThread::spawn(Box::new(crate::video::ndi::NdiSink::new(name)?))
+}
+
+#[cfg(not(feature = "ndi"))]
+pub fn ndi_output(name: &str) -> Result<SinkThread> {
+    anyhow::bail!("NDI output {:?} needs the `ndi` feature", name)
+}
+
+impl Plugin for SinkThread {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_frame(&mut self, frame: &DecodedFrame) {
+        self.send(frame);
+    }
+}
+
+impl Drop for SinkThread {
+    fn drop(&mut self) {
+        *self.pending.frame.lock() = None;
+        self.pending.ready.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::color::ColorSpace;
+    use crate::video::decoder::PixelFormat;
+
+    /// Records the pts of every frame it is given
+    struct Recorder(Arc<Mutex<Vec<i64>>>);
+
+    impl VideoSink for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn present(&mut self, frame: &DecodedFrame) -> Result<()> {
+            self.0.lock().push(frame.pts);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sink_thread_ends_with_newest_frame() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = SinkThread::spawn(Box::new(Recorder(seen.clone()))).unwrap();
+        for pts in 0..50 {
+            sink.send(&DecodedFrame {
+                pts,
+                data: vec![0; 4].into(),
+                width: 1,
+                height: 1,
+                format: PixelFormat::RGBA,
+                color: ColorSpace::default(),
+            });
+        }
+        // Wait for the last frame before closing, which discards a pending one
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while seen.lock().last() != Some(&49) && std::time::Instant::now() < deadline {
+            std::thread::yield_now();
+        }
+        drop(sink);
+
+        let seen = seen.lock();
+        assert_eq!(seen.last(), Some(&49));
+        // Frames may be skipped but never reordered
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::video::decoder::{DecodedFrame, PixelFormat};
+
+/// Save a frame as an RGBA PNG
+///
+/// YUV frames are converted with their own color space; RGBA64 frames keep
+/// all 16 bits per channel.
+pub fn write_png(frame: &DecodedFrame, path: &Path) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgba);
+    // Bursts are about timing, not file size
+    encoder.set_compression(png::Compression::Fast);
+
+    let data = if frame.format == PixelFormat::RGBA64 {
+        // PNG samples are big-endian
+        encoder.set_depth(png::BitDepth::Sixteen);
+        frame
+            .data
+            .chunks_exact(2)
+            .flat_map(|sample| [sample[1], sample[0]])
+            .collect()
+    } else {
+        encoder.set_depth(png::BitDepth::Eight);
+        frame.to_rgba()
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+/// Save `frame` in `dir` (created if needed) under a timestamped name
+///
+/// Returns the path of the new file, e.g. `screenshot-20240131-235959-042.png`.
+pub fn save_screenshot(frame: &DecodedFrame, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("screenshot-{}.png", timestamp(SystemTime::now())));
+    write_png(frame, &path)?;
+    Ok(path)
+}
+
+/// UTC `YYYYMMDD-HHMMSS-mmm`, sortable and safe in file names
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Captures N consecutive decoded frames to numbered PNGs
+///
+/// Every frame handed to `push` is kept, including ones the renderer would
+/// skip, so the files show exactly what the decoder produced. Encoding runs
+/// on a background thread to keep the UI responsive.
+pub struct FrameBurst {
+    dir: PathBuf,
+    remaining: u32,
+    captured: u32,
+    tx: Option<mpsc::Sender<(PathBuf, DecodedFrame)>>,
+    writer: Option<JoinHandle<u32>>,
+}
+
+impl FrameBurst {
+    /// Start a burst of `count` frames into a new directory under `parent`
+    pub fn start(parent: &Path, count: u32) -> Result<Self> {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let dir = parent.join(format!("burst-{}", stamp));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let (tx, rx) = mpsc::channel::<(PathBuf, DecodedFrame)>();
+        let writer = std::thread::spawn(move || {
+            let mut written = 0;
+            for (path, frame) in rx {
+                match write_png(&frame, &path) {
+                    Ok(()) => written += 1,
+                    Err(e) => tracing::warn!("Burst frame not saved: {}", e),
+                }
+            }
+            written
+        });
+
+        Ok(Self {
+            dir,
+            remaining: count,
+            captured: 0,
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    /// Output directory of this burst
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Capture a frame; returns false once the burst has all its frames
+    pub fn push(&mut self, frame: &DecodedFrame) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        // Index for ordering, PTS (us) for measuring frame gaps
+        let path = self
+            .dir
+            .join(format!("{:04}_{}.png", self.captured, frame.pts));
+        if let Some(tx) = &self.tx {
+            let _ = tx.send((path, frame.clone()));
+        }
+        self.captured += 1;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            // Lets the writer drain and exit
+            self.tx = None;
+        }
+        self.remaining > 0
+    }
+
+    /// Whether all requested frames have been captured
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Wait for the files to be written, returning how many were saved
+    pub fn finish(mut self) -> u32 {
+        self.tx = None;
+        self.writer
+            .take()
+            .and_then(|writer| writer.join().ok())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::color::ColorSpace;
+    use crate::video::decoder::PixelFormat;
+
+    #[test]
+    fn test_screenshot_names_are_utc_timestamps() {
+        let time = UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_042);
+        assert_eq!(timestamp(time), "20231114-221320-042");
+        // Leap day
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(951_782_400);
+        assert_eq!(timestamp(time), "20000229-000000-000");
+    }
+
+    #[test]
+    fn test_burst_writes_numbered_frames() {
+        let parent = std::env::temp_dir().join(format!("scrcpy-burst-{}", std::process::id()));
+        let frame = |pts| DecodedFrame {
+            pts,
+            data: vec![255; 4 * 4 * 2].into(),
+            width: 4,
+            height: 2,
+            format: PixelFormat::RGBA,
+            color: ColorSpace::default(),
+        };
+
+        let mut burst = FrameBurst::start(&parent, 2).unwrap();
+        assert!(burst.push(&frame(0)));
+        assert!(!burst.push(&frame(16_666)));
+        assert!(!burst.push(&frame(33_333)));
+        assert!(burst.is_complete());
+
+        let dir = burst.dir().to_path_buf();
+        assert_eq!(burst.finish(), 2);
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&parent).unwrap();
+        assert_eq!(files, ["0000_0.png", "0001_16666.png"]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Staging buffers in rotation: one being filled while the GPU copies another
+const STAGING_SLOTS: usize = 2;
+
+/// Row pitch of an RGBA frame in a buffer-to-texture copy
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    aligned_pitch(4 * width)
+}
+
+/// Row pitch for rows of `row_len` bytes in a buffer-to-texture copy
+pub fn aligned_pitch(row_len: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    row_len.div_ceil(align) * align
+}
+
+/// Copy tightly packed rows of `row_len` bytes into `dst` at `pitch`
+fn copy_rows(dst: &mut [u8], src: &[u8], row_len: usize, pitch: usize) {
+    for (dst_row, src_row) in dst.chunks_mut(pitch).zip(src.chunks(row_len)) {
+        dst_row[..src_row.len()].copy_from_slice(src_row);
+    }
+}
+
+/// Mapped staging buffer and whether it can be written
+struct StagingSlot {
+    buffer: wgpu::Buffer,
+    mapped: Arc<AtomicBool>,
+}
+
+/// Uploads frames through persistently mapped staging buffers
+///
+/// `Queue::write_texture` copies every frame into a fresh staging
+/// allocation on the calling thread. Here the frame is written straight into
+/// a buffer the GPU is not using and copied to the texture as part of the
+/// next submission, so a slow transfer overlaps the next frame instead of
+/// delaying presentation.
+pub struct FrameUploader {
+    slots: Vec<StagingSlot>,
+    /// Slots submitted for copying, to be mapped again
+    in_flight: Vec<usize>,
+    width: u32,
+    height: u32,
+    /// Bytes per tightly packed row
+    row_len: u32,
+}
+
+impl FrameUploader {
+    /// Uploader for frames of `bytes_per_pixel` (4 for RGBA, 8 for RGBA64)
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, bytes_per_pixel: u32) -> Self {
+        let row_len = width * bytes_per_pixel;
+        let size = aligned_pitch(row_len) as u64 * height as u64;
+        let slots = (0..STAGING_SLOTS)
+            .map(|_| StagingSlot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Frame Staging Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: true,
+                }),
+                mapped: Arc::new(AtomicBool::new(true)),
+            })
+            .collect();
+        Self {
+            slots,
+            in_flight: Vec::new(),
+            width,
+            height,
+            row_len,
+        }
+    }
+
+    /// Record a copy of `pixels` into `texture`
+    ///
+    /// Returns false without recording anything when every staging buffer is
+    /// still in use by the GPU.
+    pub fn upload(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        pixels: &[u8],
+    ) -> bool {
+        let Some(index) = self
+            .slots
+            .iter()
+            .position(|slot| slot.mapped.load(Ordering::Acquire))
+        else {
+            return false;
+        };
+        let slot = &self.slots[index];
+        let pitch = aligned_pitch(self.row_len);
+        {
+            let mut view = slot.buffer.slice(..).get_mapped_range_mut();
+            copy_rows(&mut view, pixels, self.row_len as usize, pitch as usize);
+        }
+        slot.buffer.unmap();
+        slot.mapped.store(false, Ordering::Release);
+
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &slot.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(pitch),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.in_flight.push(index);
+        true
+    }
+
+    /// Map submitted buffers again once the GPU is done copying from them
+    ///
+    /// Call after the copies are submitted; mapping completes during a later
+    /// `Device::poll`.
+    pub fn recycle(&mut self) {
+        for index in self.in_flight.drain(..) {
+            let mapped = self.slots[index].mapped.clone();
+            self.slots[index]
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Write, move |result| {
+                    if result.is_ok() {
+                        mapped.store(true, Ordering::Release);
+                    }
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_are_padded_for_copies() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(1920), 7680);
+
+        // 2x2 RGBA frame into a 12-byte pitch
+        let src: Vec<u8> = (1..=16).collect();
+        let mut dst = vec![0u8; 24];
+        copy_rows(&mut dst, &src, 8, 12);
+        assert_eq!(&dst[..8], &src[..8]);
+        assert_eq!(&dst[8..12], &[0; 4]);
+        assert_eq!(&dst[12..20], &src[8..]);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::config::VideoCodec;
+use crate::plugin::PluginHost;
+use crate::video::decoder::{HardwareVideoDecoder, PixelFormat};
+use crate::video::latency::{LatencyTracer, Mark};
+use crate::video::mailbox::FrameSender;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// Work for the decode thread
+enum Job {
+    Config(Bytes),
+    Packet {
+        data: Bytes,
+        pts: i64,
+        keyframe: bool,
+    },
+}
+
+/// Consecutive decode errors before a keyframe is requested; fewer are
+/// left to error concealment
+const KEYFRAME_AFTER_ERRORS: u32 = 3;
+
+/// Decode queue counters, as reported by the control API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DecodeQueueStats {
+    /// Packets waiting for the decoder
+    pub depth: usize,
+    /// Deepest the queue has been this session
+    pub peak: usize,
+    /// Packets dropped because the queue was full or resyncing
+    pub dropped: u64,
+}
+
+/// State shared with the decode thread
+#[derive(Default)]
+struct Shared {
+    depth: AtomicUsize,
+    peak: AtomicUsize,
+    dropped: AtomicU64,
+    keyframe_needed: AtomicBool,
+    receiver_gone: AtomicBool,
+    /// Decoding failed and no keyframe decoded cleanly since
+    corrupted: AtomicBool,
+}
+
+/// Video decoder running on its own thread behind a bounded packet queue
+///
+/// Keeps a slow decode from stalling socket reads. When the queue is full
+/// the packet is dropped, a keyframe is requested and packets are skipped
+/// until it arrives, since later frames would reference the missing one.
+/// Decode errors are concealed at first; a keyframe is requested once they
+/// keep coming, and the stream counts as corrupted until one decodes.
+pub struct DecodeWorker {
+    jobs: Option<SyncSender<Job>>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    resync: bool,
+}
+
+impl DecodeWorker {
+    /// Start the decode thread, sending decoded frames to `plugins` and `frames`
+    ///
+    /// The decoder is created on the thread itself; its errors are returned here.
+    /// Frames are stamped in `latency` as they are dequeued, decoded and converted.
+    pub fn spawn(
+        hw_decoder: &str,
+        codec: VideoCodec,
+        output_format: PixelFormat,
+        frames: FrameSender,
+        plugins: PluginHost,
+        latency: LatencyTracer,
+        capacity: usize,
+    ) -> Result<Self> {
+        let (jobs, queue) = mpsc::sync_channel::<Job>(capacity);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        let hw_decoder = hw_decoder.to_string();
+
+        let thread = std::thread::Builder::new()
+            .name("video-decode".into())
+            .spawn(move || {
+                let mut decoder = match HardwareVideoDecoder::new(&hw_decoder, codec, output_format)
+                {
+                    Ok(decoder) => {
+                        let _ = ready_tx.send(Ok(decoder.info()));
+                        decoder
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let shared = thread_shared;
+                let mut errors = 0;
+
+                for job in queue {
+                    shared.depth.fetch_sub(1, Ordering::Relaxed);
+                    match job {
+                        Job::Config(data) => {
+                            decoder.set_config(&data);
+                            // Pictures of the old parameters, left over from a reset
+                            for mut frame in decoder.take_flushed() {
+                                plugins.on_frame(&mut frame);
+                                if frames.send(frame).is_err() {
+                                    shared.receiver_gone.store(true, Ordering::Relaxed);
+                                    return;
+                                }
+                            }
+                        }
+                        Job::Packet {
+                            data,
+                            pts,
+                            keyframe,
+                        } => {
+                            latency.mark(pts, Mark::Dequeued);
+                            let decoded = {
+                                let _span = tracing::trace_span!("decode", pts).entered();
+                                decoder.decode(&data, pts)
+                            };
+                            if decoded.is_ok() {
+                                errors = 0;
+                                if keyframe && shared.corrupted.swap(false, Ordering::Relaxed) {
+                                    tracing::info!("Video stream recovered");
+                                }
+                            }
+                            match decoded {
+                                Ok(Some(mut frame)) => {
+                                    let converted = Instant::now();
+                                    let decoded = converted - decoder.last_convert_time();
+                                    latency.mark_at(frame.pts, Mark::Decoded, decoded);
+                                    latency.mark_at(frame.pts, Mark::Converted, converted);
+                                    plugins.on_frame(&mut frame);
+                                    if frames.send(frame).is_err() {
+                                        shared.receiver_gone.store(true, Ordering::Relaxed);
+                                        return;
+                                    }
+                                }
+                                Ok(None) => {} // Need more data
+                                Err(e) => {
+                                    tracing::error!("Video decoding error: {}", e);
+                                    shared.corrupted.store(true, Ordering::Relaxed);
+                                    // Again every few errors, in case the keyframe got lost
+                                    errors += 1;
+                                    if errors % KEYFRAME_AFTER_ERRORS == 0 {
+                                        shared.keyframe_needed.store(true, Ordering::Relaxed);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Hand over frames still inside the decoder (the UI may already be gone)
+                match decoder.flush() {
+                    Ok(remaining) => {
+                        for mut frame in remaining {
+                            plugins.filters().apply(&mut frame);
+                            let _ = frames.send(frame);
+                        }
+                    }
+                    Err(e) => tracing::warn!("Failed to flush video decoder: {}", e),
+                }
+            })
+            .context("Failed to start the decode thread")?;
+
+        let info = ready_rx
+            .recv()
+            .context("Decode thread exited during setup")??;
+        tracing::info!("Initialized Video Decoder: {}", info);
+
+        Ok(Self {
+            jobs: Some(jobs),
+            shared,
+            thread: Some(thread),
+            resync: false,
+        })
+    }
+
+    /// Queue a codec config packet (SPS/PPS)
+    ///
+    /// Config is never dropped: this waits for room in the queue.
+    pub fn set_config(&mut self, data: Bytes) {
+        if let Some(jobs) = &self.jobs {
+            self.shared.depth.fetch_add(1, Ordering::Relaxed);
+            if jobs.send(Job::Config(data)).is_err() {
+                self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Queue a video packet, returning false if it was dropped
+    pub fn decode(&mut self, data: Bytes, pts: i64, keyframe: bool) -> bool {
+        let Some(jobs) = &self.jobs else {
+            return false;
+        };
+        if self.resync && !keyframe {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let depth = self.shared.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        match jobs.try_send(Job::Packet {
+            data,
+            pts,
+            keyframe,
+        }) {
+            Ok(()) => {
+                self.resync = false;
+                self.shared.peak.fetch_max(depth, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                if !self.resync {
+                    tracing::debug!("Decode queue full, skipping to the next keyframe");
+                    self.resync = true;
+                    self.shared.keyframe_needed.store(true, Ordering::Relaxed);
+                }
+                false
+            }
+        }
+    }
+
+    /// Whether the stream needs a keyframe (decode error or overflow), once per need
+    pub fn take_keyframe_request(&self) -> bool {
+        self.shared.keyframe_needed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether decode errors left the picture damaged until the next keyframe
+    pub fn corrupted(&self) -> bool {
+        self.shared.corrupted.load(Ordering::Relaxed)
+    }
+
+    /// Whether the UI stopped taking frames
+    pub fn receiver_gone(&self) -> bool {
+        self.shared.receiver_gone.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> DecodeQueueStats {
+        DecodeQueueStats {
+            depth: self.shared.depth.load(Ordering::Relaxed),
+            peak: self.shared.peak.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Decode what is queued, flush the decoder and stop the thread
+    pub fn finish(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::error!("Decode thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for DecodeWorker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
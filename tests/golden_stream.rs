@@ -0,0 +1,183 @@
+//! A recorded H.264 + Opus session through the decoders and the A/V sync
+//! engine, headless: no device, network or window.
+//!
+//! `fixtures/h264_opus.scap` is a session capture holding the SPS/PPS, 30
+//! 16x16 I_PCM frames at 60 fps and 25 packets of 20 ms Opus silence.
+use futures::StreamExt;
+use scrcpy_custom::audio::decoder::HardwareAudioDecoder;
+use scrcpy_custom::config::{AudioCodec, VideoCodec};
+use scrcpy_custom::network::capture::CaptureReader;
+use scrcpy_custom::network::{Connection, MockConnection, NetworkError, PacketType};
+use scrcpy_custom::sync::{SyncAction, SyncEngine};
+use scrcpy_custom::video::decoder::{HardwareVideoDecoder, PixelFormat};
+use scrcpy_custom::{Client, Config};
+use std::fs::File;
+use std::path::PathBuf;
+
+const VIDEO_FRAMES: usize = 30;
+const AUDIO_PACKETS: usize = 25;
+
+fn fixture_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/h264_opus.scap")
+}
+
+/// What came out of the pipeline
+#[derive(Default)]
+struct Decoded {
+    video_pts: Vec<i64>,
+    /// Frames only returned by the final flush
+    flushed: usize,
+    audio_chunks: usize,
+    sync: Option<SyncEngine>,
+}
+
+/// Receive everything `connection` has, decoding and queueing it for sync
+fn run_pipeline(mut connection: MockConnection) -> Decoded {
+    let mut video = HardwareVideoDecoder::new("none", VideoCodec::H264, PixelFormat::RGBA)
+        .expect("software H.264 decoder");
+    let mut audio = HardwareAudioDecoder::new("opus", 48000, 2).expect("Opus decoder");
+    let mut sync = SyncEngine::new(50, 64, 64);
+    let mut decoded = Decoded::default();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        loop {
+            let packet = match connection.recv().await {
+                Ok(packet) => packet,
+                Err(NetworkError::ConnectionClosed) => break,
+                Err(e) => panic!("replay failed: {}", e),
+            };
+            match packet.packet_type {
+                PacketType::Video if packet.flags.config => {
+                    video.set_config(&packet.data);
+                    for frame in video.take_flushed() {
+                        decoded.video_pts.push(frame.pts);
+                    }
+                }
+                PacketType::Video => {
+                    if let Some(frame) = video.decode(&packet.data, packet.pts).unwrap() {
+                        assert_eq!((frame.width, frame.height), (16, 16));
+                        decoded.video_pts.push(frame.pts);
+                        sync.add_video_frame(frame.pts, frame.to_rgba(), frame.width, frame.height);
+                    }
+                }
+                PacketType::Audio => {
+                    let chunk = audio.decode(&packet.data, packet.pts).unwrap().unwrap();
+                    // 20 ms of stereo at 48 kHz
+                    assert_eq!(chunk.samples.len(), 960 * 2);
+                    sync.add_audio_samples(chunk.pts, chunk.samples);
+                    decoded.audio_chunks += 1;
+                }
+                other => panic!("unexpected {:?} packet", other),
+            }
+        }
+    });
+    decoded.flushed = video.flush().unwrap().len();
+    decoded.sync = Some(sync);
+    decoded
+}
+
+#[test]
+fn golden_stream_decodes_and_stays_in_sync() {
+    let connection = MockConnection::from_file(&fixture_path()).unwrap();
+    assert_eq!(connection.video_codec(), Some(VideoCodec::H264));
+    assert_eq!(connection.device_name(), Some("Golden Pixel"));
+
+    let decoded = run_pipeline(connection);
+    assert_eq!(decoded.video_pts.len() + decoded.flushed, VIDEO_FRAMES);
+    assert!(
+        decoded.video_pts.windows(2).all(|pair| pair[0] < pair[1]),
+        "video PTS not increasing: {:?}",
+        decoded.video_pts
+    );
+    assert_eq!(decoded.audio_chunks, AUDIO_PACKETS);
+
+    // Play both queues out against one clock, 60 fps frames and 20 ms audio
+    // chunks: they start together, so the engine never has to correct
+    let mut sync = decoded.sync.unwrap();
+    let (mut video_until, mut audio_until) = (0, 0);
+    while sync.sync() == SyncAction::Continue {
+        if video_until <= audio_until {
+            video_until = sync.pop_video_frame().unwrap().pts + 16_666;
+        } else {
+            audio_until = sync.pop_audio_samples().unwrap().pts + 20_000;
+        }
+    }
+    assert!(video_until > 0 && audio_until > 0);
+    assert_eq!(sync.stats().sync_corrections, 0);
+}
+
+#[test]
+fn lost_frames_do_not_stop_decoding() {
+    let reader = CaptureReader::new(File::open(fixture_path()).unwrap()).unwrap();
+    let header = reader.header().clone();
+    // Every third frame goes missing; the rest are IDR frames and still decode
+    let packets: Vec<_> = reader
+        .map(Result::unwrap)
+        .filter(|(_, packet)| packet.packet_type != PacketType::Video || packet.seq % 3 != 0)
+        .collect();
+    let video_frames = packets
+        .iter()
+        .filter(|(_, p)| p.packet_type == PacketType::Video && !p.flags.config)
+        .count();
+    assert_eq!(video_frames, VIDEO_FRAMES - VIDEO_FRAMES / 3);
+
+    let decoded = run_pipeline(MockConnection::new(header, packets));
+    assert_eq!(decoded.video_pts.len() + decoded.flushed, video_frames);
+    assert!(decoded.video_pts.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn new_parameters_mid_stream_reset_the_decoder() {
+    let reader = CaptureReader::new(File::open(fixture_path()).unwrap()).unwrap();
+    let header = reader.header().clone();
+    let mut packets: Vec<_> = reader.map(Result::unwrap).collect();
+    // The encoder restarts halfway: the parameter sets come again, with
+    // trailing zero bytes so they differ from the first ones
+    let config = packets
+        .iter()
+        .find(|(_, p)| p.flags.config)
+        .cloned()
+        .unwrap();
+    let middle = packets
+        .iter()
+        .position(|(_, p)| p.packet_type == PacketType::Video && p.seq == 16)
+        .unwrap();
+    let (at, mut restart) = (packets[middle].0, config.1);
+    restart.data = [&restart.data[..], &[0, 0]].concat().into();
+    packets.insert(middle, (at, restart));
+
+    let decoded = run_pipeline(MockConnection::new(header, packets));
+    assert_eq!(decoded.video_pts.len() + decoded.flushed, VIDEO_FRAMES);
+    assert!(decoded.video_pts.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn client_streams_decoded_frames() {
+    let mut config = Config::default();
+    config.video.hw_decoder = "none".to_string();
+    config.audio.codec = AudioCodec::Opus;
+    // Room for the whole capture, nothing is dropped while reading one stream
+    config.performance.video_buffer_size = VIDEO_FRAMES;
+    config.performance.audio_buffer_size = AUDIO_PACKETS;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let connection = MockConnection::from_file(&fixture_path()).unwrap();
+        let mut client = Client::with_connection(Box::new(connection), &config).unwrap();
+        assert_eq!(client.device_name(), Some("Golden Pixel"));
+
+        let (mut video, audio) = (client.video_frames(), client.audio_frames());
+        let mut frames = 0;
+        while let Some(frame) = video.next().await {
+            assert_eq!((frame.width, frame.height), (16, 16));
+            frames += 1;
+        }
+        assert_eq!(frames, VIDEO_FRAMES);
+        assert_eq!(audio.count().await, AUDIO_PACKETS);
+        assert!(client.video_frames().next().await.is_none());
+    });
+}